@@ -0,0 +1,77 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use geo::{Distance, Haversine, Point};
+use ridi_router::{closest_index, distance_m};
+
+// points around Sigulda, a few meters to a few km apart like the points walked on the graph
+fn coords(count: usize) -> Vec<(f32, f32)> {
+    (0..count)
+        .map(|i| {
+            let i = i as f32;
+            (
+                57.15 + (i * 0.618).fract() * 0.04,
+                24.85 + (i * 0.414).fract() * 0.04,
+            )
+        })
+        .collect()
+}
+
+fn distance(c: &mut Criterion) {
+    let coords = coords(1000);
+    let (lat, lon) = (57.15396, 24.854355);
+    let mut group = c.benchmark_group("distance");
+    group.bench_function("haversine", |b| {
+        b.iter(|| {
+            coords
+                .iter()
+                .map(|(point_lat, point_lon)| {
+                    Haversine.distance(
+                        Point::new(black_box(lon), black_box(lat)),
+                        Point::new(*point_lon, *point_lat),
+                    )
+                })
+                .sum::<f32>()
+        })
+    });
+    group.bench_function("distance_m", |b| {
+        b.iter(|| {
+            coords
+                .iter()
+                .map(|(point_lat, point_lon)| {
+                    distance_m(black_box(lat), black_box(lon), *point_lat, *point_lon)
+                })
+                .sum::<f32>()
+        })
+    });
+    group.finish();
+}
+
+fn closest(c: &mut Criterion) {
+    let coords = coords(1000);
+    let (lats, lons): (Vec<f32>, Vec<f32>) = coords.iter().copied().unzip();
+    let (lat, lon) = (57.15396, 24.854355);
+    let mut group = c.benchmark_group("closest");
+    group.bench_function("haversine_sort", |b| {
+        b.iter(|| {
+            let mut distances = coords
+                .iter()
+                .enumerate()
+                .map(|(idx, (point_lat, point_lon))| {
+                    let distance = Haversine.distance(
+                        Point::new(black_box(lon), black_box(lat)),
+                        Point::new(*point_lon, *point_lat),
+                    );
+                    (idx, distance)
+                })
+                .collect::<Vec<_>>();
+            distances.sort_by(|el1, el2| el1.1.total_cmp(&el2.1));
+            distances.first().map(|v| v.0)
+        })
+    });
+    group.bench_function("closest_index", |b| {
+        b.iter(|| closest_index(black_box(lat), black_box(lon), &lats, &lons))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, distance, closest);
+criterion_main!(benches);
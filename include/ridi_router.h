@@ -0,0 +1,32 @@
+#ifndef RIDI_ROUTER_H
+#define RIDI_ROUTER_H
+
+/* Generated with cbindgen, do not edit. Run `just ffi-header` to regenerate */
+
+#define RIDI_ROUTER_OK 0
+
+#define RIDI_ROUTER_ERR_INVALID_ARGUMENT 1
+
+#define RIDI_ROUTER_ERR_MAP_DATA 2
+
+/**
+ * Loads map data into the routing graph. Must be called once before generating routes.
+ *
+ * `input` is a path to an OSM json or osm.pbf file, `cache_dir` is an optional (nullable) path
+ * to a cache directory. Returns `RIDI_ROUTER_OK` on success.
+ */
+int ridi_router_init(const char *input, const char *cache_dir);
+
+/**
+ * Generates routes for a JSON encoded request with the same format as the IPC request message
+ * and returns a JSON encoded response message. The returned string must be released with
+ * `ridi_router_free_result`. Returns NULL if the request could not be read.
+ */
+char *ridi_router_generate_route(const char *request_json);
+
+/**
+ * Releases a result returned by `ridi_router_generate_route`. Passing NULL is a no-op.
+ */
+void ridi_router_free_result(char *result);
+
+#endif  /* RIDI_ROUTER_H */
@@ -0,0 +1,159 @@
+use std::{io, time::Duration};
+
+use serde::Serialize;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BenchError {
+    #[error("Could not write benchmark results: {error}")]
+    WriteCsv { error: csv::Error },
+
+    #[error("Could not write benchmark results: {error}")]
+    WriteJson { error: serde_json::Error },
+
+    #[error("Could not write benchmark results: {error}")]
+    Flush { error: io::Error },
+}
+
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum BenchFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+/// Single measured route generation of a scenario
+pub struct ScenarioRun {
+    pub duration: Duration,
+    pub steps: u64,
+    /// Number of routes generated, none when the generation failed
+    pub routes: Option<usize>,
+}
+
+/// Latency and work of the measured runs of a scenario
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ScenarioStats {
+    pub scenario_id: String,
+    pub runs: usize,
+    pub failed_runs: usize,
+    pub min_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+    pub avg_steps: f64,
+    pub avg_routes: f64,
+    /// Peak resident memory of the process once the runs of the scenario were done, it includes
+    /// the map data and every scenario before it. Only known on Unix
+    pub peak_rss_mb: Option<f64>,
+}
+
+/// Nearest rank percentile of sorted values
+fn percentile(sorted: &[f64], percentile: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.;
+    }
+    let rank = (percentile / 100. * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+pub fn scenario_stats(
+    scenario_id: String,
+    runs: &[ScenarioRun],
+    peak_rss_bytes: Option<u64>,
+) -> ScenarioStats {
+    let mut durations_ms = runs
+        .iter()
+        .map(|run| run.duration.as_secs_f64() * 1000.)
+        .collect::<Vec<_>>();
+    durations_ms.sort_by(|a, b| a.total_cmp(b));
+    let count = runs.len().max(1) as f64;
+    ScenarioStats {
+        scenario_id,
+        runs: runs.len(),
+        failed_runs: runs.iter().filter(|run| run.routes.is_none()).count(),
+        min_ms: percentile(&durations_ms, 0.),
+        p50_ms: percentile(&durations_ms, 50.),
+        p90_ms: percentile(&durations_ms, 90.),
+        p99_ms: percentile(&durations_ms, 99.),
+        max_ms: percentile(&durations_ms, 100.),
+        avg_steps: runs.iter().map(|run| run.steps as f64).sum::<f64>() / count,
+        avg_routes: runs
+            .iter()
+            .map(|run| run.routes.unwrap_or(0) as f64)
+            .sum::<f64>()
+            / count,
+        peak_rss_mb: peak_rss_bytes.map(|bytes| bytes as f64 / 1024. / 1024.),
+    }
+}
+
+/// Highest resident memory the process had so far
+#[cfg(unix)]
+pub fn peak_rss_bytes() -> Option<u64> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    let max_rss = unsafe { usage.assume_init() }.ru_maxrss as u64;
+    // bytes on macOS, kilobytes everywhere else
+    Some(if cfg!(target_os = "macos") {
+        max_rss
+    } else {
+        max_rss * 1024
+    })
+}
+
+#[cfg(not(unix))]
+pub fn peak_rss_bytes() -> Option<u64> {
+    None
+}
+
+pub fn write_bench_stats(stats: &[ScenarioStats], format: BenchFormat) -> Result<(), BenchError> {
+    match format {
+        BenchFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(io::stdout());
+            for scenario_stats in stats {
+                writer
+                    .serialize(scenario_stats)
+                    .map_err(|error| BenchError::WriteCsv { error })?;
+            }
+            writer
+                .flush()
+                .map_err(|error| BenchError::Flush { error })?;
+        }
+        BenchFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&stats)
+                .map_err(|error| BenchError::WriteJson { error })?
+        ),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{scenario_stats, ScenarioRun};
+
+    #[test]
+    fn bench_scenario_stats_percentiles() {
+        let runs = (1..=10)
+            .map(|ms| ScenarioRun {
+                duration: Duration::from_millis(ms * 10),
+                steps: ms * 100,
+                routes: (ms != 10).then_some(2),
+            })
+            .collect::<Vec<_>>();
+        let stats = scenario_stats("a".to_string(), &runs, Some(2 * 1024 * 1024));
+        assert_eq!(stats.runs, 10);
+        assert_eq!(stats.failed_runs, 1);
+        assert_eq!(stats.min_ms, 10.);
+        assert_eq!(stats.p50_ms, 50.);
+        assert_eq!(stats.p90_ms, 90.);
+        assert_eq!(stats.p99_ms, 100.);
+        assert_eq!(stats.max_ms, 100.);
+        assert_eq!(stats.avg_steps, 550.);
+        assert_eq!(stats.avg_routes, 1.8);
+        assert_eq!(stats.peak_rss_mb, Some(2.));
+    }
+}
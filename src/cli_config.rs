@@ -0,0 +1,205 @@
+use std::{
+    env,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use clap::Command;
+use serde::Deserialize;
+
+pub const CONFIG_ENV: &str = "RIDI_ROUTER_CONFIG";
+pub const INPUT_ENV: &str = "RIDI_ROUTER_INPUT";
+pub const CACHE_DIR_ENV: &str = "RIDI_ROUTER_CACHE_DIR";
+pub const RULE_FILE_ENV: &str = "RIDI_ROUTER_RULE_FILE";
+pub const SOCKET_NAME_ENV: &str = "RIDI_ROUTER_SOCKET_NAME";
+pub const OUTPUT_ENV: &str = "RIDI_ROUTER_OUTPUT";
+
+#[derive(Debug, thiserror::Error)]
+pub enum CliConfigError {
+    #[error("Could not read config file {file_name}: {error}")]
+    Read {
+        file_name: PathBuf,
+        error: io::Error,
+    },
+
+    #[error("Could not parse config file {file_name}: {error}")]
+    Parse {
+        file_name: PathBuf,
+        error: toml::de::Error,
+    },
+}
+
+/// Defaults for the command line arguments that are the same for most invocations. Arguments
+/// given on the command line and `RIDI_ROUTER_*` environment variables take precedence
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CliConfig {
+    pub input: Option<PathBuf>,
+    pub cache_dir: Option<PathBuf>,
+    pub rule_file: Option<PathBuf>,
+    pub socket_name: Option<String>,
+    pub output: Option<PathBuf>,
+}
+
+impl CliConfig {
+    /// `RIDI_ROUTER_CONFIG` when set, otherwise `ridi-router/config.toml` in the user config dir
+    fn file_name() -> Option<(PathBuf, bool)> {
+        if let Some(file_name) = env::var_os(CONFIG_ENV) {
+            return Some((PathBuf::from(file_name), true));
+        }
+        let config_dir = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some((config_dir.join("ridi-router").join("config.toml"), false))
+    }
+
+    /// Reads the config file, a missing file is only an error when it was named explicitly
+    pub fn read() -> Result<Self, CliConfigError> {
+        let Some((file_name, explicit)) = Self::file_name() else {
+            return Ok(Self::default());
+        };
+        let contents = match fs::read_to_string(&file_name) {
+            Ok(contents) => contents,
+            Err(error) if !explicit && error.kind() == io::ErrorKind::NotFound => {
+                return Ok(Self::default())
+            }
+            Err(error) => return Err(CliConfigError::Read { file_name, error }),
+        };
+        let config: Self = toml::from_str(&contents).map_err(|error| CliConfigError::Parse {
+            file_name: file_name.clone(),
+            error,
+        })?;
+        Ok(config.relative_to(file_name.parent().unwrap_or(Path::new(""))))
+    }
+
+    /// Relative paths in the config file are relative to the file
+    fn relative_to(self, dir: &Path) -> Self {
+        let resolve = |path: Option<PathBuf>| path.map(|path| dir.join(path));
+        Self {
+            input: resolve(self.input),
+            cache_dir: resolve(self.cache_dir),
+            rule_file: resolve(self.rule_file),
+            socket_name: self.socket_name,
+            output: resolve(self.output),
+        }
+    }
+
+    /// Values of the config file by the environment variable of the arguments they are defaults for
+    fn arg_defaults(self) -> Vec<(&'static str, OsString)> {
+        [
+            (INPUT_ENV, self.input.map(OsString::from)),
+            (CACHE_DIR_ENV, self.cache_dir.map(OsString::from)),
+            (RULE_FILE_ENV, self.rule_file.map(OsString::from)),
+            (SOCKET_NAME_ENV, self.socket_name.map(OsString::from)),
+            (OUTPUT_ENV, self.output.map(OsString::from)),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|value| (name, value)))
+        .collect()
+    }
+
+    /// Makes the config file values the defaults of the arguments that fall back to the same
+    /// environment variables, so the command line and the environment variables take precedence
+    pub fn apply_arg_defaults(self, command: Command) -> Command {
+        with_arg_defaults(command, &self.arg_defaults())
+    }
+}
+
+fn with_arg_defaults(command: Command, defaults: &[(&'static str, OsString)]) -> Command {
+    let mut command = command.mut_args(|arg| {
+        let default = arg.get_env().and_then(|env| {
+            defaults
+                .iter()
+                .find(|(name, _)| env == *name)
+                .map(|(_, value)| value.clone())
+        });
+        match default {
+            Some(default) => arg.default_value(default).required(false),
+            None => arg,
+        }
+    });
+    for subcommand in command.get_subcommands_mut() {
+        *subcommand = with_arg_defaults(std::mem::take(subcommand), defaults);
+    }
+    command
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        ffi::OsString,
+        path::{Path, PathBuf},
+    };
+
+    use clap::{Arg, Command};
+
+    use super::{CliConfig, CACHE_DIR_ENV, INPUT_ENV, SOCKET_NAME_ENV};
+
+    #[test]
+    fn cli_config_arg_defaults() {
+        let config: CliConfig = toml::from_str(
+            r#"
+            input = "maps/latvia.osm.pbf"
+            cache_dir = "/var/cache/ridi-router"
+            socket_name = "ridi"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config
+                .relative_to(Path::new("/home/rider/.config/ridi-router"))
+                .arg_defaults(),
+            vec![
+                (
+                    INPUT_ENV,
+                    OsString::from("/home/rider/.config/ridi-router/maps/latvia.osm.pbf")
+                ),
+                (CACHE_DIR_ENV, OsString::from("/var/cache/ridi-router")),
+                (SOCKET_NAME_ENV, OsString::from("ridi")),
+            ]
+        );
+
+        assert!(toml::from_str::<CliConfig>("inputs = \"map.json\"").is_err());
+    }
+
+    #[test]
+    fn cli_config_defaults_applied_to_subcommands() {
+        let command = Command::new("ridi-router").subcommand(
+            Command::new("generate-route")
+                .arg(
+                    Arg::new("input")
+                        .long("input")
+                        .env(INPUT_ENV)
+                        .required(true),
+                )
+                .arg(Arg::new("cache-dir").long("cache-dir").env(CACHE_DIR_ENV)),
+        );
+        let config = CliConfig {
+            input: Some(PathBuf::from("map.osm.pbf")),
+            ..CliConfig::default()
+        };
+        let command = config.apply_arg_defaults(command);
+
+        let matches = command
+            .clone()
+            .try_get_matches_from(["ridi-router", "generate-route"])
+            .unwrap();
+        let matches = matches.subcommand_matches("generate-route").unwrap();
+        assert_eq!(
+            matches.get_one::<String>("input").map(String::as_str),
+            Some("map.osm.pbf")
+        );
+        assert_eq!(matches.get_one::<String>("cache-dir"), None);
+
+        let matches = command
+            .try_get_matches_from(["ridi-router", "generate-route", "--input", "other.json"])
+            .unwrap();
+        let matches = matches.subcommand_matches("generate-route").unwrap();
+        assert_eq!(
+            matches.get_one::<String>("input").map(String::as_str),
+            Some("other.json")
+        );
+    }
+}
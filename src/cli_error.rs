@@ -0,0 +1,173 @@
+use serde::Serialize;
+
+#[cfg(feature = "geocoding")]
+use crate::geocoder::GeocoderError;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::ipc_handler::IpcHandlerError;
+use crate::{
+    map_data_cache::MapDataCacheError, result_writer::ResultWriterError, router::rules::RulesError,
+    router_runner::RouterRunnerError,
+};
+
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum ErrorFormat {
+    /// The error and its causes in the logs
+    #[default]
+    Text,
+    /// A single JSON object with the kind, the exit code, the message and the causes as the last
+    /// line of stderr
+    Json,
+}
+
+/// Exit code of the process, so scripts can tell why it failed
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitCode {
+    /// Anything else, including crashes
+    Failure = 1,
+    /// Arguments, coordinates, rules or input files that can't be used, the same code the
+    /// argument parser exits with
+    BadInput = 2,
+    /// No road near the start, the finish or a via point, or no place for an address
+    PointNotFound = 3,
+    /// The points were found, but no route between them
+    NoRoute = 4,
+    Cache = 5,
+    /// Connecting to the server or talking to it failed
+    Ipc = 6,
+}
+
+impl ExitCode {
+    /// Code of the first error in the chain that has one
+    pub fn of(error: &anyhow::Error) -> Self {
+        error
+            .chain()
+            .find_map(|cause| {
+                if let Some(error) = cause.downcast_ref::<RouterRunnerError>() {
+                    return Self::of_runner_error(error);
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                if cause.is::<IpcHandlerError>() {
+                    return Some(Self::Ipc);
+                }
+                if cause.is::<MapDataCacheError>() {
+                    return Some(Self::Cache);
+                }
+                if cause.is::<RulesError>() || cause.is::<clap::Error>() {
+                    return Some(Self::BadInput);
+                }
+                None
+            })
+            .unwrap_or(Self::Failure)
+    }
+
+    fn of_runner_error(error: &RouterRunnerError) -> Option<Self> {
+        match error {
+            RouterRunnerError::PointNotFound { .. } => Some(Self::PointNotFound),
+            RouterRunnerError::NoRoute => Some(Self::NoRoute),
+            RouterRunnerError::CacheWrite { .. } => Some(Self::Cache),
+            #[cfg(not(target_arch = "wasm32"))]
+            RouterRunnerError::Ipc { .. } => Some(Self::Ipc),
+            RouterRunnerError::OutputFileInvalid { .. }
+            | RouterRunnerError::InputFileInvalid { .. }
+            | RouterRunnerError::InputFileFormatIncorrect { .. }
+            | RouterRunnerError::OutputFileFormatIncorrect { .. }
+            | RouterRunnerError::Coords { .. }
+            | RouterRunnerError::Rules { .. }
+            | RouterRunnerError::CompleteRules
+            | RouterRunnerError::RulesCheck { .. }
+            | RouterRunnerError::ClientRequest
+            | RouterRunnerError::WaypointsRoutingMode
+            | RouterRunnerError::Waypoints { .. }
+            | RouterRunnerError::RoundTripLength
+            | RouterRunnerError::StreamRoutesStdout
+            | RouterRunnerError::DryRunInput
+            | RouterRunnerError::ResultWrite {
+                error: ResultWriterError::OutputExists { .. },
+            } => Some(Self::BadInput),
+            #[cfg(feature = "geocoding")]
+            RouterRunnerError::AddressRoutingMode => Some(Self::BadInput),
+            #[cfg(feature = "geocoding")]
+            RouterRunnerError::Geocoder {
+                error: GeocoderError::NotFound { .. },
+            } => Some(Self::PointNotFound),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorOutput {
+    error: ExitCode,
+    exit_code: i32,
+    message: String,
+    causes: Vec<String>,
+}
+
+/// Error the CLI exits with, reported in the format given in the arguments
+#[derive(Debug)]
+pub struct CliError {
+    pub error: anyhow::Error,
+    pub format: ErrorFormat,
+}
+
+impl CliError {
+    pub fn exit_code(&self) -> i32 {
+        ExitCode::of(&self.error) as i32
+    }
+
+    pub fn report(&self) {
+        match self.format {
+            ErrorFormat::Json => {
+                let exit_code = ExitCode::of(&self.error);
+                let output = ErrorOutput {
+                    error: exit_code,
+                    exit_code: exit_code as i32,
+                    message: self.error.to_string(),
+                    causes: self.error.chain().skip(1).map(|c| c.to_string()).collect(),
+                };
+                match serde_json::to_string(&output) {
+                    Ok(json) => eprintln!("{json}"),
+                    Err(_) => eprintln!("Router startup failed: {:?}", self.error),
+                }
+            }
+            // the subscriber is set up after the arguments are read, it may be missing
+            ErrorFormat::Text if tracing::dispatcher::has_been_set() => {
+                tracing::error!(error = ?self.error, "Router startup failed");
+            }
+            ErrorFormat::Text => eprintln!("Router startup failed: {:?}", self.error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Context;
+
+    use crate::router_runner::RouterRunnerError;
+
+    use super::ExitCode;
+
+    #[test]
+    fn exit_code_of_error_chain() {
+        let error = Err::<(), _>(RouterRunnerError::PointNotFound {
+            point: "Start point".to_string(),
+        })
+        .context("Failed to generate route")
+        .unwrap_err();
+        assert_eq!(ExitCode::of(&error), ExitCode::PointNotFound);
+        assert_eq!(
+            ExitCode::of(&RouterRunnerError::RoundTripLength.into()),
+            ExitCode::BadInput
+        );
+        assert_eq!(
+            ExitCode::of(&RouterRunnerError::StreamRoutesStdout.into()),
+            ExitCode::BadInput
+        );
+        assert_eq!(
+            ExitCode::of(&RouterRunnerError::NoRoute.into()),
+            ExitCode::NoRoute
+        );
+        assert_eq!(ExitCode::of(&anyhow::anyhow!("crash")), ExitCode::Failure);
+    }
+}
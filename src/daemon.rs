@@ -0,0 +1,192 @@
+use std::{io, path::PathBuf};
+
+#[cfg(unix)]
+use std::{
+    ffi::CString,
+    fs::File,
+    io::{IsTerminal, Read, Write},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    sync::Mutex,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum DaemonError {
+    #[error("Running as a daemon and socket permissions are only supported on Unix")]
+    Unsupported,
+
+    #[error("Failed to start the daemon process: {error}")]
+    Fork { error: io::Error },
+
+    #[error("Server failed to start, see its log for the reason")]
+    StartFailed,
+
+    #[error("Failed to write pidfile {path:?}: {error}")]
+    Pidfile { path: PathBuf, error: io::Error },
+
+    #[error("Server already running with pid {pid} according to pidfile {path:?}")]
+    AlreadyRunning { path: PathBuf, pid: i32 },
+
+    #[error("Unknown group '{name}'")]
+    Group { name: String },
+}
+
+/// Write end of the pipe the parent process waits on, until the server is ready
+#[cfg(unix)]
+static READY_PIPE: Mutex<Option<File>> = Mutex::new(None);
+
+/// Detaches the server from the terminal and the calling process. The calling process waits until
+/// the server is ready to accept connections and exits, with 1 when the server failed to start.
+/// Has to be called before any other thread is started, as only the calling thread continues in
+/// the daemon process. Logs still go to stderr unless it is a terminal, so they end up wherever
+/// the service manager sends them
+#[cfg(unix)]
+pub fn daemonize() -> Result<(), DaemonError> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(DaemonError::Fork {
+            error: io::Error::last_os_error(),
+        });
+    }
+    let (read_end, write_end) = unsafe {
+        (
+            File::from(OwnedFd::from_raw_fd(fds[0])),
+            File::from(OwnedFd::from_raw_fd(fds[1])),
+        )
+    };
+
+    match unsafe { libc::fork() } {
+        -1 => Err(DaemonError::Fork {
+            error: io::Error::last_os_error(),
+        }),
+        0 => {
+            drop(read_end);
+            if unsafe { libc::setsid() } == -1 {
+                return Err(DaemonError::Fork {
+                    error: io::Error::last_os_error(),
+                });
+            }
+            let dev_null = File::options()
+                .read(true)
+                .write(true)
+                .open("/dev/null")
+                .map_err(|error| DaemonError::Fork { error })?;
+            let mut redirected = vec![libc::STDIN_FILENO, libc::STDOUT_FILENO];
+            if io::stderr().is_terminal() {
+                redirected.push(libc::STDERR_FILENO);
+            }
+            for fd in redirected {
+                if unsafe { libc::dup2(dev_null.as_raw_fd(), fd) } == -1 {
+                    return Err(DaemonError::Fork {
+                        error: io::Error::last_os_error(),
+                    });
+                }
+            }
+            *READY_PIPE.lock().unwrap_or_else(|error| error.into_inner()) = Some(write_end);
+            Ok(())
+        }
+        _ => {
+            drop(write_end);
+            let mut ready = [0u8; 1];
+            // the pipe closes without a byte when the daemon exits before it is ready
+            match (&read_end).read(&mut ready) {
+                Ok(1) => std::process::exit(0),
+                _ => Err(DaemonError::StartFailed),
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn daemonize() -> Result<(), DaemonError> {
+    Err(DaemonError::Unsupported)
+}
+
+/// Lets the process that started the daemon exit, does nothing when not running as a daemon
+pub fn notify_ready() {
+    #[cfg(unix)]
+    if let Some(mut pipe) = READY_PIPE
+        .lock()
+        .unwrap_or_else(|error| error.into_inner())
+        .take()
+    {
+        let _ = pipe.write_all(&[1]);
+    }
+}
+
+/// Fails when the pidfile names a process that is still running
+pub fn check_pidfile(path: &PathBuf) -> Result<(), DaemonError> {
+    match std::fs::read_to_string(path)
+        .ok()
+        .and_then(|pid| pid.trim().parse::<i32>().ok())
+    {
+        Some(pid) if process_running(pid) => Err(DaemonError::AlreadyRunning {
+            path: path.clone(),
+            pid,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Writes the id of the server process to the file
+pub fn write_pidfile(path: &PathBuf) -> Result<(), DaemonError> {
+    std::fs::write(path, format!("{}\n", std::process::id())).map_err(|error| {
+        DaemonError::Pidfile {
+            path: path.clone(),
+            error,
+        }
+    })
+}
+
+#[cfg(unix)]
+fn process_running(pid: i32) -> bool {
+    pid as u32 != std::process::id() && unsafe { libc::kill(pid, 0) } == 0
+}
+
+#[cfg(not(unix))]
+fn process_running(_pid: i32) -> bool {
+    false
+}
+
+/// Id of the group with the given name or number
+#[cfg(unix)]
+pub fn group_id(name: &str) -> Result<u32, DaemonError> {
+    if let Ok(gid) = name.parse::<u32>() {
+        return Ok(gid);
+    }
+    let error = || DaemonError::Group {
+        name: name.to_string(),
+    };
+    let c_name = CString::new(name).map_err(|_| error())?;
+    let group = unsafe { libc::getgrnam(c_name.as_ptr()) };
+    if group.is_null() {
+        return Err(error());
+    }
+    Ok(unsafe { (*group).gr_gid })
+}
+
+#[cfg(not(unix))]
+pub fn group_id(_name: &str) -> Result<u32, DaemonError> {
+    Err(DaemonError::Unsupported)
+}
+
+/// Parses a file mode given in octal, like 0660
+pub fn parse_mode(mode: &str) -> Result<u32, String> {
+    u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or_else(|| format!("'{mode}' is not an octal file mode like 0660"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_mode;
+
+    #[test]
+    fn parse_socket_mode() {
+        assert_eq!(parse_mode("0660"), Ok(0o660));
+        assert_eq!(parse_mode("600"), Ok(0o600));
+        assert_eq!(parse_mode("0o770"), Ok(0o770));
+        assert!(parse_mode("0980").is_err());
+        assert!(parse_mode("17777").is_err());
+    }
+}
@@ -1,3 +1,6 @@
+pub mod reader;
+pub mod replay;
+pub mod stats;
 #[cfg(feature = "debug-viewer")]
 pub mod viewer;
 pub mod writer;
@@ -0,0 +1,40 @@
+use std::{fs, io, path::Path};
+
+use serde::de::DeserializeOwned;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DebugReaderError {
+    #[error("Could not read debug dir: {error}")]
+    ReadDebugDir { error: io::Error },
+
+    #[error("Could not read debug record: {error}")]
+    Record { error: csv::Error },
+}
+
+/// Reads the records of a debug stream from all of its files in the debug dir
+pub fn read_stream<T: DeserializeOwned>(
+    debug_dir: &Path,
+    name: &str,
+) -> Result<Vec<T>, DebugReaderError> {
+    let mut records = Vec::new();
+    for entry in
+        fs::read_dir(debug_dir).map_err(|error| DebugReaderError::ReadDebugDir { error })?
+    {
+        let file_name = entry
+            .map_err(|error| DebugReaderError::ReadDebugDir { error })?
+            .path();
+        let is_stream_file = file_name
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .is_some_and(|file_name| file_name.starts_with(name) && file_name.ends_with(".csv"));
+        if !is_stream_file {
+            continue;
+        }
+        let mut reader = csv::Reader::from_path(&file_name)
+            .map_err(|error| DebugReaderError::Record { error })?;
+        for record in reader.deserialize() {
+            records.push(record.map_err(|error| DebugReaderError::Record { error })?);
+        }
+    }
+    Ok(records)
+}
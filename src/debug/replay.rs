@@ -0,0 +1,111 @@
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+
+use derive_name::Name;
+use serde::de::DeserializeOwned;
+
+use crate::map_data::graph::{MapDataGraph, MapDataPointRef};
+
+use super::reader::{read_stream, DebugReaderError};
+use super::writer::{
+    DebugStreamItineraries, DebugStreamItineraryWaypoints, REPLAY_INPUT_FILE_NAME,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum DebugReplayError {
+    #[error("Could not read {file_name}: {error}")]
+    FileRead {
+        file_name: PathBuf,
+        error: io::Error,
+    },
+
+    #[error("Could not read debug files: {error}")]
+    Read { error: DebugReaderError },
+
+    #[error("Could not read replay input, was the debug dir written by an older version? {error}")]
+    ReplayInput { error: serde_json::Error },
+
+    #[error("Itinerary {itinerary_id} not found in the debug files")]
+    ItineraryNotFound { itinerary_id: String },
+
+    #[error("Itinerary {itinerary_id} does not match its waypoints in the debug files")]
+    ItineraryWaypoints { itinerary_id: String },
+
+    #[error("Point {id} not found in the map data, the debug files may be from other map data")]
+    PointNotFound { id: u64 },
+}
+
+/// Reads what the route generation of the debug files was started with
+pub fn read_replay_input<T: DeserializeOwned>(debug_dir: &Path) -> Result<T, DebugReplayError> {
+    let file_name = debug_dir.join(REPLAY_INPUT_FILE_NAME);
+    let file =
+        File::open(&file_name).map_err(|error| DebugReplayError::FileRead { file_name, error })?;
+    serde_json::from_reader(file).map_err(|error| DebugReplayError::ReplayInput { error })
+}
+
+/// Itinerary of the debug files with the points looked up in the loaded map data
+pub struct ReplayItinerary {
+    pub start: MapDataPointRef,
+    pub finish: MapDataPointRef,
+    pub waypoints: Vec<MapDataPointRef>,
+    pub waypoint_radius: f32,
+}
+
+impl ReplayItinerary {
+    pub fn read(debug_dir: &Path, itinerary_id: &str) -> Result<Self, DebugReplayError> {
+        let itinerary =
+            read_stream::<DebugStreamItineraries>(debug_dir, DebugStreamItineraries::name())
+                .map_err(|error| DebugReplayError::Read { error })?
+                .into_iter()
+                .find(|itinerary| itinerary.itinerary_id == itinerary_id)
+                .ok_or_else(|| DebugReplayError::ItineraryNotFound {
+                    itinerary_id: itinerary_id.to_string(),
+                })?;
+        let mut waypoints = read_stream::<DebugStreamItineraryWaypoints>(
+            debug_dir,
+            DebugStreamItineraryWaypoints::name(),
+        )
+        .map_err(|error| DebugReplayError::Read { error })?
+        .into_iter()
+        .filter(|waypoint| waypoint.itinerary_id == itinerary_id)
+        .collect::<Vec<_>>();
+        // the same itinerary can be written more than once by retries
+        waypoints.sort_by_key(|waypoint| waypoint.idx);
+        waypoints.dedup_by_key(|waypoint| waypoint.idx);
+
+        // the itinerary id is made of the point ids of the start, the waypoints and the finish
+        let point_ids = itinerary_id
+            .split('-')
+            .filter(|id| !id.is_empty())
+            .map(|id| id.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .ok()
+            .filter(|point_ids| point_ids.len() == waypoints.len() + 2)
+            .ok_or_else(|| DebugReplayError::ItineraryWaypoints {
+                itinerary_id: itinerary_id.to_string(),
+            })?;
+        let point = |id: u64, lat: f32, lon: f32| {
+            MapDataGraph::get()
+                .get_point_by_id_at(id, lat, lon)
+                .ok_or(DebugReplayError::PointNotFound { id })
+        };
+
+        Ok(Self {
+            start: point(point_ids[0], itinerary.start_lat, itinerary.start_lon)?,
+            finish: point(
+                point_ids[point_ids.len() - 1],
+                itinerary.finish_lat,
+                itinerary.finish_lon,
+            )?,
+            waypoints: waypoints
+                .iter()
+                .zip(&point_ids[1..])
+                .map(|(waypoint, id)| point(*id, waypoint.lat as f32, waypoint.lon as f32))
+                .collect::<Result<Vec<_>, _>>()?,
+            waypoint_radius: itinerary.radius as f32,
+        })
+    }
+}
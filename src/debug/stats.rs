@@ -0,0 +1,241 @@
+use std::{collections::HashMap, io, path::Path};
+
+use derive_name::Name;
+use serde::Serialize;
+
+use super::{
+    reader::{read_stream, DebugReaderError},
+    writer::{DebugStreamForkChoiceWeights, DebugStreamStepResults},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum DebugStatsError {
+    #[error("Could not read debug files: {error}")]
+    Read { error: DebugReaderError },
+
+    #[error("Could not write stats: {error}")]
+    WriteCsv { error: csv::Error },
+
+    #[error("Could not write stats: {error}")]
+    WriteJson { error: serde_json::Error },
+
+    #[error("Could not write stats: {error}")]
+    Flush { error: io::Error },
+}
+
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum DebugStatsFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+/// How a weight calculation influenced the fork choices of the debug files
+#[derive(Debug, Serialize, PartialEq)]
+pub struct WeightCalcStats {
+    pub weight_name: String,
+    /// Fork choices the weight was calculated for
+    pub calc_count: u64,
+    /// Fork choices ruled out by the weight
+    pub veto_count: u64,
+    pub veto_ratio: f64,
+    /// Forks where the weight made the navigator move back
+    pub move_back_count: u64,
+    /// Average of the weights given to the choices that weren't ruled out
+    pub avg_weight: Option<f64>,
+    /// Average of the weights given to the choices that were taken
+    pub chosen_avg_weight: Option<f64>,
+    /// Correlation between the weight and the choice being taken, from -1 to 1. Weights that
+    /// decide the choices are close to 1
+    pub chosen_correlation: Option<f64>,
+}
+
+#[derive(Default)]
+struct WeightCalcTotals {
+    calc_count: u64,
+    veto_count: u64,
+    move_back_count: u64,
+    /// Weights with whether the choice was taken, for the forks where a choice was taken
+    weights: Vec<(f64, bool)>,
+    weight_sum: f64,
+    weight_count: u64,
+}
+
+fn pearson_correlation(values: &[(f64, bool)]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+    let count = values.len() as f64;
+    let y = |chosen: bool| if chosen { 1. } else { 0. };
+    let mean_x = values.iter().map(|(x, _)| x).sum::<f64>() / count;
+    let mean_y = values.iter().map(|(_, chosen)| y(*chosen)).sum::<f64>() / count;
+    let (mut covariance, mut variance_x, mut variance_y) = (0., 0., 0.);
+    for (x, chosen) in values {
+        let (dx, dy) = (x - mean_x, y(*chosen) - mean_y);
+        covariance += dx * dy;
+        variance_x += dx * dx;
+        variance_y += dy * dy;
+    }
+    if variance_x == 0. || variance_y == 0. {
+        return None;
+    }
+    Some(covariance / (variance_x * variance_y).sqrt())
+}
+
+/// Summarizes the fork choice weights per weight calculation, the weights that rule out the most
+/// choices first
+pub fn fork_choice_stats(
+    weights: &[DebugStreamForkChoiceWeights],
+    step_results: &[DebugStreamStepResults],
+) -> Vec<WeightCalcStats> {
+    let chosen_points = step_results
+        .iter()
+        .filter(|step_result| step_result.result == "ForkChoice")
+        .map(|step_result| {
+            (
+                (step_result.itinerary_id.as_str(), step_result.step_num),
+                step_result.chosen_fork_point_id,
+            )
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut totals: HashMap<&str, WeightCalcTotals> = HashMap::new();
+    for weight in weights {
+        let total = totals.entry(weight.weight_name.as_str()).or_default();
+        total.calc_count += 1;
+        match weight.weight_type.as_str() {
+            "ForkChoiceDoNotUse" => total.veto_count += 1,
+            "LastSegmentDoNotUse" => total.move_back_count += 1,
+            _ => {
+                let value = weight.weight_value as f64;
+                total.weight_sum += value;
+                total.weight_count += 1;
+                if let Some(chosen_point) =
+                    chosen_points.get(&(weight.itinerary_id.as_str(), weight.step_num))
+                {
+                    total
+                        .weights
+                        .push((value, *chosen_point == weight.end_point_id));
+                }
+            }
+        }
+    }
+
+    let mut stats = totals
+        .into_iter()
+        .map(|(weight_name, total)| {
+            let chosen = total
+                .weights
+                .iter()
+                .filter(|(_, chosen)| *chosen)
+                .map(|(value, _)| *value)
+                .collect::<Vec<_>>();
+            WeightCalcStats {
+                weight_name: weight_name.to_string(),
+                calc_count: total.calc_count,
+                veto_count: total.veto_count,
+                veto_ratio: total.veto_count as f64 / total.calc_count as f64,
+                move_back_count: total.move_back_count,
+                avg_weight: (total.weight_count > 0)
+                    .then(|| total.weight_sum / total.weight_count as f64),
+                chosen_avg_weight: (!chosen.is_empty())
+                    .then(|| chosen.iter().sum::<f64>() / chosen.len() as f64),
+                chosen_correlation: pearson_correlation(&total.weights),
+            }
+        })
+        .collect::<Vec<_>>();
+    stats.sort_by(|a, b| {
+        b.veto_count
+            .cmp(&a.veto_count)
+            .then_with(|| a.weight_name.cmp(&b.weight_name))
+    });
+    stats
+}
+
+/// Reads the fork choice weights of the debug files and prints their stats
+pub fn write_fork_choice_stats(
+    debug_dir: &Path,
+    format: DebugStatsFormat,
+) -> Result<(), DebugStatsError> {
+    let weights = read_stream::<DebugStreamForkChoiceWeights>(
+        debug_dir,
+        DebugStreamForkChoiceWeights::name(),
+    )
+    .map_err(|error| DebugStatsError::Read { error })?;
+    let step_results =
+        read_stream::<DebugStreamStepResults>(debug_dir, DebugStreamStepResults::name())
+            .map_err(|error| DebugStatsError::Read { error })?;
+    let stats = fork_choice_stats(&weights, &step_results);
+
+    match format {
+        DebugStatsFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(io::stdout());
+            for weight_stats in &stats {
+                writer
+                    .serialize(weight_stats)
+                    .map_err(|error| DebugStatsError::WriteCsv { error })?;
+            }
+            writer
+                .flush()
+                .map_err(|error| DebugStatsError::Flush { error })?;
+        }
+        DebugStatsFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&stats)
+                .map_err(|error| DebugStatsError::WriteJson { error })?
+        ),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::debug::writer::{DebugStreamForkChoiceWeights, DebugStreamStepResults};
+
+    use super::fork_choice_stats;
+
+    #[test]
+    fn fork_choice_stats_per_weight() {
+        let weight = |step_num, end_point_id, weight_name: &str, weight_type: &str, value| {
+            DebugStreamForkChoiceWeights {
+                itinerary_id: "1-2".to_string(),
+                step_num,
+                end_point_id,
+                weight_name: weight_name.to_string(),
+                weight_type: weight_type.to_string(),
+                weight_value: value,
+                duration_us: 0,
+            }
+        };
+        let step_result = |step_num, chosen_fork_point_id| DebugStreamStepResults {
+            itinerary_id: "1-2".to_string(),
+            step_num,
+            result: "ForkChoice".to_string(),
+            chosen_fork_point_id,
+        };
+        let stats = fork_choice_stats(
+            &[
+                weight(1, 10, "prefer_same_road", "ForkChoiceUseWithWeight", 80),
+                weight(1, 11, "prefer_same_road", "ForkChoiceUseWithWeight", 10),
+                weight(1, 10, "no_loops", "ForkChoiceUseWithWeight", 0),
+                weight(1, 11, "no_loops", "ForkChoiceDoNotUse", 0),
+                weight(2, 20, "prefer_same_road", "ForkChoiceUseWithWeight", 20),
+                weight(2, 21, "prefer_same_road", "ForkChoiceUseWithWeight", 70),
+                weight(2, 20, "no_loops", "ForkChoiceDoNotUse", 0),
+                weight(2, 21, "no_loops", "ForkChoiceUseWithWeight", 0),
+            ],
+            &[step_result(1, 10), step_result(2, 21)],
+        );
+
+        assert_eq!(stats[0].weight_name, "no_loops");
+        assert_eq!(stats[0].veto_count, 2);
+        assert_eq!(stats[0].veto_ratio, 0.5);
+        assert_eq!(stats[0].chosen_correlation, None);
+
+        assert_eq!(stats[1].weight_name, "prefer_same_road");
+        assert_eq!(stats[1].calc_count, 4);
+        assert_eq!(stats[1].avg_weight, Some(45.));
+        assert_eq!(stats[1].chosen_avg_weight, Some(75.));
+        assert!(stats[1].chosen_correlation.unwrap() > 0.9);
+    }
+}
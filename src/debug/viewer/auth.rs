@@ -0,0 +1,107 @@
+use std::net::SocketAddr;
+
+use qstring::QString;
+
+use super::DebugViewerError;
+use crate::auth::tokens_match;
+
+pub const TOKEN_COOKIE_NAME: &str = "ridi_debug_viewer_token";
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct DebugViewerServeOptions {
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:1337")]
+    /// Address to serve the debug viewer on
+    pub addr: SocketAddr,
+
+    #[arg(long)]
+    /// Allow serving on a non loopback address, making the debug files readable from the network
+    pub public: bool,
+
+    #[arg(long, env = "RIDI_DEBUG_VIEWER_TOKEN", hide_env_values = true)]
+    /// Require this token, opening the viewer with `?token=<TOKEN>`, or with an
+    /// `Authorization: Bearer <TOKEN>` header
+    pub token: Option<String>,
+}
+
+/// Checks the token of the requests when the viewer was started with one. The token can be given
+/// with the `token` query parameter, which also sets a cookie for the requests of the UI, or with
+/// an `Authorization: Bearer` header
+pub struct DebugViewerAccess {
+    token: Option<String>,
+}
+
+impl DebugViewerAccess {
+    pub fn new(token: Option<String>) -> Self {
+        Self {
+            token: token.filter(|token| !token.is_empty()),
+        }
+    }
+
+    /// The debug files and the files of the UI dir are readable by anyone that can reach the
+    /// viewer, so only loopback addresses are allowed unless the viewer was made public
+    pub fn check_addr(addr: &SocketAddr, public: bool) -> Result<(), DebugViewerError> {
+        if !public && !addr.ip().is_loopback() {
+            return Err(DebugViewerError::NonLoopbackAddr { addr: *addr });
+        }
+        Ok(())
+    }
+
+    pub fn allows(&self, url: &str, authorization: Option<&str>, cookie: Option<&str>) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+        let bearer_token = authorization.and_then(|value| value.strip_prefix("Bearer "));
+        let cookie_token = cookie.and_then(|value| {
+            value.split(';').find_map(|cookie| {
+                cookie
+                    .trim()
+                    .strip_prefix(TOKEN_COOKIE_NAME)
+                    .and_then(|value| value.strip_prefix('='))
+            })
+        });
+        query_token(url).is_some_and(|given| tokens_match(&given, token))
+            || [bearer_token, cookie_token]
+                .into_iter()
+                .flatten()
+                .any(|given| tokens_match(given, token))
+    }
+
+    /// Cookie to set when the right token was given in the query, so the requests of the UI don't
+    /// need it
+    pub fn token_cookie(&self, url: &str) -> Option<String> {
+        let token = self.token.as_ref()?;
+        query_token(url)
+            .filter(|given| tokens_match(given, token))
+            .map(|token| format!("{TOKEN_COOKIE_NAME}={token}; Path=/; HttpOnly; SameSite=Strict"))
+    }
+}
+
+fn query_token(url: &str) -> Option<String> {
+    let (_, query) = url.split_once('?')?;
+    QString::from(query).get("token").map(str::to_string)
+}
+
+#[cfg(test)]
+mod test {
+    use super::DebugViewerAccess;
+
+    #[test]
+    fn debug_viewer_access_token() {
+        let open = DebugViewerAccess::new(None);
+        assert!(open.allows("/data/DebugStreamSteps", None, None));
+        assert_eq!(open.token_cookie("/?token=secret"), None);
+
+        let access = DebugViewerAccess::new(Some("secret".to_string()));
+        assert!(!access.allows("/data/DebugStreamSteps", None, None));
+        assert!(!access.allows("/?token=secrets", None, None));
+        assert!(access.allows("/?run=1&token=secret", None, None));
+        assert!(access.allows("/", Some("Bearer secret"), None));
+        assert!(!access.allows("/", Some("secret"), None));
+        assert!(access.allows("/", None, Some("other=1; ridi_debug_viewer_token=secret")));
+        assert_eq!(
+            access.token_cookie("/?token=secret").as_deref(),
+            Some("ridi_debug_viewer_token=secret; Path=/; HttpOnly; SameSite=Strict")
+        );
+        assert_eq!(access.token_cookie("/?token=secrets"), None);
+    }
+}
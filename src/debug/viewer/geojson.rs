@@ -0,0 +1,179 @@
+use serde_json::{json, Map, Value};
+
+use crate::debug::writer::{
+    DebugStreamForkChoiceWeights, DebugStreamForkChoices, DebugStreamItineraries,
+    DebugStreamItineraryWaypoints,
+};
+
+/// GeoJSON positions are longitude first
+fn position(lat: f64, lon: f64) -> Value {
+    json!([lon, lat])
+}
+
+fn point_feature(lat: f64, lon: f64, properties: Value) -> Value {
+    json!({
+        "type": "Feature",
+        "geometry": { "type": "Point", "coordinates": position(lat, lon) },
+        "properties": properties,
+    })
+}
+
+fn feature_collection(features: Vec<Value>) -> Value {
+    json!({ "type": "FeatureCollection", "features": features })
+}
+
+/// Route of the itinerary up to the step as a single feature with a line for every route chunk,
+/// with the start, finish and waypoints of the itinerary as points
+pub fn route_collection(
+    itinerary_id: &str,
+    step: u32,
+    route_chunks: &[Vec<(f64, f64)>],
+    itinerary: Option<&DebugStreamItineraries>,
+    waypoints: &[DebugStreamItineraryWaypoints],
+) -> Value {
+    let mut features = vec![json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "MultiLineString",
+            "coordinates": route_chunks
+                .iter()
+                .map(|chunk| chunk.iter().map(|(lat, lon)| position(*lat, *lon)).collect())
+                .collect::<Vec<Vec<_>>>(),
+        },
+        "properties": { "kind": "route", "itinerary_id": itinerary_id, "step": step },
+    })];
+    if let Some(itinerary) = itinerary {
+        features.push(point_feature(
+            f64::from(itinerary.start_lat),
+            f64::from(itinerary.start_lon),
+            json!({ "kind": "start", "itinerary_id": itinerary_id }),
+        ));
+        features.push(point_feature(
+            f64::from(itinerary.finish_lat),
+            f64::from(itinerary.finish_lon),
+            json!({
+                "kind": "finish",
+                "itinerary_id": itinerary_id,
+                "radius": itinerary.radius,
+            }),
+        ));
+    }
+    for waypoint in waypoints {
+        features.push(point_feature(
+            waypoint.lat,
+            waypoint.lon,
+            json!({ "kind": "waypoint", "itinerary_id": itinerary_id, "idx": waypoint.idx }),
+        ));
+    }
+    feature_collection(features)
+}
+
+/// Fork choices as lines, with the weights of each choice as `weight_<name>` and
+/// `weight_<name>_type` properties so they show up as columns in GIS tools
+pub fn fork_choices_collection(
+    fork_choices: &[DebugStreamForkChoices],
+    weights: &[DebugStreamForkChoiceWeights],
+) -> Value {
+    let features = fork_choices
+        .iter()
+        .map(|choice| {
+            let mut properties = Map::new();
+            properties.insert("itinerary_id".to_string(), json!(choice.itinerary_id));
+            properties.insert("step_num".to_string(), json!(choice.step_num));
+            properties.insert("end_point_id".to_string(), json!(choice.end_point_id));
+            properties.insert(
+                "segment_end_point".to_string(),
+                json!(choice.segment_end_point),
+            );
+            properties.insert("discarded".to_string(), json!(choice.discarded));
+            for weight in weights.iter().filter(|weight| {
+                weight.itinerary_id == choice.itinerary_id
+                    && weight.step_num == choice.step_num
+                    && weight.end_point_id == choice.end_point_id
+            }) {
+                properties.insert(
+                    format!("weight_{}", weight.weight_name),
+                    json!(weight.weight_value),
+                );
+                properties.insert(
+                    format!("weight_{}_type", weight.weight_name),
+                    json!(weight.weight_type),
+                );
+            }
+            json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "LineString",
+                    "coordinates": [
+                        position(choice.line_point_0_lat, choice.line_point_0_lon),
+                        position(choice.line_point_1_lat, choice.line_point_1_lon),
+                    ],
+                },
+                "properties": properties,
+            })
+        })
+        .collect();
+    feature_collection(features)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::debug::writer::{DebugStreamForkChoiceWeights, DebugStreamForkChoices};
+
+    use super::{fork_choices_collection, route_collection};
+
+    #[test]
+    fn debug_tables_to_geojson() {
+        let route = route_collection(
+            "it",
+            2,
+            &[
+                vec![(57.0, 24.0), (57.1, 24.1)],
+                vec![(57.1, 24.1), (57.2, 24.3)],
+            ],
+            None,
+            &[],
+        );
+        assert_eq!(
+            route["features"][0]["geometry"]["coordinates"],
+            json!([[[24.0, 57.0], [24.1, 57.1]], [[24.1, 57.1], [24.3, 57.2]]])
+        );
+
+        let choice = |end_point_id, discarded| DebugStreamForkChoices {
+            itinerary_id: "it".to_string(),
+            step_num: 2,
+            end_point_id,
+            line_point_0_lat: 57.0,
+            line_point_0_lon: 24.0,
+            line_point_1_lat: 57.1,
+            line_point_1_lon: 24.1,
+            segment_end_point: end_point_id,
+            discarded,
+        };
+        let fork_choices = fork_choices_collection(
+            &[choice(7, false), choice(8, true)],
+            &[DebugStreamForkChoiceWeights {
+                itinerary_id: "it".to_string(),
+                step_num: 2,
+                end_point_id: 7,
+                weight_name: "prefer_same_road".to_string(),
+                weight_type: "Continue".to_string(),
+                weight_value: 30,
+                duration_us: 12,
+            }],
+        );
+        let features = fork_choices["features"].as_array().unwrap();
+        assert_eq!(features.len(), 2);
+        assert_eq!(features[0]["properties"]["weight_prefer_same_road"], 30);
+        assert_eq!(
+            features[0]["properties"]["weight_prefer_same_road_type"],
+            "Continue"
+        );
+        assert_eq!(features[1]["properties"]["discarded"], true);
+        assert!(features[1]["properties"]
+            .get("weight_prefer_same_road")
+            .is_none());
+    }
+}
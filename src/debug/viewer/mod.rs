@@ -1,5 +1,6 @@
 use derive_name::Name;
 use duckdb::{params, Connection, Result, Row};
+#[cfg(feature = "debug-viewer-embedded-ui")]
 use include_directory::{include_directory, Dir};
 use qstring::QString;
 use serde::Serialize;
@@ -9,12 +10,14 @@ use std::{
     ffi::OsString,
     fs::{self, File},
     io::{self, Cursor, Read},
-    num::ParseIntError,
-    path::PathBuf,
+    net::SocketAddr,
+    num::{ParseFloatError, ParseIntError},
+    path::{Component, Path, PathBuf},
 };
 use struct_field_names_as_array::FieldNamesAsSlice;
 use tiny_http::{Header, Method, Request, Response, Server};
 use tracing::info;
+use typeshare::typeshare;
 
 use crate::debug::writer::{
     DebugStreamForkChoiceWeights, DebugStreamForkChoices, DebugStreamItineraries,
@@ -23,14 +26,50 @@ use crate::debug::writer::{
 
 use super::writer::DebugMetadata;
 
+pub mod auth;
+mod geojson;
+pub mod query;
+mod search;
+
+use auth::{DebugViewerAccess, DebugViewerServeOptions};
+use search::{search_limit, SearchFilter, StepSearchResult};
+
 const DATA_PREFIX: &str = "/data/";
+const EXPORT_PREFIX: &str = "/export/";
+const WEIGHT_TIMINGS_URL: &str = "/stats/weight-timings";
+const SEARCH_PREFIX: &str = "/search/";
 
+/// Built UI, embedded so the viewer works wherever the binary is installed
+#[cfg(feature = "debug-viewer-embedded-ui")]
 static DIST_DIR: Dir = include_directory!("$CARGO_MANIFEST_DIR/src/debug/viewer/ui/dist");
 
+/// Built UI in the source tree, served when the UI is not embedded
+#[cfg(not(feature = "debug-viewer-embedded-ui"))]
+const SOURCE_DIST_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/debug/viewer/ui/dist");
+
 fn url_for_debug_stream_name(name: &str) -> String {
     format!("{DATA_PREFIX}{name}")
 }
 
+/// Every debug dir is loaded into its own schema, so runs can be compared
+fn run_table(run: usize, name: &str) -> String {
+    format!("run{run}.{name}")
+}
+
+/// Run given with the `run` query parameter, the first one when not given
+fn query_run(query: &QString, runs: usize) -> Result<usize, DebugViewerError> {
+    let run = query
+        .get("run")
+        .map(|v| v.parse::<usize>())
+        .transpose()
+        .map_err(|error| DebugViewerError::Parse { error })?
+        .unwrap_or_default();
+    if run >= runs {
+        return Err(DebugViewerError::UnknownRun { run, runs });
+    }
+    Ok(run)
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum DebugViewerError {
     #[error("Could not start server: {error}")]
@@ -74,6 +113,9 @@ pub enum DebugViewerError {
     #[error("Could not parse number: {error}")]
     Parse { error: ParseIntError },
 
+    #[error("Could not parse number: {error}")]
+    ParseFloat { error: ParseFloatError },
+
     #[error("Missing query parameter: {param_name}")]
     MissingQueryParam { param_name: &'static str },
 
@@ -86,6 +128,12 @@ pub enum DebugViewerError {
     MetadataRead { error: io::Error },
     #[error("Metadata deserialize fail: {error}")]
     Deserialize { error: serde_json::Error },
+    #[error("No SQL or report given to query")]
+    MissingQuery,
+    #[error("Unknown run {run}, {runs} debug dirs are loaded")]
+    UnknownRun { run: usize, runs: usize },
+    #[error("Not serving the debug files on {addr}, a non loopback address, without --public")]
+    NonLoopbackAddr { addr: SocketAddr },
     #[error(
         "Debug data version {debug_data_version} does not match current version {current_version}"
     )]
@@ -96,18 +144,70 @@ pub enum DebugViewerError {
 }
 pub struct DebugViewer;
 
+/// Time spent in a weight calculation over all fork choices
+#[derive(Serialize)]
+#[typeshare]
+pub struct WeightTiming {
+    pub weight_name: String,
+    #[typeshare(serialized_as = "number")]
+    pub calc_count: i64,
+    #[typeshare(serialized_as = "number")]
+    pub total_us: i64,
+    pub avg_us: f64,
+    #[typeshare(serialized_as = "number")]
+    pub max_us: i64,
+}
+
 impl DebugViewer {
-    pub fn run(debug_dir: PathBuf) -> Result<(), DebugViewerError> {
+    /// Serves the debug files and the UI, from `ui_dir` when given, for developing the UI without
+    /// rebuilding the binary. Every debug dir is a run that can be selected with the `run` query
+    /// parameter, to compare the runs
+    pub fn run(
+        debug_dirs: Vec<PathBuf>,
+        ui_dir: Option<PathBuf>,
+        serve_options: DebugViewerServeOptions,
+    ) -> Result<(), DebugViewerError> {
+        DebugViewerAccess::check_addr(&serve_options.addr, serve_options.public)?;
+        let access = DebugViewerAccess::new(serve_options.token);
+
         let db_conn =
             Connection::open_in_memory().map_err(|error| DebugViewerError::DbOpen { error })?;
 
-        Self::prep_data(debug_dir, &db_conn)?;
+        let runs = debug_dirs.len();
+        let runs_json = serde_json::to_string(
+            &debug_dirs
+                .iter()
+                .enumerate()
+                .map(|(run, debug_dir)| {
+                    serde_json::json!({ "run": run, "debug_dir": debug_dir.to_string_lossy() })
+                })
+                .collect::<Vec<_>>(),
+        )
+        .map_err(|error| DebugViewerError::Serialize { error })?;
+        for (run, debug_dir) in debug_dirs.into_iter().enumerate() {
+            Self::prep_data(debug_dir, &db_conn, run)?;
+        }
 
-        let addr = "127.0.0.1:1337";
+        let addr = serve_options.addr;
         let server = Server::http(addr).map_err(|error| DebugViewerError::ServerStart { error })?;
-        info!(addr, "Running Debug Viewer on http://{addr}");
+        info!(%addr, "Running Debug Viewer on http://{addr}");
 
         for request in server.incoming_requests() {
+            let header = |name: &'static str| {
+                request
+                    .headers()
+                    .iter()
+                    .find(|header| header.field.equiv(name))
+                    .map(|header| header.value.as_str())
+            };
+            if !access.allows(request.url(), header("Authorization"), header("Cookie")) {
+                request
+                    .respond(Response::from_string("unauthorized").with_status_code(401))
+                    .map_err(|error| DebugViewerError::Respond { error })?;
+                continue;
+            }
+            let token_cookie = access.token_cookie(request.url());
+
             if request.method() != &Method::Get {
                 request
                     .respond(Response::from_string("not allowed").with_status_code(405))
@@ -115,8 +215,19 @@ impl DebugViewer {
                 continue;
             }
 
+            if request.url().starts_with("/runs") {
+                let response = Response::from_string(runs_json.clone()).with_header(
+                    Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+                        .map_err(|_| DebugViewerError::HeaderCreate)?,
+                );
+                request
+                    .respond(response)
+                    .map_err(|error| DebugViewerError::Respond { error })?;
+                continue;
+            }
+
             if request.url().starts_with(DATA_PREFIX) {
-                let response = match DebugViewer::handle_data_request(&request, &db_conn) {
+                let response = match DebugViewer::handle_data_request(&request, &db_conn, runs) {
                     Err(e) => {
                         request
                             .respond(Response::from_string(format!("{e:?}")).with_status_code(500))
@@ -131,8 +242,56 @@ impl DebugViewer {
                 continue;
             }
 
+            if request.url().starts_with(EXPORT_PREFIX) {
+                let response = match Self::handle_export_request(&request, &db_conn, runs) {
+                    Err(e) => {
+                        request
+                            .respond(Response::from_string(format!("{e:?}")).with_status_code(500))
+                            .map_err(|error| DebugViewerError::Respond { error })?;
+                        continue;
+                    }
+                    Ok(r) => r,
+                };
+                request
+                    .respond(response)
+                    .map_err(|error| DebugViewerError::Respond { error })?;
+                continue;
+            }
+
+            if request.url().starts_with(SEARCH_PREFIX) {
+                let response = match Self::handle_search_request(&request, &db_conn, runs) {
+                    Err(e) => {
+                        request
+                            .respond(Response::from_string(format!("{e:?}")).with_status_code(500))
+                            .map_err(|error| DebugViewerError::Respond { error })?;
+                        continue;
+                    }
+                    Ok(r) => r,
+                };
+                request
+                    .respond(response)
+                    .map_err(|error| DebugViewerError::Respond { error })?;
+                continue;
+            }
+
+            if request.url().starts_with(WEIGHT_TIMINGS_URL) {
+                let response = match Self::handle_weight_timings(&request, &db_conn, runs) {
+                    Err(e) => {
+                        request
+                            .respond(Response::from_string(format!("{e:?}")).with_status_code(500))
+                            .map_err(|error| DebugViewerError::Respond { error })?;
+                        continue;
+                    }
+                    Ok(r) => r,
+                };
+                request
+                    .respond(response)
+                    .map_err(|error| DebugViewerError::Respond { error })?;
+                continue;
+            }
+
             if request.url().starts_with("/calc/route") {
-                let response = match Self::handle_calc_route(&request, &db_conn) {
+                let response = match Self::handle_calc_route(&request, &db_conn, runs) {
                     Err(e) => {
                         request
                             .respond(Response::from_string(format!("{e:?}")).with_status_code(500))
@@ -147,7 +306,7 @@ impl DebugViewer {
                 continue;
             }
 
-            let response = match DebugViewer::handle_file_request(&request) {
+            let response = match DebugViewer::handle_file_request(&request, ui_dir.as_deref()) {
                 Err(e) => {
                     request
                         .respond(Response::from_string(format!("{e:?}")).with_status_code(500))
@@ -156,6 +315,13 @@ impl DebugViewer {
                 }
                 Ok(resp) => resp,
             };
+            let response = match token_cookie {
+                None => response,
+                Some(cookie) => response.with_header(
+                    Header::from_bytes(&b"Set-Cookie"[..], cookie.as_bytes())
+                        .map_err(|_| DebugViewerError::HeaderCreate)?,
+                ),
+            };
             request
                 .respond(response)
                 .map_err(|error| DebugViewerError::Respond { error })?;
@@ -200,7 +366,11 @@ impl DebugViewer {
         Ok(())
     }
 
-    fn prep_data(debug_dir: PathBuf, db_con: &Connection) -> Result<(), DebugViewerError> {
+    fn prep_data(
+        debug_dir: PathBuf,
+        db_con: &Connection,
+        run: usize,
+    ) -> Result<(), DebugViewerError> {
         let metadata_file_path =
             crate::debug::writer::DebugWriter::get_metadata_file_path(&debug_dir);
         let mut metadata_file = File::open(metadata_file_path)
@@ -213,6 +383,9 @@ impl DebugViewer {
                 current_version: env!("CARGO_PKG_VERSION"),
             });
         }
+        db_con
+            .execute(&format!("CREATE SCHEMA run{run};"), [])
+            .map_err(|error| DebugViewerError::DbStatementError { error })?;
         let dir_contents =
             fs::read_dir(debug_dir).map_err(|error| DebugViewerError::ReadDebugDir { error })?;
         let mut created_streams: Vec<String> = Vec::new();
@@ -233,7 +406,7 @@ impl DebugViewer {
                 Self::create_or_insert(
                     &db_con,
                     &mut created_streams,
-                    &DebugStreamSteps::name().to_string(),
+                    &run_table(run, DebugStreamSteps::name()),
                     &file_path,
                 )?;
             }
@@ -241,7 +414,7 @@ impl DebugViewer {
                 Self::create_or_insert(
                     &db_con,
                     &mut created_streams,
-                    &DebugStreamStepResults::name().to_string(),
+                    &run_table(run, DebugStreamStepResults::name()),
                     &file_path,
                 )?;
             }
@@ -249,7 +422,7 @@ impl DebugViewer {
                 Self::create_or_insert(
                     &db_con,
                     &mut created_streams,
-                    &DebugStreamItineraries::name().to_string(),
+                    &run_table(run, DebugStreamItineraries::name()),
                     &file_path,
                 )?;
             }
@@ -257,7 +430,7 @@ impl DebugViewer {
                 Self::create_or_insert(
                     &db_con,
                     &mut created_streams,
-                    &DebugStreamItineraryWaypoints::name().to_string(),
+                    &run_table(run, DebugStreamItineraryWaypoints::name()),
                     &file_path,
                 )?;
             }
@@ -265,7 +438,7 @@ impl DebugViewer {
                 Self::create_or_insert(
                     &db_con,
                     &mut created_streams,
-                    &DebugStreamForkChoices::name().to_string(),
+                    &run_table(run, DebugStreamForkChoices::name()),
                     &file_path,
                 )?;
             }
@@ -273,7 +446,7 @@ impl DebugViewer {
                 Self::create_or_insert(
                     &db_con,
                     &mut created_streams,
-                    &DebugStreamForkChoiceWeights::name().to_string(),
+                    &run_table(run, DebugStreamForkChoiceWeights::name()),
                     &file_path,
                 )?;
             }
@@ -283,6 +456,7 @@ impl DebugViewer {
 
     fn handle_data_for_table<F, T>(
         db_con: &Connection,
+        run: usize,
         table_name: &str,
         field_names: &[&str],
         query_itinerary_id: Option<String>,
@@ -295,7 +469,7 @@ impl DebugViewer {
         F: FnMut(&Row<'_>) -> Result<T>,
         T: Serialize,
     {
-        let mut sql = SqlBuilder::select_from(table_name);
+        let mut sql = SqlBuilder::select_from(run_table(run, table_name));
         let sql = sql.fields(field_names);
         let sql = if let Some(it_id) = query_itinerary_id {
             sql.and_where("itinerary_id = ?".binds(&[&it_id]))
@@ -359,9 +533,141 @@ impl DebugViewer {
         ))
     }
 
+    /// Fork choices, or the steps with fork choices, near a location or ending at a point, to find
+    /// the decisions made at a junction
+    fn handle_search_request(
+        request: &Request,
+        db_con: &Connection,
+        runs: usize,
+    ) -> Result<Response<Cursor<Vec<u8>>>, DebugViewerError> {
+        info!(
+            method = ?request.method(),
+            url = ?request.url(),
+            "received SEARCH request",
+        );
+        let query = request.url().split("?").collect::<Vec<_>>();
+        let query = query
+            .get(1)
+            .map_or_else(|| "?".to_string(), |v| format!("?{}", *v));
+        let query = QString::from(query.as_str());
+        let run = query_run(&query, runs)?;
+        let (filter, mut params) = SearchFilter::from_query(&query)?.sql("fc");
+        params.push(duckdb::types::Value::BigInt(search_limit(&query)?));
+
+        let url = request.url().split("?").next().unwrap_or_default();
+        let body = if url == format!("{SEARCH_PREFIX}forkchoices") {
+            let rows = Self::query_rows(
+                db_con,
+                &format!(
+                    "select {} from {} fc where {filter} order by itinerary_id, step_num limit ?",
+                    DebugStreamForkChoices::FIELD_NAMES_AS_SLICE
+                        .iter()
+                        .map(|field| format!("fc.{field}"))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    run_table(run, DebugStreamForkChoices::name())
+                ),
+                duckdb::params_from_iter(params),
+                fork_choice_from_row,
+            )?;
+            serde_json::to_string(&rows)
+        } else if url == format!("{SEARCH_PREFIX}steps") {
+            let rows = Self::query_rows(
+                db_con,
+                &format!(
+                    "select fc.itinerary_id, fc.step_num, any_value(sr.result),
+                            any_value(sr.chosen_fork_point_id)
+                        from {} fc
+                        left join {} sr
+                            on sr.itinerary_id = fc.itinerary_id and sr.step_num = fc.step_num
+                        where {filter}
+                        group by fc.itinerary_id, fc.step_num
+                        order by fc.itinerary_id, fc.step_num
+                        limit ?",
+                    run_table(run, DebugStreamForkChoices::name()),
+                    run_table(run, DebugStreamStepResults::name())
+                ),
+                duckdb::params_from_iter(params),
+                |row| {
+                    Ok(StepSearchResult {
+                        itinerary_id: row.get(0)?,
+                        step_num: row.get(1)?,
+                        result: row.get(2)?,
+                        chosen_fork_point_id: row.get(3)?,
+                    })
+                },
+            )?;
+            serde_json::to_string(&rows)
+        } else {
+            return Err(DebugViewerError::FileNotFound {
+                file_name: url.to_string(),
+            });
+        };
+
+        Ok(
+            Response::from_string(body.map_err(|error| DebugViewerError::Serialize { error })?)
+                .with_header(
+                    Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+                        .map_err(|_| DebugViewerError::HeaderCreate)?,
+                ),
+        )
+    }
+
+    /// Time spent per weight calculation, slowest first, for all itineraries or the one given with
+    /// `itinerary_id`
+    fn handle_weight_timings(
+        request: &Request,
+        db_con: &Connection,
+        runs: usize,
+    ) -> Result<Response<Cursor<Vec<u8>>>, DebugViewerError> {
+        info!(
+            method = ?request.method(),
+            url = ?request.url(),
+            "received WEIGHT TIMINGS request",
+        );
+        let query = request.url().split("?").collect::<Vec<_>>();
+        let query = query
+            .get(1)
+            .map_or_else(|| "?".to_string(), |v| format!("?{}", *v));
+        let query = QString::from(query.as_str());
+        let run = query_run(&query, runs)?;
+        let query_itinerary_id = query.get("itinerary_id").map(|v| v.to_string());
+
+        let rows = Self::query_rows(
+            db_con,
+            &format!(
+                "select weight_name, count(*), sum(duration_us)::bigint, avg(duration_us), max(duration_us)
+                    from {}
+                    where ?::varchar is null or itinerary_id = ?
+                    group by weight_name
+                    order by sum(duration_us) desc",
+                run_table(run, DebugStreamForkChoiceWeights::name())
+            ),
+            params![query_itinerary_id, query_itinerary_id],
+            |row| {
+                Ok(WeightTiming {
+                    weight_name: row.get(0)?,
+                    calc_count: row.get(1)?,
+                    total_us: row.get(2)?,
+                    avg_us: row.get(3)?,
+                    max_us: row.get(4)?,
+                })
+            },
+        )?;
+
+        Ok(Response::from_string(
+            serde_json::to_string(&rows).map_err(|error| DebugViewerError::Serialize { error })?,
+        )
+        .with_header(
+            Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+                .map_err(|_| DebugViewerError::HeaderCreate)?,
+        ))
+    }
+
     fn handle_calc_route(
         request: &Request,
         db_con: &Connection,
+        runs: usize,
     ) -> Result<Response<Cursor<Vec<u8>>>, DebugViewerError> {
         info!(
             method = ?request.method(),
@@ -373,6 +679,7 @@ impl DebugViewer {
             .get(1)
             .map_or_else(|| "?".to_string(), |v| format!("?{}", *v));
         let query = QString::from(query.as_str());
+        let run = query_run(&query, runs)?;
         let query_itinerary_id = query.get("itinerary_id").map(|v| v.to_string()).map_or(
             Err(DebugViewerError::MissingQueryParam {
                 param_name: "itinerary_id",
@@ -387,10 +694,11 @@ impl DebugViewer {
         )?;
 
         let mut statement = db_con
-            .prepare(
-                "select route from DebugStreamSteps
+            .prepare(&format!(
+                "select route from {}
                     where itinerary_id = ? and step_num <= ?",
-            )
+                run_table(run, DebugStreamSteps::name())
+            ))
             .map_err(|error| DebugViewerError::DbStatementError { error })?;
 
         let rows: Vec<String> = statement
@@ -412,9 +720,162 @@ impl DebugViewer {
         ))
     }
 
+    /// Route up to a step or the fork choices of the itinerary as a GeoJSON FeatureCollection, for
+    /// inspecting them in GIS tools or sharing them as files
+    fn handle_export_request(
+        request: &Request,
+        db_con: &Connection,
+        runs: usize,
+    ) -> Result<Response<Cursor<Vec<u8>>>, DebugViewerError> {
+        info!(
+            method = ?request.method(),
+            url = ?request.url(),
+            "received EXPORT request",
+        );
+        let query = request.url().split("?").collect::<Vec<_>>();
+        let query = query
+            .get(1)
+            .map_or_else(|| "?".to_string(), |v| format!("?{}", *v));
+        let query = QString::from(query.as_str());
+        let run = query_run(&query, runs)?;
+        let query_itinerary_id = query.get("itinerary_id").map(|v| v.to_string()).ok_or(
+            DebugViewerError::MissingQueryParam {
+                param_name: "itinerary_id",
+            },
+        )?;
+        let query_step = query
+            .get("step")
+            .map(|v| v.parse::<u32>())
+            .transpose()
+            .map_err(|error| DebugViewerError::Parse { error })?;
+
+        let url = request.url().split("?").next().unwrap_or_default();
+        let (file_name, collection) = if url == format!("{EXPORT_PREFIX}route.geojson") {
+            let step =
+                query_step.ok_or(DebugViewerError::MissingQueryParam { param_name: "step" })?;
+            let route_chunks = Self::query_rows(
+                db_con,
+                &format!(
+                    "select route from {} where itinerary_id = ? and step_num <= ? order by step_num",
+                    run_table(run, DebugStreamSteps::name())
+                ),
+                params![query_itinerary_id, step],
+                |row| row.get::<usize, String>(0),
+            )?
+            .iter()
+            .map(|route| serde_json::from_str::<Vec<(f64, f64)>>(route))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| DebugViewerError::SerdeDesRouteChunks { error })?;
+            let itinerary = Self::query_rows(
+                db_con,
+                &format!(
+                    "select {} from {} where itinerary_id = ?",
+                    DebugStreamItineraries::FIELD_NAMES_AS_SLICE.join(", "),
+                    run_table(run, DebugStreamItineraries::name())
+                ),
+                params![query_itinerary_id],
+                itinerary_from_row,
+            )?;
+            let waypoints = Self::query_rows(
+                db_con,
+                &format!(
+                    "select {} from {} where itinerary_id = ? order by idx",
+                    DebugStreamItineraryWaypoints::FIELD_NAMES_AS_SLICE.join(", "),
+                    run_table(run, DebugStreamItineraryWaypoints::name())
+                ),
+                params![query_itinerary_id],
+                itinerary_waypoint_from_row,
+            )?;
+            (
+                format!("route-{query_itinerary_id}-{step}.geojson"),
+                geojson::route_collection(
+                    &query_itinerary_id,
+                    step,
+                    &route_chunks,
+                    itinerary.first(),
+                    &waypoints,
+                ),
+            )
+        } else if url == format!("{EXPORT_PREFIX}forkchoices.geojson") {
+            // all steps of the itinerary when no step is given
+            let step_filter = "itinerary_id = ? and (?::integer is null or step_num = ?)";
+            let fork_choices = Self::query_rows(
+                db_con,
+                &format!(
+                    "select {} from {} where {step_filter} order by step_num",
+                    DebugStreamForkChoices::FIELD_NAMES_AS_SLICE.join(", "),
+                    run_table(run, DebugStreamForkChoices::name())
+                ),
+                params![query_itinerary_id, query_step, query_step],
+                fork_choice_from_row,
+            )?;
+            let weights = Self::query_rows(
+                db_con,
+                &format!(
+                    "select {} from {} where {step_filter}",
+                    DebugStreamForkChoiceWeights::FIELD_NAMES_AS_SLICE.join(", "),
+                    run_table(run, DebugStreamForkChoiceWeights::name())
+                ),
+                params![query_itinerary_id, query_step, query_step],
+                fork_choice_weight_from_row,
+            )?;
+            (
+                match query_step {
+                    Some(step) => format!("forkchoices-{query_itinerary_id}-{step}.geojson"),
+                    None => format!("forkchoices-{query_itinerary_id}.geojson"),
+                },
+                geojson::fork_choices_collection(&fork_choices, &weights),
+            )
+        } else {
+            return Err(DebugViewerError::FileNotFound {
+                file_name: url.to_string(),
+            });
+        };
+
+        Ok(Response::from_string(
+            serde_json::to_string(&collection)
+                .map_err(|error| DebugViewerError::Serialize { error })?,
+        )
+        .with_header(
+            Header::from_bytes(&b"Content-Type"[..], &b"application/geo+json"[..])
+                .map_err(|_| DebugViewerError::HeaderCreate)?,
+        )
+        .with_header(
+            Header::from_bytes(
+                &b"Content-Disposition"[..],
+                format!("inline; filename=\"{file_name}\"").as_bytes(),
+            )
+            .map_err(|_| DebugViewerError::HeaderCreate)?,
+        ))
+    }
+
+    fn query_rows<P, F, T>(
+        db_con: &Connection,
+        sql: &str,
+        params: P,
+        map_row: F,
+    ) -> Result<Vec<T>, DebugViewerError>
+    where
+        P: duckdb::Params,
+        F: FnMut(&Row<'_>) -> Result<T>,
+    {
+        info!(sql = sql, "Executing sql");
+        let mut statement = db_con
+            .prepare(sql)
+            .map_err(|error| DebugViewerError::DbStatementError { error })?;
+
+        let rows = statement
+            .query_map(params, map_row)
+            .map_err(|error| DebugViewerError::DbStatementError { error })?
+            .collect::<Result<Vec<_>>>()
+            .map_err(|error| DebugViewerError::DbStatementError { error })?;
+        Ok(rows)
+    }
+
     fn handle_data_request(
         request: &Request,
         db_con: &Connection,
+        runs: usize,
     ) -> Result<Response<Cursor<Vec<u8>>>, DebugViewerError> {
         info!(
             method = ?request.method(),
@@ -426,6 +887,7 @@ impl DebugViewer {
             .get(1)
             .map_or_else(|| "?".to_string(), |v| format!("?{}", *v));
         let query = QString::from(query.as_str());
+        let run = query_run(&query, runs)?;
         let query_itinerary_id = query.get("itinerary_id").map(|v| v.to_string());
         let query_step_num = query
             .get("step_num")
@@ -464,6 +926,7 @@ impl DebugViewer {
         {
             Ok(Self::handle_data_for_table(
                 &db_con,
+                run,
                 DebugStreamSteps::name(),
                 DebugStreamSteps::FIELD_NAMES_AS_SLICE,
                 query_itinerary_id,
@@ -485,6 +948,7 @@ impl DebugViewer {
         {
             Ok(Self::handle_data_for_table(
                 &db_con,
+                run,
                 DebugStreamStepResults::name(),
                 DebugStreamStepResults::FIELD_NAMES_AS_SLICE,
                 query_itinerary_id,
@@ -506,47 +970,28 @@ impl DebugViewer {
         {
             Ok(Self::handle_data_for_table(
                 &db_con,
+                run,
                 DebugStreamForkChoices::name(),
                 DebugStreamForkChoices::FIELD_NAMES_AS_SLICE,
                 query_itinerary_id,
                 query_limit,
                 query_offset,
                 query_step_num,
-                |row| {
-                    Ok(DebugStreamForkChoices {
-                        itinerary_id: row.get(0)?,
-                        step_num: row.get(1)?,
-                        end_point_id: row.get(2)?,
-                        line_point_0_lat: row.get(3)?,
-                        line_point_0_lon: row.get(4)?,
-                        line_point_1_lat: row.get(5)?,
-                        line_point_1_lon: row.get(6)?,
-                        segment_end_point: row.get(7)?,
-                        discarded: row.get(8)?,
-                    })
-                },
+                fork_choice_from_row,
             )?)
         } else if request.url().starts_with(&url_for_debug_stream_name(
             DebugStreamForkChoiceWeights::name(),
         )) {
             Ok(Self::handle_data_for_table(
                 &db_con,
+                run,
                 DebugStreamForkChoiceWeights::name(),
                 DebugStreamForkChoiceWeights::FIELD_NAMES_AS_SLICE,
                 query_itinerary_id,
                 query_limit,
                 query_offset,
                 query_step_num,
-                |row| {
-                    Ok(DebugStreamForkChoiceWeights {
-                        itinerary_id: row.get(0)?,
-                        step_num: row.get(1)?,
-                        end_point_id: row.get(2)?,
-                        weight_name: row.get(3)?,
-                        weight_type: row.get(4)?,
-                        weight_value: row.get(5)?,
-                    })
-                },
+                fork_choice_weight_from_row,
             )?)
         } else if request
             .url()
@@ -554,43 +999,28 @@ impl DebugViewer {
         {
             Ok(Self::handle_data_for_table(
                 &db_con,
+                run,
                 DebugStreamItineraries::name(),
                 DebugStreamItineraries::FIELD_NAMES_AS_SLICE,
                 query_itinerary_id,
                 query_limit,
                 query_offset,
                 query_step_num,
-                |row| {
-                    Ok(DebugStreamItineraries {
-                        itinerary_id: row.get(0)?,
-                        waypoints_count: row.get(1)?,
-                        radius: row.get(2)?,
-                        start_lat: row.get(3)?,
-                        start_lon: row.get(4)?,
-                        finish_lat: row.get(5)?,
-                        finish_lon: row.get(6)?,
-                    })
-                },
+                itinerary_from_row,
             )?)
         } else if request.url().starts_with(&url_for_debug_stream_name(
             DebugStreamItineraryWaypoints::name(),
         )) {
             Ok(Self::handle_data_for_table(
                 &db_con,
+                run,
                 DebugStreamItineraryWaypoints::name(),
                 DebugStreamItineraryWaypoints::FIELD_NAMES_AS_SLICE,
                 query_itinerary_id,
                 query_limit,
                 query_offset,
                 query_step_num,
-                |row| {
-                    Ok(DebugStreamItineraryWaypoints {
-                        itinerary_id: row.get(0)?,
-                        idx: row.get(1)?,
-                        lat: row.get(2)?,
-                        lon: row.get(3)?,
-                    })
-                },
+                itinerary_waypoint_from_row,
             )?)
         } else {
             Err(DebugViewerError::Unexpected)?
@@ -599,6 +1029,7 @@ impl DebugViewer {
 
     fn handle_file_request(
         request: &Request,
+        ui_dir: Option<&Path>,
     ) -> Result<Response<Cursor<Vec<u8>>>, DebugViewerError> {
         info!(
             method = ?request.method(),
@@ -606,31 +1037,127 @@ impl DebugViewer {
             "received FILE request",
         );
 
-        let mut file_name = request.url().to_string();
-        loop {
-            let file_name_len = file_name.len();
-            file_name = file_name.replace("../", "");
-            file_name = file_name.replace("./", "");
-            if file_name.len() == file_name_len {
-                break;
-            }
-        }
-        let file_name = file_name[1..].to_string();
-        let file_name = if file_name == "" {
+        // the UI reads the run from the query
+        let file_name = request
+            .url()
+            .split("?")
+            .next()
+            .unwrap_or_default()
+            .trim_start_matches('/')
+            .to_string();
+        let file_name = if file_name.is_empty() {
             "index.html".to_string()
         } else {
             file_name
         };
 
-        let file = DIST_DIR
-            .get_file(&file_name)
-            .map_or(Err(DebugViewerError::FileNotFound { file_name }), |v| Ok(v))?;
-        let mime_type = file.mimetype().to_string();
-        let file_contents = file.contents_utf8().unwrap();
+        if !Path::new(&file_name)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(DebugViewerError::FileNotFound { file_name });
+        }
+
+        let file_contents = Self::read_ui_file(&file_name, ui_dir).ok_or_else(|| {
+            DebugViewerError::FileNotFound {
+                file_name: file_name.clone(),
+            }
+        })?;
 
-        Ok(Response::from_string(file_contents).with_header(
-            Header::from_bytes(&b"Content-Type"[..], &mime_type.as_bytes()[..])
+        Ok(Response::from_data(file_contents).with_header(
+            Header::from_bytes(&b"Content-Type"[..], mime_type(&file_name).as_bytes())
                 .map_err(|_| DebugViewerError::HeaderCreate)?,
         ))
     }
+
+    #[cfg(feature = "debug-viewer-embedded-ui")]
+    fn read_ui_file(file_name: &str, ui_dir: Option<&Path>) -> Option<Vec<u8>> {
+        match ui_dir {
+            Some(ui_dir) => read_dir_file(ui_dir, file_name),
+            None => DIST_DIR
+                .get_file(file_name)
+                .map(|file| file.contents().to_vec()),
+        }
+    }
+
+    #[cfg(not(feature = "debug-viewer-embedded-ui"))]
+    fn read_ui_file(file_name: &str, ui_dir: Option<&Path>) -> Option<Vec<u8>> {
+        let ui_dir = ui_dir.unwrap_or(Path::new(SOURCE_DIST_DIR));
+        read_dir_file(ui_dir, file_name)
+    }
+}
+
+/// Reads a file of the dir, not following links out of it
+fn read_dir_file(dir: &Path, file_name: &str) -> Option<Vec<u8>> {
+    let dir = dir.canonicalize().ok()?;
+    let path = dir.join(file_name).canonicalize().ok()?;
+    if !path.starts_with(&dir) {
+        return None;
+    }
+    fs::read(path).ok()
+}
+
+fn fork_choice_from_row(row: &Row<'_>) -> Result<DebugStreamForkChoices> {
+    Ok(DebugStreamForkChoices {
+        itinerary_id: row.get(0)?,
+        step_num: row.get(1)?,
+        end_point_id: row.get(2)?,
+        line_point_0_lat: row.get(3)?,
+        line_point_0_lon: row.get(4)?,
+        line_point_1_lat: row.get(5)?,
+        line_point_1_lon: row.get(6)?,
+        segment_end_point: row.get(7)?,
+        discarded: row.get(8)?,
+    })
+}
+
+fn fork_choice_weight_from_row(row: &Row<'_>) -> Result<DebugStreamForkChoiceWeights> {
+    Ok(DebugStreamForkChoiceWeights {
+        itinerary_id: row.get(0)?,
+        step_num: row.get(1)?,
+        end_point_id: row.get(2)?,
+        weight_name: row.get(3)?,
+        weight_type: row.get(4)?,
+        weight_value: row.get(5)?,
+        duration_us: row.get(6)?,
+    })
+}
+
+fn itinerary_from_row(row: &Row<'_>) -> Result<DebugStreamItineraries> {
+    Ok(DebugStreamItineraries {
+        itinerary_id: row.get(0)?,
+        waypoints_count: row.get(1)?,
+        radius: row.get(2)?,
+        start_lat: row.get(3)?,
+        start_lon: row.get(4)?,
+        finish_lat: row.get(5)?,
+        finish_lon: row.get(6)?,
+    })
+}
+
+fn itinerary_waypoint_from_row(row: &Row<'_>) -> Result<DebugStreamItineraryWaypoints> {
+    Ok(DebugStreamItineraryWaypoints {
+        itinerary_id: row.get(0)?,
+        idx: row.get(1)?,
+        lat: row.get(2)?,
+        lon: row.get(3)?,
+    })
+}
+
+fn mime_type(file_name: &str) -> &'static str {
+    match Path::new(file_name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") | Some("mjs") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("json") | Some("map") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        Some("woff2") => "font/woff2",
+        Some("wasm") => "application/wasm",
+        _ => "application/octet-stream",
+    }
 }
@@ -0,0 +1,214 @@
+use std::path::PathBuf;
+
+use duckdb::{types::Value, Connection};
+use serde_json::{Map, Number};
+
+use super::{DebugViewer, DebugViewerError};
+
+/// Canned queries for the debug files
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum DebugReport {
+    /// Steps and the last step of every itinerary, the longest first
+    StepsPerItinerary,
+    /// Step results like fork choices, move backs and timeouts, the most common first
+    StepResults,
+    /// Weight calculations that ruled out fork choices, the most common first
+    DiscardedForkReasons,
+    /// Time spent per weight calculation, the slowest first
+    WeightTimings,
+}
+
+impl DebugReport {
+    fn sql(&self) -> &'static str {
+        match self {
+            Self::StepsPerItinerary => {
+                "select itinerary_id, count(*) as steps, max(step_num) as last_step
+                    from DebugStreamSteps
+                    group by itinerary_id
+                    order by steps desc"
+            }
+            Self::StepResults => {
+                "select result, count(*) as steps
+                    from DebugStreamStepResults
+                    group by result
+                    order by steps desc"
+            }
+            Self::DiscardedForkReasons => {
+                "select weight_name, count(*) as discarded_choices
+                    from DebugStreamForkChoiceWeights
+                    where weight_type = 'ForkChoiceDoNotUse'
+                    group by weight_name
+                    order by discarded_choices desc"
+            }
+            Self::WeightTimings => {
+                "select weight_name, count(*) as calc_count, sum(duration_us)::bigint as total_us,
+                        avg(duration_us) as avg_us, max(duration_us) as max_us
+                    from DebugStreamForkChoiceWeights
+                    group by weight_name
+                    order by total_us desc"
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum DebugQueryFormat {
+    /// Columns aligned for reading in a terminal
+    #[default]
+    Table,
+    /// Array with an object for every row
+    Json,
+}
+
+/// Runs a query on the debug files and prints the result, without starting the viewer
+pub struct DebugQuery;
+
+impl DebugQuery {
+    pub fn run(
+        debug_dir: PathBuf,
+        sql: Option<String>,
+        report: Option<DebugReport>,
+        format: DebugQueryFormat,
+    ) -> Result<(), DebugViewerError> {
+        let db_conn =
+            Connection::open_in_memory().map_err(|error| DebugViewerError::DbOpen { error })?;
+        DebugViewer::prep_data(debug_dir, &db_conn, 0)?;
+        // the debug streams can be queried without the schema of the run
+        db_conn
+            .execute("SET search_path = 'run0';", [])
+            .map_err(|error| DebugViewerError::DbStatementError { error })?;
+
+        let sql = match (sql, report) {
+            (Some(sql), _) => sql,
+            (None, Some(report)) => report.sql().to_string(),
+            (None, None) => return Err(DebugViewerError::MissingQuery),
+        };
+        let (columns, rows) = Self::query(&db_conn, &sql)?;
+
+        match format {
+            DebugQueryFormat::Table => print!("{}", format_table(&columns, &rows)),
+            DebugQueryFormat::Json => println!(
+                "{}",
+                serde_json::to_string_pretty(&to_json(&columns, rows))
+                    .map_err(|error| DebugViewerError::Serialize { error })?
+            ),
+        }
+        Ok(())
+    }
+
+    fn query(
+        db_con: &Connection,
+        sql: &str,
+    ) -> Result<(Vec<String>, Vec<Vec<Value>>), DebugViewerError> {
+        let mut statement = db_con
+            .prepare(sql)
+            .map_err(|error| DebugViewerError::DbStatementError { error })?;
+        let mut query_rows = statement
+            .query([])
+            .map_err(|error| DebugViewerError::DbStatementError { error })?;
+        let columns = query_rows
+            .as_ref()
+            .map(|statement| statement.column_names())
+            .unwrap_or_default();
+
+        let mut rows = Vec::new();
+        while let Some(row) = query_rows
+            .next()
+            .map_err(|error| DebugViewerError::DbStatementError { error })?
+        {
+            let values = (0..columns.len())
+                .map(|idx| row.get::<usize, Value>(idx))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|error| DebugViewerError::DbStatementError { error })?;
+            rows.push(values);
+        }
+        Ok((columns, rows))
+    }
+}
+
+fn value_to_json(value: Value) -> serde_json::Value {
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Boolean(v) => v.into(),
+        Value::TinyInt(v) => v.into(),
+        Value::SmallInt(v) => v.into(),
+        Value::Int(v) => v.into(),
+        Value::BigInt(v) => v.into(),
+        Value::UTinyInt(v) => v.into(),
+        Value::USmallInt(v) => v.into(),
+        Value::UInt(v) => v.into(),
+        Value::UBigInt(v) => v.into(),
+        Value::HugeInt(v) => i64::try_from(v).map_or_else(|_| v.to_string().into(), Into::into),
+        Value::Float(v) => {
+            Number::from_f64(f64::from(v)).map_or(serde_json::Value::Null, Into::into)
+        }
+        Value::Double(v) => Number::from_f64(v).map_or(serde_json::Value::Null, Into::into),
+        Value::Text(v) | Value::Enum(v) => v.into(),
+        Value::List(values) => values.into_iter().map(value_to_json).collect(),
+        other => format!("{other:?}").into(),
+    }
+}
+
+fn to_json(columns: &[String], rows: Vec<Vec<Value>>) -> serde_json::Value {
+    rows.into_iter()
+        .map(|row| {
+            columns
+                .iter()
+                .cloned()
+                .zip(row.into_iter().map(value_to_json))
+                .collect::<Map<_, _>>()
+        })
+        .collect()
+}
+
+fn value_to_cell(value: &Value) -> String {
+    match value_to_json(value.clone()) {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(v) => v,
+        v => v.to_string(),
+    }
+}
+
+/// Columns padded to the widest value, with a line under the header
+fn format_table(columns: &[String], rows: &[Vec<Value>]) -> String {
+    let cells = rows
+        .iter()
+        .map(|row| row.iter().map(value_to_cell).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let widths = columns
+        .iter()
+        .enumerate()
+        .map(|(idx, column)| {
+            cells
+                .iter()
+                .filter_map(|row| row.get(idx))
+                .map(|cell| cell.chars().count())
+                .chain(std::iter::once(column.chars().count()))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+    let line = |values: &[String]| {
+        values
+            .iter()
+            .zip(&widths)
+            .map(|(value, &width)| format!("{value:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+            + "\n"
+    };
+
+    let mut table = line(columns);
+    table += &line(
+        &widths
+            .iter()
+            .map(|width| "-".repeat(*width))
+            .collect::<Vec<_>>(),
+    );
+    for row in &cells {
+        table += &line(row);
+    }
+    table
+}
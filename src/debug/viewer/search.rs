@@ -0,0 +1,87 @@
+use duckdb::types::Value;
+use qstring::QString;
+use serde::Serialize;
+use typeshare::typeshare;
+
+use super::DebugViewerError;
+
+const EARTH_RADIUS_M: f64 = 6371000.;
+/// Results returned when no `limit` is given
+const DEFAULT_SEARCH_LIMIT: i64 = 500;
+
+/// Step with fork choices that matched a search
+#[derive(Serialize)]
+#[typeshare]
+pub struct StepSearchResult {
+    pub itinerary_id: String,
+    #[typeshare(serialized_as = "number")]
+    pub step_num: i64,
+    pub result: Option<String>,
+    #[typeshare(serialized_as = "number")]
+    pub chosen_fork_point_id: Option<i64>,
+}
+
+/// Fork choices with a line point within `radius_m` of `lat`,`lon`, or ending at `end_point_id`
+pub enum SearchFilter {
+    Near { lat: f64, lon: f64, radius_m: f64 },
+    EndPoint { end_point_id: i64 },
+}
+
+impl SearchFilter {
+    pub fn from_query(query: &QString) -> Result<Self, DebugViewerError> {
+        let float_param = |param_name: &'static str| {
+            query
+                .get(param_name)
+                .ok_or(DebugViewerError::MissingQueryParam { param_name })?
+                .parse::<f64>()
+                .map_err(|error| DebugViewerError::ParseFloat { error })
+        };
+        if let Some(end_point_id) = query.get("end_point_id") {
+            return Ok(Self::EndPoint {
+                end_point_id: end_point_id
+                    .parse()
+                    .map_err(|error| DebugViewerError::Parse { error })?,
+            });
+        }
+        Ok(Self::Near {
+            lat: float_param("lat")?,
+            lon: float_param("lon")?,
+            radius_m: float_param("radius_m")?,
+        })
+    }
+
+    /// Condition on the fork choice columns of the table alias, with its parameters
+    pub fn sql(&self, alias: &str) -> (String, Vec<Value>) {
+        match self {
+            Self::EndPoint { end_point_id } => (
+                format!("{alias}.end_point_id = ?"),
+                vec![Value::BigInt(*end_point_id)],
+            ),
+            Self::Near { lat, lon, radius_m } => {
+                // equirectangular distance, precise enough at the scale of a junction
+                let distance = |point: u8| {
+                    format!(
+                        "{EARTH_RADIUS_M} * sqrt(pow(radians({alias}.line_point_{point}_lat - ?), 2)
+                            + pow(radians({alias}.line_point_{point}_lon - ?) * cos(radians(?)), 2))"
+                    )
+                };
+                (
+                    format!("least({}, {}) <= ?", distance(0), distance(1)),
+                    [*lat, *lon, *lat, *lat, *lon, *lat, *radius_m]
+                        .into_iter()
+                        .map(Value::Double)
+                        .collect(),
+                )
+            }
+        }
+    }
+}
+
+pub fn search_limit(query: &QString) -> Result<i64, DebugViewerError> {
+    query
+        .get("limit")
+        .map(|limit| limit.parse::<i64>())
+        .transpose()
+        .map_err(|error| DebugViewerError::Parse { error })
+        .map(|limit| limit.unwrap_or(DEFAULT_SEARCH_LIMIT))
+}
@@ -9,6 +9,8 @@ export interface DebugStreamForkChoiceWeights {
   weight_name: string;
   weight_type: string;
   weight_value: number;
+  /** Time the weight calculation took in microseconds */
+  duration_us: number;
 }
 
 export interface DebugStreamForkChoices {
@@ -54,3 +56,19 @@ export interface DebugStreamSteps {
   route: string;
 }
 
+/** Step with fork choices that matched a search */
+export interface StepSearchResult {
+  itinerary_id: string;
+  step_num: number;
+  result?: string;
+  chosen_fork_point_id?: number;
+}
+
+/** Time spent in a weight calculation over all fork choices */
+export interface WeightTiming {
+  weight_name: string;
+  calc_count: number;
+  total_us: number;
+  avg_us: number;
+  max_us: number;
+}
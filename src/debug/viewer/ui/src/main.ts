@@ -23,7 +23,11 @@ import {
 } from "./styles/table";
 import { Pagination } from "./components/pagination";
 
-const { button, thead, tbody, div, table, td, th, tr } = van.tags;
+const { button, iframe, thead, tbody, div, table, td, th, tr } = van.tags;
+
+// debug dir to show when the viewer was started with more than one
+const query = new URLSearchParams(window.location.search);
+const run = query.get("run") ?? "0";
 
 const selection = van.state<SelectionState>({
   itinerary: null,
@@ -44,7 +48,7 @@ const Itineraries = () => {
 
   van.derive(() =>
     fetch(
-      `http://127.0.0.1:1337/data/DebugStreamItineraries?limit=${pageSize}&offset=${page.val * pageSize}`,
+      `http://127.0.0.1:1337/data/DebugStreamItineraries?limit=${pageSize}&offset=${page.val * pageSize}&run=${run}`,
     )
       .then((req) => req.json())
       .then((data) => (itineraries.val = data))
@@ -120,7 +124,7 @@ const ItineraryWaypoints = () => {
       selection.val.itinerary.itinerary_id !=
         selection.oldVal.itinerary?.itinerary_id &&
       fetch(
-        `http://127.0.0.1:1337/data/DebugStreamItineraryWaypoints?itinerary_id=${selection.val.itinerary.itinerary_id}&limit=${pageSize}&offset=${page.val * pageSize}`,
+        `http://127.0.0.1:1337/data/DebugStreamItineraryWaypoints?itinerary_id=${selection.val.itinerary.itinerary_id}&limit=${pageSize}&offset=${page.val * pageSize}&run=${run}`,
       )
         .then((req) => req.json())
         .then((data) => (itineraryWaypoints.val = data))
@@ -241,7 +245,7 @@ const Steps = () => {
         selection.oldVal.itinerary?.itinerary_id ||
         page.val !== page.oldVal) &&
       fetch(
-        `http://127.0.0.1:1337/data/DebugStreamSteps?itinerary_id=${selection.val.itinerary.itinerary_id}&limit=${pageSize}&offset=${page.val * pageSize}`,
+        `http://127.0.0.1:1337/data/DebugStreamSteps?itinerary_id=${selection.val.itinerary.itinerary_id}&limit=${pageSize}&offset=${page.val * pageSize}&run=${run}`,
       )
         .then((req) => req.json())
         .then((data) => (steps.val = data))
@@ -254,7 +258,7 @@ const Steps = () => {
       selection.val.step.step_num != selection.oldVal.step?.step_num;
     !!selection.val.itinerary &&
       fetch(
-        `http://127.0.0.1:1337/calc/route?itinerary_id=${selection.val.itinerary.itinerary_id}&step=${selection.val.step?.step_num}`,
+        `http://127.0.0.1:1337/calc/route?itinerary_id=${selection.val.itinerary.itinerary_id}&step=${selection.val.step?.step_num}&run=${run}`,
       )
         .then((resp) => resp.json())
         .then((data) => {
@@ -349,7 +353,7 @@ const ForkChoiceWeights = (
 ) => {
   const forkChoiceWeights = van.state<DebugStreamForkChoiceWeights[]>([]);
   fetch(
-    `http://127.0.0.1:1337/data/DebugStreamForkChoiceWeights?itinerary_id=${itineraryId}&step_num=${stepNum}`,
+    `http://127.0.0.1:1337/data/DebugStreamForkChoiceWeights?itinerary_id=${itineraryId}&step_num=${stepNum}&run=${run}`,
   )
     .then((resp) => resp.json())
     .then((data) => {
@@ -368,6 +372,7 @@ const ForkChoiceWeights = (
           th({ class: thClass() }, "weight name"),
           th({ class: thClass() }, "weight type"),
           th({ class: thClass() }, "weight value"),
+          th({ class: thClass() }, "time us"),
         ),
       ),
       tbody(
@@ -376,6 +381,7 @@ const ForkChoiceWeights = (
             td({ class: tdClass() }, forkChW.weight_name),
             td({ class: tdClass() }, forkChW.weight_type),
             td({ class: tdClass() }, forkChW.weight_value),
+            td({ class: tdClass() }, forkChW.duration_us),
           ]),
         ),
       ),
@@ -386,7 +392,7 @@ const ForkChoiceWeights = (
 const ForkChoices = (itineraryId: string, stepNum: number) => {
   const forkCHoices = van.state<DebugStreamForkChoices[]>([]);
   fetch(
-    `http://127.0.0.1:1337/data/DebugStreamForkChoices?itinerary_id=${itineraryId}&step_num=${stepNum}`,
+    `http://127.0.0.1:1337/data/DebugStreamForkChoices?itinerary_id=${itineraryId}&step_num=${stepNum}&run=${run}`,
   )
     .then((resp) => resp.json())
     .then((data) => (forkCHoices.val = data));
@@ -478,7 +484,7 @@ const ForkChoices = (itineraryId: string, stepNum: number) => {
 const StepResult = (itineraryId: string, stepNum: number) => {
   const stepResults = van.state<DebugStreamStepResults[]>([]);
   fetch(
-    `http://127.0.0.1:1337/data/DebugStreamStepResults?itinerary_id=${itineraryId}&step_num=${stepNum}`,
+    `http://127.0.0.1:1337/data/DebugStreamStepResults?itinerary_id=${itineraryId}&step_num=${stepNum}&run=${run}`,
   )
     .then((resp) => resp.json())
     .then((data) => (stepResults.val = data));
@@ -677,7 +683,18 @@ const MapContainer = () => {
   return mapContainer;
 };
 
+const Compare = () =>
+  div(
+    { class: "flex flex-row h-screen" },
+    ...["0", "1"].map((run) =>
+      iframe({ class: "w-1/2 h-full border", src: `/?run=${run}` }),
+    ),
+  );
+
 const App = () => {
+  if (query.has("compare")) {
+    return Compare();
+  }
   return div(
     { class: "flex flex-col lg:flex-row" },
     div(Itineraries(), ItineraryWaypoints(), Steps()),
@@ -1,11 +1,16 @@
 use derive_name::Name;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{hash_map::Entry, HashMap},
     fs::File,
-    io::{self, Write},
+    io::{self, BufWriter, Write},
     path::PathBuf,
-    sync::{OnceLock, RwLock},
+    str::FromStr,
+    sync::{
+        mpsc::{self, Receiver, Sender, SyncSender},
+        OnceLock, RwLock,
+    },
+    time::Duration,
 };
 use tracing::error;
 use typeshare::typeshare;
@@ -20,7 +25,9 @@ use crate::{
     },
 };
 
-#[derive(Serialize, derive_name::Name, struct_field_names_as_array::FieldNamesAsSlice)]
+#[derive(
+    Serialize, Deserialize, derive_name::Name, struct_field_names_as_array::FieldNamesAsSlice,
+)]
 #[typeshare]
 pub struct DebugStreamStepResults {
     pub itinerary_id: String,
@@ -31,7 +38,9 @@ pub struct DebugStreamStepResults {
     pub chosen_fork_point_id: i64,
 }
 
-#[derive(Serialize, derive_name::Name, struct_field_names_as_array::FieldNamesAsSlice)]
+#[derive(
+    Serialize, Deserialize, derive_name::Name, struct_field_names_as_array::FieldNamesAsSlice,
+)]
 #[typeshare]
 pub struct DebugStreamForkChoiceWeights {
     pub itinerary_id: String,
@@ -43,6 +52,9 @@ pub struct DebugStreamForkChoiceWeights {
     pub weight_type: String,
     #[typeshare(serialized_as = "number")]
     pub weight_value: i64,
+    /// Time the weight calculation took in microseconds
+    #[typeshare(serialized_as = "number")]
+    pub duration_us: i64,
 }
 
 #[derive(Serialize, derive_name::Name, struct_field_names_as_array::FieldNamesAsSlice)]
@@ -72,7 +84,9 @@ pub struct DebugStreamSteps {
     pub route: String,
 }
 
-#[derive(Serialize, derive_name::Name, struct_field_names_as_array::FieldNamesAsSlice)]
+#[derive(
+    Serialize, Deserialize, derive_name::Name, struct_field_names_as_array::FieldNamesAsSlice,
+)]
 #[typeshare]
 pub struct DebugStreamItineraries {
     pub itinerary_id: String,
@@ -86,7 +100,9 @@ pub struct DebugStreamItineraries {
     pub finish_lon: f32,
 }
 
-#[derive(Serialize, derive_name::Name, struct_field_names_as_array::FieldNamesAsSlice)]
+#[derive(
+    Serialize, Deserialize, derive_name::Name, struct_field_names_as_array::FieldNamesAsSlice,
+)]
 #[typeshare]
 pub struct DebugStreamItineraryWaypoints {
     pub itinerary_id: String,
@@ -106,8 +122,6 @@ pub enum DebugWriterError {
     MetadataCreate { error: io::Error },
     #[error("Could not create debug dir: {error}")]
     DirCreate { error: io::Error },
-    #[error("Could not read global debug_writer")]
-    StaticRead { error: String },
     #[error("Could not create file")]
     FileCreate {
         file_name: PathBuf,
@@ -123,62 +137,229 @@ pub enum DebugWriterError {
     SerializeMetadata { error: serde_json::Error },
     #[error("Could not write metadata {error}")]
     MetadataWrite { error: io::Error },
+    #[error("Could not start debug writer thread: {error}")]
+    ThreadSpawn { error: io::Error },
+    #[error("Debug writer thread is gone")]
+    WriterGone,
 }
 
 pub static DEBUG_DIR: OnceLock<PathBuf> = OnceLock::new();
+static DEBUG_OPTIONS: OnceLock<DebugWriterOptions> = OnceLock::new();
+/// Last step of every itinerary and whether it was within the bounding box, so the rest of the
+/// writes of the step, some of them from other threads, follow the step
+static DEBUG_BBOX_STEPS: OnceLock<RwLock<HashMap<String, (u32, bool)>>> = OnceLock::new();
+
+/// Area given as min_lat,min_lon,max_lat,max_lon
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DebugBbox {
+    min_lat: f32,
+    min_lon: f32,
+    max_lat: f32,
+    max_lon: f32,
+}
 
-thread_local! {
-    static DEBUG_WRITER: OnceLock<RwLock<DebugWriter>> = const { OnceLock::new() };
+impl DebugBbox {
+    fn contains(&self, lat: f32, lon: f32) -> bool {
+        (self.min_lat..=self.max_lat).contains(&lat) && (self.min_lon..=self.max_lon).contains(&lon)
+    }
 }
 
+impl FromStr for DebugBbox {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| format!("'{s}' has a value that is not a number: {error}"))?;
+        let [min_lat, min_lon, max_lat, max_lon] = values[..] else {
+            return Err(format!(
+                "'{s}' is not a bounding box like MIN_LAT,MIN_LON,MAX_LAT,MAX_LON"
+            ));
+        };
+        if min_lat > max_lat || min_lon > max_lon {
+            return Err(format!(
+                "'{s}' has a min larger than the max, expected MIN_LAT,MIN_LON,MAX_LAT,MAX_LON"
+            ));
+        }
+        Ok(Self {
+            min_lat,
+            min_lon,
+            max_lat,
+            max_lon,
+        })
+    }
+}
+
+/// Limits what is written to the debug files, full debug files slow down the generation of long
+/// routes a lot. Itineraries and their waypoints are always written when the itinerary is
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct DebugWriterOptions {
+    #[arg(
+        long = "debug-every-nth-step",
+        value_name = "N",
+        requires = "debug_dir",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    /// Only write every Nth step of the route generation to the debug files, starting with the
+    /// first one
+    pub every_nth_step: Option<u32>,
+
+    #[arg(long = "debug-itinerary", value_name = "ID", requires = "debug_dir")]
+    /// Only write the steps of this itinerary to the debug files. Can be given several times
+    pub itinerary_ids: Vec<String>,
+
+    #[arg(
+        long = "debug-bbox",
+        value_name = "MIN_LAT,MIN_LON,MAX_LAT,MAX_LON",
+        requires = "debug_dir"
+    )]
+    /// Only write the steps that end within the bounding box to the debug files
+    pub bbox: Option<DebugBbox>,
+}
+
+impl DebugWriterOptions {
+    fn records_itinerary(&self, itinerary_id: &str) -> bool {
+        self.itinerary_ids.is_empty() || self.itinerary_ids.iter().any(|id| id == itinerary_id)
+    }
+
+    fn records_step(&self, itinerary_id: &str, step: u32) -> bool {
+        self.records_itinerary(itinerary_id)
+            && self
+                .every_nth_step
+                .is_none_or(|every_nth_step| (step - 1).is_multiple_of(every_nth_step))
+    }
+}
+
+/// Records waiting to be written, the route generation blocks when the writer falls this far behind
+const DEBUG_CHANNEL_CAPACITY: usize = 10_000;
+/// Records written between flushes of the debug files
+const DEBUG_BATCH_SIZE: usize = 1_000;
+/// Time to wait for the pending records to be written when flushing
+const DEBUG_FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+const DEBUG_THREAD_NAME: &str = "debug-writer";
+pub const REPLAY_INPUT_FILE_NAME: &str = "replay-input.json";
+
+static DEBUG_SENDER: OnceLock<SyncSender<DebugMessage>> = OnceLock::new();
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DebugMetadata {
     pub router_version: String,
 }
 
-pub struct DebugWriter {
-    files: HashMap<String, csv::Writer<File>>,
+enum DebugRecord {
+    StepResult(DebugStreamStepResults),
+    ForkChoiceWeight(DebugStreamForkChoiceWeights),
+    ForkChoice(DebugStreamForkChoices),
+    Step(DebugStreamSteps),
+    Itinerary(DebugStreamItineraries),
+    ItineraryWaypoint(DebugStreamItineraryWaypoints),
 }
 
-impl DebugWriter {
-    fn exec<T: Fn(&mut csv::Writer<File>) -> Result<(), DebugWriterError>>(
-        file_type_id: &str,
-        cb: T,
-    ) {
-        if let Some(debug_dir) = DEBUG_DIR.get() {
-            let file_id = format!("{file_type_id}-{:?}", std::thread::current().id());
-            let res = DEBUG_WRITER.with(|debug_writer| -> Result<(), DebugWriterError> {
-                let debug_writer = debug_writer.get_or_init(|| {
-                    RwLock::new(DebugWriter {
-                        files: HashMap::new(),
-                    })
-                });
-                let mut debug_writer_write =
-                    debug_writer
-                        .write()
-                        .map_err(|error| DebugWriterError::StaticRead {
-                            error: error.to_string(),
-                        })?;
-
-                if let Some(writer) = debug_writer_write.files.get_mut(&file_id) {
-                    cb(writer)?;
-                    writer
-                        .flush()
-                        .map_err(|error| DebugWriterError::Flush { error })?;
-                } else {
-                    let mut file_name = debug_dir.clone();
-                    file_name.push(&file_id);
-                    file_name.set_extension("csv");
-                    let file = File::create(&file_name)
-                        .map_err(|error| DebugWriterError::FileCreate { file_name, error })?;
-                    let mut writer = csv::Writer::from_writer(file);
-                    cb(&mut writer)?;
-                    writer
-                        .flush()
-                        .map_err(|error| DebugWriterError::Flush { error })?;
-                    debug_writer_write.files.insert(file_id, writer);
+enum DebugMessage {
+    Record(DebugRecord),
+    /// Flush the debug files and acknowledge once the records sent before are on disk
+    Flush(Sender<()>),
+}
+
+/// Debug files owned by the writer thread, one for every debug stream
+struct DebugFiles {
+    dir: PathBuf,
+    files: HashMap<&'static str, csv::Writer<BufWriter<File>>>,
+}
+
+impl DebugFiles {
+    fn write<T: Serialize>(
+        &mut self,
+        name: &'static str,
+        record: T,
+    ) -> Result<(), DebugWriterError> {
+        let writer = match self.files.entry(name) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let mut file_name = self.dir.clone();
+                file_name.push(name);
+                file_name.set_extension("csv");
+                let file = File::create(&file_name)
+                    .map_err(|error| DebugWriterError::FileCreate { file_name, error })?;
+                entry.insert(csv::Writer::from_writer(BufWriter::new(file)))
+            }
+        };
+        writer
+            .serialize(record)
+            .map_err(|error| DebugWriterError::Write { error })
+    }
+
+    fn write_record(&mut self, record: DebugRecord) -> Result<(), DebugWriterError> {
+        match record {
+            DebugRecord::StepResult(r) => self.write(DebugStreamStepResults::name(), r),
+            DebugRecord::ForkChoiceWeight(r) => self.write(DebugStreamForkChoiceWeights::name(), r),
+            DebugRecord::ForkChoice(r) => self.write(DebugStreamForkChoices::name(), r),
+            DebugRecord::Step(r) => self.write(DebugStreamSteps::name(), r),
+            DebugRecord::Itinerary(r) => self.write(DebugStreamItineraries::name(), r),
+            DebugRecord::ItineraryWaypoint(r) => {
+                self.write(DebugStreamItineraryWaypoints::name(), r)
+            }
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), DebugWriterError> {
+        for writer in self.files.values_mut() {
+            writer
+                .flush()
+                .map_err(|error| DebugWriterError::Flush { error })?;
+        }
+        Ok(())
+    }
+
+    /// Writes the records in batches until every sender is gone, flushing after each batch
+    fn run(mut self, receiver: Receiver<DebugMessage>) {
+        while let Ok(message) = receiver.recv() {
+            let mut flush_acks = Vec::new();
+            for message in
+                std::iter::once(message).chain(receiver.try_iter().take(DEBUG_BATCH_SIZE - 1))
+            {
+                match message {
+                    DebugMessage::Record(record) => {
+                        if let Err(error) = self.write_record(record) {
+                            error!(error = debug(error), "Failed to write to log");
+                        }
+                    }
+                    DebugMessage::Flush(ack) => flush_acks.push(ack),
                 }
-                Ok(())
+            }
+            if let Err(error) = self.flush() {
+                error!(error = debug(error), "Failed to write to log");
+            }
+            for ack in flush_acks {
+                let _ = ack.send(());
+            }
+        }
+    }
+}
+
+/// Flushes the debug files when dropped, returned from [DebugWriter::init]
+#[must_use = "the debug files are flushed when the guard is dropped"]
+pub struct DebugWriterGuard;
+
+impl Drop for DebugWriterGuard {
+    fn drop(&mut self) {
+        DebugWriter::flush();
+    }
+}
+
+/// Writes the debug streams of the route generation to CSV files. The records are written on a
+/// separate thread so the route generation isn't blocked on file I/O
+pub struct DebugWriter;
+
+impl DebugWriter {
+    fn exec<T: FnOnce() -> Result<DebugRecord, DebugWriterError>>(cb: T) {
+        if let Some(sender) = DEBUG_SENDER.get() {
+            let res = cb().and_then(|record| {
+                sender
+                    .send(DebugMessage::Record(record))
+                    .map_err(|_| DebugWriterError::WriterGone)
             });
             if let Err(error) = res {
                 error!(error = debug(error), "Failed to write to log");
@@ -186,7 +367,28 @@ impl DebugWriter {
         }
     }
 
-    pub fn init(dir_name: Option<PathBuf>) -> Result<(), DebugWriterError> {
+    /// Waits until the records sent so far are written to the debug files
+    pub fn flush() {
+        let Some(sender) = DEBUG_SENDER.get() else {
+            return;
+        };
+        if std::thread::current().name() == Some(DEBUG_THREAD_NAME) {
+            return;
+        }
+        let (ack_sender, ack_receiver) = mpsc::channel();
+        if sender.send(DebugMessage::Flush(ack_sender)).is_err()
+            || ack_receiver.recv_timeout(DEBUG_FLUSH_TIMEOUT).is_err()
+        {
+            error!("Failed to flush debug files");
+        }
+    }
+
+    /// Starts the writer thread when a debug dir is given. The debug files are flushed when the
+    /// returned guard is dropped or when a thread panics
+    pub fn init(
+        dir_name: Option<PathBuf>,
+        options: DebugWriterOptions,
+    ) -> Result<DebugWriterGuard, DebugWriterError> {
         if let Some(dir_name) = dir_name {
             if std::fs::exists(&dir_name).map_err(|error| DebugWriterError::DirCheck { error })? {
                 std::fs::remove_dir_all(&dir_name)
@@ -207,10 +409,44 @@ impl DebugWriter {
                     .as_bytes(),
             )
             .map_err(|error| DebugWriterError::MetadataWrite { error })?;
-            DEBUG_DIR.get_or_init(|| dir_name);
+            DEBUG_OPTIONS.get_or_init(|| options);
+            DEBUG_DIR.get_or_init(|| dir_name.clone());
+
+            let (sender, receiver) = mpsc::sync_channel(DEBUG_CHANNEL_CAPACITY);
+            let files = DebugFiles {
+                dir: dir_name,
+                files: HashMap::new(),
+            };
+            std::thread::Builder::new()
+                .name(DEBUG_THREAD_NAME.to_string())
+                .spawn(move || files.run(receiver))
+                .map_err(|error| DebugWriterError::ThreadSpawn { error })?;
+            if DEBUG_SENDER.set(sender).is_ok() {
+                let panic_hook = std::panic::take_hook();
+                std::panic::set_hook(Box::new(move |info| {
+                    DebugWriter::flush();
+                    panic_hook(info);
+                }));
+            }
         }
 
-        Ok(())
+        Ok(DebugWriterGuard)
+    }
+
+    /// Writes what the route generation was started with, so its itineraries can be replayed
+    pub fn write_replay_input<T: Serialize>(input: &T) {
+        let Some(debug_dir) = DEBUG_DIR.get() else {
+            return;
+        };
+        let res = serde_json::to_vec(input)
+            .map_err(|error| DebugWriterError::SerializeMetadata { error })
+            .and_then(|input| {
+                std::fs::write(debug_dir.join(REPLAY_INPUT_FILE_NAME), input)
+                    .map_err(|error| DebugWriterError::MetadataWrite { error })
+            });
+        if let Err(error) = res {
+            error!(error = debug(error), "Failed to write replay input");
+        }
     }
 
     pub fn get_metadata_file_path(dir_name: &PathBuf) -> PathBuf {
@@ -219,22 +455,59 @@ impl DebugWriter {
         metadata_file
     }
 
+    /// Whether the step is written to the debug files, decided when the step itself is written
+    /// with the position the step ended at, or none when the route is still empty
+    fn records_step_at(itinerary_id: &str, step: u32, position: Option<(f32, f32)>) -> bool {
+        let Some(options) = DEBUG_OPTIONS.get() else {
+            return true;
+        };
+        if !options.records_step(itinerary_id, step) {
+            return false;
+        }
+        let Some(bbox) = options.bbox else {
+            return true;
+        };
+        let in_bbox = position.is_some_and(|(lat, lon)| bbox.contains(lat, lon));
+        if let Ok(mut bbox_steps) = DEBUG_BBOX_STEPS
+            .get_or_init(|| RwLock::new(HashMap::new()))
+            .write()
+        {
+            bbox_steps.insert(itinerary_id.to_string(), (step, in_bbox));
+        }
+        in_bbox
+    }
+
+    /// Whether the rest of the writes of the step are written to the debug files
+    fn records_step(itinerary_id: &str, step: u32) -> bool {
+        let Some(options) = DEBUG_OPTIONS.get() else {
+            return true;
+        };
+        if options.bbox.is_none() {
+            return options.records_step(itinerary_id, step);
+        }
+        DEBUG_BBOX_STEPS.get().is_some_and(|bbox_steps| {
+            bbox_steps
+                .read()
+                .is_ok_and(|bbox_steps| bbox_steps.get(itinerary_id) == Some(&(step, true)))
+        })
+    }
+
     pub fn write_step_result(
         itinerary_id: String,
         step: u32,
         result: &str,
         chosen_fork_point_id: Option<u64>,
     ) {
-        DebugWriter::exec(DebugStreamStepResults::name(), |writer| {
-            writer
-                .serialize(DebugStreamStepResults {
-                    itinerary_id: itinerary_id.clone(),
-                    step_num: step as i64,
-                    result: result.to_string(),
-                    chosen_fork_point_id: chosen_fork_point_id.map_or(0, |v| v as i64),
-                })
-                .map_err(|error| DebugWriterError::Write { error })?;
-            Ok(())
+        if !Self::records_step(&itinerary_id, step) {
+            return;
+        }
+        DebugWriter::exec(|| {
+            Ok(DebugRecord::StepResult(DebugStreamStepResults {
+                itinerary_id: itinerary_id.clone(),
+                step_num: step as i64,
+                result: result.to_string(),
+                chosen_fork_point_id: chosen_fork_point_id.map_or(0, |v| v as i64),
+            }))
         });
     }
     pub fn write_fork_choice_weight(
@@ -243,24 +516,28 @@ impl DebugWriter {
         end_point_id: &u64,
         weight_name: &String,
         weight_result: &WeightCalcResult,
+        duration: Duration,
     ) {
         let (weight_type, weight_value) = match weight_result {
             WeightCalcResult::LastSegmentDoNotUse => ("LastSegmentDoNotUse", &0),
             WeightCalcResult::ForkChoiceDoNotUse => ("ForkChoiceDoNotUse", &0),
             WeightCalcResult::ForkChoiceUseWithWeight(v) => ("ForkChoiceUseWithWeight", v),
         };
-        DebugWriter::exec(DebugStreamForkChoiceWeights::name(), |writer| {
-            writer
-                .serialize(DebugStreamForkChoiceWeights {
+        if !Self::records_step(&itinerary_id, step) {
+            return;
+        }
+        DebugWriter::exec(|| {
+            Ok(DebugRecord::ForkChoiceWeight(
+                DebugStreamForkChoiceWeights {
                     itinerary_id: itinerary_id.clone(),
                     step_num: step as i64,
                     end_point_id: *end_point_id as i64,
                     weight_name: weight_name.to_string(),
                     weight_type: weight_type.to_string(),
                     weight_value: *weight_value as i64,
-                })
-                .map_err(|error| DebugWriterError::Write { error })?;
-            Ok(())
+                    duration_us: duration.as_micros() as i64,
+                },
+            ))
         });
     }
 
@@ -270,30 +547,30 @@ impl DebugWriter {
         segment_list: &SegmentList,
         discarded_choices: &Vec<MapDataPointRef>,
     ) {
+        if !Self::records_step(&itinerary_id, step) {
+            return;
+        }
         for segment in segment_list.clone().into_iter() {
-            DebugWriter::exec(DebugStreamForkChoices::name(), |writer| {
-                writer
-                    .serialize(DebugStreamForkChoices {
-                        itinerary_id: itinerary_id.clone(),
-                        step_num: step as i64,
-                        end_point_id: segment.get_end_point().borrow().id as i64,
-                        line_point_0_lat: segment.get_line().borrow().points.0.borrow().lat as f64,
-                        line_point_0_lon: segment.get_line().borrow().points.0.borrow().lon as f64,
-                        line_point_1_lat: segment.get_line().borrow().points.1.borrow().lat as f64,
-                        line_point_1_lon: segment.get_line().borrow().points.1.borrow().lon as f64,
-                        segment_end_point: if segment.get_end_point()
-                            == &segment.get_line().borrow().points.0
-                        {
-                            0
-                        } else {
-                            1
-                        } as i64,
-                        discarded: discarded_choices
-                            .iter()
-                            .any(|c| c == segment.get_end_point()),
-                    })
-                    .map_err(|error| DebugWriterError::Write { error })?;
-                Ok(())
+            DebugWriter::exec(|| {
+                Ok(DebugRecord::ForkChoice(DebugStreamForkChoices {
+                    itinerary_id: itinerary_id.clone(),
+                    step_num: step as i64,
+                    end_point_id: segment.get_end_point().borrow().id as i64,
+                    line_point_0_lat: segment.get_line().borrow().points.0.borrow().lat as f64,
+                    line_point_0_lon: segment.get_line().borrow().points.0.borrow().lon as f64,
+                    line_point_1_lat: segment.get_line().borrow().points.1.borrow().lat as f64,
+                    line_point_1_lon: segment.get_line().borrow().points.1.borrow().lon as f64,
+                    segment_end_point: if segment.get_end_point()
+                        == &segment.get_line().borrow().points.0
+                    {
+                        0
+                    } else {
+                        1
+                    } as i64,
+                    discarded: discarded_choices
+                        .iter()
+                        .any(|c| c == segment.get_end_point()),
+                }))
             });
         }
     }
@@ -310,60 +587,91 @@ impl DebugWriter {
             Ok(WalkerMoveResult::DeadEnd) => "Dead End",
             Ok(WalkerMoveResult::Fork(_)) => "Fork",
         };
-        DebugWriter::exec(DebugStreamSteps::name(), |writer| {
-            writer
-                .serialize(DebugStreamSteps {
-                    itinerary_id: itinerary_id.clone(),
-                    step_num: step as i64,
-                    move_result: move_result.to_string(),
-                    route: serde_json::to_string(
-                        &route
-                            .get_route_chunk_since_junction_before_last()
-                            .iter()
-                            .map(|segment| {
-                                (
-                                    segment.get_end_point().borrow().lat,
-                                    segment.get_end_point().borrow().lon,
-                                )
-                            })
-                            .collect::<Vec<_>>(),
-                    )
-                    .map_err(|error| DebugWriterError::SerializeRoute { error })?,
-                })
-                .map_err(|error| DebugWriterError::Write { error })?;
-            Ok(())
+        let position = route.get_segment_last().map(|segment| {
+            let end_point = segment.get_end_point().borrow();
+            (end_point.lat, end_point.lon)
+        });
+        if !Self::records_step_at(&itinerary_id, step, position) {
+            return;
+        }
+        DebugWriter::exec(|| {
+            Ok(DebugRecord::Step(DebugStreamSteps {
+                itinerary_id: itinerary_id.clone(),
+                step_num: step as i64,
+                move_result: move_result.to_string(),
+                route: serde_json::to_string(
+                    &route
+                        .get_route_chunk_since_junction_before_last()
+                        .iter()
+                        .map(|segment| {
+                            (
+                                segment.get_end_point().borrow().lat,
+                                segment.get_end_point().borrow().lon,
+                            )
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .map_err(|error| DebugWriterError::SerializeRoute { error })?,
+            }))
         });
     }
 
     pub fn write_itineraries(itineraries: &Vec<Itinerary>) {
         for itinerary in itineraries {
-            DebugWriter::exec(DebugStreamItineraries::name(), |writer| {
-                writer
-                    .serialize(DebugStreamItineraries {
-                        itinerary_id: itinerary.id(),
-                        waypoints_count: itinerary.waypoints.len() as i64,
-                        radius: itinerary.waypoint_radius as i64,
-                        start_lat: itinerary.start.borrow().lat,
-                        start_lon: itinerary.start.borrow().lon,
-                        finish_lat: itinerary.finish.borrow().lat,
-                        finish_lon: itinerary.finish.borrow().lon,
-                    })
-                    .map_err(|error| DebugWriterError::Write { error })?;
-                Ok(())
+            if DEBUG_OPTIONS
+                .get()
+                .is_some_and(|options| !options.records_itinerary(&itinerary.id()))
+            {
+                continue;
+            }
+            DebugWriter::exec(|| {
+                Ok(DebugRecord::Itinerary(DebugStreamItineraries {
+                    itinerary_id: itinerary.id(),
+                    waypoints_count: itinerary.waypoints.len() as i64,
+                    radius: itinerary.waypoint_radius as i64,
+                    start_lat: itinerary.start.borrow().lat,
+                    start_lon: itinerary.start.borrow().lon,
+                    finish_lat: itinerary.finish.borrow().lat,
+                    finish_lon: itinerary.finish.borrow().lon,
+                }))
             });
             for (idx, wp) in itinerary.waypoints.iter().enumerate() {
-                DebugWriter::exec(DebugStreamItineraryWaypoints::name(), |writer| {
-                    writer
-                        .serialize(DebugStreamItineraryWaypoints {
+                DebugWriter::exec(|| {
+                    Ok(DebugRecord::ItineraryWaypoint(
+                        DebugStreamItineraryWaypoints {
                             itinerary_id: itinerary.id(),
                             idx: idx as i64,
                             lat: wp.borrow().lat as f64,
                             lon: wp.borrow().lon as f64,
-                        })
-                        .map_err(|error| DebugWriterError::Write { error })?;
-                    Ok(())
+                        },
+                    ))
                 });
             }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{DebugBbox, DebugWriterOptions};
+
+    #[test]
+    fn debug_writer_options_filter_steps() {
+        let options = DebugWriterOptions {
+            every_nth_step: Some(10),
+            itinerary_ids: vec!["1-2-3".to_string()],
+            bbox: None,
+        };
+        assert!(options.records_step("1-2-3", 1));
+        assert!(options.records_step("1-2-3", 21));
+        assert!(!options.records_step("1-2-3", 20));
+        assert!(!options.records_step("4-5-6", 1));
+        assert!(DebugWriterOptions::default().records_step("4-5-6", 20));
+
+        let bbox = "56.9,24.0,57.1,24.2".parse::<DebugBbox>().unwrap();
+        assert!(bbox.contains(57.0, 24.1));
+        assert!(!bbox.contains(57.0, 24.3));
+        assert!("57.1,24.0,56.9,24.2".parse::<DebugBbox>().is_err());
+        assert!("56.9,24.0,57.1".parse::<DebugBbox>().is_err());
+    }
+}
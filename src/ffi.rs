@@ -0,0 +1,114 @@
+use std::{
+    ffi::{c_char, c_int, CStr, CString},
+    panic::catch_unwind,
+    path::PathBuf,
+    str::FromStr,
+};
+
+use tracing::error;
+
+use crate::{
+    ipc_handler::{RequestMessage, ResponseMessage, RouterResult},
+    osm_data::DataSource,
+    router_runner::RouterRunner,
+};
+
+pub const RIDI_ROUTER_OK: c_int = 0;
+pub const RIDI_ROUTER_ERR_INVALID_ARGUMENT: c_int = 1;
+pub const RIDI_ROUTER_ERR_MAP_DATA: c_int = 2;
+
+fn read_c_str(value: *const c_char) -> Option<String> {
+    if value.is_null() {
+        return None;
+    }
+    // SAFETY: caller guarantees a valid, NUL terminated string that lives for the duration of the call
+    let value = unsafe { CStr::from_ptr(value) };
+    value.to_str().ok().map(|v| v.to_string())
+}
+
+fn response_to_c_str(response: ResponseMessage) -> *mut c_char {
+    let json = match serde_json::to_string(&response) {
+        Ok(json) => json,
+        Err(error) => {
+            error!(error = ?error, "Failed to serialize response");
+            return std::ptr::null_mut();
+        }
+    };
+    match CString::new(json) {
+        Ok(json) => json.into_raw(),
+        Err(error) => {
+            error!(error = ?error, "Response contains a NUL byte");
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Loads map data into the routing graph. Must be called once before generating routes.
+///
+/// `input` is a path to an OSM json or osm.pbf file, `cache_dir` is an optional (nullable) path
+/// to a cache directory. Returns `RIDI_ROUTER_OK` on success.
+#[no_mangle]
+pub extern "C" fn ridi_router_init(input: *const c_char, cache_dir: *const c_char) -> c_int {
+    let Some(input) = read_c_str(input) else {
+        error!("Input is missing or not valid UTF-8");
+        return RIDI_ROUTER_ERR_INVALID_ARGUMENT;
+    };
+    let data_source = match DataSource::from_str(&input) {
+        Ok(d) => d,
+        Err(error) => {
+            error!(error = ?error, "Invalid input");
+            return RIDI_ROUTER_ERR_INVALID_ARGUMENT;
+        }
+    };
+    let cache_dir = read_c_str(cache_dir).map(PathBuf::from);
+
+    match catch_unwind(|| RouterRunner::load_map_data(&data_source, cache_dir)) {
+        Ok(Ok(())) => RIDI_ROUTER_OK,
+        Ok(Err(error)) => {
+            error!(error = ?error, "Failed to load map data");
+            RIDI_ROUTER_ERR_MAP_DATA
+        }
+        Err(error) => {
+            error!(error = ?error, "Caught panic while loading map data");
+            RIDI_ROUTER_ERR_MAP_DATA
+        }
+    }
+}
+
+/// Generates routes for a JSON encoded request with the same format as the IPC request message
+/// and returns a JSON encoded response message. The returned string must be released with
+/// `ridi_router_free_result`. Returns NULL if the request could not be read.
+#[no_mangle]
+pub extern "C" fn ridi_router_generate_route(request_json: *const c_char) -> *mut c_char {
+    let Some(request_json) = read_c_str(request_json) else {
+        error!("Request is missing or not valid UTF-8");
+        return std::ptr::null_mut();
+    };
+    let request_message: RequestMessage = match serde_json::from_str(&request_json) {
+        Ok(r) => r,
+        Err(error) => {
+            return response_to_c_str(ResponseMessage {
+                id: String::new(),
+                result: RouterResult::Error {
+                    message: format!("Failed to deserialize request {:?}", error),
+                },
+            });
+        }
+    };
+
+    response_to_c_str(RouterRunner::handle_request(request_message, None))
+}
+
+/// Releases a result returned by `ridi_router_generate_route`. Passing NULL is a no-op.
+///
+/// # Safety
+///
+/// `result` must be a pointer returned by `ridi_router_generate_route` that has not been released
+/// yet.
+#[no_mangle]
+pub unsafe extern "C" fn ridi_router_free_result(result: *mut c_char) {
+    if result.is_null() {
+        return;
+    }
+    drop(CString::from_raw(result));
+}
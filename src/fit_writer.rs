@@ -0,0 +1,380 @@
+use std::{
+    io::{Error, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    ipc_handler::RouteMessage,
+    result_writer::AtomicFile,
+    turns::{get_coord_distances_m, get_turns, TurnKind},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum FitWriterError {
+    #[error("File Creation Error {error}")]
+    FileCreateError { error: Error },
+
+    #[error("Fit Write Error {error}")]
+    FitWrite { error: Error },
+}
+
+/// Seconds between the unix epoch and the FIT epoch of 1989-12-31 00:00 UTC
+const FIT_EPOCH_OFFSET_S: u64 = 631_065_600;
+const FIT_PROTOCOL_VERSION: u8 = 0x20;
+const FIT_PROFILE_VERSION: u16 = 2132;
+const FIT_HEADER_SIZE: u8 = 14;
+const FIT_STRING_SIZE: u8 = 16;
+
+const MESG_FILE_ID: u16 = 0;
+const MESG_LAP: u16 = 19;
+const MESG_RECORD: u16 = 20;
+const MESG_EVENT: u16 = 21;
+const MESG_COURSE: u16 = 31;
+const MESG_COURSE_POINT: u16 = 32;
+
+const BASE_ENUM: u8 = 0x00;
+const BASE_STRING: u8 = 0x07;
+const BASE_UINT16: u8 = 0x84;
+const BASE_SINT32: u8 = 0x85;
+const BASE_UINT32: u8 = 0x86;
+const BASE_UINT32Z: u8 = 0x8c;
+
+const FILE_TYPE_COURSE: u8 = 6;
+const MANUFACTURER_DEVELOPMENT: u16 = 255;
+const SPORT_MOTORCYCLING: u8 = 22;
+const EVENT_TIMER: u8 = 0;
+const EVENT_TYPE_START: u8 = 0;
+const EVENT_TYPE_STOP_DISABLE_ALL: u8 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CoursePointType {
+    Generic = 0,
+    Left = 6,
+    Right = 7,
+    SlightLeft = 19,
+    SharpLeft = 20,
+    SlightRight = 21,
+    SharpRight = 22,
+    UTurn = 23,
+}
+
+impl From<TurnKind> for CoursePointType {
+    fn from(kind: TurnKind) -> Self {
+        match kind {
+            TurnKind::SlightLeft => Self::SlightLeft,
+            TurnKind::Left => Self::Left,
+            TurnKind::SharpLeft => Self::SharpLeft,
+            TurnKind::SlightRight => Self::SlightRight,
+            TurnKind::Right => Self::Right,
+            TurnKind::SharpRight => Self::SharpRight,
+            TurnKind::UTurn => Self::UTurn,
+            TurnKind::RoundaboutExit { .. } => Self::Generic,
+        }
+    }
+}
+
+fn to_semicircles(deg: f32) -> i32 {
+    (f64::from(deg) * (2f64.powi(31) / 180.)).round() as i32
+}
+
+fn fit_crc(crc: u16, byte: u8) -> u16 {
+    const CRC_TABLE: [u16; 16] = [
+        0x0000, 0xcc01, 0xd801, 0x1400, 0xf001, 0x3c00, 0x2800, 0xe401, 0xa001, 0x6c00, 0x7800,
+        0xb401, 0x5000, 0x9c01, 0x8801, 0x4400,
+    ];
+    let tmp = CRC_TABLE[usize::from(crc & 0xf)];
+    let crc = ((crc >> 4) & 0x0fff) ^ tmp ^ CRC_TABLE[usize::from(byte & 0xf)];
+    let tmp = CRC_TABLE[usize::from(crc & 0xf)];
+    ((crc >> 4) & 0x0fff) ^ tmp ^ CRC_TABLE[usize::from((byte >> 4) & 0xf)]
+}
+
+fn fit_crc_bytes(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |crc, byte| fit_crc(crc, *byte))
+}
+
+/// Minimal encoder for the FIT messages needed in a course file, every message type uses its own
+/// local message type that is defined once
+#[derive(Default)]
+struct FitEncoder {
+    data: Vec<u8>,
+}
+
+impl FitEncoder {
+    fn define(&mut self, local_type: u8, global_type: u16, fields: &[(u8, u8, u8)]) {
+        self.data.push(0x40 | local_type);
+        self.data.push(0);
+        self.data.push(0); // little endian
+        self.data.extend_from_slice(&global_type.to_le_bytes());
+        self.data.push(fields.len() as u8);
+        for (number, size, base_type) in fields {
+            self.data.extend_from_slice(&[*number, *size, *base_type]);
+        }
+    }
+
+    fn start(&mut self, local_type: u8) -> &mut Self {
+        self.data.push(local_type);
+        self
+    }
+
+    fn u8(&mut self, value: u8) -> &mut Self {
+        self.data.push(value);
+        self
+    }
+
+    fn u16(&mut self, value: u16) -> &mut Self {
+        self.data.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn u32(&mut self, value: u32) -> &mut Self {
+        self.data.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn i32(&mut self, value: i32) -> &mut Self {
+        self.data.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn string(&mut self, value: &str) -> &mut Self {
+        let mut bytes = [0u8; FIT_STRING_SIZE as usize];
+        // cut at a char boundary and keep the last byte for the null terminator
+        let mut len = value.len().min(bytes.len() - 1);
+        while !value.is_char_boundary(len) {
+            len -= 1;
+        }
+        bytes[..len].copy_from_slice(&value.as_bytes()[..len]);
+        self.data.extend_from_slice(&bytes);
+        self
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let mut file = vec![FIT_HEADER_SIZE, FIT_PROTOCOL_VERSION];
+        file.extend_from_slice(&FIT_PROFILE_VERSION.to_le_bytes());
+        file.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        file.extend_from_slice(b".FIT");
+        let header_crc = fit_crc_bytes(&file);
+        file.extend_from_slice(&header_crc.to_le_bytes());
+        file.extend_from_slice(&self.data);
+        let crc = fit_crc_bytes(&file);
+        file.extend_from_slice(&crc.to_le_bytes());
+        file
+    }
+}
+
+pub struct FitWriter {
+    routes: Vec<RouteMessage>,
+    file_name: PathBuf,
+}
+
+impl FitWriter {
+    pub fn new(routes: Vec<RouteMessage>, file_name: PathBuf) -> Self {
+        Self { routes, file_name }
+    }
+
+    /// A FIT course holds a single route, so the first route is written to the given file and
+    /// the others to files with the route index added to the name
+    fn get_file_name(file_name: &Path, idx: usize) -> PathBuf {
+        if idx == 0 {
+            return file_name.to_path_buf();
+        }
+        let mut route_file_name = file_name.to_path_buf();
+        route_file_name.set_file_name(format!(
+            "{}_{idx}.fit",
+            file_name
+                .file_stem()
+                .map(|stem| stem.to_string_lossy())
+                .unwrap_or_default()
+        ));
+        route_file_name
+    }
+
+    fn encode_route(route: &RouteMessage, name: &str, start_time: u32) -> Vec<u8> {
+        let distances_m = get_coord_distances_m(&route.coords);
+        let speed_m_s = if route.stats.travel_time_s > 0. {
+            route.stats.len_m / route.stats.travel_time_s
+        } else {
+            0.
+        };
+        let timestamp = |distance_m: f32| -> u32 {
+            if speed_m_s > 0. {
+                start_time + (f64::from(distance_m) / speed_m_s).round() as u32
+            } else {
+                start_time
+            }
+        };
+        let total_time_ms = (route.stats.travel_time_s * 1000.).round() as u32;
+        let end_time = start_time + total_time_ms / 1000;
+        let (start_lat, start_lon) = route.coords.first().copied().unwrap_or_default();
+        let (end_lat, end_lon) = route.coords.last().copied().unwrap_or_default();
+
+        let mut fit = FitEncoder::default();
+        fit.define(
+            0,
+            MESG_FILE_ID,
+            &[
+                (0, 1, BASE_ENUM),
+                (1, 2, BASE_UINT16),
+                (2, 2, BASE_UINT16),
+                (3, 4, BASE_UINT32Z),
+                (4, 4, BASE_UINT32),
+            ],
+        );
+        fit.start(0)
+            .u8(FILE_TYPE_COURSE)
+            .u16(MANUFACTURER_DEVELOPMENT)
+            .u16(0)
+            .u32(1)
+            .u32(start_time);
+
+        fit.define(
+            1,
+            MESG_COURSE,
+            &[(5, FIT_STRING_SIZE, BASE_STRING), (4, 1, BASE_ENUM)],
+        );
+        fit.start(1).string(name).u8(SPORT_MOTORCYCLING);
+
+        fit.define(
+            2,
+            MESG_LAP,
+            &[
+                (253, 4, BASE_UINT32),
+                (2, 4, BASE_UINT32),
+                (3, 4, BASE_SINT32),
+                (4, 4, BASE_SINT32),
+                (5, 4, BASE_SINT32),
+                (6, 4, BASE_SINT32),
+                (7, 4, BASE_UINT32),
+                (8, 4, BASE_UINT32),
+                (9, 4, BASE_UINT32),
+            ],
+        );
+        fit.start(2)
+            .u32(start_time)
+            .u32(start_time)
+            .i32(to_semicircles(start_lat))
+            .i32(to_semicircles(start_lon))
+            .i32(to_semicircles(end_lat))
+            .i32(to_semicircles(end_lon))
+            .u32(total_time_ms)
+            .u32(total_time_ms)
+            .u32((route.stats.len_m * 100.).round() as u32);
+
+        fit.define(
+            3,
+            MESG_EVENT,
+            &[(253, 4, BASE_UINT32), (0, 1, BASE_ENUM), (1, 1, BASE_ENUM)],
+        );
+        fit.start(3)
+            .u32(start_time)
+            .u8(EVENT_TIMER)
+            .u8(EVENT_TYPE_START);
+
+        fit.define(
+            4,
+            MESG_RECORD,
+            &[
+                (253, 4, BASE_UINT32),
+                (0, 4, BASE_SINT32),
+                (1, 4, BASE_SINT32),
+                (5, 4, BASE_UINT32),
+            ],
+        );
+        for ((lat, lon), distance_m) in route.coords.iter().zip(distances_m.iter()) {
+            fit.start(4)
+                .u32(timestamp(*distance_m))
+                .i32(to_semicircles(*lat))
+                .i32(to_semicircles(*lon))
+                .u32((distance_m * 100.).round() as u32);
+        }
+
+        fit.define(
+            5,
+            MESG_COURSE_POINT,
+            &[
+                (254, 2, BASE_UINT16),
+                (1, 4, BASE_UINT32),
+                (2, 4, BASE_SINT32),
+                (3, 4, BASE_SINT32),
+                (4, 4, BASE_UINT32),
+                (5, 1, BASE_ENUM),
+                (6, FIT_STRING_SIZE, BASE_STRING),
+            ],
+        );
+        for (idx, turn) in get_turns(route, &distances_m).iter().enumerate() {
+            let (lat, lon) = route.coords[turn.coord_idx];
+            let distance_m = distances_m[turn.coord_idx];
+            fit.start(5)
+                .u16(idx as u16)
+                .u32(timestamp(distance_m))
+                .i32(to_semicircles(lat))
+                .i32(to_semicircles(lon))
+                .u32((distance_m * 100.).round() as u32)
+                .u8(CoursePointType::from(turn.kind) as u8)
+                .string(&turn.kind.name());
+        }
+
+        fit.start(3)
+            .u32(end_time)
+            .u8(EVENT_TIMER)
+            .u8(EVENT_TYPE_STOP_DISABLE_ALL);
+
+        fit.finish()
+    }
+
+    pub fn write_fit(self) -> Result<(), FitWriterError> {
+        let start_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| {
+                duration.as_secs().saturating_sub(FIT_EPOCH_OFFSET_S) as u32
+            });
+        for (idx, route) in self.routes.iter().enumerate() {
+            let name = format!(
+                "r_{idx}_c_{}",
+                route.stats.cluster.map_or(-1, |c| c as isize)
+            );
+            let mut file = AtomicFile::create(&Self::get_file_name(&self.file_name, idx))
+                .map_err(|error| FitWriterError::FileCreateError { error })?;
+            file.write_all(&Self::encode_route(route, &name, start_time))
+                .and_then(|_| file.commit())
+                .map_err(|error| FitWriterError::FitWrite { error })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ipc_handler::RouteMessage, router::route::RouteStats};
+
+    use super::{fit_crc_bytes, FitWriter, FIT_HEADER_SIZE};
+
+    #[test]
+    fn fit_course_encoding() {
+        let coords = vec![
+            (57.0, 24.0),
+            (57.001, 24.0),
+            (57.002, 24.0),
+            (57.002, 24.002),
+            (57.002, 24.004),
+        ];
+        let stats: RouteStats =
+            serde_json::from_str(r#"{"len_m": 470, "travel_time_s": 60, "junction_count": 0, "highway": {}, "surface": {}, "smoothness": {}, "score": 0, "cluster": null, "approximated_route": []}"#)
+                .unwrap();
+        let route = RouteMessage {
+            coords,
+            stats,
+            segments: None,
+        };
+
+        let file = FitWriter::encode_route(&route, "test", 1000);
+        assert_eq!(file[0], FIT_HEADER_SIZE);
+        assert_eq!(&file[8..12], b".FIT");
+        let data_size = u32::from_le_bytes(file[4..8].try_into().unwrap()) as usize;
+        assert_eq!(file.len(), usize::from(FIT_HEADER_SIZE) + data_size + 2);
+        // the CRC over data that ends with its own CRC is 0
+        assert_eq!(fit_crc_bytes(&file[..usize::from(FIT_HEADER_SIZE)]), 0);
+        assert_eq!(fit_crc_bytes(&file), 0);
+    }
+}
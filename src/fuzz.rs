@@ -0,0 +1,174 @@
+use std::{f64::consts::PI, io};
+
+use serde::Serialize;
+
+use crate::{
+    map_data::info::GraphInfoBbox,
+    router::{generator::RouteWithStats, navigator::seeded_hash},
+};
+
+/// Meters in a degree of latitude
+const LAT_DEGREE_M: f64 = 111_320.;
+
+#[derive(Debug, thiserror::Error)]
+pub enum FuzzError {
+    #[error("Could not write fuzz report: {error}")]
+    WriteJson { error: serde_json::Error },
+
+    #[error("Could not write fuzz report: {error}")]
+    Write { error: io::Error },
+}
+
+/// Start and finish of a case, as latitude and longitude
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+pub struct FuzzCase {
+    pub seed: u64,
+    pub start: (f32, f32),
+    pub finish: (f32, f32),
+}
+
+impl FuzzCase {
+    /// Picks a start within the bounding box and a finish up to the max distance from it, also
+    /// within the bounding box. The same seed always picks the same points
+    pub fn new(seed: u64, bbox: &GraphInfoBbox, max_distance_m: u32) -> Self {
+        let random = |value: u64| seeded_hash(seed, value) as f64 / u64::MAX as f64;
+        let start_lat = bbox.lat_min as f64 + random(0) * (bbox.lat_max - bbox.lat_min) as f64;
+        let start_lon = bbox.lon_min as f64 + random(1) * (bbox.lon_max - bbox.lon_min) as f64;
+        let distance_m = random(2) * max_distance_m as f64;
+        let bearing = random(3) * 2. * PI;
+        let finish_lat = start_lat + distance_m * bearing.cos() / LAT_DEGREE_M;
+        let finish_lon = start_lon
+            + distance_m * bearing.sin() / (LAT_DEGREE_M * start_lat.to_radians().cos().max(0.01));
+        Self {
+            seed,
+            start: (start_lat as f32, start_lon as f32),
+            finish: (
+                (finish_lat as f32).clamp(bbox.lat_min, bbox.lat_max),
+                (finish_lon as f32).clamp(bbox.lon_min, bbox.lon_max),
+            ),
+        }
+    }
+
+    /// Arguments of `generate-route` that generate the routes of the case again
+    pub fn generate_route_args(&self) -> String {
+        format!(
+            "--seed {} start-finish --start {},{} --finish {},{}",
+            self.seed, self.start.0, self.start.1, self.finish.0, self.finish.1
+        )
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FuzzFailureKind {
+    Panic,
+    Error,
+    /// No routes and at least one itinerary got stuck
+    Stuck,
+    /// No routes, the itineraries were stopped by the step limit or the time limit
+    NoRoute,
+    /// Routes that break one way roads, turn restrictions or access tags
+    Violation,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FuzzFailure {
+    #[serde(flatten)]
+    pub case: FuzzCase,
+    pub kind: FuzzFailureKind,
+    pub message: String,
+    pub generate_route_args: String,
+}
+
+impl FuzzFailure {
+    pub fn new(case: FuzzCase, kind: FuzzFailureKind, message: String) -> Self {
+        Self {
+            generate_route_args: case.generate_route_args(),
+            case,
+            kind,
+            message,
+        }
+    }
+
+    /// Failure of the generated routes, none when they are fine
+    pub fn from_routes(
+        case: FuzzCase,
+        routes: &[RouteWithStats],
+        itineraries_stuck: u64,
+    ) -> Option<Self> {
+        if routes.is_empty() {
+            return Some(if itineraries_stuck > 0 {
+                Self::new(
+                    case,
+                    FuzzFailureKind::Stuck,
+                    format!("{itineraries_stuck} itineraries stuck"),
+                )
+            } else {
+                Self::new(case, FuzzFailureKind::NoRoute, "No routes".to_string())
+            });
+        }
+        let violations = routes
+            .iter()
+            .flat_map(|route| route.stats.violations.iter())
+            .collect::<Vec<_>>();
+        (!violations.is_empty()).then(|| {
+            Self::new(
+                case,
+                FuzzFailureKind::Violation,
+                serde_json::to_string(&violations).unwrap_or_default(),
+            )
+        })
+    }
+}
+
+/// Outcome of all cases. Cases without a road near the start or the finish are skipped
+#[derive(Debug, Serialize)]
+pub struct FuzzReport {
+    pub seed: u64,
+    pub count: usize,
+    pub skipped: usize,
+    pub passed: usize,
+    pub failures: Vec<FuzzFailure>,
+}
+
+impl FuzzReport {
+    pub fn write(&self) -> Result<(), FuzzError> {
+        let json =
+            serde_json::to_string_pretty(self).map_err(|error| FuzzError::WriteJson { error })?;
+        let mut stdout = io::stdout().lock();
+        io::Write::write_all(&mut stdout, json.as_bytes())
+            .and_then(|_| io::Write::write_all(&mut stdout, b"\n"))
+            .map_err(|error| FuzzError::Write { error })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::map_data::info::GraphInfoBbox;
+
+    use super::FuzzCase;
+
+    #[test]
+    fn fuzz_case_in_bbox() {
+        let bbox = GraphInfoBbox {
+            lat_min: 57.,
+            lat_max: 57.2,
+            lon_min: 24.,
+            lon_max: 24.4,
+        };
+        for seed in 0..100 {
+            let case = FuzzCase::new(seed, &bbox, 20_000);
+            assert_eq!(case, FuzzCase::new(seed, &bbox, 20_000));
+            for (lat, lon) in [case.start, case.finish] {
+                assert!((bbox.lat_min..=bbox.lat_max).contains(&lat));
+                assert!((bbox.lon_min..=bbox.lon_max).contains(&lon));
+            }
+            // 20km is at most 0.18 degrees of latitude
+            assert!((case.start.0 - case.finish.0).abs() < 0.18);
+        }
+        assert_ne!(
+            FuzzCase::new(1, &bbox, 20_000),
+            FuzzCase::new(2, &bbox, 20_000)
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use std::{num::ParseFloatError, time::Duration};
+
+use serde::Deserialize;
+use tracing::info;
+
+/// Public Nominatim instance, its usage policy allows a request per second at most
+pub const DEFAULT_GEOCODER_URL: &str = "https://nominatim.openstreetmap.org/search";
+
+const GEOCODER_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, thiserror::Error)]
+pub enum GeocoderError {
+    #[error("Geocoder request failed: {error}")]
+    Request { error: reqwest::Error },
+
+    #[error("No place found for address '{address}'")]
+    NotFound { address: String },
+
+    #[error("Geocoder returned invalid coordinates for '{address}': {error}")]
+    Coords {
+        address: String,
+        error: ParseFloatError,
+    },
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct AddressOptions {
+    #[arg(long, value_name = "ADDRESS", requires = "finish_address")]
+    /// Start address, looked up with the geocoder and used with `--finish-address` instead of
+    /// the routing mode
+    pub start_address: Option<String>,
+
+    #[arg(long, value_name = "ADDRESS", requires = "start_address")]
+    /// Finish address, looked up with the geocoder
+    pub finish_address: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "URL",
+        default_value = DEFAULT_GEOCODER_URL,
+        env = "RIDI_GEOCODER_URL"
+    )]
+    /// Search endpoint of a Nominatim compatible geocoder
+    pub geocoder_url: String,
+}
+
+/// Search result of a Nominatim compatible geocoder, it returns the coordinates as strings
+#[derive(Debug, Deserialize)]
+struct Place {
+    lat: String,
+    lon: String,
+    #[serde(default)]
+    display_name: Option<String>,
+}
+
+/// Looks up the coordinates of addresses with a Nominatim compatible search endpoint
+pub struct Geocoder {
+    url: String,
+    client: reqwest::blocking::Client,
+}
+
+impl Geocoder {
+    pub fn new(url: &str) -> Result<Self, GeocoderError> {
+        let client = reqwest::blocking::Client::builder()
+            .user_agent(concat!("ridi-router/", env!("CARGO_PKG_VERSION")))
+            .timeout(GEOCODER_TIMEOUT)
+            .build()
+            .map_err(|error| GeocoderError::Request { error })?;
+        Ok(Self {
+            url: url.to_string(),
+            client,
+        })
+    }
+
+    /// Coordinates of the best match for the address
+    pub fn resolve(&self, address: &str) -> Result<(f32, f32), GeocoderError> {
+        let places = self
+            .client
+            .get(&self.url)
+            .query(&[("q", address), ("format", "jsonv2"), ("limit", "1")])
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.json::<Vec<Place>>())
+            .map_err(|error| GeocoderError::Request { error })?;
+        first_place_coords(address, places)
+    }
+}
+
+fn first_place_coords(address: &str, places: Vec<Place>) -> Result<(f32, f32), GeocoderError> {
+    let place = places
+        .into_iter()
+        .next()
+        .ok_or_else(|| GeocoderError::NotFound {
+            address: address.to_string(),
+        })?;
+    let parse = |value: &str| {
+        value.parse::<f32>().map_err(|error| GeocoderError::Coords {
+            address: address.to_string(),
+            error,
+        })
+    };
+    let coords = (parse(&place.lat)?, parse(&place.lon)?);
+    info!(
+        address,
+        place = place.display_name,
+        lat = coords.0,
+        lon = coords.1,
+        "Address resolved"
+    );
+    Ok(coords)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{first_place_coords, GeocoderError, Place};
+
+    #[test]
+    fn geocoder_first_place_coords() {
+        let places: Vec<Place> = serde_json::from_str(
+            r#"[{"place_id":1,"lat":"57.1539600","lon":"24.8543548","display_name":"Sigulda"},
+                {"place_id":2,"lat":"56.9","lon":"24.1","display_name":"Riga"}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            first_place_coords("Sigulda", places).unwrap(),
+            (57.15396, 24.854355)
+        );
+        assert!(matches!(
+            first_place_coords("Nowhere", Vec::new()),
+            Err(GeocoderError::NotFound { .. })
+        ));
+    }
+}
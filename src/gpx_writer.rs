@@ -1,8 +1,20 @@
-use geo::Point;
-use gpx::{errors::GpxError, write, Gpx, GpxVersion, Route as GpxRoute, Waypoint};
-use std::{collections::HashMap, fs::File, io::Error, isize, path::PathBuf};
+use clap::{Args, ValueEnum};
+use geo::{Coord, LineString, Point, Rect, SimplifyIdx};
+use gpx::{
+    errors::GpxError, write, Gpx, GpxVersion, Metadata, Route as GpxRoute, Track, TrackSegment,
+    Waypoint,
+};
+use std::{collections::HashMap, io::Error, path::PathBuf};
 
-use crate::{ipc_handler::RouteMessage, router::route::RouteStatElement};
+use time::OffsetDateTime;
+
+use crate::{
+    ipc_handler::RouteMessage,
+    result_writer::AtomicFile,
+    router::route::RouteStatElement,
+    turns::{get_coord_distances_m, get_coord_elevations, get_turns},
+    units::Units,
+};
 
 #[derive(Debug, thiserror::Error)]
 pub enum GpxWriterError {
@@ -13,9 +25,60 @@ pub enum GpxWriterError {
     GpxWrite { error: GpxError },
 }
 
+/// GPX element used for the routes, some devices only import one of them
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum GpxType {
+    /// `<trk>` with a single track segment
+    Track,
+    /// `<rte>` with route points
+    #[default]
+    Route,
+}
+
+/// Options for GPX files, ignored for other output formats
+#[derive(Debug, Clone, Default, Args)]
+pub struct GpxOptions {
+    #[arg(long, value_enum, default_value_t = GpxType::Route)]
+    /// Write the routes to GPX files as tracks or routes
+    pub gpx_type: GpxType,
+
+    #[arg(long, value_name = "NUMBER")]
+    /// Simplify the routes in GPX files to at most this many points, for devices that limit the
+    /// number of points in a route
+    pub gpx_max_points: Option<usize>,
+}
+
+/// Starting simplification tolerance in degrees, doubled until the route fits the point limit
+const SIMPLIFY_START_EPSILON_DEG: f64 = 0.000_01;
+
+/// Indices of the coordinates left after simplifying the route to at most `max_points` points,
+/// the first and the last coordinate are always kept
+fn simplify_to_max_points(coords: &[(f32, f32)], max_points: usize) -> Vec<usize> {
+    if coords.len() <= max_points {
+        return (0..coords.len()).collect();
+    }
+    let line_string = coords
+        .iter()
+        .map(|(lat, lon)| Coord {
+            x: f64::from(*lon),
+            y: f64::from(*lat),
+        })
+        .collect::<LineString>();
+    let mut epsilon = SIMPLIFY_START_EPSILON_DEG;
+    loop {
+        let indices = line_string.simplify_idx(&epsilon);
+        if indices.len() <= max_points.max(2) {
+            return indices;
+        }
+        epsilon *= 2.;
+    }
+}
+
 pub struct GpxWriter {
     routes: Vec<RouteMessage>,
     file_name: PathBuf,
+    options: GpxOptions,
+    units: Units,
 }
 
 fn sort_by_longest(map: HashMap<String, RouteStatElement>) -> Vec<(String, RouteStatElement)> {
@@ -24,74 +87,221 @@ fn sort_by_longest(map: HashMap<String, RouteStatElement>) -> Vec<(String, Route
     vec
 }
 
+/// Human readable route statistics, used as the route description in the output files
+pub fn get_route_description(route: &RouteMessage, units: Units) -> String {
+    let mut description = String::new();
+    if route.stats.partial {
+        description.push_str("Partial route, the finish was not reached\n");
+    }
+    description.push_str(&format!(
+        "Length: {}\n",
+        units.distance(route.stats.len_m, 2)
+    ));
+    description.push_str(&format!(
+        "Estimated travel time: {}h {:02}min\n",
+        (route.stats.travel_time_s / 3600.) as u64,
+        (route.stats.travel_time_s % 3600. / 60.) as u64
+    ));
+    description.push_str(&format!(
+        "Number of junctions: {}\n",
+        route.stats.junction_count
+    ));
+    description.push_str(&format!(
+        "Cluster: {}\n",
+        route.stats.cluster.map_or(-1, |c| c as isize)
+    ));
+    description.push_str(&format!("Score: {:.2}\n", route.stats.score));
+    description.push_str(&format!(
+        "Score breakdown: curves {:.2}, highway {:.2}, surface {:.2}, smoothness {:.2}, junctions {:.2}, urban {:.2}\n",
+        route.stats.score_breakdown.curves,
+        route.stats.score_breakdown.highway,
+        route.stats.score_breakdown.surface,
+        route.stats.score_breakdown.smoothness,
+        route.stats.score_breakdown.junctions,
+        route.stats.score_breakdown.urban
+    ));
+    description.push_str(&format!(
+        "Curviness: {:.0}°/{}\n",
+        units.per_distance(route.stats.curviness),
+        units.distance_unit()
+    ));
+    description.push_str(&format!(
+        "Urban: {}, rural: {}\n",
+        units.distance(route.stats.urban_len_m, 2),
+        units.distance(route.stats.rural_len_m, 2)
+    ));
+    if !route.stats.elevation_profile.is_empty() {
+        description.push_str(&format!(
+            "Ascent: {}, descent: {}, max grade: {:.1}%\n",
+            units.elevation(route.stats.ascent_m),
+            units.elevation(route.stats.descent_m),
+            route.stats.max_grade_percent
+        ));
+    }
+    if !route.stats.vias_visited.is_empty() {
+        description.push_str(&format!(
+            "Vias visited: {}/{}\n",
+            route.stats.vias_visited.iter().filter(|v| **v).count(),
+            route.stats.vias_visited.len()
+        ));
+    }
+    if !route.stats.roundabout_exits.is_empty() {
+        description.push_str(&format!(
+            "Roundabout exits: {}\n",
+            route
+                .stats
+                .roundabout_exits
+                .iter()
+                .map(|exit| exit.exit_number.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    description.push_str("Road types:\n");
+    for (road_type, stat) in sort_by_longest(route.stats.highway.clone()).iter() {
+        description.push_str(&format!(
+            " - {road_type}: {}, {:.2}%\n",
+            units.distance(stat.len_m, 2),
+            stat.percentage,
+        ));
+    }
+    description.push_str("Road surface:\n");
+    for (surface_type, stat) in sort_by_longest(route.stats.surface.clone()).iter() {
+        description.push_str(&format!(
+            " - {surface_type}: {}, {:.2}%\n",
+            units.distance(stat.len_m, 2),
+            stat.percentage,
+        ));
+    }
+    description.push_str("Road smoothness:\n");
+    for (smoothness_type, stat) in sort_by_longest(route.stats.smoothness.clone()).iter() {
+        description.push_str(&format!(
+            " - {smoothness_type}: {}, {:.2}%\n",
+            units.distance(stat.len_m, 2),
+            stat.percentage,
+        ));
+    }
+    description
+}
+
 impl GpxWriter {
-    pub fn new(routes: Vec<RouteMessage>, file_name: PathBuf) -> Self {
-        Self { routes, file_name }
+    pub fn new(
+        routes: Vec<RouteMessage>,
+        file_name: PathBuf,
+        options: GpxOptions,
+        units: Units,
+    ) -> Self {
+        Self {
+            routes,
+            file_name,
+            options,
+            units,
+        }
     }
+
+    fn new_gpx(&self, routes: &[RouteMessage]) -> Gpx {
+        let mut coords = routes.iter().flat_map(|route| route.coords.iter());
+        let bounds = coords.next().map(|(lat, lon)| {
+            coords.fold(
+                (*lat, *lon, *lat, *lon),
+                |(min_lat, min_lon, max_lat, max_lon), (lat, lon)| {
+                    (
+                        min_lat.min(*lat),
+                        min_lon.min(*lon),
+                        max_lat.max(*lat),
+                        max_lon.max(*lon),
+                    )
+                },
+            )
+        });
+        Gpx {
+            version: GpxVersion::Gpx11,
+            creator: Some("ridi-router".to_string()),
+            metadata: Some(Metadata {
+                name: self
+                    .file_name
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string()),
+                description: Some(format!("{} routes generated by ridi-router", routes.len())),
+                time: Some(OffsetDateTime::now_utc().into()),
+                bounds: bounds.map(|(min_lat, min_lon, max_lat, max_lon)| {
+                    Rect::new(
+                        Coord {
+                            x: f64::from(min_lon),
+                            y: f64::from(min_lat),
+                        },
+                        Coord {
+                            x: f64::from(max_lon),
+                            y: f64::from(max_lat),
+                        },
+                    )
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
     pub fn write_gpx(self) -> Result<(), GpxWriterError> {
         #[cfg(not(feature = "debug-split-gpx"))]
-        let mut gpx = {
-            let mut gpx = Gpx::default();
-            gpx.version = GpxVersion::Gpx11;
-            gpx
-        };
+        let mut gpx = self.new_gpx(&self.routes);
         for (idx, route) in self.routes.clone().into_iter().enumerate() {
             #[cfg(feature = "debug-split-gpx")]
-            let mut gpx = {
-                let mut gpx = Gpx::default();
-                gpx.version = GpxVersion::Gpx11;
-                gpx
-            };
-            let mut gpx_route = GpxRoute::new();
-            gpx_route.name = Some(format!(
+            let mut gpx = self.new_gpx(std::slice::from_ref(&route));
+            let route_name = format!(
                 "r_{idx}_c_{}",
                 route.stats.cluster.map_or(-1, |c| c as isize)
-            ));
-
-            let mut description = String::new();
-            description.push_str(&format!("Length: {:.2}km\n", route.stats.len_m / 1000.));
-            description.push_str(&format!(
-                "Number of junctions: {}\n",
-                route.stats.junction_count
-            ));
-            description.push_str(&format!(
-                "Cluster: {}\n",
-                route.stats.cluster.map_or(-1, |c| c as isize)
-            ));
-            description.push_str(&format!("Score: {:.2}\n", route.stats.score));
-            description.push_str("Road types:\n");
-            for (road_type, stat) in sort_by_longest(route.stats.highway).iter() {
-                description.push_str(&format!(
-                    " - {road_type}: {:.2}km, {:.2}%\n",
-                    stat.len_m / 1000.,
-                    stat.percentage,
-                ));
-            }
-            description.push_str("Road surface:\n");
-            for (surface_type, stat) in sort_by_longest(route.stats.surface).iter() {
-                description.push_str(&format!(
-                    " - {surface_type}: {:.2}km, {:.2}%\n",
-                    stat.len_m / 1000.,
-                    stat.percentage,
-                ));
-            }
-            description.push_str("Road smoothness:\n");
-            for (smoothness_type, stat) in sort_by_longest(route.stats.smoothness).iter() {
-                description.push_str(&format!(
-                    " - {smoothness_type}: {:.2}km, {:.2}%\n",
-                    stat.len_m / 1000.,
-                    stat.percentage,
-                ));
-            }
+            );
+            let distances_m = get_coord_distances_m(&route.coords);
+            let elevations = get_coord_elevations(&route, &distances_m);
+            let to_waypoint = |idx: usize| {
+                let (lat, lon) = route.coords[idx];
+                let mut waypoint = Waypoint::new(Point::new(lon as f64, lat as f64));
+                waypoint.elevation = elevations
+                    .as_ref()
+                    .map(|elevations| f64::from(elevations[idx]));
+                waypoint
+            };
 
-            gpx_route.description = Some(description);
+            let point_indices = match self.options.gpx_max_points {
+                Some(max_points) => simplify_to_max_points(&route.coords, max_points),
+                None => (0..route.coords.len()).collect(),
+            };
+            let points = point_indices
+                .into_iter()
+                .map(to_waypoint)
+                .collect::<Vec<_>>();
 
-            for (lat, lon) in &route.coords {
-                let waypoint = Waypoint::new(Point::new(*lon as f64, *lat as f64));
-                gpx_route.points.push(waypoint);
+            for turn in get_turns(&route, &distances_m) {
+                let mut waypoint = to_waypoint(turn.coord_idx);
+                waypoint.name = Some(turn.kind.name());
+                waypoint.description = Some(format!(
+                    "{route_name} at {}",
+                    self.units
+                        .distance(f64::from(distances_m[turn.coord_idx]), 2)
+                ));
+                waypoint.type_ = Some("turn".to_string());
+                gpx.waypoints.push(waypoint);
             }
 
-            gpx.routes.push(gpx_route);
+            match self.options.gpx_type {
+                GpxType::Route => {
+                    let mut gpx_route = GpxRoute::new();
+                    gpx_route.name = Some(route_name);
+                    gpx_route.description = Some(get_route_description(&route, self.units));
+                    gpx_route.points = points;
+                    gpx.routes.push(gpx_route);
+                }
+                GpxType::Track => {
+                    let mut track = Track::new();
+                    track.name = Some(route_name);
+                    track.description = Some(get_route_description(&route, self.units));
+                    let mut segment = TrackSegment::new();
+                    segment.points = points;
+                    track.segments.push(segment);
+                    gpx.tracks.push(track);
+                }
+            }
             #[cfg(feature = "debug-split-gpx")]
             {
                 let mut filename = PathBuf::from(&self.file_name);
@@ -100,20 +310,49 @@ impl GpxWriter {
                     filename.file_name().unwrap().to_string_lossy(),
                     idx
                 ));
-                let file = File::create(&filename)
+                let mut file = AtomicFile::create(&filename)
                     .map_err(|error| GpxWriterError::FileCreateError { error })?;
 
-                write(&gpx, file).map_err(|error| GpxWriterError::GpxWrite { error })?;
+                write(&gpx, &mut file).map_err(|error| GpxWriterError::GpxWrite { error })?;
+                file.commit()
+                    .map_err(|error| GpxWriterError::FileCreateError { error })?;
             }
         }
         #[cfg(not(feature = "debug-split-gpx"))]
         {
-            let file = File::create(&self.file_name)
+            let mut file = AtomicFile::create(&self.file_name)
                 .map_err(|error| GpxWriterError::FileCreateError { error })?;
 
-            write(&gpx, file).map_err(|error| GpxWriterError::GpxWrite { error })?;
+            write(&gpx, &mut file).map_err(|error| GpxWriterError::GpxWrite { error })?;
+            file.commit()
+                .map_err(|error| GpxWriterError::FileCreateError { error })?;
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::simplify_to_max_points;
+
+    #[test]
+    fn simplify_to_max_points_test() {
+        let coords = (0..100)
+            .map(|idx| {
+                let idx = idx as f32;
+                (57. + idx * 0.001, 24. + (idx * 0.3).sin() * 0.001)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(simplify_to_max_points(&coords, 100).len(), 100);
+
+        for max_points in [2, 10, 50] {
+            let indices = simplify_to_max_points(&coords, max_points);
+            assert!(indices.len() <= max_points);
+            assert!(indices.len() >= 2);
+            assert_eq!(indices.first(), Some(&0));
+            assert_eq!(indices.last(), Some(&99));
+        }
+    }
+}
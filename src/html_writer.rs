@@ -0,0 +1,321 @@
+use std::{
+    io::{BufWriter, Error, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    gpx_writer::get_route_description, ipc_handler::RouteMessage, kml_writer::escape_xml,
+    result_writer::AtomicFile, units::Units,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum HtmlWriterError {
+    #[error("File Creation Error {error}")]
+    FileCreateError { error: Error },
+
+    #[error("Html Write Error {error}")]
+    HtmlWrite { error: Error },
+}
+
+/// Route line colors, reused when there are more routes
+pub const ROUTE_COLORS: [&str; 8] = [
+    "#e6194b", "#4363d8", "#3cb44b", "#f58231", "#911eb4", "#42d4f4", "#f032e6", "#9a6324",
+];
+
+/// Width of the map in svg units, the height follows the shape of the area covered by the routes
+const MAP_WIDTH: f64 = 1000.;
+const MAP_MAX_HEIGHT: f64 = 1000.;
+const MAP_PADDING: f64 = 20.;
+
+const STYLE: &str = r#"
+body { font-family: sans-serif; margin: 1em; color: #222; }
+svg { width: 100%; max-height: 70vh; background: #f4f4f0; border: 1px solid #ccc; }
+polyline { fill: none; stroke-width: 3; stroke-linejoin: round; stroke-linecap: round; }
+svg.highlight polyline { opacity: 0.2; }
+svg.highlight polyline.active { opacity: 1; stroke-width: 5; }
+table { border-collapse: collapse; margin-top: 1em; }
+th, td { padding: 0.3em 0.6em; border-bottom: 1px solid #ddd; text-align: right; }
+tr.active { background: #eef; }
+.swatch { display: inline-block; width: 1em; height: 1em; vertical-align: middle; }
+.bar { display: flex; width: 12em; height: 0.8em; background: #eee; }
+.bar span { display: block; height: 100%; }
+pre { white-space: pre-wrap; margin: 0.3em 0; text-align: left; }
+"#;
+
+/// Highlights the route on the map when hovering its row in the table
+const SCRIPT: &str = r#"
+const map = document.querySelector("svg");
+document.querySelectorAll("tr[data-route]").forEach((row) => {
+  const line = document.getElementById("route_" + row.dataset.route);
+  row.addEventListener("mouseenter", () => {
+    map.classList.add("highlight");
+    line.classList.add("active");
+    row.classList.add("active");
+  });
+  row.addEventListener("mouseleave", () => {
+    map.classList.remove("highlight");
+    line.classList.remove("active");
+    row.classList.remove("active");
+  });
+});
+"#;
+
+/// Colors of the score breakdown parts: curves, highway, surface and smoothness
+const BREAKDOWN_COLORS: [&str; 4] = ["#f58231", "#4363d8", "#9a6324", "#3cb44b"];
+
+/// Writes a single html file with all the routes drawn on a map and a table of their statistics.
+/// Everything is inline so the file can be opened and shared without network access, which is
+/// also why there are no map tiles under the routes
+pub struct HtmlWriter {
+    routes: Vec<RouteMessage>,
+    file_name: PathBuf,
+    units: Units,
+}
+
+/// Projects route coordinates to a drawing of the given width that fits all the routes, the
+/// height follows the shape of the area covered by the routes
+pub struct Projection {
+    min_x: f64,
+    max_y: f64,
+    scale: f64,
+    cos_lat: f64,
+    pub height: f64,
+}
+
+impl Projection {
+    pub fn new(routes: &[RouteMessage], width: f64, max_height: f64, padding: f64) -> Self {
+        let coords = || routes.iter().flat_map(|route| route.coords.iter());
+        let min_lat = coords().map(|c| f64::from(c.0)).fold(f64::MAX, f64::min);
+        let max_lat = coords().map(|c| f64::from(c.0)).fold(f64::MIN, f64::max);
+        let min_lon = coords().map(|c| f64::from(c.1)).fold(f64::MAX, f64::min);
+        let max_lon = coords().map(|c| f64::from(c.1)).fold(f64::MIN, f64::max);
+        if min_lat > max_lat {
+            return Self {
+                min_x: 0.,
+                max_y: 0.,
+                scale: 1.,
+                cos_lat: 1.,
+                height: width / 2.,
+            };
+        }
+
+        let cos_lat = ((min_lat + max_lat) / 2.).to_radians().cos();
+        let width_deg = ((max_lon - min_lon) * cos_lat).max(f64::EPSILON);
+        let height_deg = (max_lat - min_lat).max(f64::EPSILON);
+        let scale =
+            ((width - 2. * padding) / width_deg).min((max_height - 2. * padding) / height_deg);
+        Self {
+            min_x: min_lon * cos_lat - padding / scale,
+            max_y: max_lat + padding / scale,
+            scale,
+            cos_lat,
+            height: height_deg * scale + 2. * padding,
+        }
+    }
+
+    pub fn project(&self, lat: f32, lon: f32) -> (f64, f64) {
+        (
+            (f64::from(lon) * self.cos_lat - self.min_x) * self.scale,
+            (self.max_y - f64::from(lat)) * self.scale,
+        )
+    }
+}
+
+impl HtmlWriter {
+    pub fn new(routes: Vec<RouteMessage>, file_name: PathBuf, units: Units) -> Self {
+        Self {
+            routes,
+            file_name,
+            units,
+        }
+    }
+
+    fn write_map<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        let projection = Projection::new(&self.routes, MAP_WIDTH, MAP_MAX_HEIGHT, MAP_PADDING);
+        writeln!(
+            writer,
+            r#"<svg viewBox="0 0 {MAP_WIDTH:.0} {:.0}" xmlns="http://www.w3.org/2000/svg">"#,
+            projection.height
+        )?;
+        // drawn in reverse so the first routes end up on top
+        for (idx, route) in self.routes.iter().enumerate().rev() {
+            write!(
+                writer,
+                r#"<polyline id="route_{idx}" stroke="{}" points=""#,
+                ROUTE_COLORS[idx % ROUTE_COLORS.len()]
+            )?;
+            for (lat, lon) in &route.coords {
+                let (x, y) = projection.project(*lat, *lon);
+                write!(writer, "{x:.1},{y:.1} ")?;
+            }
+            writeln!(writer, r#""><title>Route {idx}</title></polyline>"#)?;
+        }
+        let endpoints = self
+            .routes
+            .iter()
+            .filter_map(|route| Some((route.coords.first()?, route.coords.last()?)));
+        for (start, finish) in endpoints {
+            for ((lat, lon), color) in [(start, "#0a0"), (finish, "#a00")] {
+                let (x, y) = projection.project(*lat, *lon);
+                writeln!(
+                    writer,
+                    r##"<circle cx="{x:.1}" cy="{y:.1}" r="6" fill="{color}" stroke="#fff" stroke-width="2"/>"##
+                )?;
+            }
+        }
+        writeln!(writer, "</svg>")
+    }
+
+    fn write_table<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        writeln!(writer, "<table>")?;
+        writeln!(
+            writer,
+            "<tr><th>Route</th><th>Length</th><th>Travel time</th><th>Score</th><th>Score breakdown</th><th>Curviness</th><th>Junctions</th><th>Cluster</th><th>Details</th></tr>"
+        )?;
+        for (idx, route) in self.routes.iter().enumerate() {
+            let stats = &route.stats;
+            let breakdown = [
+                ("curves", stats.score_breakdown.curves),
+                ("highway", stats.score_breakdown.highway),
+                ("surface", stats.score_breakdown.surface),
+                ("smoothness", stats.score_breakdown.smoothness),
+                ("junctions", stats.score_breakdown.junctions),
+                ("urban", stats.score_breakdown.urban),
+            ];
+            let breakdown_total = breakdown
+                .iter()
+                .map(|(_, value)| value.max(0.))
+                .sum::<f64>();
+            write!(writer, r#"<tr data-route="{idx}">"#)?;
+            write!(
+                writer,
+                r#"<td><span class="swatch" style="background: {}"></span> {idx}</td>"#,
+                ROUTE_COLORS[idx % ROUTE_COLORS.len()]
+            )?;
+            write!(writer, "<td>{}</td>", self.units.distance(stats.len_m, 1))?;
+            write!(
+                writer,
+                "<td>{}h {:02}min</td>",
+                (stats.travel_time_s / 3600.) as u64,
+                (stats.travel_time_s % 3600. / 60.) as u64
+            )?;
+            write!(writer, "<td>{:.2}</td>", stats.score)?;
+            write!(writer, r#"<td><div class="bar">"#)?;
+            for ((name, value), color) in breakdown.iter().zip(BREAKDOWN_COLORS) {
+                if breakdown_total > 0. && *value > 0. {
+                    write!(
+                        writer,
+                        r#"<span title="{name} {value:.2}" style="width: {:.1}%; background: {color}"></span>"#,
+                        value / breakdown_total * 100.
+                    )?;
+                }
+            }
+            write!(writer, "</div></td>")?;
+            write!(
+                writer,
+                "<td>{:.0}°/{}</td>",
+                self.units.per_distance(stats.curviness),
+                self.units.distance_unit()
+            )?;
+            write!(writer, "<td>{}</td>", stats.junction_count)?;
+            write!(
+                writer,
+                "<td>{}</td>",
+                stats.cluster.map_or("-".to_string(), |c| c.to_string())
+            )?;
+            writeln!(
+                writer,
+                "<td><details><summary>Stats</summary><pre>{}</pre></details></td></tr>",
+                escape_xml(&get_route_description(route, self.units))
+            )?;
+        }
+        writeln!(writer, "</table>")?;
+        write!(writer, "<p>Score breakdown: ")?;
+        for (name, color) in ["curves", "highway", "surface", "smoothness"]
+            .iter()
+            .zip(BREAKDOWN_COLORS)
+        {
+            write!(
+                writer,
+                r#"<span class="swatch" style="background: {color}"></span> {name} "#
+            )?;
+        }
+        writeln!(writer, "</p>")
+    }
+
+    fn write_document<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        writeln!(writer, "<!DOCTYPE html>")?;
+        writeln!(writer, r#"<html lang="en">"#)?;
+        writeln!(writer, "<head>")?;
+        writeln!(writer, r#"<meta charset="utf-8">"#)?;
+        writeln!(writer, "<title>ridi-router routes</title>")?;
+        writeln!(writer, "<style>{STYLE}</style>")?;
+        writeln!(writer, "</head>")?;
+        writeln!(writer, "<body>")?;
+        writeln!(writer, "<h1>{} routes</h1>", self.routes.len())?;
+        self.write_map(writer)?;
+        self.write_table(writer)?;
+        writeln!(writer, "<script>{SCRIPT}</script>")?;
+        writeln!(writer, "</body>")?;
+        writeln!(writer, "</html>")?;
+        writer.flush()
+    }
+
+    pub fn write_html(self) -> Result<(), HtmlWriterError> {
+        let file = AtomicFile::create(&self.file_name)
+            .map_err(|error| HtmlWriterError::FileCreateError { error })?;
+
+        let mut writer = BufWriter::new(file);
+        self.write_document(&mut writer)
+            .and_then(|_| writer.into_inner().map_err(|error| error.into_error()))
+            .and_then(AtomicFile::commit)
+            .map_err(|error| HtmlWriterError::HtmlWrite { error })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ipc_handler::RouteMessage, router::route::RouteStats, units::Units};
+
+    use super::{HtmlWriter, Projection, MAP_MAX_HEIGHT, MAP_PADDING, MAP_WIDTH};
+
+    #[test]
+    fn html_report_document() {
+        let stats: RouteStats = serde_json::from_str(
+            r#"{"len_m": 1000, "junction_count": 3, "highway": {"<script>": {"len_m": 1000, "percentage": 100}}, "surface": {}, "smoothness": {}, "score": 4.5, "cluster": 1, "approximated_route": []}"#,
+        )
+        .unwrap();
+        let route = RouteMessage {
+            coords: vec![(57.0, 24.0), (57.01, 24.0), (57.01, 24.02)],
+            stats,
+            segments: None,
+        };
+
+        let projection = Projection::new(
+            std::slice::from_ref(&route),
+            MAP_WIDTH,
+            MAP_MAX_HEIGHT,
+            MAP_PADDING,
+        );
+        let (x, y) = projection.project(57.0, 24.0);
+        assert!((x - MAP_PADDING).abs() < 0.1);
+        assert!((y - (projection.height - MAP_PADDING)).abs() < 0.1);
+        let (x, y) = projection.project(57.01, 24.02);
+        assert!((x - (MAP_WIDTH - MAP_PADDING)).abs() < 0.1);
+        assert!((y - MAP_PADDING).abs() < 0.1);
+
+        let mut output = Vec::new();
+        HtmlWriter::new(
+            vec![route.clone(), route],
+            "routes.html".into(),
+            Units::Imperial,
+        )
+        .write_document(&mut output)
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.matches("<polyline").count(), 2);
+        assert_eq!(output.matches("<tr data-route").count(), 2);
+        assert!(output.contains("&lt;script&gt;"));
+        assert!(output.contains("<td>0.6mi</td>"));
+    }
+}
@@ -0,0 +1,324 @@
+use std::{io::Error, path::PathBuf};
+
+use clap::Args;
+
+use crate::ipc_handler::RouteMessage;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImageWriterError {
+    #[error("Image output requires the router to be built with the `render` feature")]
+    Disabled,
+
+    #[error("File Creation Error {error}")]
+    FileCreateError { error: Error },
+
+    #[error("Image Write Error {error}")]
+    ImageWrite { error: Error },
+}
+
+/// Options for png and svg files, ignored for other output formats
+#[derive(Debug, Clone, Default, Args)]
+pub struct RenderOptions {
+    #[arg(long)]
+    /// Draw the map data lines around the routes in png and svg files
+    pub render_graph_lines: bool,
+}
+
+/// Draws the routes to a png or svg file, chosen by the file extension, without a basemap. Meant
+/// for quick previews where opening a GIS tool is not an option
+#[cfg_attr(not(feature = "render"), allow(dead_code))]
+pub struct ImageWriter {
+    routes: Vec<RouteMessage>,
+    file_name: PathBuf,
+    options: RenderOptions,
+}
+
+impl ImageWriter {
+    pub fn new(routes: Vec<RouteMessage>, file_name: PathBuf, options: RenderOptions) -> Self {
+        Self {
+            routes,
+            file_name,
+            options,
+        }
+    }
+
+    #[cfg(not(feature = "render"))]
+    pub fn write_image(self) -> Result<(), ImageWriterError> {
+        Err(ImageWriterError::Disabled)
+    }
+
+    #[cfg(feature = "render")]
+    pub fn write_image(self) -> Result<(), ImageWriterError> {
+        let drawing = render::Drawing::new(&self.routes, self.options.render_graph_lines);
+        let file = crate::result_writer::AtomicFile::create(&self.file_name)
+            .map_err(|error| ImageWriterError::FileCreateError { error })?;
+        let mut writer = std::io::BufWriter::new(file);
+        if self.file_name.extension().is_some_and(|ext| ext == "svg") {
+            drawing.write_svg(&mut writer)
+        } else {
+            drawing.write_png(&mut writer)
+        }
+        .and_then(|_| writer.into_inner().map_err(|error| error.into_error()))
+        .and_then(crate::result_writer::AtomicFile::commit)
+        .map_err(|error| ImageWriterError::ImageWrite { error })
+    }
+}
+
+#[cfg(feature = "render")]
+mod render {
+    use std::io::{Error, Write};
+
+    use flate2::{write::ZlibEncoder, Compression};
+
+    use crate::{
+        html_writer::{Projection, ROUTE_COLORS},
+        ipc_handler::RouteMessage,
+        map_data::graph::MapDataGraph,
+    };
+
+    const IMAGE_WIDTH: f64 = 1200.;
+    const IMAGE_MAX_HEIGHT: f64 = 1200.;
+    const IMAGE_PADDING: f64 = 24.;
+
+    const BACKGROUND_COLOR: [u8; 3] = [0xf4, 0xf4, 0xf0];
+    const GRAPH_LINE_COLOR: [u8; 3] = [0xbb, 0xbb, 0xbb];
+    const START_COLOR: [u8; 3] = [0x00, 0xaa, 0x00];
+    const FINISH_COLOR: [u8; 3] = [0xaa, 0x00, 0x00];
+
+    const GRAPH_LINE_WIDTH: f64 = 1.;
+    const ROUTE_LINE_WIDTH: f64 = 3.;
+    const ENDPOINT_RADIUS: f64 = 6.;
+
+    fn parse_color(hex: &str) -> [u8; 3] {
+        let channel = |idx: usize| u8::from_str_radix(&hex[idx..idx + 2], 16).unwrap_or(0);
+        [channel(1), channel(3), channel(5)]
+    }
+
+    fn format_color(color: [u8; 3]) -> String {
+        format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+    }
+
+    struct Line {
+        points: Vec<(f64, f64)>,
+        color: [u8; 3],
+        width: f64,
+    }
+
+    /// Routes and graph lines projected to image coordinates, in drawing order
+    pub struct Drawing {
+        width: usize,
+        height: usize,
+        lines: Vec<Line>,
+        endpoints: Vec<((f64, f64), [u8; 3])>,
+    }
+
+    impl Drawing {
+        pub fn new(routes: &[RouteMessage], graph_lines: bool) -> Self {
+            let projection = Projection::new(routes, IMAGE_WIDTH, IMAGE_MAX_HEIGHT, IMAGE_PADDING);
+            let mut lines = Vec::new();
+
+            let coords = || routes.iter().flat_map(|route| route.coords.iter());
+            if graph_lines && coords().next().is_some() {
+                let min = coords().fold((f32::MAX, f32::MAX), |min, (lat, lon)| {
+                    (min.0.min(*lat), min.1.min(*lon))
+                });
+                let max = coords().fold((f32::MIN, f32::MIN), |max, (lat, lon)| {
+                    (max.0.max(*lat), max.1.max(*lon))
+                });
+                for (from, to) in MapDataGraph::get().get_line_coords_in_bounds(min, max) {
+                    lines.push(Line {
+                        points: vec![
+                            projection.project(from.0, from.1),
+                            projection.project(to.0, to.1),
+                        ],
+                        color: GRAPH_LINE_COLOR,
+                        width: GRAPH_LINE_WIDTH,
+                    });
+                }
+            }
+
+            // drawn in reverse so the first routes end up on top
+            for (idx, route) in routes.iter().enumerate().rev() {
+                lines.push(Line {
+                    points: route
+                        .coords
+                        .iter()
+                        .map(|(lat, lon)| projection.project(*lat, *lon))
+                        .collect(),
+                    color: parse_color(ROUTE_COLORS[idx % ROUTE_COLORS.len()]),
+                    width: ROUTE_LINE_WIDTH,
+                });
+            }
+
+            let mut endpoints = Vec::new();
+            for route in routes {
+                if let (Some(start), Some(finish)) = (route.coords.first(), route.coords.last()) {
+                    endpoints.push((projection.project(start.0, start.1), START_COLOR));
+                    endpoints.push((projection.project(finish.0, finish.1), FINISH_COLOR));
+                }
+            }
+
+            Self {
+                width: IMAGE_WIDTH as usize,
+                height: projection.height.ceil() as usize,
+                lines,
+                endpoints,
+            }
+        }
+
+        pub fn write_svg<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+            writeln!(
+                writer,
+                r#"<svg viewBox="0 0 {} {}" width="{}" height="{}" xmlns="http://www.w3.org/2000/svg">"#,
+                self.width, self.height, self.width, self.height
+            )?;
+            writeln!(
+                writer,
+                r#"<rect width="100%" height="100%" fill="{}"/>"#,
+                format_color(BACKGROUND_COLOR)
+            )?;
+            for line in &self.lines {
+                write!(
+                    writer,
+                    r#"<polyline fill="none" stroke="{}" stroke-width="{}" stroke-linejoin="round" stroke-linecap="round" points=""#,
+                    format_color(line.color),
+                    line.width
+                )?;
+                for (x, y) in &line.points {
+                    write!(writer, "{x:.1},{y:.1} ")?;
+                }
+                writeln!(writer, r#""/>"#)?;
+            }
+            for ((x, y), color) in &self.endpoints {
+                writeln!(
+                    writer,
+                    r##"<circle cx="{x:.1}" cy="{y:.1}" r="{ENDPOINT_RADIUS}" fill="{}" stroke="#ffffff" stroke-width="2"/>"##,
+                    format_color(*color)
+                )?;
+            }
+            writeln!(writer, "</svg>")?;
+            writer.flush()
+        }
+
+        fn rasterize(&self) -> Vec<u8> {
+            let mut pixels = BACKGROUND_COLOR.repeat(self.width * self.height);
+            let mut fill_disc = |(cx, cy): (f64, f64), radius: f64, color: [u8; 3]| {
+                let min_x = (cx - radius).floor().max(0.) as usize;
+                let min_y = (cy - radius).floor().max(0.) as usize;
+                let max_x = ((cx + radius).ceil().max(0.) as usize).min(self.width - 1);
+                let max_y = ((cy + radius).ceil().max(0.) as usize).min(self.height - 1);
+                for y in min_y..=max_y {
+                    for x in min_x..=max_x {
+                        let (dx, dy) = (x as f64 + 0.5 - cx, y as f64 + 0.5 - cy);
+                        if dx * dx + dy * dy <= radius * radius {
+                            let idx = (y * self.width + x) * 3;
+                            pixels[idx..idx + 3].copy_from_slice(&color);
+                        }
+                    }
+                }
+            };
+            for line in &self.lines {
+                let radius = (line.width / 2.).max(0.5);
+                for window in line.points.windows(2) {
+                    let ((x0, y0), (x1, y1)) = (window[0], window[1]);
+                    let steps = (x1 - x0).abs().max((y1 - y0).abs()).ceil().max(1.) as usize;
+                    for step in 0..=steps {
+                        let t = step as f64 / steps as f64;
+                        fill_disc((x0 + (x1 - x0) * t, y0 + (y1 - y0) * t), radius, line.color);
+                    }
+                }
+            }
+            for (center, color) in &self.endpoints {
+                fill_disc(*center, ENDPOINT_RADIUS + 2., [0xff, 0xff, 0xff]);
+                fill_disc(*center, ENDPOINT_RADIUS, *color);
+            }
+            pixels
+        }
+
+        fn write_png_chunk<W: Write>(
+            writer: &mut W,
+            chunk_type: &[u8; 4],
+            data: &[u8],
+        ) -> Result<(), Error> {
+            writer.write_all(&(data.len() as u32).to_be_bytes())?;
+            writer.write_all(chunk_type)?;
+            writer.write_all(data)?;
+            let mut crc = crc32fast::Hasher::new();
+            crc.update(chunk_type);
+            crc.update(data);
+            writer.write_all(&crc.finalize().to_be_bytes())
+        }
+
+        pub fn write_png<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+            let pixels = self.rasterize();
+
+            let mut header = Vec::new();
+            header.extend_from_slice(&(self.width as u32).to_be_bytes());
+            header.extend_from_slice(&(self.height as u32).to_be_bytes());
+            // 8 bit depth, truecolor, deflate compression, adaptive filtering, no interlace
+            header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            for row in pixels.chunks(self.width * 3) {
+                // filter type none
+                encoder.write_all(&[0])?;
+                encoder.write_all(row)?;
+            }
+            let image_data = encoder.finish()?;
+
+            writer.write_all(b"\x89PNG\r\n\x1a\n")?;
+            Self::write_png_chunk(writer, b"IHDR", &header)?;
+            Self::write_png_chunk(writer, b"IDAT", &image_data)?;
+            Self::write_png_chunk(writer, b"IEND", &[])?;
+            writer.flush()
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use std::io::Read;
+
+        use flate2::read::ZlibDecoder;
+
+        use crate::{ipc_handler::RouteMessage, router::route::RouteStats};
+
+        use super::{Drawing, BACKGROUND_COLOR, FINISH_COLOR};
+
+        #[test]
+        fn png_encoding() {
+            let stats: RouteStats = serde_json::from_str(
+                r#"{"len_m": 1000, "junction_count": 3, "highway": {}, "surface": {}, "smoothness": {}, "score": 4.5, "cluster": 1, "approximated_route": []}"#,
+            )
+            .unwrap();
+            let route = RouteMessage {
+                coords: vec![(57.0, 24.0), (57.01, 24.0), (57.01, 24.02)],
+                stats,
+                segments: None,
+            };
+            let drawing = Drawing::new(&[route], false);
+            let mut png = Vec::new();
+            drawing.write_png(&mut png).unwrap();
+
+            assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+            assert_eq!(&png[12..16], b"IHDR");
+            let width = u32::from_be_bytes(png[16..20].try_into().unwrap()) as usize;
+            let height = u32::from_be_bytes(png[20..24].try_into().unwrap()) as usize;
+            assert_eq!((width, height), (drawing.width, drawing.height));
+
+            let idat_len = u32::from_be_bytes(png[33..37].try_into().unwrap()) as usize;
+            assert_eq!(&png[37..41], b"IDAT");
+            let mut raw = Vec::new();
+            ZlibDecoder::new(&png[41..41 + idat_len])
+                .read_to_end(&mut raw)
+                .unwrap();
+            assert_eq!(raw.len(), (width * 3 + 1) * height);
+            assert_eq!(&raw[1..4], &BACKGROUND_COLOR);
+
+            // the finish is at the top right corner, inside the padding
+            let (x, y) = drawing.endpoints[1].0;
+            let idx = y as usize * (width * 3 + 1) + 1 + x as usize * 3;
+            assert_eq!(&raw[idx..idx + 3], &FINISH_COLOR);
+            assert!(png.ends_with(b"IEND\xae\x42\x60\x82"));
+        }
+    }
+}
@@ -1,16 +1,39 @@
-use interprocess::local_socket::{prelude::*, GenericNamespaced, ListenerOptions, Name, Stream};
+#[cfg(not(target_arch = "wasm32"))]
+use interprocess::local_socket::{
+    prelude::*, GenericFilePath, GenericNamespaced, ListenerOptions, Name, Stream,
+};
+#[cfg(all(not(target_arch = "wasm32"), unix))]
+use interprocess::TryClone;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
 use std::{
     io::{self, prelude::*, BufReader},
-    time::{SystemTime, UNIX_EPOCH},
+    net::{IpAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
+use time::OffsetDateTime;
+#[cfg(not(target_arch = "wasm32"))]
 use tracing::{info, trace, warn};
 
 use crate::{
-    router::{route::RouteStats, rules::RouterRules},
+    router::{
+        progress::GenerationProgress,
+        route::{RouteSegmentDetail, RouteStats},
+        rules::RouterRules,
+        summary::GenerationSummary,
+    },
     router_runner::RoutingMode,
+    worker_pool::Priority,
 };
 
+#[cfg(not(target_arch = "wasm32"))]
 #[derive(Debug, thiserror::Error)]
 pub enum IpcHandlerError {
     #[error("Namespace Name cannot be created, cause {error}")]
@@ -34,6 +57,15 @@ pub enum IpcHandlerError {
     #[error("Failed to connect to IPC socket: {error}")]
     Connect { error: io::Error },
 
+    #[error("No server running to connect to: {error}")]
+    ServerNotRunning { error: io::Error },
+
+    #[error("Server is busy: {message}")]
+    ServerBusy { message: String },
+
+    #[error("No response from the server within {timeout:?}")]
+    ResponseTimeout { timeout: Duration },
+
     #[error("Failed to extract utf8 from message: {error}")]
     Utf8Message { error: std::str::Utf8Error },
 
@@ -42,147 +74,862 @@ pub enum IpcHandlerError {
 
     #[error("Failed to serialize message: {error}")]
     SerializeMessage { error: serde_json::Error },
+
+    #[error("Failed to set socket permissions: {error}")]
+    SocketPermissions { error: io::Error },
+
+    #[error("Chunked response checksum {actual:08x} does not match {expected:08x}")]
+    ChunkChecksum { expected: u32, actual: u32 },
+
+    #[error("Message of {size} bytes is larger than the {max} bytes allowed")]
+    MessageTooLarge { size: u64, max: u64 },
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct RequestMessage {
     pub id: String,
     pub routing_mode: RoutingMode,
-    pub rules: RouterRules,
+    /// Complete rules for the request, the rules the server was started with are used when not
+    /// given. The `when` conditions of the rules are evaluated for the start of the route and
+    /// the departure time
+    #[serde(default)]
+    #[schemars(with = "Option<RouterRules>")]
+    pub rules: Option<serde_json::Value>,
+    /// Partial rules merged onto the rules of the request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule_overrides: Option<serde_json::Value>,
+    /// Departure time like 2025-06-21T18:30:00+03:00, the `when` conditions of the rules are
+    /// evaluated for it. The current time of the server when not given
+    #[serde(
+        default,
+        with = "time::serde::rfc3339::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(with = "Option<String>")]
+    pub departure_time: Option<OffsetDateTime>,
+    /// Time limit for route generation in seconds, routes finished by then are returned
+    #[serde(default)]
+    pub timeout_s: Option<u64>,
+    /// Send progress messages with the same id before the final response
+    #[serde(default)]
+    pub progress: bool,
+    /// Send every route in a message with the same id as soon as it is generated, before the
+    /// final response
+    #[serde(default)]
+    pub stream_routes: bool,
+    /// Seed for breaking ties between equally weighted choices, for repeatable results
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Include map data attributes for every route segment in the response
+    #[serde(default)]
+    pub output_detail: OutputDetail,
+    /// Token the server was started with, not needed for the requests of a batch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// Identifies local socket clients for rate limiting, remote clients are identified by the
+    /// address of the connection. Not needed for the requests of a batch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// Requests waiting for a worker start in the order of their priority, then in the order
+    /// they came in
+    #[serde(default)]
+    pub priority: Priority,
+    /// Send the responses in chunks, see [`IpcHandler::with_chunked_responses`]. Not needed for
+    /// the requests of a batch
+    #[serde(default)]
+    pub chunked: bool,
+}
+
+/// Amount of data in the routes of a response
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputDetail {
+    /// Route coordinates and stats
+    #[default]
+    Summary,
+    /// Route coordinates and stats, and the map data attributes of every route segment
+    Full,
+}
+
+/// Asks the server to stop generating the route for the request with the given id
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CancelMessage {
+    pub cancel: String,
+    /// Token the server was started with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+/// Asks the server for the status of the request with the given id, with its response once it
+/// is finished
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct StatusMessage {
+    pub status: String,
+    /// Token the server was started with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// Send the response in chunks, see [`IpcHandler::with_chunked_responses`]
+    #[serde(default)]
+    pub chunked: bool,
+}
+
+/// Several route requests sent together, answered with a single response that holds the
+/// response of every request
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BatchMessage {
+    pub id: String,
+    pub batch: Vec<RequestMessage>,
+    /// Generate the requests at the same time, as far as the server workers allow, instead of
+    /// one after the other
+    #[serde(default)]
+    pub parallel: bool,
+    /// Token the server was started with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// Identifies local socket clients for rate limiting, remote clients are identified by the
+    /// address of the connection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// Send the responses in chunks, see [`IpcHandler::with_chunked_responses`]
+    #[serde(default)]
+    pub chunked: bool,
+}
+
+/// Message sent to the server, a route request, a batch of them, a cancellation of one or a
+/// status query
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(untagged)]
+pub enum IpcRequest {
+    Cancel(CancelMessage),
+    Status(StatusMessage),
+    Batch(BatchMessage),
+    Route(Box<RequestMessage>),
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+impl IpcRequest {
+    pub fn id(&self) -> &str {
+        match self {
+            IpcRequest::Cancel(cancel_message) => &cancel_message.cancel,
+            IpcRequest::Status(status_message) => &status_message.status,
+            IpcRequest::Batch(batch_message) => &batch_message.id,
+            IpcRequest::Route(request_message) => &request_message.id,
+        }
+    }
+
+    pub fn token(&self) -> Option<&str> {
+        match self {
+            IpcRequest::Cancel(cancel_message) => cancel_message.token.as_deref(),
+            IpcRequest::Status(status_message) => status_message.token.as_deref(),
+            IpcRequest::Batch(batch_message) => batch_message.token.as_deref(),
+            IpcRequest::Route(request_message) => request_message.token.as_deref(),
+        }
+    }
+
+    pub fn client_id(&self) -> Option<&str> {
+        match self {
+            IpcRequest::Cancel(_) | IpcRequest::Status(_) => None,
+            IpcRequest::Batch(batch_message) => batch_message.client_id.as_deref(),
+            IpcRequest::Route(request_message) => request_message.client_id.as_deref(),
+        }
+    }
+
+    pub fn chunked(&self) -> bool {
+        match self {
+            IpcRequest::Cancel(_) => false,
+            IpcRequest::Status(status_message) => status_message.chunked,
+            IpcRequest::Batch(batch_message) => batch_message.chunked,
+            IpcRequest::Route(request_message) => request_message.chunked,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct RouteMessage {
     pub coords: Vec<(f32, f32)>,
     pub stats: RouteStats,
+    /// Map data attributes for every route segment, only with the full output detail
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub segments: Option<Vec<RouteSegmentDetail>>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub enum RouterResult {
-    Error { message: String },
-    Ok { routes: Vec<RouteMessage> },
+    Error {
+        message: String,
+    },
+    Ok {
+        routes: Vec<RouteMessage>,
+        /// Counts of the route generation, not given for cached or matched routes
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        summary: Option<GenerationSummary>,
+    },
+    Progress {
+        progress: GenerationProgress,
+    },
+    Route {
+        route: Box<RouteMessage>,
+    },
+    /// Sent instead of progress while the request waits for a free worker, 1 is the next in line
+    Queued {
+        position: usize,
+    },
+    /// Responses to the requests of a batch, in the order of the requests
+    Batch {
+        responses: Vec<ResponseMessage>,
+    },
+    /// The server can't take the request right now, because its queue is full or it is shutting
+    /// down, the request can be sent again later
+    Busy {
+        message: String,
+    },
+    /// The client sent more requests than the server allows per minute, it can send the request
+    /// again after the given number of seconds
+    RateLimited {
+        retry_after_s: u64,
+    },
+    /// Status of the request a status message asked for
+    Status {
+        status: RequestStatus,
+    },
+}
+
+/// Where a request is, as far as the server remembers it
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum RequestStatus {
+    /// Waiting for a worker or generating routes
+    InProgress,
+    /// Finished recently enough to still be in the request history of the server
+    Finished {
+        /// Time from receiving the request to responding
+        duration_ms: u64,
+        /// Short description of the result, like the number of routes or the error
+        summary: String,
+        response: Box<ResponseMessage>,
+    },
+    /// Never received, or finished too long ago to be in the request history
+    Unknown,
 }
-#[derive(Serialize, Deserialize, Debug)]
+
+/// Sends an additional message on the connection before the final response
+pub type ResponseSender = Arc<dyn Fn(ResponseMessage) + Send + Sync>;
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct ResponseMessage {
     pub id: String,
     pub result: RouterResult,
 }
 
+/// Writes a JSON schema of the messages accepted by the server, with the response message and
+/// everything it contains in the definitions
+#[cfg(feature = "rule-schema-writer")]
+pub fn generate_message_json_schema(dest: &std::path::PathBuf) -> anyhow::Result<()> {
+    let mut generator = schemars::gen::SchemaGenerator::default();
+    generator.subschema_for::<ResponseMessage>();
+    let mut schema = generator.into_root_schema_for::<IpcRequest>();
+    schemars::visit::Visitor::visit_root_schema(
+        &mut crate::router::rules::SchemaFloatRounding,
+        &mut schema,
+    );
+    let file = std::fs::File::create(dest)?;
+    serde_json::to_writer_pretty(file, &schema)?;
+    Ok(())
+}
+
+/// Where the server listens and the client connects to
+#[cfg(not(target_arch = "wasm32"))]
+enum IpcAddress<'a> {
+    Socket(Name<'a>),
+    Tcp(String),
+}
+
+/// Connection the server can read further requests from while it writes the responses
+#[cfg(not(target_arch = "wasm32"))]
+trait CancelReader: Read + Write + Sized {
+    /// Second handle to the connection to read cancel requests from, `None` where reading and
+    /// writing at the same time is not possible
+    fn cancel_reader(&self) -> Option<io::Result<Self>>;
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl CancelReader for Stream {
+    #[cfg(unix)]
+    fn cancel_reader(&self) -> Option<io::Result<Self>> {
+        Some(self.try_clone())
+    }
+
+    // named pipe reads and writes on the same handle wait for each other
+    #[cfg(not(unix))]
+    fn cancel_reader(&self) -> Option<io::Result<Self>> {
+        None
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl CancelReader for TcpStream {
+    fn cancel_reader(&self) -> Option<io::Result<Self>> {
+        Some(self.try_clone())
+    }
+}
+
+/// Permissions of the socket file the server listens on
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, Copy)]
+pub struct SocketPermissions {
+    pub mode: Option<u32>,
+    pub group: Option<u32>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
 pub struct IpcHandler<'a> {
     socket_print_name: String,
-    socket_name: Name<'a>,
+    address: IpcAddress<'a>,
+    /// Socket file used instead of a namespaced socket when the socket needs permissions
+    socket_file_path: Option<String>,
+    /// Set on the socket file once the server listens on it
+    socket_permissions: Option<SocketPermissions>,
+    /// Sent with every message by the client
+    token: Option<String>,
+    /// Time the client waits for the final response
+    response_timeout: Option<Duration>,
+    /// Times the client sends the request again when the server is not running, busy or does not
+    /// respond in time
+    retries: u32,
+    /// Asks the server to send the responses in chunks
+    chunked_responses: bool,
+}
+
+/// Wait before the first retry, doubled for every further one
+#[cfg(not(target_arch = "wasm32"))]
+const RETRY_BACKOFF_START: Duration = Duration::from_millis(500);
+
+/// Longest wait between retries
+#[cfg(not(target_arch = "wasm32"))]
+const RETRY_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// Sent instead of the message length before a chunked response
+const CHUNKED_MESSAGE: u64 = u64::MAX;
+
+/// Largest chunk of a chunked response
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Largest request the server reads from a connection, checked before the buffer for it is
+/// allocated so a peer can't make the server allocate whatever length it sends
+const MAX_REQUEST_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Largest response the client reads, routes with all their details can get large
+const MAX_RESPONSE_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Fails for message lengths over `max`
+fn check_message_size(size: u64, max: u64) -> Result<usize, IpcHandlerError> {
+    if size > max {
+        return Err(IpcHandlerError::MessageTooLarge { size, max });
+    }
+    Ok(size as usize)
 }
 
+/// Time the server waits for the next bytes of a request on a TCP connection before it closes it
+#[cfg(not(target_arch = "wasm32"))]
+const CONNECTION_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Connections the server handles at the same time, further connections are closed right away
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_CONNECTIONS: usize = 64;
+
+/// Connections the server is handling, from when they are accepted until their threads end
+#[cfg(not(target_arch = "wasm32"))]
+static OPEN_CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts a connection as open for as long as it is alive
+#[cfg(not(target_arch = "wasm32"))]
+struct OpenConnection(&'static AtomicUsize);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl OpenConnection {
+    /// Counts the connection in `open`, `None` when `max` connections are already open
+    fn start(open: &'static AtomicUsize, max: usize) -> Option<Self> {
+        if open.fetch_add(1, Ordering::SeqCst) >= max {
+            open.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        Some(Self(open))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Set once the server received a termination signal, new requests are rejected from then on
+#[cfg(not(target_arch = "wasm32"))]
+pub static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Route and batch requests the server is handling, from when they are read until their final
+/// response is written
+#[cfg(not(target_arch = "wasm32"))]
+pub static ACTIVE_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts a request as active for as long as it is alive
+#[cfg(not(target_arch = "wasm32"))]
+pub struct ActiveRequest;
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ActiveRequest {
+    /// Counts route and batch requests, fails with the response to send when the server is
+    /// shutting down. Cancel and status messages are still handled while shutting down, they
+    /// help the drain finish. The request is counted before the check so a shutdown that starts
+    /// in between still waits for it
+    pub fn start(req: &IpcRequest) -> Result<Option<Self>, Box<ResponseMessage>> {
+        match req {
+            IpcRequest::Cancel(_) | IpcRequest::Status(_) => Ok(None),
+            IpcRequest::Route(_) | IpcRequest::Batch(_) => {
+                ACTIVE_REQUESTS.fetch_add(1, Ordering::SeqCst);
+                let active_request = ActiveRequest;
+                if SHUTTING_DOWN.load(Ordering::SeqCst) {
+                    return Err(Box::new(ResponseMessage {
+                        id: req.id().to_string(),
+                        result: RouterResult::Busy {
+                            message: String::from("Server is shutting down"),
+                        },
+                    }));
+                }
+                Ok(Some(active_request))
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for ActiveRequest {
+    fn drop(&mut self) {
+        ACTIVE_REQUESTS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Messages from the thread waiting for the response when the client has a response timeout
+#[cfg(not(target_arch = "wasm32"))]
+enum ExchangeEvent {
+    Progress(GenerationProgress),
+    Done(Result<ResponseMessage, IpcHandlerError>),
+}
+
+#[cfg(not(target_arch = "wasm32"))]
 impl<'a> IpcHandler<'a> {
-    pub fn init(socket_name: Option<String>) -> Result<Self, IpcHandlerError> {
+    /// Uses a TCP connection to `tcp_addr` when given, otherwise the local socket with the given
+    /// name. The messages are the same for both
+    pub fn init(
+        socket_name: Option<String>,
+        tcp_addr: Option<String>,
+    ) -> Result<Self, IpcHandlerError> {
+        if let Some(tcp_addr) = tcp_addr {
+            return Ok(Self {
+                socket_print_name: tcp_addr.clone(),
+                address: IpcAddress::Tcp(tcp_addr),
+                socket_file_path: None,
+                socket_permissions: None,
+                token: None,
+                response_timeout: None,
+                retries: 0,
+                chunked_responses: false,
+            });
+        }
+
         let socket_name = socket_name.map_or("1".to_string(), |v| {
             v.chars()
                 .map(|c| if c.is_alphanumeric() { c } else { '-' })
                 .collect::<String>()
         });
-        let socket_print_name = if GenericNamespaced::is_supported() {
-            format!("ridi-router-{}.socket", socket_name)
+        let socket_file_path = format!("/tmp/ridi-router-{}.socket", socket_name);
+        // namespaced names are named pipes on Windows and abstract sockets on Linux, other
+        // platforms get a socket file
+        let (socket_print_name, socket_name) = if GenericNamespaced::is_supported() {
+            let name = format!("ridi-router-{}.socket", socket_name);
+            let print_name = if cfg!(windows) {
+                format!(r"\\.\pipe\{name}")
+            } else {
+                name.clone()
+            };
+            let name = name
+                .to_ns_name::<GenericNamespaced>()
+                .map_err(|error| IpcHandlerError::NamespaceName { error })?;
+            (print_name, name)
         } else {
-            format!("/tmp/ridi-router-{}.socket", socket_name)
+            let name = socket_file_path
+                .clone()
+                .to_fs_name::<GenericFilePath>()
+                .map_err(|error| IpcHandlerError::NamespaceName { error })?;
+            (socket_file_path.clone(), name)
         };
 
-        let socket_name = socket_print_name
+        Ok(Self {
+            socket_print_name,
+            address: IpcAddress::Socket(socket_name),
+            socket_file_path: Some(socket_file_path),
+            socket_permissions: None,
+            token: None,
+            response_timeout: None,
+            retries: 0,
+            chunked_responses: false,
+        })
+    }
+
+    /// Token the server was started with, sent with the messages
+    pub fn with_token(self, token: Option<String>) -> Self {
+        Self { token, ..self }
+    }
+
+    /// Time the client waits for the final response and how many times it sends the request
+    /// again, with a growing wait in between, when the server is not running, busy or does not
+    /// respond in time
+    pub fn with_retries(self, response_timeout: Option<Duration>, retries: u32) -> Self {
+        Self {
+            response_timeout,
+            retries,
+            ..self
+        }
+    }
+
+    /// Asks the server to send the responses in chunks. The message length is then followed by
+    /// chunks of at most [`CHUNK_SIZE`] bytes, each with its own length, instead of the whole
+    /// message, so clients with little memory can parse the response as it comes in
+    pub fn with_chunked_responses(self, chunked_responses: bool) -> Self {
+        Self {
+            chunked_responses,
+            ..self
+        }
+    }
+
+    /// Makes the server listen on a socket file with the given permissions, even on platforms
+    /// with namespaced sockets, as those can't be restricted to a user or group. Clients find
+    /// the socket file when there is no namespaced socket with the same name. Not used for TCP
+    pub fn with_socket_permissions(
+        self,
+        socket_permissions: Option<SocketPermissions>,
+    ) -> Result<Self, IpcHandlerError> {
+        let (Some(socket_permissions), Some(socket_file_path)) =
+            (socket_permissions, &self.socket_file_path)
+        else {
+            return Ok(self);
+        };
+        if !cfg!(unix) {
+            return Err(IpcHandlerError::SocketPermissions {
+                error: io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "socket permissions are only supported on Unix",
+                ),
+            });
+        }
+        let name = socket_file_path
             .clone()
-            .to_ns_name::<GenericNamespaced>()
+            .to_fs_name::<GenericFilePath>()
             .map_err(|error| IpcHandlerError::NamespaceName { error })?;
-
         Ok(Self {
-            socket_print_name,
-            socket_name,
+            socket_print_name: socket_file_path.clone(),
+            address: IpcAddress::Socket(name),
+            socket_permissions: Some(socket_permissions),
+            ..self
         })
     }
 
+    /// Socket file the server listens on, only on platforms without namespaced sockets or with
+    /// socket permissions. It is
+    /// left behind when the process exits without dropping the listener
+    pub fn socket_file(&self) -> Option<PathBuf> {
+        match &self.address {
+            IpcAddress::Socket(name) if name.is_path() => {
+                Some(PathBuf::from(&self.socket_print_name))
+            }
+            _ => None,
+        }
+    }
+
     pub fn listen<T>(&self, message_handler: T) -> Result<(), IpcHandlerError>
     where
-        T: Fn(RequestMessage) -> ResponseMessage + Sync + Send + Copy + 'static,
+        T: Fn(IpcRequest, Option<IpAddr>, ResponseSender) -> ResponseMessage
+            + Sync
+            + Send
+            + Copy
+            + 'static,
     {
-        let opts = ListenerOptions::new().name(self.socket_name.clone());
+        match &self.address {
+            IpcAddress::Socket(socket_name) => {
+                let opts = ListenerOptions::new().name(socket_name.clone());
+
+                let listener = match self.create_socket_listener(opts) {
+                    Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+                        return Err(IpcHandlerError::SocketAddressInUse { error: e });
+                    }
+                    x => x.map_err(|error| IpcHandlerError::CreateListener { error })?,
+                };
+                self.set_socket_permissions()
+                    .map_err(|error| IpcHandlerError::SocketPermissions { error })?;
+                self.print_ready();
 
-        let listener = match opts.create_sync() {
-            Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
-                return Err(IpcHandlerError::SocketAddressInUse { error: e });
+                for conn in listener.incoming() {
+                    IpcHandler::spawn_connection(conn, None, message_handler);
+                    if SHUTTING_DOWN.load(Ordering::SeqCst) {
+                        break;
+                    }
+                }
             }
-            x => x.map_err(|error| IpcHandlerError::CreateListener { error })?,
-        };
+            IpcAddress::Tcp(addr) => {
+                let listener = match TcpListener::bind(addr) {
+                    Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+                        return Err(IpcHandlerError::SocketAddressInUse { error: e });
+                    }
+                    x => x.map_err(|error| IpcHandlerError::CreateListener { error })?,
+                };
+                self.print_ready();
 
+                for conn in listener.incoming() {
+                    let peer = conn
+                        .as_ref()
+                        .ok()
+                        .and_then(|conn| conn.peer_addr().ok())
+                        .map(|addr| addr.ip());
+                    // a peer that stops sending can't keep its connection thread waiting
+                    let conn = conn.and_then(|conn| {
+                        conn.set_read_timeout(Some(CONNECTION_READ_TIMEOUT))?;
+                        Ok(conn)
+                    });
+                    IpcHandler::spawn_connection(conn, peer, message_handler);
+                    if SHUTTING_DOWN.load(Ordering::SeqCst) {
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// With socket permissions the socket file is created accessible only to the owner, so no one
+    /// else can connect before its permissions are set. The umask is process wide, so it is
+    /// restored right after the socket file is created
+    fn create_socket_listener(
+        &self,
+        opts: ListenerOptions<'_>,
+    ) -> io::Result<interprocess::local_socket::Listener> {
+        #[cfg(unix)]
+        if self.socket_permissions.is_some() {
+            let previous_umask = unsafe { libc::umask(0o177) };
+            let listener = opts.create_sync();
+            unsafe { libc::umask(previous_umask) };
+            return listener;
+        }
+        opts.create_sync()
+    }
+
+    fn set_socket_permissions(&self) -> io::Result<()> {
+        #[cfg(unix)]
+        if let Some(socket_permissions) = self.socket_permissions {
+            use std::os::unix::fs::PermissionsExt;
+
+            if let Some(mode) = socket_permissions.mode {
+                std::fs::set_permissions(
+                    &self.socket_print_name,
+                    std::fs::Permissions::from_mode(mode),
+                )?;
+            }
+            if socket_permissions.group.is_some() {
+                std::os::unix::fs::chown(&self.socket_print_name, None, socket_permissions.group)?;
+            }
+            info!(
+                mode = socket_permissions.mode.map(|mode| format!("{mode:o}")),
+                group = socket_permissions.group,
+                "Socket permissions set"
+            );
+        }
+        Ok(())
+    }
+
+    fn print_ready(&self) {
         info!(server_name = self.socket_print_name, "Server running");
 
         println!(";RIDI_ROUTER SERVER READY;"); // this is in stdout so calling processes know the server is ready to accept connections
+        crate::daemon::notify_ready();
+    }
 
-        for conn in listener.incoming() {
-            rayon::spawn(move || match conn {
-                Err(e) => {
-                    warn!("Incoming connection failed {}", e);
-                }
-                Ok(conn) => {
-                    trace!("received connection");
-                    let req = match IpcHandler::process_request(&conn) {
-                        Err(err) => {
-                            warn!("error from connection {:?}", err);
-                            return;
+    /// `peer` is the address of the client, for connections that have one
+    fn spawn_connection<S, T>(conn: io::Result<S>, peer: Option<IpAddr>, message_handler: T)
+    where
+        S: CancelReader + Send + 'static,
+        T: Fn(IpcRequest, Option<IpAddr>, ResponseSender) -> ResponseMessage
+            + Sync
+            + Send
+            + Copy
+            + 'static,
+    {
+        let Some(open_connection) = OpenConnection::start(&OPEN_CONNECTIONS, MAX_CONNECTIONS)
+        else {
+            warn!(
+                max = MAX_CONNECTIONS,
+                "Too many open connections, connection closed"
+            );
+            return;
+        };
+        let open_connection = Arc::new(open_connection);
+        // connections wait for a free worker before generating, so they get their own thread
+        // instead of blocking one of the rayon threads that generate the routes
+        std::thread::spawn(move || match conn {
+            Err(e) => {
+                warn!("Incoming connection failed {}", e);
+            }
+            Ok(mut conn) => {
+                trace!("received connection");
+                let req = match IpcHandler::process_request(&mut conn) {
+                    Err(err) => {
+                        warn!("error from connection {:?}", err);
+                        return;
+                    }
+                    Ok(req) => req,
+                };
+                let chunked = req.chunked();
+                // held until the final response is written, so a shutdown waits for it to be sent
+                let _active_request = match ActiveRequest::start(&req) {
+                    Ok(active_request) => active_request,
+                    Err(resp) => {
+                        if let Err(error) = IpcHandler::process_response(&mut conn, &resp, chunked)
+                        {
+                            warn!("error from connection {:?}", error);
                         }
-                        Ok(req) => req,
-                    };
-                    let resp = message_handler(req);
-                    if let Err(error) = IpcHandler::process_response(&conn, &resp) {
+                        return;
+                    }
+                };
+                let finished = Arc::new(AtomicBool::new(false));
+                if matches!(req, IpcRequest::Route(_) | IpcRequest::Batch(_)) {
+                    // the client can cancel the request on the same connection while it is
+                    // generated, the thread ends when the client closes the connection
+                    match conn.cancel_reader() {
+                        Some(Ok(mut reader)) => {
+                            let finished = finished.clone();
+                            let open_connection = open_connection.clone();
+                            std::thread::spawn(move || {
+                                let _open_connection = open_connection;
+                                IpcHandler::read_cancel_requests(
+                                    &mut reader,
+                                    peer,
+                                    message_handler,
+                                    &finished,
+                                )
+                            });
+                        }
+                        Some(Err(error)) => warn!("error from connection {:?}", error),
+                        None => {}
+                    }
+                }
+                let conn = Arc::new(Mutex::new(conn));
+                let progress_conn = conn.clone();
+                let send_progress: ResponseSender = Arc::new(move |resp| {
+                    let mut conn = progress_conn
+                        .lock()
+                        .unwrap_or_else(|error| error.into_inner());
+                    if let Err(error) = IpcHandler::process_response(&mut *conn, &resp, chunked) {
                         warn!("error from connection {:?}", error);
                     }
+                });
+                let resp = message_handler(req, peer, send_progress);
+                let mut conn = conn.lock().unwrap_or_else(|error| error.into_inner());
+                if let Err(error) = IpcHandler::process_response(&mut *conn, &resp, chunked) {
+                    warn!("error from connection {:?}", error);
                 }
-            });
-        }
+                finished.store(true, Ordering::SeqCst);
+                drop(open_connection);
+            }
+        });
+    }
 
-        Ok(())
+    /// Handles the cancel requests sent on the connection of a request in progress. Their own
+    /// responses are not sent, the cancelled request answers with an error instead. Read timeouts
+    /// are waited out until the request is `finished`
+    fn read_cancel_requests<S, T>(
+        reader: &mut S,
+        peer: Option<IpAddr>,
+        message_handler: T,
+        finished: &AtomicBool,
+    ) where
+        S: Read,
+        T: Fn(IpcRequest, Option<IpAddr>, ResponseSender) -> ResponseMessage,
+    {
+        loop {
+            match IpcHandler::process_request(reader) {
+                Ok(req @ IpcRequest::Cancel(_)) => {
+                    let resp = message_handler(req, peer, Arc::new(|_| {}));
+                    if let RouterResult::Error { message } = resp.result {
+                        warn!(req_id = resp.id, message, "Cancel request failed");
+                    }
+                }
+                Ok(req) => {
+                    warn!(
+                        req_id = req.id(),
+                        "Only cancel requests can be sent while a request is in progress"
+                    );
+                }
+                Err(IpcHandlerError::ReadLine { error })
+                    if matches!(
+                        error.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) && !finished.load(Ordering::SeqCst) => {}
+                // the client closed the connection
+                Err(IpcHandlerError::ReadLine { .. }) => return,
+                Err(error) => {
+                    warn!("error from connection {:?}", error);
+                    return;
+                }
+            }
+        }
     }
 
-    fn process_request(conn: &Stream) -> Result<RequestMessage, IpcHandlerError> {
+    fn process_request<S: Read>(conn: &mut S) -> Result<IpcRequest, IpcHandlerError> {
         let start = SystemTime::now();
         let req_timestamp = start
             .duration_since(UNIX_EPOCH)
             .expect("Time went backwards")
             .as_millis();
 
-        let mut conn = BufReader::new(conn);
-
+        // not buffered, the bytes read ahead would be lost for the cancel requests that follow
         let mut mes_len_buf = [0u8; 8];
         conn.read_exact(&mut mes_len_buf)
             .map_err(|error| IpcHandlerError::ReadLine { error })?;
 
         info!(
             req_timestamp = req_timestamp,
-            message_size = u64::from_ne_bytes(mes_len_buf),
+            message_size = u64::from_le_bytes(mes_len_buf),
             "Infomcing message"
         );
 
-        let mut buffer = vec![0; u64::from_ne_bytes(mes_len_buf) as usize];
+        let mut buffer =
+            vec![0; check_message_size(u64::from_le_bytes(mes_len_buf), MAX_REQUEST_SIZE)?];
         conn.read_exact(&mut buffer[..])
             .map_err(|error| IpcHandlerError::ReadLine { error })?;
 
         let string_message =
             std::str::from_utf8(&buffer).map_err(|error| IpcHandlerError::Utf8Message { error })?;
 
-        let request_message: RequestMessage = serde_json::from_str(&string_message)
+        let request_message: IpcRequest = serde_json::from_str(string_message)
             .map_err(|error| IpcHandlerError::DeserializeMessage { error })?;
 
         info!(
             req_timestamp = req_timestamp,
-            req_id = request_message.id,
+            req_id = request_message.id(),
             "Infomcing message received"
         );
 
         Ok(request_message)
     }
-    fn process_response(
-        conn: &Stream,
+    fn process_response<S: Write>(
+        conn: &mut S,
         response_message: &ResponseMessage,
+        chunked: bool,
     ) -> Result<(), IpcHandlerError> {
-        let mut conn = BufReader::new(conn);
-
         let string_message = serde_json::to_string(response_message)
             .map_err(|error| IpcHandlerError::SerializeMessage { error })?;
 
@@ -191,23 +938,26 @@ impl<'a> IpcHandler<'a> {
         info!(
             req_id = response_message.id,
             message_size = buffer.len(),
+            chunked,
             "Outgoing message"
         );
 
-        let mes_len_bytes: u64 = buffer.len() as u64;
-        conn.get_mut()
-            .write_all(&mes_len_bytes.to_ne_bytes()[..])
-            .map_err(|error| IpcHandlerError::WriteAll { error })?;
+        if chunked {
+            IpcHandler::write_chunked(conn, buffer)?;
+        } else {
+            let mes_len_bytes: u64 = buffer.len() as u64;
+            conn.write_all(&mes_len_bytes.to_le_bytes()[..])
+                .map_err(|error| IpcHandlerError::WriteAll { error })?;
 
-        info!(
-            req_id = response_message.id,
-            message_size = buffer.len(),
-            "Outgoing message size sent"
-        );
+            info!(
+                req_id = response_message.id,
+                message_size = buffer.len(),
+                "Outgoing message size sent"
+            );
 
-        conn.get_mut()
-            .write_all(buffer)
-            .map_err(|error| IpcHandlerError::WriteLine { error })?;
+            conn.write_all(buffer)
+                .map_err(|error| IpcHandlerError::WriteLine { error })?;
+        }
 
         info!(
             req_id = response_message.id,
@@ -218,40 +968,320 @@ impl<'a> IpcHandler<'a> {
         Ok(())
     }
 
+    /// Writes [`CHUNKED_MESSAGE`] instead of the message length, then the message in chunks of
+    /// at most [`CHUNK_SIZE`] bytes, each after its length as a little endian u32, then an empty
+    /// chunk and the CRC32 checksum of the whole message as a little endian u32
+    fn write_chunked<S: Write>(conn: &mut S, buffer: &[u8]) -> Result<(), IpcHandlerError> {
+        conn.write_all(&CHUNKED_MESSAGE.to_le_bytes()[..])
+            .map_err(|error| IpcHandlerError::WriteAll { error })?;
+        for chunk in buffer.chunks(CHUNK_SIZE) {
+            conn.write_all(&(chunk.len() as u32).to_le_bytes()[..])
+                .map_err(|error| IpcHandlerError::WriteAll { error })?;
+            conn.write_all(chunk)
+                .map_err(|error| IpcHandlerError::WriteLine { error })?;
+        }
+        conn.write_all(&0u32.to_le_bytes()[..])
+            .map_err(|error| IpcHandlerError::WriteAll { error })?;
+        conn.write_all(&crc32fast::hash(buffer).to_le_bytes()[..])
+            .map_err(|error| IpcHandlerError::WriteAll { error })
+    }
+
+    /// Reads a response message written by [`IpcHandler::process_response`], chunked or not
+    fn read_response<S: Read>(conn: &mut S) -> Result<Vec<u8>, IpcHandlerError> {
+        let mut mes_len_buf = [0u8; 8];
+        conn.read_exact(&mut mes_len_buf)
+            .map_err(|error| IpcHandlerError::ReadLine { error })?;
+        let mes_len = u64::from_le_bytes(mes_len_buf);
+        if mes_len != CHUNKED_MESSAGE {
+            let mut resp_buf = vec![0; check_message_size(mes_len, MAX_RESPONSE_SIZE)?];
+            conn.read_exact(&mut resp_buf[..])
+                .map_err(|error| IpcHandlerError::ReadLine { error })?;
+            return Ok(resp_buf);
+        }
+
+        let mut resp_buf = Vec::new();
+        let mut hasher = crc32fast::Hasher::new();
+        let mut len_buf = [0u8; 4];
+        loop {
+            conn.read_exact(&mut len_buf)
+                .map_err(|error| IpcHandlerError::ReadLine { error })?;
+            let chunk_len = u32::from_le_bytes(len_buf) as usize;
+            if chunk_len == 0 {
+                break;
+            }
+            let start = resp_buf.len();
+            check_message_size((start + chunk_len) as u64, MAX_RESPONSE_SIZE)?;
+            resp_buf.resize(start + chunk_len, 0);
+            conn.read_exact(&mut resp_buf[start..])
+                .map_err(|error| IpcHandlerError::ReadLine { error })?;
+            hasher.update(&resp_buf[start..]);
+        }
+        conn.read_exact(&mut len_buf)
+            .map_err(|error| IpcHandlerError::ReadLine { error })?;
+        let expected = u32::from_le_bytes(len_buf);
+        let actual = hasher.finalize();
+        if expected != actual {
+            return Err(IpcHandlerError::ChunkChecksum { expected, actual });
+        }
+        Ok(resp_buf)
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn connect(
         &self,
         routing_mode: &RoutingMode,
-        rules: RouterRules,
+        rules: Option<RouterRules>,
+        rule_overrides: Option<serde_json::Value>,
+        departure_time: Option<OffsetDateTime>,
         route_req_id: Option<String>,
+        timeout_s: Option<u64>,
+        seed: Option<u64>,
+        output_detail: OutputDetail,
+        priority: Priority,
+        on_progress: Option<&dyn Fn(GenerationProgress)>,
     ) -> Result<ResponseMessage, IpcHandlerError> {
-        let conn = Stream::connect(self.socket_name.clone())
-            .map_err(|error| IpcHandlerError::Connect { error })?;
-
-        let mut conn = BufReader::new(conn);
-
         let req_msg = RequestMessage {
             id: route_req_id.map_or(String::from("default-request-id"), |v| v.to_string()),
             routing_mode: routing_mode.clone(),
-            rules,
+            rules: rules
+                .map(serde_json::to_value)
+                .transpose()
+                .map_err(|error| IpcHandlerError::SerializeMessage { error })?,
+            rule_overrides,
+            departure_time,
+            timeout_s,
+            progress: on_progress.is_some(),
+            stream_routes: false,
+            seed,
+            output_detail,
+            token: self.token.clone(),
+            client_id: None,
+            priority,
+            chunked: self.chunked_responses,
+        };
+        self.send(&req_msg.id, &req_msg, on_progress, &[&req_msg.id])
+    }
+
+    pub fn cancel(&self, route_req_id: String) -> Result<ResponseMessage, IpcHandlerError> {
+        let cancel_msg = CancelMessage {
+            cancel: route_req_id,
+            token: self.token.clone(),
         };
-        let string_req = serde_json::to_string(&req_msg)
+        self.send(&cancel_msg.cancel, &cancel_msg, None, &[])
+    }
+
+    pub fn status(&self, route_req_id: String) -> Result<ResponseMessage, IpcHandlerError> {
+        let status_msg = StatusMessage {
+            status: route_req_id,
+            token: self.token.clone(),
+            chunked: self.chunked_responses,
+        };
+        self.send(&status_msg.status, &status_msg, None, &[])
+    }
+
+    pub fn batch(
+        &self,
+        mut batch_message: BatchMessage,
+        on_progress: Option<&dyn Fn(GenerationProgress)>,
+    ) -> Result<ResponseMessage, IpcHandlerError> {
+        batch_message.token = batch_message.token.or_else(|| self.token.clone());
+        batch_message.chunked |= self.chunked_responses;
+        let cancel_ids = batch_message
+            .batch
+            .iter()
+            .map(|request_message| request_message.id.as_str())
+            .collect::<Vec<_>>();
+        self.send(&batch_message.id, &batch_message, on_progress, &cancel_ids)
+    }
+
+    /// `cancel_ids` are the requests the server may still be generating when the response times
+    /// out, they are cancelled before the request is sent again so it doesn't run twice
+    fn send<T: Serialize>(
+        &self,
+        req_id: &str,
+        req_msg: &T,
+        on_progress: Option<&dyn Fn(GenerationProgress)>,
+        cancel_ids: &[&str],
+    ) -> Result<ResponseMessage, IpcHandlerError> {
+        let string_req = serde_json::to_string(req_msg)
             .map_err(|error| IpcHandlerError::SerializeMessage { error })?;
 
+        let mut backoff = RETRY_BACKOFF_START;
+        let mut attempt = 0;
+        loop {
+            let result = self.send_once(req_id, &string_req, on_progress);
+            let retry_after = match &result {
+                Err(
+                    IpcHandlerError::ServerNotRunning { .. }
+                    | IpcHandlerError::ResponseTimeout { .. },
+                )
+                | Ok(ResponseMessage {
+                    result: RouterResult::Busy { .. },
+                    ..
+                }) => Some(backoff),
+                Ok(ResponseMessage {
+                    result: RouterResult::RateLimited { retry_after_s },
+                    ..
+                }) => Some(backoff.max(Duration::from_secs(*retry_after_s))),
+                _ => None,
+            };
+
+            match retry_after {
+                Some(retry_after) if attempt < self.retries => {
+                    attempt += 1;
+                    if matches!(result, Err(IpcHandlerError::ResponseTimeout { .. })) {
+                        self.cancel_timed_out(cancel_ids);
+                    }
+                    warn!(
+                        req_id = req_id,
+                        attempt,
+                        retry_after_ms = retry_after.as_millis() as u64,
+                        "Request failed, retrying"
+                    );
+                    std::thread::sleep(retry_after);
+                    backoff = (backoff * 2).min(RETRY_BACKOFF_MAX);
+                }
+                _ => {
+                    return result.and_then(|resp_msg| match resp_msg.result {
+                        RouterResult::Busy { message } => {
+                            Err(IpcHandlerError::ServerBusy { message })
+                        }
+                        RouterResult::RateLimited { retry_after_s } => {
+                            Err(IpcHandlerError::ServerBusy {
+                                message: format!(
+                                    "Rate limited, retry after {retry_after_s} seconds"
+                                ),
+                            })
+                        }
+                        _ => Ok(resp_msg),
+                    })
+                }
+            }
+        }
+    }
+
+    fn cancel_timed_out(&self, cancel_ids: &[&str]) {
+        for cancel_id in cancel_ids {
+            let cancel_msg = CancelMessage {
+                cancel: cancel_id.to_string(),
+                token: self.token.clone(),
+            };
+            let result = serde_json::to_string(&cancel_msg)
+                .map_err(|error| IpcHandlerError::SerializeMessage { error })
+                .and_then(|string_req| self.send_once(cancel_id, &string_req, None));
+            if let Err(error) = result {
+                warn!(req_id = cancel_id, error = ?error, "Failed to cancel timed out request");
+            }
+        }
+    }
+
+    fn send_once(
+        &self,
+        req_id: &str,
+        string_req: &str,
+        on_progress: Option<&dyn Fn(GenerationProgress)>,
+    ) -> Result<ResponseMessage, IpcHandlerError> {
+        let connect_error = |error: io::Error| match error.kind() {
+            io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused => {
+                IpcHandlerError::ServerNotRunning { error }
+            }
+            _ => IpcHandlerError::Connect { error },
+        };
+        match &self.address {
+            IpcAddress::Socket(socket_name) => {
+                let conn = match Stream::connect(socket_name.clone()) {
+                    // a server with socket permissions listens on the socket file instead
+                    Err(error) if !socket_name.is_path() => match self.existing_socket_file()? {
+                        Some(name) => Stream::connect(name),
+                        None => Err(error),
+                    },
+                    conn => conn,
+                }
+                .map_err(connect_error)?;
+                self.exchange_with_timeout(conn, req_id, string_req, on_progress)
+            }
+            IpcAddress::Tcp(addr) => {
+                let conn = TcpStream::connect(addr).map_err(connect_error)?;
+                self.exchange_with_timeout(conn, req_id, string_req, on_progress)
+            }
+        }
+    }
+
+    fn existing_socket_file(&self) -> Result<Option<Name<'static>>, IpcHandlerError> {
+        self.socket_file_path
+            .clone()
+            .filter(|path| std::path::Path::new(path).exists())
+            .map(|path| {
+                path.to_fs_name::<GenericFilePath>()
+                    .map_err(|error| IpcHandlerError::NamespaceName { error })
+            })
+            .transpose()
+    }
+
+    /// Waits for the response on another thread when there is a response timeout, so the client
+    /// can give up on it whatever kind of connection it is
+    fn exchange_with_timeout<S: Read + Write + Send + 'static>(
+        &self,
+        conn: S,
+        req_id: &str,
+        string_req: &str,
+        on_progress: Option<&dyn Fn(GenerationProgress)>,
+    ) -> Result<ResponseMessage, IpcHandlerError> {
+        let Some(timeout) = self.response_timeout else {
+            return IpcHandler::exchange(conn, req_id, string_req, on_progress);
+        };
+
+        let deadline = Instant::now() + timeout;
+        let (sender, receiver) = mpsc::channel();
+        let thread_req_id = req_id.to_string();
+        let thread_string_req = string_req.to_string();
+        std::thread::spawn(move || {
+            let progress_sender = sender.clone();
+            let on_progress = move |progress| {
+                let _ = progress_sender.send(ExchangeEvent::Progress(progress));
+            };
+            let result =
+                IpcHandler::exchange(conn, &thread_req_id, &thread_string_req, Some(&on_progress));
+            let _ = sender.send(ExchangeEvent::Done(result));
+        });
+
+        loop {
+            match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(ExchangeEvent::Progress(progress)) => {
+                    if let Some(on_progress) = on_progress {
+                        on_progress(progress);
+                    }
+                }
+                Ok(ExchangeEvent::Done(result)) => return result,
+                Err(_) => return Err(IpcHandlerError::ResponseTimeout { timeout }),
+            }
+        }
+    }
+
+    /// Sends the request and reads responses until the final one, passing progress on
+    fn exchange<S: Read + Write>(
+        conn: S,
+        req_id: &str,
+        string_req: &str,
+        on_progress: Option<&dyn Fn(GenerationProgress)>,
+    ) -> Result<ResponseMessage, IpcHandlerError> {
+        let mut conn = BufReader::new(conn);
+
         let req_buf = string_req.as_bytes();
 
         info!(
-            req_id = req_msg.id,
+            req_id = req_id,
             message_size = req_buf.len(),
             "Request message"
         );
 
         let mes_len_bytes: u64 = req_buf.len() as u64;
         conn.get_mut()
-            .write_all(&mes_len_bytes.to_ne_bytes()[..])
+            .write_all(&mes_len_bytes.to_le_bytes()[..])
             .map_err(|error| IpcHandlerError::WriteAll { error })?;
 
         info!(
-            req_id = req_msg.id,
+            req_id = req_id,
             message_size = req_buf.len(),
             "Request message size sent"
         );
@@ -261,37 +1291,323 @@ impl<'a> IpcHandler<'a> {
             .map_err(|error| IpcHandlerError::WriteAll { error })?;
 
         info!(
-            req_id = req_msg.id,
+            req_id = req_id,
             message_size = req_buf.len(),
             "Request message sent"
         );
 
-        let mut mes_len_buf = [0u8; 8];
-        conn.read_exact(&mut mes_len_buf)
-            .map_err(|error| IpcHandlerError::ReadLine { error })?;
+        loop {
+            let resp_buf = IpcHandler::read_response(&mut conn)?;
 
-        info!(
-            req_id = req_msg.id,
-            message_size = u64::from_ne_bytes(mes_len_buf),
-            "Response message incoming"
+            info!(
+                req_id = req_id,
+                message_size = resp_buf.len(),
+                "Response message received"
+            );
+
+            let string_resp = std::str::from_utf8(&resp_buf)
+                .map_err(|error| IpcHandlerError::Utf8Message { error })?;
+
+            let resp_msg: ResponseMessage = serde_json::from_str(string_resp)
+                .map_err(|error| IpcHandlerError::DeserializeMessage { error })?;
+
+            match resp_msg.result {
+                RouterResult::Progress { progress } => {
+                    if let Some(on_progress) = on_progress {
+                        on_progress(progress);
+                    }
+                }
+                // streamed routes are also in the final response
+                RouterResult::Route { .. } => {}
+                RouterResult::Queued { position } => {
+                    info!(req_id = req_id, position, "Request queued");
+                }
+                _ => return Ok(resp_msg),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{Cursor, Write},
+        net::{TcpListener, TcpStream},
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            mpsc, Arc,
+        },
+        time::{Duration, Instant},
+    };
+
+    #[cfg(unix)]
+    use super::{IpcAddress, ListenerOptions, SocketPermissions};
+    use super::{
+        IpcHandler, IpcHandlerError, IpcRequest, OpenConnection, RequestMessage, ResponseMessage,
+        RouterResult, CHUNK_SIZE, MAX_REQUEST_SIZE, MAX_RESPONSE_SIZE,
+    };
+
+    #[test]
+    fn ipc_request_kinds() {
+        let rules = std::fs::read_to_string("rule-examples/rules-default.json").unwrap();
+        let route = format!(
+            r#"{{"id": "a", "routing_mode": {{"StartFinish": {{"start": {{"lat": 57.0, "lon": 24.0}}, "finish": {{"lat": 57.1, "lon": 24.1}}}}}}, "rules": {rules}}}"#
         );
 
-        let mut resp_buf = vec![0; u64::from_ne_bytes(mes_len_buf) as usize];
-        conn.read_exact(&mut resp_buf[..])
-            .map_err(|error| IpcHandlerError::ReadLine { error })?;
+        let request: IpcRequest = serde_json::from_str(&route).unwrap();
+        assert!(matches!(request, IpcRequest::Route(_)));
+        assert_eq!(request.id(), "a");
+        assert_eq!(request.token(), None);
 
-        info!(
-            req_id = req_msg.id,
-            message_size = u64::from_ne_bytes(mes_len_buf),
-            "Response message received"
+        let request: IpcRequest =
+            serde_json::from_str(&format!(r#"{{"id": "b", "batch": [{route}, {route}]}}"#))
+                .unwrap();
+        let IpcRequest::Batch(batch) = &request else {
+            panic!("expected a batch request");
+        };
+        assert_eq!(batch.batch.len(), 2);
+        assert!(!batch.parallel);
+        assert_eq!(request.id(), "b");
+
+        let request: IpcRequest =
+            serde_json::from_str(r#"{"cancel": "a", "token": "secret"}"#).unwrap();
+        assert!(matches!(request, IpcRequest::Cancel(_)));
+        assert_eq!(request.token(), Some("secret"));
+
+        let request: IpcRequest = serde_json::from_str(r#"{"status": "a"}"#).unwrap();
+        assert!(matches!(request, IpcRequest::Status(_)));
+        assert_eq!(request.id(), "a");
+    }
+
+    #[test]
+    fn chunked_response_round_trip() {
+        let response = ResponseMessage {
+            id: "a".repeat(CHUNK_SIZE * 2),
+            result: RouterResult::Ok {
+                routes: Vec::new(),
+                summary: None,
+            },
+        };
+        let message = serde_json::to_vec(&response).unwrap();
+
+        let mut buffer = Vec::new();
+        IpcHandler::process_response(&mut buffer, &response, true).unwrap();
+        assert_eq!(
+            IpcHandler::read_response(&mut Cursor::new(&buffer)).unwrap(),
+            message
         );
 
-        let string_resp = std::str::from_utf8(&resp_buf)
-            .map_err(|error| IpcHandlerError::Utf8Message { error })?;
+        let mut plain = Vec::new();
+        IpcHandler::process_response(&mut plain, &response, false).unwrap();
+        assert_eq!(
+            IpcHandler::read_response(&mut Cursor::new(&plain)).unwrap(),
+            message
+        );
 
-        let resp_msg: ResponseMessage = serde_json::from_str(string_resp)
-            .map_err(|error| IpcHandlerError::DeserializeMessage { error })?;
+        // corrupt a byte of the first chunk
+        buffer[20] ^= 1;
+        assert!(matches!(
+            IpcHandler::read_response(&mut Cursor::new(&buffer)),
+            Err(IpcHandlerError::ChunkChecksum { .. })
+        ));
+    }
+
+    #[test]
+    fn oversized_message_rejected() {
+        let mut message = (MAX_REQUEST_SIZE + 1).to_le_bytes().to_vec();
+        message.extend_from_slice(b"{}");
+        assert!(matches!(
+            IpcHandler::process_request(&mut Cursor::new(&message)),
+            Err(IpcHandlerError::MessageTooLarge { .. })
+        ));
+        // responses can be larger than requests
+        assert!(matches!(
+            IpcHandler::read_response(&mut Cursor::new(&message)),
+            Err(IpcHandlerError::ReadLine { .. })
+        ));
+
+        let mut message = (MAX_RESPONSE_SIZE + 1).to_le_bytes().to_vec();
+        message.extend_from_slice(b"{}");
+        assert!(matches!(
+            IpcHandler::read_response(&mut Cursor::new(&message)),
+            Err(IpcHandlerError::MessageTooLarge { .. })
+        ));
+        // a length that does not fit in memory on any machine
+        let message = (u64::MAX - 1).to_le_bytes();
+        assert!(matches!(
+            IpcHandler::process_request(&mut Cursor::new(&message)),
+            Err(IpcHandlerError::MessageTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn open_connections_capped() {
+        static OPEN: AtomicUsize = AtomicUsize::new(0);
+        let first = OpenConnection::start(&OPEN, 2).unwrap();
+        let second = OpenConnection::start(&OPEN, 2).unwrap();
+        assert!(OpenConnection::start(&OPEN, 2).is_none());
+        assert_eq!(OPEN.load(Ordering::SeqCst), 2);
+        drop(first);
+        assert!(OpenConnection::start(&OPEN, 2).is_some());
+        drop(second);
+        assert_eq!(OPEN.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn cancel_reader_waits_out_read_timeouts_until_finished() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut conn, _) = listener.accept().unwrap();
+        conn.set_read_timeout(Some(Duration::from_millis(20)))
+            .unwrap();
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let start = Instant::now();
+        let finish_later = finished.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            finish_later.store(true, Ordering::SeqCst);
+        });
+        IpcHandler::read_cancel_requests(
+            &mut conn,
+            None,
+            |request, _, _| ResponseMessage {
+                id: request.id().to_string(),
+                result: RouterResult::Error {
+                    message: String::from("unexpected request"),
+                },
+            },
+            &finished,
+        );
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn timed_out_request_cancelled_before_retry() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            // route requests are never answered, the connections are kept open
+            let mut route_conns = Vec::new();
+            for conn in listener.incoming() {
+                let mut conn = conn.unwrap();
+                let request = IpcHandler::process_request(&mut conn).unwrap();
+                let is_route = matches!(request, IpcRequest::Route(_));
+                let _ = sender.send((is_route, request.id().to_string()));
+                if is_route {
+                    route_conns.push(conn);
+                } else {
+                    let resp = ResponseMessage {
+                        id: request.id().to_string(),
+                        result: RouterResult::Error {
+                            message: String::from("cancelled"),
+                        },
+                    };
+                    IpcHandler::process_response(&mut conn, &resp, false).unwrap();
+                }
+            }
+        });
+
+        let request: RequestMessage = serde_json::from_str(
+            r#"{"id": "a", "routing_mode": {"StartFinish": {"start": {"lat": 57.0, "lon": 24.0}, "finish": {"lat": 57.1, "lon": 24.1}}}}"#,
+        )
+        .unwrap();
+        let ipc = IpcHandler::init(None, Some(addr))
+            .unwrap()
+            .with_retries(Some(Duration::from_millis(200)), 1);
+        assert!(matches!(
+            ipc.send("a", &request, None, &["a"]),
+            Err(IpcHandlerError::ResponseTimeout { .. })
+        ));
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            vec![
+                (true, "a".to_string()),
+                (false, "a".to_string()),
+                (true, "a".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn cancel_on_same_connection() {
+        static CANCELLED: AtomicBool = AtomicBool::new(false);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for conn in listener.incoming() {
+                IpcHandler::spawn_connection(conn, None, |request, _, _| {
+                    let result = match request {
+                        IpcRequest::Cancel(_) => {
+                            CANCELLED.store(true, Ordering::SeqCst);
+                            RouterResult::Ok {
+                                routes: Vec::new(),
+                                summary: None,
+                            }
+                        }
+                        _ => {
+                            while !CANCELLED.load(Ordering::SeqCst) {
+                                std::thread::sleep(Duration::from_millis(10));
+                            }
+                            RouterResult::Error {
+                                message: String::from("cancelled"),
+                            }
+                        }
+                    };
+                    ResponseMessage {
+                        id: request.id().to_string(),
+                        result,
+                    }
+                });
+            }
+        });
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+        for message in [
+            r#"{"id": "a", "routing_mode": {"StartFinish": {"start": {"lat": 57.0, "lon": 24.0}, "finish": {"lat": 57.1, "lon": 24.1}}}}"#,
+            r#"{"cancel": "a"}"#,
+        ] {
+            conn.write_all(&(message.len() as u64).to_le_bytes())
+                .unwrap();
+            conn.write_all(message.as_bytes()).unwrap();
+        }
+        let response: ResponseMessage =
+            serde_json::from_slice(&IpcHandler::read_response(&mut conn).unwrap()).unwrap();
+        assert_eq!(response.id, "a");
+        assert!(
+            matches!(response.result, RouterResult::Error { message } if message == "cancelled")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn socket_file_created_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let handler = IpcHandler::init(Some(format!("umask-test-{}", std::process::id())), None)
+            .unwrap()
+            .with_socket_permissions(Some(SocketPermissions {
+                mode: None,
+                group: None,
+            }))
+            .unwrap();
+        let socket_file = handler.socket_file().unwrap();
+        let _ = std::fs::remove_file(&socket_file);
+        let IpcAddress::Socket(name) = &handler.address else {
+            panic!("expected a socket address");
+        };
+
+        let listener = handler
+            .create_socket_listener(ListenerOptions::new().name(name.clone()))
+            .unwrap();
+        let mode = std::fs::metadata(&socket_file)
+            .unwrap()
+            .permissions()
+            .mode();
+        drop(listener);
+        let _ = std::fs::remove_file(&socket_file);
 
-        Ok(resp_msg)
+        assert_eq!(mode & 0o077, 0);
     }
 }
@@ -0,0 +1,155 @@
+use std::{
+    io::{BufWriter, Error, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    gpx_writer::get_route_description, ipc_handler::RouteMessage, result_writer::AtomicFile,
+    units::Units,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum KmlWriterError {
+    #[error("File Creation Error {error}")]
+    FileCreateError { error: Error },
+
+    #[error("Kml Write Error {error}")]
+    KmlWrite { error: Error },
+}
+
+/// Line colors for the routes in KML `aabbggrr` format, reused when there are more routes
+const ROUTE_COLORS: [&str; 8] = [
+    "ff0000ff", "ffff0000", "ff00aa00", "ff00a5ff", "ffff00ff", "ffffff00", "ff800080", "ff2a2aa5",
+];
+
+const ROUTE_LINE_WIDTH: u32 = 4;
+
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+pub struct KmlWriter {
+    routes: Vec<RouteMessage>,
+    file_name: PathBuf,
+    units: Units,
+}
+
+impl KmlWriter {
+    pub fn new(routes: Vec<RouteMessage>, file_name: PathBuf, units: Units) -> Self {
+        Self {
+            routes,
+            file_name,
+            units,
+        }
+    }
+
+    fn write_document<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(writer, r#"<kml xmlns="http://www.opengis.net/kml/2.2">"#)?;
+        writeln!(writer, "<Document>")?;
+        writeln!(writer, "<name>ridi-router routes</name>")?;
+        for (idx, color) in ROUTE_COLORS.iter().enumerate() {
+            writeln!(
+                writer,
+                "<Style id=\"route_{idx}\"><LineStyle><color>{color}</color><width>{ROUTE_LINE_WIDTH}</width></LineStyle></Style>"
+            )?;
+        }
+        for (idx, route) in self.routes.iter().enumerate() {
+            writeln!(writer, "<Placemark>")?;
+            writeln!(
+                writer,
+                "<name>r_{idx}_c_{}</name>",
+                route.stats.cluster.map_or(-1, |c| c as isize)
+            )?;
+            writeln!(
+                writer,
+                "<description>{}</description>",
+                escape_xml(&get_route_description(route, self.units))
+            )?;
+            writeln!(
+                writer,
+                "<styleUrl>#route_{}</styleUrl>",
+                idx % ROUTE_COLORS.len()
+            )?;
+            writeln!(writer, "<LineString>")?;
+            writeln!(writer, "<tessellate>1</tessellate>")?;
+            write!(writer, "<coordinates>")?;
+            for (lat, lon) in &route.coords {
+                write!(writer, "{lon},{lat} ")?;
+            }
+            writeln!(writer, "</coordinates>")?;
+            writeln!(writer, "</LineString>")?;
+            writeln!(writer, "</Placemark>")?;
+        }
+        writeln!(writer, "</Document>")?;
+        writeln!(writer, "</kml>")?;
+        writer.flush()
+    }
+
+    pub fn write_kml(self) -> Result<(), KmlWriterError> {
+        let file = AtomicFile::create(&self.file_name)
+            .map_err(|error| KmlWriterError::FileCreateError { error })?;
+
+        let mut writer = BufWriter::new(file);
+        self.write_document(&mut writer)
+            .and_then(|_| writer.into_inner().map_err(|error| error.into_error()))
+            .and_then(AtomicFile::commit)
+            .map_err(|error| KmlWriterError::KmlWrite { error })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ipc_handler::RouteMessage, router::route::RouteStats, units::Units};
+
+    use super::{escape_xml, KmlWriter, ROUTE_COLORS};
+
+    #[test]
+    fn escape_xml_test() {
+        assert_eq!(
+            escape_xml(r#"<a href="x">'b' & c</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&apos;b&apos; &amp; c&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn kml_document() {
+        let stats: RouteStats = serde_json::from_str(
+            r#"{"len_m": 1000, "junction_count": 3, "highway": {"<script>": {"len_m": 1000, "percentage": 100}}, "surface": {}, "smoothness": {}, "score": 4.5, "cluster": 1, "approximated_route": []}"#,
+        )
+        .unwrap();
+        let route = RouteMessage {
+            coords: vec![(57.0, 24.0), (57.01, 24.0), (57.01, 24.02)],
+            stats,
+            segments: None,
+        };
+
+        let mut output = Vec::new();
+        KmlWriter::new(
+            vec![route; ROUTE_COLORS.len() + 1],
+            "routes.kml".into(),
+            Units::Metric,
+        )
+        .write_document(&mut output)
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with(r#"<?xml version="1.0" encoding="UTF-8"?>"#));
+        assert!(output.trim_end().ends_with("</kml>"));
+        assert_eq!(
+            output.matches("<Placemark>").count(),
+            ROUTE_COLORS.len() + 1
+        );
+        assert_eq!(output.matches("<Style id=").count(), ROUTE_COLORS.len());
+        assert!(output.contains("<name>r_0_c_1</name>"));
+        // the colors are reused when there are more routes than colors
+        assert_eq!(output.matches("<styleUrl>#route_0</styleUrl>").count(), 2);
+        // coordinates are written as lon,lat
+        assert!(output.contains("<coordinates>24,57 24,57.01 24.02,57.01 </coordinates>"));
+        assert!(output.contains("&lt;script&gt;"));
+        assert!(!output.contains("<script>"));
+    }
+}
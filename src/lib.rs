@@ -0,0 +1,54 @@
+#[cfg(not(target_arch = "wasm32"))]
+mod auth;
+mod bench;
+mod cli_config;
+pub mod cli_error;
+#[cfg(not(target_arch = "wasm32"))]
+mod daemon;
+mod debug;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod fit_writer;
+mod fuzz;
+#[cfg(feature = "geocoding")]
+mod geocoder;
+mod gpx_writer;
+mod html_writer;
+mod image_writer;
+mod ipc_handler;
+mod kml_writer;
+mod map_data;
+mod map_data_cache;
+mod matrix;
+mod osm_data;
+mod profiler;
+#[cfg(not(target_arch = "wasm32"))]
+mod rate_limiter;
+#[cfg(not(target_arch = "wasm32"))]
+mod repl;
+#[cfg(not(target_arch = "wasm32"))]
+mod request_history;
+mod rerouter;
+#[cfg(not(target_arch = "wasm32"))]
+mod response_cache;
+mod result_writer;
+mod router;
+pub mod router_runner;
+mod stats_writer;
+mod telemetry;
+#[cfg(test)]
+mod test_utils;
+mod timings;
+mod track_matcher;
+mod turns;
+mod units;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+mod waypoints_reader;
+#[cfg(not(target_arch = "wasm32"))]
+mod websocket_handler;
+mod worker_pool;
+
+/// Distance math of the graph, exported for the benchmarks
+#[doc(hidden)]
+pub use map_data::proximity::{closest_index, distance_m};
@@ -1,5 +1,5 @@
 use std::{
-    cmp::{Eq, Ordering},
+    cmp::Eq,
     collections::{HashMap, HashSet},
     fmt::{Debug, Display},
     hash::Hash,
@@ -9,7 +9,6 @@ use std::{
 };
 
 use anyhow::Context;
-use geo::{Distance, Haversine, Point};
 use serde::{Deserialize, Serialize};
 use tracing::trace;
 
@@ -27,14 +26,17 @@ use crate::{
         data_reader::{OsmDataReader, ALLOWED_ACCESS_VALUES, ALLOWED_HIGHWAY_VALUES},
         DataSource,
     },
+    profiler,
     router::rules::{RouterRules, RulesTagValueAction},
+    timings,
 };
 
 use super::{
+    info::{lengths_by_value, GraphInfo, GraphInfoBbox},
     line::{LineDirection, MapDataLine},
     osm::{OsmNode, OsmRelation, OsmWay},
-    point::MapDataPoint,
-    proximity::PointGrid,
+    point::{MapDataPoint, PointElements},
+    proximity::{closest_index, PointGrid},
     rule::MapDataRuleType,
     MapDataError,
 };
@@ -69,6 +71,73 @@ impl ElementTagValueRef {
         };
         Some(&MapDataGraph::get().tags.tag_values[idx as usize])
     }
+    /// Position of the value among the tag values, which are stored once each
+    fn id(&self) -> Option<u32> {
+        self.tag_value_pos.checked_sub(1)
+    }
+}
+
+/// Ids of the ref and the name of a road. Tag values are stored once each, so equal ids are equal
+/// values, and comparing them doesn't look up the strings
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RoadIds {
+    pub hw_ref: Option<u32>,
+    pub name: Option<u32>,
+}
+
+impl RoadIds {
+    pub fn is_none(&self) -> bool {
+        self.hw_ref.is_none() && self.name.is_none()
+    }
+    /// Both have the same ref or the same name
+    pub fn is_same_road(&self, other: &RoadIds) -> bool {
+        (self.hw_ref.is_some() && self.hw_ref == other.hw_ref)
+            || (self.name.is_some() && self.name == other.name)
+    }
+}
+
+/// Way tags stored by default in addition to the ones used for navigation, so that tag matcher
+/// rules can be evaluated against them
+pub const DEFAULT_EXTRA_TAG_KEYS: [&str; 12] = [
+    "access",
+    "bridge",
+    "embankment",
+    "ford",
+    "lanes",
+    "lit",
+    "maxspeed",
+    "motorcycle",
+    "scenic",
+    "toll",
+    "tracktype",
+    "tunnel",
+];
+
+static EXTRA_TAG_KEYS: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Sets the stored way tags to `DEFAULT_EXTRA_TAG_KEYS` plus `keys`, must be called before the
+/// map data is loaded
+pub fn set_extra_tag_keys(keys: &[String]) {
+    let mut all_keys = DEFAULT_EXTRA_TAG_KEYS
+        .iter()
+        .map(|key| key.to_string())
+        .chain(keys.iter().cloned())
+        .collect::<Vec<_>>();
+    all_keys.sort();
+    all_keys.dedup();
+    if EXTRA_TAG_KEYS.set(all_keys).is_err() {
+        tracing::warn!("extra tag keys already set");
+    }
+}
+
+/// Way tags stored in addition to the ones used for navigation
+pub fn extra_tag_keys() -> &'static [String] {
+    EXTRA_TAG_KEYS.get_or_init(|| {
+        DEFAULT_EXTRA_TAG_KEYS
+            .iter()
+            .map(|key| key.to_string())
+            .collect()
+    })
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -92,6 +161,7 @@ pub struct ElementTagSet {
     highway: ElementTagValueRef,
     surface: ElementTagValueRef,
     smoothness: ElementTagValueRef,
+    extra: Vec<(ElementTagValueRef, ElementTagValueRef)>,
 }
 
 impl ElementTagSet {
@@ -110,6 +180,27 @@ impl ElementTagSet {
     pub fn smoothness(&self) -> Option<&smartstring::alias::String> {
         self.smoothness.borrow()
     }
+    pub fn road_ids(&self) -> RoadIds {
+        RoadIds {
+            hw_ref: self.hw_ref.id(),
+            name: self.name.id(),
+        }
+    }
+    /// Value of any stored tag, either one of the navigation tags or one of `extra_tag_keys`
+    pub fn get(&self, key: &str) -> Option<&smartstring::alias::String> {
+        match key {
+            "name" => self.name(),
+            "ref" => self.hw_ref(),
+            "highway" => self.highway(),
+            "surface" => self.surface(),
+            "smoothness" => self.smoothness(),
+            _ => self
+                .extra
+                .iter()
+                .find(|(extra_key, _)| extra_key.borrow().is_some_and(|k| k == key))
+                .and_then(|(_, extra_value)| extra_value.borrow()),
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
@@ -138,12 +229,22 @@ impl ElementTags {
         highway: Option<&String>,
         surface: Option<&String>,
         smoothness: Option<&String>,
+        extra: Vec<(&String, &String)>,
     ) -> ElementTagSetRef {
         let name_ref = self.get_tag_value_ref(name);
         let hw_ref_ref = self.get_tag_value_ref(hw_ref);
         let highway_ref = self.get_tag_value_ref(highway);
         let surface_ref = self.get_tag_value_ref(surface);
         let smoothness_ref = self.get_tag_value_ref(smoothness);
+        let extra_refs = extra
+            .into_iter()
+            .map(|(key, value)| {
+                (
+                    self.get_tag_value_ref(Some(key)),
+                    self.get_tag_value_ref(Some(value)),
+                )
+            })
+            .collect();
 
         let tag_set = ElementTagSet {
             name: name_ref,
@@ -151,6 +252,7 @@ impl ElementTags {
             highway: highway_ref,
             surface: surface_ref,
             smoothness: smoothness_ref,
+            extra: extra_refs,
         };
         let idx = match self.tag_set_map.get(&tag_set) {
             Some(i) => *i,
@@ -204,7 +306,7 @@ impl MapDataElement for MapDataLine {
 
 #[derive(Serialize, Deserialize)]
 pub struct MapDataElementRef<T: MapDataElement> {
-    idx: usize,
+    idx: u32,
     _marker: PhantomData<T>,
 }
 
@@ -215,15 +317,15 @@ impl<T: MapDataElement + 'static> Display for MapDataElementRef<T> {
 }
 
 impl<T: MapDataElement> MapDataElementRef<T> {
-    fn new(idx: usize) -> Self {
-        Self {
-            idx,
+    fn new(idx: usize) -> Result<Self, MapDataError> {
+        Ok(Self {
+            idx: element_idx(idx)?,
             _marker: PhantomData,
-        }
+        })
     }
 
     pub fn borrow(&self) -> &'static T {
-        T::get(self.idx)
+        T::get(self.idx as usize)
     }
 }
 
@@ -259,14 +361,87 @@ impl<T: MapDataElement + 'static> Debug for MapDataElementRef<T> {
 pub type MapDataLineRef = MapDataElementRef<MapDataLine>;
 pub type MapDataPointRef = MapDataElementRef<MapDataPoint>;
 
+impl MapDataPointRef {
+    /// Lines of the point, read from the routing graph the same way as the point
+    pub fn lines(&self) -> &'static [MapDataLineRef] {
+        self.borrow().lines.get(&MapDataGraph::get().point_lines)
+    }
+
+    /// Turn rules of the point, read from the routing graph the same way as the point
+    pub fn rules(&self) -> &'static [MapDataRule] {
+        self.borrow().rules.get(&MapDataGraph::get().point_rules)
+    }
+}
+
+/// Points passed through without anything to decide, from a point where the walker decides up to
+/// the next one
+#[derive(Debug)]
+pub struct JunctionEdge {
+    /// Lines walked with the point each of them reaches, in the order they are walked
+    pub steps: Vec<(MapDataLineRef, MapDataPointRef)>,
+    /// Point with something to decide the edge ends at
+    pub end: MapDataPointRef,
+    pub len_m: f32,
+    /// Tag sets of the lines with the length walked on each, consecutive lines with the same tag
+    /// set are counted together
+    pub tags: Vec<(ElementTagSetRef, f32)>,
+}
+
+impl JunctionEdge {
+    fn new(steps: Vec<(MapDataLineRef, MapDataPointRef)>) -> Self {
+        let mut len_m = 0.;
+        let mut tags: Vec<(ElementTagSetRef, f32)> = Vec::new();
+        for (line, _) in &steps {
+            let line = line.borrow();
+            let line_len_m = line.get_len_m();
+            len_m += line_len_m;
+            match tags.last_mut() {
+                Some((tag_set, tag_len_m)) if tag_set.tag_set_idx == line.tags.tag_set_idx => {
+                    *tag_len_m += line_len_m;
+                }
+                _ => tags.push((line.tags.clone(), line_len_m)),
+            }
+        }
+        let end = steps
+            .last()
+            .expect("junction edges pass through at least one point")
+            .1
+            .clone();
+        Self {
+            steps,
+            end,
+            len_m,
+            tags,
+        }
+    }
+}
+
+/// Index of an element as it is kept in element references and point element ranges
+fn element_idx(idx: usize) -> Result<u32, MapDataError> {
+    u32::try_from(idx).map_err(|_| MapDataError::TooManyElements { idx })
+}
+
+/// Points and lines are kept as structs in their own lists and referenced by u32 index. Only the
+/// lines and rules of the points are flattened into graph wide lists, the points keep the range
+/// of their elements in them
 #[derive(Serialize, Deserialize)]
 pub struct MapDataGraph {
     points: Vec<MapDataPoint>,
+    point_lines: Vec<MapDataLineRef>,
+    point_rules: Vec<MapDataRule>,
+    #[serde(skip)]
+    added_point_lines: Vec<(u32, MapDataLineRef)>,
+    #[serde(skip)]
+    added_point_rules: Vec<(u32, MapDataRule)>,
     points_map: HashMap<u64, usize>,
     point_grid: PointGrid<MapDataPointRef>,
     ways_lines: HashMap<u64, Vec<MapDataLineRef>>,
     lines: Vec<MapDataLine>,
     tags: ElementTags,
+    #[serde(skip)]
+    junction_density: OnceLock<f64>,
+    #[serde(skip)]
+    junction_edges: OnceLock<HashMap<(MapDataLineRef, MapDataPointRef), JunctionEdge>>,
 }
 
 #[derive(Default)]
@@ -281,23 +456,35 @@ impl MapDataGraph {
     pub fn new() -> Self {
         Self {
             points: Vec::new(),
+            point_lines: Vec::new(),
+            point_rules: Vec::new(),
+            added_point_lines: Vec::new(),
+            added_point_rules: Vec::new(),
             points_map: HashMap::new(),
             point_grid: PointGrid::new(),
             ways_lines: HashMap::new(),
             lines: Vec::new(),
             tags: ElementTags::new(),
+            junction_density: OnceLock::new(),
+            junction_edges: OnceLock::new(),
         }
     }
 
     pub fn pack(&self) -> anyhow::Result<MapDataGraphPacked> {
+        let _phase = timings::phase!("pack");
         let pack_start = Instant::now();
 
         let mut packed = MapDataGraphPacked::default();
 
-        trace!("points len {}", self.points.len());
-        trace!("proximity_lookup len {}", self.point_grid.len(),);
-        trace!("lines len {}", self.lines.len());
-        trace!("tags len {:?}", self.tags.len());
+        trace!(
+            points = self.points.len(),
+            point_lines = self.point_lines.len(),
+            point_rules = self.point_rules.len(),
+            point_grid = self.point_grid.len(),
+            lines = self.lines.len(),
+            tags = ?self.tags.len(),
+            "Packing"
+        );
 
         let mut points: Option<anyhow::Result<Vec<u8>>> = None;
         let mut point_grid: Option<anyhow::Result<Vec<u8>>> = None;
@@ -306,8 +493,10 @@ impl MapDataGraph {
 
         rayon::scope(|scope| {
             scope.spawn(|_| {
-                points =
-                    Some(bincode::serialize(&self.points).context("Failed to serialize points"));
+                points = Some(
+                    bincode::serialize(&(&self.points, &self.point_lines, &self.point_rules))
+                        .context("Failed to serialize points"),
+                );
             });
             scope.spawn(|_| {
                 point_grid = Some(
@@ -326,17 +515,14 @@ impl MapDataGraph {
         packed.lines = lines.context("Lines missing")??;
         packed.tags = tags.context("Tags missing")??;
 
-        trace!("points len {}, {}", self.points.len(), packed.points.len());
         trace!(
-            "point_grid len {}, {}",
-            self.point_grid.len(),
-            packed.point_grid.len()
+            points_bytes = packed.points.len(),
+            point_grid_bytes = packed.point_grid.len(),
+            lines_bytes = packed.lines.len(),
+            tags_bytes = packed.tags.len(),
+            pack_duration_ms = pack_start.elapsed().as_millis() as u64,
+            "Pack finished"
         );
-        trace!("lines len {} {}", self.lines.len(), packed.lines.len());
-        trace!("tags len {:?} {}", self.tags.len(), packed.tags.len());
-
-        let pack_end = pack_start.elapsed();
-        trace!("pack took {}s", pack_end.as_secs());
 
         Ok(packed)
     }
@@ -347,28 +533,33 @@ impl MapDataGraph {
     }
 
     fn get_point_ref_by_id(&self, id: &u64) -> Option<MapDataPointRef> {
-        self.points_map.get(id).map(|i| MapDataElementRef::new(*i))
+        // the indices are checked when the points are inserted
+        self.points_map
+            .get(id)
+            .and_then(|i| MapDataElementRef::new(*i).ok())
     }
 
-    pub fn insert_node(&mut self, value: OsmNode) {
+    pub fn insert_node(&mut self, value: OsmNode) -> Result<(), MapDataError> {
         let point = MapDataPoint {
             id: value.id,
             lat: value.lat as f32,
             lon: value.lon as f32,
-            lines: Vec::new(),
-            rules: Vec::new(),
+            lines: PointElements::default(),
+            rules: PointElements::default(),
             residential_in_proximity: value.residential_in_proximity,
             nogo_area: value.nogo_area,
+            elevation: value.elevation,
         };
-        self.add_point(point.clone());
+        self.add_point(point)?;
+        Ok(())
     }
 
     #[cfg(feature = "debug-with-postgres")]
     fn write_debug(&self) -> () {
         let mut debug_writer = MapDebugWriter::new();
         for line in &self.lines {
-            let point_1 = &self.points[line.points.0.idx];
-            let point_2 = &self.points[line.points.1.idx];
+            let point_1 = &self.points[line.points.0.idx as usize];
+            let point_2 = &self.points[line.points.1.idx as usize];
             if point_1.residential_in_proximity || point_2.residential_in_proximity {
                 debug_writer.write_line_residential_close(&LineString::new(vec![
                     Coord {
@@ -396,13 +587,44 @@ impl MapDataGraph {
         debug_writer.flush();
     }
 
+    /// Moves the lines and rules added to points into the graph wide lists, keeping the order
+    /// they were added in for each point
+    pub fn place_point_elements(&mut self) {
+        if self.added_point_lines.is_empty() && self.added_point_rules.is_empty() {
+            return;
+        }
+        let mut added_lines = std::mem::take(&mut self.added_point_lines);
+        added_lines.sort_by_key(|(point_idx, _)| *point_idx);
+        self.point_lines = Vec::with_capacity(added_lines.len());
+        for (point_idx, line) in added_lines {
+            // the lengths are checked when the lines and rules are added
+            self.points[point_idx as usize]
+                .lines
+                .place(self.point_lines.len() as u32);
+            self.point_lines.push(line);
+        }
+
+        let mut added_rules = std::mem::take(&mut self.added_point_rules);
+        added_rules.sort_by_key(|(point_idx, _)| *point_idx);
+        self.point_rules = Vec::with_capacity(added_rules.len());
+        for (point_idx, rule) in added_rules {
+            self.points[point_idx as usize]
+                .rules
+                .place(self.point_rules.len() as u32);
+            self.point_rules.push(rule);
+        }
+    }
+
     pub fn generate_point_hashes(&mut self) {
+        let _phase = timings::phase!("build");
+        self.place_point_elements();
         for point in self.points.iter().filter(|p| !p.lines.is_empty()) {
             let point_idx = self
                 .points_map
                 .get(&point.id)
                 .expect("Point must exist in the points map, something went very wrong");
-            let point_ref = MapDataElementRef::new(*point_idx);
+            let point_ref = MapDataElementRef::new(*point_idx)
+                .expect("Point indices are checked when the points are inserted");
             self.point_grid.insert(point.lat, point.lon, &point_ref);
         }
 
@@ -416,18 +638,17 @@ impl MapDataGraph {
         }
     }
 
-    fn get_mut_point_by_idx(&mut self, idx: usize) -> &mut MapDataPoint {
-        &mut self.points[idx]
-    }
-    fn add_line(&mut self, line: MapDataLine) -> usize {
+    fn add_line(&mut self, line: MapDataLine) -> Result<MapDataLineRef, MapDataError> {
+        let line_ref = MapDataLineRef::new(self.lines.len())?;
         self.lines.push(line);
-        self.lines.len() - 1
+        Ok(line_ref)
     }
-    fn add_point(&mut self, point: MapDataPoint) -> usize {
+    fn add_point(&mut self, point: MapDataPoint) -> Result<MapDataPointRef, MapDataError> {
         let idx = self.points.len();
+        let point_ref = MapDataPointRef::new(idx)?;
         self.points_map.insert(point.id, idx);
         self.points.push(point);
-        idx
+        Ok(point_ref)
     }
 
     fn way_is_ok(&self, osm_way: &OsmWay) -> bool {
@@ -445,6 +666,10 @@ impl MapDataGraph {
                     return false;
                 }
             }
+            // the same ways are reported as access violations when a route uses them
+            if tags.get("motorcycle").is_some_and(|v| v == "no") {
+                return false;
+            }
             let motorcycle = match tags.get("motorcycle") {
                 Some(v) => v == "yes",
                 None => false,
@@ -473,7 +698,14 @@ impl MapDataGraph {
                     let tag_surface = osm_way.tags.as_ref().and_then(|t| t.get("surface"));
                     let tag_smoothness = osm_way.tags.as_ref().and_then(|t| t.get("smoothness"));
                     let tag_highway = osm_way.tags.as_ref().and_then(|t| t.get("highway"));
+                    let tag_extra = osm_way.tags.as_ref().map_or(Vec::new(), |t| {
+                        extra_tag_keys()
+                            .iter()
+                            .filter_map(|key| t.get_key_value(key.as_str()))
+                            .collect()
+                    });
                     let line = MapDataLine {
+                        way_id: osm_way.id,
                         points: (prev_point_ref.clone(), point_ref.clone()),
                         direction: if osm_way.is_roundabout() {
                             LineDirection::Roundabout
@@ -488,17 +720,16 @@ impl MapDataGraph {
                             tag_highway,
                             tag_surface,
                             tag_smoothness,
+                            tag_extra,
                         ),
                     };
-                    let line_idx = self.add_line(line);
-                    let line_ref = MapDataLineRef::new(line_idx);
+                    let line_ref = self.add_line(line)?;
                     way_line_refs.push(line_ref.clone());
 
-                    let point_mut = self.get_mut_point_by_idx(point_ref.idx);
-                    point_mut.lines.push(line_ref.clone());
-
-                    let prev_point_mut = self.get_mut_point_by_idx(prev_point_ref.idx);
-                    prev_point_mut.lines.push(line_ref);
+                    element_idx(self.added_point_lines.len() + 1)?;
+                    self.added_point_lines
+                        .push((point_ref.idx, line_ref.clone()));
+                    self.added_point_lines.push((prev_point_ref.idx, line_ref));
                 }
                 prev_point_ref = Some(point_ref);
             } else {
@@ -613,13 +844,13 @@ impl MapDataGraph {
                 },
             )?;
 
-            let point = self.get_mut_point_by_idx(via_point.idx);
             let rule = MapDataRule {
                 from_lines,
                 to_lines,
                 rule_type,
             };
-            point.rules.push(rule);
+            element_idx(self.added_point_rules.len())?;
+            self.added_point_rules.push((via_point.idx, rule));
         } else if via_members.len() > 1 {
             return Err(MapDataError::NotYetImplemented {
                 message: String::from("not yet implemented relations with via ways"),
@@ -636,8 +867,7 @@ impl MapDataGraph {
         center_point: MapDataPointRef,
     ) -> Vec<(MapDataLineRef, MapDataPointRef)> {
         center_point
-            .borrow()
-            .lines
+            .lines()
             .iter()
             .map(|line| {
                 let other_point = if line.borrow().points.0 == center_point {
@@ -650,6 +880,27 @@ impl MapDataGraph {
             .collect()
     }
 
+    /// Coordinates of the lines with at least one point within the bounds, given as
+    /// `(min_lat, min_lon)` and `(max_lat, max_lon)`
+    #[cfg(feature = "render")]
+    pub fn get_line_coords_in_bounds(
+        &self,
+        min: (f32, f32),
+        max: (f32, f32),
+    ) -> Vec<((f32, f32), (f32, f32))> {
+        let in_bounds =
+            |(lat, lon): (f32, f32)| lat >= min.0 && lat <= max.0 && lon >= min.1 && lon <= max.1;
+        self.lines
+            .iter()
+            .filter_map(|line| {
+                let point_0 = line.points.0.borrow();
+                let point_1 = line.points.1.borrow();
+                let coords = ((point_0.lat, point_0.lon), (point_1.lat, point_1.lon));
+                (in_bounds(coords.0) || in_bounds(coords.1)).then_some(coords)
+            })
+            .collect()
+    }
+
     fn get_avoid_rules(rules: &RouterRules) -> HashSet<AvoidTag> {
         let mut avoid_tags = HashSet::new();
 
@@ -699,19 +950,28 @@ impl MapDataGraph {
                 .any(|limit_tag| !avoid_tags.contains(&AvoidTag::Highway(limit_tag.to_string())))
         });
 
-        let mut distances = closest_points
+        let candidates = closest_points
             .iter()
             .filter(|p| {
                 if avoid_proximity_to_residential && p.borrow().residential_in_proximity {
                     return false;
                 }
+                if rules.avoid_node_ids.contains(&p.borrow().id) {
+                    return false;
+                }
                 let lines = p
-                    .borrow()
-                    .lines
+                    .lines()
                     .iter()
                     .map(|line| line.borrow())
                     .collect::<Vec<_>>();
 
+                if lines.iter().any(|line| {
+                    rules.avoid_way_ids.contains(&line.way_id)
+                        || rules.is_excluded_by_tag_matchers(line.tags.borrow())
+                }) {
+                    return false;
+                }
+
                 let mut hws = lines
                     .iter()
                     .filter_map(|line| line.tags.borrow().highway().map(|hw| hw.to_string()));
@@ -744,29 +1004,260 @@ impl MapDataGraph {
                 }
                 true
             })
+            .collect::<Vec<_>>();
+        let (lats, lons): (Vec<f32>, Vec<f32>) = candidates
+            .iter()
             .map(|p| {
-                let point = &self.points[p.idx];
-                let geo_point = Point::new(point.lon, point.lat);
-                let geo_lookup_point = Point::new(lon, lat);
-                (*p, Haversine.distance(geo_point, geo_lookup_point))
+                let point = &self.points[p.idx as usize];
+                (point.lat, point.lon)
             })
-            .collect::<Vec<(&MapDataPointRef, f32)>>();
+            .unzip();
+
+        closest_index(lat, lon, &lats, &lons).map(|idx| (*candidates[idx]).clone())
+    }
+    /// Point with the id at the coordinates, for points of an earlier run when the map data is the
+    /// same. Point ids aren't kept after the map data is loaded, so the point is looked up by
+    /// coordinates
+    pub fn get_point_by_id_at(&self, id: u64, lat: f32, lon: f32) -> Option<MapDataPointRef> {
+        self.point_grid
+            .find_closest_point_refs(lat, lon, 0)?
+            .into_iter()
+            .find(|point| point.borrow().id == id)
+            .cloned()
+    }
+
+    /// Junctions per square km within the bounding box of the map data, calculated once
+    pub fn get_junction_density_per_km2(&self) -> f64 {
+        *self.junction_density.get_or_init(|| {
+            let (mut lat_min, mut lat_max, mut lon_min, mut lon_max) =
+                (f32::MAX, f32::MIN, f32::MAX, f32::MIN);
+            let mut junction_count = 0;
+            for point in self.points.iter() {
+                lat_min = lat_min.min(point.lat);
+                lat_max = lat_max.max(point.lat);
+                lon_min = lon_min.min(point.lon);
+                lon_max = lon_max.max(point.lon);
+                if point.is_junction() {
+                    junction_count += 1;
+                }
+            }
+            if junction_count == 0 {
+                return 0.;
+            }
+            let lat_km = (lat_max - lat_min) as f64 * 111.32;
+            let lon_km = (lon_max - lon_min) as f64
+                * 111.32
+                * (((lat_min + lat_max) / 2.) as f64).to_radians().cos();
+            // a single km2 at least, so tiny areas do not blow up the density
+            junction_count as f64 / (lat_km * lon_km).max(1.)
+        })
+    }
 
-        distances.sort_by(|el1, el2| {
-            if el1.1 > el2.1 {
-                Ordering::Greater
-            } else if el1.1 < el2.1 {
-                Ordering::Less
+    /// Next line and point when the line reaches a point with nothing to decide, where it only
+    /// continues on the other line, there are no turn rules and it is not a roundabout
+    pub fn get_pass_through_next(
+        &self,
+        line: &MapDataLineRef,
+        point: &MapDataPointRef,
+    ) -> Option<(MapDataLineRef, MapDataPointRef)> {
+        if point.lines().len() != 2 || !point.rules().is_empty() || line.borrow().is_roundabout() {
+            return None;
+        }
+        let next_line = point.lines().iter().find(|l| *l != line)?;
+        let next_line_borrowed = next_line.borrow();
+        if next_line_borrowed.is_roundabout()
+            || (next_line_borrowed.is_one_way() && &next_line_borrowed.points.1 == point)
+        {
+            return None;
+        }
+        let other_point = |l: &MapDataLine| {
+            if &l.points.0 == point {
+                l.points.1.clone()
             } else {
-                Ordering::Equal
+                l.points.0.clone()
             }
-        });
+        };
+        let next_point = other_point(next_line_borrowed);
+        if next_point.borrow().id == other_point(line.borrow()).borrow().id {
+            return None;
+        }
+        Some((next_line.clone(), next_point))
+    }
+
+    /// Edge that starts when the line reaches the point, up to the next point with something to
+    /// decide. Only known for lines coming from such a point
+    pub fn get_junction_edge(
+        &self,
+        line: &MapDataLineRef,
+        point: &MapDataPointRef,
+    ) -> Option<&JunctionEdge> {
+        self.junction_edges
+            .get_or_init(|| self.find_junction_edges())
+            .get(&(line.clone(), point.clone()))
+    }
+
+    /// Finds the junction to junction edges of the whole graph, called once the graph is loaded so
+    /// the first route request doesn't wait for it
+    pub fn load_junction_edges(&self) {
+        self.junction_edges
+            .get_or_init(|| self.find_junction_edges());
+    }
+
+    fn find_junction_edges(&self) -> HashMap<(MapDataLineRef, MapDataPointRef), JunctionEdge> {
+        let _phase = timings::phase!("junction_edges");
+        let mut edges = HashMap::new();
+        for (idx, line) in self.lines.iter().enumerate() {
+            let line_ref = MapDataLineRef::new(idx)
+                .expect("Line indices are checked when the lines are added");
+            for (from, to) in [
+                (&line.points.0, &line.points.1),
+                (&line.points.1, &line.points.0),
+            ] {
+                let Some(mut next) = self.get_pass_through_next(&line_ref, to) else {
+                    continue;
+                };
+                // lines in the middle of an edge are not where the walker decides
+                let is_continuation = from.lines().iter().any(|prev_line| {
+                    prev_line != &line_ref
+                        && self.get_pass_through_next(prev_line, from).is_some_and(
+                            |(next_line, next_point)| next_line == line_ref && &next_point == to,
+                        )
+                });
+                if is_continuation {
+                    continue;
+                }
+                let mut steps = vec![next.clone()];
+                // ways closed on themselves never reach a point to decide at
+                while let Some(after) = self.get_pass_through_next(&next.0, &next.1) {
+                    if steps.len() > self.lines.len() {
+                        break;
+                    }
+                    steps.push(after.clone());
+                    next = after;
+                }
+                edges.insert((line_ref.clone(), to.clone()), JunctionEdge::new(steps));
+            }
+        }
+        trace!(edges = edges.len(), "Junction edges found");
+        edges
+    }
+
+    /// Bounding box of all points, none when there are no points
+    pub fn bbox(&self) -> Option<GraphInfoBbox> {
+        self.points.iter().fold(None, |bbox, point| {
+            let bbox = bbox.unwrap_or(GraphInfoBbox {
+                lat_min: point.lat,
+                lat_max: point.lat,
+                lon_min: point.lon,
+                lon_max: point.lon,
+            });
+            Some(GraphInfoBbox {
+                lat_min: bbox.lat_min.min(point.lat),
+                lat_max: bbox.lat_max.max(point.lat),
+                lon_min: bbox.lon_min.min(point.lon),
+                lon_max: bbox.lon_max.max(point.lon),
+            })
+        })
+    }
+
+    /// Counts, lengths per highway class and surface and a memory estimate of the graph
+    pub fn info(&self) -> GraphInfo {
+        let bbox = self.bbox();
+
+        let mut total_len_m = 0.;
+        let mut one_way_len_m = 0.;
+        let mut roundabout_len_m = 0.;
+        let mut highway_len_m: HashMap<String, f64> = HashMap::new();
+        let mut surface_len_m: HashMap<String, f64> = HashMap::new();
+        let mut way_ids = HashSet::new();
+        for line in self.lines.iter() {
+            let len_m = line.get_len_m() as f64;
+            total_len_m += len_m;
+            if line.is_one_way() {
+                one_way_len_m += len_m;
+            }
+            if line.is_roundabout() {
+                roundabout_len_m += len_m;
+            }
+            let tags = line.tags.borrow();
+            let value = |value: Option<&smartstring::alias::String>| {
+                value.map_or("unknown".to_string(), |value| value.to_string())
+            };
+            *highway_len_m.entry(value(tags.highway())).or_default() += len_m;
+            *surface_len_m.entry(value(tags.surface())).or_default() += len_m;
+            way_ids.insert(line.way_id);
+        }
 
-        distances.first().map(|v| v.0.clone())
+        let percentage = |part: f64, total: f64| if total > 0. { part / total * 100. } else { 0. };
+        let restriction_count = self.point_rules.len();
+        let (tag_value_count, tag_set_count) = self.tags.len();
+        let memory_estimate_bytes = self.points.len() * size_of::<MapDataPoint>()
+            + self.point_lines.len() * size_of::<MapDataLineRef>()
+            + self
+                .point_rules
+                .iter()
+                .map(|rule| {
+                    size_of::<MapDataRule>()
+                        + (rule.from_lines.len() + rule.to_lines.len())
+                            * size_of::<MapDataLineRef>()
+                })
+                .sum::<usize>()
+            + self.lines.len() * size_of::<MapDataLine>()
+            + self.point_grid.len() * (size_of::<(i16, i16)>() + size_of::<Vec<MapDataPointRef>>())
+            + self.points.len() * size_of::<MapDataPointRef>()
+            + self
+                .tags
+                .tag_values
+                .iter()
+                .map(|value| size_of::<smartstring::alias::String>() + value.len())
+                .sum::<usize>()
+            + self
+                .tags
+                .tag_sets
+                .iter()
+                .map(|tag_set| {
+                    size_of::<ElementTagSet>()
+                        + tag_set.extra.len()
+                            * size_of::<(ElementTagValueRef, ElementTagValueRef)>()
+                })
+                .sum::<usize>();
+
+        GraphInfo {
+            point_count: self.points.len(),
+            junction_count: self
+                .points
+                .iter()
+                .filter(|point| point.is_junction())
+                .count(),
+            line_count: self.lines.len(),
+            way_count: way_ids.len(),
+            bbox,
+            total_len_km: total_len_m / 1000.,
+            one_way_percentage: percentage(one_way_len_m, total_len_m),
+            roundabout_len_km: roundabout_len_m / 1000.,
+            restriction_count,
+            elevation_percentage: percentage(
+                self.points
+                    .iter()
+                    .filter(|point| point.elevation.is_some())
+                    .count() as f64,
+                self.points.len() as f64,
+            ),
+            highway: lengths_by_value(highway_len_m, total_len_m),
+            surface: lengths_by_value(surface_len_m, total_len_m),
+            tag_value_count,
+            tag_set_count,
+            memory_estimate_bytes,
+        }
     }
+
     #[tracing::instrument(skip(packed))]
     pub fn unpack(packed: MapDataGraphPacked) -> anyhow::Result<&'static MapDataGraph> {
-        let mut points: Option<anyhow::Result<Vec<MapDataPoint>>> = None;
+        let _phase = timings::phase!("unpack");
+        #[allow(clippy::type_complexity)]
+        let mut points: Option<
+            anyhow::Result<(Vec<MapDataPoint>, Vec<MapDataLineRef>, Vec<MapDataRule>)>,
+        > = None;
         let points_map = HashMap::new();
         let mut point_grid: Option<anyhow::Result<PointGrid<MapDataPointRef>>> = None;
         let ways_lines = HashMap::new();
@@ -781,8 +1272,10 @@ impl MapDataGraph {
                     bincode::deserialize(&packed.points[..])
                         .context("could not deserialize points"),
                 );
-                let dur = start.elapsed();
-                trace!("points {}s", dur.as_secs());
+                trace!(
+                    duration_ms = start.elapsed().as_millis() as u64,
+                    "Points unpacked"
+                );
             });
             scope.spawn(|_| {
                 let start = Instant::now();
@@ -790,46 +1283,63 @@ impl MapDataGraph {
                     bincode::deserialize(&packed.point_grid[..])
                         .context("could not deserialize points"),
                 );
-                let dur = start.elapsed();
-                trace!("point_grid {}s", dur.as_secs());
+                trace!(
+                    duration_ms = start.elapsed().as_millis() as u64,
+                    "Point grid unpacked"
+                );
             });
             scope.spawn(|_| {
                 let start = Instant::now();
                 lines = Some(
                     bincode::deserialize(&packed.lines[..]).context("could not deserialize lines"),
                 );
-                let dur = start.elapsed();
-                trace!("lines {}s", dur.as_secs());
+                trace!(
+                    duration_ms = start.elapsed().as_millis() as u64,
+                    "Lines unpacked"
+                );
             });
             scope.spawn(|_| {
                 let start = Instant::now();
                 tags = Some(
                     bincode::deserialize(&packed.tags[..]).context("could not deserialize tags"),
                 );
-                let dur = start.elapsed();
-                trace!("tags {}s", dur.as_secs());
+                trace!(
+                    duration_ms = start.elapsed().as_millis() as u64,
+                    "Tags unpacked"
+                );
             });
         });
-        let unpack_duration = unpack_start.elapsed();
-        trace!(time = ?unpack_duration, "Unpack finished");
+        trace!(
+            unpack_duration_ms = unpack_start.elapsed().as_millis() as u64,
+            "Unpack finished"
+        );
 
-        let points = points.context("Points missing")??;
+        let (points, point_lines, point_rules) = points.context("Points missing")??;
         let point_grid = point_grid.context("Point grid missing")??;
         let lines = lines.context("Lines missing")??;
         let tags = tags.context("Tags missing")??;
 
-        Ok(MAP_DATA_GRAPH.get_or_init(|| MapDataGraph {
+        let graph = MAP_DATA_GRAPH.get_or_init(|| MapDataGraph {
             points,
+            point_lines,
+            point_rules,
+            added_point_lines: Vec::new(),
+            added_point_rules: Vec::new(),
             points_map,
             point_grid,
             lines,
             ways_lines,
             tags,
-        }))
+            junction_density: OnceLock::new(),
+            junction_edges: OnceLock::new(),
+        });
+        graph.load_junction_edges();
+        Ok(graph)
     }
 
     fn get_or_init(data_source: Option<&DataSource>) -> &'static MapDataGraph {
         MAP_DATA_GRAPH.get_or_init(|| {
+            profiler::zone!("load_map_data");
             let data_source = data_source.expect("data source must passed in when calling init");
             let data_reader = OsmDataReader::new(data_source.clone());
 
@@ -840,7 +1350,8 @@ impl MapDataGraph {
     }
     #[tracing::instrument]
     pub fn init(data_source: &DataSource) {
-        MapDataGraph::get_or_init(Some(data_source));
+        MapDataGraph::get_or_init(Some(data_source)).load_junction_edges();
+        profiler::frame!();
     }
     pub fn get() -> &'static MapDataGraph {
         MapDataGraph::get_or_init(None) // we've already initialized the graph
@@ -856,7 +1367,7 @@ mod tests {
     use tracing::info;
 
     use crate::{
-        router::rules::{BasicRules, GenerationRules},
+        router::rules::{BasicRules, GenerationRules, ScoringRules},
         test_utils::{graph_from_test_dataset, set_graph_static, test_dataset_1},
     };
 
@@ -1049,6 +1560,18 @@ mod tests {
         };
 
         assert!(!map_data.way_is_ok(&osm_way));
+
+        let osm_way = OsmWay {
+            id: 1,
+            point_ids: Vec::new(),
+            tags: Some(HashMap::from([
+                ("highway".to_string(), "primary".to_string()),
+                ("access".to_string(), "yes".to_string()),
+                ("motorcycle".to_string(), "no".to_string()),
+            ])),
+        };
+
+        assert!(!map_data.way_is_ok(&osm_way));
     }
 
     #[derive(Debug)]
@@ -1067,13 +1590,14 @@ mod tests {
                 let point = map_data
                     .get_point_ref_by_id(id)
                     .unwrap_or_else(|| panic!("point {} must exist", id));
+                let lines = point.lines();
                 let point = point.borrow();
                 info!("point {:#?}", point);
                 info!("test {:#?}", test);
                 point.lat == test.lat
                     && point.lon == test.lon
-                    && point.lines.len() == test.lines.len()
-                    && point.lines.iter().enumerate().all(|(idx, l)| {
+                    && lines.len() == test.lines.len()
+                    && lines.iter().enumerate().all(|(idx, l)| {
                         let test_line_id = test
                             .lines
                             .get(idx)
@@ -1229,6 +1753,32 @@ mod tests {
         }
     }
 
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn pack_unpack() {
+            let mut map_data = graph_from_test_dataset(test_dataset_1());
+            map_data.generate_point_hashes();
+            let packed = map_data.pack().expect("graph to pack");
+            let unpacked = MapDataGraph::unpack(packed).expect("graph to unpack");
+
+            assert_eq!(unpacked.points.len(), map_data.points.len());
+            assert_eq!(unpacked.lines.len(), map_data.lines.len());
+            assert_eq!(unpacked.point_lines.len(), map_data.point_lines.len());
+            for (idx, point) in map_data.points.iter().enumerate() {
+                let point_ref = MapDataPointRef::new(idx).unwrap();
+                assert_eq!(point_ref.borrow().id, point.id);
+                let line_ids = |lines: &[MapDataLineRef]| {
+                    lines.iter().map(|l| l.borrow().line_id()).collect::<Vec<_>>()
+                };
+                assert_eq!(
+                    line_ids(point_ref.lines()),
+                    line_ids(point.lines.get(&map_data.point_lines))
+                );
+            }
+        }
+    }
+
     rusty_fork_test! {
         #![rusty_fork(timeout_ms = 2000)]
         #[test]
@@ -1251,6 +1801,89 @@ mod tests {
         }
     }
 
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn way_extra_tags() {
+            let mut map_data = MapDataGraph::new();
+            for id in [1, 2] {
+                map_data.insert_node(OsmNode {
+                    id,
+                    lat: 57.0 + id as f64 * 0.001,
+                    lon: 24.0,
+                    residential_in_proximity: false,
+                    nogo_area: false,
+                    elevation: None,
+                })
+                .unwrap();
+            }
+            map_data
+                .insert_way(OsmWay {
+                    id: 1,
+                    point_ids: vec![1, 2],
+                    tags: Some(HashMap::from([
+                        ("highway".to_string(), "primary".to_string()),
+                        ("embankment".to_string(), "yes".to_string()),
+                        ("note".to_string(), "not stored".to_string()),
+                    ])),
+                })
+                .expect("failed to insert way");
+            let map_data = set_graph_static(map_data);
+
+            let point = map_data.get_point_ref_by_id(&1).unwrap();
+            let line = point.lines()[0].borrow();
+            let tags = line.tags.borrow();
+            assert_eq!(tags.get("highway").map(|v| v.as_str()), Some("primary"));
+            assert_eq!(tags.get("embankment").map(|v| v.as_str()), Some("yes"));
+            assert_eq!(tags.get("note"), None);
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn way_configured_extra_tags() {
+            set_extra_tag_keys(&["note".to_string(), "embankment".to_string()]);
+            assert_eq!(
+                extra_tag_keys()
+                    .iter()
+                    .filter(|key| *key == "embankment")
+                    .count(),
+                1
+            );
+            let mut map_data = MapDataGraph::new();
+            for id in [1, 2] {
+                map_data.insert_node(OsmNode {
+                    id,
+                    lat: 57.0 + id as f64 * 0.001,
+                    lon: 24.0,
+                    residential_in_proximity: false,
+                    nogo_area: false,
+                    elevation: None,
+                })
+                .unwrap();
+            }
+            map_data
+                .insert_way(OsmWay {
+                    id: 1,
+                    point_ids: vec![1, 2],
+                    tags: Some(HashMap::from([
+                        ("highway".to_string(), "primary".to_string()),
+                        ("embankment".to_string(), "yes".to_string()),
+                        ("note".to_string(), "stored".to_string()),
+                    ])),
+                })
+                .expect("failed to insert way");
+            let map_data = set_graph_static(map_data);
+
+            let point = map_data.get_point_ref_by_id(&1).unwrap();
+            let line = point.lines()[0].borrow();
+            let tags = line.tags.borrow();
+            assert_eq!(tags.get("embankment").map(|v| v.as_str()), Some("yes"));
+            assert_eq!(tags.get("note").map(|v| v.as_str()), Some("stored"));
+        }
+    }
+
     rusty_fork_test! {
         #![rusty_fork(timeout_ms = 2000)]
         #[test]
@@ -1328,7 +1961,7 @@ mod tests {
         let (points, ways, rules, check_point, closest_id) = test;
         let mut map_data = MapDataGraph::new();
         for point in &points {
-            map_data.insert_node(point.clone());
+            map_data.insert_node(point.clone()).unwrap();
         }
         for point in points {
             if !ways.iter().any(|w| w.point_ids.contains(&point.id)) {
@@ -1370,7 +2003,7 @@ mod tests {
             panic!("No points found");
         }
     }
-    fn get_closest_tests() -> [ClosestTest; 6] {
+    fn get_closest_tests() -> [ClosestTest; 8] {
         [
             (
                 vec![
@@ -1381,6 +2014,7 @@ mod tests {
                         lon: 24.8652,
                         residential_in_proximity: false,
                         nogo_area: false,
+                        elevation: None,
                     },
                 ],
                 vec![],
@@ -1391,6 +2025,7 @@ mod tests {
                     lon: 24.8658,
                     residential_in_proximity: false,
                     nogo_area: false,
+                    elevation: None,
                 },
                 1,
             ),
@@ -1403,6 +2038,7 @@ mod tests {
                         lon: 24.8630,
                         residential_in_proximity: false,
                         nogo_area: false,
+                        elevation: None,
                     },
                     OsmNode {
                         id: 2,
@@ -1410,6 +2046,7 @@ mod tests {
                         lon: 24.8652,
                         residential_in_proximity: false,
                         nogo_area: false,
+                        elevation: None,
                     },
                 ],
                 vec![],
@@ -1420,6 +2057,7 @@ mod tests {
                     lon: 24.8658,
                     residential_in_proximity: false,
                     nogo_area: false,
+                    elevation: None,
                 },
                 2,
             ),
@@ -1433,6 +2071,7 @@ mod tests {
                         lon: 24.875192642211914,
                         residential_in_proximity: false,
                         nogo_area: false,
+                        elevation: None,
                     },
                     OsmNode {
                         // 525.74 meters
@@ -1441,6 +2080,7 @@ mod tests {
                         lon: 24.875192642211914,
                         residential_in_proximity: false,
                         nogo_area: false,
+                        elevation: None,
                     },
                     OsmNode {
                         // 438.77 meters
@@ -1449,6 +2089,7 @@ mod tests {
                         lon: 24.877617359161377,
                         residential_in_proximity: false,
                         nogo_area: false,
+                        elevation: None,
                     },
                 ],
                 vec![],
@@ -1459,6 +2100,7 @@ mod tests {
                     lon: 24.87742424011231,
                     residential_in_proximity: false,
                     nogo_area: false,
+                    elevation: None,
                 },
                 3,
             ),
@@ -1472,6 +2114,7 @@ mod tests {
                         lon: 24.875192642211914,
                         residential_in_proximity: false,
                         nogo_area: false,
+                        elevation: None,
                     },
                     OsmNode {
                         // 3777.35 meters
@@ -1480,6 +2123,7 @@ mod tests {
                         lon: 24.877617359161377,
                         residential_in_proximity: false,
                         nogo_area: false,
+                        elevation: None,
                     },
                 ],
                 vec![],
@@ -1490,6 +2134,7 @@ mod tests {
                     lon: 24.872531890869144,
                     residential_in_proximity: false,
                     nogo_area: false,
+                    elevation: None,
                 },
                 1,
             ),
@@ -1503,6 +2148,7 @@ mod tests {
                         lon: 24.875192642211914,
                         residential_in_proximity: false,
                         nogo_area: false,
+                        elevation: None,
                     },
                     OsmNode {
                         // 3777.35 meters
@@ -1511,6 +2157,7 @@ mod tests {
                         lon: 24.877617359161377,
                         residential_in_proximity: false,
                         nogo_area: false,
+                        elevation: None,
                     },
                 ],
                 vec![],
@@ -1521,6 +2168,7 @@ mod tests {
                     lon: 24.872531890869144,
                     residential_in_proximity: false,
                     nogo_area: false,
+                    elevation: None,
                 },
                 1,
             ),
@@ -1534,6 +2182,7 @@ mod tests {
                         lon: 24.875192642211914,
                         residential_in_proximity: false,
                         nogo_area: false,
+                        elevation: None,
                     },
                     OsmNode {
                         // 525.74 meters
@@ -1542,6 +2191,7 @@ mod tests {
                         lon: 24.875192642211914,
                         residential_in_proximity: false,
                         nogo_area: false,
+                        elevation: None,
                     },
                     OsmNode {
                         // 438.77 meters
@@ -1550,6 +2200,7 @@ mod tests {
                         lon: 24.877617359161377,
                         residential_in_proximity: false,
                         nogo_area: false,
+                        elevation: None,
                     },
                 ],
                 vec![OsmWay {
@@ -1568,7 +2219,116 @@ mod tests {
                     )])),
                     surface: None,
                     smoothness: None,
+                    highway_weights: HashMap::new(),
+                    surface_weights: HashMap::new(),
+                    smoothness_weights: HashMap::new(),
+                    tag_matchers: Vec::new(),
+                    avoid_way_ids: HashSet::new(),
+                    avoid_node_ids: HashSet::new(),
                     generation: GenerationRules::default(),
+                    scoring: ScoringRules::default(),
+                    scripted_weights: Vec::new(),
+                    units: None,
+                    schema_version: Default::default(),
+                }),
+                OsmNode {
+                    id: 0,
+                    lat: 57.163429387682214,
+                    lon: 24.87742424011231,
+                    residential_in_proximity: false,
+                    nogo_area: false,
+                    elevation: None,
+                },
+                2,
+            ),
+            (
+                vec![
+                    // 6
+                    OsmNode {
+                        // 701.26 meters
+                        id: 1,
+                        lat: 57.16961885299059,
+                        lon: 24.875192642211914,
+                        residential_in_proximity: false,
+                        nogo_area: false,
+                        elevation: None,
+                    },
+                    OsmNode {
+                        // 525.74 meters
+                        id: 2,
+                        lat: 57.168,
+                        lon: 24.875192642211914,
+                        residential_in_proximity: false,
+                        nogo_area: false,
+                        elevation: None,
+                    },
+                    OsmNode {
+                        // 438.77 meters
+                        id: 3,
+                        lat: 57.159484808175435,
+                        lon: 24.877617359161377,
+                        residential_in_proximity: false,
+                        nogo_area: false,
+                        elevation: None,
+                    },
+                ],
+                vec![],
+                Some(RouterRules {
+                    avoid_node_ids: HashSet::from([3]),
+                    ..Default::default()
+                }),
+                OsmNode {
+                    id: 0,
+                    lat: 57.163429387682214,
+                    lon: 24.87742424011231,
+                    residential_in_proximity: false,
+                    nogo_area: false,
+                    elevation: None,
+                },
+                2,
+            ),
+            (
+                vec![
+                    // 7
+                    OsmNode {
+                        // 701.26 meters
+                        id: 1,
+                        lat: 57.16961885299059,
+                        lon: 24.875192642211914,
+                        residential_in_proximity: false,
+                        nogo_area: false,
+                        elevation: None,
+                    },
+                    OsmNode {
+                        // 525.74 meters
+                        id: 2,
+                        lat: 57.168,
+                        lon: 24.875192642211914,
+                        residential_in_proximity: false,
+                        nogo_area: false,
+                        elevation: None,
+                    },
+                    OsmNode {
+                        // 438.77 meters
+                        id: 3,
+                        lat: 57.159484808175435,
+                        lon: 24.877617359161377,
+                        residential_in_proximity: false,
+                        nogo_area: false,
+                        elevation: None,
+                    },
+                ],
+                vec![OsmWay {
+                    id: 33,
+                    point_ids: vec![3],
+                    tags: Some(HashMap::from([(
+                        "highway".to_string(),
+                        "primary".to_string(),
+                    )])),
+                }],
+                Some(RouterRules {
+                    avoid_way_ids: HashSet::from([33]),
+                    ..Default::default()
                 }),
                 OsmNode {
                     id: 0,
@@ -1576,6 +2336,7 @@ mod tests {
                     lon: 24.87742424011231,
                     residential_in_proximity: false,
                     nogo_area: false,
+                    elevation: None,
                 },
                 2,
             ),
@@ -1629,4 +2390,80 @@ mod tests {
             run_closest_test(tests[5].clone());
         }
     }
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn closest_lookup_6() {
+            let tests = get_closest_tests();
+            run_closest_test(tests[6].clone());
+        }
+    }
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn closest_lookup_7() {
+            let tests = get_closest_tests();
+            run_closest_test(tests[7].clone());
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn graph_info_counts() {
+            let map_data = set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let info = map_data.info();
+            assert_eq!(info.point_count, 11);
+            assert_eq!(info.junction_count, 3);
+            assert_eq!(info.line_count, 10);
+            assert_eq!(info.way_count, 5);
+            assert_eq!(info.restriction_count, 0);
+            assert_eq!(info.one_way_percentage, 0.);
+            let bbox = info.bbox.expect("points have a bbox");
+            assert_eq!((bbox.lat_min, bbox.lat_max), (1., 12.));
+            assert_eq!(info.highway.len(), 1);
+            assert_eq!(info.highway[0].value, "primary");
+            assert_eq!(info.highway[0].percentage, 100.);
+            assert_eq!(info.surface[0].value, "unknown");
+            assert!(info.memory_estimate_bytes > 0);
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 10000)]
+        #[test]
+        fn junction_edges_end_at_decisions() {
+            set_graph_static(crate::test_utils::graph_from_test_file(
+                &std::path::PathBuf::from("test-data/sigulda-100.json"),
+            ));
+            let graph = MapDataGraph::get();
+            // found when the graph is loaded, not by the first walker
+            assert!(graph.junction_edges.get().is_some());
+            let mut edge_count = 0;
+            for idx in 0..graph.lines.len() {
+                let line = MapDataLineRef::new(idx).unwrap();
+                for point in [&line.borrow().points.0, &line.borrow().points.1] {
+                    let Some(edge) = graph.get_junction_edge(&line, point) else {
+                        continue;
+                    };
+                    edge_count += 1;
+                    let mut next = (line.clone(), point.clone());
+                    let mut len_m = 0.;
+                    for step in &edge.steps {
+                        next = graph
+                            .get_pass_through_next(&next.0, &next.1)
+                            .expect("to pass through up to the end of the edge");
+                        assert_eq!(&next, step);
+                        len_m += next.0.borrow().get_len_m();
+                    }
+                    assert!(graph.get_pass_through_next(&next.0, &next.1).is_none());
+                    assert_eq!(next.1, edge.end);
+                    assert!((edge.len_m - len_m).abs() < 0.01);
+                    let tags_len_m: f32 = edge.tags.iter().map(|(_, len_m)| len_m).sum();
+                    assert!((tags_len_m - len_m).abs() < 0.01);
+                }
+            }
+            assert!(edge_count > 0);
+        }
+    }
 }
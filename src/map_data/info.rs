@@ -0,0 +1,145 @@
+use std::io;
+
+use serde::Serialize;
+
+#[derive(Debug, thiserror::Error)]
+pub enum GraphInfoError {
+    #[error("Could not write graph info: {error}")]
+    WriteJson { error: serde_json::Error },
+
+    #[error("Could not write graph info: {error}")]
+    Write { error: io::Error },
+}
+
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum GraphInfoFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Road length with one value of a tag, like a highway class or a surface
+#[derive(Debug, Serialize, PartialEq)]
+pub struct GraphInfoLength {
+    /// Tag value, `unknown` for roads without the tag
+    pub value: String,
+    pub len_km: f64,
+    pub percentage: f64,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct GraphInfoBbox {
+    pub lat_min: f32,
+    pub lat_max: f32,
+    pub lon_min: f32,
+    pub lon_max: f32,
+}
+
+/// What the router ingested from the map data
+#[derive(Debug, Serialize, PartialEq)]
+pub struct GraphInfo {
+    pub point_count: usize,
+    pub junction_count: usize,
+    pub line_count: usize,
+    pub way_count: usize,
+    /// None when there are no points
+    pub bbox: Option<GraphInfoBbox>,
+    pub total_len_km: f64,
+    /// Share of the road length that can only be ridden in one direction, roundabouts included
+    pub one_way_percentage: f64,
+    pub roundabout_len_km: f64,
+    /// Turn restrictions kept on their via points
+    pub restriction_count: usize,
+    /// Points with a known elevation
+    pub elevation_percentage: f64,
+    /// Longest first
+    pub highway: Vec<GraphInfoLength>,
+    /// Longest first
+    pub surface: Vec<GraphInfoLength>,
+    pub tag_value_count: usize,
+    pub tag_set_count: usize,
+    /// Rough size of the graph in memory, from the size of its elements without allocator
+    /// overhead
+    pub memory_estimate_bytes: usize,
+}
+
+impl GraphInfo {
+    pub fn write(&self, format: GraphInfoFormat) -> Result<(), GraphInfoError> {
+        let text = match format {
+            GraphInfoFormat::Json => serde_json::to_string_pretty(self)
+                .map_err(|error| GraphInfoError::WriteJson { error })?,
+            GraphInfoFormat::Text => self.to_text(),
+        };
+        let mut stdout = io::stdout().lock();
+        io::Write::write_all(&mut stdout, text.as_bytes())
+            .and_then(|_| io::Write::write_all(&mut stdout, b"\n"))
+            .map_err(|error| GraphInfoError::Write { error })
+    }
+
+    fn to_text(&self) -> String {
+        let lengths = |lengths: &[GraphInfoLength]| {
+            lengths
+                .iter()
+                .map(|length| {
+                    format!(
+                        "  {:<20} {:>10.1}km {:>6.1}%",
+                        length.value, length.len_km, length.percentage
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let bbox = self.bbox.as_ref().map_or("-".to_string(), |bbox| {
+            format!(
+                "{},{} - {},{}",
+                bbox.lat_min, bbox.lon_min, bbox.lat_max, bbox.lon_max
+            )
+        });
+        format!(
+            "Points: {} ({} junctions, {:.1}% with elevation)\n\
+             Lines: {} in {} ways\n\
+             Bounding box: {bbox}\n\
+             Road length: {:.1}km, {:.1}% one way, {:.1}km roundabouts\n\
+             Turn restrictions: {}\n\
+             Tags: {} values in {} sets\n\
+             Memory estimate: {:.1}MB\n\
+             Highway:\n{}\n\
+             Surface:\n{}",
+            self.point_count,
+            self.junction_count,
+            self.elevation_percentage,
+            self.line_count,
+            self.way_count,
+            self.total_len_km,
+            self.one_way_percentage,
+            self.roundabout_len_km,
+            self.restriction_count,
+            self.tag_value_count,
+            self.tag_set_count,
+            self.memory_estimate_bytes as f64 / 1024. / 1024.,
+            lengths(&self.highway),
+            lengths(&self.surface),
+        )
+    }
+}
+
+/// Lengths per value with their share of the total, longest first
+pub fn lengths_by_value(
+    lengths: impl IntoIterator<Item = (String, f64)>,
+    total_len_m: f64,
+) -> Vec<GraphInfoLength> {
+    let mut lengths = lengths
+        .into_iter()
+        .map(|(value, len_m)| GraphInfoLength {
+            value,
+            len_km: len_m / 1000.,
+            percentage: if total_len_m > 0. {
+                len_m / total_len_m * 100.
+            } else {
+                0.
+            },
+        })
+        .collect::<Vec<_>>();
+    lengths.sort_by(|a, b| b.len_km.total_cmp(&a.len_km).then(a.value.cmp(&b.value)));
+    lengths
+}
@@ -14,6 +14,7 @@ pub enum LineDirection {
 #[derive(Clone, Serialize, Deserialize)]
 pub struct MapDataLine {
     // pub id: String,
+    pub way_id: u64,
     pub points: (MapDataPointRef, MapDataPointRef),
     pub direction: LineDirection,
     pub tags: ElementTagSetRef,
@@ -40,6 +41,32 @@ impl MapDataLine {
     pub fn get_len_m(&self) -> f32 {
         self.points.0.borrow().distance_between(&self.points.1)
     }
+    /// Rough riding speed estimate based on the road type and surface
+    pub fn get_speed_kmh(&self) -> f32 {
+        let tags = self.tags.borrow();
+        let speed: f32 = match tags.highway().map(|hw| hw.as_str()) {
+            Some("motorway") | Some("motorway_link") => 110.,
+            Some("trunk") | Some("trunk_link") => 90.,
+            Some("primary") | Some("primary_link") => 80.,
+            Some("secondary") | Some("secondary_link") => 70.,
+            Some("tertiary") | Some("tertiary_link") => 60.,
+            Some("unclassified") => 50.,
+            Some("residential") => 40.,
+            Some("track") => 30.,
+            Some("living_street") | Some("service") => 20.,
+            Some("path") => 15.,
+            _ => 40.,
+        };
+        match tags.surface().map(|surface| surface.as_str()) {
+            Some("unpaved") | Some("compacted") | Some("fine_gravel") | Some("gravel")
+            | Some("dirt") | Some("ground") | Some("earth") | Some("grass") | Some("sand")
+            | Some("mud") => speed.min(40.),
+            _ => speed,
+        }
+    }
+    pub fn get_travel_time_s(&self) -> f32 {
+        self.get_len_m() / (self.get_speed_kmh() / 3.6)
+    }
 }
 
 impl PartialEq for MapDataLine {
@@ -3,6 +3,7 @@ use self::osm::OsmRelation;
 #[cfg(feature = "debug-with-postgres")]
 pub mod debug_writer;
 pub mod graph;
+pub mod info;
 pub mod line;
 pub mod osm;
 pub mod point;
@@ -32,6 +33,9 @@ pub enum MapDataError {
     #[error("Missing 'via' point {point_id} in relation {relation_id}")]
     MissingViaPoint { relation_id: u64, point_id: u64 },
 
+    #[error("Too many map data elements, element {idx} does not fit in a u32 index")]
+    TooManyElements { idx: usize },
+
     #[error("{message} - Relation: {relation:?}")]
     NotYetImplemented {
         message: String,
@@ -7,6 +7,20 @@ pub struct OsmNode {
     pub lon: f64,
     pub residential_in_proximity: bool,
     pub nogo_area: bool,
+    pub elevation: Option<f32>,
+}
+
+impl OsmNode {
+    /// Parses an `ele` tag value in meters, which may come with a unit, e.g. "123.4" or "123 m"
+    pub fn parse_elevation(value: &str) -> Option<f32> {
+        value
+            .trim()
+            .trim_end_matches('m')
+            .trim()
+            .parse::<f32>()
+            .ok()
+            .filter(|ele| ele.is_finite())
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -1,5 +1,4 @@
 use geo::Bearing;
-use geo::Distance;
 use geo::Haversine;
 use geo::Point;
 use serde::Deserialize;
@@ -7,9 +6,11 @@ use serde::Serialize;
 
 use std::fmt::Debug;
 use std::fmt::Display;
+use std::marker::PhantomData;
 
 use super::graph::MapDataLineRef;
 use super::graph::MapDataPointRef;
+use super::proximity::distance_m;
 use super::rule::MapDataRule;
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -17,17 +18,80 @@ pub struct MapDataPoint {
     pub id: u64,
     pub lat: f32,
     pub lon: f32,
-    pub lines: Vec<MapDataLineRef>,
-    pub rules: Vec<MapDataRule>,
+    pub lines: PointElements<MapDataLineRef>,
+    pub rules: PointElements<MapDataRule>,
     pub residential_in_proximity: bool,
     pub nogo_area: bool,
+    pub elevation: Option<f32>,
+}
+
+/// Range of the lines or rules of a point, the elements of all points are kept one after another
+/// in a single list on the graph instead of a list per point. The range doesn't know which graph
+/// it belongs to, so the elements are read with `MapDataPointRef::lines` and
+/// `MapDataPointRef::rules` from the routing graph, or with `get` from the list of another graph
+#[derive(Serialize, Deserialize)]
+pub struct PointElements<T> {
+    start: u32,
+    len: u32,
+    #[serde(skip)]
+    _marker: PhantomData<T>,
+}
+
+impl<T> PointElements<T> {
+    /// Adds the element at the index, the elements of a point must be placed next to each other.
+    /// The index is checked to fit in u32 when the elements are added to the graph
+    pub fn place(&mut self, idx: u32) {
+        if self.len == 0 {
+            self.start = idx;
+        }
+        self.len += 1;
+    }
+
+    /// Elements of the point in the graph wide list of the graph the point is in
+    pub fn get<'a>(&self, all: &'a [T]) -> &'a [T] {
+        let start = self.start as usize;
+        &all[start..start + self.len as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<T> Default for PointElements<T> {
+    fn default() -> Self {
+        Self {
+            start: 0,
+            len: 0,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Clone for PointElements<T> {
+    fn clone(&self) -> Self {
+        Self {
+            start: self.start,
+            len: self.len,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Debug for PointElements<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.start, self.start + self.len)
+    }
 }
 
 impl MapDataPoint {
     pub fn distance_between(&self, point: &MapDataPointRef) -> f32 {
-        let self_geo = Point::new(self.lon, self.lat);
-        let point_geo = Point::new(point.borrow().lon, point.borrow().lat);
-        Haversine.distance(self_geo, point_geo)
+        let point = point.borrow();
+        distance_m(self.lat, self.lon, point.lat, point.lon)
     }
     pub fn bearing(&self, point: &MapDataPointRef) -> f32 {
         let self_geo = Point::new(self.lon, self.lat);
@@ -56,14 +120,11 @@ impl Debug for MapDataPoint {
     junction={}
     residential_in_proximity={}
     nogo_area={}
-    rules={:#?}",
+    rules={:?}",
             self.id,
             self.lat,
             self.lon,
-            self.lines
-                .iter()
-                .map(|l| l.borrow().line_id())
-                .collect::<Vec<_>>(),
+            self.lines,
             self.is_junction(),
             self.residential_in_proximity,
             self.nogo_area,
@@ -4,7 +4,7 @@ use std::{
     u16,
 };
 
-use geo::{Contains, Coord, CoordsIter, MultiPolygon, Point};
+use geo::{Contains, Coord, CoordsIter, Distance, Haversine, MultiPolygon, Point};
 use serde::{Deserialize, Serialize};
 use wkt::ToWkt;
 
@@ -58,6 +58,55 @@ pub fn round_to_precision(v: f64, direction: RoundMethod) -> f64 {
     }
 }
 
+/// Mean earth radius, the same the haversine formula of geo uses
+const EARTH_RADIUS_M: f32 = 6_371_008.8;
+
+// 0.05 degrees is up to 5.5km, where the equirectangular approximation differs from haversine by
+// less than the precision of f32
+const FAST_DISTANCE_MAX_DEGREES: f32 = 0.05;
+
+/// Distance in meters between two coordinates. Short distances, which are most of them when
+/// walking the graph, are calculated with the equirectangular approximation, longer ones with
+/// haversine
+pub fn distance_m(lat1: f32, lon1: f32, lat2: f32, lon2: f32) -> f32 {
+    if (lat2 - lat1).abs() < FAST_DISTANCE_MAX_DEGREES
+        && (lon2 - lon1).abs() < FAST_DISTANCE_MAX_DEGREES
+    {
+        return EARTH_RADIUS_M * equirectangular_distance_sq(lat1, lon1, lat2, lon2).sqrt();
+    }
+    Haversine.distance(Point::new(lon1, lat1), Point::new(lon2, lat2))
+}
+
+/// Squared distance in radians on a sphere, without square roots or inverse trigonometry so it
+/// can be vectorized
+#[inline(always)]
+fn equirectangular_distance_sq(lat1: f32, lon1: f32, lat2: f32, lon2: f32) -> f32 {
+    let x = (lon2 - lon1).to_radians() * ((lat1 + lat2) / 2.).to_radians().cos();
+    let y = (lat2 - lat1).to_radians();
+    x * x + y * y
+}
+
+/// Index of the coordinate closest to the lookup point. Latitudes and longitudes are separate
+/// slices of the same length, so the distances are calculated in a loop the compiler can
+/// vectorize
+pub fn closest_index(lat: f32, lon: f32, lats: &[f32], lons: &[f32]) -> Option<usize> {
+    let lon_scale = lat.to_radians().cos();
+    let distances_sq = lats
+        .iter()
+        .zip(lons)
+        .map(|(point_lat, point_lon)| {
+            let x = (point_lon - lon) * lon_scale;
+            let y = point_lat - lat;
+            x * x + y * y
+        })
+        .collect::<Vec<_>>();
+    distances_sq
+        .iter()
+        .enumerate()
+        .min_by(|(_, d1), (_, d2)| d1.total_cmp(d2))
+        .map(|(idx, _)| idx)
+}
+
 #[derive(Debug)]
 pub struct AreaGrid {
     point_grid: PointGrid<MultiPolygon>,
@@ -268,7 +317,37 @@ mod test {
 
     use crate::map_data::graph::MapDataPointRef;
 
-    use super::PointGrid;
+    use super::{closest_index, distance_m, PointGrid};
+
+    #[test]
+    fn distance_fast_path() {
+        use geo::{Distance, Haversine, Point};
+
+        let haversine = |lat1: f32, lon1: f32, lat2: f32, lon2: f32| {
+            Haversine.distance(Point::new(lon1, lat1), Point::new(lon2, lat2))
+        };
+        for (lat1, lon1, lat2, lon2) in [
+            (57.15396, 24.854355, 57.156355, 24.854382),
+            (57.15396, 24.854355, 57.17, 24.89),
+            (57.15396, 24.854355, 57.1539, 24.85435),
+            (-33.9, 18.4, -33.93, 18.44),
+            (69.6, 18.9, 69.64, 18.94),
+            (57.15396, 24.854355, 56.95, 24.1),
+        ] {
+            let expected = haversine(lat1, lon1, lat2, lon2);
+            let distance = distance_m(lat1, lon1, lat2, lon2);
+            assert!(
+                (distance - expected).abs() <= expected * 1e-5 + 1e-3,
+                "{distance} {expected}"
+            );
+        }
+        assert_eq!(distance_m(57.1, 24.8, 57.1, 24.8), 0.);
+
+        let lats = [57.16, 57.1541, 57.15, 57.2];
+        let lons = [24.85, 24.8545, 24.86, 24.8];
+        assert_eq!(closest_index(57.15396, 24.854355, &lats, &lons), Some(1));
+        assert_eq!(closest_index(57.15396, 24.854355, &[], &[]), None);
+    }
 
     rusty_fork_test! {
         #![rusty_fork(timeout_ms = 2000)]
@@ -10,7 +10,11 @@ use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use tracing::info;
 
-use crate::{map_data::graph::MapDataGraphPacked, osm_data::DataSource};
+use crate::{
+    map_data::graph::{extra_tag_keys, MapDataGraphPacked},
+    osm_data::DataSource,
+    timings,
+};
 
 fn read_cache_file(file_folder: &PathBuf, file_name: &str) -> Result<Vec<u8>, MapDataCacheError> {
     let mut file = file_folder.clone();
@@ -56,10 +60,22 @@ enum WriteToCache {
     WithData(CacheMetadata),
 }
 
+// bumped when the layout of the cached graph changes, so caches written by a build with the same
+// version but a different layout are not read. The format version was introduced at 2, caches
+// written before that have no version and read as 0, so they are not read either
+// 1: lines keep the id of their way, for the `avoid_way_ids` rule, never written as a version
+// 2: point lines and rules are kept in graph wide arrays, the first version written to caches
+// 3: ways tagged `motorcycle=no` are left out
+const CACHE_FORMAT_VERSION: u32 = 3;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CacheMetadata {
     pub data_source_hash: String,
     pub router_version: String,
+    #[serde(default)]
+    pub format_version: u32,
+    #[serde(default)]
+    pub extra_tag_keys: Vec<String>,
 }
 
 pub struct MapDataCache {
@@ -92,6 +108,8 @@ impl MapDataCache {
         let new_metadata = CacheMetadata {
             data_source_hash: format!("{hash:x}"),
             router_version: env!("CARGO_PKG_VERSION").to_string(),
+            format_version: CACHE_FORMAT_VERSION,
+            extra_tag_keys: extra_tag_keys().to_vec(),
         };
 
         self.write_to_cache = WriteToCache::WithData(new_metadata.clone());
@@ -107,6 +125,7 @@ impl MapDataCache {
 
     #[tracing::instrument(skip(self))]
     pub fn read_cache(&mut self) -> Result<Option<MapDataGraphPacked>, MapDataCacheError> {
+        let _phase = timings::phase!("cache_read");
         let new_metadata = self.read_input_metadata()?;
 
         let cache_dir = match &self.cache_dir {
@@ -133,6 +152,8 @@ impl MapDataCache {
 
         if new_metadata.router_version != old_metadata.router_version
             || new_metadata.data_source_hash != old_metadata.data_source_hash
+            || new_metadata.format_version != old_metadata.format_version
+            || new_metadata.extra_tag_keys != old_metadata.extra_tag_keys
         {
             return Ok(None);
         }
@@ -173,6 +194,7 @@ impl MapDataCache {
             return Ok(());
         };
 
+        let _phase = timings::phase!("cache_write");
         let write_start = Instant::now();
 
         if let Some(cache_dir) = &self.cache_dir {
@@ -0,0 +1,181 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use rayon::prelude::*;
+use serde::Serialize;
+use tracing::{info, trace};
+
+use crate::{
+    map_data::graph::MapDataGraph,
+    router::{
+        generator::WP_LOOKUP_ALLOWED_HWS,
+        rules::RouterRules,
+        shortest_path::{shortest_paths, PathCost},
+    },
+    timings,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum MatrixError {
+    #[error("Failed to read points file: {error}")]
+    PointsRead { error: csv::Error },
+
+    #[error("Invalid point on line {line}: {message}")]
+    PointInvalid { line: usize, message: String },
+
+    #[error("Could not find point '{name}' on map")]
+    PointNotFound { name: String },
+
+    #[error("Output file format incorrect for '{filename}', expected csv or json")]
+    OutputFormat { filename: PathBuf },
+
+    #[error("Failed to write CSV: {error}")]
+    CsvWrite { error: csv::Error },
+
+    #[error("JSON Serialization error {error}")]
+    SerializeJson { error: serde_json::Error },
+
+    #[error("Failed to write output: {error}")]
+    Write { error: io::Error },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MatrixPoint {
+    pub name: String,
+    pub lat: f32,
+    pub lon: f32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Matrix {
+    pub points: Vec<MatrixPoint>,
+    pub distances_m: Vec<Vec<Option<f64>>>,
+    pub durations_s: Vec<Vec<Option<f64>>>,
+}
+
+impl Matrix {
+    /// Reads points from a CSV file with `lat,lon` or `lat,lon,name` rows. A header row is
+    /// skipped when the first row is not numeric.
+    #[tracing::instrument]
+    pub fn read_points(file: &PathBuf) -> Result<Vec<MatrixPoint>, MatrixError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_path(file)
+            .map_err(|error| MatrixError::PointsRead { error })?;
+
+        let mut points = Vec::new();
+        for (idx, record) in reader.records().enumerate() {
+            let record = record.map_err(|error| MatrixError::PointsRead { error })?;
+            let line = idx + 1;
+            let lat = record.get(0).map(|lat| lat.parse::<f32>());
+            let lon = record.get(1).map(|lon| lon.parse::<f32>());
+            let (lat, lon) = match (lat, lon) {
+                (Some(Ok(lat)), Some(Ok(lon))) => (lat, lon),
+                (Some(Err(_)), _) if idx == 0 => continue,
+                _ => {
+                    return Err(MatrixError::PointInvalid {
+                        line,
+                        message: "expected LAT,LON[,NAME]".to_string(),
+                    })
+                }
+            };
+            let name = record
+                .get(2)
+                .filter(|name| !name.is_empty())
+                .map_or_else(|| format!("{lat},{lon}"), |name| name.to_string());
+            points.push(MatrixPoint { name, lat, lon });
+        }
+        Ok(points)
+    }
+
+    #[tracing::instrument(skip(points, rules))]
+    pub fn calc(points: Vec<MatrixPoint>, rules: &RouterRules) -> Result<Self, MatrixError> {
+        let _phase = timings::phase!("route");
+        let point_refs = points
+            .iter()
+            .map(|point| {
+                MapDataGraph::get()
+                    .get_closest_to_coords(
+                        point.lat,
+                        point.lon,
+                        rules,
+                        false,
+                        Some(&WP_LOOKUP_ALLOWED_HWS),
+                    )
+                    .ok_or_else(|| MatrixError::PointNotFound {
+                        name: point.name.clone(),
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        info!(point_count = point_refs.len(), "Matrix calculation started");
+
+        let costs: Vec<Vec<Option<PathCost>>> = point_refs
+            .par_iter()
+            .map(|from| shortest_paths(from, &point_refs, rules))
+            .collect();
+
+        Ok(Self {
+            points,
+            distances_m: costs
+                .iter()
+                .map(|row| row.iter().map(|c| c.map(|c| c.len_m)).collect())
+                .collect(),
+            durations_s: costs
+                .iter()
+                .map(|row| row.iter().map(|c| c.map(|c| c.travel_time_s)).collect())
+                .collect(),
+        })
+    }
+
+    fn write_csv<W: Write>(&self, writer: W) -> Result<(), MatrixError> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer
+            .write_record(["from", "to", "distance_m", "duration_s"])
+            .map_err(|error| MatrixError::CsvWrite { error })?;
+        for (from_idx, from) in self.points.iter().enumerate() {
+            for (to_idx, to) in self.points.iter().enumerate() {
+                let format_cost =
+                    |cost: Option<f64>| cost.map_or(String::new(), |c| format!("{c:.0}"));
+                writer
+                    .write_record([
+                        from.name.clone(),
+                        to.name.clone(),
+                        format_cost(self.distances_m[from_idx][to_idx]),
+                        format_cost(self.durations_s[from_idx][to_idx]),
+                    ])
+                    .map_err(|error| MatrixError::CsvWrite { error })?;
+            }
+        }
+        writer
+            .flush()
+            .map_err(|error| MatrixError::Write { error })?;
+        Ok(())
+    }
+
+    /// Writes the matrix to a csv or json file, or as csv to stdout when no file is given
+    #[tracing::instrument(skip(self))]
+    pub fn write(&self, output: Option<PathBuf>) -> Result<(), MatrixError> {
+        let _phase = timings::phase!("write");
+        let Some(file) = output else {
+            return self.write_csv(io::stdout());
+        };
+        trace!(file = ?file, "Writing matrix");
+        match file.extension().and_then(|ext| ext.to_str()) {
+            Some("csv") => {
+                self.write_csv(File::create(&file).map_err(|error| MatrixError::Write { error })?)
+            }
+            Some("json") => {
+                let json = serde_json::to_string(self)
+                    .map_err(|error| MatrixError::SerializeJson { error })?;
+                std::fs::write(file, json).map_err(|error| MatrixError::Write { error })
+            }
+            _ => Err(MatrixError::OutputFormat { filename: file }),
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use std::{fs::File, io::BufReader};
+
 use crate::map_data::graph::MapDataGraph;
 
 use super::{json_reader::JsonReader, pbf_reader::PbfReader, DataSource, OsmDataReaderError};
@@ -40,7 +42,9 @@ impl OsmDataReader {
     pub fn read_data(mut self) -> Result<MapDataGraph, OsmDataReaderError> {
         match self.source {
             DataSource::JsonFile { ref file } => {
-                JsonReader::new(&mut self.map_data, file).read()?
+                let f =
+                    File::open(file).map_err(|error| OsmDataReaderError::FileError { error })?;
+                JsonReader::new(&mut self.map_data).read(BufReader::new(f))?
             }
             DataSource::PbfFile { ref file } => {
                 PbfReader::new(&mut self.map_data, file).read()?;
@@ -48,4 +52,11 @@ impl OsmDataReader {
         };
         Ok(self.map_data)
     }
+
+    #[cfg(feature = "wasm")]
+    pub fn read_json_str(json: &str) -> Result<MapDataGraph, OsmDataReaderError> {
+        let mut map_data = MapDataGraph::new();
+        JsonReader::new(&mut map_data).read(json.as_bytes())?;
+        Ok(map_data)
+    }
 }
@@ -131,6 +131,11 @@ impl OsmElement {
                 })?,
                 residential_in_proximity: false,
                 nogo_area: false,
+                elevation: self
+                    .tags
+                    .as_ref()
+                    .and_then(|tags| tags.get("ele"))
+                    .and_then(|ele| OsmNode::parse_elevation(ele)),
             });
         }
 
@@ -919,4 +924,17 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn node_element_elevation() {
+        let node = get_osm_element_node(1, 57.1, 24.8, Some(vec![("ele", "123.5 m")]))
+            .get_node_element()
+            .unwrap();
+        assert_eq!(node.elevation, Some(123.5));
+
+        let node = get_osm_element_node(1, 57.1, 24.8, Some(vec![("ele", "unknown")]))
+            .get_node_element()
+            .unwrap();
+        assert_eq!(node.elevation, None);
+    }
 }
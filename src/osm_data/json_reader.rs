@@ -1,13 +1,9 @@
-use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-    path::PathBuf,
-    time::Instant,
-};
+use std::io::BufRead;
 
 use tracing::{error, trace};
+use web_time::Instant;
 
-use crate::{map_data::graph::MapDataGraph, osm_data::json_parser::OsmJsonParser};
+use crate::{map_data::graph::MapDataGraph, osm_data::json_parser::OsmJsonParser, timings};
 
 use super::{
     json_parser::{OsmElement, OsmElementType},
@@ -16,23 +12,17 @@ use super::{
 
 pub struct JsonReader<'a> {
     map_data: &'a mut MapDataGraph,
-    file_name: &'a PathBuf,
 }
 
 impl<'a> JsonReader<'a> {
-    pub fn new(map_data: &'a mut MapDataGraph, file_name: &'a PathBuf) -> Self {
-        Self {
-            map_data,
-            file_name,
-        }
+    pub fn new(map_data: &'a mut MapDataGraph) -> Self {
+        Self { map_data }
     }
-    pub fn read(mut self) -> Result<(), OsmDataReaderError> {
+    pub fn read<R: BufRead>(mut self, mut reader: R) -> Result<(), OsmDataReaderError> {
         let read_start = Instant::now();
+        let parse_phase = timings::phase!("parse");
         let mut parser_state = OsmJsonParser::new();
 
-        let f =
-            File::open(self.file_name).map_err(|error| OsmDataReaderError::FileError { error })?;
-        let mut reader = BufReader::new(f);
         loop {
             let mut line = String::new();
             let len = reader
@@ -42,11 +32,15 @@ impl<'a> JsonReader<'a> {
                 break;
             }
             let line = line.as_bytes().to_owned();
-            let elements = parser_state
-                .parse_line(line)
-                .map_err(|error| OsmDataReaderError::ParserError { error })?;
+            let elements =
+                parser_state
+                    .parse_line(line)
+                    .map_err(|error| OsmDataReaderError::ParserError {
+                        error: Box::new(error),
+                    })?;
             self.process_elements(elements)?;
         }
+        drop(parse_phase);
 
         self.map_data.generate_point_hashes();
 
@@ -62,18 +56,25 @@ impl<'a> JsonReader<'a> {
         for element in elements {
             match element
                 .get_element_type()
-                .map_err(|error| OsmDataReaderError::ParserError { error })?
-            {
+                .map_err(|error| OsmDataReaderError::ParserError {
+                    error: Box::new(error),
+                })? {
                 OsmElementType::Node => {
-                    let node = element
-                        .get_node_element()
-                        .map_err(|error| OsmDataReaderError::ParserError { error })?;
-                    self.map_data.insert_node(node);
+                    let node = element.get_node_element().map_err(|error| {
+                        OsmDataReaderError::ParserError {
+                            error: Box::new(error),
+                        }
+                    })?;
+                    self.map_data
+                        .insert_node(node)
+                        .map_err(|error| OsmDataReaderError::MapDataError { error })?;
                 }
                 OsmElementType::Way => {
-                    let way = element
-                        .get_way_element()
-                        .map_err(|error| OsmDataReaderError::ParserError { error })?;
+                    let way = element.get_way_element().map_err(|error| {
+                        OsmDataReaderError::ParserError {
+                            error: Box::new(error),
+                        }
+                    })?;
                     let res = self
                         .map_data
                         .insert_way(way)
@@ -83,9 +84,11 @@ impl<'a> JsonReader<'a> {
                     }
                 }
                 OsmElementType::Relation => {
-                    let rel = element
-                        .get_relation_element()
-                        .map_err(|error| OsmDataReaderError::ParserError { error })?;
+                    let rel = element.get_relation_element().map_err(|error| {
+                        OsmDataReaderError::ParserError {
+                            error: Box::new(error),
+                        }
+                    })?;
                     let res = self
                         .map_data
                         .insert_relation(rel)
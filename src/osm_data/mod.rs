@@ -7,12 +7,13 @@ pub mod data_reader;
 pub mod json_parser;
 pub mod json_reader;
 pub mod pbf_area_reader;
+pub mod pbf_estimate;
 pub mod pbf_reader;
 
 #[derive(Debug, thiserror::Error)]
 pub enum OsmDataReaderError {
     #[error("OSM JSON parser error: {error}")]
-    ParserError { error: OsmJsonParserError },
+    ParserError { error: Box<OsmJsonParserError> },
 
     #[error("Map data error: {error}")]
     MapDataError { error: MapDataError },
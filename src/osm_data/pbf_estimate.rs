@@ -0,0 +1,248 @@
+use std::{fs::File, path::Path, time::Instant};
+
+use osmpbfreader::{blobs::result_blob_into_iter, OsmObj, OsmPbfReader};
+use serde::Serialize;
+
+use crate::map_data::{
+    graph::{MapDataLineRef, MapDataPointRef},
+    line::MapDataLine,
+    osm::{OsmNode, OsmWay},
+    point::MapDataPoint,
+};
+
+use super::{pbf_reader::is_routable_way, OsmDataReaderError};
+
+/// Blocks decoded to estimate the contents of the whole file, spread evenly over the file
+const SAMPLE_BLOCKS: usize = 64;
+
+/// Times the reader goes through the file: up to 3 passes for the residential areas, 3 for the
+/// military areas and 2 for the roads and their nodes
+const READ_PASSES: f64 = 8.;
+
+/// Tags and map entry of every object read from the file, on top of the object itself
+const OSM_OBJ_OVERHEAD_BYTES: usize = 64;
+
+/// Tags of a road kept as strings while it is read
+const WAY_TAGS_BYTES: usize = 400;
+
+/// Point in the cache: id, coordinates, 2 lines, no rules, flags and elevation
+const CACHE_POINT_BYTES: usize = 63;
+
+/// Line in the cache: way id, 2 points, direction and tags
+const CACHE_LINE_BYTES: usize = 32;
+
+/// What importing a PBF file takes, extrapolated from a sample of its blocks. The counts of
+/// points and lines assume roads share a single node with the rest of the road network
+#[derive(Debug, Serialize, PartialEq)]
+pub struct PbfEstimate {
+    pub file_size_bytes: u64,
+    pub block_count: usize,
+    pub sampled_block_count: usize,
+    pub node_count: u64,
+    pub way_count: u64,
+    pub relation_count: u64,
+    /// Roads read into the map data
+    pub road_count: u64,
+    pub point_count: u64,
+    pub line_count: u64,
+    pub graph_bytes: u64,
+    pub cache_bytes: u64,
+    /// Memory needed while the file is read, the objects read, the graph and the cache are all
+    /// held at the same time
+    pub peak_memory_bytes: u64,
+    pub read_time_s: f64,
+}
+
+/// Counts of the sampled blocks
+#[derive(Default)]
+struct BlockSample {
+    node_count: u64,
+    way_count: u64,
+    relation_count: u64,
+    road_count: u64,
+    road_node_count: u64,
+}
+
+impl PbfEstimate {
+    /// Reads the blocks of the file without decoding them and decodes [`SAMPLE_BLOCKS`] of them
+    pub fn estimate(file: &Path) -> Result<Self, OsmDataReaderError> {
+        let open =
+            || File::open(file).map_err(|error| OsmDataReaderError::PbfFileOpenError { error });
+        let file_size_bytes = open()?
+            .metadata()
+            .map_err(|error| OsmDataReaderError::FileError { error })?
+            .len();
+
+        let mut pbf = OsmPbfReader::new(open()?);
+        let block_count = pbf.blobs().count();
+        pbf.rewind()
+            .map_err(|error| OsmDataReaderError::PbfFileReadError { error })?;
+
+        let stride = block_count.div_ceil(SAMPLE_BLOCKS).max(1);
+        let mut sample = BlockSample::default();
+        let mut sampled_block_count = 0;
+        let decode_start = Instant::now();
+        for blob in pbf.blobs().step_by(stride) {
+            sampled_block_count += 1;
+            for obj in result_blob_into_iter(blob) {
+                match obj.map_err(|error| OsmDataReaderError::PbfFileReadError { error })? {
+                    OsmObj::Node(_) => sample.node_count += 1,
+                    OsmObj::Relation(_) => sample.relation_count += 1,
+                    obj @ OsmObj::Way(_) => {
+                        sample.way_count += 1;
+                        if is_routable_way(&obj) {
+                            sample.road_count += 1;
+                            sample.road_node_count +=
+                                obj.way().map_or(0, |way| way.nodes.len() as u64);
+                        }
+                    }
+                }
+            }
+        }
+        let decode_s = decode_start.elapsed().as_secs_f64();
+
+        let scale = |count: u64| {
+            (count as f64 * block_count as f64 / sampled_block_count.max(1) as f64) as u64
+        };
+        let road_count = scale(sample.road_count);
+        let line_count = scale(sample.road_node_count).saturating_sub(road_count);
+        let point_count = line_count;
+        let read_time_s =
+            decode_s / sampled_block_count.max(1) as f64 * block_count as f64 * READ_PASSES
+                / rayon::current_num_threads() as f64;
+
+        Ok(Self::from_counts(
+            file_size_bytes,
+            block_count,
+            sampled_block_count,
+            (
+                scale(sample.node_count),
+                scale(sample.way_count),
+                scale(sample.relation_count),
+            ),
+            road_count,
+            point_count,
+            line_count,
+            read_time_s,
+        ))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn from_counts(
+        file_size_bytes: u64,
+        block_count: usize,
+        sampled_block_count: usize,
+        (node_count, way_count, relation_count): (u64, u64, u64),
+        road_count: u64,
+        point_count: u64,
+        line_count: u64,
+        read_time_s: f64,
+    ) -> Self {
+        let (points, lines, roads) = (
+            point_count as usize,
+            line_count as usize,
+            road_count as usize,
+        );
+        // points have 2 lines on average, are in the point grid and in the id lookup while read
+        let graph_bytes = points
+            * (size_of::<MapDataPoint>()
+                + 2 * size_of::<MapDataLineRef>()
+                + size_of::<MapDataPointRef>()
+                + 2 * size_of::<u64>())
+            + lines * size_of::<MapDataLine>();
+        let cache_bytes = points * CACHE_POINT_BYTES + lines * CACHE_LINE_BYTES;
+        let road_refs_bytes = (lines + roads) * size_of::<u64>();
+        let osm_objs_bytes = points * (size_of::<OsmObj>() + OSM_OBJ_OVERHEAD_BYTES)
+            + roads * (size_of::<OsmObj>() + OSM_OBJ_OVERHEAD_BYTES + WAY_TAGS_BYTES)
+            + road_refs_bytes;
+        let osm_elements_bytes = points * size_of::<OsmNode>()
+            + roads * (size_of::<OsmWay>() + WAY_TAGS_BYTES)
+            + road_refs_bytes;
+
+        Self {
+            file_size_bytes,
+            block_count,
+            sampled_block_count,
+            node_count,
+            way_count,
+            relation_count,
+            road_count,
+            point_count,
+            line_count,
+            graph_bytes: graph_bytes as u64,
+            cache_bytes: cache_bytes as u64,
+            peak_memory_bytes: (osm_objs_bytes + osm_elements_bytes + graph_bytes + cache_bytes)
+                as u64,
+            read_time_s,
+        }
+    }
+}
+
+impl PbfEstimate {
+    pub fn to_text(&self) -> String {
+        format!(
+            "File: {} in {} blocks, {} blocks sampled\n\
+             Objects: {} nodes, {} ways, {} relations\n\
+             Map data: {} roads, {} points, {} lines\n\
+             Graph size: {}\n\
+             Cache size: {}\n\
+             Peak memory while reading: {}\n\
+             Read time: {:.0}s",
+            format_bytes(self.file_size_bytes),
+            self.block_count,
+            self.sampled_block_count,
+            self.node_count,
+            self.way_count,
+            self.relation_count,
+            self.road_count,
+            self.point_count,
+            self.line_count,
+            format_bytes(self.graph_bytes),
+            format_bytes(self.cache_bytes),
+            format_bytes(self.peak_memory_bytes),
+            self.read_time_s,
+        )
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const MB: f64 = 1024. * 1024.;
+    if bytes as f64 >= 1024. * MB {
+        format!("{:.1}GB", bytes as f64 / 1024. / MB)
+    } else {
+        format!("{:.1}MB", bytes as f64 / MB)
+    }
+}
+
+/// Memory that can be used without swapping, only known on Linux
+pub fn available_memory_bytes() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemAvailable:"))
+        .and_then(|value| {
+            value
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse::<u64>()
+                .ok()
+        })
+        .map(|kb| kb * 1024)
+}
+
+#[cfg(test)]
+mod test {
+    use super::PbfEstimate;
+
+    #[test]
+    fn pbf_estimate_grows_with_counts() {
+        let small = PbfEstimate::from_counts(1000, 10, 10, (100, 10, 1), 10, 90, 90, 1.);
+        let large = PbfEstimate::from_counts(1000, 10, 10, (100, 10, 1), 20, 180, 180, 1.);
+        assert!(small.graph_bytes > 0);
+        assert!(small.cache_bytes > 0);
+        assert!(small.peak_memory_bytes > small.graph_bytes + small.cache_bytes);
+        assert_eq!(large.cache_bytes, small.cache_bytes * 2);
+        assert_eq!(large.graph_bytes, small.graph_bytes * 2);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::{
     map_data::graph::MapDataGraph,
     osm_data::{data_reader::ALLOWED_HIGHWAY_VALUES, pbf_area_reader::PbfAreaReader},
+    timings,
 };
 use geo::{CoordsIter, Distance, GeodesicArea, Haversine, HaversineClosestPoint, Point};
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
@@ -21,6 +22,21 @@ const THRESHOLD_AREA: f64 = (RESIDENTIAL_PROXIMITY_THRESHOLD_METERS
     * RESIDENTIAL_PART_COVERED;
 const MILITARY_ENTRY_MAX_M: f64 = 100.;
 
+/// Ways read into the map data, the nodes they reference are read with them
+pub fn is_routable_way(obj: &osmpbfreader::OsmObj) -> bool {
+    obj.is_way()
+        && obj.tags().iter().any(|t| {
+            t.0 == "highway"
+                && (ALLOWED_HIGHWAY_VALUES.contains(&t.1.as_str())
+                    || (t.1 == "path"
+                        && obj
+                            .tags()
+                            .iter()
+                            .any(|t2| t2.0 == "motorcycle" && t2.1 == "yes")))
+        })
+        && !obj.tags().contains("motor_vehicle", "destination")
+}
+
 pub struct PbfReader<'a> {
     map_data: &'a mut MapDataGraph,
     file_name: &'a PathBuf,
@@ -42,6 +58,7 @@ impl<'a> PbfReader<'a> {
 
     pub fn read(self) -> Result<(), OsmDataReaderError> {
         let read_start = Instant::now();
+        let parse_phase = timings::phase!("parse");
 
         let r = std::fs::File::open(self.file_name)
             .map_err(|error| OsmDataReaderError::PbfFileOpenError { error })?;
@@ -60,19 +77,7 @@ impl<'a> PbfReader<'a> {
         let military_area_grid = boundary_reader.get_area_grid();
 
         let elements = pbf
-            .get_objs_and_deps(|obj| {
-                obj.is_way()
-                    && obj.tags().iter().any(|t| {
-                        t.0 == "highway"
-                            && (ALLOWED_HIGHWAY_VALUES.contains(&t.1.as_str())
-                                || (t.1 == "path"
-                                    && obj
-                                        .tags()
-                                        .iter()
-                                        .any(|t2| t2.0 == "motorcycle" && t2.1 == "yes")))
-                    })
-                    && !obj.tags().contains("motor_vehicle", "destination")
-            })
+            .get_objs_and_deps(is_routable_way)
             .map_err(|error| OsmDataReaderError::PbfFileReadError { error })?;
 
         elements
@@ -147,6 +152,10 @@ impl<'a> PbfReader<'a> {
                                     }
                                 }),
                             },
+                            elevation: node
+                                .tags
+                                .get("ele")
+                                .and_then(|ele| OsmNode::parse_elevation(ele)),
                         }));
                     } else if element.is_way() {
                         let way = element.way().ok_or(OsmDataReaderError::PbfFileError {
@@ -215,7 +224,10 @@ impl<'a> PbfReader<'a> {
             .into_iter()
             .map(|osm_element| -> Result<(), OsmDataReaderError> {
                 match osm_element {
-                    OsmElement::Node(node) => self.map_data.insert_node(node),
+                    OsmElement::Node(node) => self
+                        .map_data
+                        .insert_node(node)
+                        .map_err(|error| OsmDataReaderError::MapDataError { error })?,
                     OsmElement::Way(way) => self
                         .map_data
                         .insert_way(way)
@@ -228,6 +240,7 @@ impl<'a> PbfReader<'a> {
                 Ok(())
             })
             .collect::<Result<Vec<_>, _>>()?;
+        drop(parse_phase);
 
         self.map_data.generate_point_hashes();
 
@@ -0,0 +1,26 @@
+//! Zones and frame marks for a profiler, recorded with the `profiling-tracy` or `profiling-puffin`
+//! feature and compiled out otherwise
+
+/// Opens a zone that lasts until the end of the enclosing block, the optional text is shown with
+/// the zone, like the name of a weight calc
+macro_rules! zone {
+    ($name:literal) => {
+        #[cfg(feature = "profiling")]
+        profiling::scope!($name);
+    };
+    ($name:literal, $text:expr) => {
+        #[cfg(feature = "profiling")]
+        profiling::scope!($name, $text);
+    };
+}
+
+/// Ends a frame, frames are loading the map data and generating the routes of a request
+macro_rules! frame {
+    () => {
+        #[cfg(feature = "profiling")]
+        profiling::finish_frame!();
+    };
+}
+
+pub(crate) use frame;
+pub(crate) use zone;
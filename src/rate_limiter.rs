@@ -0,0 +1,96 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Length of the window the request limit applies to
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Limits how many route requests each client can send per minute, so one client sending too
+/// many requests can't keep the workers busy for everyone else. Counts the requests of the last
+/// minute, so a client can send again as soon as its oldest request is a minute old
+pub struct RateLimiter {
+    requests_per_minute: usize,
+    /// Times of the requests of the last minute for every client, oldest first
+    clients: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_minute: usize) -> Self {
+        Self {
+            requests_per_minute,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts `requests` for the client, a batch counts as all of its requests. When that would
+    /// go over the limit nothing is counted and the time until the client can send them again is
+    /// returned
+    pub fn check(&self, client: &str, requests: usize) -> Result<(), Duration> {
+        self.check_at(client, requests, Instant::now())
+    }
+
+    fn check_at(&self, client: &str, requests: usize, now: Instant) -> Result<(), Duration> {
+        let mut clients = self
+            .clients
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        clients.retain(|_, times| {
+            while times
+                .front()
+                .is_some_and(|time| now.duration_since(*time) >= WINDOW)
+            {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+
+        let times = clients.entry(client.to_string()).or_default();
+        if requests > self.requests_per_minute {
+            return Err(WINDOW);
+        }
+        let allowed = self.requests_per_minute - requests;
+        if times.len() > allowed {
+            // the request that has to leave the window before there is room for these
+            let blocking = times[times.len() - allowed - 1];
+            return Err(WINDOW.saturating_sub(now.duration_since(blocking)));
+        }
+        times.extend(std::iter::repeat_n(now, requests));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::RateLimiter;
+
+    #[test]
+    fn rate_limiter_per_client() {
+        let limiter = RateLimiter::new(3);
+        let start = Instant::now();
+
+        assert!(limiter.check_at("a", 2, start).is_ok());
+        assert!(limiter
+            .check_at("a", 1, start + Duration::from_secs(10))
+            .is_ok());
+        assert_eq!(
+            limiter.check_at("a", 1, start + Duration::from_secs(20)),
+            Err(Duration::from_secs(40))
+        );
+        assert!(limiter
+            .check_at("b", 3, start + Duration::from_secs(20))
+            .is_ok());
+
+        // the first two requests are out of the window, the one at 10s is still in it
+        assert!(limiter
+            .check_at("a", 2, start + Duration::from_secs(60))
+            .is_ok());
+        assert_eq!(
+            limiter.check_at("a", 1, start + Duration::from_secs(61)),
+            Err(Duration::from_secs(9))
+        );
+    }
+}
@@ -0,0 +1,372 @@
+use std::{
+    io::{BufRead, IsTerminal, Write},
+    path::PathBuf,
+    time::Instant,
+};
+
+use clap::{Parser, Subcommand};
+use serde_json::Value;
+
+use crate::{
+    ipc_handler::{IpcHandler, OutputDetail, ResponseMessage, RouteMessage, RouterResult},
+    result_writer::{DataDestination, OutputOptions, ResultWriter},
+    router::{progress::GenerationProgress, rule_conditions::apply_conditions, rules},
+    router_runner::RoutingMode,
+    units::Units,
+    worker_pool::Priority,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReplError {
+    #[error("Failed to read command: {error}")]
+    ReadLine { error: std::io::Error },
+}
+
+/// Commands typed into the REPL, one per line
+#[derive(Parser)]
+#[command(name = "repl", no_binary_name = true, disable_version_flag = true)]
+struct ReplLine {
+    #[command(subcommand)]
+    command: ReplCommand,
+}
+
+#[derive(Subcommand)]
+enum ReplCommand {
+    #[command(flatten)]
+    Route(RoutingMode),
+    /// Send the last routing request again, with the current rule overrides, seed and time limit
+    Rerun,
+    /// Override a rule, given as a dot separated path like `basic.prefer_same_road.priority` and
+    /// a JSON value. Values that are not valid JSON are used as strings
+    Set {
+        path: String,
+
+        #[arg(required = true, allow_hyphen_values = true, trailing_var_arg = true)]
+        value: Vec<String>,
+    },
+    /// Remove a rule override, including the overrides within it
+    Unset { path: String },
+    /// Replace the rule overrides with the partial rules of a JSON, TOML or YAML file
+    Load { file: PathBuf },
+    /// Remove all rule overrides, the rules the server was started with are used
+    Clear,
+    /// Print the rule overrides sent with the requests
+    Overrides,
+    /// Seed for the next requests, cleared when not given
+    Seed { seed: Option<u64> },
+    /// Time limit in seconds for the next requests, cleared when not given
+    Timeout { seconds: Option<u64> },
+    /// Print the stats of a route of the last response, by its number in the table
+    Show { number: usize },
+    /// Write the last response to a json, gpx, kml, fit, html, png, svg, csv or tsv file
+    Save { output: DataDestination },
+    /// Leave the REPL
+    #[command(alias = "exit")]
+    Quit,
+}
+
+/// Sends routing requests to a running server one after the other, keeping the rule overrides,
+/// seed and time limit between them, and prints a short table of the routes of every response
+pub struct Repl<'a> {
+    ipc: IpcHandler<'a>,
+    units: Units,
+    on_progress: Option<fn(GenerationProgress)>,
+    rule_overrides: Value,
+    seed: Option<u64>,
+    timeout_s: Option<u64>,
+    last_routing_mode: Option<RoutingMode>,
+    last_response: Option<ResponseMessage>,
+    request_count: usize,
+}
+
+impl<'a> Repl<'a> {
+    pub fn new(
+        ipc: IpcHandler<'a>,
+        rule_overrides: Option<Value>,
+        units: Option<Units>,
+        on_progress: Option<fn(GenerationProgress)>,
+    ) -> Self {
+        Self {
+            ipc,
+            units: units.unwrap_or_default(),
+            on_progress,
+            rule_overrides: rule_overrides.unwrap_or_else(|| Value::Object(Default::default())),
+            seed: None,
+            timeout_s: None,
+            last_routing_mode: None,
+            last_response: None,
+            request_count: 0,
+        }
+    }
+
+    /// Reads commands from stdin until `quit` or the end of input. Failed commands print their
+    /// error and the REPL goes on
+    pub fn run(mut self) -> Result<(), ReplError> {
+        let stdin = std::io::stdin();
+        let interactive = stdin.is_terminal();
+        if interactive {
+            println!("Type `help` for the commands, `quit` to leave");
+        }
+        let mut lines = stdin.lock().lines();
+        loop {
+            if interactive {
+                print!("ridi> ");
+                let _ = std::io::stdout().flush();
+            }
+            let Some(line) = lines.next() else {
+                return Ok(());
+            };
+            let line = line.map_err(|error| ReplError::ReadLine { error })?;
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            if words.is_empty() {
+                continue;
+            }
+            let command = match ReplLine::try_parse_from(words) {
+                Ok(repl_line) => repl_line.command,
+                Err(error) => {
+                    let _ = error.print();
+                    continue;
+                }
+            };
+            if matches!(command, ReplCommand::Quit) {
+                return Ok(());
+            }
+            if let Err(error) = self.handle(command) {
+                println!("Error: {error}");
+            }
+        }
+    }
+
+    fn handle(&mut self, command: ReplCommand) -> anyhow::Result<()> {
+        match command {
+            ReplCommand::Route(routing_mode) => self.send(routing_mode)?,
+            ReplCommand::Rerun => {
+                let routing_mode = self
+                    .last_routing_mode
+                    .clone()
+                    .ok_or_else(|| anyhow::anyhow!("No request to run again yet"))?;
+                self.send(routing_mode)?;
+            }
+            ReplCommand::Set { path, value } => {
+                let value = value.join(" ");
+                let value = serde_json::from_str(&value).unwrap_or(Value::String(value));
+                set_path(&mut self.rule_overrides, &path, value);
+                self.print_overrides()?;
+            }
+            ReplCommand::Unset { path } => {
+                unset_path(&mut self.rule_overrides, &path);
+                self.print_overrides()?;
+            }
+            ReplCommand::Load { file } => {
+                self.rule_overrides = rules::read_rules_file(&file)?;
+                self.print_overrides()?;
+            }
+            ReplCommand::Clear => {
+                self.rule_overrides = Value::Object(Default::default());
+                self.print_overrides()?;
+            }
+            ReplCommand::Overrides => self.print_overrides()?,
+            ReplCommand::Seed { seed } => self.seed = seed,
+            ReplCommand::Timeout { seconds } => self.timeout_s = seconds,
+            ReplCommand::Show { number } => {
+                let route = self
+                    .last_routes()
+                    .and_then(|routes| routes.get(number.wrapping_sub(1)))
+                    .ok_or_else(|| anyhow::anyhow!("No route {number} in the last response"))?;
+                let mut stats = serde_json::to_value(&route.stats)?;
+                // the points of the route are no use in a terminal
+                if let Some(stats) = stats.as_object_mut() {
+                    stats.remove("approximated_route");
+                    stats.remove("elevation_profile");
+                }
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+            }
+            ReplCommand::Save { output } => {
+                let response = self
+                    .last_response
+                    .clone()
+                    .ok_or_else(|| anyhow::anyhow!("No response to save yet"))?;
+                let options = OutputOptions {
+                    units: Some(self.units),
+                    ..OutputOptions::default()
+                };
+                ResultWriter::write(output, &options, response)?;
+            }
+            ReplCommand::Quit => {}
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, routing_mode: RoutingMode) -> anyhow::Result<()> {
+        self.request_count += 1;
+        let id = format!("repl-{}", self.request_count);
+        let rule_overrides = match self.rule_overrides.as_object() {
+            Some(overrides) if overrides.is_empty() => None,
+            _ => {
+                let mut rule_overrides = self.rule_overrides.clone();
+                apply_conditions(
+                    &mut rule_overrides,
+                    Some(&routing_mode.rule_condition_trip(None)),
+                )?;
+                Some(rule_overrides)
+            }
+        };
+        let on_progress = self.on_progress;
+        let on_progress = on_progress
+            .as_ref()
+            .map(|on_progress| on_progress as &dyn Fn(GenerationProgress));
+
+        let start = Instant::now();
+        let response = self.ipc.connect(
+            &routing_mode,
+            None,
+            rule_overrides,
+            None,
+            Some(id),
+            self.timeout_s,
+            self.seed,
+            OutputDetail::Summary,
+            Priority::Interactive,
+            on_progress,
+        );
+        if on_progress.is_some() {
+            eprintln!();
+        }
+        let response = response?;
+        print!(
+            "{}",
+            format_response(&response, start.elapsed().as_secs_f64(), self.units)
+        );
+
+        self.last_routing_mode = Some(routing_mode);
+        self.last_response = Some(response);
+        Ok(())
+    }
+
+    fn last_routes(&self) -> Option<&Vec<RouteMessage>> {
+        match &self.last_response.as_ref()?.result {
+            RouterResult::Ok { routes, .. } => Some(routes),
+            _ => None,
+        }
+    }
+
+    fn print_overrides(&self) -> anyhow::Result<()> {
+        println!("{}", serde_json::to_string_pretty(&self.rule_overrides)?);
+        Ok(())
+    }
+}
+
+/// Sets the value at a dot separated path, creating the objects on the way
+fn set_path(root: &mut Value, path: &str, value: Value) {
+    let mut current = root;
+    for key in path.split('.') {
+        if !current.is_object() {
+            *current = Value::Object(Default::default());
+        }
+        current = current
+            .as_object_mut()
+            .expect("replaced with an object above")
+            .entry(key)
+            .or_insert(Value::Null);
+    }
+    *current = value;
+}
+
+/// Removes the value at a dot separated path, and the objects left empty by it
+fn unset_path(root: &mut Value, path: &str) {
+    let Some(object) = root.as_object_mut() else {
+        return;
+    };
+    match path.split_once('.') {
+        None => {
+            object.remove(path);
+        }
+        Some((key, rest)) => {
+            if let Some(child) = object.get_mut(key) {
+                unset_path(child, rest);
+                if child.as_object().is_some_and(|child| child.is_empty()) {
+                    object.remove(key);
+                }
+            }
+        }
+    }
+}
+
+/// A line about the response and a row per route with the stats that tell routes apart at a
+/// glance: distance, travel time, score, cluster, junctions, curviness and ascent
+fn format_response(response: &ResponseMessage, elapsed_s: f64, units: Units) -> String {
+    let (routes, summary) = match &response.result {
+        RouterResult::Ok { routes, summary } => (routes, summary),
+        RouterResult::Error { message } => return format!("{}: error: {message}\n", response.id),
+        RouterResult::Busy { message } => return format!("{}: busy: {message}\n", response.id),
+        RouterResult::RateLimited { retry_after_s } => {
+            return format!(
+                "{}: rate limited, retry after {retry_after_s}s\n",
+                response.id
+            )
+        }
+        other => return format!("{}: unexpected result {other:?}\n", response.id),
+    };
+    let mut out = format!(
+        "{}: {} routes in {elapsed_s:.1}s",
+        response.id,
+        routes.len()
+    );
+    if let Some(summary) = summary {
+        out.push_str(&format!(
+            ", {} of {} itineraries finished, {} steps",
+            summary.itineraries_finished, summary.itineraries_attempted, summary.steps
+        ));
+    }
+    out.push('\n');
+    if routes.is_empty() {
+        return out;
+    }
+    out.push_str(&format!(
+        "{:>3} {:>10} {:>8} {:>8} {:>7} {:>9} {:>9} {:>8}\n",
+        "#", "distance", "time", "score", "cluster", "junctions", "curviness", "ascent"
+    ));
+    for (idx, route) in routes.iter().enumerate() {
+        let stats = &route.stats;
+        let travel_time_min = (stats.travel_time_s / 60.).round() as u64;
+        let cluster = stats.cluster.map_or("-".to_string(), |cluster| {
+            format!("{cluster}{}", if stats.cluster_best { "*" } else { "" })
+        });
+        out.push_str(&format!(
+            "{:>3} {:>10} {:>8} {:>8.2} {:>7} {:>9} {:>9.0} {:>8}{}\n",
+            idx + 1,
+            units.distance(stats.len_m, 1),
+            format!("{}:{:02}", travel_time_min / 60, travel_time_min % 60),
+            stats.score,
+            cluster,
+            stats.junction_count,
+            units.per_distance(stats.curviness),
+            units.elevation(stats.ascent_m),
+            if stats.partial { " partial" } else { "" }
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{set_path, unset_path};
+
+    #[test]
+    fn repl_set_unset_path() {
+        let mut overrides = json!({});
+        set_path(&mut overrides, "basic.prefer_same_road.priority", json!(80));
+        set_path(&mut overrides, "basic.step_limit", json!(1000));
+        assert_eq!(
+            overrides,
+            json!({"basic": {"prefer_same_road": {"priority": 80}, "step_limit": 1000}})
+        );
+
+        unset_path(&mut overrides, "basic.prefer_same_road.priority");
+        assert_eq!(overrides, json!({"basic": {"step_limit": 1000}}));
+        unset_path(&mut overrides, "basic.step_limit");
+        assert_eq!(overrides, json!({}));
+    }
+}
@@ -0,0 +1,207 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+use tracing::warn;
+
+use crate::ipc_handler::{RequestStatus, ResponseMessage, RouterResult};
+
+#[derive(Debug, thiserror::Error)]
+pub enum RequestHistoryError {
+    #[error("Failed to open request history log {path:?}: {error}")]
+    LogOpen {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+}
+
+struct FinishedRequest {
+    duration_ms: u64,
+    summary: String,
+    response: ResponseMessage,
+}
+
+struct HistoryState {
+    /// Requests being handled by id, several requests can use the same id
+    in_progress: HashMap<String, usize>,
+    /// Most recently finished requests, oldest first
+    finished: VecDeque<FinishedRequest>,
+}
+
+/// Line of the request history log
+#[derive(Serialize)]
+struct HistoryLogLine<'a> {
+    finished_at_ms: u128,
+    id: &'a str,
+    /// Hash of the routing mode, rules and generation options, same as the response cache key
+    params_hash: Option<String>,
+    duration_ms: u64,
+    summary: &'a str,
+}
+
+/// Remembers the requests the server is handling and the responses of the most recently finished
+/// ones, so a client that lost its connection can still get the result with a status message.
+/// Every finished request is also appended to the log file, when there is one
+pub struct RequestHistory {
+    size: usize,
+    state: Mutex<HistoryState>,
+    log: Option<Mutex<File>>,
+}
+
+impl RequestHistory {
+    pub fn new(size: usize, log_file: Option<PathBuf>) -> Result<Self, RequestHistoryError> {
+        let log = log_file
+            .map(|path| {
+                File::options()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .map_err(|error| RequestHistoryError::LogOpen { path, error })
+            })
+            .transpose()?
+            .map(Mutex::new);
+        Ok(Self {
+            size,
+            state: Mutex::new(HistoryState {
+                in_progress: HashMap::new(),
+                finished: VecDeque::new(),
+            }),
+            log,
+        })
+    }
+
+    pub fn start(&self, id: &str) {
+        let mut state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+        *state.in_progress.entry(id.to_string()).or_default() += 1;
+    }
+
+    pub fn finish(&self, params_hash: Option<u64>, duration: Duration, response: &ResponseMessage) {
+        let summary = summary(&response.result);
+        let duration_ms = duration.as_millis() as u64;
+        self.write_log(&HistoryLogLine {
+            finished_at_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis(),
+            id: &response.id,
+            params_hash: params_hash.map(|params_hash| format!("{params_hash:016x}")),
+            duration_ms,
+            summary: &summary,
+        });
+
+        let mut state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+        if let Some(count) = state.in_progress.get_mut(&response.id) {
+            *count -= 1;
+            if *count == 0 {
+                state.in_progress.remove(&response.id);
+            }
+        }
+        if self.size == 0 {
+            return;
+        }
+        state.finished.push_back(FinishedRequest {
+            duration_ms,
+            summary,
+            response: response.clone(),
+        });
+        if state.finished.len() > self.size {
+            state.finished.pop_front();
+        }
+    }
+
+    /// Requests still in progress come first, then the most recently finished one with the id
+    pub fn status(&self, id: &str) -> RequestStatus {
+        let state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+        if state.in_progress.contains_key(id) {
+            return RequestStatus::InProgress;
+        }
+        state
+            .finished
+            .iter()
+            .rev()
+            .find(|finished| finished.response.id == id)
+            .map_or(RequestStatus::Unknown, |finished| RequestStatus::Finished {
+                duration_ms: finished.duration_ms,
+                summary: finished.summary.clone(),
+                response: Box::new(finished.response.clone()),
+            })
+    }
+
+    fn write_log(&self, line: &HistoryLogLine) {
+        let Some(log) = &self.log else {
+            return;
+        };
+        let result = serde_json::to_string(line)
+            .map_err(std::io::Error::from)
+            .and_then(|line| {
+                writeln!(
+                    log.lock().unwrap_or_else(|error| error.into_inner()),
+                    "{line}"
+                )
+            });
+        if let Err(error) = result {
+            warn!(error = ?error, "Failed to write request history log");
+        }
+    }
+}
+
+fn summary(result: &RouterResult) -> String {
+    match result {
+        RouterResult::Ok { routes, .. } => format!("{} routes", routes.len()),
+        RouterResult::Error { message } => format!("error: {message}"),
+        RouterResult::Busy { message } => format!("busy: {message}"),
+        RouterResult::RateLimited { retry_after_s } => {
+            format!("rate limited, retry after {retry_after_s} seconds")
+        }
+        _ => String::from("no routes"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::ipc_handler::{RequestStatus, ResponseMessage, RouterResult};
+
+    use super::RequestHistory;
+
+    fn response(id: &str) -> ResponseMessage {
+        ResponseMessage {
+            id: id.to_string(),
+            result: RouterResult::Ok {
+                routes: Vec::new(),
+                summary: None,
+            },
+        }
+    }
+
+    #[test]
+    fn request_history_status() {
+        let history = RequestHistory::new(2, None).unwrap();
+        assert!(matches!(history.status("a"), RequestStatus::Unknown));
+
+        history.start("a");
+        assert!(matches!(history.status("a"), RequestStatus::InProgress));
+        history.finish(None, Duration::from_millis(5), &response("a"));
+        assert!(matches!(
+            history.status("a"),
+            RequestStatus::Finished { duration_ms: 5, ref summary, .. } if summary == "0 routes"
+        ));
+
+        for id in ["b", "c"] {
+            history.start(id);
+            history.finish(None, Duration::ZERO, &response(id));
+        }
+        assert!(matches!(history.status("a"), RequestStatus::Unknown));
+        assert!(matches!(
+            history.status("c"),
+            RequestStatus::Finished { .. }
+        ));
+    }
+}
@@ -0,0 +1,147 @@
+use geo::{Distance, Haversine, Point};
+use tracing::info;
+
+use crate::{
+    map_data::graph::MapDataGraph,
+    router::{
+        generator::RouteWithStats, route::Route, rules::RouterRules, shortest_path::shortest_route,
+    },
+    track_matcher::{TrackMatcher, TrackMatcherError},
+};
+
+/// How far ahead of the closest point of the previous route to rejoin it, so that the corrected
+/// route does not lead back to where the rider left the plan
+const REJOIN_AHEAD_M: f64 = 1000.;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RerouterError {
+    #[error("Previous route has no points")]
+    EmptyRoute,
+
+    #[error("Could not find {point} on map")]
+    PointNotFound { point: String },
+
+    #[error("No connection found from the current position to the previous route")]
+    NoConnection,
+
+    #[error("Failed to match the rest of the previous route: {error}")]
+    TrackMatch { error: TrackMatcherError },
+}
+
+pub struct Rerouter;
+
+impl Rerouter {
+    /// Creates a route from the current position that rejoins the previous route ahead and then
+    /// follows it to the finish. Only the connection is searched, the rest of the previous route
+    /// is matched back to the map as is.
+    #[tracing::instrument(skip(previous_route, rules))]
+    pub fn reroute(
+        current: (f32, f32),
+        previous_route: &[(f32, f32)],
+        rules: &RouterRules,
+    ) -> Result<RouteWithStats, RerouterError> {
+        let geo = |(lat, lon): (f32, f32)| Point::new(lon as f64, lat as f64);
+        let current_geo = geo(current);
+
+        let closest_idx = previous_route
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                Haversine
+                    .distance(current_geo, geo(**a))
+                    .total_cmp(&Haversine.distance(current_geo, geo(**b)))
+            })
+            .map(|(idx, _)| idx)
+            .ok_or(RerouterError::EmptyRoute)?;
+
+        let mut rejoin_idx = closest_idx;
+        let mut ahead_m = 0.;
+        while ahead_m < REJOIN_AHEAD_M && rejoin_idx + 1 < previous_route.len() {
+            ahead_m += Haversine.distance(
+                geo(previous_route[rejoin_idx]),
+                geo(previous_route[rejoin_idx + 1]),
+            );
+            rejoin_idx += 1;
+        }
+
+        let start = MapDataGraph::get()
+            .get_closest_to_coords(current.0, current.1, rules, false, None)
+            .ok_or(RerouterError::PointNotFound {
+                point: "Current position".to_string(),
+            })?;
+        // snapped the same way as in track matching, so the connection ends where the rest of
+        // the previous route starts
+        let (rejoin_lat, rejoin_lon) = previous_route[rejoin_idx];
+        let rejoin = MapDataGraph::get()
+            .get_closest_to_coords(rejoin_lat, rejoin_lon, rules, false, None)
+            .ok_or(RerouterError::PointNotFound {
+                point: "Rejoin point".to_string(),
+            })?;
+
+        let mut segments =
+            shortest_route(&start, &rejoin, rules).ok_or(RerouterError::NoConnection)?;
+
+        info!(
+            closest_idx,
+            rejoin_idx,
+            connection_segments = segments.len(),
+            "Rejoining previous route"
+        );
+
+        if rejoin_idx + 1 < previous_route.len() {
+            let rest = TrackMatcher::match_track(&previous_route[rejoin_idx..], rules)
+                .map_err(|error| RerouterError::TrackMatch { error })?;
+            segments.extend(rest.route);
+        }
+
+        let route = Route::from(segments);
+        Ok(RouteWithStats {
+            stats: route.calc_stats(rules),
+            route,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusty_fork::rusty_fork_test;
+
+    use crate::{
+        router::rules::RouterRules,
+        test_utils::{graph_from_test_dataset, set_graph_static, test_dataset_1},
+    };
+
+    use super::{Rerouter, RerouterError};
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn reroute_from_current_position() {
+            let mut map_data = graph_from_test_dataset(test_dataset_1());
+            map_data.generate_point_hashes();
+            set_graph_static(map_data);
+            let rules = RouterRules::default();
+
+            // previous route 9-8-4-3-2-1, the rider is at 7, closest to 8 of the previous route,
+            // so the route rejoins it at the next point 4 and follows it from there
+            let previous_route = [(9., 9.), (8., 8.), (4., 4.), (3., 3.), (2., 2.), (1., 1.)];
+            let rerouted = Rerouter::reroute((7., 7.), &previous_route, &rules).unwrap();
+            let point_ids = rerouted
+                .route
+                .iter()
+                .map(|segment| segment.get_end_point().borrow().id)
+                .collect::<Vec<_>>();
+            assert_eq!(point_ids, vec![6, 3, 4, 3, 2, 1]);
+            assert!(rerouted.stats.len_m > 0.);
+
+            assert!(matches!(
+                Rerouter::reroute((7., 7.), &[], &rules),
+                Err(RerouterError::EmptyRoute)
+            ));
+            assert!(matches!(
+                Rerouter::reroute((7., 7.), &[(11., 11.), (12., 12.)], &rules),
+                Err(RerouterError::NoConnection)
+            ));
+        }
+    }
+}
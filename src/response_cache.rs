@@ -0,0 +1,154 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+    sync::Mutex,
+};
+
+use serde_json::Value;
+
+use crate::{
+    ipc_handler::{RequestMessage, RouteMessage},
+    router::rules::RouterRules,
+};
+
+/// Decimal places coordinates are rounded to for the cache key, about a meter
+const COORD_DECIMALS: i32 = 5;
+
+struct CacheState {
+    routes: HashMap<u64, Vec<RouteMessage>>,
+    /// Keys from the least to the most recently used
+    order: VecDeque<u64>,
+}
+
+/// Keeps the routes of the most recent successful requests, so a request identical to one of
+/// them is answered right away instead of generating the routes again. Planning UIs tend to send
+/// the same request again after small changes elsewhere
+pub struct ResponseCache {
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+impl ResponseCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(CacheState {
+                routes: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Requests with the same routing mode, with coordinates rounded to about a meter, the same
+    /// rules and the same generation options get the same key. The id and the progress options
+    /// don't change the routes, so they are not part of it. `rules` are the rules the request is
+    /// generated with, after merging in its rule overrides
+    pub fn key(request_message: &RequestMessage, rules: &RouterRules) -> u64 {
+        let mut routing_mode =
+            serde_json::to_value(&request_message.routing_mode).unwrap_or(Value::Null);
+        round_coords(&mut routing_mode);
+        // converting to a value first sorts the keys of the maps in the rules
+        let rules = serde_json::to_value(rules).unwrap_or(Value::Null);
+
+        let mut hasher = DefaultHasher::new();
+        routing_mode.to_string().hash(&mut hasher);
+        rules.to_string().hash(&mut hasher);
+        request_message.timeout_s.hash(&mut hasher);
+        request_message.seed.hash(&mut hasher);
+        format!("{:?}", request_message.output_detail).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub fn get(&self, key: u64) -> Option<Vec<RouteMessage>> {
+        let mut state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+        let routes = state.routes.get(&key).cloned()?;
+        state.order.retain(|k| *k != key);
+        state.order.push_back(key);
+        Some(routes)
+    }
+
+    pub fn insert(&self, key: u64, routes: Vec<RouteMessage>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+        if state.routes.insert(key, routes).is_some() {
+            state.order.retain(|k| *k != key);
+        }
+        state.order.push_back(key);
+        while state.order.len() > self.capacity {
+            if let Some(oldest) = state.order.pop_front() {
+                state.routes.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// Rounds the `lat` and `lon` fields of every object in the value
+fn round_coords(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, field) in map.iter_mut() {
+                match field {
+                    Value::Number(number) if key == "lat" || key == "lon" => {
+                        if let Some(rounded) = number
+                            .as_f64()
+                            .map(|n| {
+                                let factor = 10f64.powi(COORD_DECIMALS);
+                                (n * factor).round() / factor
+                            })
+                            .and_then(serde_json::Number::from_f64)
+                        {
+                            *number = rounded;
+                        }
+                    }
+                    _ => round_coords(field),
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(round_coords),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ipc_handler::RequestMessage, router::rules::RouterRules};
+
+    use super::ResponseCache;
+
+    fn request(id: &str, lat: f32) -> RequestMessage {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "routing_mode": {
+                "StartFinish": {
+                    "start": { "lat": lat, "lon": 24.0 },
+                    "finish": { "lat": 57.1, "lon": 24.1 }
+                }
+            },
+            "rules": {}
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn response_cache_key() {
+        let rules = RouterRules::default();
+        let key = ResponseCache::key(&request("a", 57.0), &rules);
+        assert_eq!(key, ResponseCache::key(&request("b", 57.000001), &rules));
+        assert_ne!(key, ResponseCache::key(&request("a", 57.001), &rules));
+    }
+
+    #[test]
+    fn response_cache_evicts_least_recently_used() {
+        let cache = ResponseCache::new(2);
+        cache.insert(1, Vec::new());
+        cache.insert(2, Vec::new());
+        assert!(cache.get(1).is_some());
+        cache.insert(3, Vec::new());
+
+        assert!(cache.get(1).is_some());
+        assert!(cache.get(2).is_none());
+        assert!(cache.get(3).is_some());
+    }
+}
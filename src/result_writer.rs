@@ -1,13 +1,24 @@
 use std::{
+    collections::HashSet,
+    fs::File,
     io::{self, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
+use clap::Args;
+use geo::{Coord, LineString, SimplifyIdx};
 use tracing::{info, trace};
 
 use crate::{
-    gpx_writer::{GpxWriter, GpxWriterError},
-    ipc_handler::ResponseMessage,
+    fit_writer::{FitWriter, FitWriterError},
+    gpx_writer::{GpxOptions, GpxWriter, GpxWriterError},
+    html_writer::{HtmlWriter, HtmlWriterError},
+    image_writer::{ImageWriter, ImageWriterError, RenderOptions},
+    ipc_handler::{OutputDetail, RequestStatus, ResponseMessage, RouteMessage, RouterResult},
+    kml_writer::{KmlWriter, KmlWriterError},
+    stats_writer::{StatsWriter, StatsWriterError},
+    timings,
+    units::{Units, M_IN_MI},
 };
 
 #[derive(Debug, thiserror::Error)]
@@ -18,28 +29,517 @@ pub enum ResultWriterError {
     #[error("GPX writing failed: {error}")]
     Gpx { error: GpxWriterError },
 
+    #[error("KML writing failed: {error}")]
+    Kml { error: KmlWriterError },
+
+    #[error("FIT writing failed: {error}")]
+    Fit { error: FitWriterError },
+
+    #[error("HTML writing failed: {error}")]
+    Html { error: HtmlWriterError },
+
+    #[error("Image writing failed: {error}")]
+    Image { error: ImageWriterError },
+
+    #[error("Stats writing failed: {error}")]
+    Stats { error: StatsWriterError },
+
     #[error("Failed to generate routes: {error}")]
     RoutesGenerationFailed { error: String },
 
+    #[error("Failed to generate routes for batch requests: {}", errors.join("; "))]
+    BatchRequestsFailed { errors: Vec<String> },
+
     #[error("Failed to write to stdout: {error}")]
     Stdout { error: io::Error },
 
     #[error("Failed to write to file: {error}")]
     FileWrite { error: io::Error },
+
+    #[error("Output file {file:?} exists, pass --force to overwrite it or --auto-increment to write to a free name")]
+    OutputExists { file: PathBuf },
+
+    #[error("Unknown placeholder {{{placeholder}}} in output file name, expected one of {FILE_NAME_PLACEHOLDERS:?}")]
+    UnknownFileNamePlaceholder { placeholder: String },
+
+    #[error("More than one route would be written to {file:?}, add {{index}} or {{rank}} to the output file name")]
+    DuplicateFileName { file: PathBuf },
 }
 
+/// Placeholders that make every route get written to its own file, named by its values
+const FILE_NAME_PLACEHOLDERS: [&str; 5] = ["index", "rank", "score", "len_km", "cluster"];
+
 #[derive(Debug, Clone)]
 pub enum DataDestination {
     Stdout,
-    Gpx { file: PathBuf },
-    Json { file: PathBuf },
+    Gpx {
+        file: PathBuf,
+        options: GpxOptions,
+    },
+    Kml {
+        file: PathBuf,
+    },
+    Fit {
+        file: PathBuf,
+    },
+    Html {
+        file: PathBuf,
+    },
+    Image {
+        file: PathBuf,
+        options: RenderOptions,
+    },
+    Stats {
+        file: PathBuf,
+    },
+    Json {
+        file: PathBuf,
+    },
+}
+
+impl DataDestination {
+    fn get_file(&self) -> Option<&PathBuf> {
+        match self {
+            DataDestination::Stdout => None,
+            DataDestination::Gpx { file, .. }
+            | DataDestination::Kml { file }
+            | DataDestination::Fit { file }
+            | DataDestination::Html { file }
+            | DataDestination::Image { file, .. }
+            | DataDestination::Stats { file }
+            | DataDestination::Json { file } => Some(file),
+        }
+    }
+
+    fn with_file(&self, file: PathBuf) -> Self {
+        match self {
+            DataDestination::Stdout => DataDestination::Stdout,
+            DataDestination::Gpx { options, .. } => DataDestination::Gpx {
+                file,
+                options: options.clone(),
+            },
+            DataDestination::Kml { .. } => DataDestination::Kml { file },
+            DataDestination::Fit { .. } => DataDestination::Fit { file },
+            DataDestination::Html { .. } => DataDestination::Html { file },
+            DataDestination::Image { options, .. } => DataDestination::Image {
+                file,
+                options: options.clone(),
+            },
+            DataDestination::Stats { .. } => DataDestination::Stats { file },
+            DataDestination::Json { .. } => DataDestination::Json { file },
+        }
+    }
+
+    pub fn with_gpx_options(self, options: GpxOptions) -> Self {
+        match self {
+            DataDestination::Gpx { file, .. } => DataDestination::Gpx { file, options },
+            dest => dest,
+        }
+    }
+
+    pub fn with_render_options(self, options: RenderOptions) -> Self {
+        match self {
+            DataDestination::Image { file, .. } => DataDestination::Image { file, options },
+            dest => dest,
+        }
+    }
+}
+
+/// File names of the routes, in the order of the routes. Fails when two routes get the same name
+/// instead of one overwriting the other
+fn render_file_names(
+    template: &str,
+    routes: &[RouteMessage],
+) -> Result<Vec<PathBuf>, ResultWriterError> {
+    let mut by_score = (0..routes.len()).collect::<Vec<_>>();
+    by_score.sort_by(|a, b| routes[*b].stats.score.total_cmp(&routes[*a].stats.score));
+
+    let mut names = HashSet::new();
+    routes
+        .iter()
+        .enumerate()
+        .map(|(index, route)| {
+            let rank = by_score
+                .iter()
+                .position(|idx| *idx == index)
+                .unwrap_or(index)
+                + 1;
+            let file = render_file_name(template, index, rank, route)?;
+            if !names.insert(file.clone()) {
+                return Err(ResultWriterError::DuplicateFileName { file });
+            }
+            Ok(file)
+        })
+        .collect()
+}
+
+/// Replaces the `{placeholder}` parts of the file name with the values of the route, `index` is
+/// the position of the route in the response and `rank` the position by score starting from 1
+fn render_file_name(
+    template: &str,
+    index: usize,
+    rank: usize,
+    route: &RouteMessage,
+) -> Result<PathBuf, ResultWriterError> {
+    let mut file_name = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        file_name.push_str(&rest[..start]);
+        let placeholder = &rest[start + 1..start + len];
+        match placeholder {
+            "index" => file_name.push_str(&index.to_string()),
+            "rank" => file_name.push_str(&rank.to_string()),
+            "score" => file_name.push_str(&format!("{:.2}", route.stats.score)),
+            "len_km" => file_name.push_str(&format!("{:.1}", route.stats.len_m / 1000.)),
+            "len_mi" => file_name.push_str(&format!("{:.1}", route.stats.len_m / M_IN_MI)),
+            "cluster" => {
+                file_name.push_str(&route.stats.cluster.map_or(-1, |c| c as isize).to_string())
+            }
+            _ => {
+                return Err(ResultWriterError::UnknownFileNamePlaceholder {
+                    placeholder: placeholder.to_string(),
+                })
+            }
+        }
+        rest = &rest[start + len + 1..];
+    }
+    file_name.push_str(rest);
+    Ok(PathBuf::from(file_name))
+}
+
+/// Options applied to the routes before they are written, in any output format
+#[derive(Debug, Clone, Default, Args)]
+pub struct OutputOptions {
+    #[arg(long, value_name = "DECIMALS")]
+    /// Round route coordinates to this many decimal places, 5 decimals are about 1 meter
+    pub coord_precision: Option<u32>,
+
+    #[arg(long, value_name = "METERS")]
+    /// Simplify routes with the Douglas-Peucker algorithm, leaving out points that are closer
+    /// than this to the simplified line
+    pub simplify_tolerance_m: Option<f64>,
+
+    #[arg(long, value_enum, default_value_t = OutputDetail::Summary)]
+    /// Full detail adds the map data attributes of every route segment to json output: road
+    /// type, surface, smoothness, name, ref, length and travel time
+    pub output_detail: OutputDetail,
+
+    #[arg(long, value_enum)]
+    /// Units of the distances in route descriptions, turn instructions and reports, the
+    /// `units` of the rules are used when not given, and metric when neither is
+    pub units: Option<Units>,
+
+    #[arg(long, conflicts_with = "auto_increment")]
+    /// Overwrite output files that exist, they are kept by default
+    pub force: bool,
+
+    #[arg(long)]
+    /// Write to a free file name with a number added, like routes-1.gpx, when an output file
+    /// exists
+    pub auto_increment: bool,
+}
+
+/// Meters in a degree of latitude
+const DEG_LAT_M: f64 = 111_320.;
+
+/// Indices of the coordinates left after Douglas-Peucker simplification with a tolerance in
+/// meters, coordinates are projected to meters around the first one so the tolerance is the
+/// same in every direction
+fn simplify_coords(coords: &[(f32, f32)], tolerance_m: f64) -> Vec<usize> {
+    let Some((first_lat, _)) = coords.first() else {
+        return Vec::new();
+    };
+    let deg_lon_m = DEG_LAT_M * f64::from(*first_lat).to_radians().cos();
+    coords
+        .iter()
+        .map(|(lat, lon)| Coord {
+            x: f64::from(*lon) * deg_lon_m,
+            y: f64::from(*lat) * DEG_LAT_M,
+        })
+        .collect::<LineString>()
+        .simplify_idx(&tolerance_m)
+}
+
+/// First file name with a number added to the stem that does not exist, like routes-1.gpx
+fn free_file_name(file: &Path) -> PathBuf {
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = file
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|number| file.with_file_name(format!("{stem}-{number}{extension}")))
+        .find(|file| !file.exists())
+        .expect("a free number is found before running out of them")
+}
+
+/// Hidden file next to the output, with the extension of the output
+fn temp_file_name(file: &Path) -> PathBuf {
+    let mut temp_file_name = format!(
+        ".{}.tmp-{}",
+        file.file_name().unwrap_or_default().to_string_lossy(),
+        std::process::id()
+    );
+    if let Some(extension) = file.extension() {
+        temp_file_name.push('.');
+        temp_file_name.push_str(&extension.to_string_lossy());
+    }
+    file.with_file_name(temp_file_name)
+}
+
+/// Output file written under a hidden name next to it and renamed once complete, so a failed
+/// write never leaves a partial output or destroys the previous one. The hidden file is removed
+/// when dropped without a commit
+pub struct AtomicFile {
+    file: Option<File>,
+    temp_path: PathBuf,
+    path: PathBuf,
+}
+
+impl AtomicFile {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let temp_path = temp_file_name(path);
+        Ok(Self {
+            file: Some(File::create(&temp_path)?),
+            temp_path,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Syncs the hidden file to disk before renaming it, so the output is never replaced by a file
+    /// that is not fully written. The hidden file is removed when the commit fails
+    pub fn commit(mut self) -> io::Result<()> {
+        let result = self
+            .file
+            .take()
+            .map_or(Ok(()), |mut file| {
+                file.flush()?;
+                file.sync_all()
+            })
+            .and_then(|()| std::fs::rename(&self.temp_path, &self.path));
+        if result.is_err() {
+            let _ = std::fs::remove_file(&self.temp_path);
+        }
+        result
+    }
+
+    fn file(&mut self) -> io::Result<&mut File> {
+        self.file
+            .as_mut()
+            .ok_or_else(|| io::Error::other("File already committed"))
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file()?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file()?.flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = std::fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+fn round_coord(value: f32, precision: u32) -> f32 {
+    let factor = 10f64.powi(precision as i32);
+    ((f64::from(value) * factor).round() / factor) as f32
+}
+
+impl OutputOptions {
+    /// Options with the units of the rules, when no units were given
+    pub fn with_rules_units(&self, rules_units: Option<Units>) -> Self {
+        Self {
+            units: self.units.or(rules_units),
+            ..self.clone()
+        }
+    }
+
+    /// File to write to instead of the output file, when it exists
+    fn output_file(&self, file: &Path) -> Result<PathBuf, ResultWriterError> {
+        if self.force || !file.exists() {
+            Ok(file.to_path_buf())
+        } else if self.auto_increment {
+            let free_file = free_file_name(file);
+            info!(file = ?file, free_file = ?free_file, "Output file exists, writing to a free name");
+            Ok(free_file)
+        } else {
+            Err(ResultWriterError::OutputExists {
+                file: file.to_path_buf(),
+            })
+        }
+    }
+
+    fn apply(&self, response: &mut ResponseMessage) {
+        if let RouterResult::Batch { responses } = &mut response.result {
+            for response in responses.iter_mut() {
+                self.apply(response);
+            }
+            return;
+        }
+        let RouterResult::Ok { routes, .. } = &mut response.result else {
+            return;
+        };
+        for route in routes.iter_mut() {
+            if let Some(tolerance_m) = self.simplify_tolerance_m {
+                let coords_count = route.coords.len();
+                route.coords = simplify_coords(&route.coords, tolerance_m)
+                    .into_iter()
+                    .map(|idx| route.coords[idx])
+                    .collect();
+                trace!(
+                    coords_count,
+                    simplified_count = route.coords.len(),
+                    "Route simplified"
+                );
+            }
+            if let Some(precision) = self.coord_precision {
+                for (lat, lon) in route.coords.iter_mut() {
+                    *lat = round_coord(*lat, precision);
+                    *lon = round_coord(*lon, precision);
+                }
+            }
+        }
+    }
 }
 
 pub struct ResultWriter;
 impl ResultWriter {
+    fn write_file_per_route(
+        dest: DataDestination,
+        template: String,
+        options: &OutputOptions,
+        response: ResponseMessage,
+    ) -> Result<(), ResultWriterError> {
+        let id = response.id.clone();
+        let routes = Self::get_routes(response)?;
+        let files = render_file_names(&template, &routes)?;
+
+        for (route, file) in routes.into_iter().zip(files) {
+            if let Some(dir) = file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                std::fs::create_dir_all(dir)
+                    .map_err(|error| ResultWriterError::FileWrite { error })?;
+            }
+            Self::write_response(
+                dest.with_file(file),
+                options,
+                ResponseMessage {
+                    id: id.clone(),
+                    result: RouterResult::Ok {
+                        routes: vec![route],
+                        summary: None,
+                    },
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Routes from the response, for the formats that can't represent errors or progress
+    fn get_routes(response: ResponseMessage) -> Result<Vec<RouteMessage>, ResultWriterError> {
+        match response.result {
+            RouterResult::Error { message } => {
+                Err(ResultWriterError::RoutesGenerationFailed { error: message })
+            }
+            RouterResult::Progress { .. } => Err(ResultWriterError::RoutesGenerationFailed {
+                error: "Response is a progress message without routes".to_string(),
+            }),
+            RouterResult::Route { route } => Ok(vec![*route]),
+            RouterResult::Queued { .. } => Err(ResultWriterError::RoutesGenerationFailed {
+                error: "Response is a queue message without routes".to_string(),
+            }),
+            RouterResult::Busy { message } => {
+                Err(ResultWriterError::RoutesGenerationFailed { error: message })
+            }
+            RouterResult::RateLimited { retry_after_s } => {
+                Err(ResultWriterError::RoutesGenerationFailed {
+                    error: format!("Rate limited, retry after {retry_after_s} seconds"),
+                })
+            }
+            RouterResult::Status { status } => match status {
+                RequestStatus::Finished { response, .. } => Self::get_routes(*response),
+                RequestStatus::InProgress | RequestStatus::Unknown => {
+                    Err(ResultWriterError::RoutesGenerationFailed {
+                        error: "Request is not finished".to_string(),
+                    })
+                }
+            },
+            RouterResult::Ok { routes, .. } => Ok(routes),
+            RouterResult::Batch { responses } => {
+                let mut routes = Vec::new();
+                let mut errors = Vec::new();
+                for response in responses {
+                    let id = response.id.clone();
+                    match Self::get_routes(response) {
+                        Ok(response_routes) => routes.extend(response_routes),
+                        Err(error) => errors.push(format!("{id}: {error}")),
+                    }
+                }
+                if !errors.is_empty() {
+                    return Err(ResultWriterError::BatchRequestsFailed { errors });
+                }
+                Ok(routes)
+            }
+        }
+    }
+
+    /// Writes a route to stdout as a single line of JSON, for streaming routes one by one
+    pub fn write_route_line(route: RouteMessage) -> Result<(), ResultWriterError> {
+        let mut json = serde_json::to_string(&route)
+            .map_err(|error| ResultWriterError::SerializeJson { error })?;
+        json.push('\n');
+
+        trace!(bytes_len = json.len(), "Writing route line to stdout");
+
+        let mut stdout = std::io::stdout().lock();
+        stdout
+            .write_all(json.as_bytes())
+            .and_then(|_| stdout.flush())
+            .map_err(|error| ResultWriterError::Stdout { error })
+    }
+
     #[tracing::instrument(skip(response))]
     pub fn write(
         dest: DataDestination,
+        options: &OutputOptions,
+        mut response: ResponseMessage,
+    ) -> Result<(), ResultWriterError> {
+        let _phase = timings::phase!("write");
+        options.apply(&mut response);
+        Self::write_response(dest, options, response)
+    }
+
+    fn write_response(
+        dest: DataDestination,
+        options: &OutputOptions,
+        response: ResponseMessage,
+    ) -> Result<(), ResultWriterError> {
+        let units = options.units.unwrap_or_default();
+        let Some(file) = dest.get_file() else {
+            return Self::write_destination(dest, units, response);
+        };
+        let template = file.to_string_lossy().to_string();
+        if template.contains('{') {
+            return Self::write_file_per_route(dest, template, options, response);
+        }
+
+        let file = options.output_file(file)?;
+        Self::write_destination(dest.with_file(file), units, response)
+    }
+
+    fn write_destination(
+        dest: DataDestination,
+        units: Units,
         response: ResponseMessage,
     ) -> Result<(), ResultWriterError> {
         match dest {
@@ -54,20 +554,66 @@ impl ResultWriter {
                     .map_err(|error| ResultWriterError::Stdout { error })?;
                 Ok(())
             }
-            DataDestination::Gpx { file } => match response.result {
-                crate::ipc_handler::RouterResult::Error { message } => {
-                    Err(ResultWriterError::RoutesGenerationFailed { error: message })
-                }
-                crate::ipc_handler::RouterResult::Ok { routes } => {
-                    info!(file = ?file, "Writing gpx");
+            DataDestination::Gpx { file, options } => {
+                let routes = Self::get_routes(response)?;
+                info!(file = ?file, "Writing gpx");
 
-                    GpxWriter::new(routes, file.clone())
-                        .write_gpx()
-                        .map_err(|error| ResultWriterError::Gpx { error })?;
+                GpxWriter::new(routes, file.clone(), options, units)
+                    .write_gpx()
+                    .map_err(|error| ResultWriterError::Gpx { error })?;
 
-                    Ok(())
-                }
-            },
+                Ok(())
+            }
+            DataDestination::Kml { file } => {
+                let routes = Self::get_routes(response)?;
+                info!(file = ?file, "Writing kml");
+
+                KmlWriter::new(routes, file.clone(), units)
+                    .write_kml()
+                    .map_err(|error| ResultWriterError::Kml { error })?;
+
+                Ok(())
+            }
+            DataDestination::Fit { file } => {
+                let routes = Self::get_routes(response)?;
+                info!(file = ?file, "Writing fit");
+
+                FitWriter::new(routes, file.clone())
+                    .write_fit()
+                    .map_err(|error| ResultWriterError::Fit { error })?;
+
+                Ok(())
+            }
+            DataDestination::Html { file } => {
+                let routes = Self::get_routes(response)?;
+                info!(file = ?file, "Writing html");
+
+                HtmlWriter::new(routes, file.clone(), units)
+                    .write_html()
+                    .map_err(|error| ResultWriterError::Html { error })?;
+
+                Ok(())
+            }
+            DataDestination::Image { file, options } => {
+                let routes = Self::get_routes(response)?;
+                info!(file = ?file, "Writing image");
+
+                ImageWriter::new(routes, file.clone(), options)
+                    .write_image()
+                    .map_err(|error| ResultWriterError::Image { error })?;
+
+                Ok(())
+            }
+            DataDestination::Stats { file } => {
+                let routes = Self::get_routes(response)?;
+                info!(file = ?file, "Writing stats");
+
+                StatsWriter::new(routes, file.clone())
+                    .write_stats()
+                    .map_err(|error| ResultWriterError::Stats { error })?;
+
+                Ok(())
+            }
             DataDestination::Json { file } => {
                 let json = serde_json::to_string(&response)
                     .map_err(|error| ResultWriterError::SerializeJson { error })?;
@@ -78,7 +624,11 @@ impl ResultWriter {
                     "Writing json"
                 );
 
-                std::fs::write(file, json)
+                AtomicFile::create(&file)
+                    .and_then(|mut atomic_file| {
+                        atomic_file.write_all(json.as_bytes())?;
+                        atomic_file.commit()
+                    })
                     .map_err(|error| ResultWriterError::FileWrite { error })?;
 
                 Ok(())
@@ -86,3 +636,163 @@ impl ResultWriter {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{io::Write, path::PathBuf};
+
+    use crate::{
+        ipc_handler::{ResponseMessage, RouteMessage, RouterResult},
+        router::route::RouteStats,
+    };
+
+    use super::{
+        free_file_name, render_file_name, render_file_names, simplify_coords, temp_file_name,
+        AtomicFile, OutputOptions, ResultWriter, ResultWriterError,
+    };
+
+    #[test]
+    fn file_name_templates() {
+        let stats: RouteStats = serde_json::from_str(
+            r#"{"len_m": 123456, "junction_count": 0, "highway": {}, "surface": {}, "smoothness": {}, "score": 4.567, "cluster": 2, "approximated_route": []}"#,
+        )
+        .unwrap();
+        let route = RouteMessage {
+            coords: Vec::new(),
+            stats,
+            segments: None,
+        };
+
+        assert_eq!(
+            render_file_name("routes/{rank}_{score}_{index}.gpx", 3, 1, &route).unwrap(),
+            PathBuf::from("routes/1_4.57_3.gpx")
+        );
+        assert_eq!(
+            render_file_name("r_{len_km}km_c{cluster}.fit", 0, 2, &route).unwrap(),
+            PathBuf::from("r_123.5km_c2.fit")
+        );
+        assert_eq!(
+            render_file_name("r_{len_mi}mi.gpx", 0, 2, &route).unwrap(),
+            PathBuf::from("r_76.7mi.gpx")
+        );
+        assert!(matches!(
+            render_file_name("{name}.kml", 0, 1, &route),
+            Err(ResultWriterError::UnknownFileNamePlaceholder { placeholder }) if placeholder == "name"
+        ));
+
+        let routes = vec![route.clone(), route];
+        assert_eq!(
+            render_file_names("r_{rank}.gpx", &routes).unwrap(),
+            vec![PathBuf::from("r_1.gpx"), PathBuf::from("r_2.gpx")]
+        );
+        assert!(matches!(
+            render_file_names("r_c{cluster}.gpx", &routes),
+            Err(ResultWriterError::DuplicateFileName { file }) if file.as_os_str() == "r_c2.gpx"
+        ));
+    }
+
+    #[test]
+    fn output_file_exists() {
+        let dir = std::env::temp_dir().join(format!("ridi-output-exists-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("routes.gpx");
+        std::fs::write(&file, "").unwrap();
+        std::fs::write(dir.join("routes-1.gpx"), "").unwrap();
+
+        assert!(matches!(
+            OutputOptions::default().output_file(&file),
+            Err(ResultWriterError::OutputExists { .. })
+        ));
+        let force = OutputOptions {
+            force: true,
+            ..OutputOptions::default()
+        };
+        assert_eq!(force.output_file(&file).unwrap(), file);
+        let auto_increment = OutputOptions {
+            auto_increment: true,
+            ..OutputOptions::default()
+        };
+        assert_eq!(
+            auto_increment.output_file(&file).unwrap(),
+            dir.join("routes-2.gpx")
+        );
+        assert_eq!(free_file_name(&dir.join("stats")), dir.join("stats-1"));
+        assert_eq!(temp_file_name(&file).extension().unwrap_or_default(), "gpx");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn atomic_file_removed_on_failed_commit() {
+        let dir = std::env::temp_dir().join(format!("ridi-atomic-file-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("routes.gpx");
+
+        let mut atomic_file = AtomicFile::create(&file).unwrap();
+        atomic_file.write_all(b"routes").unwrap();
+        atomic_file.commit().unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "routes");
+
+        // a non empty directory in place of the output makes the rename fail
+        let blocked = dir.join("blocked");
+        std::fs::create_dir_all(blocked.join("inner")).unwrap();
+        let mut atomic_file = AtomicFile::create(&blocked).unwrap();
+        atomic_file.write_all(b"routes").unwrap();
+        assert!(atomic_file.commit().is_err());
+        assert!(!temp_file_name(&blocked).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn simplify_coords_tolerance() {
+        // a straight line north with a 5m bump in the middle
+        let coords = vec![
+            (57.0, 24.0),
+            (57.0005, 24.0),
+            (57.001, 24.000_08),
+            (57.0015, 24.0),
+            (57.002, 24.0),
+        ];
+        assert_eq!(simplify_coords(&coords, 1.), vec![0, 1, 2, 3, 4]);
+        assert_eq!(simplify_coords(&coords, 3.), vec![0, 2, 4]);
+        assert_eq!(simplify_coords(&coords, 10.), vec![0, 4]);
+        assert!(simplify_coords(&[], 10.).is_empty());
+    }
+
+    #[test]
+    fn batch_routes_failed_requests() {
+        let response = |id: &str, result| ResponseMessage {
+            id: id.to_string(),
+            result,
+        };
+        let batch = response(
+            "batch",
+            RouterResult::Batch {
+                responses: vec![
+                    response(
+                        "a",
+                        RouterResult::Ok {
+                            routes: Vec::new(),
+                            summary: None,
+                        },
+                    ),
+                    response(
+                        "b",
+                        RouterResult::Error {
+                            message: "No route found".to_string(),
+                        },
+                    ),
+                ],
+            },
+        );
+
+        match ResultWriter::get_routes(batch) {
+            Err(ResultWriterError::BatchRequestsFailed { errors }) => {
+                assert_eq!(errors.len(), 1);
+                assert!(errors[0].starts_with("b: "));
+            }
+            other => panic!("unexpected result {other:?}"),
+        }
+    }
+}
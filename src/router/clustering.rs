@@ -1,10 +1,74 @@
-use super::route::Route;
-use hdbscan::{Hdbscan, HdbscanHyperParams};
+use crate::profiler;
+
+use super::{
+    route::Route,
+    rules::{ClusteringAlgorithm, ClusteringDistanceMetric, GenerationRulesClustering},
+};
+use hdbscan::{DistanceMetric, Hdbscan, HdbscanHyperParams};
 use serde::{Deserialize, Serialize};
 use tracing::error;
 
 const APPROXIMATION_POINTS: usize = 10;
 
+/// Label of routes that are not in any cluster
+pub const NOISE_LABEL: i32 = -1;
+
+fn get_distance(a: &[f32], b: &[f32], metric: &ClusteringDistanceMetric) -> f64 {
+    let diffs = a.iter().zip(b).map(|(a, b)| (a - b).abs() as f64);
+    match metric {
+        ClusteringDistanceMetric::Euclidean => diffs.map(|diff| diff * diff).sum::<f64>().sqrt(),
+        ClusteringDistanceMetric::Manhattan => diffs.sum(),
+        ClusteringDistanceMetric::Chebyshev => diffs.fold(0., f64::max),
+    }
+}
+
+/// Density based clustering, points with at least `min_points` neighbours within `epsilon`
+/// (counting themselves) start a cluster and pull their neighbours into it
+fn dbscan(
+    points: &[Vec<f32>],
+    epsilon: f64,
+    min_points: usize,
+    metric: &ClusteringDistanceMetric,
+) -> Vec<i32> {
+    let get_neighbours = |idx: usize| {
+        (0..points.len())
+            .filter(|other_idx| get_distance(&points[idx], &points[*other_idx], metric) <= epsilon)
+            .collect::<Vec<_>>()
+    };
+    let mut labels: Vec<Option<i32>> = vec![None; points.len()];
+    let mut cluster = 0;
+    for idx in 0..points.len() {
+        if labels[idx].is_some() {
+            continue;
+        }
+        let neighbours = get_neighbours(idx);
+        if neighbours.len() < min_points {
+            labels[idx] = Some(NOISE_LABEL);
+            continue;
+        }
+        labels[idx] = Some(cluster);
+        let mut queue = neighbours;
+        while let Some(other_idx) = queue.pop() {
+            if labels[other_idx] == Some(NOISE_LABEL) {
+                labels[other_idx] = Some(cluster);
+            }
+            if labels[other_idx].is_some() {
+                continue;
+            }
+            labels[other_idx] = Some(cluster);
+            let other_neighbours = get_neighbours(other_idx);
+            if other_neighbours.len() >= min_points {
+                queue.extend(other_neighbours);
+            }
+        }
+        cluster += 1;
+    }
+    labels
+        .into_iter()
+        .map(|label| label.unwrap_or(NOISE_LABEL))
+        .collect()
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Clustering {
     pub approximated_routes: Vec<Vec<[f32; 2]>>,
@@ -12,7 +76,8 @@ pub struct Clustering {
 }
 
 impl Clustering {
-    pub fn generate(routes: &Vec<Route>) -> Option<Self> {
+    pub fn generate(routes: &Vec<Route>, rules: &GenerationRulesClustering) -> Option<Self> {
+        profiler::zone!("cluster_routes");
         let mut approximated_routes = Vec::new();
         // let mut point_array = Array::zeros((0, 2 * APPROXIMATION_POINTS));
         let mut points = Vec::new();
@@ -50,17 +115,33 @@ impl Clustering {
             return None;
         }
 
-        let params = HdbscanHyperParams::builder()
-            .epsilon(0.1)
-            .min_cluster_size(2)
-            .build();
-        let alg = Hdbscan::new(&points, params);
-        let labels = match alg.cluster() {
-            Ok(l) => l,
-            Err(e) => {
-                error!("Failed to cluster routes: {e}");
-                return None;
+        let labels = match rules.algorithm {
+            ClusteringAlgorithm::Hdbscan => {
+                let params = HdbscanHyperParams::builder()
+                    .epsilon(rules.epsilon)
+                    .min_cluster_size(rules.min_points)
+                    .dist_metric(match rules.distance_metric {
+                        ClusteringDistanceMetric::Euclidean => DistanceMetric::Euclidean,
+                        ClusteringDistanceMetric::Manhattan => DistanceMetric::Manhattan,
+                        ClusteringDistanceMetric::Chebyshev => DistanceMetric::Chebyshev,
+                    })
+                    .build();
+                let alg = Hdbscan::new(&points, params);
+                match alg.cluster() {
+                    Ok(l) => l,
+                    Err(e) => {
+                        error!("Failed to cluster routes: {e}");
+                        return None;
+                    }
+                }
             }
+            ClusteringAlgorithm::Dbscan => dbscan(
+                &points,
+                rules.epsilon,
+                rules.min_points,
+                &rules.distance_metric,
+            ),
+            ClusteringAlgorithm::None => vec![NOISE_LABEL; points.len()],
         };
 
         Some(Self {
@@ -69,3 +150,30 @@ impl Clustering {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::router::rules::ClusteringDistanceMetric;
+
+    use super::{dbscan, NOISE_LABEL};
+
+    #[test]
+    fn dbscan_clusters() {
+        let points = vec![
+            vec![0., 0.],
+            vec![0.05, 0.],
+            vec![0.1, 0.05],
+            vec![5., 5.],
+            vec![5.05, 5.],
+            vec![10., 0.],
+        ];
+        assert_eq!(
+            dbscan(&points, 0.08, 2, &ClusteringDistanceMetric::Euclidean),
+            vec![0, 0, 0, 1, 1, NOISE_LABEL]
+        );
+        assert_eq!(
+            dbscan(&points, 0.08, 2, &ClusteringDistanceMetric::Manhattan),
+            vec![0, 0, NOISE_LABEL, 1, 1, NOISE_LABEL]
+        );
+    }
+}
@@ -1,23 +1,42 @@
-use std::{collections::HashMap, ops::Sub, time::Instant};
+use std::{
+    collections::{BTreeMap, HashSet},
+    ops::Sub,
+    sync::Arc,
+};
 
 use crate::{
     debug::writer::DebugWriter,
-    map_data::graph::{MapDataGraph, MapDataPointRef},
-    router::{clustering::Clustering, rules::RouterRules, weights::weight_check_avoid_rules},
+    map_data::graph::{MapDataGraph, MapDataLineRef, MapDataPointRef},
+    profiler,
+    router::{
+        clustering::{Clustering, NOISE_LABEL},
+        rules::RouterRules,
+        weights::weight_check_avoid_rules,
+    },
+    timings,
 };
 use geo::{Destination, Haversine, Point};
 use hdbscan::{Hdbscan, HdbscanError, HdbscanHyperParams};
 use rayon::prelude::*;
 use tracing::{error, info, trace};
+use web_time::Instant;
 
+#[cfg(feature = "scripting")]
+use super::scripting::weight_scripted;
 use super::{
-    itinerary::Itinerary,
-    navigator::{NavigationResult, Navigator},
+    cancellation::CancellationToken,
+    itinerary::{Itinerary, ItineraryVia},
+    navigator::{DeadEnds, NavigationResult, Navigator, PartialRoute},
+    progress::{ProgressCallback, ProgressReporter},
     route::{Route, RouteStats},
+    scripting::{load_scripts, ScriptingError},
+    summary::GenerationCounters,
     weights::{
-        weight_avoid_nogo_areas, weight_check_distance_to_next, weight_heading, weight_no_loops,
-        weight_no_sharp_turns, weight_no_short_detours, weight_prefer_same_road,
-        weight_progress_speed, weight_rules_highway, weight_rules_smoothness, weight_rules_surface,
+        weight_avoid_nogo_areas, weight_avoid_urban, weight_check_distance_to_next, weight_heading,
+        weight_max_length, weight_no_loops, weight_no_same_road_return, weight_no_sharp_turns,
+        weight_no_short_detours, weight_no_steep_grades, weight_prefer_curvy,
+        weight_prefer_same_road, weight_progress_speed, weight_rules_highway,
+        weight_rules_smoothness, weight_rules_surface, weight_tag_matchers, weight_tag_preferences,
         WeightCalc,
     },
 };
@@ -31,10 +50,22 @@ pub const WP_LOOKUP_ALLOWED_HWS: [&'static str; 6] = [
     "unclassified",
 ];
 
+/// Out-and-back spikes up to this total length are removed from the generated routes
+const SPIKE_MAX_LEN_M: f64 = 2000.;
+
+/// Bearings used for round trips without a specified bearing
+const ROUND_TRIP_SWEEP_BEARINGS_DEG: [f32; 8] = [0., 45., 90., 135., 180., 225., 270., 315.];
+
 #[derive(Debug, thiserror::Error)]
 pub enum GeneratorError {
     #[error("Hdbscan error: {error}")]
     Hdbscan { error: HdbscanError },
+
+    #[error("Route generation was cancelled")]
+    Cancelled,
+
+    #[error("Weight script error: {error}")]
+    Scripting { error: ScriptingError },
 }
 
 #[derive(Debug, Clone)]
@@ -43,28 +74,125 @@ pub struct RouteWithStats {
     pub route: Route,
 }
 
+/// Called with every route as soon as it is generated, before the routes are clustered and
+/// ranked, so the stats have no cluster and the route may not be in the final result
+pub type RouteCallback = Arc<dyn Fn(RouteWithStats) + Send + Sync>;
+
 pub struct Generator {
     start: MapDataPointRef,
     finish: MapDataPointRef,
-    round_trip: Option<(f32, u32)>,
+    vias: Vec<ItineraryVia>,
+    round_trip: Option<(Option<f32>, u32)>,
     rules: RouterRules,
+    deadline: Option<Instant>,
+    cancellation: Option<CancellationToken>,
+    progress: Option<ProgressCallback>,
+    route_callback: Option<RouteCallback>,
+    counters: Option<Arc<GenerationCounters>>,
+    seed: Option<u64>,
 }
 
 impl Generator {
     pub fn new(
         start: MapDataPointRef,
         finish: MapDataPointRef,
-        round_trip: Option<(f32, u32)>,
+        vias: Vec<ItineraryVia>,
+        round_trip: Option<(Option<f32>, u32)>,
         rules: RouterRules,
     ) -> Self {
         Self {
             start,
             finish,
+            vias,
             round_trip,
             rules,
+            deadline: None,
+            cancellation: None,
+            progress: None,
+            route_callback: None,
+            counters: None,
+            seed: None,
         }
     }
 
+    /// Stops generation at the deadline and returns the routes finished by then
+    pub fn with_deadline(mut self, deadline: Option<Instant>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Aborts generation as soon as the token is cancelled
+    pub fn with_cancellation(mut self, cancellation: Option<CancellationToken>) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    /// Reports generation progress periodically while the routes are generated
+    pub fn with_progress(mut self, progress: Option<ProgressCallback>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Passes every route to the callback as soon as it is generated
+    pub fn with_route_callback(mut self, route_callback: Option<RouteCallback>) -> Self {
+        self.route_callback = route_callback;
+        self
+    }
+
+    /// Counts the itineraries, steps and forks into the counters, to read a summary of the
+    /// generation from them. Without counters the summary is only logged
+    pub fn with_counters(mut self, counters: Option<Arc<GenerationCounters>>) -> Self {
+        self.counters = counters;
+        self
+    }
+
+    /// Makes tie-breaking between equally weighted choices follow the seed, so repeated runs with
+    /// the same inputs and seed generate the same routes
+    pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Generates the route of a single itinerary of an earlier run, built like the generated
+    /// itineraries, for debugging its steps
+    pub fn replay_itinerary(
+        &self,
+        waypoints: Vec<MapDataPointRef>,
+        waypoint_radius: f32,
+    ) -> NavigationResult {
+        let itinerary = if self.round_trip.is_some() {
+            Itinerary::new_round_trip(
+                self.start.clone(),
+                self.finish.clone(),
+                waypoints,
+                waypoint_radius,
+            )
+        } else {
+            Itinerary::new_start_finish(
+                self.start.clone(),
+                self.finish.clone(),
+                waypoints,
+                waypoint_radius,
+            )
+            .with_vias(&self.vias)
+        };
+        Navigator::new(
+            itinerary,
+            self.rules.clone(),
+            self.get_weight_calcs(),
+            self.round_trip.is_some(),
+        )
+        .with_dead_ends(Some(Arc::new(DeadEnds::new())))
+        .with_seed(self.seed)
+        .generate_routes()
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .is_some_and(|cancellation| cancellation.is_cancelled())
+    }
+
     fn create_waypoints_around(
         &self,
         point: &MapDataPointRef,
@@ -107,6 +235,7 @@ impl Generator {
         avoid_residential: bool,
         round_trip_bearing_adjustment: Option<f32>,
     ) -> Vec<Itinerary> {
+        profiler::zone!("generate_itineraries");
         if let Some(round_trip) = self.round_trip {
             let start_geo = Point::new(self.start.borrow().lon, self.start.borrow().lat);
 
@@ -119,7 +248,7 @@ impl Generator {
                 .iter()
                 .flat_map(|side_left_ratio| {
                     let bearing_adjusted =
-                        round_trip.0 + round_trip_bearing_adjustment.unwrap_or(0.);
+                        round_trip.0.unwrap_or(0.) + round_trip_bearing_adjustment.unwrap_or(0.);
                     let bearing = if bearing_adjusted < 0. {
                         360. - bearing_adjusted.abs()
                     } else {
@@ -216,31 +345,46 @@ impl Generator {
                 })
                 .collect();
         }
+        // waypoints around start and finish point towards the first and the last via
+        let first_target = self.vias.first().map_or(&self.finish, |via| &via.point);
+        let last_target = self.vias.last().map_or(&self.start, |via| &via.point);
         let from_waypoints = self.create_waypoints_around(
             &self.start,
-            &self.finish.borrow().bearing(&self.start),
+            &first_target.borrow().bearing(&self.start),
             avoid_residential,
         );
         let to_waypoints = self.create_waypoints_around(
             &self.finish,
-            &self.start.borrow().bearing(&self.finish),
+            &last_target.borrow().bearing(&self.finish),
             avoid_residential,
         );
+        let via_points = self
+            .vias
+            .iter()
+            .map(|via| via.point.clone())
+            .collect::<Vec<_>>();
         let mut itineraries = vec![Itinerary::new_start_finish(
             self.start.clone(),
             self.finish.clone(),
-            Vec::new(),
+            via_points.clone(),
             3000.,
-        )];
+        )
+        .with_vias(&self.vias)];
 
         from_waypoints.iter().for_each(|from_wp| {
             to_waypoints.iter().for_each(|to_wp| {
-                itineraries.push(Itinerary::new_start_finish(
-                    self.start.clone(),
-                    self.finish.clone(),
-                    vec![from_wp.clone(), to_wp.clone()],
-                    3000.,
-                ))
+                let mut waypoints = vec![from_wp.clone()];
+                waypoints.extend(via_points.iter().cloned());
+                waypoints.push(to_wp.clone());
+                itineraries.push(
+                    Itinerary::new_start_finish(
+                        self.start.clone(),
+                        self.finish.clone(),
+                        waypoints,
+                        3000.,
+                    )
+                    .with_vias(&self.vias),
+                )
             })
         });
         itineraries
@@ -251,8 +395,12 @@ impl Generator {
         &self,
         itineraries: Vec<Itinerary>,
     ) -> Result<Vec<Itinerary>, GeneratorError> {
+        // itineraries with only the user provided vias are always kept
+        let (generated, mut fixed): (Vec<_>, Vec<_>) = itineraries
+            .into_iter()
+            .partition(|i| i.waypoints.len() > self.vias.len());
         let mut points = Vec::new();
-        for itinerary in itineraries.iter().filter(|i| i.waypoints.len() > 0) {
+        for itinerary in generated.iter() {
             points.push(
                 itinerary
                     .waypoints
@@ -266,7 +414,7 @@ impl Generator {
             );
         }
         if points.is_empty() {
-            return Ok(vec![]);
+            return Ok(fixed);
         }
         let params = HdbscanHyperParams::builder()
             .epsilon(0.01)
@@ -278,26 +426,211 @@ impl Generator {
             Err(e) => return Err(GeneratorError::Hdbscan { error: e }),
         };
 
-        let mut deduped_itineraries_map = HashMap::new();
+        let mut deduped_itineraries_map = BTreeMap::new();
         labels.iter().enumerate().for_each(|(idx, label)| {
-            deduped_itineraries_map.insert(*label, itineraries[idx].clone());
+            deduped_itineraries_map.insert(*label, generated[idx].clone());
         });
 
         let mut deduped_itineraries = deduped_itineraries_map.into_values().collect::<Vec<_>>();
-        deduped_itineraries.append(
-            &mut itineraries
-                .into_iter()
-                .filter(|i| i.waypoints.len() == 0)
-                .collect(),
-        );
+        deduped_itineraries.append(&mut fixed);
 
         Ok(deduped_itineraries)
     }
 
+    fn get_vias_visited(&self, route: &Route) -> Vec<bool> {
+        self.vias
+            .iter()
+            .map(|via| {
+                route.iter().any(|segment| {
+                    segment
+                        .get_end_point()
+                        .borrow()
+                        .distance_between(&via.point)
+                        <= via.radius
+                })
+            })
+            .collect()
+    }
+
+    /// Drops routes that share too much of their length with a better scoring route. Dissimilarity
+    /// is the share of a route's length not on lines used by an already kept route. Routes are
+    /// compared from the best score down, the kept routes stay in the order they were passed in.
+    #[tracing::instrument(skip(self, routes))]
+    fn filter_similar_routes(&self, routes: Vec<RouteWithStats>) -> Vec<RouteWithStats> {
+        profiler::zone!("filter_similar_routes");
+        let diversity = &self.rules.generation.route_diversity;
+        if !diversity.enabled {
+            return routes;
+        }
+        let mut by_score = (0..routes.len()).collect::<Vec<_>>();
+        by_score.sort_by(|a, b| routes[*b].stats.score.total_cmp(&routes[*a].stats.score));
+
+        let mut kept_lines: Vec<HashSet<MapDataLineRef>> = Vec::new();
+        let mut keep = vec![false; routes.len()];
+        let mut discarded_count = 0;
+        for idx in by_score {
+            let lines = routes[idx]
+                .route
+                .iter()
+                .map(|segment| segment.get_line().clone())
+                .collect::<HashSet<_>>();
+            let total_len: f32 = lines.iter().map(|line| line.borrow().get_len_m()).sum();
+            let is_distinct = kept_lines.iter().all(|kept_lines| {
+                let shared_len: f32 = lines
+                    .intersection(kept_lines)
+                    .map(|line| line.borrow().get_len_m())
+                    .sum();
+                total_len == 0. || 1. - shared_len / total_len >= diversity.min_dissimilarity
+            });
+            if is_distinct {
+                keep[idx] = true;
+                kept_lines.push(lines);
+            } else {
+                discarded_count += 1;
+            }
+        }
+
+        trace!(discarded_count, "Similar routes discarded");
+
+        routes
+            .into_iter()
+            .zip(keep)
+            .filter_map(|(route, keep)| keep.then_some(route))
+            .collect()
+    }
+
+    fn get_weight_calcs(&self) -> Vec<WeightCalc> {
+        #[allow(unused_mut)]
+        let mut weight_calcs = vec![
+            WeightCalc {
+                name: "weight_avoid_nogo_areas".to_string(),
+                calc: weight_avoid_nogo_areas,
+            },
+            WeightCalc {
+                name: "weight_max_length".to_string(),
+                calc: weight_max_length,
+            },
+            WeightCalc {
+                name: "weight_no_sharp_turns".to_string(),
+                calc: weight_no_sharp_turns,
+            },
+            WeightCalc {
+                name: "weight_no_short_detours".to_string(),
+                calc: weight_no_short_detours,
+            },
+            WeightCalc {
+                name: "weight_no_steep_grades".to_string(),
+                calc: weight_no_steep_grades,
+            },
+            WeightCalc {
+                name: "weight_prefer_curvy".to_string(),
+                calc: weight_prefer_curvy,
+            },
+            WeightCalc {
+                name: "weight_avoid_urban".to_string(),
+                calc: weight_avoid_urban,
+            },
+            WeightCalc {
+                name: "weight_progress_speed".to_string(),
+                calc: weight_progress_speed,
+            },
+            WeightCalc {
+                name: "weight_check_distance_to_next".to_string(),
+                calc: weight_check_distance_to_next,
+            },
+            WeightCalc {
+                name: "weight_prefer_same_road".to_string(),
+                calc: weight_prefer_same_road,
+            },
+            WeightCalc {
+                name: "weight_no_same_road_return".to_string(),
+                calc: weight_no_same_road_return,
+            },
+            WeightCalc {
+                name: "weight_no_loops".to_string(),
+                calc: weight_no_loops,
+            },
+            WeightCalc {
+                name: "weight_heading".to_string(),
+                calc: weight_heading,
+            },
+            WeightCalc {
+                name: "weight_rules_highway".to_string(),
+                calc: weight_rules_highway,
+            },
+            WeightCalc {
+                name: "weight_rules_surface".to_string(),
+                calc: weight_rules_surface,
+            },
+            WeightCalc {
+                name: "weight_rules_smoothness".to_string(),
+                calc: weight_rules_smoothness,
+            },
+            WeightCalc {
+                name: "weight_tag_preferences".to_string(),
+                calc: weight_tag_preferences,
+            },
+            WeightCalc {
+                name: "weight_tag_matchers".to_string(),
+                calc: weight_tag_matchers,
+            },
+            WeightCalc {
+                name: "weight_check_avoid_rules".to_string(),
+                calc: weight_check_avoid_rules,
+            },
+        ];
+        #[cfg(feature = "scripting")]
+        if !self.rules.scripted_weights.is_empty() {
+            weight_calcs.push(WeightCalc {
+                name: "weight_scripted".to_string(),
+                calc: weight_scripted,
+            });
+        }
+        weight_calcs
+    }
+
+    /// Bearing adjustments the itineraries are generated with in turn. Round trips without a
+    /// bearing sweep all the directions, the ones with a bearing retry around it
+    fn round_trip_bearing_adjustments(&self) -> Vec<f32> {
+        match self.round_trip {
+            Some((None, _)) => ROUND_TRIP_SWEEP_BEARINGS_DEG.to_vec(),
+            Some((Some(_), _)) => std::iter::once(0.)
+                .chain(
+                    self.rules
+                        .generation
+                        .route_generation_retry
+                        .round_trip_adjustment_bearing_deg
+                        .iter()
+                        .copied(),
+                )
+                .collect(),
+            // no adjustment by default, only for round trip
+            None => vec![0.],
+        }
+    }
+
     #[tracing::instrument(skip(self))]
-    pub fn generate_routes(self) -> Result<Vec<RouteWithStats>, GeneratorError> {
+    pub fn generate_routes(mut self) -> Result<Vec<RouteWithStats>, GeneratorError> {
+        let _phase = timings::phase!("route");
+        let counters = self.counters.get_or_insert_with(Default::default).clone();
+        let routes = self.generate_counted_routes();
+        counters.summary().log();
+        profiler::frame!();
+        routes
+    }
+
+    fn generate_counted_routes(self) -> Result<Vec<RouteWithStats>, GeneratorError> {
+        profiler::zone!("generate_routes");
+        load_scripts(&self.rules).map_err(|error| GeneratorError::Scripting { error })?;
         let route_generation_start = Instant::now();
         let mut routes: Vec<Route> = Vec::new();
+        let mut best_partial: Option<PartialRoute> = None;
+        let bearing_sweep = matches!(self.round_trip, Some((None, _)));
+        let progress = self
+            .progress
+            .clone()
+            .map(|callback| Arc::new(ProgressReporter::new(callback)));
+        let dead_ends = Arc::new(DeadEnds::new());
         'outer: for avoid_residential in self
             .rules
             .generation
@@ -305,103 +638,153 @@ impl Generator {
             .avoid_residential
             .iter()
         {
-            // no adjustment by default, only for round trip
-            let mut adjustments = vec![0.];
-            if self.round_trip.is_some() {
-                adjustments.append(
-                    &mut self
-                        .rules
-                        .generation
-                        .route_generation_retry
-                        .round_trip_adjustment_bearing_deg
-                        .clone(),
-                );
-            }
-            for adjustment in adjustments {
-                if routes.len()
-                    >= self
-                        .rules
-                        .generation
-                        .route_generation_retry
-                        .trigger_min_route_count
+            for (adjustment_idx, adjustment) in self
+                .round_trip_bearing_adjustments()
+                .into_iter()
+                .enumerate()
+            {
+                // a bearing sweep is always completed so that all directions are compared
+                if (!bearing_sweep || adjustment_idx == 0)
+                    && routes.len()
+                        >= self
+                            .rules
+                            .generation
+                            .route_generation_retry
+                            .trigger_min_route_count
+                {
+                    break 'outer;
+                }
+                if self.is_cancelled() {
+                    info!("Route generation cancelled");
+                    return Err(GeneratorError::Cancelled);
+                }
+                if self
+                    .deadline
+                    .is_some_and(|deadline| Instant::now() >= deadline)
                 {
+                    info!(routes_count = routes.len(), "Deadline reached");
                     break 'outer;
                 }
                 let itineraries = self.generate_itineraries(*avoid_residential, Some(adjustment));
                 let itineraries = self.dedupe_itineraries(itineraries)?;
                 let itinerary_count = itineraries.len();
+                if let Some(progress) = &progress {
+                    progress.add_itineraries(itinerary_count);
+                }
 
                 DebugWriter::write_itineraries(&itineraries);
 
                 let route_gen_start_instant = Instant::now();
 
-                let mut routes_new = itineraries
+                let nav_results = itineraries
                     .into_par_iter()
                     .map(|itinerary| {
-                        Navigator::new(
+                        let nav_result = Navigator::new(
                             itinerary,
                             self.rules.clone(),
-                            vec![
-                                WeightCalc {
-                                    name: "weight_avoid_nogo_areas".to_string(),
-                                    calc: weight_avoid_nogo_areas,
-                                },
-                                WeightCalc {
-                                    name: "weight_no_sharp_turns".to_string(),
-                                    calc: weight_no_sharp_turns,
-                                },
-                                WeightCalc {
-                                    name: "weight_no_short_detours".to_string(),
-                                    calc: weight_no_short_detours,
-                                },
-                                WeightCalc {
-                                    name: "weight_progress_speed".to_string(),
-                                    calc: weight_progress_speed,
-                                },
-                                WeightCalc {
-                                    name: "weight_check_distance_to_next".to_string(),
-                                    calc: weight_check_distance_to_next,
-                                },
-                                WeightCalc {
-                                    name: "weight_prefer_same_road".to_string(),
-                                    calc: weight_prefer_same_road,
-                                },
-                                WeightCalc {
-                                    name: "weight_no_loops".to_string(),
-                                    calc: weight_no_loops,
-                                },
-                                WeightCalc {
-                                    name: "weight_heading".to_string(),
-                                    calc: weight_heading,
-                                },
-                                WeightCalc {
-                                    name: "weight_rules_highway".to_string(),
-                                    calc: weight_rules_highway,
-                                },
-                                WeightCalc {
-                                    name: "weight_rules_surface".to_string(),
-                                    calc: weight_rules_surface,
-                                },
-                                WeightCalc {
-                                    name: "weight_rules_smoothness".to_string(),
-                                    calc: weight_rules_smoothness,
-                                },
-                                WeightCalc {
-                                    name: "weight_check_avoid_rules".to_string(),
-                                    calc: weight_check_avoid_rules,
-                                },
-                            ],
+                            self.get_weight_calcs(),
                             self.round_trip.is_some(),
                         )
-                        .generate_routes()
-                    })
-                    .filter_map(|nav_route| match nav_route {
-                        NavigationResult::Stuck => None,
-                        NavigationResult::Finished(route) => Some(route),
-                        NavigationResult::Stopped => None,
+                        .with_deadline(self.deadline)
+                        .with_cancellation(self.cancellation.clone())
+                        .with_progress(progress.clone())
+                        .with_counters(self.counters.clone())
+                        // dead ends shared between navigators running in parallel change the
+                        // steps taken from run to run, seeded runs keep them per itinerary
+                        .with_dead_ends(Some(if self.seed.is_some() {
+                            Arc::new(DeadEnds::new())
+                        } else {
+                            dead_ends.clone()
+                        }))
+                        .with_seed(self.seed)
+                        .generate_routes();
+                        let nav_result = match nav_result {
+                            NavigationResult::Finished(route) => NavigationResult::Finished(
+                                route.without_spikes(SPIKE_MAX_LEN_M, |point| {
+                                    self.vias.iter().any(|via| {
+                                        point.borrow().distance_between(&via.point) <= via.radius
+                                    })
+                                }),
+                            ),
+                            nav_result => nav_result,
+                        };
+                        if let Some(counters) = &self.counters {
+                            counters.itinerary_done(&nav_result);
+                        }
+                        if progress.is_some() || self.route_callback.is_some() {
+                            let stats = match &nav_result {
+                                NavigationResult::Finished(route) => {
+                                    Some(route.calc_stats(&self.rules))
+                                }
+                                _ => None,
+                            };
+                            if let Some(progress) = &progress {
+                                progress.itinerary_done(stats.as_ref().map(|stats| stats.score));
+                            }
+                            if let (
+                                Some(route_callback),
+                                Some(mut stats),
+                                NavigationResult::Finished(route),
+                            ) = (&self.route_callback, stats, &nav_result)
+                            {
+                                if route.get_segment_count() > 0
+                                    && self.rules.generation.route_length.contains(stats.len_m)
+                                {
+                                    stats.vias_visited = self.get_vias_visited(route);
+                                    route_callback(RouteWithStats {
+                                        stats,
+                                        route: route.clone(),
+                                    });
+                                }
+                            }
+                        }
+                        nav_result
                     })
                     .collect::<Vec<_>>();
 
+                if let Some(progress) = &progress {
+                    progress.report();
+                }
+
+                if self.is_cancelled() {
+                    info!("Route generation cancelled");
+                    return Err(GeneratorError::Cancelled);
+                }
+
+                let mut routes_new = Vec::new();
+                for nav_result in nav_results {
+                    match nav_result {
+                        NavigationResult::Stuck | NavigationResult::Stopped => {}
+                        NavigationResult::Finished(route) => routes_new.push(route),
+                        NavigationResult::Partial(partial) => {
+                            if best_partial
+                                .as_ref()
+                                .is_none_or(|best| partial.is_better_than(best))
+                            {
+                                best_partial = Some(partial);
+                            }
+                        }
+                    }
+                }
+
+                let routes_count_before_len_check = routes_new.len();
+                routes_new.retain(|route| {
+                    // nothing is left of routes that only go out and back once the spikes are
+                    // removed
+                    route.get_segment_count() > 0
+                        && self
+                            .rules
+                            .generation
+                            .route_length
+                            .contains(route.get_len_m())
+                });
+                if routes_new.len() < routes_count_before_len_check {
+                    info!(
+                        discarded_count = routes_count_before_len_check - routes_new.len(),
+                        "Empty routes and routes outside of the length window discarded"
+                    );
+                }
+
                 let route_gen_duration_secs = route_gen_start_instant.elapsed().as_secs();
                 info!(
                     route_gen_duration_secs,
@@ -415,28 +798,52 @@ impl Generator {
             }
         }
 
-        let clustering = match Clustering::generate(&routes) {
+        if routes.is_empty() {
+            if let Some(partial) = best_partial {
+                info!(
+                    waypoints_passed = partial.waypoints_passed,
+                    distance_to_next_m = partial.distance_to_next_m,
+                    "No finished routes, returning partial route"
+                );
+                let mut stats = partial.route.calc_stats(&self.rules);
+                if !stats.violations.is_empty() {
+                    error!(violations = ?stats.violations, "Partial route breaks traffic rules");
+                }
+                stats.partial = true;
+                stats.vias_visited = self.get_vias_visited(&partial.route);
+                return Ok(vec![RouteWithStats {
+                    stats,
+                    route: partial.route,
+                }]);
+            }
+        }
+
+        let clustering = match Clustering::generate(&routes, &self.rules.generation.clustering) {
             None => return Ok(Vec::new()),
             Some(c) => c,
         };
 
-        let mut cluster_best: HashMap<i32, RouteWithStats> = HashMap::new();
+        let mut cluster_best: BTreeMap<i32, RouteWithStats> = BTreeMap::new();
         let mut noise = Vec::new();
         let _routes: Vec<_> = routes
             .iter()
             .enumerate()
             .map(|(idx, route)| {
                 let mut stats = route.calc_stats(&self.rules);
+                if !stats.violations.is_empty() {
+                    error!(violations = ?stats.violations, "Generated route breaks traffic rules");
+                }
                 let approx_route = &clustering.approximated_routes[idx];
-                stats.cluster = Some(clustering.labels[idx] as usize);
+                let label = clustering.labels[idx];
+                stats.cluster = (label != NOISE_LABEL).then_some(label as usize);
                 stats.approximated_route = approx_route.iter().map(|p| (p[0], p[1])).collect();
+                stats.vias_visited = self.get_vias_visited(route);
                 let route_with_stats = RouteWithStats {
                     stats,
                     route: route.clone(),
                 };
 
-                let label = clustering.labels[idx];
-                if label != -1 {
+                if label != NOISE_LABEL {
                     if let Some(current_best) = cluster_best.get(&label) {
                         if current_best.stats.score < route_with_stats.stats.score {
                             cluster_best.insert(label, route_with_stats.clone());
@@ -454,11 +861,18 @@ impl Generator {
         trace!(route_count = routes.len(), "routes");
         trace!(noise_count = noise.len(), "noise");
 
-        let mut best_routes = cluster_best.into_iter().map(|el| el.1).collect::<Vec<_>>();
+        let mut best_routes = cluster_best
+            .into_values()
+            .map(|mut route| {
+                route.stats.cluster_best = true;
+                route
+            })
+            .collect::<Vec<_>>();
         noise.sort_by(|a, b| b.stats.score.total_cmp(&a.stats.score));
 
         let noise_count = if best_routes.len() > 10 { 3 } else { 10 };
         best_routes.append(&mut noise[..noise.len().min(noise_count)].to_vec());
+        let best_routes = self.filter_similar_routes(best_routes);
 
         let route_generation_duration_secs = route_generation_start.elapsed().as_secs();
         info!(route_generation_duration_secs, "Route generation finished");
@@ -466,3 +880,157 @@ impl Generator {
         Ok(best_routes)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rusty_fork::rusty_fork_test;
+
+    use crate::{
+        map_data::{
+            graph::MapDataGraph,
+            osm::{OsmNode, OsmWay},
+        },
+        router::{route::segment::Segment, rules::RouterRules},
+        test_utils::{
+            graph_from_test_dataset, line_is_between_point_ids, set_graph_static, test_dataset_1,
+        },
+    };
+
+    use std::collections::{HashMap, HashSet};
+
+    use geo::{Destination, Haversine, Point};
+
+    use super::{Generator, Route, RouteWithStats, ROUND_TRIP_SWEEP_BEARINGS_DEG};
+
+    fn route_with_score(point_ids: &[u64], score: f64) -> RouteWithStats {
+        let segments = point_ids
+            .windows(2)
+            .map(|ids| {
+                let to = MapDataGraph::get()
+                    .test_get_point_ref_by_id(&ids[1])
+                    .unwrap();
+                let line = to
+                    .lines()
+                    .iter()
+                    .find(|line| line_is_between_point_ids(line, ids[0], ids[1]))
+                    .expect("line to exist")
+                    .clone();
+                Segment::new(line, to)
+            })
+            .collect::<Vec<_>>();
+        let route = Route::from(segments);
+        let mut stats = route.calc_stats(&RouterRules::default());
+        stats.score = score;
+        RouteWithStats { stats, route }
+    }
+
+    fn generator(rules: RouterRules) -> Generator {
+        let start = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+        Generator::new(start.clone(), start, Vec::new(), None, rules)
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn round_trip_without_bearing_sweeps_bearings() {
+            // spokes 5 km from the start at 1 in every swept direction
+            let center = Point::new(24_f64, 57.);
+            let mut map_data = MapDataGraph::new();
+            map_data
+                .insert_node(OsmNode {
+                    id: 1,
+                    lat: 57.,
+                    lon: 24.,
+                    residential_in_proximity: false,
+                    nogo_area: false,
+                    elevation: None,
+                })
+                .unwrap();
+            for (idx, bearing) in ROUND_TRIP_SWEEP_BEARINGS_DEG.iter().enumerate() {
+                let id = idx as u64 + 2;
+                let spoke = Haversine.destination(center, f64::from(*bearing), 5000.);
+                map_data
+                    .insert_node(OsmNode {
+                        id,
+                        lat: spoke.y(),
+                        lon: spoke.x(),
+                        residential_in_proximity: false,
+                        nogo_area: false,
+                        elevation: None,
+                    })
+                    .unwrap();
+                map_data
+                    .insert_way(OsmWay {
+                        id,
+                        point_ids: vec![1, id],
+                        tags: Some(HashMap::from([(
+                            "highway".to_string(),
+                            "primary".to_string(),
+                        )])),
+                    })
+                    .unwrap();
+            }
+            map_data.generate_point_hashes();
+            set_graph_static(map_data);
+            let start = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let mut rules = RouterRules::default();
+            rules.generation.waypoint_generation.round_trip.variation_distance_ratios = vec![1.];
+            rules.generation.waypoint_generation.round_trip.variation_bearing_deg = vec![0.];
+
+            let generator =
+                Generator::new(start.clone(), start.clone(), Vec::new(), Some((None, 25000)), rules);
+            let adjustments = generator.round_trip_bearing_adjustments();
+            assert_eq!(adjustments, ROUND_TRIP_SWEEP_BEARINGS_DEG.to_vec());
+
+            let tip_ids = adjustments
+                .into_iter()
+                .map(|adjustment| {
+                    let itineraries = generator.generate_itineraries(false, Some(adjustment));
+                    assert_eq!(itineraries.len(), 1);
+                    let tip = itineraries[0].waypoints[1].clone();
+                    let tip_bearing = start.borrow().bearing(&tip);
+                    let offset = (tip_bearing - adjustment).rem_euclid(360.);
+                    assert!(
+                        offset.min(360. - offset) < 1.,
+                        "tip at {tip_bearing} for {adjustment}"
+                    );
+                    tip.borrow().id
+                })
+                .collect::<HashSet<_>>();
+            assert_eq!(tip_ids.len(), ROUND_TRIP_SWEEP_BEARINGS_DEG.len());
+
+            let generator =
+                Generator::new(start.clone(), start, Vec::new(), Some((Some(90.), 25000)), RouterRules::default());
+            assert_eq!(
+                generator.round_trip_bearing_adjustments(),
+                vec![0., -135., -90., -45., 45., 90., 135.]
+            );
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn similar_routes_filtered_when_enabled() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let routes = || {
+                vec![
+                    route_with_score(&[5, 3, 6, 7], 1.),
+                    route_with_score(&[1, 2, 3, 4], 3.),
+                    route_with_score(&[1, 2, 3], 2.),
+                ]
+            };
+            let scores = |routes: Vec<RouteWithStats>| {
+                routes.iter().map(|route| route.stats.score).collect::<Vec<_>>()
+            };
+
+            let unfiltered = generator(RouterRules::default()).filter_similar_routes(routes());
+            assert_eq!(scores(unfiltered), vec![1., 3., 2.]);
+
+            let mut rules = RouterRules::default();
+            rules.generation.route_diversity.enabled = true;
+            let filtered = generator(rules).filter_similar_routes(routes());
+            assert_eq!(scores(filtered), vec![1., 3.]);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::fmt::Display;
+use std::{collections::HashMap, fmt::Display};
 
 use crate::map_data::graph::MapDataPointRef;
 
@@ -8,6 +8,14 @@ pub struct WaypointHistoryElement {
     pub from_point: MapDataPointRef,
 }
 
+pub const VIA_DEFAULT_RADIUS_M: f32 = 500.;
+
+#[derive(Clone, Debug)]
+pub struct ItineraryVia {
+    pub point: MapDataPointRef,
+    pub radius: f32,
+}
+
 #[derive(Clone, Debug)]
 pub struct Itinerary {
     pub start: MapDataPointRef,
@@ -15,6 +23,7 @@ pub struct Itinerary {
     pub waypoints: Vec<MapDataPointRef>,
     pub next: MapDataPointRef,
     pub waypoint_radius: f32,
+    pub via_radius: HashMap<MapDataPointRef, f32>,
     pub switched_wps_on: Vec<WaypointHistoryElement>,
     pub check_loop_since_last_wp: bool,
     pub visit_all_wps: bool,
@@ -49,11 +58,22 @@ impl Itinerary {
             next: waypoints.first().map_or(finish.clone(), |w| w.clone()),
             waypoints,
             finish,
+            via_radius: HashMap::new(),
             switched_wps_on: Vec::new(),
             check_loop_since_last_wp: false,
             visit_all_wps: false,
         }
     }
+    pub fn with_vias(mut self, vias: &[ItineraryVia]) -> Self {
+        self.via_radius = vias
+            .iter()
+            .map(|via| (via.point.clone(), via.radius))
+            .collect();
+        if !vias.is_empty() {
+            self.visit_all_wps = true;
+        }
+        self
+    }
     pub fn new_round_trip(
         start: MapDataPointRef,
         finish: MapDataPointRef,
@@ -66,12 +86,24 @@ impl Itinerary {
             next: waypoints.first().map_or(finish.clone(), |w| w.clone()),
             waypoints,
             finish,
+            via_radius: HashMap::new(),
             switched_wps_on: Vec::new(),
             check_loop_since_last_wp: false,
             visit_all_wps: true,
         }
     }
 
+    /// Straight line distance from start through all the waypoints to finish
+    pub fn straight_line_len_m(&self) -> f32 {
+        let mut len_m = 0.;
+        let mut prev = &self.start;
+        for point in self.waypoints.iter().chain(std::iter::once(&self.finish)) {
+            len_m += prev.borrow().distance_between(point);
+            prev = point;
+        }
+        len_m
+    }
+
     pub fn id(&self) -> String {
         format!(
             "{}-{}-{}",
@@ -99,9 +131,16 @@ impl Itinerary {
         false
     }
 
+    fn get_next_radius(&self) -> f32 {
+        self.via_radius
+            .get(&self.next)
+            .copied()
+            .unwrap_or(self.waypoint_radius)
+    }
+
     pub fn check_set_next(&mut self, current: MapDataPointRef) -> bool {
         if self.next != self.finish
-            && current.borrow().distance_between(&self.next) <= self.waypoint_radius
+            && current.borrow().distance_between(&self.next) <= self.get_next_radius()
         {
             if let Some(idx) = self.waypoints.iter().position(|w| w == &self.next) {
                 let prev_point = self.next.clone();
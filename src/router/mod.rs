@@ -1,8 +1,16 @@
+pub mod cancellation;
 pub mod clustering;
 pub mod generator;
 pub mod itinerary;
 pub mod navigator;
+pub mod progress;
 pub mod route;
+pub mod rule_conditions;
 pub mod rules;
+pub mod rules_check;
+pub mod scripting;
+pub mod shortest_path;
+pub mod summary;
+pub mod via_order;
 pub mod walker;
 pub mod weights;
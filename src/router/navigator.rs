@@ -1,21 +1,46 @@
 use std::{
+    cmp::Ordering,
     collections::{HashMap, HashSet},
     fmt::Debug,
+    sync::{Arc, RwLock},
 };
 
+use rayon::prelude::*;
 use tracing::trace;
+use web_time::Instant;
 
 use crate::{
-    debug::writer::DebugWriter, map_data::graph::MapDataPointRef, router::rules::RouterRules,
+    debug::writer::DebugWriter,
+    map_data::graph::{MapDataGraph, MapDataPointRef},
+    profiler,
+    router::rules::RouterRules,
 };
 
 use super::{
+    cancellation::CancellationToken,
     itinerary::Itinerary,
+    progress::ProgressReporter,
     route::Route,
+    summary::GenerationCounters,
     walker::{Walker, WalkerMoveResult},
     weights::{WeightCalc, WeightCalcInput},
 };
 
+/// Step budget bounds when the step limit is not set in the rules
+const STEP_LIMIT_MIN: u32 = 10_000;
+const STEP_LIMIT_MAX: u32 = 300_000;
+
+/// Steps allowed for every junction expected to be passed on the way
+const STEP_LIMIT_PER_JUNCTION: f64 = 100.;
+
+/// Estimates the steps needed from the straight line distance of the trip and the junctions per
+/// square km of the map data, a straight line crossing an area passes about the square root of
+/// its junction density per km
+pub fn get_adaptive_step_limit(straight_line_len_m: f32, junction_density_per_km2: f64) -> u32 {
+    let junctions_passed = straight_line_len_m as f64 / 1000. * junction_density_per_km2.sqrt();
+    ((junctions_passed * STEP_LIMIT_PER_JUNCTION) as u32).clamp(STEP_LIMIT_MIN, STEP_LIMIT_MAX)
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum WeightCalcResult {
     ForkChoiceUseWithWeight(u8),
@@ -85,10 +110,21 @@ impl DiscardedForkChoices {
     }
 }
 
+/// Mixes the seed with a value, used to break ties between equally weighted fork choices the same
+/// way on every run with the same seed
+pub(crate) fn seeded_hash(seed: u64, value: u64) -> u64 {
+    // splitmix64 finalizer
+    let mut hash = seed ^ value.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
 #[derive(Clone)]
 pub struct ForkWeights {
     pub discard_fork: bool,
     weight_list: HashMap<MapDataPointRef, u32>,
+    tie_break_seed: Option<u64>,
 }
 
 impl ForkWeights {
@@ -96,8 +132,14 @@ impl ForkWeights {
         Self {
             discard_fork: false,
             weight_list: HashMap::new(),
+            tie_break_seed: None,
         }
     }
+
+    pub fn with_tie_break_seed(mut self, tie_break_seed: Option<u64>) -> Self {
+        self.tie_break_seed = tie_break_seed;
+        self
+    }
     pub fn add_calc_result(
         &mut self,
         choice_point_ref: &MapDataPointRef,
@@ -135,7 +177,14 @@ impl ForkWeights {
 
     fn get_choices_sorted_by_weight(&self) -> Vec<(&MapDataPointRef, &u32)> {
         let mut vec = self.weight_list.iter().collect::<Vec<_>>();
-        vec.sort_by(|v, v2| v2.1.cmp(v.1));
+        vec.sort_by(|v, v2| {
+            v2.1.cmp(v.1).then_with(|| match self.tie_break_seed {
+                None => Ordering::Equal,
+                Some(seed) => {
+                    seeded_hash(seed, v.0.borrow().id).cmp(&seeded_hash(seed, v2.0.borrow().id))
+                }
+            })
+        });
         vec
     }
 
@@ -165,10 +214,82 @@ impl Debug for ForkWeights {
     }
 }
 
+/// Fork choices found to lead only into dead ends, shared by the navigators of a generator run so
+/// the same cul-de-sac networks are not explored again for every itinerary. Only valid for
+/// itineraries with the same finish, as dead ends containing the finish are not recorded.
+#[derive(Debug, Default)]
+pub struct DeadEnds {
+    choices: RwLock<HashSet<(MapDataPointRef, MapDataPointRef)>>,
+}
+
+impl DeadEnds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&self, fork_point: MapDataPointRef, choice_point: MapDataPointRef) {
+        self.choices
+            .write()
+            .unwrap_or_else(|error| error.into_inner())
+            .insert((fork_point, choice_point));
+    }
+
+    pub fn get_dead_end_choices(
+        &self,
+        fork_point: &MapDataPointRef,
+        choice_points: &[MapDataPointRef],
+    ) -> Vec<MapDataPointRef> {
+        let choices = self
+            .choices
+            .read()
+            .unwrap_or_else(|error| error.into_inner());
+        choice_points
+            .iter()
+            .filter(|choice_point| choices.contains(&(fork_point.clone(), (*choice_point).clone())))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Choice made at a fork, with the route length at the fork to find the segments walked after it
+#[derive(Debug)]
+struct ForkChoiceMade {
+    fork_point: MapDataPointRef,
+    choice_point: MapDataPointRef,
+    segment_count: usize,
+}
+
+/// Route walked up to the point where navigation got closest to finishing the itinerary
+#[derive(Debug, Clone)]
+pub struct PartialRoute {
+    pub route: Route,
+    pub waypoints_passed: usize,
+    pub distance_to_next_m: f32,
+}
+
+impl PartialRoute {
+    /// Passing more waypoints is progress, the distance to the next one only decides between
+    /// routes that passed the same number of them
+    pub fn is_better_than(&self, other: &PartialRoute) -> bool {
+        self.waypoints_passed > other.waypoints_passed
+            || (self.waypoints_passed == other.waypoints_passed
+                && self.distance_to_next_m < other.distance_to_next_m)
+    }
+}
+
+/// Best partial route so far. Until the walker moves back past its end it is the start of the route
+/// walked, and only copied out of it then
+struct BestPartial {
+    partial: PartialRoute,
+    walked_segment_count: Option<usize>,
+}
+
 pub enum NavigationResult {
     Stuck,
     Stopped,
     Finished(Route),
+    /// Returned instead of `Stuck` or `Stopped` when partial routes are enabled in the rules
+    Partial(PartialRoute),
 }
 
 pub struct Navigator {
@@ -177,6 +298,14 @@ pub struct Navigator {
     walker: Walker,
     weight_calcs: Vec<WeightCalc>,
     discarded_fork_choices: DiscardedForkChoices,
+    best_partial: Option<BestPartial>,
+    deadline: Option<Instant>,
+    cancellation: Option<CancellationToken>,
+    progress: Option<Arc<ProgressReporter>>,
+    counters: Option<Arc<GenerationCounters>>,
+    dead_ends: Option<Arc<DeadEnds>>,
+    fork_choices_made: Vec<ForkChoiceMade>,
+    seed: Option<u64>,
 }
 
 impl Navigator {
@@ -192,16 +321,182 @@ impl Navigator {
             rules,
             weight_calcs,
             discarded_fork_choices: DiscardedForkChoices::new(reset_at_new_next),
+            best_partial: None,
+            deadline: None,
+            cancellation: None,
+            progress: None,
+            counters: None,
+            dead_ends: None,
+            fork_choices_made: Vec::new(),
+            seed: None,
+        }
+    }
+
+    pub fn with_deadline(mut self, deadline: Option<Instant>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    pub fn with_cancellation(mut self, cancellation: Option<CancellationToken>) -> Self {
+        self.walker = self.walker.with_cancellation(cancellation.clone());
+        self.cancellation = cancellation;
+        self
+    }
+
+    pub fn with_progress(mut self, progress: Option<Arc<ProgressReporter>>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    pub fn with_counters(mut self, counters: Option<Arc<GenerationCounters>>) -> Self {
+        self.counters = counters;
+        self
+    }
+
+    pub fn with_dead_ends(mut self, dead_ends: Option<Arc<DeadEnds>>) -> Self {
+        self.dead_ends = dead_ends;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Moves back to the previous fork. When the choice made there is known to lead only into
+    /// dead ends, it is recorded for the other navigators.
+    fn move_back(&mut self, into_dead_end: bool) {
+        profiler::zone!("navigator_move_back");
+        if let (true, Some(counters)) = (into_dead_end, &self.counters) {
+            counters.dead_end();
+        }
+        let choice_made = self.fork_choices_made.pop();
+        let dead_end_choice = choice_made.filter(|choice_made| {
+            into_dead_end
+                && self.dead_ends.is_some()
+                && (choice_made.segment_count..self.walker.get_route().get_segment_count()).all(
+                    |idx| {
+                        self.walker
+                            .get_route()
+                            .get_segment_by_index(idx)
+                            .is_none_or(|segment| *segment.get_end_point() != self.itinerary.finish)
+                    },
+                )
+        });
+
+        if self
+            .itinerary
+            .check_set_back(self.walker.get_last_point().clone())
+        {
+            self.discarded_fork_choices.set_prev_next();
+        }
+        let prev_fork_segment_count = self.walker.get_prev_fork_segment_count();
+        self.save_best_partial(prev_fork_segment_count);
+        self.walker.move_backwards_to(prev_fork_segment_count);
+
+        let segment_count = self.walker.get_route().get_segment_count();
+        while self
+            .fork_choices_made
+            .last()
+            .is_some_and(|choice_made| choice_made.segment_count >= segment_count)
+        {
+            self.fork_choices_made.pop();
+        }
+        // only recorded when the walker is back at the fork the choice was made at, otherwise
+        // the choices made and the route walked are out of step
+        if let (Some(dead_end_choice), Some(dead_ends)) = (dead_end_choice, &self.dead_ends) {
+            if dead_end_choice.segment_count == segment_count
+                && *self.walker.get_last_point() == dead_end_choice.fork_point
+            {
+                dead_ends.add(dead_end_choice.fork_point, dead_end_choice.choice_point);
+            }
+        }
+    }
+
+    fn update_best_partial(&mut self) {
+        if !self.rules.generation.partial_routes.enabled
+            || self.walker.get_route().get_segment_count() == 0
+        {
+            return;
+        }
+        let partial = PartialRoute {
+            route: Route::new(),
+            waypoints_passed: self.itinerary.switched_wps_on.len(),
+            distance_to_next_m: self
+                .walker
+                .get_last_point()
+                .borrow()
+                .distance_between(&self.itinerary.next),
+        };
+        if self
+            .best_partial
+            .as_ref()
+            .is_none_or(|best| partial.is_better_than(&best.partial))
+        {
+            self.best_partial = Some(BestPartial {
+                partial,
+                walked_segment_count: Some(self.walker.get_route().get_segment_count()),
+            });
         }
     }
 
+    /// Copies the best partial route out of the route walked when moving back past its end
+    fn save_best_partial(&mut self, segment_count_after_move: usize) {
+        let Some(best) = &mut self.best_partial else {
+            return;
+        };
+        if let Some(walked_segment_count) = best
+            .walked_segment_count
+            .filter(|count| *count > segment_count_after_move)
+        {
+            best.partial.route = Route::from(
+                self.walker
+                    .get_route()
+                    .get_route_chunk(0, walked_segment_count)
+                    .to_vec(),
+            );
+            best.walked_segment_count = None;
+        }
+    }
+
+    fn stopped_result(self, result: NavigationResult) -> NavigationResult {
+        let Some(mut best) = self.best_partial else {
+            return result;
+        };
+        if let Some(walked_segment_count) = best.walked_segment_count {
+            let mut route = self.walker.into_route();
+            route.truncate(walked_segment_count);
+            best.partial.route = route;
+        }
+        NavigationResult::Partial(best.partial)
+    }
+
     #[tracing::instrument(skip(self), fields(id = self.itinerary.id()))]
     pub fn generate_routes(mut self) -> NavigationResult {
+        profiler::zone!("navigator_generate_routes", &self.itinerary.id());
         trace!("Route gen for itinerary {}", self.itinerary);
 
+        let step_limit = self.rules.basic.step_limit.as_ref().map_or_else(
+            || {
+                get_adaptive_step_limit(
+                    self.itinerary.straight_line_len_m(),
+                    MapDataGraph::get().get_junction_density_per_km2(),
+                )
+            },
+            |step_limit| step_limit.0,
+        );
+        trace!(step_limit, "Step limit");
+
         let mut loop_counter = 0;
         loop {
+            profiler::zone!("navigator_step");
             loop_counter += 1;
+            if let Some(progress) = &self.progress {
+                progress.step();
+            }
+            if let Some(counters) = &self.counters {
+                counters.step();
+            }
 
             let move_result = self
                 .walker
@@ -213,16 +508,34 @@ impl Navigator {
                 &move_result,
                 self.walker.get_route(),
             );
+            trace!(
+                step = loop_counter,
+                last_point = %self.walker.get_last_point(),
+                move_result = ?move_result,
+                "Step"
+            );
 
             if move_result == Ok(WalkerMoveResult::Finish) {
-                return NavigationResult::Finished(self.walker.get_route().clone());
+                return NavigationResult::Finished(self.walker.into_route());
             }
+            self.update_best_partial();
             if let Ok(WalkerMoveResult::Fork(fork_choices)) = move_result {
+                if let Some(counters) = &self.counters {
+                    counters.fork_evaluated();
+                }
                 let last_point = self.walker.get_last_point();
-                let discarded_choices = &self
+                let mut discarded_choices = self
                     .discarded_fork_choices
                     .get_discarded_choices_for_point(last_point)
-                    .map_or(Vec::new(), |d| d);
+                    .unwrap_or_default();
+                let all_choices = fork_choices.get_all_segment_points();
+                let dead_end_choices = self.dead_ends.as_ref().map_or(Vec::new(), |dead_ends| {
+                    dead_ends.get_dead_end_choices(last_point, &all_choices)
+                });
+                let all_choices_dead_ends =
+                    !all_choices.is_empty() && dead_end_choices.len() == all_choices.len();
+                discarded_choices.extend(dead_end_choices);
+                let discarded_choices = &discarded_choices;
                 DebugWriter::write_fork_choices(
                     self.itinerary.id(),
                     loop_counter,
@@ -235,40 +548,53 @@ impl Navigator {
                     self.discarded_fork_choices.set_new_next();
                 }
 
-                let fork_weights = fork_choices.clone().into_iter().fold(
-                    ForkWeights::new(),
-                    |mut fork_weights, fork_route_segment| {
-                        if !fork_weights.discard_fork {
-                            let fork_weight_calc_results = self
-                                .weight_calcs
-                                .iter()
-                                .map(|weight_calc| {
-                                    let weight_calc_result = (weight_calc.calc)(WeightCalcInput {
-                                        route: self.walker.get_route(),
-                                        itinerary: &self.itinerary,
-                                        current_fork_segment: &fork_route_segment,
-                                        walker_from_fork: Walker::new(
-                                            fork_route_segment.get_end_point().clone(),
-                                        ),
-                                        rules: &self.rules,
-                                    });
-                                    DebugWriter::write_fork_choice_weight(
-                                        self.itinerary.id(),
-                                        loop_counter,
-                                        &fork_route_segment.get_end_point().borrow().id,
-                                        &weight_calc.name,
-                                        &weight_calc_result,
-                                    );
-                                    weight_calc_result
-                                })
-                                .collect::<Vec<_>>();
+                // fork choices are evaluated in parallel, several weight calcs walk ahead from the
+                // fork which makes them expensive in junction dense areas
+                let fork_weight_calc_results = fork_choices
+                    .clone()
+                    .into_iter()
+                    .collect::<Vec<_>>()
+                    .into_par_iter()
+                    .map(|fork_route_segment| {
+                        let weight_calc_results = self
+                            .weight_calcs
+                            .iter()
+                            .map(|weight_calc| {
+                                profiler::zone!("weight_calc", &weight_calc.name);
+                                let calc_start = Instant::now();
+                                let weight_calc_result = (weight_calc.calc)(WeightCalcInput {
+                                    route: self.walker.get_route(),
+                                    itinerary: &self.itinerary,
+                                    current_fork_segment: &fork_route_segment,
+                                    walker_from_fork: Walker::new(
+                                        fork_route_segment.get_end_point().clone(),
+                                    ),
+                                    rules: &self.rules,
+                                });
+                                DebugWriter::write_fork_choice_weight(
+                                    self.itinerary.id(),
+                                    loop_counter,
+                                    &fork_route_segment.get_end_point().borrow().id,
+                                    &weight_calc.name,
+                                    &weight_calc_result,
+                                    calc_start.elapsed(),
+                                );
+                                weight_calc_result
+                            })
+                            .collect::<Vec<_>>();
+                        (fork_route_segment, weight_calc_results)
+                    })
+                    .collect::<Vec<_>>();
 
+                let fork_weights = fork_weight_calc_results.into_iter().fold(
+                    ForkWeights::new().with_tie_break_seed(self.seed),
+                    |mut fork_weights, (fork_route_segment, weight_calc_results)| {
+                        if !fork_weights.discard_fork {
                             fork_weights.add_calc_result(
                                 fork_route_segment.get_end_point(),
-                                &fork_weight_calc_results,
+                                &weight_calc_results,
                             );
                         }
-
                         fork_weights
                     },
                 );
@@ -276,6 +602,7 @@ impl Navigator {
                 let chosen_fork_point = fork_weights.get_choice_id_by_index_from_heaviest(0);
 
                 if let Some(chosen_fork_point) = chosen_fork_point {
+                    trace!(step = loop_counter, chosen = %chosen_fork_point, "Fork choice");
                     self.discarded_fork_choices
                         .add_discarded_choice(last_point, &chosen_fork_point);
                     DebugWriter::write_step_result(
@@ -284,6 +611,11 @@ impl Navigator {
                         "ForkChoice",
                         Some(chosen_fork_point.borrow().id),
                     );
+                    self.fork_choices_made.push(ForkChoiceMade {
+                        fork_point: last_point.clone(),
+                        choice_point: chosen_fork_point.clone(),
+                        segment_count: self.walker.get_route().get_segment_count(),
+                    });
                     self.walker.set_fork_choice_point_ref(chosen_fork_point);
                 } else {
                     if self
@@ -299,15 +631,9 @@ impl Navigator {
                             "Stuck",
                             None,
                         );
-                        return NavigationResult::Stuck;
+                        return self.stopped_result(NavigationResult::Stuck);
                     }
-                    if self
-                        .itinerary
-                        .check_set_back(self.walker.get_last_point().clone())
-                    {
-                        self.discarded_fork_choices.set_prev_next();
-                    }
-                    self.walker.move_backwards_to_prev_fork();
+                    self.move_back(all_choices_dead_ends);
                     DebugWriter::write_step_result(
                         self.itinerary.id(),
                         loop_counter,
@@ -317,19 +643,35 @@ impl Navigator {
                 }
             } else if move_result == Ok(WalkerMoveResult::DeadEnd) {
                 DebugWriter::write_step_result(self.itinerary.id(), loop_counter, "MoveBack", None);
-                if self
-                    .itinerary
-                    .check_set_back(self.walker.get_last_point().clone())
-                {
-                    self.discarded_fork_choices.set_prev_next();
-                }
-                self.walker.move_backwards_to_prev_fork();
+                self.move_back(true);
             }
 
-            if loop_counter >= self.rules.basic.step_limit.0 {
+            if self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                trace!("Reached deadline at loop {loop_counter}, stopping");
+                DebugWriter::write_step_result(self.itinerary.id(), loop_counter, "Timeout", None);
+                return self.stopped_result(NavigationResult::Stopped);
+            }
+            if self
+                .cancellation
+                .as_ref()
+                .is_some_and(|cancellation| cancellation.is_cancelled())
+            {
+                trace!("Cancelled at loop {loop_counter}, stopping");
+                DebugWriter::write_step_result(
+                    self.itinerary.id(),
+                    loop_counter,
+                    "Cancelled",
+                    None,
+                );
+                return NavigationResult::Stopped;
+            }
+            if loop_counter >= step_limit {
                 trace!("Reached loop {loop_counter}, stopping");
                 DebugWriter::write_step_result(self.itinerary.id(), loop_counter, "Stopped", None);
-                return NavigationResult::Stopped;
+                return self.stopped_result(NavigationResult::Stopped);
             }
         }
     }
@@ -340,8 +682,9 @@ mod test {
     use crate::{
         map_data::graph::MapDataGraph,
         router::{
+            cancellation::CancellationToken,
             itinerary::Itinerary,
-            navigator::{NavigationResult, WeightCalcResult},
+            navigator::{DeadEnds, NavigationResult, WeightCalcResult},
             rules::RouterRules,
             weights::{WeightCalc, WeightCalcInput},
         },
@@ -350,8 +693,9 @@ mod test {
         },
     };
 
-    use super::Navigator;
+    use super::{get_adaptive_step_limit, ForkWeights, Navigator, STEP_LIMIT_MAX, STEP_LIMIT_MIN};
     use rusty_fork::rusty_fork_test;
+    use std::sync::Arc;
 
     rusty_fork_test! {
         #![rusty_fork(timeout_ms = 2000)]
@@ -517,6 +861,133 @@ mod test {
         }
     }
 
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn navigate_stuck_return_partial_route() {
+            fn weight(input: WeightCalcInput) -> WeightCalcResult {
+                if input.current_fork_segment.get_end_point().borrow().id == 7 {
+                    return WeightCalcResult::ForkChoiceDoNotUse;
+                }
+                WeightCalcResult::ForkChoiceUseWithWeight(1)
+            }
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let from = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let to = MapDataGraph::get().test_get_point_ref_by_id(&7).unwrap();
+            let itinerary = Itinerary::new_start_finish(from, to.clone(), Vec::new(), 0.);
+            let mut rules = RouterRules::default();
+            rules.generation.partial_routes.enabled = true;
+            let navigator = Navigator::new(
+                itinerary,
+                rules,
+                vec![WeightCalc{ calc: weight, name:"weight".to_string()}],
+                false
+            );
+            let NavigationResult::Partial(partial) = navigator.generate_routes() else {
+                panic!("expected a partial route");
+            };
+            let last_point = partial.route.get_segment_last().expect("route to not be empty").get_end_point().clone();
+            assert_ne!(last_point, to);
+            assert_eq!(partial.distance_to_next_m, last_point.borrow().distance_between(&to));
+        }
+    }
+
+    #[test]
+    fn adaptive_step_limit() {
+        assert_eq!(get_adaptive_step_limit(1000., 1.), STEP_LIMIT_MIN);
+        assert_eq!(get_adaptive_step_limit(200_000., 4.), 40_000);
+        assert_eq!(get_adaptive_step_limit(200_000., 100.), 200_000);
+        assert_eq!(get_adaptive_step_limit(1_000_000., 100.), STEP_LIMIT_MAX);
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn fork_weights_seeded_tie_break() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let points = [2, 3, 5, 7]
+                .map(|id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap());
+            let weight = vec![WeightCalcResult::ForkChoiceUseWithWeight(5)];
+            for seed in 0..10 {
+                let mut fork_weights = ForkWeights::new().with_tie_break_seed(Some(seed));
+                let mut fork_weights_reversed = ForkWeights::new().with_tie_break_seed(Some(seed));
+                for point in points.iter() {
+                    fork_weights.add_calc_result(point, &weight);
+                }
+                for point in points.iter().rev() {
+                    fork_weights_reversed.add_calc_result(point, &weight);
+                }
+                for idx in 0..points.len() {
+                    assert_eq!(
+                        fork_weights.get_choice_id_by_index_from_heaviest(idx),
+                        fork_weights_reversed.get_choice_id_by_index_from_heaviest(idx)
+                    );
+                }
+            }
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn navigate_cancelled() {
+            fn weight(input: WeightCalcInput) -> WeightCalcResult {
+                if input.current_fork_segment.get_end_point().borrow().id == 7 {
+                    return WeightCalcResult::ForkChoiceDoNotUse;
+                }
+                WeightCalcResult::ForkChoiceUseWithWeight(1)
+            }
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let from = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let to = MapDataGraph::get().test_get_point_ref_by_id(&7).unwrap();
+            let itinerary = Itinerary::new_start_finish(from, to, Vec::new(), 0.);
+            let mut rules = RouterRules::default();
+            rules.generation.partial_routes.enabled = true;
+            let cancellation = CancellationToken::new();
+            cancellation.cancel();
+            let navigator = Navigator::new(
+                itinerary,
+                rules,
+                vec![WeightCalc{ calc: weight, name:"weight".to_string()}],
+                false
+            )
+            .with_cancellation(Some(cancellation));
+            assert!(matches!(navigator.generate_routes(), NavigationResult::Stopped));
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn navigate_records_dead_ends() {
+            fn weight(input: WeightCalcInput) -> WeightCalcResult {
+                if input.current_fork_segment.get_end_point().borrow().id == 7 {
+                    return WeightCalcResult::ForkChoiceDoNotUse;
+                }
+                WeightCalcResult::ForkChoiceUseWithWeight(1)
+            }
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let from = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let to = MapDataGraph::get().test_get_point_ref_by_id(&7).unwrap();
+            let fork = MapDataGraph::get().test_get_point_ref_by_id(&3).unwrap();
+            let dead_end_choice = MapDataGraph::get().test_get_point_ref_by_id(&5).unwrap();
+            let itinerary = Itinerary::new_start_finish(from, to, Vec::new(), 0.);
+            let dead_ends = Arc::new(DeadEnds::new());
+            let navigator = Navigator::new(
+                itinerary,
+                RouterRules::default(),
+                vec![WeightCalc{ calc: weight, name:"weight".to_string()}],
+                false
+            )
+            .with_dead_ends(Some(dead_ends.clone()));
+            assert!(matches!(navigator.generate_routes(), NavigationResult::Stuck));
+            assert_eq!(
+                dead_ends.get_dead_end_choices(&fork, std::slice::from_ref(&dead_end_choice)),
+                vec![dead_end_choice]
+            );
+        }
+    }
+
     rusty_fork_test! {
         #![rusty_fork(timeout_ms = 2000)]
         #[test]
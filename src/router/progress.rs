@@ -0,0 +1,140 @@
+use std::sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use web_time::{Duration, Instant};
+
+/// Progress is reported at most this often, except at the end of an itinerary batch
+const PROGRESS_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Steps between checks if a progress report is due, so the clock is not read on every step
+const PROGRESS_STEP_CHECK_INTERVAL: u64 = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct GenerationProgress {
+    pub itineraries_done: usize,
+    pub itineraries_total: usize,
+    pub steps: u64,
+    pub best_score: Option<f64>,
+}
+
+pub type ProgressCallback = Arc<dyn Fn(GenerationProgress) + Send + Sync>;
+
+/// Collects progress from the navigators running in parallel and passes it on to the callback
+pub struct ProgressReporter {
+    callback: ProgressCallback,
+    itineraries_done: AtomicUsize,
+    itineraries_total: AtomicUsize,
+    steps: AtomicU64,
+    best_score: Mutex<Option<f64>>,
+    last_report: Mutex<Instant>,
+}
+
+impl ProgressReporter {
+    pub fn new(callback: ProgressCallback) -> Self {
+        Self {
+            callback,
+            itineraries_done: AtomicUsize::new(0),
+            itineraries_total: AtomicUsize::new(0),
+            steps: AtomicU64::new(0),
+            best_score: Mutex::new(None),
+            last_report: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn add_itineraries(&self, count: usize) {
+        self.itineraries_total.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn step(&self) {
+        let steps = self.steps.fetch_add(1, Ordering::Relaxed) + 1;
+        if steps.is_multiple_of(PROGRESS_STEP_CHECK_INTERVAL) {
+            self.report_if_due();
+        }
+    }
+
+    pub fn itinerary_done(&self, score: Option<f64>) {
+        self.itineraries_done.fetch_add(1, Ordering::Relaxed);
+        if let Some(score) = score {
+            let mut best_score = self
+                .best_score
+                .lock()
+                .unwrap_or_else(|error| error.into_inner());
+            if best_score.is_none_or(|best_score| best_score < score) {
+                *best_score = Some(score);
+            }
+        }
+        self.report_if_due();
+    }
+
+    pub fn get(&self) -> GenerationProgress {
+        GenerationProgress {
+            itineraries_done: self.itineraries_done.load(Ordering::Relaxed),
+            itineraries_total: self.itineraries_total.load(Ordering::Relaxed),
+            steps: self.steps.load(Ordering::Relaxed),
+            best_score: *self
+                .best_score
+                .lock()
+                .unwrap_or_else(|error| error.into_inner()),
+        }
+    }
+
+    fn report_if_due(&self) {
+        {
+            let mut last_report = self
+                .last_report
+                .lock()
+                .unwrap_or_else(|error| error.into_inner());
+            if last_report.elapsed() < PROGRESS_REPORT_INTERVAL {
+                return;
+            }
+            *last_report = Instant::now();
+        }
+        (self.callback)(self.get());
+    }
+
+    pub fn report(&self) {
+        *self
+            .last_report
+            .lock()
+            .unwrap_or_else(|error| error.into_inner()) = Instant::now();
+        (self.callback)(self.get());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::{GenerationProgress, ProgressReporter};
+
+    #[test]
+    fn progress_keeps_best_score() {
+        let reported: Arc<Mutex<Vec<GenerationProgress>>> = Arc::new(Mutex::new(Vec::new()));
+        let reported_cb = reported.clone();
+        let reporter = ProgressReporter::new(Arc::new(move |progress| {
+            reported_cb.lock().unwrap().push(progress)
+        }));
+        reporter.add_itineraries(3);
+        reporter.step();
+        reporter.step();
+        reporter.itinerary_done(Some(2.));
+        reporter.itinerary_done(None);
+        reporter.itinerary_done(Some(1.));
+        reporter.report();
+
+        let reported = reported.lock().unwrap();
+        assert_eq!(
+            reported.last(),
+            Some(&GenerationProgress {
+                itineraries_done: 3,
+                itineraries_total: 3,
+                steps: 2,
+                best_score: Some(2.),
+            })
+        );
+    }
+}
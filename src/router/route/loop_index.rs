@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use crate::map_data::graph::{MapDataPointRef, RoadIds};
+
+use super::segment::Segment;
+
+// about 110m of latitude, points within the loop distance are at most a cell or two apart
+const CELL_DEGREES: f32 = 0.001;
+const CELL_LAT_M: f32 = 111_320. * CELL_DEGREES;
+
+type CellId = (i32, i32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum RoadKey {
+    Ref(u32),
+    Name(u32),
+}
+
+fn road_keys(road_ids: &RoadIds) -> impl Iterator<Item = RoadKey> {
+    road_ids
+        .hw_ref
+        .map(RoadKey::Ref)
+        .into_iter()
+        .chain(road_ids.name.map(RoadKey::Name))
+}
+
+fn cell_id(lat: f32, lon: f32) -> CellId {
+    (
+        (lat / CELL_DEGREES).floor() as i32,
+        (lon / CELL_DEGREES).floor() as i32,
+    )
+}
+
+/// Positions of the segments of a route by their end point, and by their road and where they end,
+/// kept up to date as segments are added and removed so loop checks don't scan the whole route
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoopIndex {
+    points: HashMap<MapDataPointRef, Vec<usize>>,
+    roads: HashMap<(RoadKey, CellId), Vec<usize>>,
+}
+
+impl LoopIndex {
+    /// Adds the segment at the position, which comes after all the positions added before
+    pub fn push(&mut self, idx: usize, segment: &Segment) {
+        self.points
+            .entry(segment.get_end_point().clone())
+            .or_default()
+            .push(idx);
+        let point = segment.get_end_point().borrow();
+        let cell = cell_id(point.lat, point.lon);
+        for road_key in road_keys(segment.get_road_ids()) {
+            self.roads.entry((road_key, cell)).or_default().push(idx);
+        }
+    }
+
+    /// Removes the segment, which is the last one added
+    pub fn pop(&mut self, segment: &Segment) {
+        if let Some(positions) = self.points.get_mut(segment.get_end_point()) {
+            positions.pop();
+            if positions.is_empty() {
+                self.points.remove(segment.get_end_point());
+            }
+        }
+        let point = segment.get_end_point().borrow();
+        let cell = cell_id(point.lat, point.lon);
+        for road_key in road_keys(segment.get_road_ids()) {
+            if let Some(positions) = self.roads.get_mut(&(road_key, cell)) {
+                positions.pop();
+                if positions.is_empty() {
+                    self.roads.remove(&(road_key, cell));
+                }
+            }
+        }
+    }
+
+    /// Positions of the segments ending at the point, in route order
+    pub fn positions_of(&self, point: &MapDataPointRef) -> &[usize] {
+        self.points.get(point).map_or(&[], |positions| positions)
+    }
+
+    /// Positions of the segments with the same ref or name ending within the distance of the
+    /// coordinates, and some further away, in no particular order
+    pub fn positions_on_road_near<'a>(
+        &'a self,
+        road_ids: &RoadIds,
+        lat: f32,
+        lon: f32,
+        distance_m: f32,
+    ) -> impl Iterator<Item = usize> + 'a {
+        let (lat_cell, lon_cell) = cell_id(lat, lon);
+        let lat_offset = (distance_m / CELL_LAT_M).ceil() as i32;
+        let lon_offset =
+            (distance_m / (CELL_LAT_M * lat.to_radians().cos().max(0.01))).ceil() as i32;
+        road_keys(road_ids)
+            .flat_map(move |road_key| {
+                (-lat_offset..=lat_offset).flat_map(move |lat_diff| {
+                    (-lon_offset..=lon_offset)
+                        .map(move |lon_diff| (road_key, (lat_cell + lat_diff, lon_cell + lon_diff)))
+                })
+            })
+            .filter_map(|key| self.roads.get(&key))
+            .flatten()
+            .copied()
+    }
+}
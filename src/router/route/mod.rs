@@ -1,58 +1,163 @@
+mod loop_index;
 pub mod score;
 pub mod segment;
 pub mod segment_list;
 
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt::Debug};
 
-use score::Score;
+use schemars::JsonSchema;
+use score::{Score, ScoreBreakdown};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    map_data::{graph::MapDataPointRef, line::MapDataLine, point::MapDataPoint},
+    map_data::{
+        graph::{MapDataLineRef, MapDataPointRef, RoadIds},
+        line::MapDataLine,
+        point::MapDataPoint,
+        rule::MapDataRuleType,
+    },
+    osm_data::data_reader::ALLOWED_ACCESS_VALUES,
     router::rules::RouterRules,
 };
 
-use self::segment::Segment;
+use self::{loop_index::LoopIndex, segment::Segment};
 
 const LOOP_DISTANCE_THRESHOLD: f32 = 50.;
 const LOOP_SEGMENT_THESHOLD: usize = 10;
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub struct RouteStatElement {
     pub len_m: f64,
     pub percentage: f64,
 }
 
+/// Exit taken from a roundabout, counted from the entry in driving direction
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct RoundaboutExit {
+    /// Index of the last segment on the roundabout
+    pub segment_idx: usize,
+    pub exit_number: u32,
+    pub lat: f32,
+    pub lon: f32,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteViolationKind {
+    /// One way road used against its direction
+    OneWay,
+    /// Turn prohibited by a turn restriction at the start of the segment
+    TurnRestriction,
+    /// Road with access or motorcycle tags that don't allow motorcycles
+    Access,
+}
+
+/// Traffic rule broken by the route, found when checking the finished route against the map data
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct RouteViolation {
+    pub segment_idx: usize,
+    pub kind: RouteViolationKind,
+    pub way_id: u64,
+    pub lat: f32,
+    pub lon: f32,
+}
+
+/// Map data attributes of a route segment, with the coordinates where the segment ends
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct RouteSegmentDetail {
+    pub way_id: u64,
+    pub lat: f32,
+    pub lon: f32,
+    pub highway: Option<String>,
+    pub surface: Option<String>,
+    pub smoothness: Option<String>,
+    pub name: Option<String>,
+    #[serde(rename = "ref")]
+    pub hw_ref: Option<String>,
+    pub len_m: f32,
+    pub travel_time_s: f32,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Point {
     pub lat: f64,
     pub lon: f64,
 }
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct RouteStats {
+    /// Set when no route reached the finish and this is the closest approach to it
+    #[serde(default)]
+    pub partial: bool,
     pub len_m: f64,
+    #[serde(default)]
+    pub travel_time_s: f64,
     pub junction_count: u32,
     pub highway: HashMap<String, RouteStatElement>,
     pub surface: HashMap<String, RouteStatElement>,
     pub smoothness: HashMap<String, RouteStatElement>,
     pub score: f64,
+    /// Contributions to the score, which is their sum
+    #[serde(default)]
+    pub score_breakdown: ScoreBreakdown,
+    /// Cluster of similar routes this route is in, none when it is not similar to other routes
     pub cluster: Option<usize>,
+    /// Set when the route is the best scoring route of its cluster
+    #[serde(default)]
+    pub cluster_best: bool,
     pub approximated_route: Vec<(f32, f32)>,
+    #[serde(default)]
+    pub vias_visited: Vec<bool>,
+    /// Distance near residential areas
+    #[serde(default)]
+    pub urban_len_m: f64,
+    #[serde(default)]
+    pub rural_len_m: f64,
+    /// Heading change in curves in degrees per km
+    #[serde(default)]
+    pub curviness: f64,
+    #[serde(default)]
+    pub ascent_m: f64,
+    #[serde(default)]
+    pub descent_m: f64,
+    #[serde(default)]
+    pub max_grade_percent: f64,
+    /// Distance from the start in meters and elevation in meters for points with known elevation
+    #[serde(default)]
+    pub elevation_profile: Vec<(f64, f32)>,
+    #[serde(default)]
+    pub roundabout_exits: Vec<RoundaboutExit>,
+    /// Traffic rules broken by the route, expected to be empty
+    #[serde(default)]
+    pub violations: Vec<RouteViolation>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Clone, PartialEq)]
 pub struct Route {
     route_segments: Vec<Segment>,
+    loop_index: LoopIndex,
+    /// Length of the route up to and including each segment, so the length is known without
+    /// going over the whole route when it grows and is truncated while navigating
+    cumulative_len_m: Vec<f64>,
+}
+
+impl Debug for Route {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Route")
+            .field("route_segments", &self.route_segments)
+            .finish()
+    }
 }
 
 impl Route {
     pub fn new() -> Self {
         Route {
             route_segments: Vec::new(),
+            loop_index: LoopIndex::default(),
+            cumulative_len_m: Vec::new(),
         }
     }
-    pub fn get_route_chunk(&self, start: usize, end: usize) -> Vec<Segment> {
-        self.route_segments[start..end].to_vec()
+    pub fn get_route_chunk(&self, start: usize, end: usize) -> &[Segment] {
+        &self.route_segments[start..end]
     }
     pub fn get_segment_last(&self) -> Option<&Segment> {
         self.route_segments.last()
@@ -60,28 +165,30 @@ impl Route {
     pub fn get_segment_by_index(&self, idx: usize) -> Option<&Segment> {
         self.route_segments.get(idx)
     }
+    pub fn get_len_m(&self) -> f64 {
+        self.cumulative_len_m.last().copied().unwrap_or(0.)
+    }
     pub fn get_segment_count(&self) -> usize {
         self.route_segments.len()
     }
-    pub fn remove_last_segment(&mut self) -> Option<Segment> {
-        self.route_segments.pop()
-    }
     pub fn add_segment(&mut self, segment: Segment) {
+        self.loop_index.push(self.route_segments.len(), &segment);
+        self.cumulative_len_m
+            .push(self.get_len_m() + f64::from(segment.get_line().borrow().get_len_m()));
         self.route_segments.push(segment)
     }
 
-    pub fn split_at_point(&self, point: &MapDataPointRef) -> Self {
-        let point_pos = self
-            .route_segments
-            .iter()
-            .position(|seg| seg.get_end_point() == point)
-            .map_or(0, |v| v);
-
-        let route_segments = self.route_segments[point_pos..].to_vec();
-        Self { route_segments }
+    /// Keeps the first segments and drops the rest, for backtracking
+    pub fn truncate(&mut self, segment_count: usize) {
+        while self.route_segments.len() > segment_count {
+            if let Some(segment) = self.route_segments.pop() {
+                self.loop_index.pop(&segment);
+            }
+        }
+        self.cumulative_len_m.truncate(segment_count);
     }
 
-    pub fn get_route_chunk_since_junction_before_last(&self) -> Vec<Segment> {
+    pub fn get_route_chunk_since_junction_before_last(&self) -> &[Segment] {
         let idx_from = match self.get_segment_last() {
             None => 0,
             Some(last_segment) => self
@@ -96,7 +203,7 @@ impl Route {
                 })
                 .map_or(0, |v| v.0),
         };
-        self.route_segments[idx_from..].to_vec()
+        &self.route_segments[idx_from..]
     }
     pub fn get_junction_before_last_segment(&self) -> Option<&Segment> {
         match self.get_segment_last() {
@@ -109,97 +216,56 @@ impl Route {
         }
     }
     pub fn has_looped(&self, since_point: Option<&MapDataPointRef>) -> bool {
-        let since_point_pos = if let Some(since_point) = since_point {
-            self.route_segments
-                .iter()
-                .position(|segment| segment.get_end_point() == since_point)
-                .map_or(0, |p| p)
-        } else {
-            0
+        let Some(last_segment) = self.route_segments.last() else {
+            return false;
         };
-        let last_segment = self.route_segments.last();
-        if let Some(last_segment) = last_segment {
-            let last_segment_point = last_segment.get_end_point();
-            let last_segment_line_tags = last_segment.get_line().borrow().tags.borrow();
-            let last_segment_line_hw_ref = last_segment_line_tags.hw_ref();
-            let last_segment_line_name = last_segment_line_tags.name();
-            let end_index = self.route_segments.len().checked_sub(1);
-            if let Some(end_index) = end_index {
-                let slice_len = self.route_segments[since_point_pos..end_index].len();
-                return self.route_segments[since_point_pos..end_index]
-                    .iter()
-                    .enumerate()
-                    .any(|(idx, segment)| {
-                        // if points are equal or
-                        // if points are less than 20m apart and
-                        // there are at least 20 segments between points
-                        // and hw ref or road name exist and match
-                        // treat them as looped as they are proabaly two sides of a motorway or
-                        // multi lane road with a direction separator
-                        let segment_point = segment.get_end_point();
-                        let are_points_eq = segment_point == last_segment_point;
-
-                        let distance_between_points_over_threshold =
-                            segment_point.borrow().distance_between(last_segment_point)
-                                < LOOP_DISTANCE_THRESHOLD;
-                        let route_segments_between_points_over_threshold =
-                            slice_len - idx > LOOP_SEGMENT_THESHOLD;
-
-                        let segment_line_tags = segment.get_line().borrow().tags.borrow();
-                        let segment_line_hw_ref = segment_line_tags.hw_ref();
-                        let segment_line_name = segment_line_tags.name();
-
-                        are_points_eq
-                            || (distance_between_points_over_threshold
-                                && route_segments_between_points_over_threshold
-                                && ((segment_line_hw_ref.is_some()
-                                    && last_segment_line_hw_ref.is_some()
-                                    && segment_line_hw_ref == last_segment_line_hw_ref)
-                                    || (segment_line_name.is_some()
-                                        && last_segment_line_name.is_some()
-                                        && segment_line_name == last_segment_line_name)))
-                    });
-            }
+        let end_index = self.route_segments.len() - 1;
+        let since_point_pos = since_point
+            .and_then(|since_point| self.loop_index.positions_of(since_point).first())
+            .map_or(0, |p| *p);
+
+        let last_segment_point = last_segment.get_end_point();
+        if self
+            .loop_index
+            .positions_of(last_segment_point)
+            .iter()
+            .any(|idx| (since_point_pos..end_index).contains(idx))
+        {
+            return true;
         }
-        false
+
+        // if points are less than 50m apart and
+        // there are more than 10 segments between points
+        // and hw ref or road name exist and match
+        // treat them as looped as they are proabaly two sides of a motorway or
+        // multi lane road with a direction separator
+        let last_point = last_segment_point.borrow();
+        self.loop_index
+            .positions_on_road_near(
+                last_segment.get_road_ids(),
+                last_point.lat,
+                last_point.lon,
+                LOOP_DISTANCE_THRESHOLD,
+            )
+            .any(|idx| {
+                idx >= since_point_pos
+                    && idx + LOOP_SEGMENT_THESHOLD < end_index
+                    && self.route_segments[idx]
+                        .get_end_point()
+                        .borrow()
+                        .distance_between(last_segment_point)
+                        < LOOP_DISTANCE_THRESHOLD
+            })
     }
-    pub fn is_back_on_road_within_distance(
-        &self,
-        hw_ref: Option<smartstring::alias::String>,
-        hw_name: Option<smartstring::alias::String>,
-        len_check_m: f32,
-    ) -> bool {
+    pub fn is_back_on_road_within_distance(&self, road_ids: &RoadIds, len_check_m: f32) -> bool {
         let mut len_tot_m = 0.;
 
-        if hw_ref.is_none() && hw_name.is_none() {
+        if road_ids.is_none() {
             return false;
         }
 
         if let Some(last_route_segment) = self.get_segment_last() {
-            if (last_route_segment
-                .get_line()
-                .borrow()
-                .tags
-                .borrow()
-                .hw_ref()
-                .is_some()
-                && last_route_segment
-                    .get_line()
-                    .borrow()
-                    .tags
-                    .borrow()
-                    .hw_ref()
-                    == hw_ref.as_ref())
-                || (last_route_segment
-                    .get_line()
-                    .borrow()
-                    .tags
-                    .borrow()
-                    .name()
-                    .is_some()
-                    && last_route_segment.get_line().borrow().tags.borrow().name()
-                        == hw_name.as_ref())
-            {
+            if last_route_segment.get_road_ids().is_same_road(road_ids) {
                 return false;
             }
         }
@@ -211,11 +277,7 @@ impl Route {
                     .get_end_point()
                     .borrow()
                     .distance_between(segment.get_end_point());
-                if (segment.get_line().borrow().tags.borrow().hw_ref().is_some()
-                    && segment.get_line().borrow().tags.borrow().hw_ref() == hw_ref.as_ref())
-                    || (segment.get_line().borrow().tags.borrow().name().is_some()
-                        && segment.get_line().borrow().tags.borrow().name() == hw_name.as_ref())
-                {
+                if segment.get_road_ids().is_same_road(road_ids) {
                     return len_check_m >= len_tot_m;
                 }
             }
@@ -227,30 +289,41 @@ impl Route {
 
         false
     }
-    pub fn get_junctions_from_end(&self, num_of_junctions: usize) -> Option<Segment> {
-        if self.route_segments.len() < num_of_junctions + 1 {
+    /// Segment at the junction the number of junctions back from the end, only looking at the
+    /// segments after the point
+    pub fn get_junctions_from_end_since(
+        &self,
+        point: &MapDataPointRef,
+        num_of_junctions: usize,
+    ) -> Option<&Segment> {
+        let point_pos = self
+            .route_segments
+            .iter()
+            .position(|seg| seg.get_end_point() == point)
+            .unwrap_or(0);
+        let route_segments = &self.route_segments[point_pos..];
+        if route_segments.len() < num_of_junctions + 1 {
             return None;
         }
 
         let mut segment_num = 0;
-        for segment in self.route_segments.iter().rev() {
+        for segment in route_segments.iter().rev() {
             if segment.get_end_point().borrow().is_junction() {
                 segment_num += 1;
             }
             if segment_num == num_of_junctions {
-                return Some(segment.clone());
+                return Some(segment);
             }
         }
 
         None
     }
-    pub fn get_segments_from_end(&self, num_of_segments: usize) -> Option<Segment> {
+    pub fn get_segments_from_end(&self, num_of_segments: usize) -> Option<&Segment> {
         if self.route_segments.len() < num_of_segments + 1 {
             return None;
         }
         self.route_segments
             .get(self.route_segments.len() - 1 - num_of_segments)
-            .cloned()
     }
 
     pub fn calc_stats(&self, rules: &RouterRules) -> RouteStats {
@@ -289,17 +362,27 @@ impl Route {
             stat_map
         }
         let mut len_m: f64 = 0.;
+        let mut travel_time_s: f64 = 0.;
         let mut junction_count = 0;
         let mut highway: HashMap<String, f64> = HashMap::new();
         let mut surface: HashMap<String, f64> = HashMap::new();
         let mut smoothness: HashMap<String, f64> = HashMap::new();
+        let mut urban_len_m: f64 = 0.;
+        let mut ascent_m: f64 = 0.;
+        let mut descent_m: f64 = 0.;
+        let mut max_grade_percent: f64 = 0.;
+        let mut elevation_profile: Vec<(f64, f32)> = Vec::new();
 
         for segment in &self.route_segments {
             let line_len: f64 = segment.get_line().borrow().get_len_m().into();
             len_m += line_len;
+            travel_time_s += f64::from(segment.get_line().borrow().get_travel_time_s());
             if segment.get_end_point().borrow().is_junction() {
                 junction_count += 1;
             }
+            if segment.get_end_point().borrow().residential_in_proximity {
+                urban_len_m += line_len;
+            }
             let line_tags = segment.get_line().borrow().tags.borrow();
             let highway_val = line_tags.highway();
             update_map(&highway_val, line_len, &mut highway);
@@ -307,28 +390,243 @@ impl Route {
             update_map(&surface_val, line_len, &mut surface);
             let smoothness_val = line_tags.smoothness();
             update_map(&smoothness_val, line_len, &mut smoothness);
+
+            if let Some(elevation) = segment.get_end_point().borrow().elevation {
+                if let Some((prev_len_m, prev_elevation)) = elevation_profile.last() {
+                    let diff = f64::from(elevation - prev_elevation);
+                    if diff > 0. {
+                        ascent_m += diff;
+                    } else {
+                        descent_m -= diff;
+                    }
+                    let distance = len_m - prev_len_m;
+                    if distance > 0. {
+                        max_grade_percent = max_grade_percent.max((diff / distance * 100.).abs());
+                    }
+                }
+                elevation_profile.push((len_m, elevation));
+            }
         }
 
+        let score_breakdown = Score::calc_score(self, rules);
+
         RouteStats {
+            partial: false,
             len_m,
+            travel_time_s,
             junction_count,
             highway: calc_stat_map(len_m, &highway),
             smoothness: calc_stat_map(len_m, &smoothness),
             surface: calc_stat_map(len_m, &surface),
-            score: Score::calc_score(self, rules),
+            score: score_breakdown.total(),
+            score_breakdown,
+            urban_len_m,
+            rural_len_m: len_m - urban_len_m,
+            curviness: Score::calc_curviness(self, rules.basic.prefer_curvy.min_curve_radius_m),
             cluster: None,
+            cluster_best: false,
             approximated_route: Vec::new(),
+            vias_visited: Vec::new(),
+            ascent_m,
+            descent_m,
+            max_grade_percent,
+            elevation_profile,
+            roundabout_exits: self.get_roundabout_exits(),
+            violations: self.get_violations(),
+        }
+    }
+
+    /// Map data attributes of every segment of the route: the way, the point the segment ends at,
+    /// its tags and its length and travel time
+    pub fn get_segment_details(&self) -> Vec<RouteSegmentDetail> {
+        self.route_segments
+            .iter()
+            .map(|segment| {
+                let line = segment.get_line().borrow();
+                let tags = line.tags.borrow();
+                let end_point = segment.get_end_point().borrow();
+                let tag = |value: Option<&smartstring::alias::String>| {
+                    value.map(|value| value.to_string())
+                };
+                RouteSegmentDetail {
+                    way_id: line.way_id,
+                    lat: end_point.lat,
+                    lon: end_point.lon,
+                    highway: tag(tags.highway()),
+                    surface: tag(tags.surface()),
+                    smoothness: tag(tags.smoothness()),
+                    name: tag(tags.name()),
+                    hw_ref: tag(tags.hw_ref()),
+                    len_m: line.get_len_m(),
+                    travel_time_s: line.get_travel_time_s(),
+                }
+            })
+            .collect()
+    }
+
+    /// Checks the route against one way directions, turn restrictions and access tags again, to
+    /// catch routes the walker should not have produced
+    pub fn get_violations(&self) -> Vec<RouteViolation> {
+        let mut violations = Vec::new();
+        let mut prev_line: Option<&MapDataLineRef> = None;
+
+        for (segment_idx, segment) in self.route_segments.iter().enumerate() {
+            let line_ref = segment.get_line();
+            let line = line_ref.borrow();
+            let start_point = if &line.points.0 == segment.get_end_point() {
+                &line.points.1
+            } else {
+                &line.points.0
+            };
+            let mut add_violation = |kind| {
+                let start_point = start_point.borrow();
+                violations.push(RouteViolation {
+                    segment_idx,
+                    kind,
+                    way_id: line.way_id,
+                    lat: start_point.lat,
+                    lon: start_point.lon,
+                })
+            };
+
+            if line.is_one_way() && &line.points.0 == segment.get_end_point() {
+                add_violation(RouteViolationKind::OneWay);
+            }
+
+            if let Some(prev_line) = prev_line {
+                let rules = start_point
+                    .rules()
+                    .iter()
+                    .filter(|rule| rule.from_lines.contains(prev_line))
+                    .collect::<Vec<_>>();
+                let not_allowed = rules.iter().any(|rule| {
+                    rule.rule_type == MapDataRuleType::NotAllowed
+                        && rule.to_lines.contains(line_ref)
+                });
+                let only_allowed = rules
+                    .iter()
+                    .filter(|rule| rule.rule_type == MapDataRuleType::OnlyAllowed)
+                    .collect::<Vec<_>>();
+                if not_allowed
+                    || (!only_allowed.is_empty()
+                        && !only_allowed
+                            .iter()
+                            .any(|rule| rule.to_lines.contains(line_ref)))
+                {
+                    add_violation(RouteViolationKind::TurnRestriction);
+                }
+            }
+
+            let tags = line.tags.borrow();
+            if tags
+                .get("access")
+                .is_some_and(|access| !ALLOWED_ACCESS_VALUES.contains(&access.as_str()))
+                || tags
+                    .get("motorcycle")
+                    .is_some_and(|motorcycle| motorcycle == "no")
+            {
+                add_violation(RouteViolationKind::Access);
+            }
+
+            prev_line = Some(line_ref);
         }
+
+        violations
+    }
+
+    /// Finds which exit was taken on each roundabout the route passes. Every road leaving the
+    /// roundabout after the entry point counts as an exit, roads that can only be used to enter
+    /// the roundabout do not.
+    pub fn get_roundabout_exits(&self) -> Vec<RoundaboutExit> {
+        let mut exits = Vec::new();
+        let mut passed_exit_count = 0;
+        for (idx, segment) in self.route_segments.iter().enumerate() {
+            if !segment.get_line().borrow().is_roundabout() {
+                passed_exit_count = 0;
+                continue;
+            }
+            let Some(next_segment) = self.route_segments.get(idx + 1) else {
+                break;
+            };
+            let point = segment.get_end_point();
+            if !next_segment.get_line().borrow().is_roundabout() {
+                exits.push(RoundaboutExit {
+                    segment_idx: idx,
+                    exit_number: passed_exit_count + 1,
+                    lat: point.borrow().lat,
+                    lon: point.borrow().lon,
+                });
+                continue;
+            }
+            passed_exit_count += point
+                .lines()
+                .iter()
+                .filter(|line| {
+                    let line = line.borrow();
+                    !line.is_roundabout() && !(line.is_one_way() && &line.points.1 == point)
+                })
+                .count() as u32;
+        }
+        exits
     }
 
     pub fn iter(&self) -> std::slice::Iter<Segment> {
         self.route_segments.iter()
     }
+
+    /// Removes out-and-back spikes, where the route turns around and comes back on the same lines,
+    /// up to `max_spike_len_m` in total length. Spikes that pass a point for which `keep` returns
+    /// true are left in place, so that a via at the end of a dead end is not lost.
+    pub fn without_spikes<F>(&self, max_spike_len_m: f64, keep: F) -> Self
+    where
+        F: Fn(&MapDataPointRef) -> bool,
+    {
+        let mut route_segments = self.route_segments.clone();
+        let mut idx = 0;
+        while idx + 1 < route_segments.len() {
+            if route_segments[idx].get_line() != route_segments[idx + 1].get_line() {
+                idx += 1;
+                continue;
+            }
+
+            // expand from the turnaround for as long as the way out matches the way back
+            let mut first = idx;
+            let mut last = idx + 1;
+            while first > 0
+                && last + 1 < route_segments.len()
+                && route_segments[first - 1].get_line() == route_segments[last + 1].get_line()
+            {
+                first -= 1;
+                last += 1;
+            }
+
+            let spike = &route_segments[first..=last];
+            let spike_len_m: f64 = spike
+                .iter()
+                .map(|segment| f64::from(segment.get_line().borrow().get_len_m()))
+                .sum();
+            if spike_len_m > max_spike_len_m
+                || spike.iter().any(|segment| keep(segment.get_end_point()))
+            {
+                idx = last;
+                continue;
+            }
+
+            route_segments.drain(first..=last);
+            idx = first.saturating_sub(1);
+        }
+
+        Self::from(route_segments)
+    }
 }
 
 impl From<Vec<Segment>> for Route {
     fn from(route_segments: Vec<Segment>) -> Self {
-        Route { route_segments }
+        let mut route = Route::new();
+        route_segments
+            .into_iter()
+            .for_each(|segment| route.add_segment(segment));
+        route
     }
 }
 
@@ -347,3 +645,248 @@ impl IntoIterator for Route {
         self.route_segments.into_iter()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rusty_fork::rusty_fork_test;
+
+    use crate::{
+        map_data::{
+            graph::MapDataGraph,
+            osm::{OsmRelation, OsmRelationMember, OsmRelationMemberRole, OsmRelationMemberType},
+        },
+        router::rules::{RouterRules, ScoringRuleComponent},
+        test_utils::{
+            graph_from_test_dataset, line_is_between_point_ids, set_graph_static, test_dataset_1,
+            test_dataset_2, test_dataset_3,
+        },
+    };
+
+    use super::{segment::Segment, Route, RouteViolationKind};
+
+    fn segment(from_id: u64, to_id: u64) -> Segment {
+        let to = MapDataGraph::get()
+            .test_get_point_ref_by_id(&to_id)
+            .unwrap();
+        let line = to
+            .lines()
+            .iter()
+            .find(|line| line_is_between_point_ids(line, from_id, to_id))
+            .expect("line to exist")
+            .clone();
+        Segment::new(line, to)
+    }
+
+    fn point_ids(route: &Route) -> Vec<u64> {
+        route
+            .iter()
+            .map(|segment| segment.get_end_point().borrow().id)
+            .collect()
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn without_spikes() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let route = Route::from(vec![
+                segment(1, 2),
+                segment(2, 3),
+                segment(3, 4),
+                segment(4, 3),
+                segment(3, 6),
+            ]);
+
+            assert_eq!(point_ids(&route.without_spikes(f64::MAX, |_| false)), vec![2, 3, 6]);
+            assert_eq!(
+                point_ids(&route.without_spikes(0., |_| false)),
+                vec![2, 3, 4, 3, 6]
+            );
+            assert_eq!(
+                point_ids(&route.without_spikes(f64::MAX, |p| p.borrow().id == 4)),
+                vec![2, 3, 4, 3, 6]
+            );
+
+            let route = Route::from(vec![
+                segment(1, 2),
+                segment(2, 3),
+                segment(3, 2),
+                segment(2, 1),
+            ]);
+            assert!(route.without_spikes(f64::MAX, |_| false).iter().next().is_none());
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn score_breakdown() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let route = Route::from(vec![segment(1, 2), segment(2, 3), segment(3, 6)]);
+
+            let stats = route.calc_stats(&RouterRules::default());
+            assert_eq!(stats.score_breakdown.junctions, 0.);
+            assert_eq!(stats.score_breakdown.urban, 0.);
+            assert_eq!(stats.score, stats.score_breakdown.total());
+
+            let mut rules = RouterRules::default();
+            rules.scoring.curves = ScoringRuleComponent::disabled();
+            rules.scoring.junctions = ScoringRuleComponent::default();
+            let stats = route.calc_stats(&rules);
+            // points 3 and 6 are junctions
+            let expected = -2. / stats.len_m * 1000.;
+            assert!((stats.score_breakdown.junctions - expected).abs() < 1e-9);
+            assert_eq!(stats.score_breakdown.curves, 0.);
+            assert_eq!(stats.score, stats.score_breakdown.total());
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn has_looped_after_truncate() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let mut route = Route::from(vec![
+                segment(1, 2),
+                segment(2, 3),
+                segment(3, 4),
+                segment(4, 3),
+            ]);
+            assert!(route.has_looped(None));
+            let point_4 = MapDataGraph::get().test_get_point_ref_by_id(&4).unwrap();
+            assert!(!route.has_looped(Some(&point_4)));
+
+            let len_m = route.get_len_m();
+            route.truncate(3);
+            assert!(!route.has_looped(None));
+            assert!(route.get_len_m() < len_m);
+            assert_eq!(
+                route.get_len_m(),
+                Route::from(route.iter().cloned().collect::<Vec<_>>()).get_len_m()
+            );
+            route.add_segment(segment(4, 3));
+            assert!(route.has_looped(None));
+            route.truncate(0);
+            assert!(!route.has_looped(None));
+            assert_eq!(route, Route::new());
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn roundabout_exits() {
+            set_graph_static(graph_from_test_dataset(test_dataset_2()));
+            let route = Route::from(vec![
+                segment(6, 7),
+                segment(7, 11),
+                segment(11, 12),
+                segment(12, 121),
+            ]);
+            let exits = route.get_roundabout_exits();
+            assert_eq!(exits.len(), 1);
+            assert_eq!(exits[0].segment_idx, 2);
+            assert_eq!(exits[0].exit_number, 2);
+
+            let route = Route::from(vec![segment(6, 7), segment(7, 11), segment(11, 111)]);
+            assert_eq!(
+                route
+                    .get_roundabout_exits()
+                    .iter()
+                    .map(|exit| exit.exit_number)
+                    .collect::<Vec<_>>(),
+                vec![1]
+            );
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn violations_one_way() {
+            set_graph_static(graph_from_test_dataset(test_dataset_2()));
+            let route = Route::from(vec![segment(9, 8), segment(8, 7), segment(7, 2)]);
+            assert!(route.get_violations().is_empty());
+
+            let route = Route::from(vec![segment(3, 2), segment(2, 7), segment(7, 8)]);
+            assert_eq!(
+                route
+                    .get_violations()
+                    .iter()
+                    .map(|violation| (violation.segment_idx, violation.kind.clone(), violation.way_id))
+                    .collect::<Vec<_>>(),
+                vec![
+                    (1, RouteViolationKind::OneWay, 72),
+                    (2, RouteViolationKind::OneWay, 87)
+                ]
+            );
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn segment_details() {
+            set_graph_static(graph_from_test_dataset(test_dataset_2()));
+            let route = Route::from(vec![segment(9, 8), segment(8, 7)]);
+            let details = route.get_segment_details();
+            assert_eq!(details.len(), 2);
+            assert_eq!(details[1].way_id, 87);
+            assert_eq!(details[1].highway.as_deref(), Some("primary"));
+            assert_eq!(details[1].surface, None);
+            assert_eq!(
+                (details[1].lat, details[1].lon),
+                {
+                    let end_point = route.get_segment_by_index(1).unwrap().get_end_point().borrow();
+                    (end_point.lat, end_point.lon)
+                }
+            );
+            assert!(details.iter().all(|detail| detail.len_m > 0. && detail.travel_time_s > 0.));
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn violations_turn_restriction() {
+            let test_data = test_dataset_3();
+            let rules = vec![OsmRelation {
+                id: 1,
+                members: vec![
+                    OsmRelationMember {
+                        member_ref: 13,
+                        role: OsmRelationMemberRole::From,
+                        member_type: OsmRelationMemberType::Way,
+                    },
+                    OsmRelationMember {
+                        member_ref: 3,
+                        role: OsmRelationMemberRole::Via,
+                        member_type: OsmRelationMemberType::Node,
+                    },
+                    OsmRelationMember {
+                        member_ref: 36,
+                        role: OsmRelationMemberRole::To,
+                        member_type: OsmRelationMemberType::Way,
+                    },
+                ],
+                tags: HashMap::from([
+                    ("type".to_string(), "restriction".to_string()),
+                    ("restriction".to_string(), "no_left_turn".to_string()),
+                ]),
+            }];
+            set_graph_static(graph_from_test_dataset((test_data.0, test_data.1, rules)));
+
+            let route = Route::from(vec![segment(1, 3), segment(3, 4)]);
+            assert!(route.get_violations().is_empty());
+
+            let route = Route::from(vec![segment(1, 3), segment(3, 6)]);
+            let violations = route.get_violations();
+            assert_eq!(violations.len(), 1);
+            assert_eq!(violations[0].segment_idx, 1);
+            assert_eq!(violations[0].kind, RouteViolationKind::TurnRestriction);
+            assert_eq!(violations[0].way_id, 36);
+        }
+    }
+}
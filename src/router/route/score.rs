@@ -1,5 +1,8 @@
 use std::collections::HashMap;
 
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
 use crate::router::rules::{RouterRules, RulesTagValueAction};
 
 use super::Route;
@@ -10,12 +13,20 @@ fn scale_priority(priority: u8) -> f64 {
     priority as f64 / 255.0
 }
 
+/// Adjustment from the priority of the tag, the numeric preference table takes precedence over the
+/// rule priorities when it is set
 fn get_rule_adjustment(
     bearing_diff: f64,
     tag: &Option<&smartstring::alias::String>,
     rule: &Option<HashMap<String, RulesTagValueAction>>,
+    weights: &HashMap<String, u8>,
 ) -> f64 {
     if let Some(ref curr_tag) = tag {
+        if !weights.is_empty() {
+            return weights
+                .get(curr_tag.as_str())
+                .map_or(0., |value| bearing_diff * scale_priority(*value));
+        }
         if let Some(ref tag_rules) = rule {
             if let Some(curr_tag_rule) = tag_rules.get(curr_tag.as_str()) {
                 if let RulesTagValueAction::Priority {
@@ -30,42 +41,73 @@ fn get_rule_adjustment(
     0.
 }
 
+/// Parts the route score is made of. Curves is the heading change in degrees per km, highway,
+/// surface and smoothness are the adjustments to it from the rule priorities or preference tables
+/// of the roads the curves are on. Junctions and urban take off the junctions per km and the percentage of the
+/// length in built-up areas, which the avoid urban weight calc steers away from while routing.
+/// Each part is weighted by the scoring rules.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq)]
+pub struct ScoreBreakdown {
+    pub curves: f64,
+    pub highway: f64,
+    pub surface: f64,
+    pub smoothness: f64,
+    #[serde(default)]
+    pub junctions: f64,
+    #[serde(default)]
+    pub urban: f64,
+}
+
+impl ScoreBreakdown {
+    pub fn total(&self) -> f64 {
+        self.curves + self.highway + self.surface + self.smoothness + self.junctions + self.urban
+    }
+}
+
 impl Score {
-    pub fn calc_score(route: &Route, rules: &RouterRules) -> f64 {
+    pub fn calc_score(route: &Route, rules: &RouterRules) -> ScoreBreakdown {
         let mut prev_bearing: Option<f32> = None;
-        let mut tot_bearing_diff_adj: f64 = 0.;
+        let mut tot = ScoreBreakdown::default();
         let mut len_m: f64 = 0.;
+        let mut junction_count: f64 = 0.;
+        let mut urban_len_m: f64 = 0.;
 
         for segment in route.iter() {
             let line_len: f64 = segment.get_line().borrow().get_len_m().into();
             len_m += line_len;
+            if segment.get_end_point().borrow().is_junction() {
+                junction_count += 1.;
+            }
+            if segment.get_end_point().borrow().residential_in_proximity {
+                urban_len_m += line_len;
+            }
 
             let curr_bearing = segment.get_bearing();
             if let Some(prev_bearing) = prev_bearing {
                 let bearing_diff = (prev_bearing - curr_bearing).abs() as f64;
-                tot_bearing_diff_adj += if bearing_diff >= 90. {
-                    // assumption is that a 90 or more
-                    // degree turn is a junction, not a curve
-                    // we don't want junctions
-                    0.
-                } else {
-                    let mut adjusted = bearing_diff;
-                    adjusted += get_rule_adjustment(
+                // assumption is that a 90 or more
+                // degree turn is a junction, not a curve
+                // we don't want junctions
+                if bearing_diff < 90. {
+                    tot.curves += bearing_diff;
+                    tot.highway += get_rule_adjustment(
                         bearing_diff,
                         &segment.get_line().borrow().tags.borrow().highway(),
                         &rules.highway,
+                        &rules.highway_weights,
                     );
-                    adjusted += get_rule_adjustment(
+                    tot.surface += get_rule_adjustment(
                         bearing_diff,
                         &segment.get_line().borrow().tags.borrow().surface(),
                         &rules.surface,
+                        &rules.surface_weights,
                     );
-                    adjusted += get_rule_adjustment(
+                    tot.smoothness += get_rule_adjustment(
                         bearing_diff,
                         &segment.get_line().borrow().tags.borrow().smoothness(),
                         &rules.smoothness,
+                        &rules.smoothness_weights,
                     );
-                    adjusted
                 }
             }
             prev_bearing = if segment.get_end_point().borrow().is_junction() {
@@ -82,6 +124,49 @@ impl Score {
             };
         }
 
-        tot_bearing_diff_adj / len_m * 1000.
+        ScoreBreakdown {
+            curves: rules.scoring.curves.apply(tot.curves / len_m * 1000.),
+            highway: rules.scoring.highway.apply(tot.highway / len_m * 1000.),
+            surface: rules.scoring.surface.apply(tot.surface / len_m * 1000.),
+            smoothness: rules
+                .scoring
+                .smoothness
+                .apply(tot.smoothness / len_m * 1000.),
+            junctions: rules
+                .scoring
+                .junctions
+                .apply(-junction_count / len_m * 1000.),
+            urban: rules.scoring.urban.apply(-urban_len_m / len_m * 100.),
+        }
+    }
+
+    /// Heading change in degrees per km of curves, ignoring turns of 90 degrees or more, which are
+    /// junctions, and curves with a radius under `min_curve_radius_m`, which are mostly corners
+    pub fn calc_curviness(route: &Route, min_curve_radius_m: f32) -> f64 {
+        let mut prev: Option<(f32, f32)> = None;
+        let mut tot_curve_deg: f64 = 0.;
+        let mut len_m: f64 = 0.;
+
+        for segment in route.iter() {
+            let line_len = segment.get_line().borrow().get_len_m();
+            len_m += f64::from(line_len);
+            let curr_bearing = segment.get_bearing();
+            if let Some((prev_bearing, prev_len)) = prev {
+                let diff = (prev_bearing - curr_bearing).abs() % 360.;
+                let angle_deg = if diff > 180. { 360. - diff } else { diff };
+                if angle_deg > 0. && angle_deg < 90. {
+                    let radius_m = (prev_len + line_len) / 2. / angle_deg.to_radians();
+                    if radius_m >= min_curve_radius_m {
+                        tot_curve_deg += f64::from(angle_deg);
+                    }
+                }
+            }
+            prev = Some((curr_bearing, line_len));
+        }
+
+        if len_m == 0. {
+            return 0.;
+        }
+        tot_curve_deg / len_m * 1000.
     }
 }
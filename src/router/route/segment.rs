@@ -1,16 +1,23 @@
 use std::fmt::Debug;
 
-use crate::map_data::graph::{MapDataLineRef, MapDataPointRef};
+use crate::map_data::graph::{MapDataLineRef, MapDataPointRef, RoadIds};
 
 #[derive(PartialEq, Clone)]
 pub struct Segment {
     line: MapDataLineRef,
     end_point: MapDataPointRef,
+    // looked up once, the same road checks run for every segment of the route on every step
+    road_ids: RoadIds,
 }
 
 impl Segment {
     pub fn new(line: MapDataLineRef, end_point: MapDataPointRef) -> Self {
-        Self { line, end_point }
+        let road_ids = line.borrow().tags.borrow().road_ids();
+        Self {
+            line,
+            end_point,
+            road_ids,
+        }
     }
     pub fn get_end_point(&self) -> &MapDataPointRef {
         &self.end_point
@@ -18,6 +25,9 @@ impl Segment {
     pub fn get_line(&self) -> &MapDataLineRef {
         &self.line
     }
+    pub fn get_road_ids(&self) -> &RoadIds {
+        &self.road_ids
+    }
     pub fn get_bearing(&self) -> f32 {
         if self.end_point == self.line.borrow().points.0 {
             return self
@@ -0,0 +1,324 @@
+use std::{f64::consts::PI, sync::OnceLock};
+
+use serde::Deserialize;
+use serde_json::Value;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime, UtcOffset};
+
+use super::rules::RulesError;
+
+/// Sun altitude at sunrise and sunset, accounting for refraction and the size of the sun
+const SUN_ALTITUDE_AT_HORIZON_DEG: f64 = -0.833;
+const EARTH_AXIAL_TILT_DEG: f64 = 23.4397;
+/// Julian date of 2000-01-01 12:00 UTC
+const J2000: f64 = 2451545.;
+/// Julian date of 1970-01-01 00:00 UTC
+const JULIAN_UNIX_EPOCH: f64 = 2440587.5;
+const MINUTES_IN_DAY: u32 = 24 * 60;
+
+/// Time zone offset of departures that are not given, read once at startup
+static LOCAL_OFFSET: OnceLock<UtcOffset> = OnceLock::new();
+
+/// Reads the local time zone offset used for departures that are not given. Has to be called
+/// before any other thread is started, as the offset can't be read safely once there are other
+/// threads. Returns false when the offset can't be read, departures are in UTC then
+pub fn init_local_offset() -> bool {
+    let offset = UtcOffset::current_local_offset();
+    let _ = LOCAL_OFFSET.set(offset.unwrap_or(UtcOffset::UTC));
+    offset.is_ok()
+}
+
+/// Departure of the trip that the `when` conditions of the rules are evaluated against
+#[derive(Debug, Clone, Copy)]
+pub struct RuleConditionTrip {
+    pub departure: OffsetDateTime,
+    pub start_lat: f32,
+    pub start_lon: f32,
+}
+
+impl RuleConditionTrip {
+    pub fn new(departure: Option<OffsetDateTime>, start_lat: f32, start_lon: f32) -> Self {
+        Self {
+            departure: departure.unwrap_or_else(|| {
+                OffsetDateTime::now_utc()
+                    .to_offset(LOCAL_OFFSET.get().copied().unwrap_or(UtcOffset::UTC))
+            }),
+            start_lat,
+            start_lon,
+        }
+    }
+}
+
+/// Parses a departure time like 2025-06-21T18:30:00+03:00
+pub fn parse_departure_time(departure: &str) -> Result<OffsetDateTime, String> {
+    OffsetDateTime::parse(departure, &Rfc3339).map_err(|error| {
+        format!("'{departure}' is not a time like 2025-06-21T18:30:00+03:00: {error}")
+    })
+}
+
+/// Condition of a rule entry, the entry only applies when every given condition is met
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleCondition {
+    /// Months of the departure, 1 for January to 12 for December
+    months: Option<Vec<u8>>,
+    /// Range of the time of the departure, like `sunset..sunrise` or `22:00..06:00`. The range
+    /// ends are a time of the day in the time zone of the departure, `sunrise` or `sunset`
+    time: Option<String>,
+}
+
+impl RuleCondition {
+    fn parse(condition: &Value) -> Result<Self, String> {
+        let condition: RuleCondition =
+            serde_json::from_value(condition.clone()).map_err(|error| error.to_string())?;
+        if let Some(month) = condition
+            .months
+            .iter()
+            .flatten()
+            .find(|month| !(1..=12).contains(*month))
+        {
+            return Err(format!("month {month} is not between 1 and 12"));
+        }
+        if let Some(time) = &condition.time {
+            parse_time_range(time)?;
+        }
+        Ok(condition)
+    }
+
+    fn is_met(&self, trip: &RuleConditionTrip) -> Result<bool, String> {
+        if let Some(months) = &self.months {
+            if !months.contains(&u8::from(trip.departure.month())) {
+                return Ok(false);
+            }
+        }
+        if let Some(time) = &self.time {
+            let (from, to) = parse_time_range(time)?;
+            let (sunrise, sunset) = sun_times(trip);
+            let minutes = |time: TimeOfDay| match time {
+                TimeOfDay::Sunrise => sunrise,
+                TimeOfDay::Sunset => sunset,
+                TimeOfDay::Clock(minutes) => minutes,
+            };
+            let (from, to) = (minutes(from), minutes(to));
+            let departure =
+                u32::from(trip.departure.hour()) * 60 + u32::from(trip.departure.minute());
+            let in_range = if from <= to {
+                (from..to).contains(&departure)
+            } else {
+                departure >= from || departure < to
+            };
+            if !in_range {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TimeOfDay {
+    Sunrise,
+    Sunset,
+    /// Minutes from midnight
+    Clock(u32),
+}
+
+fn parse_time_range(range: &str) -> Result<(TimeOfDay, TimeOfDay), String> {
+    let parse = |time: &str| match time.trim() {
+        "sunrise" => Ok(TimeOfDay::Sunrise),
+        "sunset" => Ok(TimeOfDay::Sunset),
+        time => time
+            .split_once(':')
+            .and_then(|(hours, minutes)| {
+                Some((hours.parse::<u32>().ok()?, minutes.parse::<u32>().ok()?))
+            })
+            .filter(|(hours, minutes)| *hours <= 24 && *minutes < 60)
+            .map(|(hours, minutes)| TimeOfDay::Clock((hours * 60 + minutes).min(MINUTES_IN_DAY)))
+            .ok_or_else(|| format!("'{time}' is not a time like 22:00, `sunrise` or `sunset`")),
+    };
+    let (from, to) = range
+        .split_once("..")
+        .ok_or_else(|| format!("'{range}' is not a range like sunset..sunrise or 22:00..06:00"))?;
+    Ok((parse(from)?, parse(to)?))
+}
+
+/// Sunrise and sunset on the day of the departure at the start of the trip, in minutes from
+/// midnight in the time zone of the departure. When the sun doesn't set, sunrise is at the start
+/// of the day and sunset at the end, when it doesn't rise, the other way around
+fn sun_times(trip: &RuleConditionTrip) -> (u32, u32) {
+    let lat = f64::from(trip.start_lat).to_radians();
+    let lon = f64::from(trip.start_lon);
+    let days = f64::from(trip.departure.date().to_julian_day()) - J2000 + 0.0008;
+    let mean_solar_day = days - lon / 360.;
+    let anomaly = (357.5291 + 0.98560028 * mean_solar_day).rem_euclid(360.);
+    let anomaly_rad = anomaly.to_radians();
+    let center = 1.9148 * anomaly_rad.sin()
+        + 0.02 * (2. * anomaly_rad).sin()
+        + 0.0003 * (3. * anomaly_rad).sin();
+    let ecliptic_lon = (anomaly + center + 180. + 102.9372)
+        .rem_euclid(360.)
+        .to_radians();
+    let transit =
+        J2000 + mean_solar_day + 0.0053 * anomaly_rad.sin() - 0.0069 * (2. * ecliptic_lon).sin();
+    let declination = (ecliptic_lon.sin() * EARTH_AXIAL_TILT_DEG.to_radians().sin()).asin();
+    let hour_angle_cos = (SUN_ALTITUDE_AT_HORIZON_DEG.to_radians().sin()
+        - lat.sin() * declination.sin())
+        / (lat.cos() * declination.cos());
+    if hour_angle_cos < -1. {
+        return (0, MINUTES_IN_DAY);
+    }
+    if hour_angle_cos > 1. {
+        return (MINUTES_IN_DAY, 0);
+    }
+    let hour_angle_days = hour_angle_cos.acos() / (2. * PI);
+    let minutes = |julian_date: f64| {
+        let offset_s = f64::from(trip.departure.offset().whole_seconds());
+        let local_s = (julian_date - JULIAN_UNIX_EPOCH) * 86400. + offset_s;
+        (local_s.rem_euclid(86400.) / 60.) as u32
+    };
+    (
+        minutes(transit - hour_angle_days),
+        minutes(transit + hour_angle_days),
+    )
+}
+
+/// Applies the `when` conditions of the rules: every object with a `when` field is removed from
+/// the rules when its condition isn't met for the trip, or kept without the field when it is.
+/// Rules can only have conditions when there is a trip to evaluate them against
+pub fn apply_conditions(
+    rules: &mut Value,
+    trip: Option<&RuleConditionTrip>,
+) -> Result<(), RulesError> {
+    apply_conditions_at(rules, trip, "")
+}
+
+fn apply_conditions_at(
+    value: &mut Value,
+    trip: Option<&RuleConditionTrip>,
+    path: &str,
+) -> Result<(), RulesError> {
+    let child_path = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{path}.{key}")
+        }
+    };
+    match value {
+        Value::Object(fields) => {
+            let mut removed = Vec::new();
+            for (key, field) in fields.iter_mut() {
+                let field_path = child_path(key);
+                if !condition_met(field, trip, &field_path)? {
+                    removed.push(key.clone());
+                    continue;
+                }
+                apply_conditions_at(field, trip, &field_path)?;
+            }
+            for key in removed {
+                fields.remove(&key);
+            }
+        }
+        Value::Array(items) => {
+            let mut kept = Vec::with_capacity(items.len());
+            for (index, mut item) in items.drain(..).enumerate() {
+                let item_path = format!("{path}[{index}]");
+                if condition_met(&mut item, trip, &item_path)? {
+                    apply_conditions_at(&mut item, trip, &item_path)?;
+                    kept.push(item);
+                }
+            }
+            *items = kept;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Removes the `when` field of the value and tells if its condition is met
+fn condition_met(
+    value: &mut Value,
+    trip: Option<&RuleConditionTrip>,
+    path: &str,
+) -> Result<bool, RulesError> {
+    let Some(condition) = value
+        .as_object_mut()
+        .and_then(|fields| fields.remove("when"))
+    else {
+        return Ok(true);
+    };
+    let invalid = |message| RulesError::ConditionInvalid {
+        path: path.to_string(),
+        message,
+    };
+    let condition = RuleCondition::parse(&condition).map_err(invalid)?;
+    let trip = trip.ok_or_else(|| RulesError::ConditionWithoutTrip {
+        path: path.to_string(),
+    })?;
+    condition.is_met(trip).map_err(invalid)
+}
+
+/// Checks the `when` condition of a rules entry without a trip, for validating rule files
+pub fn check_condition(condition: &Value) -> Result<(), String> {
+    RuleCondition::parse(condition).map(|_| ())
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{apply_conditions, parse_departure_time, sun_times, RuleConditionTrip};
+    use crate::router::rules::RulesError;
+
+    #[test]
+    fn conditions_for_departure() {
+        let rules = json!({
+            "basic": { "avoid_urban": { "enabled": true, "priority": 60, "when": { "months": [11, 12, 1, 2] } } },
+            "tag_matchers": [
+                { "key": "lit", "value": "no", "action": "avoid", "when": { "time": "sunset..sunrise" } },
+                { "key": "natural", "value": "saddle", "action": "exclude" }
+            ]
+        });
+        // Riga, the sun sets around 22:20 at midsummer
+        let trip = |date_time| RuleConditionTrip {
+            departure: parse_departure_time(date_time).unwrap(),
+            start_lat: 56.95,
+            start_lon: 24.1,
+        };
+
+        let mut summer_evening = rules.clone();
+        apply_conditions(
+            &mut summer_evening,
+            Some(&trip("2025-06-21T20:00:00+03:00")),
+        )
+        .unwrap();
+        assert_eq!(summer_evening["basic"], json!({}));
+        assert_eq!(summer_evening["tag_matchers"].as_array().unwrap().len(), 1);
+
+        let mut winter_night = rules.clone();
+        apply_conditions(&mut winter_night, Some(&trip("2025-12-21T17:00:00+02:00"))).unwrap();
+        assert_eq!(
+            winter_night["basic"]["avoid_urban"],
+            json!({ "enabled": true, "priority": 60 })
+        );
+        assert_eq!(
+            winter_night["tag_matchers"][0],
+            json!({ "key": "lit", "value": "no", "action": "avoid" })
+        );
+
+        let (sunrise, sunset) = sun_times(&trip("2025-06-21T12:00:00+03:00"));
+        assert!((4 * 60 + 20..4 * 60 + 40).contains(&sunrise), "{sunrise}");
+        assert!((22 * 60 + 10..22 * 60 + 30).contains(&sunset), "{sunset}");
+
+        assert!(matches!(
+            apply_conditions(&mut rules.clone(), None),
+            Err(RulesError::ConditionWithoutTrip { path }) if path == "basic.avoid_urban"
+        ));
+        assert!(matches!(
+            apply_conditions(
+                &mut json!({ "highway": { "track": { "action": "avoid", "when": { "time": "dusk..dawn" } } } }),
+                Some(&trip("2025-06-21T20:00:00+03:00"))
+            ),
+            Err(RulesError::ConditionInvalid { path, .. }) if path == "highway.track"
+        ));
+    }
+}
@@ -1,9 +1,16 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::{self, BufRead, IsTerminal};
-use std::{path::PathBuf, str::Utf8Error};
-use tracing::trace;
+use std::{
+    path::{Path, PathBuf},
+    str::Utf8Error,
+};
+use tracing::{trace, warn};
+
+use crate::{map_data::graph::ElementTagSet, units::Units};
+
+use super::rule_conditions::{apply_conditions, RuleConditionTrip};
 
 #[derive(Debug, thiserror::Error)]
 pub enum RulesError {
@@ -16,10 +23,70 @@ pub enum RulesError {
     #[error("Failed to parse JSON: {error}")]
     JsonParse { error: serde_json::Error },
 
+    #[error("Failed to parse TOML: {error}")]
+    TomlParse { error: toml::de::Error },
+
+    #[error("Failed to parse YAML: {error}")]
+    YamlParse { error: serde_norway::Error },
+
     #[error("Failed to read from stdin: {error}")]
     StdinRead { error: io::Error },
+
+    #[error("Rule '{field}' can't be overridden")]
+    NotOverridable { field: String },
+
+    #[error("Failed to extend '{name}': {error}")]
+    Extends {
+        name: String,
+        error: Box<RulesError>,
+    },
+
+    #[error("Rules extend themselves through '{name}'")]
+    ExtendsCycle { name: String },
+
+    #[error("`extends` must be the name of a preset or a rules file")]
+    ExtendsInvalid,
+
+    #[error("Invalid condition of {path}: {message}")]
+    ConditionInvalid { path: String, message: String },
+
+    #[error("{path} has a `when` condition, conditions can only be used when generating a route")]
+    ConditionWithoutTrip { path: String },
+
+    #[error(
+        "Rules have schema_version {version}, supported versions are 1 to {RULES_SCHEMA_VERSION}"
+    )]
+    SchemaVersion { version: u64 },
 }
 
+/// Version of the rules format, rules without a `schema_version` are from before it was added
+/// and are version 1
+pub const RULES_SCHEMA_VERSION: u32 = 2;
+
+/// Upgrades of the rules format, the one at index `i` upgrades version `i + 1` to `i + 2` and
+/// returns a warning for every change it made
+const MIGRATIONS: [fn(&mut serde_json::Value) -> Vec<String>; 1] = [migrate_step_limit];
+
+/// Built-in rules that rule files can extend by name
+const PRESETS: [(&str, &str); 4] = [
+    (
+        "default",
+        include_str!("../../rule-examples/rules-default.json"),
+    ),
+    (
+        "avoid-unpaved",
+        include_str!("../../rule-examples/rules-avoid-unpaved.json"),
+    ),
+    (
+        "prefer-unpaved",
+        include_str!("../../rule-examples/rules-prefer-unpaved.json"),
+    ),
+    (
+        "gravel-touring",
+        include_str!("../../rule-examples/rules-gravel-touring.json"),
+    ),
+];
+
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
 #[serde(tag = "action", rename_all = "lowercase", deny_unknown_fields)]
 pub enum RulesTagValueAction {
@@ -27,16 +94,47 @@ pub enum RulesTagValueAction {
     Priority { value: u8 },
 }
 
+/// Action for ways with a matching tag. Penalty and bonus use `amount` as the priority value
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RulesTagMatcherAction {
+    Exclude,
+    Penalty,
+    Bonus,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
-pub struct BasicRuleStepLimit(pub u32);
+pub struct RulesTagMatcher {
+    pub key: String,
+    pub value: String,
+    pub action: RulesTagMatcherAction,
+    #[serde(default)]
+    pub amount: u8,
+}
 
-impl Default for BasicRuleStepLimit {
-    fn default() -> Self {
-        Self(30000)
+impl RulesTagMatcher {
+    pub fn matches(&self, tags: &ElementTagSet) -> bool {
+        tags.get(&self.key)
+            .is_some_and(|value| value == self.value.as_str())
     }
 }
 
+/// User provided weight function, written in Rhai, called for every fork choice when the router is
+/// built with the `scripting` feature
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RulesScriptedWeight {
+    /// Path to the script file
+    pub file: PathBuf,
+    /// Name of the function in the script to call
+    pub function: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BasicRuleStepLimit(pub u32);
+
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct BasicRulePreferSameRoad {
@@ -74,6 +172,7 @@ impl Default for BasicRuleProgressDirection {
 pub struct BasicRuleProgressSpeed {
     pub enabled: bool,
     pub check_steps_back: usize,
+    #[schemars(range(min = 0.0))]
     pub last_step_distance_below_avg_with_ratio: f32,
 }
 
@@ -91,6 +190,7 @@ impl Default for BasicRuleProgressSpeed {
 #[serde(deny_unknown_fields)]
 pub struct BasicRuleNoShortDetour {
     pub enabled: bool,
+    #[schemars(range(min = 0.0))]
     pub min_detour_len_m: f32,
 }
 
@@ -106,6 +206,7 @@ impl Default for BasicRuleNoShortDetour {
 #[serde(deny_unknown_fields)]
 pub struct BasicRuleNoSharpTurns {
     pub enabled: bool,
+    #[schemars(range(min = 0.0, max = 180.0))]
     pub under_deg: f32,
     pub priority: u8,
 }
@@ -120,11 +221,90 @@ impl Default for BasicRuleNoSharpTurns {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BasicRuleNoSteepGrades {
+    pub enabled: bool,
+    #[schemars(range(min = 0.0))]
+    pub max_grade_percent: f32,
+    pub priority: u8,
+}
+
+impl Default for BasicRuleNoSteepGrades {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_grade_percent: 10.,
+            priority: 40,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BasicRuleAvoidUrban {
+    pub enabled: bool,
+    pub priority: u8,
+}
+
+impl Default for BasicRuleAvoidUrban {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            priority: 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BasicRuleNoSameRoadReturn {
+    pub enabled: bool,
+    pub priority: u8,
+    /// Length of the end of the route that is not checked, so staying on the current road is
+    /// not penalized
+    #[schemars(range(min = 0.0))]
+    pub skip_recent_m: f32,
+}
+
+impl Default for BasicRuleNoSameRoadReturn {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            priority: 40,
+            skip_recent_m: 2000.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BasicRulePreferCurvy {
+    pub enabled: bool,
+    pub priority: u8,
+    #[schemars(range(min = 0.0))]
+    pub min_curve_radius_m: f32,
+    /// Weight every road that is not straight gets on top of its curviness based priority
+    pub curvy_road_bonus: u8,
+}
+
+impl Default for BasicRulePreferCurvy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            priority: 60,
+            min_curve_radius_m: 15.,
+            curvy_road_bonus: 20,
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct BasicRules {
+    /// Derived from the trip distance and the road density of the map data when not set
     #[serde(default)]
-    pub step_limit: BasicRuleStepLimit,
+    pub step_limit: Option<BasicRuleStepLimit>,
 
     #[serde(default)]
     pub prefer_same_road: BasicRulePreferSameRoad,
@@ -140,6 +320,18 @@ pub struct BasicRules {
 
     #[serde(default)]
     pub no_sharp_turns: BasicRuleNoSharpTurns,
+
+    #[serde(default)]
+    pub no_steep_grades: BasicRuleNoSteepGrades,
+
+    #[serde(default)]
+    pub prefer_curvy: BasicRulePreferCurvy,
+
+    #[serde(default)]
+    pub avoid_urban: BasicRuleAvoidUrban,
+
+    #[serde(default)]
+    pub no_same_road_return: BasicRuleNoSameRoadReturn,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
@@ -208,6 +400,92 @@ pub struct GenerationRulesWaypoints {
     pub round_trip: GenerationRulesRoundTrip,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct GenerationRulesRouteDiversity {
+    pub enabled: bool,
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub min_dissimilarity: f32,
+}
+
+impl Default for GenerationRulesRouteDiversity {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_dissimilarity: 0.2,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct GenerationRulesPartialRoutes {
+    pub enabled: bool,
+}
+
+/// Length window for the generated routes, either end can be left open
+#[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct GenerationRulesRouteLength {
+    #[serde(default)]
+    #[schemars(range(min = 0.0))]
+    pub min_length_m: Option<f64>,
+    #[serde(default)]
+    #[schemars(range(min = 0.0))]
+    pub max_length_m: Option<f64>,
+}
+
+impl GenerationRulesRouteLength {
+    pub fn contains(&self, len_m: f64) -> bool {
+        self.min_length_m
+            .is_none_or(|min_length_m| len_m >= min_length_m)
+            && self
+                .max_length_m
+                .is_none_or(|max_length_m| len_m <= max_length_m)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ClusteringAlgorithm {
+    Hdbscan,
+    Dbscan,
+    /// Every route is noise, the best scoring routes are returned
+    None,
+}
+
+/// Distance between routes, each route approximated by the same number of evenly spread points
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ClusteringDistanceMetric {
+    Euclidean,
+    Manhattan,
+    Chebyshev,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct GenerationRulesClustering {
+    pub algorithm: ClusteringAlgorithm,
+    /// Distance in degrees under which routes are in the same cluster
+    #[schemars(range(min = 0.0))]
+    pub epsilon: f64,
+    /// Minimum cluster size for hdbscan, minimum neighbours of a core route for dbscan
+    pub min_points: usize,
+    pub distance_metric: ClusteringDistanceMetric,
+}
+
+impl Default for GenerationRulesClustering {
+    fn default() -> Self {
+        Self {
+            algorithm: ClusteringAlgorithm::Hdbscan,
+            epsilon: 0.1,
+            min_points: 2,
+            distance_metric: ClusteringDistanceMetric::Euclidean,
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct GenerationRules {
@@ -215,6 +493,85 @@ pub struct GenerationRules {
     pub waypoint_generation: GenerationRulesWaypoints,
     #[serde(default)]
     pub route_generation_retry: GenerationRulesRetry,
+    #[serde(default)]
+    pub route_diversity: GenerationRulesRouteDiversity,
+    #[serde(default)]
+    pub partial_routes: GenerationRulesPartialRoutes,
+    #[serde(default)]
+    pub clustering: GenerationRulesClustering,
+    #[serde(default)]
+    pub route_length: GenerationRulesRouteLength,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScoringRuleComponent {
+    pub enabled: bool,
+    pub weight: f64,
+}
+
+impl Default for ScoringRuleComponent {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            weight: 1.,
+        }
+    }
+}
+
+impl ScoringRuleComponent {
+    /// Parts added after the score was first made configurable, they are left out of the score
+    /// unless the rule file sets them, so routes rank the same as before
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Self::default()
+        }
+    }
+
+    pub fn apply(&self, value: f64) -> f64 {
+        if self.enabled {
+            value * self.weight
+        } else {
+            0.
+        }
+    }
+}
+
+/// Weights of the parts the route score is made of
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ScoringRules {
+    #[serde(default)]
+    pub curves: ScoringRuleComponent,
+
+    #[serde(default)]
+    pub highway: ScoringRuleComponent,
+
+    #[serde(default)]
+    pub surface: ScoringRuleComponent,
+
+    #[serde(default)]
+    pub smoothness: ScoringRuleComponent,
+
+    #[serde(default = "ScoringRuleComponent::disabled")]
+    pub junctions: ScoringRuleComponent,
+
+    #[serde(default = "ScoringRuleComponent::disabled")]
+    pub urban: ScoringRuleComponent,
+}
+
+impl Default for ScoringRules {
+    fn default() -> Self {
+        Self {
+            curves: ScoringRuleComponent::default(),
+            highway: ScoringRuleComponent::default(),
+            surface: ScoringRuleComponent::default(),
+            smoothness: ScoringRuleComponent::default(),
+            junctions: ScoringRuleComponent::disabled(),
+            urban: ScoringRuleComponent::disabled(),
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
@@ -226,17 +583,60 @@ pub struct RouterRules {
     pub surface: Option<HashMap<String, RulesTagValueAction>>,
     pub smoothness: Option<HashMap<String, RulesTagValueAction>>,
     #[serde(default)]
+    pub highway_weights: HashMap<String, u8>,
+    #[serde(default)]
+    pub surface_weights: HashMap<String, u8>,
+    #[serde(default)]
+    pub smoothness_weights: HashMap<String, u8>,
+    #[serde(default)]
+    pub tag_matchers: Vec<RulesTagMatcher>,
+    #[serde(default)]
+    pub avoid_way_ids: HashSet<u64>,
+    #[serde(default)]
+    pub avoid_node_ids: HashSet<u64>,
+    #[serde(default)]
     pub generation: GenerationRules,
+    #[serde(default)]
+    pub scoring: ScoringRules,
+    #[serde(default)]
+    pub scripted_weights: Vec<RulesScriptedWeight>,
+    /// Units of the distances in route descriptions, turn instructions and reports written by
+    /// the command line, metric when not set. Route generation always uses meters
+    #[serde(default)]
+    pub units: Option<Units>,
+    /// Version of the rules format, rules of older versions are upgraded when they are read
+    #[serde(default)]
+    pub schema_version: RulesSchemaVersion,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(transparent)]
+pub struct RulesSchemaVersion(pub u32);
+
+impl Default for RulesSchemaVersion {
+    fn default() -> Self {
+        Self(RULES_SCHEMA_VERSION)
+    }
 }
 
 impl RouterRules {
+    pub fn is_excluded_by_tag_matchers(&self, tags: &ElementTagSet) -> bool {
+        self.tag_matchers.iter().any(|matcher| {
+            matcher.action == RulesTagMatcherAction::Exclude && matcher.matches(tags)
+        })
+    }
+
+    /// Reads the rules file, the `when` conditions of the rules are evaluated for the trip
     #[tracing::instrument]
-    pub fn read_from_file(file: PathBuf) -> Result<Self, RulesError> {
-        let file = std::fs::read(file).map_err(|error| RulesError::FileRead { error })?;
-        let text =
-            std::str::from_utf8(&file[..]).map_err(|error| RulesError::FileParse { error })?;
-        let rules: RouterRules =
-            serde_json::from_str(text).map_err(|error| RulesError::JsonParse { error })?;
+    pub fn read_from_file(
+        file: PathBuf,
+        trip: Option<&RuleConditionTrip>,
+    ) -> Result<Self, RulesError> {
+        let rules = Self::from_value(
+            Self::read_resolved_value(&file)?,
+            &file.to_string_lossy(),
+            trip,
+        )?;
 
         trace!(
             rules = serde_json::to_string_pretty(&rules).unwrap(),
@@ -245,8 +645,14 @@ impl RouterRules {
         Ok(rules)
     }
 
+    /// Rules file as a JSON value, with the rules it extends merged in
+    pub fn read_resolved_value(file: &Path) -> Result<serde_json::Value, RulesError> {
+        let rules = read_rules_file(file)?;
+        resolve_extends(rules, file.parent(), &mut vec![file_key(file)])
+    }
+
     #[tracing::instrument]
-    pub fn read_from_stdin() -> Result<Self, RulesError> {
+    pub fn read_from_stdin(trip: Option<&RuleConditionTrip>) -> Result<Self, RulesError> {
         let mut text = String::new();
         let stdin = io::stdin();
         let rules: RouterRules = if !stdin.is_terminal() {
@@ -255,7 +661,11 @@ impl RouterRules {
                 text.push_str(&line);
             }
 
-            serde_json::from_str(&text).map_err(|error| RulesError::JsonParse { error })?
+            let rules =
+                serde_json::from_str(&text).map_err(|error| RulesError::JsonParse { error })?;
+            // files are extended relative to the working directory
+            let rules = resolve_extends(rules, None, &mut Vec::new())?;
+            Self::from_value(rules, "stdin", trip)?
         } else {
             RouterRules::default()
         };
@@ -263,18 +673,413 @@ impl RouterRules {
         Ok(rules)
     }
 
-    pub fn read(file: Option<PathBuf>) -> Result<Self, RulesError> {
+    /// Rules from a JSON value, upgraded to the current schema version, the `when` conditions of
+    /// the rules are evaluated for the trip. `source` names the rules in the migration warnings
+    pub fn from_value(
+        mut rules: serde_json::Value,
+        source: &str,
+        trip: Option<&RuleConditionTrip>,
+    ) -> Result<Self, RulesError> {
+        migrate(&mut rules, source)?;
+        apply_conditions(&mut rules, trip)?;
+        serde_json::from_value(rules).map_err(|error| RulesError::JsonParse { error })
+    }
+
+    pub fn read(
+        file: Option<PathBuf>,
+        trip: Option<&RuleConditionTrip>,
+    ) -> Result<Self, RulesError> {
         match file {
-            None => Self::read_from_stdin(),
-            Some(file) => Self::read_from_file(file),
+            None => Self::read_from_stdin(trip),
+            Some(file) => Self::read_from_file(file, trip),
+        }
+    }
+
+    /// Rules with the partial rules in `overrides` merged in: objects are merged field by field,
+    /// any other value replaces the one in these rules. When `overridable` is given, only the
+    /// fields in it, as dot separated paths like `basic.prefer_same_road`, and the fields within
+    /// them can be overridden
+    pub fn with_overrides(
+        &self,
+        overrides: &serde_json::Value,
+        overridable: Option<&[String]>,
+    ) -> Result<Self, RulesError> {
+        if let Some(overridable) = overridable {
+            check_overridable(overrides, "", overridable)?;
+        }
+        let mut rules =
+            serde_json::to_value(self).map_err(|error| RulesError::JsonParse { error })?;
+        merge_json(&mut rules, overrides);
+        serde_json::from_value(rules).map_err(|error| RulesError::JsonParse { error })
+    }
+}
+
+/// Reads a rules file as TOML or YAML by its extension, as JSON otherwise
+pub fn read_rules_file(file: &Path) -> Result<serde_json::Value, RulesError> {
+    let extension = file
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    let file = std::fs::read(file).map_err(|error| RulesError::FileRead { error })?;
+    let text = std::str::from_utf8(&file[..]).map_err(|error| RulesError::FileParse { error })?;
+    match extension.as_deref() {
+        Some("toml") => toml::from_str(text).map_err(|error| RulesError::TomlParse { error }),
+        Some("yaml" | "yml") => {
+            serde_norway::from_str(text).map_err(|error| RulesError::YamlParse { error })
         }
+        _ => serde_json::from_str(text).map_err(|error| RulesError::JsonParse { error }),
+    }
+}
+
+/// Tells files apart however they are referred to
+fn file_key(path: &Path) -> String {
+    path.canonicalize()
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Replaces the `extends` field of the rules with the rules it names, with these rules merged
+/// onto them. The name is a built-in preset or a rules file relative to `dir`, the directory of
+/// the file the rules are from. `extended` holds the rules extended so far, to catch cycles.
+/// Every document is upgraded to the current schema version before it is merged
+fn resolve_extends(
+    mut rules: serde_json::Value,
+    dir: Option<&Path>,
+    extended: &mut Vec<String>,
+) -> Result<serde_json::Value, RulesError> {
+    migrate(&mut rules, extended.last().map_or("stdin", String::as_str))?;
+    let Some(name) = rules
+        .as_object_mut()
+        .and_then(|rules| rules.remove("extends"))
+    else {
+        return Ok(rules);
+    };
+    let name = name.as_str().ok_or(RulesError::ExtendsInvalid)?.to_string();
+
+    let (key, base, base_dir) = match PRESETS.iter().find(|(preset, _)| *preset == name) {
+        Some((_, preset)) => (
+            name.clone(),
+            serde_json::from_str(preset).map_err(|error| RulesError::JsonParse { error }),
+            None,
+        ),
+        None => {
+            let path = dir.map_or_else(|| PathBuf::from(&name), |dir| dir.join(&name));
+            let key = file_key(&path);
+            let base_dir = path.parent().map(Path::to_path_buf);
+            (key, read_rules_file(&path), base_dir)
+        }
+    };
+    if extended.contains(&key) {
+        return Err(RulesError::ExtendsCycle { name });
+    }
+    extended.push(key);
+
+    let mut base = base
+        .and_then(|base| resolve_extends(base, base_dir.as_deref(), extended))
+        .map_err(|error| RulesError::Extends {
+            name: name.clone(),
+            error: Box::new(error),
+        })?;
+    merge_json(&mut base, &rules);
+    Ok(base)
+}
+
+/// Upgrades rules of an older `schema_version` to the current one, logging every change.
+/// `source` names the rules in the warnings
+fn migrate(rules: &mut serde_json::Value, source: &str) -> Result<(), RulesError> {
+    let Some(fields) = rules.as_object() else {
+        return Ok(());
+    };
+    let version = match fields.get("schema_version") {
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .filter(|version| (1..=RULES_SCHEMA_VERSION as u64).contains(version))
+            .ok_or_else(|| RulesError::SchemaVersion {
+                version: version.as_u64().unwrap_or_default(),
+            })?,
+    };
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize - 1) {
+        for warning in migration(rules) {
+            warn!(
+                rules = source,
+                from_version = index + 1,
+                to_version = index + 2,
+                "{warning}"
+            );
+        }
+    }
+    if let Some(fields) = rules.as_object_mut() {
+        fields.insert(
+            "schema_version".to_string(),
+            serde_json::Value::from(RULES_SCHEMA_VERSION),
+        );
+    }
+    Ok(())
+}
+
+/// Version 1 rule files pinned `basic.step_limit` to the old default of 30000, the limit is now
+/// derived from the length of the trip when it is not set
+fn migrate_step_limit(rules: &mut serde_json::Value) -> Vec<String> {
+    let Some(basic) = rules
+        .get_mut("basic")
+        .and_then(serde_json::Value::as_object_mut)
+    else {
+        return Vec::new();
+    };
+    if basic.get("step_limit") != Some(&serde_json::Value::from(30000)) {
+        return Vec::new();
+    }
+    basic.remove("step_limit");
+    vec![
+        "removed basic.step_limit 30000, the old default, the limit is now derived from the trip"
+            .to_string(),
+    ]
+}
+
+fn merge_json(base: &mut serde_json::Value, overrides: &serde_json::Value) {
+    match (base, overrides) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(key) {
+                    Some(base_value) => merge_json(base_value, value),
+                    None => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides.clone(),
+    }
+}
+
+fn check_overridable(
+    overrides: &serde_json::Value,
+    path: &str,
+    overridable: &[String],
+) -> Result<(), RulesError> {
+    let is_overridable = overridable.iter().any(|field| {
+        path == field
+            || path
+                .strip_prefix(field.as_str())
+                .is_some_and(|rest| rest.starts_with('.'))
+    });
+    if !path.is_empty() && is_overridable {
+        return Ok(());
+    }
+    match overrides {
+        serde_json::Value::Object(fields) => fields.iter().try_for_each(|(key, value)| {
+            let path = if path.is_empty() {
+                key.clone()
+            } else {
+                format!("{path}.{key}")
+            };
+            check_overridable(value, &path, overridable)
+        }),
+        _ => Err(RulesError::NotOverridable {
+            field: path.to_string(),
+        }),
     }
 }
 
 #[cfg(feature = "rule-schema-writer")]
 pub fn generate_json_schema(dest: &PathBuf) -> anyhow::Result<()> {
-    let schema = schemars::schema_for!(RouterRules);
+    use schemars::visit::Visitor;
+
+    let mut schema = schemars::schema_for!(RouterRules);
+    SchemaFloatRounding.visit_root_schema(&mut schema);
     let file = std::fs::File::create(dest)?;
     serde_json::to_writer_pretty(file, &schema)?;
     Ok(())
 }
+
+/// Defaults of `f32` fields are widened to `f64` in the schema, writing 0.2 as
+/// 0.20000000298023224, so the values that are an `f32` are written as the shortest `f32` instead
+#[cfg(feature = "rule-schema-writer")]
+pub(crate) struct SchemaFloatRounding;
+
+#[cfg(feature = "rule-schema-writer")]
+impl SchemaFloatRounding {
+    fn round(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Number(number) if number.is_f64() => {
+                let rounded = number
+                    .as_f64()
+                    .filter(|float| f64::from(*float as f32) == *float)
+                    .and_then(|float| (float as f32).to_string().parse::<f64>().ok())
+                    .and_then(serde_json::Number::from_f64);
+                if let Some(rounded) = rounded {
+                    *number = rounded;
+                }
+            }
+            serde_json::Value::Array(values) => values.iter_mut().for_each(Self::round),
+            serde_json::Value::Object(fields) => fields.values_mut().for_each(Self::round),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(feature = "rule-schema-writer")]
+impl schemars::visit::Visitor for SchemaFloatRounding {
+    fn visit_schema_object(&mut self, schema: &mut schemars::schema::SchemaObject) {
+        if let Some(metadata) = &mut schema.metadata {
+            metadata.default.iter_mut().for_each(Self::round);
+            metadata.examples.iter_mut().for_each(Self::round);
+        }
+        schema
+            .enum_values
+            .iter_mut()
+            .flatten()
+            .for_each(Self::round);
+        schema.const_value.iter_mut().for_each(Self::round);
+        schemars::visit::visit_schema_object(self, schema);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{
+        migrate, resolve_extends, RouterRules, RulesError, RulesSchemaVersion, PRESETS,
+        RULES_SCHEMA_VERSION,
+    };
+
+    #[test]
+    fn rules_with_overrides() {
+        let base = RouterRules::default();
+        let overrides = json!({
+            "basic": { "prefer_same_road": { "enabled": false } },
+            "highway_weights": { "primary": 10 }
+        });
+
+        let rules = base.with_overrides(&overrides, None).unwrap();
+        assert!(!rules.basic.prefer_same_road.enabled);
+        assert_eq!(
+            rules.basic.prefer_same_road.priority,
+            base.basic.prefer_same_road.priority
+        );
+        assert_eq!(rules.highway_weights.get("primary"), Some(&10));
+
+        let overridable = ["basic.prefer_same_road".to_string()];
+        assert!(matches!(
+            base.with_overrides(&overrides, Some(&overridable)),
+            Err(RulesError::NotOverridable { field }) if field == "highway_weights.primary"
+        ));
+        let overridable = [
+            "basic.prefer_same_road".to_string(),
+            "highway_weights".to_string(),
+        ];
+        assert!(base.with_overrides(&overrides, Some(&overridable)).is_ok());
+    }
+
+    #[test]
+    fn rules_extends_presets_and_files() {
+        for (name, _) in PRESETS {
+            let rules = resolve_extends(json!({ "extends": name }), None, &mut Vec::new())
+                .unwrap_or_else(|error| panic!("preset {name}: {error}"));
+            assert!(serde_json::from_value::<RouterRules>(rules).is_ok());
+        }
+
+        let rules = resolve_extends(
+            json!({
+                "extends": "gravel-touring",
+                "basic": { "avoid_urban": { "priority": 10 } }
+            }),
+            None,
+            &mut Vec::new(),
+        )
+        .unwrap();
+        let rules: RouterRules = serde_json::from_value(rules).unwrap();
+        assert!(rules.basic.avoid_urban.enabled);
+        assert_eq!(rules.basic.avoid_urban.priority, 10);
+        assert!(rules
+            .surface
+            .is_some_and(|surface| surface.contains_key("gravel")));
+
+        let dir = std::env::temp_dir().join(format!("ridi-rules-extends-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.json"), r#"{"extends": "b.json"}"#).unwrap();
+        std::fs::write(dir.join("b.json"), r#"{"extends": "a.json"}"#).unwrap();
+        let result = RouterRules::read_from_file(dir.join("a.json"), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let Err(RulesError::Extends { error, .. }) = result else {
+            panic!("expected an extends error");
+        };
+        assert!(matches!(*error, RulesError::ExtendsCycle { ref name } if name == "a.json"));
+    }
+
+    #[test]
+    fn rules_from_toml_and_yaml() {
+        let dir = std::env::temp_dir().join(format!("ridi-rules-formats-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("rules.toml"),
+            "extends = \"rules.yml\"\n\n[basic.no_sharp_turns]\nunder_deg = 120\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("rules.yml"),
+            "# wider turns\nextends: default\nbasic:\n  no_sharp_turns:\n    enabled: false\nsurface_weights:\n  gravel: 200\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("broken.yaml"), "basic: [\n").unwrap();
+        let rules = RouterRules::read_from_file(dir.join("rules.toml"), None);
+        let broken = RouterRules::read_from_file(dir.join("broken.yaml"), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let rules = rules.unwrap();
+        assert!(!rules.basic.no_sharp_turns.enabled);
+        assert_eq!(rules.basic.no_sharp_turns.under_deg, 120.0);
+        assert_eq!(rules.surface_weights.get("gravel"), Some(&200));
+        assert!(matches!(broken, Err(RulesError::YamlParse { .. })));
+    }
+
+    #[test]
+    fn rules_schema_migration() {
+        let mut rules = json!({ "basic": { "step_limit": 30000 } });
+        migrate(&mut rules, "test").unwrap();
+        assert_eq!(
+            rules,
+            json!({ "basic": {}, "schema_version": RULES_SCHEMA_VERSION })
+        );
+
+        let mut rules = json!({ "schema_version": 2, "basic": { "step_limit": 30000 } });
+        migrate(&mut rules, "test").unwrap();
+        assert_eq!(rules["basic"]["step_limit"], 30000);
+
+        assert!(matches!(
+            migrate(&mut json!({ "schema_version": 99 }), "test"),
+            Err(RulesError::SchemaVersion { version: 99 })
+        ));
+
+        // rules sent with requests have no file to be upgraded when read from
+        let rules =
+            RouterRules::from_value(json!({ "basic": { "step_limit": 30000 } }), "test", None)
+                .unwrap();
+        assert!(rules.basic.step_limit.is_none());
+        assert_eq!(
+            rules.schema_version,
+            RulesSchemaVersion(RULES_SCHEMA_VERSION)
+        );
+    }
+
+    #[test]
+    fn scoring_rules_defaults() {
+        // rules written before the scoring components were configurable
+        let rules = RouterRules::from_value(json!({ "basic": {} }), "test", None).unwrap();
+        assert!(rules.scoring.curves.enabled);
+        assert_eq!(rules.scoring.smoothness.weight, 1.);
+
+        let rules = RouterRules::from_value(
+            json!({ "scoring": { "curves": { "weight": 2.0 }, "highway": { "enabled": false } } }),
+            "test",
+            None,
+        )
+        .unwrap();
+        assert!(rules.scoring.curves.enabled);
+        assert_eq!(rules.scoring.curves.weight, 2.);
+        assert!(!rules.scoring.highway.enabled);
+        assert_eq!(rules.scoring.highway.weight, 1.);
+    }
+}
@@ -0,0 +1,354 @@
+use std::fmt::Display;
+
+use serde_json::{Map, Value};
+
+use super::{rule_conditions::check_condition, rules::RouterRules};
+
+/// Closest known name is suggested for unknown fields and values at least this similar
+const SUGGESTION_SIMILARITY: f64 = 0.8;
+
+/// Problem found in a rules document, at the dot separated path of the field
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleDiagnostic {
+    pub path: String,
+    pub message: String,
+}
+
+impl Display for RuleDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = if self.path.is_empty() {
+            "rules"
+        } else {
+            &self.path
+        };
+        write!(f, "{path}: {}", self.message)
+    }
+}
+
+/// Checks the rules against the schema of [`RouterRules`], reporting every unknown field with
+/// the closest known one, every value of the wrong type or outside of its range and every
+/// missing field, instead of stopping at the first problem like parsing the rules does
+pub fn check_rules(rules: &Value) -> Vec<RuleDiagnostic> {
+    let schema = serde_json::to_value(schemars::schema_for!(RouterRules)).unwrap_or(Value::Null);
+    let checker = SchemaChecker {
+        definitions: schema
+            .get("definitions")
+            .and_then(Value::as_object)
+            .cloned()
+            .unwrap_or_default(),
+    };
+    let mut diagnostics = Vec::new();
+    checker.check(rules, &schema, "", &mut diagnostics);
+    check_route_length(rules, &mut diagnostics);
+    diagnostics.sort_by(|a, b| (&a.path, &a.message).cmp(&(&b.path, &b.message)));
+    // objects reached through more than one sub schema are checked more than once
+    diagnostics.dedup();
+    diagnostics
+}
+
+/// Checks that need more than one field
+fn check_route_length(rules: &Value, diagnostics: &mut Vec<RuleDiagnostic>) {
+    let route_length = rules.pointer("/generation/route_length");
+    let length = |field| {
+        route_length
+            .and_then(|route_length| route_length.get(field))
+            .and_then(Value::as_f64)
+    };
+    if let (Some(min_length_m), Some(max_length_m)) =
+        (length("min_length_m"), length("max_length_m"))
+    {
+        if min_length_m > max_length_m {
+            diagnostics.push(RuleDiagnostic {
+                path: "generation.route_length".to_string(),
+                message: format!(
+                    "min_length_m {min_length_m} is over max_length_m {max_length_m}, no route can \
+                     be returned"
+                ),
+            });
+        }
+    }
+}
+
+struct SchemaChecker {
+    definitions: Map<String, Value>,
+}
+
+impl SchemaChecker {
+    fn check(&self, value: &Value, schema: &Value, path: &str, out: &mut Vec<RuleDiagnostic>) {
+        let diagnostic = |message: String| RuleDiagnostic {
+            path: path.to_string(),
+            message,
+        };
+
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            if let Some(definition) = reference
+                .strip_prefix("#/definitions/")
+                .and_then(|name| self.definitions.get(name))
+            {
+                self.check(value, definition, path, out);
+            }
+            return;
+        }
+        for sub_schema in schema
+            .get("allOf")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            self.check(value, sub_schema, path, out);
+        }
+        let variants = schema
+            .get("anyOf")
+            .or_else(|| schema.get("oneOf"))
+            .and_then(Value::as_array);
+        // enums with documented values have a variant for every documented value
+        let variant_values = variants.and_then(|variants| {
+            variants
+                .iter()
+                .map(|variant| variant.get("enum").and_then(Value::as_array))
+                .collect::<Option<Vec<_>>>()
+        });
+        if let Some(variant_values) = variant_values {
+            let allowed = variant_values.into_iter().flatten().cloned().collect();
+            self.check(
+                value,
+                &serde_json::json!({ "enum": Value::Array(allowed) }),
+                path,
+                out,
+            );
+        } else if let Some(variants) = variants {
+            // the variant closest to the value has the most useful diagnostics
+            let best = variants
+                .iter()
+                .map(|variant| {
+                    let mut variant_out = Vec::new();
+                    self.check(value, variant, path, &mut variant_out);
+                    variant_out
+                })
+                .min_by_key(Vec::len);
+            out.extend(best.unwrap_or_default());
+        }
+
+        if let Some(expected) = schema.get("type") {
+            let types = match expected {
+                Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+                Value::String(expected) => vec![expected.as_str()],
+                _ => Vec::new(),
+            };
+            if !types.iter().any(|expected| has_type(value, expected)) {
+                out.push(diagnostic(format!(
+                    "expected {}, found {}",
+                    types.join(" or "),
+                    type_name(value)
+                )));
+                return;
+            }
+        }
+
+        if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+            if !allowed.contains(value) {
+                let names = allowed.iter().filter_map(Value::as_str).collect::<Vec<_>>();
+                let suggestion = value
+                    .as_str()
+                    .and_then(|value| suggest(value, names.iter().copied()))
+                    .map_or(String::new(), |name| format!(", did you mean `{name}`?"));
+                out.push(diagnostic(format!(
+                    "{value} is not one of {}{suggestion}",
+                    names.join(", ")
+                )));
+            }
+        }
+
+        match value {
+            Value::Number(number) => {
+                if let Some(message) = check_range(number, schema) {
+                    out.push(diagnostic(message));
+                }
+            }
+            Value::Array(items) => {
+                if let Some(item_schema) = schema.get("items") {
+                    for (index, item) in items.iter().enumerate() {
+                        self.check(item, item_schema, &format!("{path}[{index}]"), out);
+                    }
+                }
+            }
+            Value::Object(fields) => self.check_object(fields, schema, path, out),
+            _ => {}
+        }
+    }
+
+    fn check_object(
+        &self,
+        fields: &Map<String, Value>,
+        schema: &Value,
+        path: &str,
+        out: &mut Vec<RuleDiagnostic>,
+    ) {
+        let field_path = |field: &str| {
+            if path.is_empty() {
+                field.to_string()
+            } else {
+                format!("{path}.{field}")
+            }
+        };
+        let properties = schema.get("properties").and_then(Value::as_object);
+
+        for field in schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+        {
+            if !fields.contains_key(field) {
+                out.push(RuleDiagnostic {
+                    path: path.to_string(),
+                    message: format!("missing field `{field}`"),
+                });
+            }
+        }
+
+        for (field, value) in fields {
+            // any entry can have a condition, it is not a part of the schema
+            if field == "when" {
+                if let Err(message) = check_condition(value) {
+                    out.push(RuleDiagnostic {
+                        path: field_path(field),
+                        message,
+                    });
+                }
+                continue;
+            }
+            match (
+                properties.and_then(|properties| properties.get(field)),
+                schema.get("additionalProperties"),
+            ) {
+                (Some(field_schema), _) => self.check(value, field_schema, &field_path(field), out),
+                (None, Some(Value::Bool(false))) => {
+                    let suggestion = properties
+                        .and_then(|properties| {
+                            suggest(field, properties.keys().map(String::as_str))
+                        })
+                        .map_or(String::new(), |name| format!(", did you mean `{name}`?"));
+                    out.push(RuleDiagnostic {
+                        path: field_path(field),
+                        message: format!("unknown field `{field}`{suggestion}"),
+                    });
+                }
+                (None, Some(value_schema)) if value_schema.is_object() => {
+                    self.check(value, value_schema, &field_path(field), out)
+                }
+                (None, _) => {}
+            }
+        }
+    }
+}
+
+fn has_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Checks the minimum and maximum of the schema and the range of integer formats like uint8
+fn check_range(number: &serde_json::Number, schema: &Value) -> Option<String> {
+    let value = number.as_f64()?;
+    let format_max = match schema.get("format").and_then(Value::as_str) {
+        Some("uint8") => Some(u8::MAX as f64),
+        Some("uint16") => Some(u16::MAX as f64),
+        Some("uint32") => Some(u32::MAX as f64),
+        _ => None,
+    };
+    let min = schema.get("minimum").and_then(Value::as_f64);
+    let max = schema.get("maximum").and_then(Value::as_f64).or(format_max);
+    let out_of_range = min.is_some_and(|min| value < min) || max.is_some_and(|max| value > max);
+    out_of_range.then(|| match (min, max) {
+        (Some(min), Some(max)) => format!("{number} is not between {min} and {max}"),
+        (Some(min), None) => format!("{number} is below the minimum of {min}"),
+        (None, _) => format!(
+            "{number} is over the maximum of {}",
+            max.unwrap_or_default()
+        ),
+    })
+}
+
+fn suggest<'a>(name: &str, known: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    known
+        .map(|known| (strsim::jaro_winkler(name, known), known))
+        .filter(|(similarity, _)| *similarity >= SUGGESTION_SIMILARITY)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, known)| known)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{check_rules, RuleDiagnostic};
+
+    #[test]
+    fn check_rules_diagnostics() {
+        let rules = json!({
+            "basic": {
+                "prefer_same_rode": { "enabled": true, "priority": 30 },
+                "no_sharp_turns": { "enabled": true, "under_deg": 200.0 },
+                "avoid_urban": { "enabled": "yes", "priority": 300 }
+            },
+            "generation": {
+                "clustering": {
+                    "algorithm": "hdbscam",
+                    "epsilon": 0.1,
+                    "min_points": 2,
+                    "distance_metric": "euclidean"
+                },
+                "route_length": { "min_length_m": 100000.0, "max_length_m": 80000.0 }
+            },
+            "highway": {
+                "track": { "action": "priority", "value": 256 },
+                "path": { "action": "avoid", "when": { "time": "dusk..dawn" } }
+            }
+        });
+
+        let diagnostics = check_rules(&rules)
+            .iter()
+            .map(RuleDiagnostic::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            [
+                "basic.avoid_urban.enabled: expected boolean, found string",
+                "basic.avoid_urban.priority: 300 is not between 0 and 255",
+                "basic.no_sharp_turns: missing field `priority`",
+                "basic.no_sharp_turns.under_deg: 200.0 is not between 0 and 180",
+                "basic.prefer_same_rode: unknown field `prefer_same_rode`, did you mean `prefer_same_road`?",
+                "generation.clustering.algorithm: \"hdbscam\" is not one of hdbscan, dbscan, none, did you mean `hdbscan`?",
+                "generation.route_length: min_length_m 100000 is over max_length_m 80000, no route can be returned",
+                "highway.path.when: 'dusk' is not a time like 22:00, `sunrise` or `sunset`",
+                "highway.track.value: 256 is not between 0 and 255",
+            ]
+        );
+
+        let default_rules =
+            serde_json::from_str(include_str!("../../rule-examples/rules-default.json")).unwrap();
+        assert!(check_rules(&default_rules).is_empty());
+    }
+}
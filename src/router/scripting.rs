@@ -0,0 +1,299 @@
+use std::{io, path::PathBuf};
+
+#[cfg(not(feature = "scripting"))]
+use super::rules::RouterRules;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScriptingError {
+    #[error("Scripted weights are set in the rules, but the router was built without the `scripting` feature")]
+    Disabled,
+
+    #[error("Failed to read weight script {file:?}: {error}")]
+    FileRead { file: PathBuf, error: io::Error },
+
+    #[cfg(feature = "scripting")]
+    #[error("Failed to compile weight script {file:?}: {error}")]
+    Compile {
+        file: PathBuf,
+        error: rhai::ParseError,
+    },
+
+    #[error("Function {function} not found in weight script {file:?}")]
+    FunctionNotFound { file: PathBuf, function: String },
+}
+
+#[cfg(not(feature = "scripting"))]
+pub fn load_scripts(rules: &RouterRules) -> Result<(), ScriptingError> {
+    if rules.scripted_weights.is_empty() {
+        return Ok(());
+    }
+    Err(ScriptingError::Disabled)
+}
+
+#[cfg(feature = "scripting")]
+pub use engine::{load_scripts, weight_scripted};
+
+#[cfg(feature = "scripting")]
+mod engine {
+    use std::{
+        collections::HashMap,
+        path::PathBuf,
+        sync::{Arc, OnceLock, RwLock},
+    };
+
+    use rhai::{module_resolvers::DummyModuleResolver, Array, Dynamic, Engine, Map, Scope, AST};
+    use tracing::{error, trace};
+
+    use crate::router::{
+        navigator::WeightCalcResult, route::segment::Segment, rules::RouterRules,
+        weights::WeightCalcInput,
+    };
+
+    use super::ScriptingError;
+
+    /// Limits for a single weight function call, so a broken script can't hang route generation
+    const SCRIPT_MAX_OPERATIONS: u64 = 100_000;
+    const SCRIPT_MAX_CALL_LEVELS: usize = 32;
+    const SCRIPT_MAX_COLLECTION_SIZE: usize = 10_000;
+
+    /// Number of the last route segments passed to the scripts
+    const SCRIPT_ROUTE_TAIL_LEN: usize = 10;
+
+    fn get_engine() -> &'static Engine {
+        static ENGINE: OnceLock<Engine> = OnceLock::new();
+        ENGINE.get_or_init(|| {
+            let mut engine = Engine::new();
+            engine
+                .set_module_resolver(DummyModuleResolver::new())
+                .set_max_operations(SCRIPT_MAX_OPERATIONS)
+                .set_max_call_levels(SCRIPT_MAX_CALL_LEVELS)
+                .set_max_string_size(SCRIPT_MAX_COLLECTION_SIZE)
+                .set_max_array_size(SCRIPT_MAX_COLLECTION_SIZE)
+                .set_max_map_size(SCRIPT_MAX_COLLECTION_SIZE)
+                .on_print(|text| trace!(text, "weight script print"))
+                .on_debug(|text, _, _| trace!(text, "weight script debug"));
+            engine
+        })
+    }
+
+    fn get_scripts() -> &'static RwLock<HashMap<PathBuf, Arc<AST>>> {
+        static SCRIPTS: OnceLock<RwLock<HashMap<PathBuf, Arc<AST>>>> = OnceLock::new();
+        SCRIPTS.get_or_init(|| RwLock::new(HashMap::new()))
+    }
+
+    /// Compiles the scripts referenced in the rules and checks that the functions exist,
+    /// scripts are compiled again on every call so changes are picked up by a running server
+    pub fn load_scripts(rules: &RouterRules) -> Result<(), ScriptingError> {
+        for scripted_weight in rules.scripted_weights.iter() {
+            let file = scripted_weight.file.clone();
+            let source =
+                std::fs::read_to_string(&file).map_err(|error| ScriptingError::FileRead {
+                    file: file.clone(),
+                    error,
+                })?;
+            let ast = get_engine()
+                .compile(source)
+                .map_err(|error| ScriptingError::Compile {
+                    file: file.clone(),
+                    error,
+                })?;
+            if !ast
+                .iter_functions()
+                .any(|function| function.name == scripted_weight.function)
+            {
+                return Err(ScriptingError::FunctionNotFound {
+                    file,
+                    function: scripted_weight.function.clone(),
+                });
+            }
+            get_scripts()
+                .write()
+                .unwrap_or_else(|error| error.into_inner())
+                .insert(file, Arc::new(ast));
+        }
+        Ok(())
+    }
+
+    fn segment_to_map(segment: &Segment) -> Map {
+        let line = segment.get_line().borrow();
+        let tags = line.tags.borrow();
+        let end_point = segment.get_end_point().borrow();
+        let tag = |value: Option<&smartstring::alias::String>| {
+            value.map_or(Dynamic::UNIT, |value| value.to_string().into())
+        };
+
+        let mut map = Map::new();
+        map.insert("way_id".into(), (line.way_id as rhai::INT).into());
+        map.insert("highway".into(), tag(tags.highway()));
+        map.insert("surface".into(), tag(tags.surface()));
+        map.insert("smoothness".into(), tag(tags.smoothness()));
+        map.insert("name".into(), tag(tags.name()));
+        map.insert("ref".into(), tag(tags.hw_ref()));
+        map.insert("len_m".into(), (line.get_len_m() as rhai::FLOAT).into());
+        map.insert(
+            "bearing".into(),
+            (segment.get_bearing() as rhai::FLOAT).into(),
+        );
+        map.insert("is_roundabout".into(), line.is_roundabout().into());
+        map.insert("is_one_way".into(), line.is_one_way().into());
+        map.insert("end_lat".into(), (end_point.lat as rhai::FLOAT).into());
+        map.insert("end_lon".into(), (end_point.lon as rhai::FLOAT).into());
+        map.insert("end_is_junction".into(), end_point.is_junction().into());
+        map.insert(
+            "end_residential".into(),
+            end_point.residential_in_proximity.into(),
+        );
+        map
+    }
+
+    fn itinerary_to_map(input: &WeightCalcInput) -> Map {
+        let next = input.itinerary.next.borrow();
+        let finish = input.itinerary.finish.borrow();
+        let fork_end = input.current_fork_segment.get_end_point().borrow();
+
+        let mut map = Map::new();
+        map.insert("next_lat".into(), (next.lat as rhai::FLOAT).into());
+        map.insert("next_lon".into(), (next.lon as rhai::FLOAT).into());
+        map.insert("finish_lat".into(), (finish.lat as rhai::FLOAT).into());
+        map.insert("finish_lon".into(), (finish.lon as rhai::FLOAT).into());
+        map.insert(
+            "distance_to_next_m".into(),
+            (fork_end.distance_between(&input.itinerary.next) as rhai::FLOAT).into(),
+        );
+        map.insert(
+            "distance_to_finish_m".into(),
+            (fork_end.distance_between(&input.itinerary.finish) as rhai::FLOAT).into(),
+        );
+        map.insert(
+            "waypoint_count".into(),
+            (input.itinerary.waypoints.len() as rhai::INT).into(),
+        );
+        map
+    }
+
+    /// Integers are used as the weight clamped to 0-255, negative numbers or `false` exclude the
+    /// fork choice, anything else does not affect the weight
+    fn weight_from_script_result(result: Dynamic) -> WeightCalcResult {
+        if let Ok(weight) = result.as_int() {
+            if weight < 0 {
+                return WeightCalcResult::ForkChoiceDoNotUse;
+            }
+            return WeightCalcResult::ForkChoiceUseWithWeight(weight.min(u8::MAX.into()) as u8);
+        }
+        if result.as_bool() == Ok(false) {
+            return WeightCalcResult::ForkChoiceDoNotUse;
+        }
+        WeightCalcResult::ForkChoiceUseWithWeight(0)
+    }
+
+    /// Calls the scripted weight functions from the rules with the fork segment, the last segments
+    /// of the route and the itinerary, and sums up the weights
+    pub fn weight_scripted(input: WeightCalcInput) -> WeightCalcResult {
+        trace!("weight_scripted");
+
+        let fork = segment_to_map(input.current_fork_segment);
+        let route_tail = input
+            .route
+            .iter()
+            .rev()
+            .take(SCRIPT_ROUTE_TAIL_LEN)
+            .rev()
+            .map(|segment| Dynamic::from_map(segment_to_map(segment)))
+            .collect::<Array>();
+        let itinerary = itinerary_to_map(&input);
+
+        let scripts = get_scripts()
+            .read()
+            .unwrap_or_else(|error| error.into_inner());
+        let mut weight = 0u8;
+        for scripted_weight in input.rules.scripted_weights.iter() {
+            let Some(ast) = scripts.get(&scripted_weight.file) else {
+                error!(file = ?scripted_weight.file, "Weight script not loaded");
+                continue;
+            };
+            let result = get_engine().call_fn::<Dynamic>(
+                &mut Scope::new(),
+                ast,
+                &scripted_weight.function,
+                (fork.clone(), route_tail.clone(), itinerary.clone()),
+            );
+            match result {
+                Err(error) => {
+                    error!(
+                        file = ?scripted_weight.file,
+                        function = scripted_weight.function,
+                        error = ?error,
+                        "Weight script failed"
+                    );
+                }
+                Ok(result) => match weight_from_script_result(result) {
+                    WeightCalcResult::ForkChoiceUseWithWeight(script_weight) => {
+                        weight = weight.saturating_add(script_weight)
+                    }
+                    result => return result,
+                },
+            }
+        }
+        WeightCalcResult::ForkChoiceUseWithWeight(weight)
+    }
+
+    #[cfg(test)]
+    mod test {
+        use rhai::Dynamic;
+
+        use crate::router::navigator::WeightCalcResult;
+
+        use super::{get_engine, weight_from_script_result};
+
+        #[test]
+        fn script_results_to_weights() {
+            let ast = get_engine()
+                .compile(
+                    r#"
+                    fn prefer_gravel(fork, route_tail, itinerary) {
+                        if fork.highway == "motorway" { return false; }
+                        if fork.surface == "gravel" { return 300; }
+                        if route_tail.len() > 5 { return -1; }
+                        ()
+                    }
+                    "#,
+                )
+                .unwrap();
+            let tests = [
+                (
+                    r#"#{ highway: "motorway" }"#,
+                    0,
+                    WeightCalcResult::ForkChoiceDoNotUse,
+                ),
+                (
+                    r#"#{ highway: "track", surface: "gravel" }"#,
+                    0,
+                    WeightCalcResult::ForkChoiceUseWithWeight(255),
+                ),
+                (
+                    r#"#{ highway: "track" }"#,
+                    6,
+                    WeightCalcResult::ForkChoiceDoNotUse,
+                ),
+                (
+                    r#"#{ highway: "track" }"#,
+                    0,
+                    WeightCalcResult::ForkChoiceUseWithWeight(0),
+                ),
+            ];
+            for (fork, route_len, expected) in tests {
+                let fork = get_engine().eval::<rhai::Map>(fork).unwrap();
+                let route_tail = vec![Dynamic::UNIT; route_len];
+                let result = get_engine()
+                    .call_fn::<Dynamic>(
+                        &mut rhai::Scope::new(),
+                        &ast,
+                        "prefer_gravel",
+                        (fork, route_tail, rhai::Map::new()),
+                    )
+                    .unwrap();
+                assert_eq!(weight_from_script_result(result), expected);
+            }
+        }
+    }
+}
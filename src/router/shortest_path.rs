@@ -0,0 +1,330 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use crate::map_data::{
+    graph::{MapDataGraph, MapDataLineRef, MapDataPointRef},
+    line::MapDataLine,
+};
+
+use super::{
+    route::segment::Segment,
+    rules::{RouterRules, RulesTagValueAction},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathCost {
+    pub len_m: f64,
+    pub travel_time_s: f64,
+}
+
+struct QueueItem {
+    len_m: f64,
+    travel_time_s: f64,
+    point: MapDataPointRef,
+}
+
+impl PartialEq for QueueItem {
+    fn eq(&self, other: &Self) -> bool {
+        self.len_m == other.len_m
+    }
+}
+
+impl Eq for QueueItem {}
+
+impl PartialOrd for QueueItem {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueueItem {
+    // reversed so that the binary heap pops the shortest path first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.len_m.total_cmp(&self.len_m)
+    }
+}
+
+fn line_is_avoided(line: &MapDataLine, rules: &RouterRules) -> bool {
+    fn is_avoid(
+        tag_rules: &Option<HashMap<String, RulesTagValueAction>>,
+        tag: Option<&smartstring::alias::String>,
+    ) -> bool {
+        match (tag_rules, tag) {
+            (Some(tag_rules), Some(tag)) => {
+                tag_rules.get(tag.as_str()) == Some(&RulesTagValueAction::Avoid)
+            }
+            _ => false,
+        }
+    }
+    let tags = line.tags.borrow();
+    rules.avoid_way_ids.contains(&line.way_id)
+        || rules.is_excluded_by_tag_matchers(tags)
+        || is_avoid(&rules.highway, tags.highway())
+        || is_avoid(&rules.surface, tags.surface())
+        || is_avoid(&rules.smoothness, tags.smoothness())
+}
+
+struct SearchResult {
+    found: HashMap<MapDataPointRef, PathCost>,
+    previous: HashMap<MapDataPointRef, (MapDataLineRef, MapDataPointRef)>,
+}
+
+fn search(
+    from: &MapDataPointRef,
+    targets: &[MapDataPointRef],
+    rules: &RouterRules,
+) -> SearchResult {
+    let mut remaining = targets.len();
+    let mut found: HashMap<MapDataPointRef, PathCost> = HashMap::new();
+    let mut previous: HashMap<MapDataPointRef, (MapDataLineRef, MapDataPointRef)> = HashMap::new();
+    let mut best_len: HashMap<MapDataPointRef, f64> = HashMap::from([(from.clone(), 0.)]);
+    let mut queue = BinaryHeap::from([QueueItem {
+        len_m: 0.,
+        travel_time_s: 0.,
+        point: from.clone(),
+    }]);
+
+    while let Some(item) = queue.pop() {
+        if remaining == 0 {
+            break;
+        }
+        if best_len
+            .get(&item.point)
+            .is_some_and(|len| *len < item.len_m)
+        {
+            continue;
+        }
+        if targets.contains(&item.point) && !found.contains_key(&item.point) {
+            found.insert(
+                item.point.clone(),
+                PathCost {
+                    len_m: item.len_m,
+                    travel_time_s: item.travel_time_s,
+                },
+            );
+            remaining -= targets.iter().filter(|t| *t == &item.point).count();
+        }
+        for (line_ref, next_point) in MapDataGraph::get().get_adjacent(item.point.clone()) {
+            let line = line_ref.borrow();
+            if line.is_one_way() && line.points.1 == item.point {
+                continue;
+            }
+            if rules.avoid_node_ids.contains(&next_point.borrow().id)
+                || line_is_avoided(line, rules)
+            {
+                continue;
+            }
+            let len_m = item.len_m + f64::from(line.get_len_m());
+            if best_len.get(&next_point).is_some_and(|len| *len <= len_m) {
+                continue;
+            }
+            best_len.insert(next_point.clone(), len_m);
+            previous.insert(next_point.clone(), (line_ref, item.point.clone()));
+            queue.push(QueueItem {
+                len_m,
+                travel_time_s: item.travel_time_s + f64::from(line.get_travel_time_s()),
+                point: next_point,
+            });
+        }
+    }
+
+    SearchResult { found, previous }
+}
+
+/// Plain shortest distance search from one point to a set of targets. Respects one way roads and
+/// avoid rules, but not turn restrictions or any of the weight based preferences, so it is meant
+/// for quick estimates rather than route generation. Travel time is the estimate along the
+/// shortest path. Returns a cost for each target in the same order, `None` when not reachable.
+pub fn shortest_paths(
+    from: &MapDataPointRef,
+    targets: &[MapDataPointRef],
+    rules: &RouterRules,
+) -> Vec<Option<PathCost>> {
+    let result = search(from, targets, rules);
+    targets
+        .iter()
+        .map(|target| result.found.get(target).copied())
+        .collect()
+}
+
+/// Same search as `shortest_paths`, but returns the segments of the shortest path to a single
+/// target. Empty when `from` and `to` are the same point, `None` when not reachable.
+pub fn shortest_route(
+    from: &MapDataPointRef,
+    to: &MapDataPointRef,
+    rules: &RouterRules,
+) -> Option<Vec<Segment>> {
+    let result = search(from, std::slice::from_ref(to), rules);
+    if !result.found.contains_key(to) {
+        return None;
+    }
+    let mut segments = Vec::new();
+    let mut current = to.clone();
+    while &current != from {
+        let (line, prev_point) = result.previous.get(&current)?;
+        segments.push(Segment::new(line.clone(), current.clone()));
+        current = prev_point.clone();
+    }
+    segments.reverse();
+    Some(segments)
+}
+
+struct TimeQueueItem {
+    travel_time_s: f64,
+    point: MapDataPointRef,
+}
+
+impl PartialEq for TimeQueueItem {
+    fn eq(&self, other: &Self) -> bool {
+        self.travel_time_s == other.travel_time_s
+    }
+}
+
+impl Eq for TimeQueueItem {}
+
+impl PartialOrd for TimeQueueItem {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TimeQueueItem {
+    // reversed so that the binary heap pops the quickest path first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.travel_time_s.total_cmp(&self.travel_time_s)
+    }
+}
+
+/// Average speed of the roads that can be reached from the point within the travel time, from the
+/// per line travel time estimates. Each line counts by its length, so the long main roads a ride
+/// spends most of its time on weigh more than short side streets. `None` when no line can be
+/// reached, respects one way roads and avoid rules the same as `shortest_paths`.
+pub fn average_speed_kmh(
+    from: &MapDataPointRef,
+    max_travel_time_s: f64,
+    rules: &RouterRules,
+) -> Option<f32> {
+    let mut len_m: f64 = 0.;
+    let mut travel_time_s: f64 = 0.;
+    let mut counted_lines: HashSet<MapDataLineRef> = HashSet::new();
+    let mut best_time: HashMap<MapDataPointRef, f64> = HashMap::from([(from.clone(), 0.)]);
+    let mut queue = BinaryHeap::from([TimeQueueItem {
+        travel_time_s: 0.,
+        point: from.clone(),
+    }]);
+
+    while let Some(item) = queue.pop() {
+        if item.travel_time_s >= max_travel_time_s {
+            break;
+        }
+        if best_time
+            .get(&item.point)
+            .is_some_and(|time| *time < item.travel_time_s)
+        {
+            continue;
+        }
+        for (line_ref, next_point) in MapDataGraph::get().get_adjacent(item.point.clone()) {
+            let line = line_ref.borrow();
+            if line.is_one_way() && line.points.1 == item.point {
+                continue;
+            }
+            if rules.avoid_node_ids.contains(&next_point.borrow().id)
+                || line_is_avoided(line, rules)
+            {
+                continue;
+            }
+            let line_travel_time_s = f64::from(line.get_travel_time_s());
+            if counted_lines.insert(line_ref.clone()) {
+                len_m += f64::from(line.get_len_m());
+                travel_time_s += line_travel_time_s;
+            }
+            let next_travel_time_s = item.travel_time_s + line_travel_time_s;
+            if best_time
+                .get(&next_point)
+                .is_some_and(|time| *time <= next_travel_time_s)
+            {
+                continue;
+            }
+            best_time.insert(next_point.clone(), next_travel_time_s);
+            queue.push(TimeQueueItem {
+                travel_time_s: next_travel_time_s,
+                point: next_point,
+            });
+        }
+    }
+
+    if travel_time_s > 0. {
+        Some((len_m / travel_time_s * 3.6) as f32)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusty_fork::rusty_fork_test;
+
+    use crate::{
+        map_data::graph::MapDataGraph,
+        router::rules::RouterRules,
+        test_utils::{graph_from_test_dataset, set_graph_static, test_dataset_1},
+    };
+
+    use super::{average_speed_kmh, shortest_paths, shortest_route};
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn shortest_paths_to_targets() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let from = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let targets = [1, 3, 7, 11]
+                .iter()
+                .map(|id| MapDataGraph::get().test_get_point_ref_by_id(id).unwrap())
+                .collect::<Vec<_>>();
+
+            let costs = shortest_paths(&from, &targets, &RouterRules::default());
+
+            assert_eq!(costs[0].map(|c| c.len_m), Some(0.));
+            let to_3 = costs[1].expect("point 3 should be reachable");
+            let to_7 = costs[2].expect("point 7 should be reachable");
+            assert!(to_3.len_m > 0. && to_3.travel_time_s > 0.);
+            assert!(to_7.len_m > 0. && to_7.travel_time_s > 0.);
+            assert_eq!(costs[3], None);
+        }
+
+        #[test]
+        fn shortest_route_segments() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let from = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let to = MapDataGraph::get().test_get_point_ref_by_id(&7).unwrap();
+
+            let route = shortest_route(&from, &to, &RouterRules::default())
+                .expect("point 7 should be reachable");
+            let point_ids = route
+                .iter()
+                .map(|segment| segment.get_end_point().borrow().id)
+                .collect::<Vec<_>>();
+            assert_eq!(point_ids, vec![2, 3, 6, 7]);
+            assert!(shortest_route(&from, &from, &RouterRules::default())
+                .is_some_and(|route| route.is_empty()));
+        }
+
+        #[test]
+        fn average_speed_of_reachable_lines() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let from = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let primary_speed_kmh = MapDataGraph::get().get_adjacent(from.clone())[0]
+                .0
+                .borrow()
+                .get_speed_kmh();
+
+            let speed_kmh = average_speed_kmh(&from, 3600., &RouterRules::default())
+                .expect("lines should be reachable");
+            assert!((speed_kmh - primary_speed_kmh).abs() < 0.01);
+            assert_eq!(average_speed_kmh(&from, 0., &RouterRules::default()), None);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use web_time::Instant;
+
+use super::navigator::NavigationResult;
+
+/// Counts of a route generation, for monitoring its health in batch jobs
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct GenerationSummary {
+    pub itineraries_attempted: u64,
+    pub itineraries_finished: u64,
+    pub itineraries_stuck: u64,
+    /// Stopped by the step limit, the deadline or a cancellation
+    pub itineraries_stopped: u64,
+    /// Stuck or stopped, with a partial route kept
+    pub itineraries_partial: u64,
+    pub steps: u64,
+    /// Moves back from a dead end, or from a fork where every choice leads into one
+    pub dead_ends: u64,
+    /// Forks where the fork choice weights were calculated
+    pub forks_evaluated: u64,
+    pub duration_ms: u64,
+}
+
+impl GenerationSummary {
+    pub fn log(&self) {
+        info!(
+            itineraries_attempted = self.itineraries_attempted,
+            itineraries_finished = self.itineraries_finished,
+            itineraries_stuck = self.itineraries_stuck,
+            itineraries_stopped = self.itineraries_stopped,
+            itineraries_partial = self.itineraries_partial,
+            steps = self.steps,
+            dead_ends = self.dead_ends,
+            forks_evaluated = self.forks_evaluated,
+            duration_ms = self.duration_ms,
+            "Route generation summary"
+        );
+    }
+}
+
+/// Collects the counts of the navigators running in parallel. Counters shared by several
+/// generators add up their counts
+pub struct GenerationCounters {
+    start: Instant,
+    itineraries_attempted: AtomicU64,
+    itineraries_finished: AtomicU64,
+    itineraries_stuck: AtomicU64,
+    itineraries_stopped: AtomicU64,
+    itineraries_partial: AtomicU64,
+    steps: AtomicU64,
+    dead_ends: AtomicU64,
+    forks_evaluated: AtomicU64,
+}
+
+impl Default for GenerationCounters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GenerationCounters {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            itineraries_attempted: AtomicU64::new(0),
+            itineraries_finished: AtomicU64::new(0),
+            itineraries_stuck: AtomicU64::new(0),
+            itineraries_stopped: AtomicU64::new(0),
+            itineraries_partial: AtomicU64::new(0),
+            steps: AtomicU64::new(0),
+            dead_ends: AtomicU64::new(0),
+            forks_evaluated: AtomicU64::new(0),
+        }
+    }
+
+    pub fn itinerary_done(&self, nav_result: &NavigationResult) {
+        self.itineraries_attempted.fetch_add(1, Ordering::Relaxed);
+        let counter = match nav_result {
+            NavigationResult::Finished(_) => &self.itineraries_finished,
+            NavigationResult::Stuck => &self.itineraries_stuck,
+            NavigationResult::Stopped => &self.itineraries_stopped,
+            NavigationResult::Partial(_) => &self.itineraries_partial,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn step(&self) {
+        self.steps.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dead_end(&self) {
+        self.dead_ends.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn fork_evaluated(&self) {
+        self.forks_evaluated.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn summary(&self) -> GenerationSummary {
+        GenerationSummary {
+            itineraries_attempted: self.itineraries_attempted.load(Ordering::Relaxed),
+            itineraries_finished: self.itineraries_finished.load(Ordering::Relaxed),
+            itineraries_stuck: self.itineraries_stuck.load(Ordering::Relaxed),
+            itineraries_stopped: self.itineraries_stopped.load(Ordering::Relaxed),
+            itineraries_partial: self.itineraries_partial.load(Ordering::Relaxed),
+            steps: self.steps.load(Ordering::Relaxed),
+            dead_ends: self.dead_ends.load(Ordering::Relaxed),
+            forks_evaluated: self.forks_evaluated.load(Ordering::Relaxed),
+            duration_ms: self.start.elapsed().as_millis() as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::router::{navigator::NavigationResult, route::Route};
+
+    use super::GenerationCounters;
+
+    #[test]
+    fn generation_counters_summary() {
+        let counters = GenerationCounters::new();
+        counters.itinerary_done(&NavigationResult::Finished(Route::new()));
+        counters.itinerary_done(&NavigationResult::Stuck);
+        counters.itinerary_done(&NavigationResult::Stopped);
+        counters.step();
+        counters.step();
+        counters.dead_end();
+        counters.fork_evaluated();
+
+        let summary = counters.summary();
+        assert_eq!(summary.itineraries_attempted, 3);
+        assert_eq!(summary.itineraries_finished, 1);
+        assert_eq!(summary.itineraries_stuck, 1);
+        assert_eq!(summary.itineraries_stopped, 1);
+        assert_eq!(summary.itineraries_partial, 0);
+        assert_eq!(summary.steps, 2);
+        assert_eq!(summary.dead_ends, 1);
+        assert_eq!(summary.forks_evaluated, 1);
+    }
+}
@@ -0,0 +1,144 @@
+use tracing::trace;
+
+use crate::map_data::graph::MapDataPointRef;
+
+use super::itinerary::ItineraryVia;
+
+/// Up to this many vias all orderings are checked, above it a heuristic is used
+const EXACT_MAX_VIAS: usize = 8;
+
+/// Reorders vias to minimize the straight line distance from start through all vias to finish.
+pub fn optimize_via_order(
+    start: &MapDataPointRef,
+    finish: &MapDataPointRef,
+    vias: Vec<ItineraryVia>,
+) -> Vec<ItineraryVia> {
+    if vias.len() < 2 {
+        return vias;
+    }
+
+    // index 0 is start, 1..=n are vias, n + 1 is finish
+    let points = std::iter::once(start)
+        .chain(vias.iter().map(|via| &via.point))
+        .chain(std::iter::once(finish))
+        .collect::<Vec<_>>();
+    let dist = points
+        .iter()
+        .map(|from| {
+            points
+                .iter()
+                .map(|to| from.borrow().distance_between(to))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let order = if vias.len() <= EXACT_MAX_VIAS {
+        order_exact(&dist)
+    } else {
+        order_heuristic(&dist)
+    };
+    trace!(?order, "Optimized via order");
+
+    let mut vias = vias.into_iter().map(Some).collect::<Vec<_>>();
+    order
+        .into_iter()
+        .filter_map(|idx| vias[idx - 1].take())
+        .collect()
+}
+
+fn path_len(dist: &[Vec<f32>], order: &[usize]) -> f32 {
+    let finish = dist.len() - 1;
+    std::iter::once(&0)
+        .chain(order.iter())
+        .zip(order.iter().chain(std::iter::once(&finish)))
+        .map(|(from, to)| dist[*from][*to])
+        .sum()
+}
+
+fn order_exact(dist: &[Vec<f32>]) -> Vec<usize> {
+    fn permute(
+        dist: &[Vec<f32>],
+        current: &mut Vec<usize>,
+        remaining: &mut Vec<usize>,
+        best: &mut (f32, Vec<usize>),
+    ) {
+        if remaining.is_empty() {
+            let len = path_len(dist, current);
+            if len < best.0 {
+                *best = (len, current.clone());
+            }
+            return;
+        }
+        for idx in 0..remaining.len() {
+            let via = remaining.remove(idx);
+            current.push(via);
+            permute(dist, current, remaining, best);
+            current.pop();
+            remaining.insert(idx, via);
+        }
+    }
+
+    let mut remaining = (1..dist.len() - 1).collect::<Vec<_>>();
+    let mut best = (f32::MAX, remaining.clone());
+    permute(dist, &mut Vec::new(), &mut remaining, &mut best);
+    best.1
+}
+
+fn order_heuristic(dist: &[Vec<f32>]) -> Vec<usize> {
+    // nearest neighbour from the start
+    let mut remaining = (1..dist.len() - 1).collect::<Vec<_>>();
+    let mut order = Vec::with_capacity(remaining.len());
+    let mut current = 0;
+    while !remaining.is_empty() {
+        let (pos, _) = remaining
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| dist[current][**a].total_cmp(&dist[current][**b]))
+            .expect("remaining is not empty");
+        current = remaining.remove(pos);
+        order.push(current);
+    }
+
+    // 2-opt improvement with fixed start and finish
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for i in 0..order.len() - 1 {
+            for j in i + 1..order.len() {
+                let mut candidate = order.clone();
+                candidate[i..=j].reverse();
+                if path_len(dist, &candidate) + 0.01 < path_len(dist, &order) {
+                    order = candidate;
+                    improved = true;
+                }
+            }
+        }
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{order_exact, order_heuristic, path_len};
+
+    fn dist_on_line(positions: &[f32]) -> Vec<Vec<f32>> {
+        positions
+            .iter()
+            .map(|from| positions.iter().map(|to| (from - to).abs()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn via_order_exact() {
+        let dist = dist_on_line(&[0., 30., 10., 20., 40.]);
+        assert_eq!(order_exact(&dist), vec![2, 3, 1]);
+        assert_eq!(path_len(&dist, &[2, 3, 1]), 40.);
+    }
+
+    #[test]
+    fn via_order_heuristic() {
+        let dist = dist_on_line(&[0., 70., 10., 50., 20., 90., 30., 60., 40., 80., 100.]);
+        let order = order_heuristic(&dist);
+        assert_eq!(path_len(&dist, &order), 100.);
+    }
+}
@@ -1,11 +1,17 @@
 use std::{collections::HashSet, fmt::Debug};
 
-use crate::map_data::{
-    graph::{MapDataGraph, MapDataPointRef},
-    rule::MapDataRuleType,
+use crate::{
+    map_data::{
+        graph::{MapDataGraph, MapDataPointRef},
+        rule::MapDataRuleType,
+    },
+    profiler,
 };
 
-use super::route::{segment::Segment, segment_list::SegmentList, Route};
+use super::{
+    cancellation::CancellationToken,
+    route::{segment::Segment, segment_list::SegmentList, Route},
+};
 
 #[derive(Debug, PartialEq, thiserror::Error)]
 pub enum WalkerError {
@@ -14,12 +20,16 @@ pub enum WalkerError {
         id: u64,
         available_fork_ids: Vec<u64>,
     },
+
+    #[error("Walk was cancelled")]
+    Cancelled,
 }
 
 pub struct Walker {
     start: MapDataPointRef,
     route_walked: Route,
     next_fork_choice_point: Option<MapDataPointRef>,
+    cancellation: Option<CancellationToken>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -35,9 +45,17 @@ impl Walker {
             start: start.clone(),
             route_walked: Route::new(),
             next_fork_choice_point: None,
+            cancellation: None,
         }
     }
 
+    /// Stops walks between forks once cancelled, as long stretches without forks can take a
+    /// while to walk
+    pub fn with_cancellation(mut self, cancellation: Option<CancellationToken>) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
     pub fn get_last_point(&self) -> &MapDataPointRef {
         let last_element = self.get_route().get_segment_last();
         let last_point = match last_element {
@@ -48,10 +66,8 @@ impl Walker {
     }
 
     fn get_segments_for_point(&self, center_point: &MapDataPointRef) -> SegmentList {
-        let center_point_borrowed = center_point.borrow();
-
-        let not_allow_rules = center_point_borrowed
-            .rules
+        let not_allow_rules = center_point
+            .rules()
             .iter()
             .filter(|rule| rule.rule_type == MapDataRuleType::NotAllowed)
             .collect::<Vec<_>>();
@@ -84,23 +100,30 @@ impl Walker {
         segment_list
     }
 
-    fn get_fork_segments_for_segment(&self, segment: &Segment) -> SegmentList {
+    /// Point the walker came to the segment at `segment_idx` of the route walked from
+    fn get_point_before(&self, segment_idx: usize) -> &MapDataPointRef {
+        segment_idx
+            .checked_sub(1)
+            .and_then(|idx| self.route_walked.get_segment_by_index(idx))
+            .map_or(&self.start, |segment| segment.get_end_point())
+    }
+
+    /// Point the walker came to the last segment of the route walked from
+    fn get_point_before_last(&self) -> &MapDataPointRef {
+        self.get_point_before(self.route_walked.get_segment_count().saturating_sub(1))
+    }
+
+    fn get_fork_segments_for_segment(
+        &self,
+        segment: &Segment,
+        prev_point: &MapDataPointRef,
+    ) -> SegmentList {
         let center_point = segment.get_end_point();
         let center_line = segment.get_line();
+        let prev_point = &prev_point.borrow();
 
-        let prev_point = if let Some(idx) = self.route_walked.get_segment_count().checked_sub(2) {
-            if let Some(p) = self.route_walked.get_segment_by_index(idx) {
-                &p.get_end_point().borrow()
-            } else {
-                &self.start.borrow()
-            }
-        } else {
-            &self.start.borrow()
-        };
-
-        let center_point_borrowed = center_point.borrow();
-        let only_allow_rules = center_point_borrowed
-            .rules
+        let only_allow_rules = center_point
+            .rules()
             .iter()
             .filter(|rule| {
                 rule.rule_type == MapDataRuleType::OnlyAllowed
@@ -108,8 +131,8 @@ impl Walker {
             })
             .collect::<Vec<_>>();
 
-        let not_allow_rules = center_point_borrowed
-            .rules
+        let not_allow_rules = center_point
+            .rules()
             .iter()
             .filter(|rule| {
                 rule.rule_type == MapDataRuleType::NotAllowed
@@ -162,7 +185,7 @@ impl Walker {
         self.next_fork_choice_point = Some(point);
     }
 
-    fn get_roundabout_exits(&self, segment: &Segment) -> SegmentList {
+    fn get_roundabout_exits(&self, segment: &Segment, prev_point: &MapDataPointRef) -> SegmentList {
         let mut visited_points: HashSet<MapDataPointRef> = HashSet::new();
         if !segment.get_line().borrow().is_roundabout() {
             return SegmentList::new();
@@ -173,7 +196,7 @@ impl Walker {
         let mut current_segment = segment.clone();
 
         loop {
-            let fork_segments = self.get_fork_segments_for_segment(&current_segment);
+            let fork_segments = self.get_fork_segments_for_segment(&current_segment, prev_point);
             let fork_segments: Vec<_> = fork_segments.into();
 
             segments.push(
@@ -235,7 +258,8 @@ impl Walker {
             }
             visited_points.insert(last_point);
 
-            let fork_segments = self.get_fork_segments_for_segment(&current_segment);
+            let fork_segments =
+                self.get_fork_segments_for_segment(&current_segment, self.get_point_before_last());
             let fork_segments: Vec<_> = fork_segments.into();
 
             if fork_segments
@@ -266,8 +290,16 @@ impl Walker {
         &mut self,
         is_finished: T,
     ) -> Result<WalkerMoveResult, WalkerError> {
+        profiler::zone!("walker_move_forward");
         let mut visited_junction: HashSet<MapDataPointRef> = HashSet::new();
         loop {
+            if self
+                .cancellation
+                .as_ref()
+                .is_some_and(|cancellation| cancellation.is_cancelled())
+            {
+                return Err(WalkerError::Cancelled);
+            }
             let point = match self.route_walked.get_segment_last() {
                 Some(route_segment) => route_segment.get_end_point(),
                 None => &self.start,
@@ -276,13 +308,34 @@ impl Walker {
                 return Ok(WalkerMoveResult::Finish);
             }
 
+            // points up to the end of a junction edge have nothing to decide, the walker jumps to
+            // the end of the edge without looking for forks
+            let junction_edge = self
+                .route_walked
+                .get_segment_last()
+                .filter(|_| self.next_fork_choice_point.is_none())
+                .and_then(|segment| {
+                    MapDataGraph::get()
+                        .get_junction_edge(segment.get_line(), segment.get_end_point())
+                });
+            if let Some(junction_edge) = junction_edge {
+                for (line, point) in &junction_edge.steps {
+                    self.route_walked
+                        .add_segment(Segment::new(line.clone(), point.clone()));
+                    if is_finished(point.clone()) {
+                        return Ok(WalkerMoveResult::Finish);
+                    }
+                }
+                continue;
+            }
+
             let available_segments = match self.route_walked.get_segment_last() {
                 None => self.get_segments_for_point(&self.start),
                 Some(segment) => {
                     if segment.get_line().borrow().is_roundabout() {
-                        self.get_roundabout_exits(segment)
+                        self.get_roundabout_exits(segment, self.get_point_before_last())
                     } else {
-                        self.get_fork_segments_for_segment(segment)
+                        self.get_fork_segments_for_segment(segment, self.get_point_before_last())
                     }
                 }
             };
@@ -329,30 +382,43 @@ impl Walker {
         }
     }
 
-    pub fn move_backwards_to_prev_fork(&mut self) -> Option<SegmentList> {
+    /// Number of segments the route walked is left with after moving back to the previous fork
+    pub fn get_prev_fork_segment_count(&self) -> usize {
+        let segment_count = self.route_walked.get_segment_count().saturating_sub(1);
+        (0..segment_count)
+            .rev()
+            .find(|idx| {
+                self.route_walked
+                    .get_segment_by_index(*idx)
+                    .is_some_and(|segment| {
+                        // the forks are checked as if the walker had moved back to the segment,
+                        // so the line it came in on is not one of the choices
+                        let prev_point = self.get_point_before(*idx);
+                        (segment.get_end_point().borrow().is_junction()
+                            && self
+                                .get_fork_segments_for_segment(segment, prev_point)
+                                .get_segment_count()
+                                > 1)
+                            || (segment.get_line().borrow().is_roundabout()
+                                && self
+                                    .get_roundabout_exits(segment, prev_point)
+                                    .get_segment_count()
+                                    > 1)
+                    })
+            })
+            .map_or(0, |idx| idx + 1)
+    }
+
+    /// Moves back to the fork found with `get_prev_fork_segment_count`
+    pub fn move_backwards_to(&mut self, segment_count: usize) -> Option<SegmentList> {
+        profiler::zone!("walker_move_backwards");
         self.next_fork_choice_point = None;
-        self.route_walked.remove_last_segment();
-        loop {
-            let last_segment = self.route_walked.get_segment_last();
-            if let Some(last_segment) = last_segment {
-                if (last_segment.get_end_point().borrow().is_junction()
-                    && self
-                        .get_fork_segments_for_segment(last_segment)
-                        .get_segment_count()
-                        > 1)
-                    || (last_segment.get_line().borrow().is_roundabout()
-                        && self.get_roundabout_exits(last_segment).get_segment_count() > 1)
-                {
-                    break;
-                }
-            } else {
-                break;
-            }
-            self.route_walked.remove_last_segment();
-        }
+        self.route_walked.truncate(segment_count);
 
         if let Some(last_segment) = self.route_walked.get_segment_last() {
-            return Some(self.get_fork_segments_for_segment(last_segment));
+            return Some(
+                self.get_fork_segments_for_segment(last_segment, self.get_point_before_last()),
+            );
         }
 
         None
@@ -361,6 +427,10 @@ impl Walker {
     pub fn get_route(&self) -> &Route {
         &self.route_walked
     }
+
+    pub fn into_route(self) -> Route {
+        self.route_walked
+    }
 }
 
 #[cfg(test)]
@@ -377,6 +447,7 @@ mod tests {
             osm::{OsmRelation, OsmRelationMember, OsmRelationMemberRole, OsmRelationMemberType},
         },
         router::{
+            cancellation::CancellationToken,
             route::Route,
             walker::{WalkerError, WalkerMoveResult},
         },
@@ -408,6 +479,25 @@ mod tests {
         }
     }
 
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn walker_cancelled() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let start = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let finish = MapDataGraph::get().test_get_point_ref_by_id(&7).unwrap();
+            let cancellation = CancellationToken::new();
+            cancellation.cancel();
+
+            let mut walker = Walker::new(start).with_cancellation(Some(cancellation));
+
+            assert_eq!(
+                walker.move_forward_to_next_fork(|p| p == finish),
+                Err(WalkerError::Cancelled)
+            );
+        }
+    }
+
     rusty_fork_test! {
         #![rusty_fork(timeout_ms = 2000)]
         #[test]
@@ -597,7 +687,7 @@ mod tests {
 
             assert!(walker.move_forward_to_next_fork(|p| p == point2) == Ok(WalkerMoveResult::DeadEnd));
 
-            let choices = match walker.move_backwards_to_prev_fork() {
+            let choices = match walker.move_backwards_to(walker.get_prev_fork_segment_count()) {
                 None => panic!("Expected to be back at point 3 with choices"),
                 Some(c) => c,
             };
@@ -646,6 +736,34 @@ mod tests {
         }
     }
 
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn walk_back_past_junction_without_fork() {
+            set_graph_static(graph_from_test_dataset(test_dataset_2()));
+
+            let start = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let finish = MapDataGraph::get().test_get_point_ref_by_id(&10).unwrap();
+
+            let mut walker = Walker::new(start.clone());
+
+            // 2 is a junction, but 7 - 2 is one way towards it, so the only way on is 3 and the
+            // first fork is at 3
+            let choices = match walker.move_forward_to_next_fork(|p| p == finish) {
+                Ok(WalkerMoveResult::Fork(c)) => c,
+                _ => panic!("did not get choices for routes"),
+            };
+            assert_eq!(choices.get_segment_count(), 2);
+            assert_eq!(walker.get_route().get_segment_count(), 2);
+
+            // the line 1 - 2 the walker came in on is not a choice at 2, so there is no fork to
+            // move back to
+            assert_eq!(walker.get_prev_fork_segment_count(), 0);
+            assert!(walker.move_backwards_to(0).is_none());
+            assert_eq!(walker.get_route().get_segment_count(), 0);
+        }
+    }
+
     rusty_fork_test! {
         #![rusty_fork(timeout_ms = 2000)]
         #[test]
@@ -3,12 +3,13 @@ use std::collections::HashMap;
 use geo::{Bearing, Haversine, Point};
 use tracing::{error, trace};
 
-use crate::router::rules::{RouterRules, RulesTagValueAction};
+use crate::map_data::graph::{JunctionEdge, MapDataGraph};
+use crate::router::rules::{RouterRules, RulesTagMatcherAction, RulesTagValueAction};
 
 use super::{
     itinerary::Itinerary,
     navigator::WeightCalcResult,
-    route::{segment::Segment, Route},
+    route::{score::Score, segment::Segment, Route},
     walker::{Walker, WalkerMoveResult},
 };
 
@@ -92,32 +93,11 @@ pub fn weight_prefer_same_road(input: WeightCalcInput) -> WeightCalcResult {
     if !input.rules.basic.prefer_same_road.enabled {
         return WeightCalcResult::ForkChoiceUseWithWeight(0);
     }
-    let current_ref = input
-        .route
-        .get_segment_last()
-        .and_then(|s| s.get_line().borrow().tags.borrow().hw_ref());
-    let current_name = input
-        .route
-        .get_segment_last()
-        .and_then(|s| s.get_line().borrow().tags.borrow().name());
-    let fork_ref = input
-        .current_fork_segment
-        .get_line()
-        .borrow()
-        .tags
-        .borrow()
-        .hw_ref();
-    let fork_name = input
-        .current_fork_segment
-        .get_line()
-        .borrow()
-        .tags
-        .borrow()
-        .name();
-
-    if (current_ref.is_some() && fork_ref.is_some() && current_ref == fork_ref)
-        || (current_name.is_some() && fork_name.is_some() && current_name == fork_name)
-    {
+    if input.route.get_segment_last().is_some_and(|segment| {
+        segment
+            .get_road_ids()
+            .is_same_road(input.current_fork_segment.get_road_ids())
+    }) {
         return WeightCalcResult::ForkChoiceUseWithWeight(
             input.rules.basic.prefer_same_road.priority,
         );
@@ -126,6 +106,38 @@ pub fn weight_prefer_same_road(input: WeightCalcInput) -> WeightCalcResult {
     WeightCalcResult::ForkChoiceUseWithWeight(0)
 }
 
+/// Penalizes round trips coming back on a road they already took, by giving the priority to roads
+/// that don't share a ref or name with the route before the last `skip_recent_m`
+pub fn weight_no_same_road_return(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_no_same_road_return");
+
+    let rule = &input.rules.basic.no_same_road_return;
+    if !rule.enabled || input.itinerary.start != input.itinerary.finish {
+        return WeightCalcResult::ForkChoiceUseWithWeight(0);
+    }
+
+    let fork_road_ids = input.current_fork_segment.get_road_ids();
+    if fork_road_ids.is_none() {
+        return WeightCalcResult::ForkChoiceUseWithWeight(rule.priority);
+    }
+
+    let mut recent_len_m = 0.;
+    let is_return = input
+        .route
+        .iter()
+        .rev()
+        .skip_while(|segment| {
+            recent_len_m += segment.get_line().borrow().get_len_m();
+            recent_len_m <= rule.skip_recent_m
+        })
+        .any(|segment| fork_road_ids.is_same_road(segment.get_road_ids()));
+
+    if is_return {
+        return WeightCalcResult::ForkChoiceUseWithWeight(0);
+    }
+    WeightCalcResult::ForkChoiceUseWithWeight(rule.priority)
+}
+
 pub fn weight_no_loops(input: WeightCalcInput) -> WeightCalcResult {
     trace!("weight_no_loops");
     if input
@@ -159,6 +171,124 @@ pub fn weight_no_sharp_turns(input: WeightCalcInput) -> WeightCalcResult {
     WeightCalcResult::ForkChoiceUseWithWeight(0)
 }
 
+/// Walker along the fork segment up to the next fork. `walker_from_fork` starts at the end of the
+/// fork segment, where a road going on in both directions is a fork already
+fn walker_along_fork(input: &WeightCalcInput) -> Walker {
+    let line = input.current_fork_segment.get_line().borrow();
+    let end_point = input.current_fork_segment.get_end_point();
+    let start_point = if &line.points.0 == end_point {
+        &line.points.1
+    } else {
+        &line.points.0
+    };
+    let mut walker = Walker::new(start_point.clone());
+    walker.set_fork_choice_point_ref(end_point.clone());
+    walker
+}
+
+pub fn weight_no_steep_grades(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_no_steep_grades");
+
+    if !input.rules.basic.no_steep_grades.enabled {
+        return WeightCalcResult::ForkChoiceUseWithWeight(0);
+    }
+
+    // elevation is only known on some points, so the grade is checked between the first and the
+    // last point with elevation up to the next fork
+    let mut walker = walker_along_fork(&input);
+    if let Err(e) = walker.move_forward_to_next_fork(|p| input.itinerary.is_finished(p)) {
+        error!("weight calc error {:#?}", e);
+        return WeightCalcResult::ForkChoiceDoNotUse;
+    }
+    let mut len_m = 0.;
+    let mut first: Option<(f32, f32)> = None;
+    let mut last: Option<(f32, f32)> = None;
+    for segment in walker.get_route().iter() {
+        len_m += segment.get_line().borrow().get_len_m();
+        if let Some(elevation) = segment.get_end_point().borrow().elevation {
+            if first.is_none() {
+                first = Some((len_m, elevation));
+            }
+            last = Some((len_m, elevation));
+        }
+    }
+    if let (Some((first_len, first_ele)), Some((last_len, last_ele))) = (first, last) {
+        let distance = last_len - first_len;
+        if distance > 0.
+            && ((last_ele - first_ele) / distance * 100.).abs()
+                > input.rules.basic.no_steep_grades.max_grade_percent
+        {
+            return WeightCalcResult::ForkChoiceUseWithWeight(0);
+        }
+    }
+
+    WeightCalcResult::ForkChoiceUseWithWeight(input.rules.basic.no_steep_grades.priority)
+}
+
+/// Prefers roads outside of built-up areas, the priority is scaled by the share of the road up to
+/// the next fork that is not near residential areas
+pub fn weight_avoid_urban(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_avoid_urban");
+
+    if !input.rules.basic.avoid_urban.enabled {
+        return WeightCalcResult::ForkChoiceUseWithWeight(0);
+    }
+
+    let mut walker = walker_along_fork(&input);
+    if let Err(e) = walker.move_forward_to_next_fork(|p| input.itinerary.is_finished(p)) {
+        error!("weight calc error {:#?}", e);
+        return WeightCalcResult::ForkChoiceDoNotUse;
+    }
+    let mut len_m = 0.;
+    let mut rural_len_m = 0.;
+    for segment in walker.get_route().iter() {
+        let line_len = segment.get_line().borrow().get_len_m();
+        len_m += line_len;
+        if !segment.get_end_point().borrow().residential_in_proximity {
+            rural_len_m += line_len;
+        }
+    }
+    if len_m == 0. {
+        return WeightCalcResult::ForkChoiceUseWithWeight(0);
+    }
+
+    WeightCalcResult::ForkChoiceUseWithWeight(
+        (rural_len_m / len_m * f32::from(input.rules.basic.avoid_urban.priority)).round() as u8,
+    )
+}
+
+/// Heading change per km at which a road gets the full curvy road priority
+const CURVY_ROAD_DEG_PER_KM: f64 = 300.;
+/// Heading change per km under which a road is considered straight
+const STRAIGHT_ROAD_DEG_PER_KM: f64 = 30.;
+
+/// Prefers twisty roads, roads that are not straight get `curvy_road_bonus` and up to `priority`
+/// more the curvier they are up to the next fork
+pub fn weight_prefer_curvy(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_prefer_curvy");
+
+    let rule = &input.rules.basic.prefer_curvy;
+    if !rule.enabled {
+        return WeightCalcResult::ForkChoiceUseWithWeight(0);
+    }
+
+    let mut walker = walker_along_fork(&input);
+    if let Err(e) = walker.move_forward_to_next_fork(|p| input.itinerary.is_finished(p)) {
+        error!("weight calc error {:#?}", e);
+        return WeightCalcResult::ForkChoiceDoNotUse;
+    }
+    let curviness = Score::calc_curviness(walker.get_route(), rule.min_curve_radius_m);
+    if curviness < STRAIGHT_ROAD_DEG_PER_KM {
+        return WeightCalcResult::ForkChoiceUseWithWeight(0);
+    }
+
+    let curvy_priority = (curviness / CURVY_ROAD_DEG_PER_KM).min(1.) * f64::from(rule.priority);
+    WeightCalcResult::ForkChoiceUseWithWeight(
+        rule.curvy_road_bonus
+            .saturating_add(curvy_priority.round() as u8),
+    )
+}
+
 pub fn weight_no_short_detours(input: WeightCalcInput) -> WeightCalcResult {
     trace!("weight_no_short_detours");
     if !input.rules.basic.no_short_detours.enabled
@@ -171,25 +301,8 @@ pub fn weight_no_short_detours(input: WeightCalcInput) -> WeightCalcResult {
         return WeightCalcResult::ForkChoiceUseWithWeight(0);
     }
 
-    let hw_ref = input
-        .current_fork_segment
-        .get_line()
-        .borrow()
-        .tags
-        .borrow()
-        .hw_ref()
-        .cloned();
-    let hw_name = input
-        .current_fork_segment
-        .get_line()
-        .borrow()
-        .tags
-        .borrow()
-        .name()
-        .cloned();
     if input.route.is_back_on_road_within_distance(
-        hw_ref,
-        hw_name,
+        input.current_fork_segment.get_road_ids(),
         input.rules.basic.no_short_detours.min_detour_len_m,
     ) {
         return WeightCalcResult::LastSegmentDoNotUse;
@@ -221,8 +334,7 @@ pub fn weight_check_distance_to_next(input: WeightCalcInput) -> WeightCalcResult
         .map_or(&input.itinerary.start, |v| &v.on_point);
     let distance_to_next_junctions_back = match input
         .route
-        .split_at_point(check_from)
-        .get_junctions_from_end(check_junctions_back)
+        .get_junctions_from_end_since(check_from, check_junctions_back)
     {
         None => return WeightCalcResult::ForkChoiceUseWithWeight(0),
         Some(segment) => segment
@@ -284,20 +396,74 @@ pub fn weight_progress_speed(input: WeightCalcInput) -> WeightCalcResult {
     WeightCalcResult::ForkChoiceUseWithWeight(0)
 }
 
+/// Junction edge the route has to walk to its end after the fork segment, none when the fork
+/// segment does not start one or the route finishes on it
+fn junction_edge_after_fork(input: &WeightCalcInput) -> Option<&'static JunctionEdge> {
+    MapDataGraph::get()
+        .get_junction_edge(
+            input.current_fork_segment.get_line(),
+            input.current_fork_segment.get_end_point(),
+        )
+        .filter(|edge| {
+            !edge
+                .steps
+                .iter()
+                .any(|(_, point)| input.itinerary.is_finished(point.clone()))
+        })
+}
+
+/// Excludes fork choices after which the route can't reach the finish within the maximum route
+/// length, even in a straight line through the next waypoint or in a straight line from the end of
+/// the junction edge the fork leads into
+pub fn weight_max_length(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_max_length");
+
+    let Some(max_length_m) = input.rules.generation.route_length.max_length_m else {
+        return WeightCalcResult::ForkChoiceUseWithWeight(0);
+    };
+
+    let fork_end_point = input.current_fork_segment.get_end_point().borrow();
+    let mut remaining_min_m = fork_end_point.distance_between(&input.itinerary.next);
+    if input.itinerary.next != input.itinerary.finish {
+        remaining_min_m += input
+            .itinerary
+            .next
+            .borrow()
+            .distance_between(&input.itinerary.finish);
+    }
+    // the next waypoint can be passed on the edge, so only the finish is left after its end
+    if let Some(edge) = junction_edge_after_fork(&input) {
+        remaining_min_m = remaining_min_m
+            .max(edge.len_m + edge.end.borrow().distance_between(&input.itinerary.finish));
+    }
+    let len_m = input.route.get_len_m()
+        + f64::from(input.current_fork_segment.get_line().borrow().get_len_m())
+        + f64::from(remaining_min_m);
+
+    if len_m > max_length_m {
+        return WeightCalcResult::ForkChoiceDoNotUse;
+    }
+    WeightCalcResult::ForkChoiceUseWithWeight(0)
+}
+
+/// Rule for the tag of the segment, the priorities are left out when the numeric preference table
+/// for the same tag is set as the table takes precedence. Avoid rules always apply
 fn get_rule_for_tag(
     rule: &Option<HashMap<String, RulesTagValueAction>>,
+    weights: &HashMap<String, u8>,
     segment_tag: Option<&smartstring::alias::String>,
 ) -> Option<WeightCalcResult> {
     if let Some(ref rule_tag) = rule {
         if let Some(segment_tag) = segment_tag {
             let rule_tag = rule_tag.get(&segment_tag.to_string());
             if let Some(rule_tag) = rule_tag {
-                return Some(match rule_tag {
-                    RulesTagValueAction::Avoid => WeightCalcResult::ForkChoiceDoNotUse,
+                return match rule_tag {
+                    RulesTagValueAction::Avoid => Some(WeightCalcResult::ForkChoiceDoNotUse),
+                    RulesTagValueAction::Priority { .. } if !weights.is_empty() => None,
                     RulesTagValueAction::Priority { value } => {
-                        WeightCalcResult::ForkChoiceUseWithWeight(*value)
+                        Some(WeightCalcResult::ForkChoiceUseWithWeight(*value))
                     }
-                });
+                };
             }
         }
     }
@@ -325,6 +491,7 @@ pub fn weight_rules_highway(input: WeightCalcInput) -> WeightCalcResult {
         .any(|seg| {
             if let Some(tag_rule) = get_rule_for_tag(
                 &input.rules.highway,
+                &input.rules.highway_weights,
                 seg.get_line().borrow().tags.borrow().highway(),
             ) {
                 if tag_rule == WeightCalcResult::ForkChoiceDoNotUse {
@@ -339,6 +506,7 @@ pub fn weight_rules_highway(input: WeightCalcInput) -> WeightCalcResult {
 
     if let Some(res) = get_rule_for_tag(
         &input.rules.highway,
+        &input.rules.highway_weights,
         input
             .current_fork_segment
             .get_line()
@@ -367,6 +535,7 @@ pub fn weight_rules_surface(input: WeightCalcInput) -> WeightCalcResult {
         .any(|seg| {
             if let Some(tag_rule) = get_rule_for_tag(
                 &input.rules.surface,
+                &input.rules.surface_weights,
                 seg.get_line().borrow().tags.borrow().surface(),
             ) {
                 if tag_rule == WeightCalcResult::ForkChoiceDoNotUse {
@@ -381,6 +550,7 @@ pub fn weight_rules_surface(input: WeightCalcInput) -> WeightCalcResult {
 
     if let Some(res) = get_rule_for_tag(
         &input.rules.surface,
+        &input.rules.surface_weights,
         input
             .current_fork_segment
             .get_line()
@@ -409,6 +579,7 @@ pub fn weight_rules_smoothness(input: WeightCalcInput) -> WeightCalcResult {
         .any(|seg| {
             if let Some(tag_rule) = get_rule_for_tag(
                 &input.rules.smoothness,
+                &input.rules.smoothness_weights,
                 seg.get_line().borrow().tags.borrow().smoothness(),
             ) {
                 if tag_rule == WeightCalcResult::ForkChoiceDoNotUse {
@@ -423,6 +594,7 @@ pub fn weight_rules_smoothness(input: WeightCalcInput) -> WeightCalcResult {
 
     if let Some(res) = get_rule_for_tag(
         &input.rules.smoothness,
+        &input.rules.smoothness_weights,
         input
             .current_fork_segment
             .get_line()
@@ -437,6 +609,89 @@ pub fn weight_rules_smoothness(input: WeightCalcInput) -> WeightCalcResult {
     WeightCalcResult::ForkChoiceUseWithWeight(0)
 }
 
+/// Averages the values from the numeric preference tables that list a tag of the fork's road,
+/// tags not listed in a table do not affect the result. A table that is set replaces the
+/// priorities of the coarse preferences for its tag
+pub fn weight_tag_preferences(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_tag_preferences");
+
+    if is_last_point_near_residential(&input) {
+        return WeightCalcResult::ForkChoiceUseWithWeight(0);
+    }
+
+    let line = input.current_fork_segment.get_line().borrow();
+    let tags = line.tags.borrow();
+    let values = [
+        (&input.rules.highway_weights, tags.highway()),
+        (&input.rules.surface_weights, tags.surface()),
+        (&input.rules.smoothness_weights, tags.smoothness()),
+    ]
+    .into_iter()
+    .filter_map(|(table, tag)| tag.and_then(|tag| table.get(tag.as_str())))
+    .map(|value| u32::from(*value))
+    .collect::<Vec<_>>();
+
+    if values.is_empty() {
+        return WeightCalcResult::ForkChoiceUseWithWeight(0);
+    }
+    WeightCalcResult::ForkChoiceUseWithWeight(
+        (values.iter().sum::<u32>() / values.len() as u32) as u8,
+    )
+}
+
+/// Excludes roads matching an exclude matcher and sums up the bonuses of matching and the
+/// penalties of not matching matchers, so that roads matching a penalty matcher lose that amount
+/// compared to the others
+pub fn weight_tag_matchers(input: WeightCalcInput) -> WeightCalcResult {
+    trace!("weight_tag_matchers");
+
+    if input.rules.tag_matchers.is_empty() {
+        return WeightCalcResult::ForkChoiceUseWithWeight(0);
+    }
+
+    if input
+        .route
+        .get_route_chunk_since_junction_before_last()
+        .iter()
+        .any(|seg| {
+            input
+                .rules
+                .is_excluded_by_tag_matchers(seg.get_line().borrow().tags.borrow())
+        })
+    {
+        return WeightCalcResult::LastSegmentDoNotUse;
+    }
+
+    let line = input.current_fork_segment.get_line().borrow();
+    let tags = line.tags.borrow();
+    if input.rules.is_excluded_by_tag_matchers(tags) {
+        return WeightCalcResult::ForkChoiceDoNotUse;
+    }
+    // the route can't turn off the junction edge the fork leads into before its end
+    if junction_edge_after_fork(&input).is_some_and(|edge| {
+        edge.tags
+            .iter()
+            .any(|(tags, _)| input.rules.is_excluded_by_tag_matchers(tags.borrow()))
+    }) {
+        return WeightCalcResult::ForkChoiceDoNotUse;
+    }
+
+    let weight = input
+        .rules
+        .tag_matchers
+        .iter()
+        .fold(0u8, |weight, matcher| match matcher.action {
+            RulesTagMatcherAction::Bonus if matcher.matches(tags) => {
+                weight.saturating_add(matcher.amount)
+            }
+            RulesTagMatcherAction::Penalty if !matcher.matches(tags) => {
+                weight.saturating_add(matcher.amount)
+            }
+            _ => weight,
+        });
+    WeightCalcResult::ForkChoiceUseWithWeight(weight)
+}
+
 pub fn weight_avoid_nogo_areas(input: WeightCalcInput) -> WeightCalcResult {
     trace!("weight_avoid_nogo_areas");
     if input
@@ -459,7 +714,7 @@ pub fn weight_avoid_nogo_areas(input: WeightCalcInput) -> WeightCalcResult {
 }
 
 fn was_on_avoid<F>(
-    route_chunk: &Vec<Segment>,
+    route_chunk: &[Segment],
     tag_rule: &Option<HashMap<String, RulesTagValueAction>>,
     tag_getter: F,
 ) -> bool
@@ -489,21 +744,33 @@ pub fn weight_check_avoid_rules(input: WeightCalcInput) -> WeightCalcResult {
     trace!("weight_check_avoid_rules");
 
     let last_chunk = input.route.get_route_chunk_since_junction_before_last();
-    if was_on_avoid(&last_chunk, &input.rules.highway, |segment| {
+    if was_on_avoid(last_chunk, &input.rules.highway, |segment| {
         segment.get_line().borrow().tags.borrow().highway()
     }) {
         return WeightCalcResult::LastSegmentDoNotUse;
     }
-    if was_on_avoid(&last_chunk, &input.rules.surface, |segment| {
+    if was_on_avoid(last_chunk, &input.rules.surface, |segment| {
         segment.get_line().borrow().tags.borrow().surface()
     }) {
         return WeightCalcResult::LastSegmentDoNotUse;
     }
-    if was_on_avoid(&last_chunk, &input.rules.smoothness, |segment| {
+    if was_on_avoid(last_chunk, &input.rules.smoothness, |segment| {
         segment.get_line().borrow().tags.borrow().smoothness()
     }) {
         return WeightCalcResult::LastSegmentDoNotUse;
     }
+    if last_chunk.iter().any(|segment| {
+        input
+            .rules
+            .avoid_way_ids
+            .contains(&segment.get_line().borrow().way_id)
+            || input
+                .rules
+                .avoid_node_ids
+                .contains(&segment.get_end_point().borrow().id)
+    }) {
+        return WeightCalcResult::LastSegmentDoNotUse;
+    }
 
     WeightCalcResult::ForkChoiceUseWithWeight(0)
 }
@@ -511,21 +778,33 @@ pub fn weight_check_avoid_rules(input: WeightCalcInput) -> WeightCalcResult {
 #[cfg(test)]
 mod test {
 
-    use std::path::PathBuf;
+    use std::{collections::HashMap, path::PathBuf};
 
     use rusty_fork::rusty_fork_test;
     use tracing::info;
 
     use crate::{
-        map_data::graph::{MapDataGraph, MapDataPointRef},
+        map_data::{
+            graph::{MapDataGraph, MapDataPointRef},
+            osm::{OsmNode, OsmWay},
+        },
         router::{
-            itinerary::Itinerary, navigator::WeightCalcResult, route::segment::Segment,
-            rules::RouterRules, walker::Walker,
+            itinerary::Itinerary,
+            navigator::WeightCalcResult,
+            route::{segment::Segment, Route},
+            rules::{RouterRules, RulesTagMatcher, RulesTagMatcherAction, RulesTagValueAction},
+            walker::Walker,
+        },
+        test_utils::{
+            graph_from_test_dataset, graph_from_test_file, set_graph_static, test_dataset_1,
         },
-        test_utils::{graph_from_test_file, set_graph_static},
     };
 
-    use super::{get_priority_from_headings, weight_heading, WeightCalcInput};
+    use super::{
+        get_priority_from_headings, weight_avoid_urban, weight_heading, weight_max_length,
+        weight_no_same_road_return, weight_no_steep_grades, weight_prefer_curvy,
+        weight_rules_highway, weight_tag_matchers, weight_tag_preferences, WeightCalcInput,
+    };
 
     #[test]
     fn get_prio_from_headings() {
@@ -557,9 +836,8 @@ mod test {
         end_point: MapDataPointRef,
         opposite_point_for_line: MapDataPointRef,
     ) -> Segment {
-        let end_point_borrowed = end_point.borrow();
-        let line = end_point_borrowed
-            .lines
+        let line = end_point
+            .lines()
             .iter()
             .find(|line| {
                 let line = line.borrow();
@@ -627,4 +905,445 @@ mod test {
             assert_eq!(fork_weight, WeightCalcResult::ForkChoiceUseWithWeight(64));
         }
     }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn weight_max_length_test() {
+            set_graph_static(graph_from_test_file(&PathBuf::from("test-data/sigulda-100.json")));
+            let from = MapDataGraph::get()
+                .test_get_point_ref_by_id(&885564366)
+                .expect("did not find start point");
+            let to = MapDataGraph::get()
+                .test_get_point_ref_by_id(&33416714)
+                .expect("did not find end point");
+            let fork_point = MapDataGraph::get()
+                .test_get_point_ref_by_id(&81272994)
+                .expect("to find fork point");
+            let segment = get_route_segment(fork_point, from.clone());
+            let itinerary = Itinerary::new_start_finish(from.clone(), to.clone(), Vec::new(), 0.);
+            let walker = Walker::new(from.clone());
+            let straight_line_m = f64::from(from.borrow().distance_between(&to));
+
+            let mut rules = RouterRules::default();
+            let tests = [
+                (None, WeightCalcResult::ForkChoiceUseWithWeight(0)),
+                (Some(straight_line_m * 10.), WeightCalcResult::ForkChoiceUseWithWeight(0)),
+                (Some(straight_line_m / 2.), WeightCalcResult::ForkChoiceDoNotUse),
+            ];
+            for (max_length_m, expected) in tests {
+                rules.generation.route_length.max_length_m = max_length_m;
+                let fork_weight = weight_max_length(WeightCalcInput {
+                    route: walker.get_route(),
+                    itinerary: &itinerary,
+                    current_fork_segment: &segment,
+                    walker_from_fork: Walker::new(from.clone()),
+                    rules: &rules,
+                });
+                assert_eq!(fork_weight, expected);
+            }
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn weight_no_same_road_return_test() {
+            set_graph_static(graph_from_test_file(&PathBuf::from("test-data/sigulda-100.json")));
+            // 9212889586 - 885564366 - 81272994 are all on the P8 road
+            let start = MapDataGraph::get()
+                .test_get_point_ref_by_id(&9212889586)
+                .expect("did not find start point");
+            let middle = MapDataGraph::get()
+                .test_get_point_ref_by_id(&885564366)
+                .expect("did not find middle point");
+            let fork_point = MapDataGraph::get()
+                .test_get_point_ref_by_id(&81272994)
+                .expect("to find fork point");
+
+            let mut route = Route::new();
+            route.add_segment(get_route_segment(middle.clone(), start.clone()));
+            let segment = get_route_segment(fork_point, middle.clone());
+
+            let round_trip = Itinerary::new_start_finish(start.clone(), start.clone(), Vec::new(), 0.);
+            let start_finish = Itinerary::new_start_finish(start.clone(), middle.clone(), Vec::new(), 0.);
+
+            let mut rules = RouterRules::default();
+            rules.basic.no_same_road_return.enabled = true;
+            rules.basic.no_same_road_return.skip_recent_m = 0.;
+            let priority = rules.basic.no_same_road_return.priority;
+            let mut disabled_rules = rules.clone();
+            disabled_rules.basic.no_same_road_return.enabled = false;
+            let mut skip_recent_rules = rules.clone();
+            skip_recent_rules.basic.no_same_road_return.skip_recent_m = 2000.;
+
+            let tests = [
+                (&round_trip, &rules, WeightCalcResult::ForkChoiceUseWithWeight(0)),
+                (&round_trip, &disabled_rules, WeightCalcResult::ForkChoiceUseWithWeight(0)),
+                (&start_finish, &rules, WeightCalcResult::ForkChoiceUseWithWeight(0)),
+                (
+                    &round_trip,
+                    &skip_recent_rules,
+                    WeightCalcResult::ForkChoiceUseWithWeight(priority),
+                ),
+            ];
+            for (itinerary, rules, expected) in tests {
+                let fork_weight = weight_no_same_road_return(WeightCalcInput {
+                    route: &route,
+                    itinerary,
+                    current_fork_segment: &segment,
+                    walker_from_fork: Walker::new(middle.clone()),
+                    rules,
+                });
+                assert_eq!(fork_weight, expected);
+            }
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn weight_max_length_junction_edge_test() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let start = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let fork_point = MapDataGraph::get().test_get_point_ref_by_id(&2).unwrap();
+            // 2 is passed through up to the junction at 3, which is farther from the finish
+            let segment = get_route_segment(fork_point.clone(), start.clone());
+            let itinerary = Itinerary::new_start_finish(start.clone(), start.clone(), Vec::new(), 0.);
+            let walker = Walker::new(start.clone());
+            let line_m = f64::from(start.borrow().distance_between(&fork_point));
+
+            let mut rules = RouterRules::default();
+            let tests = [
+                (line_m * 5., WeightCalcResult::ForkChoiceUseWithWeight(0)),
+                (line_m * 3., WeightCalcResult::ForkChoiceDoNotUse),
+            ];
+            for (max_length_m, expected) in tests {
+                rules.generation.route_length.max_length_m = Some(max_length_m);
+                let fork_weight = weight_max_length(WeightCalcInput {
+                    route: walker.get_route(),
+                    itinerary: &itinerary,
+                    current_fork_segment: &segment,
+                    walker_from_fork: Walker::new(start.clone()),
+                    rules: &rules,
+                });
+                assert_eq!(fork_weight, expected);
+            }
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn weight_tag_matchers_junction_edge_test() {
+            // 1 - 2 is primary, 2 - 3 a track up to the junction at 3
+            let mut map_data = MapDataGraph::new();
+            for id in 1..=5 {
+                map_data
+                    .insert_node(OsmNode {
+                        id,
+                        lat: 57.0 + id as f64 * 0.001,
+                        lon: 24.0 + if id == 5 { 0.001 } else { 0. },
+                        residential_in_proximity: false,
+                        nogo_area: false,
+                        elevation: None,
+                    })
+                    .unwrap();
+            }
+            for (id, point_ids, highway) in [
+                (1, vec![1, 2], "primary"),
+                (2, vec![2, 3], "track"),
+                (3, vec![3, 4], "primary"),
+                (4, vec![3, 5], "primary"),
+            ] {
+                map_data
+                    .insert_way(OsmWay {
+                        id,
+                        point_ids,
+                        tags: Some(HashMap::from([(
+                            "highway".to_string(),
+                            highway.to_string(),
+                        )])),
+                    })
+                    .unwrap();
+            }
+            set_graph_static(map_data);
+            let start = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let fork_point = MapDataGraph::get().test_get_point_ref_by_id(&2).unwrap();
+            let finish = MapDataGraph::get().test_get_point_ref_by_id(&4).unwrap();
+            let segment = get_route_segment(fork_point, start.clone());
+            let walker = Walker::new(start.clone());
+
+            let rules = RouterRules {
+                tag_matchers: vec![RulesTagMatcher {
+                    key: "highway".to_string(),
+                    value: "track".to_string(),
+                    action: RulesTagMatcherAction::Exclude,
+                    amount: 0,
+                }],
+                ..RouterRules::default()
+            };
+            let tests = [
+                (finish, WeightCalcResult::ForkChoiceDoNotUse),
+                // finishing on the edge does not need the track
+                (
+                    MapDataGraph::get().test_get_point_ref_by_id(&3).unwrap(),
+                    WeightCalcResult::ForkChoiceUseWithWeight(0),
+                ),
+            ];
+            for (finish, expected) in tests {
+                let itinerary = Itinerary::new_start_finish(start.clone(), finish, Vec::new(), 0.);
+                let fork_weight = weight_tag_matchers(WeightCalcInput {
+                    route: walker.get_route(),
+                    itinerary: &itinerary,
+                    current_fork_segment: &segment,
+                    walker_from_fork: Walker::new(start.clone()),
+                    rules: &rules,
+                });
+                assert_eq!(fork_weight, expected);
+            }
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn weight_prefer_curvy_test() {
+            // 1 - 2 - 3 - 4 goes straight north, 1 - 5 - 6 - 7 - 8 zigzags east
+            let mut map_data = MapDataGraph::new();
+            for (id, lat, lon) in [
+                (1, 57.0, 24.0),
+                (2, 57.001, 24.0),
+                (3, 57.002, 24.0),
+                (4, 57.003, 24.0),
+                (5, 57.001, 24.002),
+                (6, 57.0, 24.004),
+                (7, 57.001, 24.006),
+                (8, 57.0, 24.008),
+                (9, 56.999, 24.0),
+            ] {
+                map_data
+                    .insert_node(OsmNode {
+                        id,
+                        lat,
+                        lon,
+                        residential_in_proximity: false,
+                        nogo_area: false,
+                        elevation: None,
+                    })
+                    .unwrap();
+            }
+            for (id, point_ids) in [
+                (1, vec![9, 1]),
+                (2, vec![1, 2, 3, 4]),
+                (3, vec![1, 5, 6, 7, 8]),
+            ] {
+                map_data
+                    .insert_way(OsmWay {
+                        id,
+                        point_ids,
+                        tags: Some(HashMap::from([(
+                            "highway".to_string(),
+                            "primary".to_string(),
+                        )])),
+                    })
+                    .unwrap();
+            }
+            set_graph_static(map_data);
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+            let itinerary = Itinerary::new_start_finish(point(9), point(4), Vec::new(), 0.);
+            let straight = get_route_segment(point(2), point(1));
+            let curvy = get_route_segment(point(5), point(1));
+
+            let mut rules = RouterRules::default();
+            rules.basic.prefer_curvy.enabled = true;
+            let bonus = rules.basic.prefer_curvy.curvy_road_bonus;
+            let priority = rules.basic.prefer_curvy.priority;
+            for (segment, expected) in [(&straight, 0), (&curvy, bonus + priority)] {
+                let fork_weight = weight_prefer_curvy(WeightCalcInput {
+                    route: &Route::new(),
+                    itinerary: &itinerary,
+                    current_fork_segment: segment,
+                    walker_from_fork: Walker::new(segment.get_end_point().clone()),
+                    rules: &rules,
+                });
+                assert_eq!(fork_weight, WeightCalcResult::ForkChoiceUseWithWeight(expected));
+            }
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn weight_avoid_urban_test() {
+            // 1 - 2 - 3 goes north through the countryside, 1 - 4 - 5 east through a town
+            let mut map_data = MapDataGraph::new();
+            for (id, lat, lon, residential_in_proximity) in [
+                (1, 57.0, 24.0, false),
+                (2, 57.001, 24.0, false),
+                (3, 57.002, 24.0, false),
+                (4, 57.0, 24.002, true),
+                (5, 57.0, 24.004, true),
+                (6, 56.999, 24.0, false),
+            ] {
+                map_data
+                    .insert_node(OsmNode {
+                        id,
+                        lat,
+                        lon,
+                        residential_in_proximity,
+                        nogo_area: false,
+                        elevation: None,
+                    })
+                    .unwrap();
+            }
+            for (id, point_ids) in [(1, vec![6, 1]), (2, vec![1, 2, 3]), (3, vec![1, 4, 5])] {
+                map_data
+                    .insert_way(OsmWay {
+                        id,
+                        point_ids,
+                        tags: Some(HashMap::from([(
+                            "highway".to_string(),
+                            "primary".to_string(),
+                        )])),
+                    })
+                    .unwrap();
+            }
+            set_graph_static(map_data);
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+            let itinerary = Itinerary::new_start_finish(point(6), point(3), Vec::new(), 0.);
+            let rural = get_route_segment(point(2), point(1));
+            let urban = get_route_segment(point(4), point(1));
+
+            let mut rules = RouterRules::default();
+            rules.basic.avoid_urban.enabled = true;
+            let priority = rules.basic.avoid_urban.priority;
+            for (segment, expected) in [(&rural, priority), (&urban, 0)] {
+                let fork_weight = weight_avoid_urban(WeightCalcInput {
+                    route: &Route::new(),
+                    itinerary: &itinerary,
+                    current_fork_segment: segment,
+                    walker_from_fork: Walker::new(segment.get_end_point().clone()),
+                    rules: &rules,
+                });
+                assert_eq!(fork_weight, WeightCalcResult::ForkChoiceUseWithWeight(expected));
+            }
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn weight_no_steep_grades_test() {
+            // 1 - 2 - 3 goes north on the flat, 1 - 4 - 5 east up a 20% grade
+            let mut map_data = MapDataGraph::new();
+            for (id, lat, lon, elevation) in [
+                (1, 57.0, 24.0, 100.),
+                (2, 57.001, 24.0, 100.),
+                (3, 57.002, 24.0, 101.),
+                (4, 57.0, 24.002, 124.),
+                (5, 57.0, 24.004, 148.),
+                (6, 56.999, 24.0, 100.),
+            ] {
+                map_data
+                    .insert_node(OsmNode {
+                        id,
+                        lat,
+                        lon,
+                        residential_in_proximity: false,
+                        nogo_area: false,
+                        elevation: Some(elevation),
+                    })
+                    .unwrap();
+            }
+            for (id, point_ids) in [(1, vec![6, 1]), (2, vec![1, 2, 3]), (3, vec![1, 4, 5])] {
+                map_data
+                    .insert_way(OsmWay {
+                        id,
+                        point_ids,
+                        tags: Some(HashMap::from([(
+                            "highway".to_string(),
+                            "primary".to_string(),
+                        )])),
+                    })
+                    .unwrap();
+            }
+            set_graph_static(map_data);
+            let point = |id| MapDataGraph::get().test_get_point_ref_by_id(&id).unwrap();
+            let itinerary = Itinerary::new_start_finish(point(6), point(3), Vec::new(), 0.);
+            let flat = get_route_segment(point(2), point(1));
+            let steep = get_route_segment(point(4), point(1));
+
+            let mut rules = RouterRules::default();
+            rules.basic.no_steep_grades.enabled = true;
+            let priority = rules.basic.no_steep_grades.priority;
+            for (segment, expected) in [(&flat, priority), (&steep, 0)] {
+                let fork_weight = weight_no_steep_grades(WeightCalcInput {
+                    route: &Route::new(),
+                    itinerary: &itinerary,
+                    current_fork_segment: segment,
+                    walker_from_fork: Walker::new(segment.get_end_point().clone()),
+                    rules: &rules,
+                });
+                assert_eq!(fork_weight, WeightCalcResult::ForkChoiceUseWithWeight(expected));
+            }
+        }
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn weight_tag_preferences_take_precedence_test() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let start = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let finish = MapDataGraph::get().test_get_point_ref_by_id(&4).unwrap();
+            let segment = get_route_segment(
+                MapDataGraph::get().test_get_point_ref_by_id(&2).unwrap(),
+                start.clone(),
+            );
+            let itinerary = Itinerary::new_start_finish(start.clone(), finish, Vec::new(), 0.);
+            let weights = |rules: &RouterRules| {
+                [weight_rules_highway, weight_tag_preferences].map(|calc| {
+                    calc(WeightCalcInput {
+                        route: &Route::new(),
+                        itinerary: &itinerary,
+                        current_fork_segment: &segment,
+                        walker_from_fork: Walker::new(start.clone()),
+                        rules,
+                    })
+                })
+            };
+
+            let mut rules = RouterRules {
+                highway: Some(HashMap::from([(
+                    "primary".to_string(),
+                    RulesTagValueAction::Priority { value: 50 },
+                )])),
+                ..RouterRules::default()
+            };
+            assert_eq!(
+                weights(&rules),
+                [
+                    WeightCalcResult::ForkChoiceUseWithWeight(50),
+                    WeightCalcResult::ForkChoiceUseWithWeight(0)
+                ]
+            );
+
+            rules.highway_weights = HashMap::from([("primary".to_string(), 10)]);
+            assert_eq!(
+                weights(&rules),
+                [
+                    WeightCalcResult::ForkChoiceUseWithWeight(0),
+                    WeightCalcResult::ForkChoiceUseWithWeight(10)
+                ]
+            );
+
+            // avoiding a tag still applies with a table
+            rules.highway = Some(HashMap::from([(
+                "primary".to_string(),
+                RulesTagValueAction::Avoid,
+            )]));
+            assert_eq!(weights(&rules)[0], WeightCalcResult::ForkChoiceDoNotUse);
+        }
+    }
 }
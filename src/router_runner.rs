@@ -1,26 +1,98 @@
 use anyhow::{Context, Result};
-use std::panic::catch_unwind;
-use std::{num::ParseFloatError, path::PathBuf, str::FromStr, time::Instant};
+use rayon::prelude::*;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::{
+    collections::HashMap,
+    io::{IsTerminal, Write},
+    net::IpAddr,
+    num::ParseFloatError,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
 
-use clap::Parser;
+use clap::{CommandFactory, FromArgMatches, Parser};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use tracing::{info, trace};
-
-use crate::osm_data::DataSource;
+use tracing::{error, error_span, info, trace, warn};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::auth::tokens_match;
+use crate::bench::{self, BenchError, BenchFormat, ScenarioRun};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::daemon::{self, DaemonError};
+use crate::fuzz::{FuzzCase, FuzzError, FuzzFailure, FuzzFailureKind, FuzzReport};
+#[cfg(feature = "geocoding")]
+use crate::geocoder::{AddressOptions, Geocoder, GeocoderError};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::ipc_handler::{
+    BatchMessage, IpcHandler, IpcHandlerError, RequestStatus, SocketPermissions, ACTIVE_REQUESTS,
+    SHUTTING_DOWN,
+};
+use crate::matrix::{Matrix, MatrixError};
+use crate::osm_data::{
+    pbf_estimate::{self, PbfEstimate},
+    DataSource, OsmDataReaderError,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::rate_limiter::RateLimiter;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::repl::{Repl, ReplError};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::request_history::{RequestHistory, RequestHistoryError};
+use crate::rerouter::{Rerouter, RerouterError};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::response_cache::ResponseCache;
 use crate::router::generator::{GeneratorError, WP_LOOKUP_ALLOWED_HWS};
+use crate::track_matcher::{TrackMatcher, TrackMatcherError};
+use crate::waypoints_reader::{WaypointsReader, WaypointsReaderError};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::websocket_handler::{WebSocketHandler, WebSocketHandlerError};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::worker_pool::{Priority, WorkerPool, WorkerPoolError};
 use crate::{
-    debug::writer::DebugWriter,
-    ipc_handler::{IpcHandler, IpcHandlerError, ResponseMessage, RouteMessage, RouterResult},
-    map_data::graph::MapDataGraph,
+    cli_config::{self, CliConfig},
+    cli_error::{CliError, ErrorFormat},
+    debug::{
+        replay::{self, DebugReplayError, ReplayItinerary},
+        stats::{self as debug_stats, DebugStatsError, DebugStatsFormat},
+        writer::{DebugWriter, DebugWriterOptions},
+    },
+    gpx_writer::GpxOptions,
+    image_writer::RenderOptions,
+    ipc_handler::{
+        IpcRequest, OutputDetail, RequestMessage, ResponseMessage, ResponseSender, RouteMessage,
+        RouterResult,
+    },
+    map_data::{
+        graph::{MapDataGraph, MapDataPointRef},
+        info::{GraphInfoError, GraphInfoFormat},
+    },
     map_data_cache::{MapDataCache, MapDataCacheError},
-    result_writer::{DataDestination, ResultWriter, ResultWriterError},
+    result_writer::{DataDestination, OutputOptions, ResultWriter, ResultWriterError},
     router::{
-        generator::{Generator, RouteWithStats},
-        rules::RouterRules,
+        cancellation::CancellationToken,
+        generator::{Generator, RouteCallback, RouteWithStats},
+        itinerary::{ItineraryVia, VIA_DEFAULT_RADIUS_M},
+        navigator::NavigationResult,
+        progress::{GenerationProgress, ProgressCallback},
+        rule_conditions::{self, apply_conditions, RuleConditionTrip},
+        rules::{self, RouterRules, RulesError},
+        rules_check::check_rules,
+        shortest_path::average_speed_kmh,
+        summary::{GenerationCounters, GenerationSummary},
+        via_order::optimize_via_order,
     },
+    telemetry::{self, TelemetryOptions},
+    units,
 };
 
-use clap::Subcommand;
+use clap::{Args, Subcommand};
+use time::OffsetDateTime;
 
 #[derive(Debug, thiserror::Error)]
 pub enum RouterRunnerError {
@@ -43,21 +115,107 @@ pub enum RouterRunnerError {
         error: Option<ParseFloatError>,
     },
 
+    #[cfg(not(target_arch = "wasm32"))]
     #[error("IPC error: {error}")]
     Ipc { error: IpcHandlerError },
 
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("WebSocket error: {error}")]
+    WebSocket { error: WebSocketHandlerError },
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("Failed to set termination signal handler: {error}")]
+    SignalHandler { error: ctrlc::Error },
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("REPL error: {error}")]
+    Repl { error: ReplError },
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("Failed to start request history: {error}")]
+    RequestHistory { error: RequestHistoryError },
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("Daemon error: {error}")]
+    Daemon { error: DaemonError },
+
+    #[error("Failed to apply rules: {error}")]
+    Rules { error: RulesError },
+
+    #[error("Server only accepts rule overrides, not complete rules")]
+    CompleteRules,
+
+    #[error("Found {count} problems in the rules")]
+    RulesCheck { count: usize },
+
+    #[error("Either a routing mode or a requests file is required, but not both")]
+    ClientRequest,
+
+    #[error("Either a routing mode or a waypoints file is required, but not both")]
+    WaypointsRoutingMode,
+
+    #[cfg(feature = "geocoding")]
+    #[error("Either a routing mode, a waypoints file or addresses are required, only one of them")]
+    AddressRoutingMode,
+
+    #[cfg(feature = "geocoding")]
+    #[error("Failed to resolve address: {error}")]
+    Geocoder { error: GeocoderError },
+
+    #[error("Failed to read waypoints: {error}")]
+    Waypoints { error: WaypointsReaderError },
+
+    #[error("Round trip requires either a distance or a duration")]
+    RoundTripLength,
+
+    #[error("Streamed routes are printed to stdout, --stream-routes requires an --output file")]
+    StreamRoutesStdout,
+
     #[error("Could not find {point} on map")]
     PointNotFound { point: String },
 
+    #[error("No route found")]
+    NoRoute,
+
     #[error("Failed to write result: {error}")]
     ResultWrite { error: ResultWriterError },
 
+    #[error("Failed to write graph info: {error}")]
+    GraphInfo { error: GraphInfoError },
+
+    #[error("Dry run is only supported for PBF input")]
+    DryRunInput,
+
+    #[error("Failed to estimate import: {error}")]
+    DryRun { error: Box<OsmDataReaderError> },
+
     #[error("Failed to write cache: {error}")]
     CacheWrite { error: MapDataCacheError },
 
     #[error("Failed to generate routes: {error}")]
     GenerateRoute { error: GeneratorError },
 
+    #[error("Failed to calculate matrix: {error}")]
+    Matrix { error: MatrixError },
+
+    #[error("Failed to match track: {error}")]
+    TrackMatch { error: TrackMatcherError },
+
+    #[error("Failed to reroute: {error}")]
+    Reroute { error: RerouterError },
+
+    #[error("Failed to replay itinerary: {error}")]
+    DebugReplay { error: DebugReplayError },
+
+    #[error("Failed to benchmark: {error}")]
+    Bench { error: BenchError },
+
+    #[error("Failed to fuzz routes: {error}")]
+    Fuzz { error: FuzzError },
+
+    #[error("Failed to summarize fork choices: {error}")]
+    DebugStats { error: DebugStatsError },
+
     #[cfg(feature = "debug-viewer")]
     #[error("Failed run debug viewer: {error}")]
     DebugViewer {
@@ -71,9 +229,22 @@ pub enum RouterRunnerError {
 struct Cli {
     #[command(subcommand)]
     pub mode: CliMode,
+
+    #[command(flatten)]
+    pub telemetry_options: TelemetryOptions,
+
+    #[arg(long, global = true, value_name = "FORMAT", default_value = "text")]
+    /// Format of the error the process exits with, the exit code tells the kind of the error:
+    /// 2 bad input, 3 point not found, 4 no route, 5 cache error, 6 IPC error, 1 anything else
+    pub error_format: ErrorFormat,
+
+    #[arg(long, global = true, value_name = "KEYS", value_delimiter = ',')]
+    /// Way tags to store in the map data in addition to the default ones, so that tag matcher
+    /// rules can match them, comma separated. The cache is rebuilt when these change
+    pub extra_tag_keys: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Coords {
     lat: f32,
     lon: f32,
@@ -115,6 +286,142 @@ impl FromStr for Coords {
     }
 }
 
+/// Average speed used to size a round trip requested by duration when no road around the start
+/// can be reached to estimate it from
+const ROUND_TRIP_DEFAULT_SPEED_KMH: f32 = 60.;
+
+fn duration_to_distance_m(duration_min: u32, speed_kmh: f32) -> u32 {
+    (duration_min as f32 / 60. * speed_kmh * 1000.) as u32
+}
+
+/// Length of a round trip for the duration, sized with the average speed of the roads within half
+/// the duration of the start, as the loop turns back about half way
+fn round_trip_duration_distance_m(
+    start: &MapDataPointRef,
+    duration_min: u32,
+    rules: &RouterRules,
+) -> u32 {
+    let speed_kmh = average_speed_kmh(start, f64::from(duration_min) * 60. / 2., rules)
+        .unwrap_or(ROUND_TRIP_DEFAULT_SPEED_KMH);
+    let distance = duration_to_distance_m(duration_min, speed_kmh);
+    info!(speed_kmh, distance, "Round trip duration speed");
+    distance
+}
+
+const PROGRESS_BAR_WIDTH: usize = 30;
+
+/// Measured runs of every benchmark scenario
+const DEFAULT_BENCH_ITERATIONS: usize = 10;
+
+/// Random start and finish pairs generated by `fuzz-routes`
+const DEFAULT_FUZZ_COUNT: usize = 100;
+
+/// Longest distance between the start and the finish of a fuzzed case
+const DEFAULT_FUZZ_MAX_DISTANCE_M: u32 = 50_000;
+
+/// Time limit of every fuzzed case, so a single slow case does not hold up the rest
+const DEFAULT_FUZZ_TIMEOUT_S: u64 = 60;
+
+/// Route requests that can wait for a free worker in server mode before new ones are rejected
+const DEFAULT_QUEUE_DEPTH: usize = 100;
+
+/// Finished requests the server keeps the responses of in server mode
+const DEFAULT_HISTORY_SIZE: usize = 100;
+
+/// Seconds the server waits for requests in progress to finish after a termination signal
+const DEFAULT_DRAIN_TIMEOUT_S: u64 = 30;
+
+/// Time given to cancelled requests to send their response once the drain timeout is reached
+#[cfg(not(target_arch = "wasm32"))]
+const CANCEL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Set once the drain timeout is reached, requests still waiting for a worker are not started
+#[cfg(not(target_arch = "wasm32"))]
+static DRAIN_TIMED_OUT: AtomicBool = AtomicBool::new(false);
+
+/// Redraws a single progress line on stderr, stdout is reserved for results
+fn print_progress(progress: GenerationProgress) {
+    let filled = (progress.itineraries_done * PROGRESS_BAR_WIDTH)
+        .checked_div(progress.itineraries_total)
+        .unwrap_or(0);
+    eprint!(
+        "\r[{}{}] {}/{} itineraries, {} steps, best score {}    ",
+        "#".repeat(filled),
+        "-".repeat(PROGRESS_BAR_WIDTH - filled),
+        progress.itineraries_done,
+        progress.itineraries_total,
+        progress.steps,
+        progress
+            .best_score
+            .map_or("-".to_string(), |score| format!("{score:.2}"))
+    );
+    let _ = std::io::stderr().flush();
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ViaCoords {
+    lat: f32,
+    lon: f32,
+    radius: Option<f32>,
+}
+
+impl FromStr for ViaCoords {
+    type Err = RouterRunnerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (coords, radius) = match s.splitn(3, ",").collect::<Vec<_>>()[..] {
+            [lat, lon, radius] => (format!("{lat},{lon}"), Some(radius)),
+            _ => (s.to_string(), None),
+        };
+        let coords = Coords::from_str(&coords)?;
+        let radius = radius
+            .map(|radius| {
+                radius.parse().map_err(|error| RouterRunnerError::Coords {
+                    name: "Via RADIUS".to_string(),
+                    cause: "not parsable as f64".to_string(),
+                    error: Some(error),
+                })
+            })
+            .transpose()?;
+        let via = ViaCoords {
+            lat: coords.lat,
+            lon: coords.lon,
+            radius,
+        };
+        via.radius_m("Via RADIUS")?;
+        Ok(via)
+    }
+}
+
+impl ViaCoords {
+    /// The radius or the default one, checked here as well because requests and waypoints files
+    /// are not parsed from the arguments
+    fn radius_m(&self, name: &str) -> Result<f32, RouterRunnerError> {
+        match self.radius {
+            Some(radius) if radius.is_nan() || radius <= 0. => Err(RouterRunnerError::Coords {
+                name: name.to_string(),
+                cause: "must be greater than 0".to_string(),
+                error: None,
+            }),
+            radius => Ok(radius.unwrap_or(VIA_DEFAULT_RADIUS_M)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct PreviousRoute(Vec<(f32, f32)>);
+
+impl FromStr for PreviousRoute {
+    type Err = RouterRunnerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let coords = TrackMatcher::read_gpx(&PathBuf::from(s))
+            .map_err(|error| RouterRunnerError::TrackMatch { error })?;
+        Ok(PreviousRoute(coords))
+    }
+}
+
 impl FromStr for DataSource {
     type Err = RouterRunnerError;
 
@@ -147,14 +454,30 @@ impl FromStr for DataDestination {
             if ext == "json" {
                 return Ok(DataDestination::Json { file });
             } else if ext == "gpx" {
-                return Ok(DataDestination::Gpx { file });
+                return Ok(DataDestination::Gpx {
+                    file,
+                    options: GpxOptions::default(),
+                });
+            } else if ext == "kml" {
+                return Ok(DataDestination::Kml { file });
+            } else if ext == "fit" {
+                return Ok(DataDestination::Fit { file });
+            } else if ext == "html" {
+                return Ok(DataDestination::Html { file });
+            } else if ext == "png" || ext == "svg" {
+                return Ok(DataDestination::Image {
+                    file,
+                    options: RenderOptions::default(),
+                });
+            } else if ext == "csv" || ext == "tsv" {
+                return Ok(DataDestination::Stats { file });
             }
         }
         Err(RouterRunnerError::OutputFileFormatIncorrect { filename: file })
     }
 }
 
-#[derive(Clone, Subcommand, Debug, Serialize, Deserialize)]
+#[derive(Clone, Subcommand, Debug, Serialize, Deserialize, JsonSchema)]
 #[arg()]
 pub enum RoutingMode {
     /// Generate a route between specific Start coordinates and specific Finish coordinates
@@ -166,33 +489,163 @@ pub enum RoutingMode {
         #[arg(long, value_name = "LAT,LON")]
         /// Finish coordinates in the format of 11.12543,32.12432
         finish: Coords,
+
+        #[arg(long, value_name = "LAT,LON[,RADIUS]")]
+        /// Via point the route has to pass through, in the order given, in the format of
+        /// 11.12543,32.12432 with an optional radius in meters greater than 0, for example
+        /// 11.12543,32.12432,200. Can be repeated
+        #[serde(default)]
+        via: Vec<ViaCoords>,
+
+        #[arg(long)]
+        /// Reorder via points to minimize the total distance instead of visiting them in the
+        /// order given
+        #[serde(default)]
+        optimize_order: bool,
     },
     /// Generate a route that starts and finishes at the same point and loops in a direction
-    /// for a specified distance
+    /// for a specified distance or duration
     RoundTrip {
         #[arg(long, value_name = "LAT,LON")]
         /// Start and finish coordinates in the format of 11.12543,32.12432
         start_finish: Coords,
 
         #[arg(long, value_name = "DEGREES")]
-        /// Degrees, where: North: 0°, East: 90°, South: 180°, West: 270°
-        bearing: f32,
+        /// Degrees, where: North: 0°, East: 90°, South: 180°, West: 270°. When omitted, loops in
+        /// all directions are generated and the best ones are returned
+        #[serde(default)]
+        bearing: Option<f32>,
+
+        #[arg(
+            long,
+            value_name = "DISTANCE",
+            value_parser = units::parse_distance_m,
+            required_unless_present = "duration",
+            conflicts_with = "duration"
+        )]
+        /// Desired trip distance, in meters or with a unit of m, km, mi or ft, like 150mi
+        #[serde(default)]
+        distance: Option<u32>,
+
+        #[arg(long, value_name = "MINUTES")]
+        /// Duration in minutes of the desired trip, used instead of distance
+        #[serde(default)]
+        duration: Option<u32>,
+    },
+    /// Generate a corrected route from the current position off the route that rejoins a
+    /// previously generated route and follows it to the finish
+    Reroute {
+        #[arg(long, value_name = "LAT,LON")]
+        /// Current position in the format of 11.12543,32.12432
+        current: Coords,
 
-        #[arg(long, value_name = "METERS")]
-        /// Distance in meters of the desired trip distance
-        distance: u32,
+        #[arg(long, value_name = "FILE")]
+        /// GPX file with the previously generated route. In server requests the route is given
+        /// as a list of [LAT, LON] coordinates, the same as in the response
+        previous_route: PreviousRoute,
     },
 }
 
+impl RoutingMode {
+    /// A start-finish route between the looked up addresses, or the routing mode of the command
+    /// when there are no addresses
+    #[cfg(feature = "geocoding")]
+    fn from_addresses(
+        routing_mode: Option<&RoutingMode>,
+        waypoints_file: Option<&Path>,
+        address_options: &AddressOptions,
+    ) -> Result<Option<Self>, RouterRunnerError> {
+        let (Some(start), Some(finish)) = (
+            &address_options.start_address,
+            &address_options.finish_address,
+        ) else {
+            return Ok(routing_mode.cloned());
+        };
+        if routing_mode.is_some() || waypoints_file.is_some() {
+            return Err(RouterRunnerError::AddressRoutingMode);
+        }
+        let geocoder = Geocoder::new(&address_options.geocoder_url)
+            .map_err(|error| RouterRunnerError::Geocoder { error })?;
+        let resolve = |address: &str| {
+            geocoder
+                .resolve(address)
+                .map(|(lat, lon)| Coords { lat, lon })
+                .map_err(|error| RouterRunnerError::Geocoder { error })
+        };
+        Ok(Some(RoutingMode::StartFinish {
+            start: resolve(start)?,
+            finish: resolve(finish)?,
+            via: Vec::new(),
+            optimize_order: false,
+        }))
+    }
+
+    /// The routing mode of the command, or a start-finish route through the points of the
+    /// waypoints file
+    fn from_args(
+        routing_mode: Option<&RoutingMode>,
+        waypoints_file: Option<&Path>,
+    ) -> Result<Self, RouterRunnerError> {
+        match (routing_mode, waypoints_file) {
+            (Some(routing_mode), None) => Ok(routing_mode.clone()),
+            (None, Some(waypoints_file)) => {
+                let mut points = WaypointsReader::read(waypoints_file)
+                    .map_err(|error| RouterRunnerError::Waypoints { error })?
+                    .into_iter()
+                    .map(|waypoint| ViaCoords {
+                        lat: waypoint.lat,
+                        lon: waypoint.lon,
+                        radius: waypoint.radius,
+                    });
+                let start = points
+                    .next()
+                    .ok_or(RouterRunnerError::WaypointsRoutingMode)?;
+                let mut via = points.collect::<Vec<_>>();
+                let finish = via.pop().ok_or(RouterRunnerError::WaypointsRoutingMode)?;
+                Ok(RoutingMode::StartFinish {
+                    start: Coords {
+                        lat: start.lat,
+                        lon: start.lon,
+                    },
+                    finish: Coords {
+                        lat: finish.lat,
+                        lon: finish.lon,
+                    },
+                    via,
+                    optimize_order: false,
+                })
+            }
+            _ => Err(RouterRunnerError::WaypointsRoutingMode),
+        }
+    }
+
+    /// Where the route starts, the `when` conditions of the rules are evaluated for this place
+    fn start(&self) -> &Coords {
+        match self {
+            RoutingMode::StartFinish { start, .. } => start,
+            RoutingMode::RoundTrip { start_finish, .. } => start_finish,
+            RoutingMode::Reroute { current, .. } => current,
+        }
+    }
+
+    pub(crate) fn rule_condition_trip(
+        &self,
+        departure: Option<OffsetDateTime>,
+    ) -> RuleConditionTrip {
+        let start = self.start();
+        RuleConditionTrip::new(departure, start.lat, start.lon)
+    }
+}
+
 #[derive(Subcommand)]
 enum CliMode {
     /// Load input data and generate a route
     GenerateRoute {
-        #[arg(long, value_name = "FILE")]
+        #[arg(long, value_name = "FILE", env = cli_config::INPUT_ENV)]
         /// Input file name for json or osm.pbf file
         input: DataSource,
 
-        #[arg(long, value_name = "FILE")]
+        #[arg(long, value_name = "FILE", env = cli_config::CACHE_DIR_ENV)]
         /// Directory to store the generated cache. If specified, it will attempt to read form the
         /// cache, if not found, inout file will be read. If cache is not present, it will be
         /// generated for future
@@ -202,14 +655,24 @@ enum CliMode {
             long,
             value_name = "FILE",
             required = false,
-            default_value = "DataDestination::Stdout"
+            default_value = "DataDestination::Stdout",
+            env = cli_config::OUTPUT_ENV
         )]
-        /// Destination json or gpx file path and name. If not specified, results piped to screen
+        /// Destination json, gpx, kml, fit, html, png, svg, csv or tsv file path and name. If not specified, results piped to screen
         output: DataDestination,
 
-        #[arg(long, value_name = "FILE")]
-        /// JSON file with specified rules for route generation. Default values used if file not
-        /// specified
+        #[command(flatten)]
+        gpx_options: GpxOptions,
+
+        #[command(flatten)]
+        render_options: RenderOptions,
+
+        #[command(flatten)]
+        output_options: OutputOptions,
+
+        #[arg(long, value_name = "FILE", env = cli_config::RULE_FILE_ENV)]
+        /// JSON, TOML or YAML file with specified rules for route generation. Default values used
+        /// if file not specified
         rule_file: Option<PathBuf>,
 
         #[arg(long, value_name = "DIR")]
@@ -217,72 +680,603 @@ enum CliMode {
         /// examining route generation rules. Can be viewed with the 'debug-viewer' binary
         debug_dir: Option<PathBuf>,
 
+        #[command(flatten)]
+        debug_writer_options: DebugWriterOptions,
+
+        #[arg(long)]
+        /// Print every route to stdout as a line of JSON as soon as it is generated, before the
+        /// routes are clustered and ranked. The final result is written to the output file, which
+        /// is required so that it doesn't mix with the streamed routes
+        stream_routes: bool,
+
+        #[command(flatten)]
+        generation_options: GenerationOptions,
+
+        #[arg(long, value_name = "FILE")]
+        /// GPX or GeoJSON file with the start, via and finish points in order, used instead of
+        /// the routing mode. GPX waypoints or route points, and GeoJSON Point features with an
+        /// optional `radius` property are read
+        waypoints_file: Option<PathBuf>,
+
+        #[cfg(feature = "geocoding")]
+        #[command(flatten)]
+        address_options: AddressOptions,
+
         #[command(subcommand)]
         /// Routing mode to generate a route between start and finish coordinates or a round trip
         /// mode to generate a route with the same start and finish coordinates
-        routing_mode: RoutingMode,
+        routing_mode: Option<RoutingMode>,
     },
-    /// Start a server for generating routes
-    StartServer {
+    /// Load input data once and generate the routes of every job in a requests file
+    GenerateBatch {
+        #[arg(long, value_name = "FILE", env = cli_config::INPUT_ENV)]
+        /// Input file name for json or osm.pbf file
+        input: DataSource,
+
+        #[arg(long, value_name = "FILE", env = cli_config::CACHE_DIR_ENV)]
+        /// Directory to store the generated cache. If specified, it will attempt to read form the
+        /// cache, if not found, inout file will be read. If cache is not present, it will be
+        /// generated for future
+        cache_dir: Option<PathBuf>,
+
+        #[arg(long, value_name = "FILE")]
+        /// JSON file with an array of jobs, each with a routing mode and an optional id, rule
+        /// file, output file, seed and time limit
+        requests: PathBuf,
+
+        #[arg(
+            long,
+            value_name = "FILE",
+            required = false,
+            default_value = "DataDestination::Stdout",
+            env = cli_config::OUTPUT_ENV
+        )]
+        /// Destination of the responses of the jobs without an output file of their own, as a
+        /// batch response. If not specified, results piped to screen
+        output: DataDestination,
+
+        #[command(flatten)]
+        output_options: OutputOptions,
+
+        #[arg(long, value_name = "FILE", env = cli_config::RULE_FILE_ENV)]
+        /// JSON, TOML or YAML file with rules for the jobs without a rule file of their own.
+        /// Default values used if file not specified
+        rule_file: Option<PathBuf>,
+
+        #[command(flatten)]
+        generation_options: GenerationOptions,
+    },
+    /// Load input data once and generate the routes of every scenario several times, reporting
+    /// latency percentiles, steps and peak memory per scenario
+    Bench {
+        #[arg(long, value_name = "FILE", env = cli_config::INPUT_ENV)]
+        /// Input file name for json or osm.pbf file
+        input: DataSource,
+
+        #[arg(long, value_name = "FILE", env = cli_config::CACHE_DIR_ENV)]
+        /// Directory to store the generated cache. If specified, it will attempt to read form the
+        /// cache, if not found, inout file will be read. If cache is not present, it will be
+        /// generated for future
+        cache_dir: Option<PathBuf>,
+
+        #[arg(long, value_name = "FILE")]
+        /// JSON file with an array of scenarios in the same format as the `generate-batch`
+        /// requests file, their outputs are ignored
+        scenarios: PathBuf,
+
+        #[arg(long, value_name = "NUMBER", default_value_t = DEFAULT_BENCH_ITERATIONS)]
+        /// Measured runs of every scenario
+        iterations: usize,
+
+        #[arg(long, value_name = "NUMBER", default_value_t = 1)]
+        /// Runs of every scenario before the measured ones, not part of the results
+        warmup: usize,
+
+        #[arg(long, value_name = "FILE", env = cli_config::RULE_FILE_ENV)]
+        /// JSON, TOML or YAML file with rules for the scenarios without a rule file of their own.
+        /// Default values used if file not specified
+        rule_file: Option<PathBuf>,
+
+        #[command(flatten)]
+        generation_options: GenerationOptions,
+
+        #[arg(long, value_name = "FORMAT", default_value = "csv")]
+        /// Print the results as CSV or as JSON
+        format: BenchFormat,
+    },
+    /// Generate routes between random start and finish points within the map data and report
+    /// the panics, errors, stuck generations and routes that break traffic rules, with the seed
+    /// to generate every failed case again
+    FuzzRoutes {
+        #[arg(long, value_name = "FILE", env = cli_config::INPUT_ENV)]
+        /// Input file name for json or osm.pbf file
+        input: DataSource,
+
+        #[arg(long, value_name = "FILE", env = cli_config::CACHE_DIR_ENV)]
+        /// Directory to store the generated cache. If specified, it will attempt to read form the
+        /// cache, if not found, inout file will be read. If cache is not present, it will be
+        /// generated for future
+        cache_dir: Option<PathBuf>,
+
+        #[arg(long, value_name = "NUMBER", default_value_t = DEFAULT_FUZZ_COUNT)]
+        /// Number of random start and finish pairs
+        count: usize,
+
+        #[arg(long, value_name = "NUMBER")]
+        /// Seed of the first case, the following cases use the next numbers. A failed case is
+        /// picked again with its own seed and a count of 1. Random when not given
+        seed: Option<u64>,
+
+        #[arg(
+            long,
+            value_name = "DISTANCE",
+            value_parser = units::parse_distance_m,
+            default_value_t = DEFAULT_FUZZ_MAX_DISTANCE_M
+        )]
+        /// Longest distance between the start and the finish, in meters or with a unit of m, km,
+        /// mi or ft
+        max_distance: u32,
+
+        #[arg(long, value_name = "SECONDS", default_value_t = DEFAULT_FUZZ_TIMEOUT_S)]
+        /// Time limit of the route generation of every case
+        timeout: u64,
+
+        #[arg(long, value_name = "FILE", env = cli_config::RULE_FILE_ENV)]
+        /// JSON, TOML or YAML file with rules. Default values used if file not specified
+        rule_file: Option<PathBuf>,
+    },
+    /// Calculate shortest distances and travel times between all pairs of points
+    Matrix {
+        #[arg(long, value_name = "FILE", env = cli_config::INPUT_ENV)]
+        /// Input file name for json or osm.pbf file
+        input: DataSource,
+
+        #[arg(long, value_name = "FILE", env = cli_config::CACHE_DIR_ENV)]
+        /// Directory to store the generated cache. If specified, it will attempt to read form the
+        /// cache, if not found, inout file will be read. If cache is not present, it will be
+        /// generated for future
+        cache_dir: Option<PathBuf>,
+
+        #[arg(long, value_name = "FILE")]
+        /// CSV file with a point per line in the format of LAT,LON or LAT,LON,NAME
+        points: PathBuf,
+
         #[arg(long, value_name = "FILE")]
+        /// Destination csv or json file path and name. If not specified, csv is piped to screen
+        output: Option<PathBuf>,
+
+        #[arg(long, value_name = "FILE", env = cli_config::RULE_FILE_ENV)]
+        /// JSON, TOML or YAML file with specified rules. Only avoid rules are used. Default values
+        /// used if file not specified
+        rule_file: Option<PathBuf>,
+    },
+    /// Match a recorded GPX track to the map and calculate the same stats as for generated routes
+    Match {
+        #[arg(long, value_name = "FILE", env = cli_config::INPUT_ENV)]
         /// Input file name for json or osm.pbf file
         input: DataSource,
 
+        #[arg(long, value_name = "FILE", env = cli_config::CACHE_DIR_ENV)]
+        /// Directory to store the generated cache. If specified, it will attempt to read form the
+        /// cache, if not found, inout file will be read. If cache is not present, it will be
+        /// generated for future
+        cache_dir: Option<PathBuf>,
+
         #[arg(long, value_name = "FILE")]
+        /// GPX file with the recorded track
+        gpx: PathBuf,
+
+        #[arg(
+            long,
+            value_name = "FILE",
+            required = false,
+            default_value = "DataDestination::Stdout",
+            env = cli_config::OUTPUT_ENV
+        )]
+        /// Destination json, gpx, kml, fit, html, png, svg, csv or tsv file path and name. If not specified, results piped to screen
+        output: DataDestination,
+
+        #[command(flatten)]
+        gpx_options: GpxOptions,
+
+        #[command(flatten)]
+        render_options: RenderOptions,
+
+        #[command(flatten)]
+        output_options: OutputOptions,
+
+        #[arg(long, value_name = "FILE", env = cli_config::RULE_FILE_ENV)]
+        /// JSON, TOML or YAML file with specified rules used for the score. Default values used if
+        /// file not specified
+        rule_file: Option<PathBuf>,
+    },
+    /// Start a server for generating routes
+    #[cfg(not(target_arch = "wasm32"))]
+    StartServer {
+        #[arg(long, value_name = "FILE", env = cli_config::INPUT_ENV)]
+        /// Input file name for json or osm.pbf file
+        input: DataSource,
+
+        #[arg(long, value_name = "FILE", env = cli_config::CACHE_DIR_ENV)]
         /// Directory to store the generated cache. If specified, it will attempt to read form the
         /// cache, if not found, inout file will be read. If cache is not present, it will be
         /// generated for future
         cache_dir: Option<PathBuf>,
 
-        #[arg(long, value_name = "NAME")]
+        #[arg(long, value_name = "NAME", env = cli_config::SOCKET_NAME_ENV)]
         /// Socket name in advanced cases where several servers are required to be running at the same time
         socket_name: Option<String>,
+
+        #[arg(long, value_name = "NUMBER")]
+        /// Number of route requests generated at the same time, further requests wait for one of
+        /// them to finish. Defaults to the number of CPU threads
+        workers: Option<usize>,
+
+        #[arg(long, value_name = "NUMBER", default_value_t = DEFAULT_QUEUE_DEPTH)]
+        /// Number of route requests that can wait for a worker, further requests are rejected
+        queue_depth: usize,
+
+        #[arg(long, value_name = "HOST:PORT", conflicts_with = "socket_name")]
+        /// Listen for clients over TCP on this address instead of the local socket, so clients can
+        /// connect from other machines
+        listen: Option<String>,
+
+        #[arg(long, value_name = "HOST:PORT")]
+        /// Also accept requests over WebSocket on this address, with progress and routes sent back
+        /// as they are generated. Requires the `websocket` feature
+        websocket: Option<String>,
+
+        #[arg(long, value_name = "SECONDS", default_value_t = DEFAULT_DRAIN_TIMEOUT_S)]
+        /// On SIGINT or SIGTERM, time given to requests in progress to finish before they are
+        /// cancelled and the server exits
+        drain_timeout: u64,
+
+        #[arg(long, value_name = "NUMBER")]
+        /// Keep the routes of this many recent requests and answer identical requests from them
+        /// right away instead of generating the routes again. Disabled if not specified
+        response_cache: Option<usize>,
+
+        #[arg(
+            long,
+            value_name = "TOKEN",
+            env = "RIDI_ROUTER_TOKEN",
+            hide_env_values = true
+        )]
+        /// Shared secret clients have to send with every message, requests without it are
+        /// rejected. Recommended when listening over TCP or WebSocket
+        token: Option<String>,
+
+        #[arg(long, value_name = "NUMBER")]
+        /// Route requests each client can send per minute, further requests are rejected until
+        /// older ones are a minute old. Clients are told apart by the `client_id` of the message
+        /// or their address. Not limited if not specified
+        rate_limit: Option<usize>,
+
+        #[arg(long, value_name = "FILE", env = cli_config::RULE_FILE_ENV)]
+        /// JSON, TOML or YAML file with the rules used for requests that don't send complete
+        /// rules, their rule overrides are merged onto these. Default values used if file not
+        /// specified
+        rule_file: Option<PathBuf>,
+
+        #[arg(long, value_name = "FIELD", value_delimiter = ',')]
+        /// Rules requests can override, as dot separated paths like `basic.prefer_same_road`,
+        /// including the fields within them. Requests with complete rules are rejected when
+        /// specified, otherwise any rule can be overridden
+        overridable_rules: Option<Vec<String>>,
+
+        #[arg(long, value_name = "NUMBER", default_value_t = DEFAULT_HISTORY_SIZE)]
+        /// Number of recently finished requests kept with their responses, for clients asking
+        /// for the status of a request
+        history_size: usize,
+
+        #[arg(long, value_name = "FILE")]
+        /// Append a JSON line for every finished request to this file, with the id, a hash of the
+        /// parameters, the duration and a summary of the result
+        history_log: Option<PathBuf>,
+
+        #[arg(long)]
+        /// Only estimate the size of the graph and the cache, the memory needed and the time the
+        /// import takes from a sample of the PBF file, without starting the server
+        dry_run: bool,
+
+        #[command(flatten)]
+        daemon_options: DaemonOptions,
     },
     /// Start a client to connect to a running server to generate a route
+    #[cfg(not(target_arch = "wasm32"))]
     StartClient {
         #[arg(
             long,
             value_name = "FILE",
             required = false,
-            default_value = "DataDestination::Stdout"
+            default_value = "DataDestination::Stdout",
+            env = cli_config::OUTPUT_ENV
         )]
-        /// Destination json or gpx file path and name. If not specified, results piped to screen
+        /// Destination json, gpx, kml, fit, html, png, svg, csv or tsv file path and name. If not specified, results piped to screen
         output: DataDestination,
 
+        #[command(flatten)]
+        gpx_options: GpxOptions,
+
+        #[command(flatten)]
+        output_options: OutputOptions,
+
         #[command(subcommand)]
         /// Routing mode to generate a route between start and finish coordinates or a round trip
-        /// mode to generate a route with the same start and finish coordinates
-        routing_mode: RoutingMode,
+        /// mode to generate a route with the same start and finish coordinates. Not used with
+        /// `--requests`
+        routing_mode: Option<RoutingMode>,
+
+        #[arg(long, value_name = "FILE")]
+        /// JSON file with an array of request messages, sent to the server as a single batch
+        /// instead of a request for the routing mode. The response holds a response for every
+        /// request
+        requests: Option<PathBuf>,
+
+        #[arg(long, requires = "requests")]
+        /// Let the server generate the requests from `--requests` at the same time
+        parallel: bool,
 
-        #[arg(long, value_name = "NAME")]
+        #[arg(long, value_name = "NAME", env = cli_config::SOCKET_NAME_ENV)]
         /// Socket name in advanced cases where several servers are required to be running at the same time
         socket_name: Option<String>,
 
-        #[arg(long, value_name = "FILE")]
-        /// JSON file with specified rules for route generation. Default values used if file not
-        /// specified
+        #[arg(long, value_name = "HOST:PORT", conflicts_with = "socket_name")]
+        /// Connect to a server listening over TCP on this address instead of the local socket
+        connect: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "TOKEN",
+            env = "RIDI_ROUTER_TOKEN",
+            hide_env_values = true
+        )]
+        /// Token the server was started with
+        token: Option<String>,
+
+        #[arg(long, value_name = "FILE", env = cli_config::RULE_FILE_ENV)]
+        /// JSON, TOML or YAML file with specified rules for route generation. Default values used
+        /// if file not specified
         rule_file: Option<PathBuf>,
 
+        #[arg(long, value_name = "FILE", conflicts_with = "rule_file")]
+        /// JSON, TOML or YAML file with partial rules merged onto the rules the server was started
+        /// with, instead of sending complete rules
+        rule_overrides: Option<PathBuf>,
+
         #[arg(long, value_name = "IDENTIFIER")]
         /// Route request id to track individual requests in flight
         route_req_id: Option<String>,
+
+        #[command(flatten)]
+        generation_options: GenerationOptions,
+
+        #[command(flatten)]
+        retry_options: RetryOptions,
+
+        #[arg(long, value_enum)]
+        /// Order in which the server starts requests waiting for a worker. Use `batch` for bulk
+        /// jobs so requests someone is waiting for go first. Applies to all requests of a
+        /// requests file when specified, normal is used for a single request when not specified
+        priority: Option<Priority>,
+
+        #[arg(long)]
+        /// Ask the server to send responses in length prefixed chunks with a checksum, for
+        /// clients that parse the response as it comes in
+        chunked_responses: bool,
+
+        #[arg(long)]
+        /// Show generation progress reported by the server
+        progress: bool,
     },
-    /// Create an input data cache
-    PrepCache {
+    /// Connect to a running server and send routing requests typed one after the other, tweaking
+    /// the rule overrides, seed and time limit in between. Type `help` for the commands
+    #[cfg(not(target_arch = "wasm32"))]
+    Repl {
+        #[arg(long, value_name = "NAME", env = cli_config::SOCKET_NAME_ENV)]
+        /// Socket name in advanced cases where several servers are required to be running at the same time
+        socket_name: Option<String>,
+
+        #[arg(long, value_name = "HOST:PORT", conflicts_with = "socket_name")]
+        /// Connect to a server listening over TCP on this address instead of the local socket
+        connect: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "TOKEN",
+            env = "RIDI_ROUTER_TOKEN",
+            hide_env_values = true
+        )]
+        /// Token the server was started with
+        token: Option<String>,
+
         #[arg(long, value_name = "FILE")]
-        /// Input file name for json or osm.pbf file
-        input: DataSource,
+        /// JSON, TOML or YAML file with partial rules to start with, merged onto the rules the
+        /// server was started with
+        rule_overrides: Option<PathBuf>,
 
-        #[arg(long, value_name = "DIR")]
-        /// Directory to store the generated cache
-        cache_dir: PathBuf,
+        #[arg(long, value_enum)]
+        /// Units of the distances in the route tables, metric when not given
+        units: Option<units::Units>,
+
+        #[command(flatten)]
+        retry_options: RetryOptions,
+
+        #[arg(long)]
+        /// Show generation progress reported by the server
+        progress: bool,
     },
-    /// Run Debug viewer
-    #[cfg(feature = "debug-viewer")]
-    DebugViewer {
-        #[arg(long, value_name = "DIR")]
-        /// Load a directory with debug files generated when generating a route
+    /// Cancel a route request in flight on a running server
+    #[cfg(not(target_arch = "wasm32"))]
+    CancelRequest {
+        #[arg(long, value_name = "NAME", env = cli_config::SOCKET_NAME_ENV)]
+        /// Socket name in advanced cases where several servers are required to be running at the same time
+        socket_name: Option<String>,
+
+        #[arg(long, value_name = "HOST:PORT", conflicts_with = "socket_name")]
+        /// Connect to a server listening over TCP on this address instead of the local socket
+        connect: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "TOKEN",
+            env = "RIDI_ROUTER_TOKEN",
+            hide_env_values = true
+        )]
+        /// Token the server was started with
+        token: Option<String>,
+
+        #[arg(long, value_name = "IDENTIFIER")]
+        /// Route request id used when the request was started
+        route_req_id: String,
+
+        #[command(flatten)]
+        retry_options: RetryOptions,
+    },
+    /// Show whether a route request is in progress on a running server, or its response once it
+    /// is finished
+    #[cfg(not(target_arch = "wasm32"))]
+    RequestStatus {
+        #[arg(long, value_name = "NAME", env = cli_config::SOCKET_NAME_ENV)]
+        /// Socket name in advanced cases where several servers are required to be running at the same time
+        socket_name: Option<String>,
+
+        #[arg(long, value_name = "HOST:PORT", conflicts_with = "socket_name")]
+        /// Connect to a server listening over TCP on this address instead of the local socket
+        connect: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "TOKEN",
+            env = "RIDI_ROUTER_TOKEN",
+            hide_env_values = true
+        )]
+        /// Token the server was started with
+        token: Option<String>,
+
+        #[arg(long, value_name = "IDENTIFIER")]
+        /// Route request id used when the request was started
+        route_req_id: String,
+
+        #[command(flatten)]
+        retry_options: RetryOptions,
+    },
+    /// Create an input data cache
+    PrepCache {
+        #[arg(long, value_name = "FILE", env = cli_config::INPUT_ENV)]
+        /// Input file name for json or osm.pbf file
+        input: DataSource,
+
+        #[arg(long, value_name = "DIR", env = cli_config::CACHE_DIR_ENV)]
+        /// Directory to store the generated cache
+        cache_dir: PathBuf,
+
+        #[arg(long)]
+        /// Only estimate the size of the graph and the cache, the memory needed and the time the
+        /// import takes from a sample of the PBF file, without importing it
+        dry_run: bool,
+    },
+    /// Load input data and print what was read: counts, bounding box, road length per highway
+    /// class and surface, one way share, turn restrictions and a memory estimate
+    GraphInfo {
+        #[arg(long, value_name = "FILE", env = cli_config::INPUT_ENV)]
+        /// Input file name for json or osm.pbf file
+        input: DataSource,
+
+        #[arg(long, value_name = "FILE", env = cli_config::CACHE_DIR_ENV)]
+        /// Directory to store the generated cache. If specified, it will attempt to read form the
+        /// cache, if not found, inout file will be read. If cache is not present, it will be
+        /// generated for future
+        cache_dir: Option<PathBuf>,
+
+        #[arg(long, value_name = "FORMAT", default_value = "text")]
+        /// Print the info as text or as JSON
+        format: GraphInfoFormat,
+    },
+    /// Check a rule file and print the effective rules, with the extended rules and defaults
+    /// filled in
+    RulesCheck {
+        #[arg(long, value_name = "FILE", env = cli_config::RULE_FILE_ENV)]
+        /// JSON, TOML or YAML file with the rules to check
+        rule_file: PathBuf,
+    },
+    /// Generate a single itinerary from the debug files of an earlier route generation again, with
+    /// trace logging, to debug its steps
+    Replay {
+        #[arg(long, value_name = "FILE", env = cli_config::INPUT_ENV)]
+        /// Input file name for json or osm.pbf file, the same as for the route generation
+        input: DataSource,
+
+        #[arg(long, value_name = "DIR", env = cli_config::CACHE_DIR_ENV)]
+        /// Directory to read the map data cache from or store it to
+        cache_dir: Option<PathBuf>,
+
+        #[arg(long, value_name = "DIR")]
+        /// Directory with the debug files of the route generation
+        debug_dir: PathBuf,
+
+        #[arg(long, value_name = "ID")]
+        /// Id of the itinerary to generate, as shown in the debug viewer
+        itinerary_id: String,
+
+        #[arg(
+            long,
+            value_name = "FILE",
+            required = false,
+            default_value = "DataDestination::Stdout",
+            env = cli_config::OUTPUT_ENV
+        )]
+        /// Destination file for the route of the itinerary. If not specified, results piped to
+        /// screen
+        output: DataDestination,
+
+        #[command(flatten)]
+        output_options: OutputOptions,
+    },
+    /// Summarize the fork choice weights of the debug files per weight calculation: how often it
+    /// ruled out a choice, the average weight it gave and how well it predicts the choice taken
+    DebugStats {
+        #[arg(long, value_name = "DIR")]
+        /// Directory with debug files generated when generating a route
+        debug_dir: PathBuf,
+
+        #[arg(long, value_name = "FORMAT", default_value = "csv")]
+        /// Print the stats as CSV or as JSON
+        format: DebugStatsFormat,
+    },
+    /// Run Debug viewer
+    #[cfg(feature = "debug-viewer")]
+    DebugViewer {
+        #[arg(long, value_name = "DIR", required = true)]
+        /// Load a directory with debug files generated when generating a route. Given twice, both
+        /// runs are loaded to compare them side by side
+        debug_dir: Vec<PathBuf>,
+
+        #[arg(long, value_name = "DIR")]
+        /// Serve the UI from this directory instead of the built in UI, for developing the UI
+        ui_dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        serve_options: crate::debug::viewer::auth::DebugViewerServeOptions,
+    },
+    /// Query the debug files and print the result, without starting the debug viewer
+    #[cfg(feature = "debug-viewer")]
+    DebugQuery {
+        #[arg(long, value_name = "DIR")]
+        /// Directory with debug files generated when generating a route
         debug_dir: PathBuf,
+
+        #[arg(long, value_name = "SQL", required_unless_present = "report")]
+        /// DuckDB SQL query, the debug streams are tables like DebugStreamSteps
+        sql: Option<String>,
+
+        #[arg(long, value_name = "REPORT", conflicts_with = "sql")]
+        /// Run a predefined report instead of a query
+        report: Option<crate::debug::viewer::query::DebugReport>,
+
+        #[arg(long, value_name = "FORMAT", default_value = "table")]
+        /// Print the result as a table or as JSON
+        format: crate::debug::viewer::query::DebugQueryFormat,
     },
     /// Generate JSON schema file for rule files
     #[cfg(feature = "rule-schema-writer")]
@@ -291,18 +1285,148 @@ enum CliMode {
         /// Destination location of the JSON schema file for the rule file
         destination: PathBuf,
     },
+    /// Generate JSON schema file for the request and response messages of the server
+    #[cfg(feature = "rule-schema-writer")]
+    MessageSchemaWrite {
+        #[arg(long, value_name = "FILE")]
+        /// Destination location of the JSON schema file for the messages
+        destination: PathBuf,
+    },
+}
+
+/// How long the client waits for the server and how often it tries again
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, Args)]
+pub struct RetryOptions {
+    #[arg(long, value_name = "SECONDS")]
+    /// Time to wait for the final response from the server, waits until the server responds if
+    /// not specified
+    response_timeout: Option<u64>,
+
+    #[arg(long, value_name = "NUMBER", default_value_t = 0)]
+    /// Times to send the request again when the server is not running, busy or does not respond
+    /// in time, waiting longer before every retry
+    retries: u32,
+}
+
+/// How the server runs as a service
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, Args)]
+pub struct DaemonOptions {
+    #[arg(long)]
+    /// Detach from the terminal and run in the background. The command exits once the server is
+    /// ready to accept connections, with an error when it failed to start. Unix only
+    daemonize: bool,
+
+    #[arg(long, value_name = "FILE")]
+    /// Write the process id of the server to this file, removed when the server stops. The
+    /// server does not start when the file names a process that is still running
+    pidfile: Option<PathBuf>,
+
+    #[arg(long, value_name = "MODE", value_parser = daemon::parse_mode, conflicts_with = "listen")]
+    /// Octal permissions of the socket, like 0660. The server then listens on a socket file in
+    /// /tmp instead of a namespaced socket. Unix only
+    socket_mode: Option<u32>,
+
+    #[arg(long, value_name = "GROUP", conflicts_with = "listen")]
+    /// Group name or id the socket belongs to, so its members can connect when the socket mode
+    /// allows the group. The server then listens on a socket file in /tmp. Unix only
+    socket_group: Option<String>,
+}
+
+/// What a route generation was started with, written to the debug dir to replay its itineraries
+#[derive(Serialize, Deserialize)]
+struct ReplayInput {
+    routing_mode: RoutingMode,
+    rules: RouterRules,
+    seed: Option<u64>,
+}
+
+/// Job of a `generate-batch` requests file. Paths are relative to the requests file
+#[derive(Deserialize)]
+struct BatchJob {
+    /// Identifies the job in the logs and the batch response, its index in the file when not given
+    #[serde(default)]
+    id: Option<String>,
+    routing_mode: RoutingMode,
+    /// Rules of the job, the rule file of the command is used when not given
+    #[serde(default)]
+    rule_file: Option<PathBuf>,
+    /// File the routes of the job are written to, in the format of its extension. The response is
+    /// part of the batch response when not given
+    #[serde(default)]
+    output: Option<PathBuf>,
+    #[serde(default)]
+    seed: Option<u64>,
+    #[serde(default)]
+    timeout_s: Option<u64>,
+}
+
+/// Batch job with its rules read and paths resolved
+struct PreparedBatchJob {
+    id: String,
+    routing_mode: RoutingMode,
+    rules: RouterRules,
+    output: Option<DataDestination>,
+    seed: Option<u64>,
+    timeout_s: Option<u64>,
+}
+
+/// Options for a single route generation run
+#[derive(Debug, Clone, Args)]
+pub struct GenerationOptions {
+    #[arg(long, value_name = "SECONDS")]
+    /// Time limit for route generation. When reached, the routes finished by then are returned
+    timeout: Option<u64>,
+
+    #[arg(long, value_name = "NUMBER")]
+    /// Seed for breaking ties between equally weighted choices. Runs with the same inputs and seed
+    /// generate the same routes, as long as no time limit is reached
+    seed: Option<u64>,
+
+    #[arg(long, value_name = "TIME", value_parser = rule_conditions::parse_departure_time)]
+    /// Departure time like 2025-06-21T18:30:00+03:00, the `when` conditions of the rules are
+    /// evaluated for it. The current time when not given
+    departure_time: Option<OffsetDateTime>,
 }
 
 pub struct RouterRunner;
 
+/// Rules the server was started with, the `when` conditions are evaluated for every request
+struct ServerRules {
+    rules: serde_json::Value,
+    overridable: Option<Vec<String>>,
+}
+
+/// Optional settings of a single route generation, everything not set is left unlimited or off
+#[derive(Default)]
+pub(crate) struct GenerateRouteOptions {
+    pub timeout: Option<Duration>,
+    pub cancellation: Option<CancellationToken>,
+    pub progress: Option<ProgressCallback>,
+    pub route_callback: Option<RouteCallback>,
+    pub counters: Option<Arc<GenerationCounters>>,
+    pub seed: Option<u64>,
+}
+
 impl RouterRunner {
     #[tracing::instrument(skip_all)]
-    fn generate_route(
+    pub(crate) fn generate_route(
         routing_mode: &RoutingMode,
         rules: RouterRules,
+        options: GenerateRouteOptions,
     ) -> Result<Vec<RouteWithStats>, RouterRunnerError> {
+        let GenerateRouteOptions {
+            timeout,
+            cancellation,
+            progress,
+            route_callback,
+            counters,
+            seed,
+        } = options;
+        let deadline = timeout.map(|timeout| web_time::Instant::now() + timeout);
         let (start_lat, start_lon, finish_lat, finish_lon) = match routing_mode {
-            RoutingMode::StartFinish { start, finish } => {
+            RoutingMode::StartFinish { start, finish, .. } => {
                 (start.lat, start.lon, finish.lat, finish.lon)
             }
             RoutingMode::RoundTrip { start_finish, .. } => (
@@ -311,6 +1435,14 @@ impl RouterRunner {
                 start_finish.lat,
                 start_finish.lon,
             ),
+            RoutingMode::Reroute {
+                current,
+                previous_route,
+            } => {
+                return Rerouter::reroute((current.lat, current.lon), &previous_route.0, &rules)
+                    .map(|route| vec![route])
+                    .map_err(|error| RouterRunnerError::Reroute { error })
+            }
         };
         let start = MapDataGraph::get()
             .get_closest_to_coords(
@@ -340,32 +1472,592 @@ impl RouterRunner {
 
         trace!("Finish point {finish}");
 
-        let round_trip = if let RoutingMode::RoundTrip {
-            bearing, distance, ..
+        let round_trip = match routing_mode {
+            RoutingMode::RoundTrip {
+                bearing,
+                distance: Some(distance),
+                ..
+            } => Some((*bearing, *distance)),
+            RoutingMode::RoundTrip {
+                bearing,
+                duration: Some(duration),
+                ..
+            } => Some((
+                *bearing,
+                round_trip_duration_distance_m(&start, *duration, &rules),
+            )),
+            RoutingMode::RoundTrip { .. } => return Err(RouterRunnerError::RoundTripLength),
+            RoutingMode::StartFinish { .. } | RoutingMode::Reroute { .. } => None,
+        };
+        let vias = RouterRunner::resolve_vias(routing_mode, &rules, &start, &finish)?;
+        let route_generator = Generator::new(start, finish, vias, round_trip, rules)
+            .with_deadline(deadline)
+            .with_cancellation(cancellation)
+            .with_progress(progress)
+            .with_route_callback(route_callback)
+            .with_counters(counters)
+            .with_seed(seed);
+        route_generator
+            .generate_routes()
+            .map_err(|error| RouterRunnerError::GenerateRoute { error })
+    }
+
+    /// Via points of the routing mode in the map data, in the order they are visited
+    fn resolve_vias(
+        routing_mode: &RoutingMode,
+        rules: &RouterRules,
+        start: &MapDataPointRef,
+        finish: &MapDataPointRef,
+    ) -> Result<Vec<ItineraryVia>, RouterRunnerError> {
+        if let RoutingMode::StartFinish {
+            via,
+            optimize_order,
+            ..
         } = routing_mode
         {
-            Some((*bearing, *distance))
+            let vias = via
+                .iter()
+                .enumerate()
+                .map(|(idx, via)| {
+                    let radius = via.radius_m(&format!("Via point {} radius", idx + 1))?;
+                    let point = MapDataGraph::get()
+                        .get_closest_to_coords(
+                            via.lat,
+                            via.lon,
+                            rules,
+                            false,
+                            Some(&WP_LOOKUP_ALLOWED_HWS),
+                        )
+                        .ok_or(RouterRunnerError::PointNotFound {
+                            point: format!("Via point {}", idx + 1),
+                        })?;
+                    trace!("Via point {point}");
+                    Ok(ItineraryVia { point, radius })
+                })
+                .collect::<Result<Vec<_>, RouterRunnerError>>()?;
+            if *optimize_order {
+                Ok(optimize_via_order(start, finish, vias))
+            } else {
+                Ok(vias)
+            }
         } else {
-            None
+            Ok(Vec::new())
+        }
+    }
+
+    fn create_response(
+        id: String,
+        route_result: Result<Vec<RouteWithStats>, RouterRunnerError>,
+        output_detail: OutputDetail,
+        summary: Option<GenerationSummary>,
+    ) -> ResponseMessage {
+        ResponseMessage {
+            id,
+            result: route_result.map_or_else(
+                |error| RouterResult::Error {
+                    message: format!("Error generating route {:?}", error),
+                },
+                |routes| RouterResult::Ok {
+                    routes: routes
+                        .iter()
+                        .map(|route| RouterRunner::create_route_message(route, output_detail))
+                        .collect(),
+                    summary,
+                },
+            ),
+        }
+    }
+
+    fn create_route_message(route: &RouteWithStats, output_detail: OutputDetail) -> RouteMessage {
+        RouteMessage {
+            coords: route
+                .route
+                .clone()
+                .into_iter()
+                .map(|segment| {
+                    (
+                        segment.get_end_point().borrow().lat,
+                        segment.get_end_point().borrow().lon,
+                    )
+                })
+                .collect(),
+            stats: route.stats.clone(),
+            segments: (output_detail == OutputDetail::Full)
+                .then(|| route.route.get_segment_details()),
+        }
+    }
+
+    fn in_flight_requests() -> &'static Mutex<HashMap<String, CancellationToken>> {
+        static IN_FLIGHT_REQUESTS: OnceLock<Mutex<HashMap<String, CancellationToken>>> =
+            OnceLock::new();
+        IN_FLIGHT_REQUESTS.get_or_init(|| Mutex::new(HashMap::new()))
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn worker_pool() -> &'static OnceLock<WorkerPool> {
+        static WORKER_POOL: OnceLock<WorkerPool> = OnceLock::new();
+        &WORKER_POOL
+    }
+
+    /// Worker pool set up by the server, or one with the defaults when not set up
+    #[cfg(not(target_arch = "wasm32"))]
+    fn shared_worker_pool() -> &'static WorkerPool {
+        RouterRunner::worker_pool()
+            .get_or_init(|| WorkerPool::new(rayon::current_num_threads(), DEFAULT_QUEUE_DEPTH))
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn server_token() -> &'static OnceLock<String> {
+        static SERVER_TOKEN: OnceLock<String> = OnceLock::new();
+        &SERVER_TOKEN
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn rate_limiter() -> &'static OnceLock<RateLimiter> {
+        static RATE_LIMITER: OnceLock<RateLimiter> = OnceLock::new();
+        &RATE_LIMITER
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn request_history() -> &'static OnceLock<RequestHistory> {
+        static REQUEST_HISTORY: OnceLock<RequestHistory> = OnceLock::new();
+        &REQUEST_HISTORY
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn response_cache() -> &'static OnceLock<ResponseCache> {
+        static RESPONSE_CACHE: OnceLock<ResponseCache> = OnceLock::new();
+        &RESPONSE_CACHE
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn handle_ipc_request(
+        ipc_request: IpcRequest,
+        peer: Option<IpAddr>,
+        send_response: ResponseSender,
+    ) -> ResponseMessage {
+        if let Some(server_token) = RouterRunner::server_token().get() {
+            if !ipc_request
+                .token()
+                .is_some_and(|token| tokens_match(token, server_token))
+            {
+                warn!(
+                    req_id = ipc_request.id(),
+                    "Request with invalid token rejected"
+                );
+                return ResponseMessage {
+                    id: ipc_request.id().to_string(),
+                    result: RouterResult::Error {
+                        message: String::from("Invalid or missing token"),
+                    },
+                };
+            }
+        }
+
+        if let Some(rate_limiter) = RouterRunner::rate_limiter().get() {
+            let requests = match &ipc_request {
+                IpcRequest::Cancel(_) | IpcRequest::Status(_) => 0,
+                IpcRequest::Batch(batch_message) => batch_message.batch.len(),
+                IpcRequest::Route(_) => 1,
+            };
+            let client = RouterRunner::rate_limit_client(peer, ipc_request.client_id());
+            if let Err(retry_after) = rate_limiter.check(&client, requests) {
+                let retry_after_s = retry_after.as_secs_f64().ceil() as u64;
+                warn!(
+                    req_id = ipc_request.id(),
+                    client, retry_after_s, "Request rate limited"
+                );
+                return ResponseMessage {
+                    id: ipc_request.id().to_string(),
+                    result: RouterResult::RateLimited { retry_after_s },
+                };
+            }
+        }
+
+        match ipc_request {
+            IpcRequest::Route(request_message) => {
+                RouterRunner::handle_queued_request(*request_message, send_response)
+            }
+            IpcRequest::Batch(batch_message) => {
+                let responses = if batch_message.parallel {
+                    // at most as many threads as there are workers, each one takes the next
+                    // request when done, so a large batch doesn't fill up the queue
+                    let threads = RouterRunner::shared_worker_pool()
+                        .workers()
+                        .min(batch_message.batch.len());
+                    let requests = Mutex::new(batch_message.batch.into_iter().enumerate());
+                    let mut responses = std::thread::scope(|scope| {
+                        let handles = (0..threads)
+                            .map(|_| {
+                                let send_response = send_response.clone();
+                                let requests = &requests;
+                                scope.spawn(move || {
+                                    let mut responses = Vec::new();
+                                    loop {
+                                        let Some((idx, request_message)) = requests
+                                            .lock()
+                                            .unwrap_or_else(|error| error.into_inner())
+                                            .next()
+                                        else {
+                                            break;
+                                        };
+                                        responses.push((
+                                            idx,
+                                            RouterRunner::handle_queued_request(
+                                                request_message,
+                                                send_response.clone(),
+                                            ),
+                                        ));
+                                    }
+                                    responses
+                                })
+                            })
+                            .collect::<Vec<_>>();
+                        handles
+                            .into_iter()
+                            .flat_map(|handle| {
+                                // panics in route generation are already caught
+                                handle.join().expect("batch request thread panicked")
+                            })
+                            .collect::<Vec<_>>()
+                    });
+                    responses.sort_by_key(|(idx, _)| *idx);
+                    responses
+                        .into_iter()
+                        .map(|(_, response)| response)
+                        .collect()
+                } else {
+                    batch_message
+                        .batch
+                        .into_iter()
+                        .map(|request_message| {
+                            RouterRunner::handle_queued_request(
+                                request_message,
+                                send_response.clone(),
+                            )
+                        })
+                        .collect()
+                };
+                ResponseMessage {
+                    id: batch_message.id,
+                    result: RouterResult::Batch { responses },
+                }
+            }
+            IpcRequest::Cancel(cancel_message) => {
+                RouterRunner::handle_cancel(cancel_message.cancel)
+            }
+            IpcRequest::Status(status_message) => {
+                RouterRunner::handle_status(status_message.status)
+            }
+        }
+    }
+
+    /// Client a request counts towards in rate limiting. Remote clients are told apart by their
+    /// address, a `client_id` they send can't get them a limit of their own. It only tells apart
+    /// local socket clients, which all share one limit without it
+    #[cfg(not(target_arch = "wasm32"))]
+    fn rate_limit_client(peer: Option<IpAddr>, client_id: Option<&str>) -> String {
+        match (peer, client_id) {
+            (Some(peer), _) => peer.to_string(),
+            (None, Some(client_id)) => client_id.to_string(),
+            (None, None) => "local".to_string(),
+        }
+    }
+
+    /// Keeps track of the request in the request history while it is handled
+    #[cfg(not(target_arch = "wasm32"))]
+    fn handle_queued_request(
+        request_message: RequestMessage,
+        send_response: ResponseSender,
+    ) -> ResponseMessage {
+        let start = Instant::now();
+        let request_history = RouterRunner::request_history().get();
+        let response_cache = RouterRunner::response_cache().get();
+        let params_hash = (request_history.is_some() || response_cache.is_some())
+            .then(|| RouterRunner::request_rules(&request_message).ok())
+            .flatten()
+            .map(|rules| ResponseCache::key(&request_message, &rules));
+
+        if let Some(request_history) = request_history {
+            request_history.start(&request_message.id);
+        }
+        let response = RouterRunner::handle_cached_request(
+            request_message,
+            response_cache.zip(params_hash),
+            send_response,
+        );
+        if let Some(request_history) = request_history {
+            request_history.finish(params_hash, start.elapsed(), &response);
+        }
+        response
+    }
+
+    /// Answers from the response cache when possible, otherwise waits for a free worker before
+    /// generating the route
+    #[cfg(not(target_arch = "wasm32"))]
+    fn handle_cached_request(
+        request_message: RequestMessage,
+        response_cache: Option<(&ResponseCache, u64)>,
+        send_response: ResponseSender,
+    ) -> ResponseMessage {
+        if let Some(routes) =
+            response_cache.and_then(|(response_cache, cache_key)| response_cache.get(cache_key))
+        {
+            info!(req_id = request_message.id, "Response from cache");
+            return ResponseMessage {
+                id: request_message.id,
+                result: RouterResult::Ok {
+                    routes,
+                    summary: None,
+                },
+            };
+        }
+
+        let worker = RouterRunner::shared_worker_pool().acquire(
+            &request_message.id,
+            request_message.priority,
+            |position| {
+                if request_message.progress {
+                    send_response(ResponseMessage {
+                        id: request_message.id.clone(),
+                        result: RouterResult::Queued { position },
+                    });
+                }
+            },
+        );
+        let _worker = match worker {
+            Ok(worker) => worker,
+            Err(error @ WorkerPoolError::Cancelled) => {
+                return ResponseMessage {
+                    id: request_message.id,
+                    result: RouterResult::Error {
+                        message: error.to_string(),
+                    },
+                }
+            }
+            Err(error @ WorkerPoolError::QueueFull { .. }) => {
+                return ResponseMessage {
+                    id: request_message.id,
+                    result: RouterResult::Busy {
+                        message: error.to_string(),
+                    },
+                }
+            }
         };
-        let route_generator = Generator::new(start.clone(), finish.clone(), round_trip, rules);
-        let routes = route_generator
-            .generate_routes()
-            .map_err(|error| RouterRunnerError::GenerateRoute { error })?;
-        Ok(routes)
+        if DRAIN_TIMED_OUT.load(Ordering::SeqCst) {
+            return ResponseMessage {
+                id: request_message.id,
+                result: RouterResult::Busy {
+                    message: String::from("Server is shutting down"),
+                },
+            };
+        }
+        let response = RouterRunner::handle_request(request_message, Some(send_response));
+        if let (Some((response_cache, cache_key)), RouterResult::Ok { routes, .. }) =
+            (response_cache, &response.result)
+        {
+            response_cache.insert(cache_key, routes.clone());
+        }
+        response
     }
 
-    #[tracing::instrument(skip_all)]
-    fn run_dual(
+    #[cfg(not(target_arch = "wasm32"))]
+    fn handle_status(route_req_id: String) -> ResponseMessage {
+        let status = RouterRunner::request_history()
+            .get()
+            .map_or(RequestStatus::Unknown, |request_history| {
+                request_history.status(&route_req_id)
+            });
+        ResponseMessage {
+            id: route_req_id,
+            result: RouterResult::Status { status },
+        }
+    }
+
+    fn handle_cancel(route_req_id: String) -> ResponseMessage {
+        #[cfg(not(target_arch = "wasm32"))]
+        if RouterRunner::worker_pool()
+            .get()
+            .is_some_and(|worker_pool| worker_pool.cancel(&route_req_id))
+        {
+            info!(req_id = route_req_id, "Cancelling queued request");
+            return ResponseMessage {
+                id: route_req_id,
+                result: RouterResult::Ok {
+                    routes: Vec::new(),
+                    summary: None,
+                },
+            };
+        }
+        let cancellation = RouterRunner::in_flight_requests()
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .get(&route_req_id)
+            .cloned();
+        let result = match cancellation {
+            Some(cancellation) => {
+                info!(req_id = route_req_id, "Cancelling request");
+                cancellation.cancel();
+                RouterResult::Ok {
+                    routes: Vec::new(),
+                    summary: None,
+                }
+            }
+            None => RouterResult::Error {
+                message: format!("No request in flight with id {route_req_id}"),
+            },
+        };
+        ResponseMessage {
+            id: route_req_id,
+            result,
+        }
+    }
+
+    fn server_rules() -> &'static OnceLock<ServerRules> {
+        static SERVER_RULES: OnceLock<ServerRules> = OnceLock::new();
+        &SERVER_RULES
+    }
+
+    /// Rules for a request: the complete rules sent with it, or else the server rules, with the
+    /// rule overrides of the request merged in. The `when` conditions are evaluated for the start
+    /// of the route and the departure time of the request
+    fn request_rules(request_message: &RequestMessage) -> Result<RouterRules, RouterRunnerError> {
+        let server_rules = RouterRunner::server_rules().get();
+        let overridable = server_rules.and_then(|server_rules| server_rules.overridable.as_deref());
+        let rules = match &request_message.rules {
+            Some(_) if overridable.is_some() => return Err(RouterRunnerError::CompleteRules),
+            Some(rules) => rules.clone(),
+            None => server_rules.map_or_else(
+                || serde_json::Value::Object(Default::default()),
+                |server_rules| server_rules.rules.clone(),
+            ),
+        };
+        let trip = request_message
+            .routing_mode
+            .rule_condition_trip(request_message.departure_time);
+        let rules = RouterRules::from_value(rules, "request", Some(&trip))
+            .map_err(|error| RouterRunnerError::Rules { error })?;
+        match &request_message.rule_overrides {
+            Some(rule_overrides) => {
+                let mut rule_overrides = rule_overrides.clone();
+                apply_conditions(&mut rule_overrides, Some(&trip))
+                    .map_err(|error| RouterRunnerError::Rules { error })?;
+                rules
+                    .with_overrides(&rule_overrides, overridable)
+                    .map_err(|error| RouterRunnerError::Rules { error })
+            }
+            None => Ok(rules),
+        }
+    }
+
+    pub(crate) fn handle_request(
+        request_message: RequestMessage,
+        send_response: Option<ResponseSender>,
+    ) -> ResponseMessage {
+        let rules = match RouterRunner::request_rules(&request_message) {
+            Ok(rules) => rules,
+            Err(error) => {
+                return ResponseMessage {
+                    id: request_message.id,
+                    result: RouterResult::Error {
+                        message: error.to_string(),
+                    },
+                }
+            }
+        };
+        let progress = send_response
+            .clone()
+            .filter(|_| request_message.progress)
+            .map(|send_response| {
+                let id = request_message.id.clone();
+                Arc::new(move |progress| {
+                    send_response(ResponseMessage {
+                        id: id.clone(),
+                        result: RouterResult::Progress { progress },
+                    })
+                }) as ProgressCallback
+            });
+        let route_callback =
+            send_response
+                .filter(|_| request_message.stream_routes)
+                .map(|send_response| {
+                    let id = request_message.id.clone();
+                    let output_detail = request_message.output_detail;
+                    Arc::new(move |route: RouteWithStats| {
+                        send_response(ResponseMessage {
+                            id: id.clone(),
+                            result: RouterResult::Route {
+                                route: Box::new(RouterRunner::create_route_message(
+                                    &route,
+                                    output_detail,
+                                )),
+                            },
+                        })
+                    }) as RouteCallback
+                });
+        let cancellation = CancellationToken::new();
+        let counters = Arc::new(GenerationCounters::new());
+        RouterRunner::in_flight_requests()
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .insert(request_message.id.clone(), cancellation.clone());
+
+        let route_res = catch_unwind(AssertUnwindSafe(|| {
+            RouterRunner::generate_route(
+                &request_message.routing_mode,
+                rules,
+                GenerateRouteOptions {
+                    timeout: request_message.timeout_s.map(Duration::from_secs),
+                    cancellation: Some(cancellation.clone()),
+                    progress: progress.clone(),
+                    route_callback: route_callback.clone(),
+                    counters: Some(counters.clone()),
+                    seed: request_message.seed,
+                },
+            )
+        }));
+
+        // a newer request may have reused the same id, only remove our own token
+        let mut in_flight_requests = RouterRunner::in_flight_requests()
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        if in_flight_requests
+            .get(&request_message.id)
+            .is_some_and(|registered| registered.is_same(&cancellation))
+        {
+            in_flight_requests.remove(&request_message.id);
+        }
+        drop(in_flight_requests);
+
+        match route_res {
+            Ok(route_res) => RouterRunner::create_response(
+                request_message.id,
+                route_res,
+                request_message.output_detail,
+                Some(counters.summary()),
+            ),
+            Err(error) => ResponseMessage {
+                id: request_message.id,
+                result: RouterResult::Error {
+                    message: format!("Caught panic {:?}", error),
+                },
+            },
+        }
+    }
+
+    #[tracing::instrument]
+    pub(crate) fn load_map_data(
         data_source: &DataSource,
         cache_dir: Option<PathBuf>,
-        routing_mode: &RoutingMode,
-        data_destination: &DataDestination,
-        rule_file: Option<PathBuf>,
-        debug_dir: Option<PathBuf>,
     ) -> Result<()> {
-        DebugWriter::init(debug_dir).context("Failed to init debug writer")?;
-        let rules = RouterRules::read(rule_file).context("Failed to read rules")?;
+        // reading the map data panics on a missing file, it's bad input rather than a crash
+        let (DataSource::JsonFile { file } | DataSource::PbfFile { file }) = data_source;
+        if !file.is_file() {
+            return Err(RouterRunnerError::InputFileInvalid {
+                filename: file.to_string_lossy().to_string(),
+            }
+            .into());
+        }
         let mut data_cache = MapDataCache::init(cache_dir, data_source);
         let cached_map_data = data_cache.read_cache();
         let cached_map_data = match cached_map_data {
@@ -399,57 +2091,623 @@ impl RouterRunner {
             }
         }
 
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(skip_all)]
+    fn run_dual(
+        data_source: &DataSource,
+        cache_dir: Option<PathBuf>,
+        routing_mode: &RoutingMode,
+        data_destination: &DataDestination,
+        output_options: &OutputOptions,
+        rule_file: Option<PathBuf>,
+        debug_dir: Option<PathBuf>,
+        debug_writer_options: &DebugWriterOptions,
+        stream_routes: bool,
+        generation_options: &GenerationOptions,
+    ) -> Result<()> {
+        if stream_routes && matches!(data_destination, DataDestination::Stdout) {
+            return Err(RouterRunnerError::StreamRoutesStdout.into());
+        }
+        let _debug_writer = DebugWriter::init(debug_dir, debug_writer_options.clone())
+            .context("Failed to init debug writer")?;
+        let trip = routing_mode.rule_condition_trip(generation_options.departure_time);
+        let rules = RouterRules::read(rule_file, Some(&trip)).context("Failed to read rules")?;
+        let output_options = &output_options.with_rules_units(rules.units);
+        DebugWriter::write_replay_input(&ReplayInput {
+            routing_mode: routing_mode.clone(),
+            rules: rules.clone(),
+            seed: generation_options.seed,
+        });
+        RouterRunner::load_map_data(data_source, cache_dir)?;
+
         info!("Route generation started");
 
-        let route_result = RouterRunner::generate_route(routing_mode, rules);
+        let counters = Arc::new(GenerationCounters::new());
+        let route_result = RouterRunner::generate_route(
+            routing_mode,
+            rules,
+            GenerateRouteOptions {
+                timeout: generation_options.timeout.map(Duration::from_secs),
+                progress: std::io::stderr()
+                    .is_terminal()
+                    .then(|| Arc::new(print_progress) as ProgressCallback),
+                route_callback: stream_routes.then(|| {
+                    let output_detail = output_options.output_detail;
+                    Arc::new(move |route: RouteWithStats| {
+                        if let Err(error) = ResultWriter::write_route_line(
+                            RouterRunner::create_route_message(&route, output_detail),
+                        ) {
+                            error!(error = ?error, "Failed to write streamed route");
+                        }
+                    }) as RouteCallback
+                }),
+                counters: Some(counters.clone()),
+                seed: generation_options.seed,
+                ..Default::default()
+            },
+        );
+        if std::io::stderr().is_terminal() {
+            eprintln!();
+        }
+        // the response is written either way, the process exits with the reason there's no route
+        let route_error = match &route_result {
+            Ok(routes) if routes.is_empty() => Some(RouterRunnerError::NoRoute.into()),
+            Ok(_) => None,
+            Err(RouterRunnerError::PointNotFound { point }) => Some(
+                RouterRunnerError::PointNotFound {
+                    point: point.clone(),
+                }
+                .into(),
+            ),
+            Err(error) => Some(anyhow::anyhow!("Failed to generate route: {error}")),
+        };
         ResultWriter::write(
             data_destination.clone(),
-            ResponseMessage {
-                id: "oo".to_string(),
-                result: route_result.map_or_else(
-                    |error| RouterResult::Error {
-                        message: format!("Error generating route {:?}", error),
-                    },
-                    |routes| RouterResult::Ok {
-                        routes: routes
-                            .iter()
-                            .map(|route| RouteMessage {
-                                coords: route
-                                    .route
-                                    .clone()
-                                    .into_iter()
-                                    .map(|segment| {
-                                        (
-                                            segment.get_end_point().borrow().lat,
-                                            segment.get_end_point().borrow().lon,
-                                        )
-                                    })
-                                    .collect(),
-                                stats: route.stats.clone(),
-                            })
-                            .collect(),
-                    },
-                ),
-            },
+            output_options,
+            RouterRunner::create_response(
+                "oo".to_string(),
+                route_result,
+                output_options.output_detail,
+                Some(counters.summary()),
+            ),
         )
         .map_err(|error| RouterRunnerError::ResultWrite { error })?;
-        Ok(())
+        route_error.map_or(Ok(()), Err)
     }
 
-    #[tracing::instrument]
-    fn run_cache(data_source: &DataSource, cache_dir: PathBuf) -> anyhow::Result<()> {
-        let startup_start = Instant::now();
+    /// Reads the rules of the jobs and checks their outputs, so mistakes in the requests file show
+    /// up before the map data is loaded
+    fn prepare_batch_jobs(
+        requests: &Path,
+        rule_file: Option<PathBuf>,
+        generation_options: &GenerationOptions,
+    ) -> Result<Vec<PreparedBatchJob>> {
+        let jobs = serde_json::from_str::<Vec<BatchJob>>(
+            &std::fs::read_to_string(requests).context("Failed to read requests")?,
+        )
+        .context("Failed to parse requests")?;
+        let requests_dir = requests.parent().unwrap_or(Path::new(""));
+        jobs.into_iter()
+            .enumerate()
+            .map(|(idx, job)| {
+                let id = job.id.unwrap_or_else(|| idx.to_string());
+                let trip = job
+                    .routing_mode
+                    .rule_condition_trip(generation_options.departure_time);
+                let rules = RouterRules::read(
+                    job.rule_file
+                        .map(|rule_file| requests_dir.join(rule_file))
+                        .or_else(|| rule_file.clone()),
+                    Some(&trip),
+                )
+                .with_context(|| format!("Failed to read rules of job {id}"))?;
+                let output = job
+                    .output
+                    .map(|output| {
+                        DataDestination::from_str(&requests_dir.join(output).to_string_lossy())
+                    })
+                    .transpose()
+                    .with_context(|| format!("Invalid output of job {id}"))?;
+                Ok(PreparedBatchJob {
+                    id,
+                    routing_mode: job.routing_mode,
+                    rules,
+                    output,
+                    seed: job.seed.or(generation_options.seed),
+                    timeout_s: job.timeout_s.or(generation_options.timeout),
+                })
+            })
+            .collect()
+    }
 
-        let mut data_cache = MapDataCache::init(Some(cache_dir), data_source);
-        data_cache
-            .read_input_metadata()
-            .map_err(|error| RouterRunnerError::CacheWrite { error })?;
-        MapDataGraph::init(data_source);
-        let packed_data = MapDataGraph::get()
-            .pack()
-            .context("Failed to pack map data")?;
-        data_cache
-            .write_cache(packed_data)
+    #[tracing::instrument(skip_all)]
+    fn run_batch(
+        data_source: &DataSource,
+        cache_dir: Option<PathBuf>,
+        requests: &Path,
+        data_destination: &DataDestination,
+        output_options: &OutputOptions,
+        rule_file: Option<PathBuf>,
+        generation_options: &GenerationOptions,
+    ) -> Result<()> {
+        let jobs = RouterRunner::prepare_batch_jobs(requests, rule_file, generation_options)?;
+        RouterRunner::load_map_data(data_source, cache_dir)?;
+
+        info!(job_count = jobs.len(), "Batch route generation started");
+
+        // the jobs share the rayon pool with the itineraries of every job
+        let responses = jobs
+            .into_par_iter()
+            .filter_map(|job| {
+                let counters = Arc::new(GenerationCounters::new());
+                let units = job.rules.units;
+                let route_result = catch_unwind(AssertUnwindSafe(|| {
+                    RouterRunner::generate_route(
+                        &job.routing_mode,
+                        job.rules,
+                        GenerateRouteOptions {
+                            timeout: job.timeout_s.map(Duration::from_secs),
+                            counters: Some(counters.clone()),
+                            seed: job.seed,
+                            ..Default::default()
+                        },
+                    )
+                }));
+                let response = match route_result {
+                    Ok(route_result) => RouterRunner::create_response(
+                        job.id.clone(),
+                        route_result,
+                        output_options.output_detail,
+                        Some(counters.summary()),
+                    ),
+                    Err(error) => ResponseMessage {
+                        id: job.id.clone(),
+                        result: RouterResult::Error {
+                            message: format!("Caught panic {:?}", error),
+                        },
+                    },
+                };
+                let Some(output) = job.output else {
+                    info!(job_id = job.id, "Batch job done");
+                    return Some(response);
+                };
+                match ResultWriter::write(output, &output_options.with_rules_units(units), response)
+                {
+                    Ok(()) => {
+                        info!(job_id = job.id, "Batch job written");
+                        None
+                    }
+                    Err(error) => {
+                        error!(job_id = job.id, error = ?error, "Failed to write batch job");
+                        Some(ResponseMessage {
+                            id: job.id,
+                            result: RouterResult::Error {
+                                message: format!("Failed to write output {:?}", error),
+                            },
+                        })
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        ResultWriter::write(
+            data_destination.clone(),
+            output_options,
+            ResponseMessage {
+                id: "batch".to_string(),
+                result: RouterResult::Batch { responses },
+            },
+        )
+        .map_err(|error| RouterRunnerError::ResultWrite { error })?;
+        Ok(())
+    }
+
+    /// Generates the routes of every scenario one after the other, so the runs don't compete for
+    /// the rayon pool and their latency is comparable between releases
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(skip_all)]
+    fn run_bench(
+        data_source: &DataSource,
+        cache_dir: Option<PathBuf>,
+        scenarios: &Path,
+        iterations: usize,
+        warmup: usize,
+        rule_file: Option<PathBuf>,
+        generation_options: &GenerationOptions,
+        format: BenchFormat,
+    ) -> Result<()> {
+        let scenarios = RouterRunner::prepare_batch_jobs(scenarios, rule_file, generation_options)?;
+        let load_start = Instant::now();
+        RouterRunner::load_map_data(data_source, cache_dir)?;
+        info!(
+            load_ms = load_start.elapsed().as_millis() as u64,
+            peak_rss_bytes = bench::peak_rss_bytes(),
+            "Map data loaded for benchmark"
+        );
+
+        let stats = scenarios
+            .into_iter()
+            .map(|scenario| {
+                let runs = (0..warmup + iterations)
+                    .map(|_| {
+                        let counters = Arc::new(GenerationCounters::new());
+                        let start = Instant::now();
+                        let route_result = RouterRunner::generate_route(
+                            &scenario.routing_mode,
+                            scenario.rules.clone(),
+                            GenerateRouteOptions {
+                                timeout: scenario.timeout_s.map(Duration::from_secs),
+                                counters: Some(counters.clone()),
+                                seed: scenario.seed,
+                                ..Default::default()
+                            },
+                        );
+                        ScenarioRun {
+                            duration: start.elapsed(),
+                            steps: counters.summary().steps,
+                            routes: route_result.ok().map(|routes| routes.len()),
+                        }
+                    })
+                    .skip(warmup)
+                    .collect::<Vec<_>>();
+                let stats = bench::scenario_stats(scenario.id, &runs, bench::peak_rss_bytes());
+                info!(
+                    scenario_id = stats.scenario_id,
+                    p50_ms = stats.p50_ms,
+                    "Benchmark scenario done"
+                );
+                stats
+            })
+            .collect::<Vec<_>>();
+
+        bench::write_bench_stats(&stats, format)
+            .map_err(|error| RouterRunnerError::Bench { error })?;
+        Ok(())
+    }
+
+    /// Generates the routes of every random case, the cases run in parallel on the rayon pool
+    #[tracing::instrument(skip_all)]
+    fn run_fuzz_routes(
+        data_source: &DataSource,
+        cache_dir: Option<PathBuf>,
+        count: usize,
+        seed: Option<u64>,
+        max_distance_m: u32,
+        timeout_s: u64,
+        rule_file: Option<PathBuf>,
+    ) -> Result<()> {
+        let seed = seed.unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |since| since.as_nanos() as u64)
+        });
+        RouterRunner::load_map_data(data_source, cache_dir)?;
+        let bbox = MapDataGraph::get()
+            .bbox()
+            .context("No points in the map data")?;
+        info!(seed, count, "Route fuzzing started");
+
+        let outcomes = (0..count as u64)
+            .into_par_iter()
+            // none for skipped cases, passed cases have no failure
+            .map(|idx| -> Result<Option<Option<FuzzFailure>>> {
+                let case = FuzzCase::new(seed.wrapping_add(idx), &bbox, max_distance_m);
+                let routing_mode = RoutingMode::StartFinish {
+                    start: Coords {
+                        lat: case.start.0,
+                        lon: case.start.1,
+                    },
+                    finish: Coords {
+                        lat: case.finish.0,
+                        lon: case.finish.1,
+                    },
+                    via: Vec::new(),
+                    optimize_order: false,
+                };
+                let trip = routing_mode.rule_condition_trip(None);
+                // rules are not read from stdin, the cases run in parallel
+                let rules = rule_file
+                    .clone()
+                    .map(|rule_file| RouterRules::read_from_file(rule_file, Some(&trip)))
+                    .transpose()
+                    .context("Failed to read rules")?
+                    .unwrap_or_default();
+                let counters = Arc::new(GenerationCounters::new());
+                let route_result = catch_unwind(AssertUnwindSafe(|| {
+                    RouterRunner::generate_route(
+                        &routing_mode,
+                        rules,
+                        GenerateRouteOptions {
+                            timeout: Some(Duration::from_secs(timeout_s)),
+                            counters: Some(counters.clone()),
+                            seed: Some(case.seed),
+                            ..Default::default()
+                        },
+                    )
+                }));
+                let failure = match route_result {
+                    Err(error) => Some(FuzzFailure::new(
+                        case,
+                        FuzzFailureKind::Panic,
+                        error
+                            .downcast_ref::<&str>()
+                            .map(|message| message.to_string())
+                            .or_else(|| error.downcast_ref::<String>().cloned())
+                            .unwrap_or_else(|| "Caught panic".to_string()),
+                    )),
+                    Ok(Err(RouterRunnerError::PointNotFound { .. })) => return Ok(None),
+                    Ok(Err(error)) => Some(FuzzFailure::new(
+                        case,
+                        FuzzFailureKind::Error,
+                        error.to_string(),
+                    )),
+                    Ok(Ok(routes)) => FuzzFailure::from_routes(
+                        case,
+                        &routes,
+                        counters.summary().itineraries_stuck,
+                    ),
+                };
+                if let Some(failure) = &failure {
+                    warn!(
+                        seed = case.seed,
+                        kind = ?failure.kind,
+                        message = failure.message,
+                        "Fuzzed case failed"
+                    );
+                }
+                Ok(Some(failure))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut report = FuzzReport {
+            seed,
+            count,
+            skipped: outcomes.iter().filter(|outcome| outcome.is_none()).count(),
+            passed: 0,
+            failures: outcomes.into_iter().flatten().flatten().collect(),
+        };
+        report.passed = count - report.skipped - report.failures.len();
+        report
+            .write()
+            .map_err(|error| RouterRunnerError::Fuzz { error })?;
+        Ok(())
+    }
+
+    /// Prints what importing the PBF file takes, with a warning when it needs more memory than
+    /// is available
+    #[tracing::instrument(skip_all)]
+    fn run_dry_run(data_source: &DataSource) -> Result<()> {
+        let DataSource::PbfFile { file } = data_source else {
+            return Err(RouterRunnerError::DryRunInput.into());
+        };
+        let estimate = PbfEstimate::estimate(file).map_err(|error| RouterRunnerError::DryRun {
+            error: Box::new(error),
+        })?;
+        println!("{}", estimate.to_text());
+        if let Some(available) = pbf_estimate::available_memory_bytes() {
+            println!(
+                "Available memory: {}",
+                pbf_estimate::format_bytes(available)
+            );
+            if estimate.peak_memory_bytes > available {
+                warn!(
+                    peak_memory_bytes = estimate.peak_memory_bytes,
+                    available_memory_bytes = available,
+                    "Reading the map data likely needs more memory than is available"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    fn run_graph_info(
+        data_source: &DataSource,
+        cache_dir: Option<PathBuf>,
+        format: GraphInfoFormat,
+    ) -> Result<()> {
+        RouterRunner::load_map_data(data_source, cache_dir)?;
+        MapDataGraph::get()
+            .info()
+            .write(format)
+            .map_err(|error| RouterRunnerError::GraphInfo { error })?;
+        Ok(())
+    }
+
+    /// Generates a single itinerary of the debug files again, with the rules and seed of the route
+    /// generation, logging every step
+    #[tracing::instrument(skip_all)]
+    fn run_replay(
+        data_source: &DataSource,
+        cache_dir: Option<PathBuf>,
+        debug_dir: &Path,
+        itinerary_id: &str,
+        data_destination: &DataDestination,
+        output_options: &OutputOptions,
+    ) -> Result<()> {
+        let replay_input: ReplayInput = replay::read_replay_input(debug_dir)
+            .map_err(|error| RouterRunnerError::DebugReplay { error })?;
+        if replay_input.seed.is_none() {
+            warn!("The route generation had no seed, the replay may take different steps");
+        }
+        let rules = replay_input.rules;
+        let output_options = &output_options.with_rules_units(rules.units);
+        RouterRunner::load_map_data(data_source, cache_dir)?;
+
+        let itinerary = ReplayItinerary::read(debug_dir, itinerary_id)
+            .map_err(|error| RouterRunnerError::DebugReplay { error })?;
+        let vias = RouterRunner::resolve_vias(
+            &replay_input.routing_mode,
+            &rules,
+            &itinerary.start,
+            &itinerary.finish,
+        )?;
+        // only whether it is a round trip matters, the itinerary waypoints are already generated
+        let round_trip =
+            matches!(replay_input.routing_mode, RoutingMode::RoundTrip { .. }).then_some((None, 0));
+        let generator = Generator::new(
+            itinerary.start,
+            itinerary.finish,
+            vias,
+            round_trip,
+            rules.clone(),
+        )
+        .with_seed(replay_input.seed);
+
+        info!(itinerary_id, "Replay started");
+        let trace_subscriber = tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .with_file(true)
+            .with_line_number(true)
+            .with_max_level(tracing::Level::TRACE)
+            .finish();
+        let nav_result = tracing::subscriber::with_default(trace_subscriber, || {
+            generator.replay_itinerary(itinerary.waypoints, itinerary.waypoint_radius)
+        });
+        let route = match nav_result {
+            NavigationResult::Finished(route) => {
+                info!(itinerary_id, "Replay finished");
+                Some(route)
+            }
+            NavigationResult::Partial(partial) => {
+                info!(
+                    itinerary_id,
+                    waypoints_passed = partial.waypoints_passed,
+                    "Replay returned a partial route"
+                );
+                Some(partial.route)
+            }
+            NavigationResult::Stuck => {
+                info!(itinerary_id, "Replay got stuck");
+                None
+            }
+            NavigationResult::Stopped => {
+                info!(itinerary_id, "Replay stopped");
+                None
+            }
+        };
+
+        ResultWriter::write(
+            data_destination.clone(),
+            output_options,
+            RouterRunner::create_response(
+                "replay".to_string(),
+                Ok(route
+                    .into_iter()
+                    .map(|route| RouteWithStats {
+                        stats: route.calc_stats(&rules),
+                        route,
+                    })
+                    .collect()),
+                output_options.output_detail,
+                None,
+            ),
+        )
+        .map_err(|error| RouterRunnerError::ResultWrite { error })?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    fn run_matrix(
+        data_source: &DataSource,
+        cache_dir: Option<PathBuf>,
+        points: &PathBuf,
+        output: Option<PathBuf>,
+        rule_file: Option<PathBuf>,
+    ) -> Result<()> {
+        let rules = RouterRules::read(rule_file, None).context("Failed to read rules")?;
+        let points =
+            Matrix::read_points(points).map_err(|error| RouterRunnerError::Matrix { error })?;
+        RouterRunner::load_map_data(data_source, cache_dir)?;
+
+        let matrix_start = Instant::now();
+        let matrix =
+            Matrix::calc(points, &rules).map_err(|error| RouterRunnerError::Matrix { error })?;
+        info!(
+            matrix_secs = matrix_start.elapsed().as_secs(),
+            "Matrix done"
+        );
+
+        matrix
+            .write(output)
+            .map_err(|error| RouterRunnerError::Matrix { error })?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    fn run_match(
+        data_source: &DataSource,
+        cache_dir: Option<PathBuf>,
+        gpx: &PathBuf,
+        data_destination: &DataDestination,
+        output_options: &OutputOptions,
+        rule_file: Option<PathBuf>,
+    ) -> Result<()> {
+        let rules = RouterRules::read(rule_file, None).context("Failed to read rules")?;
+        let output_options = &output_options.with_rules_units(rules.units);
+        let coords =
+            TrackMatcher::read_gpx(gpx).map_err(|error| RouterRunnerError::TrackMatch { error })?;
+        RouterRunner::load_map_data(data_source, cache_dir)?;
+
+        let route_result = TrackMatcher::match_track(&coords, &rules)
+            .map(|route| vec![route])
+            .map_err(|error| RouterRunnerError::TrackMatch { error });
+        ResultWriter::write(
+            data_destination.clone(),
+            output_options,
+            RouterRunner::create_response(
+                "match".to_string(),
+                route_result,
+                output_options.output_detail,
+                None,
+            ),
+        )
+        .map_err(|error| RouterRunnerError::ResultWrite { error })?;
+        Ok(())
+    }
+
+    /// Prints every problem found in the rules to stderr, or the effective rules to stdout when
+    /// there are none
+    #[tracing::instrument]
+    fn run_rules_check(rule_file: &Path) -> anyhow::Result<()> {
+        let rules = RouterRules::read_resolved_value(rule_file)
+            .map_err(|error| RouterRunnerError::Rules { error })?;
+        let diagnostics = check_rules(&rules);
+        if !diagnostics.is_empty() {
+            for diagnostic in &diagnostics {
+                eprintln!("{diagnostic}");
+            }
+            return Err(RouterRunnerError::RulesCheck {
+                count: diagnostics.len(),
+            }
+            .into());
+        }
+
+        let rules: RouterRules =
+            serde_json::from_value(rules).map_err(|error| RouterRunnerError::Rules {
+                error: RulesError::JsonParse { error },
+            })?;
+        println!("{}", serde_json::to_string_pretty(&rules)?);
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    fn run_cache(data_source: &DataSource, cache_dir: PathBuf) -> anyhow::Result<()> {
+        let startup_start = Instant::now();
+
+        let mut data_cache = MapDataCache::init(Some(cache_dir), data_source);
+        data_cache
+            .read_input_metadata()
+            .map_err(|error| RouterRunnerError::CacheWrite { error })?;
+        MapDataGraph::init(data_source);
+        let packed_data = MapDataGraph::get()
+            .pack()
+            .context("Failed to pack map data")?;
+        data_cache
+            .write_cache(packed_data)
             .map_err(|error| RouterRunnerError::CacheWrite { error })?;
 
         let startup_end = startup_start.elapsed();
@@ -458,174 +2716,862 @@ impl RouterRunner {
         Ok(())
     }
 
-    #[tracing::instrument]
+    #[cfg(not(target_arch = "wasm32"))]
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(skip(token))]
     fn run_server(
         data_source: &DataSource,
         cache_dir: Option<PathBuf>,
         socket_name: Option<String>,
+        workers: Option<usize>,
+        queue_depth: usize,
+        listen: Option<String>,
+        websocket: Option<String>,
+        drain_timeout: Duration,
+        response_cache: Option<usize>,
+        token: Option<String>,
+        rate_limit: Option<usize>,
+        rule_file: Option<PathBuf>,
+        overridable_rules: Option<Vec<String>>,
+        history_size: usize,
+        history_log: Option<PathBuf>,
+        daemon_options: &DaemonOptions,
     ) -> anyhow::Result<()> {
-        let startup_start = Instant::now();
-
-        let mut data_cache = MapDataCache::init(cache_dir, data_source);
-        let cached_map_data = data_cache.read_cache();
-        let cached_map_data = match cached_map_data {
-            Ok(d) => d,
-            Err(error) => {
-                tracing::error!(error = ?error, "Failed to process cache");
+        let socket_permissions =
+            if daemon_options.socket_mode.is_some() || daemon_options.socket_group.is_some() {
+                Some(SocketPermissions {
+                    mode: daemon_options.socket_mode,
+                    group: daemon_options
+                        .socket_group
+                        .as_deref()
+                        .map(daemon::group_id)
+                        .transpose()
+                        .map_err(|error| RouterRunnerError::Daemon { error })?,
+                })
+            } else {
                 None
-            }
-        };
-        let unpack_ok = if let Some(packed_data) = cached_map_data {
-            let unpack_result = MapDataGraph::unpack(packed_data);
-            if let Err(ref error) = unpack_result {
-                tracing::error!(error = ?error, "Unpack unsuccessful");
-                let cache_metadata = data_cache.read_input_metadata();
-                if let Err(ref error) = cache_metadata {
-                    tracing::error!(error = ?error, "Cache metadata prep after unpack unsuccessful failed");
-                }
-            }
-            unpack_result.is_ok()
-        } else {
-            false
-        };
+            };
+        // the server was daemonized before telemetry was initialized, in `run_cli`
+        if let Some(pidfile) = &daemon_options.pidfile {
+            daemon::write_pidfile(pidfile).map_err(|error| RouterRunnerError::Daemon { error })?;
+        }
 
-        if !unpack_ok {
-            MapDataGraph::init(data_source);
-            let packed_data = MapDataGraph::get()
-                .pack()
-                .context("Failed to pack map data")?;
-            if let Err(error) = data_cache.write_cache(packed_data) {
-                tracing::error!(error = ?error, "Failed to write cache");
-            }
+        let startup_start = Instant::now();
+        let request_history = RequestHistory::new(history_size, history_log)
+            .map_err(|error| RouterRunnerError::RequestHistory { error })?;
+        let _ = RouterRunner::request_history().set(request_history);
+        if rule_file.is_some() || overridable_rules.is_some() {
+            let rules = rule_file
+                .map(|rule_file| RouterRules::read_resolved_value(&rule_file))
+                .transpose()
+                .context("Failed to read rules")?
+                .unwrap_or_else(|| serde_json::Value::Object(Default::default()));
+            // the `when` conditions are evaluated for every request, this only checks the rules
+            RouterRules::from_value(
+                rules.clone(),
+                "server",
+                Some(&RuleConditionTrip::new(None, 0., 0.)),
+            )
+            .context("Failed to read rules")?;
+            info!(overridable_rules = ?overridable_rules, "Server rules");
+            let _ = RouterRunner::server_rules().set(ServerRules {
+                rules,
+                overridable: overridable_rules,
+            });
         }
+        let workers = workers.unwrap_or_else(rayon::current_num_threads);
+        let _ = RouterRunner::worker_pool().set(WorkerPool::new(workers, queue_depth));
+        info!(workers, queue_depth, "Worker pool");
+        if let Some(response_cache) = response_cache.filter(|capacity| *capacity > 0) {
+            let _ = RouterRunner::response_cache().set(ResponseCache::new(response_cache));
+            info!(response_cache, "Response cache");
+        }
+        if let Some(token) = token.filter(|token| !token.is_empty()) {
+            let _ = RouterRunner::server_token().set(token);
+            info!("Requests require a token");
+        }
+        if let Some(rate_limit) = rate_limit {
+            let _ = RouterRunner::rate_limiter().set(RateLimiter::new(rate_limit));
+            info!(rate_limit, "Requests per minute per client");
+        }
+        let websocket_handler = websocket
+            .map(|addr| WebSocketHandler::bind(&addr))
+            .transpose()
+            .map_err(|error| RouterRunnerError::WebSocket { error })?;
+
+        RouterRunner::load_map_data(data_source, cache_dir)?;
 
         let startup_end = startup_start.elapsed();
         info!(startup_time_secs = startup_end.as_secs(), "Startup");
 
-        let ipc =
-            IpcHandler::init(socket_name).map_err(|error| RouterRunnerError::Ipc { error })?;
+        let ipc = IpcHandler::init(socket_name, listen)
+            .and_then(|ipc| ipc.with_socket_permissions(socket_permissions))
+            .map_err(|error| RouterRunnerError::Ipc { error })?;
 
-        ipc.listen(|request_message| {
-            let route_res = catch_unwind(|| {
-                RouterRunner::generate_route(&request_message.routing_mode, request_message.rules)
-            });
+        // the files the server created, removed when it stops
+        let server_files = ipc
+            .socket_file()
+            .into_iter()
+            .chain(daemon_options.pidfile.clone())
+            .collect::<Vec<_>>();
+        ctrlc::set_handler(move || {
+            if SHUTTING_DOWN.swap(true, Ordering::SeqCst) {
+                warn!("Second termination signal, exiting without waiting for requests");
+                std::process::exit(1);
+            }
+            // draining happens on its own thread so a second signal can still force the exit
+            let server_files = server_files.clone();
+            std::thread::spawn(move || RouterRunner::drain_requests(drain_timeout, server_files));
+        })
+        .map_err(|error| RouterRunnerError::SignalHandler { error })?;
 
-            let route_res = match route_res {
-                Ok(r) => r,
-                Err(error) => {
-                    return ResponseMessage {
-                        id: request_message.id,
-                        result: RouterResult::Error {
-                            message: format!("Caught panic {:?}", error),
-                        },
-                    };
+        if let Some(websocket_handler) = websocket_handler {
+            std::thread::spawn(move || {
+                if let Err(error) = websocket_handler.listen(RouterRunner::handle_ipc_request) {
+                    error!(error = ?error, "WebSocket server stopped");
                 }
-            };
+            });
+        }
 
-            ResponseMessage {
-                id: request_message.id,
-                result: route_res.map_or_else(
-                    |error| RouterResult::Error {
-                        message: format!("Error generating route {:?}", error),
-                    },
-                    |routes| RouterResult::Ok {
-                        routes: routes
-                            .iter()
-                            .map(|route| RouteMessage {
-                                coords: route
-                                    .route
-                                    .clone()
-                                    .into_iter()
-                                    .map(|segment| {
-                                        (
-                                            segment.get_end_point().borrow().lat,
-                                            segment.get_end_point().borrow().lon,
-                                        )
-                                    })
-                                    .collect(),
-                                stats: route.stats.clone(),
-                            })
-                            .collect(),
-                    },
-                ),
+        ipc.listen(RouterRunner::handle_ipc_request)
+            .map_err(|error| RouterRunnerError::Ipc { error })?;
+        // the listener stops once the server is shutting down, the drain exits the process
+        if SHUTTING_DOWN.load(Ordering::SeqCst) {
+            loop {
+                std::thread::park();
             }
-        })
-        .map_err(|error| RouterRunnerError::Ipc { error })?;
+        }
         Ok(())
     }
 
-    #[tracing::instrument]
+    /// Called once the server is shutting down and rejecting new requests. Waits for the requests
+    /// in progress to finish, cancels the ones still running after `drain_timeout` and exits
+    #[cfg(not(target_arch = "wasm32"))]
+    fn drain_requests(drain_timeout: Duration, server_files: Vec<PathBuf>) -> ! {
+        let active_requests = ACTIVE_REQUESTS.load(Ordering::SeqCst);
+        info!(
+            active_requests,
+            drain_timeout_s = drain_timeout.as_secs(),
+            "Shutting down, waiting for requests in progress"
+        );
+
+        let wait_for_requests = |timeout: Duration| {
+            let deadline = Instant::now() + timeout;
+            while ACTIVE_REQUESTS.load(Ordering::SeqCst) > 0 && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            ACTIVE_REQUESTS.load(Ordering::SeqCst)
+        };
+
+        let mut exit_code = 0;
+        let active_requests = wait_for_requests(drain_timeout);
+        if active_requests > 0 {
+            warn!(
+                active_requests,
+                "Drain timeout reached, cancelling requests"
+            );
+            DRAIN_TIMED_OUT.store(true, Ordering::SeqCst);
+            for cancellation in RouterRunner::in_flight_requests()
+                .lock()
+                .unwrap_or_else(|error| error.into_inner())
+                .values()
+            {
+                cancellation.cancel();
+            }
+            let active_requests = wait_for_requests(CANCEL_GRACE_PERIOD);
+            if active_requests > 0 {
+                warn!(active_requests, "Requests still running at exit");
+                exit_code = 1;
+            }
+        }
+
+        for server_file in server_files {
+            let _ = std::fs::remove_file(server_file);
+        }
+        info!("Server stopped");
+        std::process::exit(exit_code);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(skip(token))]
     fn run_client(
-        routing_mode: &RoutingMode,
+        routing_mode: Option<&RoutingMode>,
+        requests: Option<&PathBuf>,
+        parallel: bool,
         data_destination: &DataDestination,
+        output_options: &OutputOptions,
         socket_name: Option<String>,
+        connect: Option<String>,
+        token: Option<String>,
         rule_file: Option<PathBuf>,
+        rule_overrides: Option<PathBuf>,
         route_req_id: Option<String>,
+        generation_options: &GenerationOptions,
+        retry_options: &RetryOptions,
+        priority: Option<Priority>,
+        chunked_responses: bool,
+        progress: bool,
     ) -> Result<()> {
         let client_start = Instant::now();
-        let rules = RouterRules::read(rule_file).context("Failed to read rules")?;
-        let ipc =
-            IpcHandler::init(socket_name).map_err(|error| RouterRunnerError::Ipc { error })?;
+        let ipc = IpcHandler::init(socket_name, connect)
+            .map_err(|error| RouterRunnerError::Ipc { error })?
+            .with_token(token)
+            .with_retries(
+                retry_options.response_timeout.map(Duration::from_secs),
+                retry_options.retries,
+            )
+            .with_chunked_responses(chunked_responses);
+        let on_progress = progress.then_some(&print_progress as &dyn Fn(GenerationProgress));
+        let mut rules_units = None;
+        let response = match (routing_mode, requests) {
+            (Some(routing_mode), None) => {
+                let trip = routing_mode.rule_condition_trip(generation_options.departure_time);
+                // with overrides the rules the server was started with are used
+                let (rules, rule_overrides) = match rule_overrides {
+                    Some(rule_overrides) => {
+                        let mut rule_overrides = rules::read_rules_file(&rule_overrides)
+                            .context("Failed to read rule overrides")?;
+                        apply_conditions(&mut rule_overrides, Some(&trip))
+                            .context("Failed to read rule overrides")?;
+                        (None, Some(rule_overrides))
+                    }
+                    None => (
+                        Some(
+                            RouterRules::read(rule_file, Some(&trip))
+                                .context("Failed to read rules")?,
+                        ),
+                        None,
+                    ),
+                };
+                rules_units = rules.as_ref().and_then(|rules| rules.units);
+                ipc.connect(
+                    routing_mode,
+                    rules,
+                    rule_overrides,
+                    generation_options.departure_time,
+                    route_req_id,
+                    generation_options.timeout,
+                    generation_options.seed,
+                    output_options.output_detail,
+                    priority.unwrap_or_default(),
+                    on_progress,
+                )
+            }
+            (None, Some(requests)) => {
+                let mut batch = serde_json::from_str::<Vec<RequestMessage>>(
+                    &std::fs::read_to_string(requests).context("Failed to read requests")?,
+                )
+                .context("Failed to parse requests")?;
+                if let Some(priority) = priority {
+                    batch
+                        .iter_mut()
+                        .for_each(|request_message| request_message.priority = priority);
+                }
+                ipc.batch(
+                    BatchMessage {
+                        id: route_req_id.unwrap_or_else(|| "default-request-id".to_string()),
+                        batch,
+                        parallel,
+                        token: None,
+                        client_id: None,
+                        chunked: false,
+                    },
+                    on_progress,
+                )
+            }
+            _ => return Err(RouterRunnerError::ClientRequest.into()),
+        }
+        .map_err(|error| RouterRunnerError::Ipc { error })?;
+        if progress {
+            eprintln!();
+        }
+        ResultWriter::write(
+            data_destination.clone(),
+            &output_options.with_rules_units(rules_units),
+            response,
+        )
+        .map_err(|error| RouterRunnerError::ResultWrite { error })?;
+
+        let client_run = client_start.elapsed();
+        info!(client_run_secs = client_run.as_secs(), "Client done");
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument]
+    fn run_repl(
+        socket_name: Option<String>,
+        connect: Option<String>,
+        token: Option<String>,
+        rule_overrides: Option<PathBuf>,
+        units: Option<units::Units>,
+        retry_options: &RetryOptions,
+        progress: bool,
+    ) -> Result<()> {
+        let ipc = IpcHandler::init(socket_name, connect)
+            .map_err(|error| RouterRunnerError::Ipc { error })?
+            .with_token(token)
+            .with_retries(
+                retry_options.response_timeout.map(Duration::from_secs),
+                retry_options.retries,
+            );
+        let rule_overrides = rule_overrides
+            .map(|rule_overrides| rules::read_rules_file(&rule_overrides))
+            .transpose()
+            .context("Failed to read rule overrides")?;
+        Repl::new(
+            ipc,
+            rule_overrides,
+            units,
+            progress.then_some(print_progress as fn(GenerationProgress)),
+        )
+        .run()
+        .map_err(|error| RouterRunnerError::Repl { error })?;
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tracing::instrument]
+    fn run_cancel(
+        socket_name: Option<String>,
+        connect: Option<String>,
+        token: Option<String>,
+        route_req_id: String,
+        retry_options: &RetryOptions,
+    ) -> Result<()> {
+        let ipc = IpcHandler::init(socket_name, connect)
+            .map_err(|error| RouterRunnerError::Ipc { error })?
+            .with_token(token)
+            .with_retries(
+                retry_options.response_timeout.map(Duration::from_secs),
+                retry_options.retries,
+            );
         let response = ipc
-            .connect(routing_mode, rules, route_req_id)
+            .cancel(route_req_id)
             .map_err(|error| RouterRunnerError::Ipc { error })?;
-        ResultWriter::write(data_destination.clone(), response)
+        ResultWriter::write(DataDestination::Stdout, &OutputOptions::default(), response)
             .map_err(|error| RouterRunnerError::ResultWrite { error })?;
+        Ok(())
+    }
 
-        let client_run = client_start.elapsed();
-        info!(client_run_secs = client_run.as_secs(), "Client done");
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tracing::instrument]
+    fn run_status(
+        socket_name: Option<String>,
+        connect: Option<String>,
+        token: Option<String>,
+        route_req_id: String,
+        retry_options: &RetryOptions,
+    ) -> Result<()> {
+        let ipc = IpcHandler::init(socket_name, connect)
+            .map_err(|error| RouterRunnerError::Ipc { error })?
+            .with_token(token)
+            .with_retries(
+                retry_options.response_timeout.map(Duration::from_secs),
+                retry_options.retries,
+            );
+        let response = ipc
+            .status(route_req_id)
+            .map_err(|error| RouterRunnerError::Ipc { error })?;
+        ResultWriter::write(DataDestination::Stdout, &OutputOptions::default(), response)
+            .map_err(|error| RouterRunnerError::ResultWrite { error })?;
         Ok(())
     }
 
     #[tracing::instrument]
-    pub fn run() -> Result<()> {
-        let cli = Cli::parse();
+    pub fn run() -> Result<(), CliError> {
+        let command = CliConfig::read()
+            .map_err(|error| CliError {
+                error: error.into(),
+                format: ErrorFormat::Text,
+            })?
+            .apply_arg_defaults(Cli::command());
+        let cli =
+            Cli::from_arg_matches(&command.get_matches()).unwrap_or_else(|error| error.exit());
+        let format = cli.error_format;
+        RouterRunner::run_cli(cli).map_err(|error| CliError { error, format })
+    }
+
+    fn run_cli(cli: Cli) -> Result<()> {
+        // only the calling thread continues in the daemon, so it starts before telemetry starts
+        // the threads that export the traces
+        #[cfg(not(target_arch = "wasm32"))]
+        if let CliMode::StartServer {
+            daemon_options,
+            dry_run: false,
+            ..
+        } = &cli.mode
+        {
+            if let Some(pidfile) = &daemon_options.pidfile {
+                daemon::check_pidfile(pidfile)
+                    .map_err(|error| RouterRunnerError::Daemon { error })?;
+            }
+            if daemon_options.daemonize {
+                daemon::daemonize().map_err(|error| RouterRunnerError::Daemon { error })?;
+            }
+        }
+        let local_offset_found = rule_conditions::init_local_offset();
+        let _telemetry = telemetry::init(&cli.telemetry_options)?;
+        if !local_offset_found {
+            tracing::warn!("local time zone offset not available, departures default to UTC");
+        }
+        let span = error_span!("Process", service = "ridi-router");
+        let _entered = span.enter();
+        crate::map_data::graph::set_extra_tag_keys(&cli.extra_tag_keys);
         match &cli.mode {
             CliMode::GenerateRoute {
                 routing_mode,
+                waypoints_file,
                 cache_dir,
                 rule_file,
                 input,
                 output,
+                gpx_options,
+                render_options,
+                output_options,
                 debug_dir,
+                debug_writer_options,
+                stream_routes,
+                generation_options,
+                #[cfg(feature = "geocoding")]
+                address_options,
             } => RouterRunner::run_dual(
                 input,
                 cache_dir.clone(),
-                routing_mode,
-                output,
+                &RoutingMode::from_args(
+                    #[cfg(feature = "geocoding")]
+                    RoutingMode::from_addresses(
+                        routing_mode.as_ref(),
+                        waypoints_file.as_deref(),
+                        address_options,
+                    )?
+                    .as_ref(),
+                    #[cfg(not(feature = "geocoding"))]
+                    routing_mode.as_ref(),
+                    waypoints_file.as_deref(),
+                )?,
+                &output
+                    .clone()
+                    .with_gpx_options(gpx_options.clone())
+                    .with_render_options(render_options.clone()),
+                output_options,
                 rule_file.clone(),
                 debug_dir.clone(),
+                debug_writer_options,
+                *stream_routes,
+                generation_options,
+            ),
+            CliMode::GenerateBatch {
+                input,
+                cache_dir,
+                requests,
+                output,
+                output_options,
+                rule_file,
+                generation_options,
+            } => RouterRunner::run_batch(
+                input,
+                cache_dir.clone(),
+                requests,
+                output,
+                output_options,
+                rule_file.clone(),
+                generation_options,
+            ),
+            CliMode::Bench {
+                input,
+                cache_dir,
+                scenarios,
+                iterations,
+                warmup,
+                rule_file,
+                generation_options,
+                format,
+            } => RouterRunner::run_bench(
+                input,
+                cache_dir.clone(),
+                scenarios,
+                *iterations,
+                *warmup,
+                rule_file.clone(),
+                generation_options,
+                *format,
+            ),
+            CliMode::FuzzRoutes {
+                input,
+                cache_dir,
+                count,
+                seed,
+                max_distance,
+                timeout,
+                rule_file,
+            } => RouterRunner::run_fuzz_routes(
+                input,
+                cache_dir.clone(),
+                *count,
+                *seed,
+                *max_distance,
+                *timeout,
+                rule_file.clone(),
+            ),
+            CliMode::Matrix {
+                input,
+                cache_dir,
+                points,
+                output,
+                rule_file,
+            } => RouterRunner::run_matrix(
+                input,
+                cache_dir.clone(),
+                points,
+                output.clone(),
+                rule_file.clone(),
+            ),
+            CliMode::Match {
+                input,
+                cache_dir,
+                gpx,
+                output,
+                gpx_options,
+                render_options,
+                output_options,
+                rule_file,
+            } => RouterRunner::run_match(
+                input,
+                cache_dir.clone(),
+                gpx,
+                &output
+                    .clone()
+                    .with_gpx_options(gpx_options.clone())
+                    .with_render_options(render_options.clone()),
+                output_options,
+                rule_file.clone(),
             ),
-            CliMode::PrepCache { input, cache_dir } => {
-                RouterRunner::run_cache(input, cache_dir.clone()).context("Failed to run cache")
+            CliMode::PrepCache {
+                input,
+                dry_run: true,
+                ..
+            } => RouterRunner::run_dry_run(input),
+            CliMode::PrepCache {
+                input, cache_dir, ..
+            } => RouterRunner::run_cache(input, cache_dir.clone()).context("Failed to run cache"),
+            CliMode::GraphInfo {
+                input,
+                cache_dir,
+                format,
+            } => RouterRunner::run_graph_info(input, cache_dir.clone(), *format),
+            CliMode::RulesCheck { rule_file } => {
+                RouterRunner::run_rules_check(rule_file).context("Failed to check rules")
             }
+            #[cfg(not(target_arch = "wasm32"))]
+            CliMode::StartServer {
+                input,
+                dry_run: true,
+                ..
+            } => RouterRunner::run_dry_run(input),
+            #[cfg(not(target_arch = "wasm32"))]
             CliMode::StartServer {
                 input,
                 cache_dir,
                 socket_name,
-            } => RouterRunner::run_server(input, cache_dir.clone(), socket_name.clone())
-                .context("Failed to run server"),
+                workers,
+                queue_depth,
+                listen,
+                websocket,
+                drain_timeout,
+                response_cache,
+                token,
+                rate_limit,
+                rule_file,
+                overridable_rules,
+                history_size,
+                history_log,
+                daemon_options,
+                dry_run: false,
+            } => RouterRunner::run_server(
+                input,
+                cache_dir.clone(),
+                socket_name.clone(),
+                *workers,
+                *queue_depth,
+                listen.clone(),
+                websocket.clone(),
+                Duration::from_secs(*drain_timeout),
+                *response_cache,
+                token.clone(),
+                *rate_limit,
+                rule_file.clone(),
+                overridable_rules.clone(),
+                *history_size,
+                history_log.clone(),
+                daemon_options,
+            )
+            .context("Failed to run server"),
+            #[cfg(not(target_arch = "wasm32"))]
             CliMode::StartClient {
                 routing_mode,
                 output,
+                gpx_options,
+                output_options,
+                requests,
+                parallel,
                 socket_name,
+                connect,
+                token,
                 rule_file,
+                rule_overrides,
                 route_req_id,
+                generation_options,
+                retry_options,
+                priority,
+                chunked_responses,
+                progress,
             } => RouterRunner::run_client(
-                routing_mode,
-                output,
+                routing_mode.as_ref(),
+                requests.as_ref(),
+                *parallel,
+                &output.clone().with_gpx_options(gpx_options.clone()),
+                output_options,
                 socket_name.clone(),
+                connect.clone(),
+                token.clone(),
                 rule_file.clone(),
+                rule_overrides.clone(),
                 route_req_id.clone(),
+                generation_options,
+                retry_options,
+                *priority,
+                *chunked_responses,
+                *progress,
             ),
-            #[cfg(feature = "debug-viewer")]
-            CliMode::DebugViewer { debug_dir } => {
-                Ok(crate::debug::viewer::DebugViewer::run(debug_dir.clone())?)
+            #[cfg(not(target_arch = "wasm32"))]
+            CliMode::Repl {
+                socket_name,
+                connect,
+                token,
+                rule_overrides,
+                units,
+                retry_options,
+                progress,
+            } => RouterRunner::run_repl(
+                socket_name.clone(),
+                connect.clone(),
+                token.clone(),
+                rule_overrides.clone(),
+                *units,
+                retry_options,
+                *progress,
+            ),
+            #[cfg(not(target_arch = "wasm32"))]
+            CliMode::CancelRequest {
+                socket_name,
+                connect,
+                token,
+                route_req_id,
+                retry_options,
+            } => RouterRunner::run_cancel(
+                socket_name.clone(),
+                connect.clone(),
+                token.clone(),
+                route_req_id.clone(),
+                retry_options,
+            ),
+            #[cfg(not(target_arch = "wasm32"))]
+            CliMode::RequestStatus {
+                socket_name,
+                connect,
+                token,
+                route_req_id,
+                retry_options,
+            } => RouterRunner::run_status(
+                socket_name.clone(),
+                connect.clone(),
+                token.clone(),
+                route_req_id.clone(),
+                retry_options,
+            ),
+            CliMode::Replay {
+                input,
+                cache_dir,
+                debug_dir,
+                itinerary_id,
+                output,
+                output_options,
+            } => RouterRunner::run_replay(
+                input,
+                cache_dir.clone(),
+                debug_dir,
+                itinerary_id,
+                output,
+                output_options,
+            ),
+            CliMode::DebugStats { debug_dir, format } => {
+                Ok(debug_stats::write_fork_choice_stats(debug_dir, *format)
+                    .map_err(|error| RouterRunnerError::DebugStats { error })?)
             }
+            #[cfg(feature = "debug-viewer")]
+            CliMode::DebugViewer {
+                debug_dir,
+                ui_dir,
+                serve_options,
+            } => Ok(crate::debug::viewer::DebugViewer::run(
+                debug_dir.clone(),
+                ui_dir.clone(),
+                serve_options.clone(),
+            )?),
+            #[cfg(feature = "debug-viewer")]
+            CliMode::DebugQuery {
+                debug_dir,
+                sql,
+                report,
+                format,
+            } => Ok(crate::debug::viewer::query::DebugQuery::run(
+                debug_dir.clone(),
+                sql.clone(),
+                *report,
+                *format,
+            )?),
             #[cfg(feature = "rule-schema-writer")]
             CliMode::RuleSchemaWrite { destination } => {
                 Ok(crate::router::rules::generate_json_schema(destination)?)
             }
+            #[cfg(feature = "rule-schema-writer")]
+            CliMode::MessageSchemaWrite { destination } => Ok(
+                crate::ipc_handler::generate_message_json_schema(destination)?,
+            ),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{str::FromStr, sync::Arc};
+
+    use rusty_fork::rusty_fork_test;
+    use serde_json::json;
+
+    use super::{
+        duration_to_distance_m, round_trip_duration_distance_m, RouterRunner, RouterRunnerError,
+        ViaCoords, ROUND_TRIP_DEFAULT_SPEED_KMH,
+    };
+    use crate::{
+        ipc_handler::{IpcRequest, RequestMessage, RouterResult},
+        map_data::graph::MapDataGraph,
+        router::{
+            cancellation::CancellationToken, itinerary::VIA_DEFAULT_RADIUS_M, rules::RouterRules,
+        },
+        test_utils::{graph_from_test_dataset, set_graph_static, test_dataset_1},
+    };
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn round_trip_duration_sized_by_road_speed() {
+            set_graph_static(graph_from_test_dataset(test_dataset_1()));
+            let start = MapDataGraph::get().test_get_point_ref_by_id(&1).unwrap();
+            let primary_speed_kmh = MapDataGraph::get().get_adjacent(start.clone())[0]
+                .0
+                .borrow()
+                .get_speed_kmh();
+
+            let distance = round_trip_duration_distance_m(&start, 90, &RouterRules::default());
+            assert!(distance.abs_diff(duration_to_distance_m(90, primary_speed_kmh)) <= 1);
+            assert_ne!(distance, duration_to_distance_m(90, ROUND_TRIP_DEFAULT_SPEED_KMH));
+        }
+    }
+
+    #[test]
+    fn via_coords_radius() {
+        let via = ViaCoords::from_str("57.1,24.8").unwrap();
+        assert_eq!(via.radius_m("via").unwrap(), VIA_DEFAULT_RADIUS_M);
+        let via = ViaCoords::from_str("57.1,24.8,200").unwrap();
+        assert_eq!(via.radius_m("via").unwrap(), 200.);
+        for radius in ["0", "-100", "NaN"] {
+            assert!(matches!(
+                ViaCoords::from_str(&format!("57.1,24.8,{radius}")),
+                Err(RouterRunnerError::Coords { .. })
+            ));
+        }
+
+        let via: ViaCoords =
+            serde_json::from_value(json!({ "lat": 57.1, "lon": 24.8, "radius": -1 })).unwrap();
+        assert!(matches!(
+            via.radius_m("via"),
+            Err(RouterRunnerError::Coords { .. })
+        ));
+    }
+
+    #[test]
+    fn rate_limit_clients() {
+        let peer = "10.0.0.1".parse().ok();
+        assert_eq!(RouterRunner::rate_limit_client(peer, None), "10.0.0.1");
+        assert_eq!(RouterRunner::rate_limit_client(peer, Some("a")), "10.0.0.1");
+        assert_eq!(RouterRunner::rate_limit_client(None, Some("a")), "a");
+        assert_eq!(RouterRunner::rate_limit_client(None, None), "local");
+    }
+
+    #[test]
+    fn request_rules_conditions() {
+        let request = |departure_time: &str| -> RequestMessage {
+            serde_json::from_value(json!({
+                "id": "a",
+                "routing_mode": {
+                    "StartFinish": {
+                        "start": { "lat": 56.95, "lon": 24.1 },
+                        "finish": { "lat": 57.1, "lon": 24.1 }
+                    }
+                },
+                "rules": {
+                    "basic": { "avoid_urban": { "enabled": true, "priority": 60, "when": { "months": [12] } } }
+                },
+                "rule_overrides": {
+                    "basic": { "prefer_curvy": { "enabled": true, "priority": 70, "when": { "time": "sunset..sunrise" } } }
+                },
+                "departure_time": departure_time
+            }))
+            .unwrap()
+        };
+
+        let winter_night =
+            RouterRunner::request_rules(&request("2025-12-21T22:00:00+02:00")).unwrap();
+        assert!(winter_night.basic.avoid_urban.enabled);
+        assert_eq!(winter_night.basic.avoid_urban.priority, 60);
+        assert!(winter_night.basic.prefer_curvy.enabled);
+        assert_eq!(winter_night.basic.prefer_curvy.priority, 70);
+
+        let summer_day =
+            RouterRunner::request_rules(&request("2025-06-21T12:00:00+03:00")).unwrap();
+        assert!(!summer_day.basic.avoid_urban.enabled);
+        assert_eq!(summer_day.basic.avoid_urban.priority, 50);
+        assert_ne!(summer_day.basic.prefer_curvy.priority, 70);
+    }
+
+    #[test]
+    fn cancel_round_trip() {
+        let cancellation = CancellationToken::new();
+        RouterRunner::in_flight_requests()
+            .lock()
+            .unwrap()
+            .insert("cancel-round-trip".to_string(), cancellation.clone());
+        let cancel = |id: &str| {
+            let request: IpcRequest = serde_json::from_value(json!({ "cancel": id })).unwrap();
+            RouterRunner::handle_ipc_request(request, None, Arc::new(|_| {}))
+        };
+
+        let response = cancel("cancel-round-trip");
+        assert_eq!(response.id, "cancel-round-trip");
+        assert!(matches!(response.result, RouterResult::Ok { routes, .. } if routes.is_empty()));
+        assert!(cancellation.is_cancelled());
+
+        RouterRunner::in_flight_requests()
+            .lock()
+            .unwrap()
+            .remove("cancel-round-trip");
+        assert!(matches!(
+            cancel("cancel-round-trip").result,
+            RouterResult::Error { .. }
+        ));
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::{Error, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    ipc_handler::RouteMessage, result_writer::AtomicFile, router::route::RouteStatElement,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum StatsWriterError {
+    #[error("File Creation Error {error}")]
+    FileCreateError { error: Error },
+
+    #[error("Stats Write Error {error}")]
+    CsvWrite { error: csv::Error },
+
+    #[error("Stats Flush Error {error}")]
+    Flush { error: Error },
+}
+
+/// Writes a row of statistics per route to a csv file, or a tsv file when the file extension is
+/// `tsv`. Road type, surface and smoothness percentages get a column for every value found in
+/// any of the routes, so runs with different rules can be compared side by side
+pub struct StatsWriter {
+    routes: Vec<RouteMessage>,
+    file_name: PathBuf,
+}
+
+fn get_keys<'a>(
+    routes: &'a [RouteMessage],
+    get_map: impl Fn(&'a RouteMessage) -> &'a HashMap<String, RouteStatElement>,
+) -> Vec<&'a String> {
+    routes
+        .iter()
+        .flat_map(|route| get_map(route).keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+fn get_percentage(map: &HashMap<String, RouteStatElement>, key: &String) -> String {
+    format!("{:.2}", map.get(key).map_or(0., |stat| stat.percentage))
+}
+
+impl StatsWriter {
+    pub fn new(routes: Vec<RouteMessage>, file_name: PathBuf) -> Self {
+        Self { routes, file_name }
+    }
+
+    fn write_table<W: Write>(&self, writer: W, delimiter: u8) -> Result<(), StatsWriterError> {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .from_writer(writer);
+
+        let highways = get_keys(&self.routes, |route| &route.stats.highway);
+        let surfaces = get_keys(&self.routes, |route| &route.stats.surface);
+        let smoothness = get_keys(&self.routes, |route| &route.stats.smoothness);
+
+        let mut header = [
+            "index",
+            "len_m",
+            "travel_time_s",
+            "score",
+            "junction_count",
+            "cluster",
+            "cluster_best",
+            "partial",
+            "curviness",
+            "urban_len_m",
+            "rural_len_m",
+            "ascent_m",
+            "descent_m",
+        ]
+        .map(String::from)
+        .to_vec();
+        header.extend(highways.iter().map(|key| format!("highway_{key}_percent")));
+        header.extend(surfaces.iter().map(|key| format!("surface_{key}_percent")));
+        header.extend(
+            smoothness
+                .iter()
+                .map(|key| format!("smoothness_{key}_percent")),
+        );
+        writer
+            .write_record(&header)
+            .map_err(|error| StatsWriterError::CsvWrite { error })?;
+
+        for (idx, route) in self.routes.iter().enumerate() {
+            let stats = &route.stats;
+            let mut row = vec![
+                idx.to_string(),
+                format!("{:.0}", stats.len_m),
+                format!("{:.0}", stats.travel_time_s),
+                format!("{:.2}", stats.score),
+                stats.junction_count.to_string(),
+                stats.cluster.map_or(String::new(), |c| c.to_string()),
+                stats.cluster_best.to_string(),
+                stats.partial.to_string(),
+                format!("{:.1}", stats.curviness),
+                format!("{:.0}", stats.urban_len_m),
+                format!("{:.0}", stats.rural_len_m),
+                format!("{:.0}", stats.ascent_m),
+                format!("{:.0}", stats.descent_m),
+            ];
+            row.extend(
+                highways
+                    .iter()
+                    .map(|key| get_percentage(&stats.highway, key)),
+            );
+            row.extend(
+                surfaces
+                    .iter()
+                    .map(|key| get_percentage(&stats.surface, key)),
+            );
+            row.extend(
+                smoothness
+                    .iter()
+                    .map(|key| get_percentage(&stats.smoothness, key)),
+            );
+            writer
+                .write_record(&row)
+                .map_err(|error| StatsWriterError::CsvWrite { error })?;
+        }
+        writer
+            .flush()
+            .map_err(|error| StatsWriterError::Flush { error })
+    }
+
+    pub fn write_stats(self) -> Result<(), StatsWriterError> {
+        let delimiter = if self.file_name.extension().is_some_and(|ext| ext == "tsv") {
+            b'\t'
+        } else {
+            b','
+        };
+        let mut file = AtomicFile::create(&self.file_name)
+            .map_err(|error| StatsWriterError::FileCreateError { error })?;
+        self.write_table(&mut file, delimiter)?;
+        file.commit()
+            .map_err(|error| StatsWriterError::Flush { error })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ipc_handler::RouteMessage, router::route::RouteStats};
+
+    use super::StatsWriter;
+
+    #[test]
+    fn stats_table_columns() {
+        let route = |highway: &str| {
+            let stats: RouteStats = serde_json::from_str(&format!(
+                r#"{{"len_m": 1000, "junction_count": 3, "highway": {{"{highway}": {{"len_m": 1000, "percentage": 100}}}}, "surface": {{}}, "smoothness": {{}}, "score": 4.5, "cluster": 1, "approximated_route": []}}"#
+            ))
+            .unwrap();
+            RouteMessage {
+                coords: Vec::new(),
+                stats,
+                segments: None,
+            }
+        };
+        let writer = StatsWriter::new(vec![route("primary"), route("track")], "stats.tsv".into());
+        let mut output = Vec::new();
+        writer.write_table(&mut output, b'\t').unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with("descent_m\thighway_primary_percent\thighway_track_percent"));
+        assert_eq!(
+            lines[1],
+            "0\t1000\t0\t4.50\t3\t1\tfalse\tfalse\t0.0\t0\t0\t0\t0\t100.00\t0.00"
+        );
+        assert!(lines[2].ends_with("\t0.00\t100.00"));
+    }
+}
@@ -0,0 +1,212 @@
+use std::io::{self, IsTerminal};
+
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Layer, Registry};
+
+use crate::timings::{self, TimingsReport};
+
+#[derive(Debug, thiserror::Error)]
+pub enum TelemetryError {
+    #[error("Subscriber setup failed: {error}")]
+    SetGlobalDefault {
+        error: tracing::subscriber::SetGlobalDefaultError,
+    },
+
+    #[cfg(feature = "otel")]
+    #[error("Could not create the OTLP exporter: {error}")]
+    OtlpExporter {
+        error: opentelemetry::trace::TraceError,
+    },
+
+    #[cfg(feature = "profiling-puffin")]
+    #[error("Could not create the profile file: {error}")]
+    PuffinFile { error: std::io::Error },
+}
+
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum LogFormat {
+    /// A JSON object per line
+    Json,
+    /// Several lines per event, for reading
+    Pretty,
+    /// A short line per event
+    Compact,
+}
+
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct TelemetryOptions {
+    #[arg(long, global = true, value_name = "LEVEL", default_value = "info")]
+    /// Log events of this level and above. `RUST_LOG` directives like
+    /// `ridi_router::router::navigator=trace` are added to it
+    pub log_level: LogLevel,
+
+    #[arg(long, global = true, value_name = "FORMAT")]
+    /// Format of the logs written to stderr, text in a terminal and JSON otherwise by default
+    pub log_format: Option<LogFormat>,
+
+    #[arg(long, global = true)]
+    /// Print the time spent reading and building the map data, reading and writing the cache,
+    /// generating routes and writing the results to stderr when the router exits
+    pub timings: bool,
+
+    #[cfg(feature = "otel")]
+    #[arg(long, global = true, value_name = "URL", env = "RIDI_OTEL_ENDPOINT")]
+    /// Export the traces to an OTLP collector over HTTP, for example
+    /// http://localhost:4318/v1/traces
+    pub otel_endpoint: Option<String>,
+
+    #[cfg(feature = "profiling-puffin")]
+    #[arg(long, global = true, value_name = "FILE")]
+    /// Record the profiler zones and write the frames to this file when the router exits, to open
+    /// with puffin_viewer
+    pub puffin_file: Option<std::path::PathBuf>,
+}
+
+pub(crate) type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Keeps the exporters running, flushes the traces that were not exported yet when dropped
+#[must_use]
+pub struct TelemetryGuard {
+    timings: Option<TimingsReport>,
+    #[cfg(feature = "otel")]
+    tracer_provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+    #[cfg(feature = "profiling-puffin")]
+    puffin_frames: Option<(std::fs::File, puffin::GlobalFrameView)>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if let Some(timings) = self.timings.take() {
+            eprint!("{timings}");
+        }
+        #[cfg(feature = "otel")]
+        if let Some(tracer_provider) = self.tracer_provider.take() {
+            if let Err(error) = tracer_provider.shutdown() {
+                tracing::error!(error = ?error, "Could not flush the traces");
+            }
+        }
+        #[cfg(feature = "profiling-puffin")]
+        if let Some((mut file, frames)) = self.puffin_frames.take() {
+            puffin::GlobalProfiler::lock().new_frame();
+            if let Err(error) = frames.lock().write(&mut file) {
+                tracing::error!(error = ?error, "Could not write the profile");
+            }
+        }
+    }
+}
+
+/// The log level with the `RUST_LOG` directives, invalid directives are skipped
+fn env_filter(level: LogLevel) -> EnvFilter {
+    EnvFilter::builder()
+        .with_default_directive(LevelFilter::from(level).into())
+        .from_env_lossy()
+}
+
+/// Logs to stderr, as text in a terminal and as JSON otherwise unless the format is given
+fn fmt_layer(options: &TelemetryOptions) -> BoxedLayer {
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .with_file(true)
+        .with_line_number(true)
+        .with_thread_names(true);
+    let filter = env_filter(options.log_level);
+    match options.log_format {
+        Some(LogFormat::Json) => layer.json().with_filter(filter).boxed(),
+        Some(LogFormat::Pretty) => layer.pretty().with_filter(filter).boxed(),
+        Some(LogFormat::Compact) => layer.compact().with_filter(filter).boxed(),
+        None if io::stdin().is_terminal() => layer.with_filter(filter).boxed(),
+        None => layer.json().with_filter(filter).boxed(),
+    }
+}
+
+#[cfg(feature = "otel")]
+fn otlp_layer(
+    endpoint: &str,
+    level: LogLevel,
+) -> Result<(BoxedLayer, opentelemetry_sdk::trace::SdkTracerProvider), TelemetryError> {
+    use opentelemetry::trace::TracerProvider;
+    use opentelemetry_otlp::WithExportConfig;
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|error| TelemetryError::OtlpExporter { error })?;
+    let tracer_provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            opentelemetry_sdk::Resource::builder()
+                .with_service_name("ridi-router")
+                .build(),
+        )
+        .build();
+    let layer = tracing_opentelemetry::layer()
+        .with_tracer(tracer_provider.tracer("ridi-router"))
+        .with_filter(env_filter(level))
+        .boxed();
+    Ok((layer, tracer_provider))
+}
+
+/// Sets up the global tracing subscriber, with the exporters enabled in the options
+pub fn init(options: &TelemetryOptions) -> Result<TelemetryGuard, TelemetryError> {
+    #[allow(unused_mut)]
+    let mut layers = vec![fmt_layer(options)];
+    #[allow(unused_mut)]
+    let mut guard = TelemetryGuard {
+        timings: None,
+        #[cfg(feature = "otel")]
+        tracer_provider: None,
+        #[cfg(feature = "profiling-puffin")]
+        puffin_frames: None,
+    };
+
+    if options.timings {
+        let (layer, report) = timings::layer();
+        layers.push(layer);
+        guard.timings = Some(report);
+    }
+
+    #[cfg(feature = "otel")]
+    if let Some(endpoint) = &options.otel_endpoint {
+        let (layer, tracer_provider) = otlp_layer(endpoint, options.log_level)?;
+        layers.push(layer);
+        guard.tracer_provider = Some(tracer_provider);
+    }
+
+    #[cfg(feature = "profiling-puffin")]
+    if let Some(puffin_file) = &options.puffin_file {
+        let file = std::fs::File::create(puffin_file)
+            .map_err(|error| TelemetryError::PuffinFile { error })?;
+        // the frame view collects the frames from when it is created
+        let frames = puffin::GlobalFrameView::default();
+        puffin::set_scopes_on(true);
+        guard.puffin_frames = Some((file, frames));
+    }
+
+    tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layers))
+        .map_err(|error| TelemetryError::SetGlobalDefault { error })?;
+    Ok(guard)
+}
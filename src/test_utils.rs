@@ -18,6 +18,7 @@ fn make_osm_point_with_id(id: u64) -> OsmNode {
         id,
         residential_in_proximity: false,
         nogo_area: false,
+        elevation: None,
     }
 }
 
@@ -153,6 +154,7 @@ pub fn test_dataset_1() -> OsmTestData {
                 lon: 1.0,
                 residential_in_proximity: false,
                 nogo_area: false,
+                elevation: None,
             },
             OsmNode {
                 id: 2,
@@ -160,6 +162,7 @@ pub fn test_dataset_1() -> OsmTestData {
                 lon: 2.0,
                 residential_in_proximity: false,
                 nogo_area: false,
+                elevation: None,
             },
             OsmNode {
                 id: 3,
@@ -167,6 +170,7 @@ pub fn test_dataset_1() -> OsmTestData {
                 lon: 3.0,
                 residential_in_proximity: false,
                 nogo_area: false,
+                elevation: None,
             },
             OsmNode {
                 id: 4,
@@ -174,6 +178,7 @@ pub fn test_dataset_1() -> OsmTestData {
                 lon: 4.0,
                 residential_in_proximity: false,
                 nogo_area: false,
+                elevation: None,
             },
             OsmNode {
                 id: 5,
@@ -181,6 +186,7 @@ pub fn test_dataset_1() -> OsmTestData {
                 lon: 5.0,
                 residential_in_proximity: false,
                 nogo_area: false,
+                elevation: None,
             },
             OsmNode {
                 id: 6,
@@ -188,6 +194,7 @@ pub fn test_dataset_1() -> OsmTestData {
                 lon: 6.0,
                 residential_in_proximity: false,
                 nogo_area: false,
+                elevation: None,
             },
             OsmNode {
                 id: 7,
@@ -195,6 +202,7 @@ pub fn test_dataset_1() -> OsmTestData {
                 lon: 7.0,
                 residential_in_proximity: false,
                 nogo_area: false,
+                elevation: None,
             },
             OsmNode {
                 id: 8,
@@ -202,6 +210,7 @@ pub fn test_dataset_1() -> OsmTestData {
                 lon: 8.0,
                 residential_in_proximity: false,
                 nogo_area: false,
+                elevation: None,
             },
             OsmNode {
                 id: 9,
@@ -209,6 +218,7 @@ pub fn test_dataset_1() -> OsmTestData {
                 lon: 9.0,
                 residential_in_proximity: false,
                 nogo_area: false,
+                elevation: None,
             },
             OsmNode {
                 id: 11,
@@ -216,6 +226,7 @@ pub fn test_dataset_1() -> OsmTestData {
                 lon: 11.0,
                 residential_in_proximity: false,
                 nogo_area: false,
+                elevation: None,
             },
             OsmNode {
                 id: 12,
@@ -223,6 +234,7 @@ pub fn test_dataset_1() -> OsmTestData {
                 lon: 12.0,
                 residential_in_proximity: false,
                 nogo_area: false,
+                elevation: None,
             },
         ],
         vec![
@@ -277,6 +289,7 @@ pub fn test_dataset_3() -> OsmTestData {
                 lon: 1.0,
                 residential_in_proximity: false,
                 nogo_area: false,
+                elevation: None,
             },
             OsmNode {
                 id: 3,
@@ -284,6 +297,7 @@ pub fn test_dataset_3() -> OsmTestData {
                 lon: 3.0,
                 residential_in_proximity: false,
                 nogo_area: false,
+                elevation: None,
             },
             OsmNode {
                 id: 4,
@@ -291,6 +305,7 @@ pub fn test_dataset_3() -> OsmTestData {
                 lon: 4.0,
                 residential_in_proximity: false,
                 nogo_area: false,
+                elevation: None,
             },
             OsmNode {
                 id: 5,
@@ -298,6 +313,7 @@ pub fn test_dataset_3() -> OsmTestData {
                 lon: 5.0,
                 residential_in_proximity: false,
                 nogo_area: false,
+                elevation: None,
             },
             OsmNode {
                 id: 6,
@@ -305,6 +321,7 @@ pub fn test_dataset_3() -> OsmTestData {
                 lon: 6.0,
                 residential_in_proximity: false,
                 nogo_area: false,
+                elevation: None,
             },
             OsmNode {
                 id: 7,
@@ -312,6 +329,7 @@ pub fn test_dataset_3() -> OsmTestData {
                 lon: 7.0,
                 residential_in_proximity: false,
                 nogo_area: false,
+                elevation: None,
             },
         ],
         vec![
@@ -365,7 +383,9 @@ pub fn graph_from_test_dataset(test_data: OsmTestData) -> MapDataGraph {
     let mut map_data = MapDataGraph::new();
     let (test_nodes, test_ways, test_relations) = &test_data;
     for test_node in test_nodes {
-        map_data.insert_node(test_node.clone());
+        map_data
+            .insert_node(test_node.clone())
+            .expect("failed to insert node");
     }
     for test_way in test_ways {
         map_data
@@ -381,8 +401,11 @@ pub fn graph_from_test_dataset(test_data: OsmTestData) -> MapDataGraph {
     map_data
 }
 
-pub fn set_graph_static(map_data: MapDataGraph) -> &'static MapDataGraph {
-    MAP_DATA_GRAPH.get_or_init(|| map_data)
+pub fn set_graph_static(mut map_data: MapDataGraph) -> &'static MapDataGraph {
+    map_data.place_point_elements();
+    let graph = MAP_DATA_GRAPH.get_or_init(|| map_data);
+    graph.load_junction_edges();
+    graph
 }
 
 pub fn line_is_between_point_ids(line: &MapDataLineRef, id1: u64, id2: u64) -> bool {
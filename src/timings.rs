@@ -0,0 +1,157 @@
+//! Time spent in the phases of a run, reported when the router exits with `--timings`. A phase is
+//! a span with the `ridi_router::timings` target, so the phases also show up in the logs and traces
+
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
+
+use tracing::{span, Level, Subscriber};
+use tracing_subscriber::{filter::Targets, layer::Context, registry::LookupSpan, Layer};
+use web_time::{Duration, Instant};
+
+use crate::telemetry::BoxedLayer;
+
+pub(crate) const TIMINGS_TARGET: &str = "ridi_router::timings";
+
+/// Enters a phase that lasts until the returned guard is dropped. Phases are `parse` and `build`
+/// of the map data, `cache_read`, `unpack`, `pack` and `cache_write` of the cache, `route` and
+/// `write` of the results
+macro_rules! phase {
+    ($name:literal) => {
+        tracing::info_span!(target: $crate::timings::TIMINGS_TARGET, $name).entered()
+    };
+}
+
+pub(crate) use phase;
+
+struct PhaseTiming {
+    name: &'static str,
+    count: u32,
+    total: Duration,
+}
+
+/// Phase durations summed by name, in the order the phases were first entered
+#[derive(Clone)]
+pub struct TimingsReport {
+    start: Instant,
+    phases: Arc<Mutex<Vec<PhaseTiming>>>,
+}
+
+impl TimingsReport {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            phases: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    fn record(&self, name: &'static str, duration: Duration) {
+        let mut phases = self
+            .phases
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        match phases.iter_mut().find(|phase| phase.name == name) {
+            Some(phase) => {
+                phase.count += 1;
+                phase.total += duration;
+            }
+            None => phases.push(PhaseTiming {
+                name,
+                count: 1,
+                total: duration,
+            }),
+        }
+    }
+}
+
+impl Display for TimingsReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Timings, {:.3}s in total",
+            self.start.elapsed().as_secs_f64()
+        )?;
+        let phases = self
+            .phases
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        for phase in phases.iter() {
+            write!(
+                f,
+                "  {:<12}{:>10.3}s",
+                phase.name,
+                phase.total.as_secs_f64()
+            )?;
+            if phase.count > 1 {
+                write!(f, "  {} times", phase.count)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+struct PhaseStart(Instant);
+
+struct TimingsLayer {
+    report: TimingsReport,
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for TimingsLayer {
+    fn on_new_span(&self, _attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(PhaseStart(Instant::now()));
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(&id) {
+            if let Some(PhaseStart(start)) = span.extensions().get::<PhaseStart>() {
+                self.report.record(span.name(), start.elapsed());
+            }
+        }
+    }
+}
+
+/// Layer that records the phases into the returned report
+pub fn layer() -> (BoxedLayer, TimingsReport) {
+    let report = TimingsReport::new();
+    let layer = TimingsLayer {
+        report: report.clone(),
+    }
+    .with_filter(Targets::new().with_target(TIMINGS_TARGET, Level::INFO))
+    .boxed();
+    (layer, report)
+}
+
+#[cfg(test)]
+mod test {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::layer;
+
+    #[test]
+    fn phases_summed_in_order() {
+        let (layer, report) = layer();
+        let subscriber = tracing_subscriber::registry().with(vec![layer]);
+        tracing::subscriber::with_default(subscriber, || {
+            {
+                let _parse = phase!("parse");
+                tracing::info_span!(target: "ridi_router::router", "not_a_phase").in_scope(|| {});
+            }
+            for _ in 0..2 {
+                let _route = phase!("route");
+            }
+        });
+
+        let report = report.to_string();
+        let lines = report.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("Timings, "));
+        assert!(lines[1].trim_start().starts_with("parse"));
+        assert!(!lines[1].ends_with("times"));
+        assert!(lines[2].trim_start().starts_with("route"));
+        assert!(lines[2].ends_with("2 times"));
+    }
+}
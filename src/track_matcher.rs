@@ -0,0 +1,206 @@
+use std::{fs::File, io::BufReader, path::PathBuf};
+
+use gpx::errors::GpxError;
+use tracing::{info, trace};
+
+use crate::{
+    map_data::graph::{MapDataGraph, MapDataPointRef},
+    router::{
+        generator::RouteWithStats,
+        route::{segment::Segment, Route},
+        rules::RouterRules,
+        shortest_path::shortest_route,
+    },
+    timings,
+};
+
+/// A connection between two matched points longer than this ratio of the straight line distance
+/// is most likely caused by a point snapped to a nearby parallel road and is skipped
+const MAX_DETOUR_RATIO: f32 = 3.;
+/// Allowance for short connections where the ratio is not meaningful
+const MAX_DETOUR_ALLOWANCE_M: f32 = 200.;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TrackMatcherError {
+    #[error("Failed to open GPX file: {error}")]
+    FileOpen { error: std::io::Error },
+
+    #[error("Failed to read GPX: {error}")]
+    GpxRead { error: GpxError },
+
+    #[error("GPX file contains no track or route points")]
+    NoPoints,
+
+    #[error("None of the track points could be matched to the map")]
+    NoMatch,
+}
+
+pub struct TrackMatcher;
+
+impl TrackMatcher {
+    /// Reads coordinates of all track points, or route points if there are no tracks
+    #[tracing::instrument]
+    pub fn read_gpx(file: &PathBuf) -> Result<Vec<(f32, f32)>, TrackMatcherError> {
+        let file = File::open(file).map_err(|error| TrackMatcherError::FileOpen { error })?;
+        let gpx = gpx::read(BufReader::new(file))
+            .map_err(|error| TrackMatcherError::GpxRead { error })?;
+
+        let mut coords = gpx
+            .tracks
+            .iter()
+            .flat_map(|track| track.segments.iter())
+            .flat_map(|segment| segment.points.iter())
+            .map(|point| (point.point().y() as f32, point.point().x() as f32))
+            .collect::<Vec<_>>();
+        if coords.is_empty() {
+            coords = gpx
+                .routes
+                .iter()
+                .flat_map(|route| route.points.iter())
+                .map(|point| (point.point().y() as f32, point.point().x() as f32))
+                .collect();
+        }
+        if coords.is_empty() {
+            return Err(TrackMatcherError::NoPoints);
+        }
+        Ok(coords)
+    }
+
+    /// Snaps the coordinates to the closest map points and connects them with the shortest path
+    /// between each pair to get the line sequence that was most likely taken.
+    #[tracing::instrument(skip(coords, rules))]
+    pub fn match_track(
+        coords: &[(f32, f32)],
+        rules: &RouterRules,
+    ) -> Result<RouteWithStats, TrackMatcherError> {
+        let _phase = timings::phase!("route");
+        let mut matched_points: Vec<MapDataPointRef> = Vec::new();
+        for (lat, lon) in coords {
+            if let Some(point) =
+                MapDataGraph::get().get_closest_to_coords(*lat, *lon, rules, false, None)
+            {
+                if matched_points.last() != Some(&point) {
+                    matched_points.push(point);
+                }
+            }
+        }
+        let Some(first_point) = matched_points.first() else {
+            return Err(TrackMatcherError::NoMatch);
+        };
+
+        let mut segments: Vec<Segment> = Vec::new();
+        let mut skipped_count = 0;
+        let mut current = first_point.clone();
+        for next in matched_points.iter().skip(1) {
+            let Some(connection) = shortest_route(&current, next, rules) else {
+                skipped_count += 1;
+                continue;
+            };
+            let connection_len: f32 = connection
+                .iter()
+                .map(|segment| segment.get_line().borrow().get_len_m())
+                .sum();
+            let straight_len = current.borrow().distance_between(next);
+            if connection_len > straight_len * MAX_DETOUR_RATIO + MAX_DETOUR_ALLOWANCE_M {
+                skipped_count += 1;
+                continue;
+            }
+            trace!(
+                from = current.borrow().id,
+                to = next.borrow().id,
+                connection_len,
+                "Matched connection"
+            );
+            segments.extend(connection);
+            current = next.clone();
+        }
+
+        info!(
+            track_point_count = coords.len(),
+            matched_point_count = matched_points.len(),
+            skipped_count,
+            segment_count = segments.len(),
+            "Track matched"
+        );
+
+        let route = Route::from(segments);
+        Ok(RouteWithStats {
+            stats: route.calc_stats(rules),
+            route,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusty_fork::rusty_fork_test;
+
+    use crate::{
+        router::rules::RouterRules,
+        test_utils::{graph_from_test_dataset, set_graph_static, test_dataset_1},
+    };
+
+    use super::{TrackMatcher, TrackMatcherError};
+
+    #[test]
+    fn read_gpx_points() {
+        let dir = std::env::temp_dir().join(format!("ridi-track-matcher-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let header = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1">"#;
+        std::fs::write(
+            dir.join("track.gpx"),
+            format!(
+                r#"{header}<trk><trkseg><trkpt lat="57.1" lon="24.8"></trkpt><trkpt lat="57.2" lon="24.9"></trkpt></trkseg></trk><rte><rtept lat="1" lon="1"></rtept></rte></gpx>"#
+            ),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("route.gpx"),
+            format!(r#"{header}<rte><rtept lat="57.1" lon="24.8"></rtept></rte></gpx>"#),
+        )
+        .unwrap();
+        std::fs::write(dir.join("empty.gpx"), format!("{header}</gpx>")).unwrap();
+
+        let track = TrackMatcher::read_gpx(&dir.join("track.gpx"));
+        let route = TrackMatcher::read_gpx(&dir.join("route.gpx"));
+        let empty = TrackMatcher::read_gpx(&dir.join("empty.gpx"));
+        let missing = TrackMatcher::read_gpx(&dir.join("missing.gpx"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(track.unwrap(), vec![(57.1, 24.8), (57.2, 24.9)]);
+        assert_eq!(route.unwrap(), vec![(57.1, 24.8)]);
+        assert!(matches!(empty, Err(TrackMatcherError::NoPoints)));
+        assert!(matches!(missing, Err(TrackMatcherError::FileOpen { .. })));
+    }
+
+    rusty_fork_test! {
+        #![rusty_fork(timeout_ms = 2000)]
+        #[test]
+        fn match_track_points() {
+            let mut map_data = graph_from_test_dataset(test_dataset_1());
+            map_data.generate_point_hashes();
+            set_graph_static(map_data);
+            let rules = RouterRules::default();
+
+            // 11 can't be reached from 9, so it is skipped and the track continues from 9
+            let matched = TrackMatcher::match_track(
+                &[(1., 1.), (1.9, 2.1), (4., 4.), (9., 9.), (11., 11.), (9., 9.)],
+                &rules,
+            )
+            .unwrap();
+            let point_ids = matched
+                .route
+                .iter()
+                .map(|segment| segment.get_end_point().borrow().id)
+                .collect::<Vec<_>>();
+            assert_eq!(point_ids, vec![2, 3, 4, 8, 9]);
+            assert!(matched.stats.len_m > 0.);
+
+            assert!(matches!(
+                TrackMatcher::match_track(&[], &rules),
+                Err(TrackMatcherError::NoMatch)
+            ));
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use geo::{Bearing, Distance, Haversine, Point};
+
+use crate::ipc_handler::RouteMessage;
+
+/// Heading is compared this far before and after a point, so the zig-zag of a single road in the
+/// map data is not taken as a turn
+const TURN_LOOKAROUND_M: f32 = 30.;
+/// Heading change from which a point is a turn
+const TURN_MIN_DEG: f32 = 35.;
+/// Turns closer than this to the previous turn are left out
+const TURN_MIN_SPACING_M: f32 = 100.;
+/// Turns this close to a roundabout exit are left out, the exit is a turn of its own
+const ROUNDABOUT_RADIUS_M: f32 = 100.;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TurnKind {
+    SlightLeft,
+    Left,
+    SharpLeft,
+    SlightRight,
+    Right,
+    SharpRight,
+    UTurn,
+    RoundaboutExit { exit_number: u32 },
+}
+
+impl TurnKind {
+    fn from_heading_change(change_deg: f32) -> Self {
+        match change_deg {
+            c if c.abs() >= 160. => Self::UTurn,
+            c if c <= -120. => Self::SharpLeft,
+            c if c <= -60. => Self::Left,
+            c if c < 0. => Self::SlightLeft,
+            c if c >= 120. => Self::SharpRight,
+            c if c >= 60. => Self::Right,
+            _ => Self::SlightRight,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::SlightLeft => "Slight left".to_string(),
+            Self::Left => "Left".to_string(),
+            Self::SharpLeft => "Sharp left".to_string(),
+            Self::SlightRight => "Slight right".to_string(),
+            Self::Right => "Right".to_string(),
+            Self::SharpRight => "Sharp right".to_string(),
+            Self::UTurn => "U-turn".to_string(),
+            Self::RoundaboutExit { exit_number } => format!("Exit {exit_number}"),
+        }
+    }
+}
+
+/// Turn along the route, there are no turn instructions in the map data so turns are found where
+/// the heading of the route changes, and roundabout exits from the route stats
+#[derive(Debug, Clone, PartialEq)]
+pub struct Turn {
+    pub coord_idx: usize,
+    pub kind: TurnKind,
+}
+
+/// Distance from the start of the route for every coordinate
+pub fn get_coord_distances_m(coords: &[(f32, f32)]) -> Vec<f32> {
+    let mut distances_m = Vec::with_capacity(coords.len());
+    let mut distance_m = 0.;
+    for (idx, (lat, lon)) in coords.iter().enumerate() {
+        if let Some((prev_lat, prev_lon)) = idx.checked_sub(1).map(|i| coords[i]) {
+            distance_m +=
+                Haversine.distance(Point::new(prev_lon, prev_lat), Point::new(*lon, *lat));
+        }
+        distances_m.push(distance_m);
+    }
+    distances_m
+}
+
+pub fn get_turns(route: &RouteMessage, distances_m: &[f32]) -> Vec<Turn> {
+    let coords = &route.coords;
+    let point = |idx: usize| Point::new(coords[idx].1, coords[idx].0);
+    let roundabout_exits = route
+        .stats
+        .roundabout_exits
+        .iter()
+        .map(|exit| (Point::new(exit.lon, exit.lat), exit.exit_number))
+        .collect::<Vec<_>>();
+
+    let mut turns: Vec<Turn> = Vec::new();
+    let mut exits_added = vec![false; roundabout_exits.len()];
+    for idx in 1..coords.len().saturating_sub(1) {
+        if let Some(exit_idx) = roundabout_exits
+            .iter()
+            .position(|(exit, _)| Haversine.distance(*exit, point(idx)) < 1.)
+        {
+            if !exits_added[exit_idx] {
+                exits_added[exit_idx] = true;
+                turns.push(Turn {
+                    coord_idx: idx,
+                    kind: TurnKind::RoundaboutExit {
+                        exit_number: roundabout_exits[exit_idx].1,
+                    },
+                });
+            }
+            continue;
+        }
+        if roundabout_exits
+            .iter()
+            .any(|(exit, _)| Haversine.distance(*exit, point(idx)) < ROUNDABOUT_RADIUS_M)
+        {
+            continue;
+        }
+        if turns
+            .last()
+            .is_some_and(|last| distances_m[idx] - distances_m[last.coord_idx] < TURN_MIN_SPACING_M)
+        {
+            continue;
+        }
+
+        let Some(before_idx) =
+            (0..idx).rfind(|i| distances_m[idx] - distances_m[*i] >= TURN_LOOKAROUND_M)
+        else {
+            continue;
+        };
+        let Some(after_idx) = (idx + 1..coords.len())
+            .find(|i| distances_m[*i] - distances_m[idx] >= TURN_LOOKAROUND_M)
+        else {
+            continue;
+        };
+        let bearing_in = Haversine.bearing(point(before_idx), point(idx));
+        let bearing_out = Haversine.bearing(point(idx), point(after_idx));
+        let change_deg = (bearing_out - bearing_in + 540.) % 360. - 180.;
+        if change_deg.abs() < TURN_MIN_DEG {
+            continue;
+        }
+        turns.push(Turn {
+            coord_idx: idx,
+            kind: TurnKind::from_heading_change(change_deg),
+        });
+    }
+    turns
+}
+
+/// Elevation for every coordinate, interpolated by distance from the elevation profile of the
+/// route, none when the map data has no elevation
+pub fn get_coord_elevations(route: &RouteMessage, distances_m: &[f32]) -> Option<Vec<f32>> {
+    let profile = &route.stats.elevation_profile;
+    let first = profile.first()?;
+    let last = profile.last()?;
+    Some(
+        distances_m
+            .iter()
+            .map(|distance_m| {
+                let distance_m = f64::from(*distance_m);
+                match profile.iter().position(|(len_m, _)| *len_m >= distance_m) {
+                    None => last.1,
+                    Some(0) => first.1,
+                    Some(idx) => {
+                        let (len_before, ele_before) = profile[idx - 1];
+                        let (len_after, ele_after) = profile[idx];
+                        let ratio = ((distance_m - len_before) / (len_after - len_before)) as f32;
+                        ele_before + (ele_after - ele_before) * ratio
+                    }
+                }
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ipc_handler::RouteMessage, router::route::RouteStats};
+
+    use super::{get_coord_distances_m, get_coord_elevations, get_turns, TurnKind};
+
+    #[test]
+    fn turns_and_elevations() {
+        let coords = vec![
+            (57.0, 24.0),
+            (57.001, 24.0),
+            (57.002, 24.0),
+            (57.002, 24.002),
+            (57.002, 24.004),
+        ];
+        let mut stats: RouteStats = serde_json::from_str(
+            r#"{"len_m": 470, "junction_count": 0, "highway": {}, "surface": {}, "smoothness": {}, "score": 0, "cluster": null, "approximated_route": []}"#,
+        )
+        .unwrap();
+        stats.elevation_profile = vec![(0., 100.), (222., 200.)];
+        let route = RouteMessage {
+            coords,
+            stats,
+            segments: None,
+        };
+
+        let distances_m = get_coord_distances_m(&route.coords);
+        assert_eq!(distances_m.len(), 5);
+        assert!((distances_m[2] - 222.6).abs() < 1.);
+
+        let turns = get_turns(&route, &distances_m);
+        assert_eq!(turns.len(), 1);
+        assert_eq!(turns[0].coord_idx, 2);
+        assert_eq!(turns[0].kind, TurnKind::Right);
+
+        let elevations = get_coord_elevations(&route, &[0., 111., 222., 400.]).unwrap();
+        assert_eq!(elevations, vec![100., 150., 200., 200.]);
+    }
+}
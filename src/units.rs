@@ -0,0 +1,107 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+pub const M_IN_MI: f64 = 1609.344;
+const M_IN_FT: f64 = 0.3048;
+
+/// Unit system of distances in human readable output, route generation always uses meters
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Units {
+    /// Kilometers and meters
+    #[default]
+    Metric,
+    /// Miles and feet
+    Imperial,
+}
+
+impl Units {
+    /// Name of the long distance unit
+    pub fn distance_unit(&self) -> &'static str {
+        match self {
+            Self::Metric => "km",
+            Self::Imperial => "mi",
+        }
+    }
+
+    /// Meters in the long distance unit
+    fn distance_unit_m(&self) -> f64 {
+        match self {
+            Self::Metric => 1000.,
+            Self::Imperial => M_IN_MI,
+        }
+    }
+
+    /// Meters in the long distance unit, like 12.35km or 7.67mi
+    pub fn distance(&self, distance_m: f64, decimals: usize) -> String {
+        format!(
+            "{:.decimals$}{}",
+            distance_m / self.distance_unit_m(),
+            self.distance_unit()
+        )
+    }
+
+    /// Meters in the short distance unit used for elevation, like 350m or 1148ft
+    pub fn elevation(&self, elevation_m: f64) -> String {
+        match self {
+            Self::Metric => format!("{elevation_m:.0}m"),
+            Self::Imperial => format!("{:.0}ft", elevation_m / M_IN_FT),
+        }
+    }
+
+    /// Value given per kilometer in the long distance unit
+    pub fn per_distance(&self, per_km: f64) -> f64 {
+        per_km * self.distance_unit_m() / 1000.
+    }
+}
+
+/// Parses a distance with an optional unit, like 150mi, 80km, 5000m or 2500ft, into meters.
+/// Distances without a unit are meters
+pub fn parse_distance_m(distance: &str) -> Result<u32, String> {
+    let distance = distance.trim();
+    let split = distance
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(distance.len());
+    let (value, unit) = distance.split_at(split);
+    let unit_m = match unit.trim().to_lowercase().as_str() {
+        "" | "m" => 1.,
+        "km" => 1000.,
+        "mi" => M_IN_MI,
+        "ft" => M_IN_FT,
+        unit => {
+            return Err(format!(
+                "unknown distance unit '{unit}', expected one of m, km, mi or ft"
+            ))
+        }
+    };
+    value
+        .parse::<f64>()
+        .ok()
+        .map(|value| value * unit_m)
+        .filter(|distance_m| *distance_m >= 0. && *distance_m <= u32::MAX as f64)
+        .map(|distance_m| distance_m.round() as u32)
+        .ok_or_else(|| format!("'{distance}' is not a distance like 150mi, 80km or 5000"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_distance_m, Units};
+
+    #[test]
+    fn distances_in_units() {
+        assert_eq!(parse_distance_m("5000"), Ok(5000));
+        assert_eq!(parse_distance_m("80km"), Ok(80000));
+        assert_eq!(parse_distance_m("150mi"), Ok(241402));
+        assert_eq!(parse_distance_m("1.5 KM"), Ok(1500));
+        assert_eq!(parse_distance_m("100ft"), Ok(30));
+        assert!(parse_distance_m("150 leagues").is_err());
+        assert!(parse_distance_m("mi").is_err());
+
+        assert_eq!(Units::Metric.distance(123456., 2), "123.46km");
+        assert_eq!(Units::Imperial.distance(241402., 1), "150.0mi");
+        assert_eq!(Units::Imperial.elevation(350.), "1148ft");
+        assert_eq!(Units::Imperial.per_distance(10.), 16.09344);
+    }
+}
@@ -0,0 +1,82 @@
+use serde::Serialize;
+use serde_json::json;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    map_data::graph::MAP_DATA_GRAPH,
+    osm_data::data_reader::OsmDataReader,
+    router::{generator::RouteWithStats, rules::RouterRules},
+    router_runner::{GenerateRouteOptions, RouterRunner, RoutingMode},
+};
+
+fn routes_to_geojson(routes: &[RouteWithStats]) -> serde_json::Value {
+    json!({
+        "type": "FeatureCollection",
+        "features": routes
+            .iter()
+            .map(|route| {
+                json!({
+                    "type": "Feature",
+                    "geometry": {
+                        "type": "LineString",
+                        "coordinates": route
+                            .route
+                            .iter()
+                            .map(|segment| {
+                                let point = segment.get_end_point().borrow();
+                                [point.lon, point.lat]
+                            })
+                            .collect::<Vec<_>>(),
+                    },
+                    "properties": route.stats,
+                })
+            })
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// Load map data from an OSM JSON blob in the overpass format. Map data can only be loaded once
+/// per module instance.
+#[wasm_bindgen(js_name = initMapData)]
+pub fn init_map_data(osm_json: &str) -> Result<(), JsError> {
+    if MAP_DATA_GRAPH.get().is_some() {
+        return Err(JsError::new("Map data already loaded"));
+    }
+    let map_data =
+        OsmDataReader::read_json_str(osm_json).map_err(|error| JsError::new(&error.to_string()))?;
+    MAP_DATA_GRAPH
+        .get_or_init(|| map_data)
+        .load_junction_edges();
+    Ok(())
+}
+
+/// Generate routes on the loaded map data. `routing_mode` uses the same format as in the server
+/// request message, for example `{ "StartFinish": { "start": { "lat": 57.1, "lon": 24.8 },
+/// "finish": { "lat": 57.3, "lon": 25.2 } } }`, `rules` is a rule file object or `undefined` for
+/// default rules. Returns a GeoJSON FeatureCollection with a LineString feature per route and
+/// route stats as feature properties.
+#[wasm_bindgen(js_name = generateRoutes)]
+pub fn generate_routes(routing_mode: JsValue, rules: JsValue) -> Result<JsValue, JsError> {
+    if MAP_DATA_GRAPH.get().is_none() {
+        return Err(JsError::new("Map data not loaded"));
+    }
+    let routing_mode: RoutingMode = serde_wasm_bindgen::from_value(routing_mode)
+        .map_err(|error| JsError::new(&error.to_string()))?;
+    let rules: RouterRules = if rules.is_undefined() || rules.is_null() {
+        RouterRules::default()
+    } else {
+        let rules: serde_json::Value = serde_wasm_bindgen::from_value(rules)
+            .map_err(|error| JsError::new(&error.to_string()))?;
+        // without a clock to evaluate them for, rules with `when` conditions are an error
+        RouterRules::from_value(rules, "wasm", None)
+            .map_err(|error| JsError::new(&error.to_string()))?
+    };
+
+    let routes =
+        RouterRunner::generate_route(&routing_mode, rules, GenerateRouteOptions::default())
+            .map_err(|error| JsError::new(&error.to_string()))?;
+
+    routes_to_geojson(&routes)
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(|error| JsError::new(&error.to_string()))
+}
@@ -0,0 +1,157 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use gpx::errors::GpxError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WaypointsReaderError {
+    #[error("Failed to open waypoints file: {error}")]
+    FileOpen { error: std::io::Error },
+
+    #[error("Failed to read GPX: {error}")]
+    GpxRead { error: GpxError },
+
+    #[error("Failed to read GeoJSON: {error}")]
+    GeoJsonRead { error: serde_json::Error },
+
+    #[error("GeoJSON is not a Feature or a FeatureCollection")]
+    GeoJsonType,
+
+    #[error("Waypoints file has to be a gpx, geojson or json file")]
+    FileFormat,
+
+    #[error("Waypoints file contains {count} points, at least a start and a finish are required")]
+    TooFewPoints { count: usize },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Waypoint {
+    pub lat: f32,
+    pub lon: f32,
+    pub radius: Option<f32>,
+}
+
+pub struct WaypointsReader;
+
+impl WaypointsReader {
+    /// Reads the points of a GPX or GeoJSON file in order, the first is the start, the last is
+    /// the finish and the ones between are via points
+    #[tracing::instrument]
+    pub fn read(file: &Path) -> Result<Vec<Waypoint>, WaypointsReaderError> {
+        let extension = file
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+        let reader = BufReader::new(
+            File::open(file).map_err(|error| WaypointsReaderError::FileOpen { error })?,
+        );
+        let waypoints = match extension.as_deref() {
+            Some("gpx") => Self::read_gpx(reader)?,
+            Some("geojson") | Some("json") => Self::read_geojson(
+                serde_json::from_reader(reader)
+                    .map_err(|error| WaypointsReaderError::GeoJsonRead { error })?,
+            )?,
+            _ => return Err(WaypointsReaderError::FileFormat),
+        };
+        if waypoints.len() < 2 {
+            return Err(WaypointsReaderError::TooFewPoints {
+                count: waypoints.len(),
+            });
+        }
+        Ok(waypoints)
+    }
+
+    /// Waypoints, or route points if there are no waypoints
+    fn read_gpx(reader: impl std::io::Read) -> Result<Vec<Waypoint>, WaypointsReaderError> {
+        let gpx = gpx::read(reader).map_err(|error| WaypointsReaderError::GpxRead { error })?;
+        let points = if gpx.waypoints.is_empty() {
+            gpx.routes
+                .iter()
+                .flat_map(|route| route.points.iter())
+                .collect::<Vec<_>>()
+        } else {
+            gpx.waypoints.iter().collect()
+        };
+        Ok(points
+            .into_iter()
+            .map(|point| Waypoint {
+                lat: point.point().y() as f32,
+                lon: point.point().x() as f32,
+                radius: None,
+            })
+            .collect())
+    }
+
+    /// Point features, with an optional `radius` property in meters. Features with other
+    /// geometries are skipped
+    fn read_geojson(geojson: serde_json::Value) -> Result<Vec<Waypoint>, WaypointsReaderError> {
+        let features = match geojson["type"].as_str() {
+            Some("FeatureCollection") => geojson["features"]
+                .as_array()
+                .cloned()
+                .ok_or(WaypointsReaderError::GeoJsonType)?,
+            Some("Feature") => vec![geojson],
+            _ => return Err(WaypointsReaderError::GeoJsonType),
+        };
+        Ok(features
+            .iter()
+            .filter(|feature| feature["geometry"]["type"].as_str() == Some("Point"))
+            .filter_map(|feature| {
+                let coordinates = feature["geometry"]["coordinates"].as_array()?;
+                Some(Waypoint {
+                    lat: coordinates.get(1)?.as_f64()? as f32,
+                    lon: coordinates.first()?.as_f64()? as f32,
+                    radius: feature["properties"]["radius"]
+                        .as_f64()
+                        .map(|radius| radius as f32),
+                })
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Waypoint, WaypointsReader};
+
+    #[test]
+    fn waypoints_reader_formats() {
+        let gpx = r#"<?xml version="1.0"?>
+            <gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1">
+              <wpt lat="57.0" lon="24.0"><name>Start</name></wpt>
+              <wpt lat="57.1" lon="24.2"></wpt>
+              <wpt lat="57.2" lon="24.4"></wpt>
+            </gpx>"#;
+        let waypoints = WaypointsReader::read_gpx(gpx.as_bytes()).unwrap();
+        assert_eq!(waypoints.len(), 3);
+        assert_eq!(
+            waypoints[1],
+            Waypoint {
+                lat: 57.1,
+                lon: 24.2,
+                radius: None
+            }
+        );
+
+        let geojson = serde_json::json!({
+            "type": "FeatureCollection",
+            "features": [
+                {"type": "Feature", "geometry": {"type": "Point", "coordinates": [24.0, 57.0]}},
+                {"type": "Feature", "geometry": {"type": "LineString", "coordinates": [[24.0, 57.0], [24.1, 57.1]]}},
+                {"type": "Feature", "geometry": {"type": "Point", "coordinates": [24.2, 57.1]}, "properties": {"radius": 200}},
+                {"type": "Feature", "geometry": {"type": "Point", "coordinates": [24.4, 57.2]}, "properties": null}
+            ]
+        });
+        let waypoints = WaypointsReader::read_geojson(geojson).unwrap();
+        assert_eq!(waypoints.len(), 3);
+        assert_eq!(
+            waypoints[1],
+            Waypoint {
+                lat: 57.1,
+                lon: 24.2,
+                radius: Some(200.)
+            }
+        );
+
+        assert!(WaypointsReader::read_geojson(serde_json::json!({"type": "Point"})).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+#[cfg(feature = "websocket")]
+use std::{
+    net::{IpAddr, TcpListener, TcpStream},
+    sync::{atomic::Ordering, Arc, Mutex},
+};
+
+#[cfg(feature = "websocket")]
+use tracing::{info, trace, warn};
+#[cfg(feature = "websocket")]
+use tungstenite::{
+    handshake::server::NoCallback, HandshakeError, Message, ServerHandshake, WebSocket,
+};
+
+#[cfg(feature = "websocket")]
+use crate::ipc_handler::{
+    ActiveRequest, IpcRequest, ResponseMessage, ResponseSender, RouterResult, SHUTTING_DOWN,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum WebSocketHandlerError {
+    #[error("WebSocket server requires the router to be built with the `websocket` feature")]
+    Disabled,
+
+    #[cfg(feature = "websocket")]
+    #[error("Failed to listen on {addr}: {error}")]
+    Bind { addr: String, error: std::io::Error },
+
+    #[cfg(feature = "websocket")]
+    #[error("WebSocket handshake failed: {error}")]
+    Handshake {
+        error: Box<HandshakeError<ServerHandshake<TcpStream, NoCallback>>>,
+    },
+
+    #[cfg(feature = "websocket")]
+    #[error("WebSocket error: {error}")]
+    WebSocket { error: Box<tungstenite::Error> },
+
+    #[cfg(feature = "websocket")]
+    #[error("Failed to serialize message: {error}")]
+    SerializeMessage { error: serde_json::Error },
+}
+
+/// Accepts the same request and cancel messages as the socket server, one JSON message per text
+/// frame. Progress and routes are sent back as they are generated when the request asks for them,
+/// followed by the final response, so a UI can follow long generations live. A connection can be
+/// used for several requests, one at a time.
+#[cfg_attr(not(feature = "websocket"), allow(dead_code))]
+pub struct WebSocketHandler {
+    #[cfg(feature = "websocket")]
+    addr: String,
+    #[cfg(feature = "websocket")]
+    listener: TcpListener,
+}
+
+#[cfg(not(feature = "websocket"))]
+impl WebSocketHandler {
+    pub fn bind(_addr: &str) -> Result<Self, WebSocketHandlerError> {
+        Err(WebSocketHandlerError::Disabled)
+    }
+
+    pub fn listen<T>(self, _message_handler: T) -> Result<(), WebSocketHandlerError> {
+        Err(WebSocketHandlerError::Disabled)
+    }
+}
+
+#[cfg(feature = "websocket")]
+impl WebSocketHandler {
+    pub fn bind(addr: &str) -> Result<Self, WebSocketHandlerError> {
+        let listener = TcpListener::bind(addr).map_err(|error| WebSocketHandlerError::Bind {
+            addr: addr.to_string(),
+            error,
+        })?;
+        Ok(Self {
+            addr: addr.to_string(),
+            listener,
+        })
+    }
+
+    pub fn listen<T>(self, message_handler: T) -> Result<(), WebSocketHandlerError>
+    where
+        T: Fn(IpcRequest, Option<IpAddr>, ResponseSender) -> ResponseMessage
+            + Sync
+            + Send
+            + Copy
+            + 'static,
+    {
+        info!(addr = self.addr, "WebSocket server running");
+
+        for stream in self.listener.incoming() {
+            // connections stay open between requests, so they get their own thread instead of
+            // blocking one of the rayon threads that generate the routes
+            std::thread::spawn(move || match stream {
+                Err(e) => {
+                    warn!("Incoming WebSocket connection failed {}", e);
+                }
+                Ok(stream) => {
+                    trace!("received WebSocket connection");
+                    if let Err(error) = WebSocketHandler::handle_connection(stream, message_handler)
+                    {
+                        warn!("error from WebSocket connection {:?}", error);
+                    }
+                }
+            });
+            if SHUTTING_DOWN.load(Ordering::SeqCst) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_connection<T>(
+        stream: TcpStream,
+        message_handler: T,
+    ) -> Result<(), WebSocketHandlerError>
+    where
+        T: Fn(IpcRequest, Option<IpAddr>, ResponseSender) -> ResponseMessage,
+    {
+        let peer = stream.peer_addr().ok().map(|addr| addr.ip());
+        let socket =
+            tungstenite::accept(stream).map_err(|error| WebSocketHandlerError::Handshake {
+                error: Box::new(error),
+            })?;
+        let socket = Arc::new(Mutex::new(socket));
+
+        loop {
+            let message = socket
+                .lock()
+                .unwrap_or_else(|error| error.into_inner())
+                .read();
+            let text = match message {
+                Ok(Message::Text(text)) => text,
+                Ok(Message::Close(_))
+                | Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => {
+                    return Ok(());
+                }
+                Ok(_) => continue,
+                Err(error) => {
+                    return Err(WebSocketHandlerError::WebSocket {
+                        error: Box::new(error),
+                    })
+                }
+            };
+
+            let req = match serde_json::from_str::<IpcRequest>(&text) {
+                Ok(req) => req,
+                Err(error) => {
+                    let resp = ResponseMessage {
+                        id: String::new(),
+                        result: RouterResult::Error {
+                            message: format!("Failed to deserialize request {:?}", error),
+                        },
+                    };
+                    WebSocketHandler::send(&socket, &resp)?;
+                    continue;
+                }
+            };
+            info!(req_id = req.id(), "WebSocket message received");
+
+            // held until the final response is sent, so a shutdown waits for it
+            let _active_request = match ActiveRequest::start(&req) {
+                Ok(active_request) => active_request,
+                Err(resp) => {
+                    WebSocketHandler::send(&socket, &resp)?;
+                    continue;
+                }
+            };
+
+            let progress_socket = socket.clone();
+            let send_progress: ResponseSender = Arc::new(move |resp| {
+                if let Err(error) = WebSocketHandler::send(&progress_socket, &resp) {
+                    warn!("error from WebSocket connection {:?}", error);
+                }
+            });
+            let resp = message_handler(req, peer, send_progress);
+            WebSocketHandler::send(&socket, &resp)?;
+        }
+    }
+
+    fn send(
+        socket: &Mutex<WebSocket<TcpStream>>,
+        response_message: &ResponseMessage,
+    ) -> Result<(), WebSocketHandlerError> {
+        let string_message = serde_json::to_string(response_message)
+            .map_err(|error| WebSocketHandlerError::SerializeMessage { error })?;
+
+        socket
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .send(Message::text(string_message))
+            .map_err(|error| WebSocketHandlerError::WebSocket {
+                error: Box::new(error),
+            })
+    }
+}
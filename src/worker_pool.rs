@@ -0,0 +1,277 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Condvar, Mutex},
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, thiserror::Error)]
+pub enum WorkerPoolError {
+    #[error("Server is busy, {queue_depth} requests are already waiting")]
+    QueueFull { queue_depth: usize },
+
+    #[error("Request was cancelled while waiting for a worker")]
+    Cancelled,
+}
+
+/// Order in which requests waiting for a worker are started
+#[derive(
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// Someone is waiting for the routes, started before any other request
+    Interactive,
+    #[default]
+    Normal,
+    /// Bulk jobs, only started when no other request is waiting
+    Batch,
+}
+
+struct PoolState {
+    busy: usize,
+    /// Ticket given to the next request, requests of the same priority get a worker in ticket
+    /// order
+    next_ticket: usize,
+    /// Ids of the requests waiting for a worker, in the order they get one
+    waiting: BTreeMap<(Priority, usize), String>,
+}
+
+/// Limits how many route requests the server generates at the same time. The map data is shared
+/// read-only between them, so the limit is only there to keep each generation from being slowed
+/// down by too many others competing for the CPU. Requests over the limit wait in a queue of
+/// limited depth and get a worker in the order of their priority, then in the order they came in
+pub struct WorkerPool {
+    workers: usize,
+    queue_depth: usize,
+    state: Mutex<PoolState>,
+    available: Condvar,
+}
+
+/// Worker taken from the pool, given back when dropped
+pub struct Worker<'a> {
+    pool: &'a WorkerPool,
+}
+
+impl WorkerPool {
+    pub fn new(workers: usize, queue_depth: usize) -> Self {
+        Self {
+            workers: workers.max(1),
+            queue_depth,
+            state: Mutex::new(PoolState {
+                busy: 0,
+                next_ticket: 0,
+                waiting: BTreeMap::new(),
+            }),
+            available: Condvar::new(),
+        }
+    }
+
+    /// Number of requests generated at the same time
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    /// Waits until a worker is free, `on_queued` is called with the position in the queue, starting
+    /// from 1, every time it changes. The position can also grow when requests with a higher
+    /// priority come in. Fails right away when the queue is full, or once the request is
+    /// cancelled while waiting
+    pub fn acquire(
+        &self,
+        id: &str,
+        priority: Priority,
+        on_queued: impl Fn(usize),
+    ) -> Result<Worker<'_>, WorkerPoolError> {
+        let mut state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+        let waiting = state.waiting.len();
+        if (state.busy >= self.workers || waiting > 0) && waiting >= self.queue_depth {
+            return Err(WorkerPoolError::QueueFull {
+                queue_depth: self.queue_depth,
+            });
+        }
+        let entry = (priority, state.next_ticket);
+        state.next_ticket += 1;
+        state.waiting.insert(entry, id.to_string());
+
+        let mut reported_position = None;
+        loop {
+            if !state.waiting.contains_key(&entry) {
+                return Err(WorkerPoolError::Cancelled);
+            }
+            if state.waiting.first_key_value().map(|(first, _)| first) == Some(&entry)
+                && state.busy < self.workers
+            {
+                state.waiting.remove(&entry);
+                state.busy += 1;
+                // the next request in line may be able to start as well
+                self.available.notify_all();
+                return Ok(Worker { pool: self });
+            }
+            let position = state.waiting.range(..entry).count() + 1;
+            if reported_position != Some(position) {
+                reported_position = Some(position);
+                drop(state);
+                on_queued(position);
+                state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+                continue;
+            }
+            state = self
+                .available
+                .wait(state)
+                .unwrap_or_else(|error| error.into_inner());
+        }
+    }
+
+    /// Removes the requests with the id from the queue, returns false when none are waiting
+    pub fn cancel(&self, id: &str) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+        let waiting = state.waiting.len();
+        state.waiting.retain(|_, waiting_id| waiting_id != id);
+        let cancelled = state.waiting.len() < waiting;
+        if cancelled {
+            self.available.notify_all();
+        }
+        cancelled
+    }
+}
+
+impl Drop for Worker<'_> {
+    fn drop(&mut self) {
+        let mut state = self
+            .pool
+            .state
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        state.busy -= 1;
+        self.pool.available.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use super::{Priority, WorkerPool, WorkerPoolError};
+
+    #[test]
+    fn worker_pool_limit() {
+        let pool = WorkerPool::new(2, 10);
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+        let positions = Mutex::new(Vec::new());
+
+        thread::scope(|scope| {
+            for _ in 0..6 {
+                scope.spawn(|| {
+                    let _worker = pool
+                        .acquire("req", Priority::Normal, |position| {
+                            positions.lock().unwrap().push(position)
+                        })
+                        .unwrap();
+                    let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now_running, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(20));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+        let positions = positions.into_inner().unwrap();
+        assert!(positions.contains(&1));
+        assert!(positions.iter().all(|position| (1..=4).contains(position)));
+    }
+
+    #[test]
+    fn worker_pool_queue_full() {
+        let pool = WorkerPool::new(1, 1);
+        let _worker = pool.acquire("req", Priority::Normal, |_| {}).unwrap();
+
+        thread::scope(|scope| {
+            let queued = scope.spawn(|| pool.acquire("req", Priority::Normal, |_| {}).map(|_| ()));
+            while pool.state.lock().unwrap().next_ticket < 2 {
+                thread::yield_now();
+            }
+            assert!(matches!(
+                pool.acquire("req", Priority::Interactive, |_| {}),
+                Err(WorkerPoolError::QueueFull { queue_depth: 1 })
+            ));
+            drop(_worker);
+            assert!(queued.join().unwrap().is_ok());
+        });
+    }
+
+    #[test]
+    fn worker_pool_priority() {
+        let pool = WorkerPool::new(1, 10);
+        let worker = pool.acquire("req", Priority::Normal, |_| {}).unwrap();
+        let started = Mutex::new(Vec::new());
+
+        thread::scope(|scope| {
+            let wait_for_queue = |len| {
+                while pool.state.lock().unwrap().waiting.len() < len {
+                    thread::yield_now();
+                }
+            };
+            let start = |priority| {
+                let _worker = pool.acquire("req", priority, |_| {}).unwrap();
+                started.lock().unwrap().push(priority);
+            };
+            scope.spawn(move || start(Priority::Batch));
+            wait_for_queue(1);
+            scope.spawn(move || start(Priority::Normal));
+            wait_for_queue(2);
+            scope.spawn(move || start(Priority::Interactive));
+            wait_for_queue(3);
+            drop(worker);
+        });
+
+        assert_eq!(
+            started.into_inner().unwrap(),
+            vec![Priority::Interactive, Priority::Normal, Priority::Batch]
+        );
+    }
+
+    #[test]
+    fn worker_pool_cancel() {
+        let pool = WorkerPool::new(1, 10);
+        let worker = pool.acquire("running", Priority::Normal, |_| {}).unwrap();
+
+        thread::scope(|scope| {
+            let queued =
+                scope.spawn(|| pool.acquire("queued", Priority::Normal, |_| {}).map(|_| ()));
+            while pool.state.lock().unwrap().waiting.is_empty() {
+                thread::yield_now();
+            }
+            assert!(!pool.cancel("running"));
+            assert!(pool.cancel("queued"));
+            assert!(matches!(
+                queued.join().unwrap(),
+                Err(WorkerPoolError::Cancelled)
+            ));
+        });
+
+        drop(worker);
+        assert!(pool.state.lock().unwrap().waiting.is_empty());
+        assert!(pool.acquire("next", Priority::Normal, |_| {}).is_ok());
+    }
+}